@@ -0,0 +1,70 @@
+/* the C API of the path tracer, see src/capi.rs. build the library with
+ * cargo rustc --release --lib --features capi --crate-type cdylib
+ * and link against target/release/libpath_tracer.so (.dylib, .dll) */
+
+#ifndef PATH_TRACER_H
+#define PATH_TRACER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PT_OK 0
+#define PT_INVALID_ARGUMENT (-1)
+#define PT_PANIC (-2)
+
+#define PT_DIFFUSE 0
+#define PT_METAL 1
+#define PT_GLASS 2
+#define PT_LIGHT 3
+
+typedef struct PtWorld PtWorld;
+
+/* the structs start with their size, set it to sizeof the struct. new fields are only ever
+ * added at the end, so a program built against this header keeps working with later versions
+ * of the library */
+typedef struct PtMaterial {
+    size_t size;
+    int kind;
+    double color[3];
+    /* metal and glass */
+    double roughness;
+    /* glass */
+    double ior;
+} PtMaterial;
+
+typedef struct PtCamera {
+    size_t size;
+    uint32_t width;
+    uint32_t height;
+    uint32_t samples_per_pixel;
+    uint32_t max_depth;
+    double look_from[3];
+    double look_at[3];
+    double vup[3];
+    /* vertical field of view in degrees */
+    double vfov;
+    /* 0 for a different image every time */
+    uint64_t seed;
+} PtCamera;
+
+PtWorld *pt_world_new(void);
+void pt_world_free(PtWorld *world);
+int pt_set_background(PtWorld *world, double r, double g, double b);
+int pt_add_sphere(PtWorld *world, const double center[3], double radius,
+                  const PtMaterial *material);
+int pt_add_quad(PtWorld *world, const double q[3], const double u[3], const double v[3],
+                const PtMaterial *material);
+
+/* width * height * 4 floats of linear RGBA from the top left, NULL on failure */
+float *pt_render(PtWorld *world, const PtCamera *camera);
+void pt_buffer_free(float *buffer, uint32_t width, uint32_t height);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,331 @@
+//! Auxiliary outputs (AOVs) rendered next to the beauty image.
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use image::{ImageBuffer, Rgb, Rgb32FImage, RgbImage};
+use rayon::prelude::*;
+
+use crate::vec3::{Float, Vec2, Vec3};
+
+/// blur radii of the depth of field preview are capped at this many pixels, to keep it fast
+pub const MAX_DEFOCUS_RADIUS: Float = 32.0;
+
+/// Settings for the ink lines drawn where the depth or the normal jumps between neighbouring pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Outline {
+    /// relative depth difference that counts as an edge
+    pub depth_threshold: Float,
+    /// angle between neighbouring normals in degrees that counts as an edge
+    pub normal_threshold: Float,
+    pub color: Vec3,
+}
+
+impl Outline {
+    pub fn new(depth_threshold: Float, normal_threshold: Float, color: Vec3) -> Outline {
+        Outline {
+            depth_threshold,
+            normal_threshold,
+            color,
+        }
+    }
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Self::new(0.1, 30.0, Vec3::ZERO)
+    }
+}
+
+/// The names of the objects and materials in the ID passes by their IDs, which a compositor
+/// needs to pick them by name (Cryptomatte's manifest). an ID is the MurmurHash3 of the name,
+/// so it stays the same between renders and scene edits as long as the name does
+#[derive(Debug, Clone, Default)]
+pub struct IdManifest {
+    pub objects: BTreeMap<String, u32>,
+    pub materials: BTreeMap<String, u32>,
+}
+
+impl IdManifest {
+    pub fn add_object(&mut self, name: &str) -> u32 {
+        *self
+            .objects
+            .entry(name.to_string())
+            .or_insert(id_hash(name))
+    }
+
+    pub fn add_material(&mut self, name: &str) -> u32 {
+        *self
+            .materials
+            .entry(name.to_string())
+            .or_insert(id_hash(name))
+    }
+
+    /// `[objects]` and `[materials]` tables of names to hex IDs
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let ids = |names: &BTreeMap<String, u32>| {
+            let table: toml::Table = names
+                .iter()
+                .map(|(name, id)| (name.clone(), format!("{id:08x}").into()))
+                .collect();
+            toml::Value::from(table)
+        };
+        let mut table = toml::Table::new();
+        table.insert("objects".into(), ids(&self.objects));
+        table.insert("materials".into(), ids(&self.materials));
+        fs::write(path, table.to_string())
+    }
+}
+
+/// 32 bit MurmurHash3 of `name`, never 0 which is left for pixels without a hit
+pub fn id_hash(name: &str) -> u32 {
+    let mix = |k: u32| {
+        k.wrapping_mul(0xcc9e2d51)
+            .rotate_left(15)
+            .wrapping_mul(0x1b873593)
+    };
+    let bytes = name.as_bytes();
+    let mut h: u32 = 0;
+    let mut chunks = bytes.chunks_exact(4);
+    for chunk in &mut chunks {
+        let k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        h = (h ^ mix(k))
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe6546b64);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0, |k, &b| (k << 8) | b as u32);
+        h ^= mix(k);
+    }
+    h ^= bytes.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^= h >> 16;
+    h.max(1)
+}
+
+/// an ID stored in a float channel the way Cryptomatte does, its bits as they are except
+/// for the exponents of denormals, infinities and NaNs, which don't survive compositing
+pub fn id_to_float(id: u32) -> f32 {
+    let exponent = (id >> 23) & 0xff;
+    match exponent {
+        0 | 0xff if id != 0 => f32::from_bits(id ^ (1 << 23)),
+        _ => f32::from_bits(id),
+    }
+}
+
+/// a color for previewing an ID, black for no hit
+pub fn id_color(id: u32) -> Rgb<u8> {
+    if id == 0 {
+        return Rgb([0, 0, 0]);
+    }
+    // brighter than the background, whatever the hash
+    let [r, g, b, _] = id.to_le_bytes();
+    Rgb([r, g, b].map(|c| 48 + (c as u16 * 207 / 255) as u8))
+}
+
+/// Per pixel depth and normal of the first hit, pixels that hit nothing have infinite depth.
+/// the object and material IDs are there when the scene was tagged for the ID passes
+pub struct GBuffer {
+    pub width: usize,
+    pub height: usize,
+    pub depth: Vec<Float>,
+    pub normal: Vec<Vec3>,
+    pub object_id: Vec<u32>,
+    pub material_id: Vec<u32>,
+    /// how far the hit moves across the image from shutter open to close, in pixels with y
+    /// going down
+    pub motion: Vec<Vec2>,
+    /// how far it moves in the scene, see `HitInfo::motion`
+    pub world_motion: Vec<Vec3>,
+    /// and in the space of the object it's on, see `HitInfo::object_motion`
+    pub object_motion: Vec<Vec3>,
+}
+
+impl GBuffer {
+    pub fn new(width: usize, height: usize) -> GBuffer {
+        GBuffer {
+            width,
+            height,
+            depth: vec![Float::INFINITY; width * height],
+            normal: vec![Vec3::ZERO; width * height],
+            object_id: vec![0; width * height],
+            material_id: vec![0; width * height],
+            motion: vec![Vec2::ZERO; width * height],
+            world_motion: vec![Vec3::ZERO; width * height],
+            object_motion: vec![Vec3::ZERO; width * height],
+        }
+    }
+
+    /// `rows` one above the other, each as wide as the buffer
+    pub fn from_rows(width: usize, rows: Vec<GBuffer>) -> GBuffer {
+        let mut gbuffer = GBuffer::new(width, 0);
+        for row in rows {
+            gbuffer.height += row.height;
+            gbuffer.depth.extend(row.depth);
+            gbuffer.normal.extend(row.normal);
+            gbuffer.object_id.extend(row.object_id);
+            gbuffer.material_id.extend(row.material_id);
+            gbuffer.motion.extend(row.motion);
+            gbuffer.world_motion.extend(row.world_motion);
+            gbuffer.object_motion.extend(row.object_motion);
+        }
+        gbuffer
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, depth: Float, normal: Vec3) {
+        let i = y * self.width + x;
+        self.depth[i] = depth;
+        self.normal[i] = normal;
+    }
+
+    pub fn set_ids(&mut self, x: usize, y: usize, object_id: u32, material_id: u32) {
+        let i = y * self.width + x;
+        self.object_id[i] = object_id;
+        self.material_id[i] = material_id;
+    }
+
+    pub fn set_motion(&mut self, x: usize, y: usize, motion: Vec2, world: Vec3, object: Vec3) {
+        let i = y * self.width + x;
+        self.motion[i] = motion;
+        self.world_motion[i] = world;
+        self.object_motion[i] = object;
+    }
+
+    /// the motion across the image in red and green, the way compositors read motion vectors
+    pub fn motion_image(&self) -> Rgb32FImage {
+        let vectors: Vec<Vec3> = self.motion.iter().map(|m| m.extend(0.0)).collect();
+        self.vector_image(&vectors)
+    }
+
+    /// a vector per pixel as it is, x in red, y in green and z in blue
+    #[allow(clippy::unnecessary_cast)]
+    pub fn vector_image(&self, vectors: &[Vec3]) -> Rgb32FImage {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let v = vectors[y as usize * self.width + x as usize];
+            Rgb([v.x as f32, v.y as f32, v.z as f32])
+        })
+    }
+
+    /// every ID in its own color, to see which pixels belong together
+    pub fn id_preview(&self, ids: &[u32]) -> RgbImage {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            id_color(ids[y as usize * self.width + x as usize])
+        })
+    }
+
+    /// the object IDs in red and the material IDs in green, as Cryptomatte stores them
+    pub fn id_image(&self) -> Rgb32FImage {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let i = y as usize * self.width + x as usize;
+            Rgb([
+                id_to_float(self.object_id[i]),
+                id_to_float(self.material_id[i]),
+                0.0,
+            ])
+        })
+    }
+
+    /// how strongly each pixel lies on an edge, from 0 to 1
+    pub fn edges(&self, outline: &Outline) -> Vec<Float> {
+        let min_cos = outline.normal_threshold.to_radians().cos();
+        let mut edges: Vec<Float> = vec![0.0; self.width * self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                // compare against the right and bottom neighbours, half the ink goes to each side
+                let mut neighbours = vec![];
+                if x + 1 < self.width {
+                    neighbours.push(i + 1);
+                }
+                if y + 1 < self.height {
+                    neighbours.push(i + self.width);
+                }
+                for j in neighbours {
+                    if self.is_edge(i, j, outline.depth_threshold, min_cos) {
+                        edges[i] = (edges[i] + 0.5).min(1.0);
+                        edges[j] = (edges[j] + 0.5).min(1.0);
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    /// a fake depth of field for `image`, rendered through the same pixels as the buffer:
+    /// every pixel is spread over a disk `blur_radius` pixels wide. blurry background isn't
+    /// spread over sharper pixels in front of it, so edges of objects in focus stay crisp.
+    /// blurring happens on linear colors, `image` is gamma corrected
+    pub fn depth_of_field(&self, image: &RgbImage, blur_radius: &[Float]) -> RgbImage {
+        let linear: Vec<Vec3> = image
+            .pixels()
+            .map(|p| Vec3::new(p[0] as Float, p[1] as Float, p[2] as Float) / 255.0)
+            .map(|c| c * c)
+            .collect();
+        // anything under half a pixel is sharp
+        let radius: Vec<Float> = blur_radius
+            .iter()
+            .map(|r| r.clamp(0.5, MAX_DEFOCUS_RADIUS))
+            .collect();
+        let reach = radius.iter().copied().fold(0.0, Float::max).ceil() as isize;
+
+        let blurred: Vec<Vec3> = (0..self.width * self.height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = ((i % self.width) as isize, (i / self.width) as isize);
+                let (mut sum, mut total) = (Vec3::ZERO, 0.0);
+                for ny in (y - reach).max(0)..(y + reach + 1).min(self.height as isize) {
+                    for nx in (x - reach).max(0)..(x + reach + 1).min(self.width as isize) {
+                        let j = ny as usize * self.width + nx as usize;
+                        let distance = (((nx - x).pow(2) + (ny - y).pow(2)) as Float).sqrt();
+                        if distance > radius[j]
+                            || (self.depth[j] > self.depth[i] && distance > radius[i])
+                        {
+                            continue;
+                        }
+                        // spreading over a bigger disk makes each pixel of it dimmer
+                        let weight = 1.0 / (radius[j] * radius[j]);
+                        sum += weight * linear[j];
+                        total += weight;
+                    }
+                }
+                sum / total
+            })
+            .collect();
+
+        let mut result = RgbImage::new(image.width(), image.height());
+        for (pixel, color) in result.pixels_mut().zip(blurred) {
+            let color = color.map(|c| (c.sqrt().clamp(0.0, 1.0) * 255.0).round());
+            *pixel = Rgb([color.x as u8, color.y as u8, color.z as u8]);
+        }
+        result
+    }
+
+    fn is_edge(&self, i: usize, j: usize, depth_threshold: Float, min_cos: Float) -> bool {
+        let (d0, d1) = (self.depth[i], self.depth[j]);
+        match (d0.is_finite(), d1.is_finite()) {
+            (false, false) => false,
+            (true, true) => {
+                (d0 - d1).abs() > depth_threshold * d0.min(d1)
+                    || self.normal[i].dot(self.normal[j]) < min_cos
+            }
+            _ => true,
+        }
+    }
+}
+
+/// `out.png` with name `depth` becomes `out_depth.png`. the passes named this way are 8 bit
+/// images, which EXR can't hold, so `out.exr` gets `out_depth.png`
+pub fn aov_filename(filename: &str, name: &str) -> String {
+    let path = Path::new(filename);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("out");
+    let file = match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("exr") => format!("{stem}_{name}.png"),
+        Some(ext) => format!("{stem}_{name}.{ext}"),
+        None => format!("{stem}_{name}"),
+    };
+    path.with_file_name(file).to_string_lossy().into_owned()
+}
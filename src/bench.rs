@@ -0,0 +1,404 @@
+//! A fixed suite of small scenes rendered at fixed seeds and sample counts, timing how long
+//! loading, building the BVH and rendering take and counting the rays traced, so performance
+//! regressions in the BVH or the BSDFs can be tracked between commits.
+
+use std::{fmt::Write, path::Path, time::Instant};
+
+use crate::{
+    ray_stats::{self, RayStats},
+    scene::{parse_scene, MaterialRegistry, SceneError},
+    vec3::Float,
+};
+
+/// Where the time of one scene went, the best of all runs
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BenchStages {
+    /// parsing the file and building the objects and materials
+    pub load: f64,
+    /// the scene's BVH over its objects. meshes build their own while they load
+    pub bvh: f64,
+    /// preprocessing and tracing every sample
+    pub render: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub objects: usize,
+    pub lights: usize,
+    pub stages: BenchStages,
+    /// counted in a separate run, counting slows the traversal down
+    pub stats: RayStats,
+}
+
+impl BenchResult {
+    /// closest hit and shadow rays per second of rendering
+    pub fn rays_per_second(&self) -> f64 {
+        (self.stats.closest_hit_rays + self.stats.shadow_rays) as f64 / self.stages.render
+    }
+
+    fn to_json(&self) -> String {
+        let rays = (self.stats.closest_hit_rays + self.stats.shadow_rays).max(1) as f64;
+        format!(
+            concat!(
+                "{{\"name\": \"{}\", \"width\": {}, \"height\": {}, \"samples_per_pixel\": {}, ",
+                "\"objects\": {}, \"lights\": {}, ",
+                "\"stages\": {{\"load\": {:.6}, \"bvh\": {:.6}, \"render\": {:.6}}}, ",
+                "\"rays\": {{\"camera\": {}, \"closest_hit\": {}, \"shadow\": {}}}, ",
+                "\"rays_per_second\": {:.0}, \"nodes_per_ray\": {:.3}, \"primitives_per_ray\": {:.3}}}"
+            ),
+            self.name,
+            self.width,
+            self.height,
+            self.samples_per_pixel,
+            self.objects,
+            self.lights,
+            self.stages.load,
+            self.stages.bvh,
+            self.stages.render,
+            self.stats.camera_rays,
+            self.stats.closest_hit_rays,
+            self.stats.shadow_rays,
+            self.rays_per_second(),
+            self.stats.nodes_visited as f64 / rays,
+            self.stats.primitive_tests as f64 / rays,
+        )
+    }
+}
+
+/// The results of the whole suite
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub runs: usize,
+    pub threads: usize,
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    pub fn to_json(&self) -> String {
+        let precision = if cfg!(feature = "f32") { "f32" } else { "f64" };
+        let mut json = format!(
+            "{{\n  \"version\": \"{}\",\n  \"precision\": \"{precision}\",\n  \"threads\": {},\n  \"runs\": {},\n  \"scenes\": [\n",
+            env!("CARGO_PKG_VERSION"),
+            self.threads,
+            self.runs,
+        );
+        for (i, result) in self.results.iter().enumerate() {
+            let comma = if i + 1 < self.results.len() { "," } else { "" };
+            let _ = writeln!(json, "    {}{comma}", result.to_json());
+        }
+        let render: f64 = self.results.iter().map(|result| result.stages.render).sum();
+        let _ = write!(json, "  ],\n  \"total_render\": {render:.6}\n}}\n");
+        json
+    }
+}
+
+/// a scene of the suite, as a scene file
+struct BenchScene {
+    name: &'static str,
+    text: String,
+}
+
+// every scene renders with a fixed seed, so each run and the counting run trace the same rays
+const CORNELL: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 128
+samples_per_pixel = 32
+max_depth = 8
+seed = 1
+vfov = 40.0
+look_from = [278, 278, -800]
+look_at = [278, 278, 0]
+
+[environment]
+color = [0, 0, 0]
+
+[[objects]]
+type = "quad"
+q = [555, 0, 0]
+u = [0, 555, 0]
+v = [0, 0, 555]
+material = { type = "diffuse", color = [0.12, 0.45, 0.15] }
+
+[[objects]]
+type = "quad"
+q = [0, 0, 0]
+u = [0, 555, 0]
+v = [0, 0, 555]
+material = { type = "diffuse", color = [0.65, 0.05, 0.05] }
+
+[[objects]]
+type = "quad"
+q = [0, 0, 0]
+u = [555, 0, 0]
+v = [0, 0, 555]
+material = { type = "diffuse", color = [0.73, 0.73, 0.73] }
+
+[[objects]]
+type = "quad"
+q = [555, 555, 555]
+u = [-555, 0, 0]
+v = [0, 0, -555]
+material = { type = "diffuse", color = [0.73, 0.73, 0.73] }
+
+[[objects]]
+type = "quad"
+q = [0, 0, 555]
+u = [555, 0, 0]
+v = [0, 555, 0]
+material = { type = "diffuse", color = [0.73, 0.73, 0.73] }
+
+[[objects]]
+type = "cuboid"
+min = [0, 0, 0]
+max = [165, 330, 165]
+axis = [0, 1, 0]
+angle = 0.261799
+translate = [265, 0, 295]
+material = { type = "diffuse", color = [0.73, 0.73, 0.73] }
+
+[[objects]]
+type = "sphere"
+center = [190, 90, 190]
+radius = 90
+material = { type = "glass", ior = 1.5 }
+
+[[lights]]
+type = "quad"
+q = [343, 554, 332]
+u = [-130, 0, 0]
+v = [0, 0, -105]
+material = { type = "light", color = [15, 15, 15] }
+"#;
+
+// one sphere of each kind of BSDF, for the time spent evaluating and sampling them
+const MATERIALS: &str = r#"
+[camera]
+aspect_ratio = 2.0
+image_width = 192
+samples_per_pixel = 32
+max_depth = 8
+seed = 2
+vfov = 30.0
+look_from = [0, 2, 12]
+look_at = [0, 0.8, 0]
+
+[environment]
+color = [0.7, 0.8, 1.0]
+
+[[objects]]
+type = "sphere"
+center = [0, -1000, 0]
+radius = 1000
+material = { type = "diffuse", color = { type = "checker", scale = 0.5, even = [0.2, 0.2, 0.2], odd = [0.8, 0.8, 0.8] } }
+
+[[objects]]
+type = "sphere"
+center = [-4.5, 1, 0]
+radius = 1
+material = { type = "principled", color = [0.8, 0.2, 0.1], roughness = 0.4, clearcoat = 1.0, sheen = 0.5 }
+
+[[objects]]
+type = "sphere"
+center = [-2.25, 1, 0]
+radius = 1
+material = { type = "principled", color = [0.9, 0.9, 0.9], roughness = 0.1, spec_trans = 1.0 }
+
+[[objects]]
+type = "sphere"
+center = [0, 1, 0]
+radius = 1
+material = { type = "glass", roughness = 0.2, ior = 1.5 }
+
+[[objects]]
+type = "sphere"
+center = [2.25, 1, 0]
+radius = 1
+material = { type = "metal", color = [0.9, 0.6, 0.3], roughness = 0.3 }
+
+[[objects]]
+type = "sphere"
+center = [4.5, 1, 0]
+radius = 1
+material = { type = "cloth", color = [0.2, 0.3, 0.7] }
+
+[[lights]]
+type = "sphere"
+center = [0, 8, 4]
+radius = 1.5
+material = { type = "light", color = [8, 8, 8] }
+"#;
+
+/// many small objects, for building and traversing a deep BVH
+fn sphere_field() -> String {
+    let mut text = String::from(
+        r#"
+[camera]
+aspect_ratio = 1.5
+image_width = 192
+samples_per_pixel = 16
+max_depth = 4
+seed = 3
+vfov = 35.0
+look_from = [0, 14, 22]
+look_at = [0, 0, 0]
+
+[environment]
+color = [0.7, 0.8, 1.0]
+
+[[objects]]
+type = "quad"
+q = [-40, 0, -40]
+u = [80, 0, 0]
+v = [0, 0, 80]
+material = { type = "diffuse", color = [0.5, 0.5, 0.5] }
+"#,
+    );
+    let n = 40;
+    for i in 0..n {
+        for j in 0..n {
+            let x = (i as Float - n as Float / 2.0) * 0.6;
+            let z = (j as Float - n as Float / 2.0) * 0.6;
+            let material = match (i + j) % 3 {
+                0 => r#"{ type = "diffuse", color = [0.7, 0.3, 0.2] }"#,
+                1 => r#"{ type = "metal", color = [0.8, 0.8, 0.8], roughness = 0.2 }"#,
+                _ => r#"{ type = "principled", color = [0.2, 0.5, 0.7], roughness = 0.3 }"#,
+            };
+            let _ = write!(
+                text,
+                "\n[[objects]]\ntype = \"sphere\"\ncenter = [{x}, 0.25, {z}]\nradius = 0.25\nmaterial = {material}\n"
+            );
+        }
+    }
+    text.push_str(
+        r#"
+[[lights]]
+type = "sphere"
+center = [0, 20, 0]
+radius = 3
+material = { type = "light", color = [6, 6, 6] }
+"#,
+    );
+    text
+}
+
+// a few thousand triangles, for the mesh BVH and the triangle tests
+const MESH: &str = r#"
+[camera]
+aspect_ratio = 1.0
+image_width = 128
+samples_per_pixel = 32
+max_depth = 6
+seed = 4
+frame = true
+look_from = [0, 0.5, 3]
+look_at = [0, 0, 0]
+
+[environment]
+color = [0.6, 0.6, 0.6]
+
+[[objects]]
+type = "mesh"
+file = "assets/bunny.obj"
+material = { type = "principled", color = [0.8, 0.8, 0.8], roughness = 0.5 }
+"#;
+
+fn suite() -> Vec<BenchScene> {
+    vec![
+        BenchScene {
+            name: "cornell",
+            text: CORNELL.to_string(),
+        },
+        BenchScene {
+            name: "materials",
+            text: MATERIALS.to_string(),
+        },
+        BenchScene {
+            name: "sphere_field",
+            text: sphere_field(),
+        },
+        BenchScene {
+            name: "mesh",
+            text: MESH.to_string(),
+        },
+    ]
+}
+
+/// render every scene of the suite `runs` times, keeping the fastest time of each stage, then
+/// once more counting the rays
+pub fn run_bench(runs: usize) -> Result<BenchReport, SceneError> {
+    // meshes are looked up next to the crate, so the suite runs from any directory
+    let base_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let materials = MaterialRegistry::with_builtins();
+    let runs = runs.max(1);
+
+    let mut results = Vec::new();
+    for bench in suite() {
+        log::info!("benchmarking {}", bench.name);
+        let mut best: Option<BenchStages> = None;
+        for _ in 0..runs {
+            let start = Instant::now();
+            let mut scene = parse_scene(&bench.text, base_dir, &materials)?;
+            let load = start.elapsed().as_secs_f64() - scene.bvh_time;
+            scene.camera.init();
+            let start = Instant::now();
+            scene.camera.render_image(&scene.world);
+            let stages = BenchStages {
+                load,
+                bvh: scene.bvh_time,
+                render: start.elapsed().as_secs_f64(),
+            };
+            best = Some(match best {
+                Some(best) => BenchStages {
+                    load: best.load.min(stages.load),
+                    bvh: best.bvh.min(stages.bvh),
+                    render: best.render.min(stages.render),
+                },
+                None => stages,
+            });
+        }
+
+        let mut scene = parse_scene(&bench.text, base_dir, &materials)?;
+        scene.camera.init();
+        let stats = count_rays(|| {
+            scene.camera.render_image(&scene.world);
+        });
+        let image = scene.camera.render_info(0.0);
+        results.push(BenchResult {
+            name: bench.name,
+            width: image.width,
+            height: image.height,
+            samples_per_pixel: image.samples_per_pixel,
+            objects: scene.world.objects.len(),
+            lights: scene.world.lights.len(),
+            stages: best.unwrap_or_default(),
+            stats,
+        });
+    }
+
+    Ok(BenchReport {
+        runs,
+        threads: rayon::current_num_threads(),
+        results,
+    })
+}
+
+/// everything `render` counts on any of the render threads
+fn count_rays(render: impl FnOnce()) -> RayStats {
+    let take_all = || {
+        let mut total = RayStats::take();
+        for stats in rayon::broadcast(|_| RayStats::take()) {
+            total += stats;
+        }
+        total
+    };
+    take_all();
+    ray_stats::set_enabled(true);
+    render();
+    ray_stats::set_enabled(false);
+    take_all()
+}
@@ -0,0 +1,163 @@
+//! Blue-noise masks: tiles of values in [0, 1) where neighbouring pixels have values far apart,
+//! so any threshold of the tile gives evenly spread dots without clumps or holes.
+//!
+//! the camera uses one to dither its samples (`Camera::blue_noise`, Georgiev and Fajardo's
+//! dithered sampling): every pixel takes the same low discrepancy sequence of samples, shifted
+//! by the mask's value there. the error of neighbouring pixels is then anticorrelated, and at a
+//! few samples per pixel the noise looks like fine grain instead of blotches.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    error::{open_image, Error},
+    vec3::Float,
+};
+
+/// how far apart in pixels points still push each other away while generating a mask
+const SIGMA: Float = 1.5;
+
+/// A square tile of blue noise, repeated over the image
+#[derive(Debug, Clone)]
+pub struct BlueNoiseMask {
+    size: usize,
+    values: Vec<Float>,
+}
+
+impl BlueNoiseMask {
+    /// a `size` by `size` tile made with the void and cluster method. the same size always gives
+    /// the same tile. it takes time quadratic in the pixels, 64 is plenty
+    pub fn generate(size: usize) -> BlueNoiseMask {
+        let size = size.max(2);
+        let n = size * size;
+        let energy = Energy::new(size);
+
+        // start from random points, then move the point with the most neighbours into the
+        // biggest hole until that's where it already was
+        let mut rng = StdRng::seed_from_u64(size as u64);
+        let mut points = vec![false; n];
+        let mut field = vec![0.0; n];
+        let initial = (n / 10).max(1);
+        let mut placed = 0;
+        while placed < initial {
+            let i = rng.gen_range(0..n);
+            if !points[i] {
+                points[i] = true;
+                energy.add(&mut field, i, 1.0);
+                placed += 1;
+            }
+        }
+        for _ in 0..n {
+            let cluster = tightest_cluster(&points, &field);
+            points[cluster] = false;
+            energy.add(&mut field, cluster, -1.0);
+            let void = largest_void(&points, &field);
+            points[void] = true;
+            energy.add(&mut field, void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+
+        // the initial points are ranked by taking them out tightest cluster first, the rest by
+        // filling the biggest hole until there are none
+        let mut rank = vec![0; n];
+        let (mut removing, mut removing_field) = (points.clone(), field.clone());
+        for r in (0..initial).rev() {
+            let cluster = tightest_cluster(&removing, &removing_field);
+            removing[cluster] = false;
+            energy.add(&mut removing_field, cluster, -1.0);
+            rank[cluster] = r;
+        }
+        for r in initial..n {
+            let void = largest_void(&points, &field);
+            points[void] = true;
+            energy.add(&mut field, void, 1.0);
+            rank[void] = r;
+        }
+
+        BlueNoiseMask {
+            size,
+            values: rank
+                .into_iter()
+                .map(|r| (r as Float + 0.5) / n as Float)
+                .collect(),
+        }
+    }
+
+    /// a mask from the first channel of a square image, e.g. one of the tiles that come with
+    /// papers on blue noise
+    pub fn from_image(filename: &str) -> Result<BlueNoiseMask, Error> {
+        let image = open_image(filename)?.to_luma32f();
+        let size = image.width().min(image.height()) as usize;
+        let values = (0..size * size)
+            .map(|i| {
+                let value = image.get_pixel((i % size) as u32, (i / size) as u32).0[0] as Float;
+                value.clamp(0.0, 1.0 - Float::EPSILON)
+            })
+            .collect();
+        Ok(BlueNoiseMask { size, values })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// the value at pixel (`x`, `y`) of the tiled mask for the `dim`th random number of a
+    /// sample. every dimension reads the tile shifted by a different amount, so the numbers of
+    /// one pixel aren't all the same
+    pub fn value(&self, x: usize, y: usize, dim: usize) -> Float {
+        // steps along the plastic number's sequence, which never line up with each other
+        let shift_x = (dim as Float * 0.754_877_7).fract();
+        let shift_y = (dim as Float * 0.569_840_3).fract();
+        let x = (x + (shift_x * self.size as Float) as usize) % self.size;
+        let y = (y + (shift_y * self.size as Float) as usize) % self.size;
+        self.values[y * self.size + x]
+    }
+}
+
+/// the gaussian every point spreads over the tile around it, wrapping around the edges
+struct Energy {
+    size: usize,
+    kernel: Vec<Float>,
+}
+
+impl Energy {
+    fn new(size: usize) -> Energy {
+        let kernel = (0..size * size)
+            .map(|i| {
+                let wrapped = |d: usize| d.min(size - d) as Float;
+                let (dx, dy) = (wrapped(i % size), wrapped(i / size));
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+        Energy { size, kernel }
+    }
+
+    /// add (or take away, with a negative `sign`) the point at `i` to `field`
+    fn add(&self, field: &mut [Float], i: usize, sign: Float) {
+        let (px, py) = (i % self.size, i / self.size);
+        for y in 0..self.size {
+            let dy = (y + self.size - py) % self.size;
+            for x in 0..self.size {
+                let dx = (x + self.size - px) % self.size;
+                field[y * self.size + x] += sign * self.kernel[dy * self.size + dx];
+            }
+        }
+    }
+}
+
+/// the point with the most energy from the others around it
+fn tightest_cluster(points: &[bool], field: &[Float]) -> usize {
+    (0..points.len())
+        .filter(|&i| points[i])
+        .max_by(|&a, &b| field[a].total_cmp(&field[b]))
+        .unwrap()
+}
+
+/// the empty pixel the points leave the most room around
+fn largest_void(points: &[bool], field: &[Float]) -> usize {
+    (0..points.len())
+        .filter(|&i| !points[i])
+        .min_by(|&a, &b| field[a].total_cmp(&field[b]))
+        .unwrap()
+}
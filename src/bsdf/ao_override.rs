@@ -0,0 +1,72 @@
+use crate::{
+    hittable::HitInfo,
+    integrator::AoSettings,
+    ray::Ray,
+    texture::ImageTexture,
+    vec3::{Float, Vec3},
+};
+
+use super::{sided::Sidedness, BxDFMaterial, MatPtr};
+
+/// Wraps a material to give it its own ambient occlusion settings, everything else is passed through
+#[derive(Clone)]
+pub struct AoOverride {
+    bxdf: MatPtr,
+    settings: AoSettings,
+}
+
+impl AoOverride {
+    pub fn new(bxdf: MatPtr, settings: AoSettings) -> AoOverride {
+        AoOverride { bxdf, settings }
+    }
+}
+
+impl BxDFMaterial for AoOverride {
+    fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
+        self.bxdf.sample(ray, info)
+    }
+
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        self.bxdf.pdf(view_dir, light_dir, info)
+    }
+
+    fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
+        self.bxdf.eval(view_dir, light_dir, info)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        self.bxdf.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.bxdf.is_emissive()
+    }
+
+    fn normal_map(&self) -> Option<&ImageTexture> {
+        self.bxdf.normal_map()
+    }
+
+    fn ao_settings(&self) -> Option<AoSettings> {
+        Some(self.settings)
+    }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        self.bxdf.is_specular(info)
+    }
+
+    fn shadow_transmittance(&self, dir: Vec3, info: &HitInfo) -> Vec3 {
+        self.bxdf.shadow_transmittance(dir, info)
+    }
+
+    fn opacity(&self, info: &HitInfo) -> Float {
+        self.bxdf.opacity(info)
+    }
+
+    fn sidedness(&self) -> Sidedness {
+        self.bxdf.sidedness()
+    }
+
+    fn problems(&self) -> Vec<String> {
+        self.bxdf.problems()
+    }
+}
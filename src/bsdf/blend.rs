@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    hittable::HitInfo,
+    integrator::AoSettings,
+    ray::Ray,
+    sampler::rng,
+    texture::{ImageTexture, Texture},
+    vec3::{Float, Vec3},
+};
+
+use super::{BxDFMaterial, MatPtr};
+
+/// Blends two materials by a mask texture, `a` where it's 0 and `b` where it's 1, e.g. rust
+/// patches on painted metal or moss on stone. like `MixBxDf` but the amount changes over the
+/// surface
+#[derive(Clone)]
+pub struct BlendMaterial {
+    a: MatPtr,
+    b: MatPtr,
+    mask: Arc<dyn Texture<Float>>,
+}
+
+impl BlendMaterial {
+    pub fn new(a: MatPtr, b: MatPtr, mask: Arc<dyn Texture<Float>>) -> BlendMaterial {
+        BlendMaterial { a, b, mask }
+    }
+
+    fn t(&self, info: &HitInfo) -> Float {
+        info.texture(&self.mask).clamp(0.0, 1.0)
+    }
+}
+
+impl BxDFMaterial for BlendMaterial {
+    fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
+        if rng().gen::<Float>() < self.t(info) {
+            self.b.sample(ray, info)
+        } else {
+            self.a.sample(ray, info)
+        }
+    }
+
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let t = self.t(info);
+        let pdf_a = if t < 1.0 {
+            self.a.pdf(view_dir, light_dir, info)
+        } else {
+            0.0
+        };
+        let pdf_b = if t > 0.0 {
+            self.b.pdf(view_dir, light_dir, info)
+        } else {
+            0.0
+        };
+        (1.0 - t) * pdf_a + t * pdf_b
+    }
+
+    fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
+        let t = self.t(info);
+        let mut f = Vec3::ZERO;
+        if t < 1.0 {
+            f += (1.0 - t) * self.a.eval(view_dir, light_dir, info);
+        }
+        if t > 0.0 {
+            f += t * self.b.eval(view_dir, light_dir, info);
+        }
+        f
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        let t = self.mask.value(u, v, &p).clamp(0.0, 1.0);
+        (1.0 - t) * self.a.emitted(u, v, p) + t * self.b.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.a.is_emissive() || self.b.is_emissive()
+    }
+
+    /// only one normal map can be used, `a`'s if it has one
+    fn normal_map(&self) -> Option<&ImageTexture> {
+        self.a.normal_map().or_else(|| self.b.normal_map())
+    }
+
+    fn ao_settings(&self) -> Option<AoSettings> {
+        self.a.ao_settings().or_else(|| self.b.ao_settings())
+    }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        match self.t(info) {
+            t if t <= 0.0 => self.a.is_specular(info),
+            t if t >= 1.0 => self.b.is_specular(info),
+            _ => self.a.is_specular(info) && self.b.is_specular(info),
+        }
+    }
+
+    fn opacity(&self, info: &HitInfo) -> Float {
+        let t = self.t(info);
+        (1.0 - t) * self.a.opacity(info) + t * self.b.opacity(info)
+    }
+
+    /// the blend of what gets through the solid parts of both, as a share of the blended opacity
+    fn shadow_transmittance(&self, dir: Vec3, info: &HitInfo) -> Vec3 {
+        let t = self.t(info);
+        let opacity = self.opacity(info);
+        if opacity <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let a = self.a.opacity(info) * self.a.shadow_transmittance(dir, info);
+        let b = self.b.opacity(info) * self.b.shadow_transmittance(dir, info);
+        ((1.0 - t) * a + t * b) / opacity
+    }
+
+    fn problems(&self) -> Vec<String> {
+        [self.a.problems(), self.b.problems()].concat()
+    }
+}
@@ -1,18 +1,22 @@
-use crate::{hittable::HitInfo, ray::Ray, vec3::Vec3};
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    vec3::{Float, Vec3},
+};
 
 use super::{
     r0,
-    sampling::{ggx, gtr1, to_local, to_world},
+    sampling::{ggx, gtr1},
     BxDFMaterial,
 };
 
 #[derive(Clone)]
 pub struct ClearcoatBRDF {
-    alpha_g: f64,
+    alpha_g: Float,
 }
 
 impl ClearcoatBRDF {
-    pub fn new(clearcoat_gloss: f64) -> Self {
+    pub fn new(clearcoat_gloss: Float) -> Self {
         Self {
             alpha_g: (1.0 - clearcoat_gloss) * 0.1 + clearcoat_gloss * 0.001,
         }
@@ -22,11 +26,11 @@ impl ClearcoatBRDF {
 impl BxDFMaterial for ClearcoatBRDF {
     fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let view_dir = -ray.direction();
-        let v = to_local(info.shading_normal, view_dir);
+        let v = info.shading_frame().to_local(view_dir);
 
-        let h = gtr1::sample_microfacet_normal(0.25);
+        let h = gtr1::sample_microfacet_normal(self.alpha_g);
         let specular_dir_local = (-v).reflect(h);
-        let specular_dir = to_world(info.shading_normal, specular_dir_local);
+        let specular_dir = info.shading_frame().to_world(specular_dir_local);
         if specular_dir.dot(info.shading_normal) <= 0.0 {
             None
         } else {
@@ -34,22 +38,26 @@ impl BxDFMaterial for ClearcoatBRDF {
         }
     }
 
-    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64 {
-        let v = to_local(info.shading_normal, view_dir);
-        let l = to_local(info.shading_normal, light_dir);
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
+        // `sample` never goes below the surface
+        if l.z <= 0.0 {
+            return 0.0;
+        }
         let h = (v + l).normalize();
-        let pdf_h =
-            ggx::G1(v, 0.25) * v.dot(h).abs() * gtr1::D(l.dot(h).abs(), self.alpha_g) / v.z.abs();
-        let jacobian = 1.0 / (4.0 * l.dot(h).abs());
-        pdf_h * jacobian
+        gtr1::pdf(h, self.alpha_g) / (4.0 * l.dot(h).abs())
     }
 
     fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
-        let v = to_local(info.shading_normal, view_dir);
-        let l = to_local(info.shading_normal, light_dir);
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
+        if l.z <= 0.0 {
+            return Vec3::ZERO;
+        }
         let h = (v + l).normalize();
 
-        let d = gtr1::D(l.dot(h).abs(), self.alpha_g);
+        let d = gtr1::D(h.z.abs(), self.alpha_g);
 
         let g = ggx::G(v, l, 0.25);
 
@@ -61,6 +69,6 @@ impl BxDFMaterial for ClearcoatBRDF {
     }
 }
 
-fn schlick_fresnel(r0: Vec3, angle: f64) -> Vec3 {
+fn schlick_fresnel(r0: Vec3, angle: Float) -> Vec3 {
     r0 + (1.0 - r0) * (1.0 - angle).powi(5)
 }
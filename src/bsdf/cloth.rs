@@ -0,0 +1,195 @@
+//! Fabric like velvet, satin or felt: a diffuse base under a microfiber sheen, after Estevez
+//! and Kulla, "Production Friendly Microfacet Sheen BRDF" (2017). The fibers standing up from
+//! the cloth are a microfacet distribution that is densest around the horizon (the "Charlie"
+//! sheen), so the cloth lights up at grazing angles and along its silhouette. The base only
+//! gets the light the sheen didn't reflect, which is looked up in a table of the sheen's albedo.
+
+use std::sync::{Arc, OnceLock};
+
+use rand::Rng;
+
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    sampler::rng,
+    texture::{SolidTexture, Texture},
+    vec3::{consts::PI, Float, Vec3, VectorExt},
+};
+
+use super::{
+    multiscatter::{stratified, AlbedoTable},
+    sampling::cosine_sample_hemisphere,
+    BxDFMaterial,
+};
+
+#[derive(Clone)]
+pub struct ClothBSDF {
+    base_color: Arc<dyn Texture<Vec3>>,
+    sheen_color: Arc<dyn Texture<Vec3>>,
+    /// how far the fibers lean over, from 0.07 (upright, a thin rim) to 1 (a broad glow)
+    roughness: Arc<dyn Texture<Float>>,
+}
+
+impl ClothBSDF {
+    pub fn new(
+        base_color: Arc<dyn Texture<Vec3>>,
+        sheen_color: Arc<dyn Texture<Vec3>>,
+        roughness: Arc<dyn Texture<Float>>,
+    ) -> Self {
+        Self {
+            base_color,
+            sheen_color,
+            roughness,
+        }
+    }
+
+    pub fn from_rgb(base_color: Vec3, sheen_color: Vec3, roughness: Float) -> Self {
+        Self::new(
+            Arc::new(SolidTexture::new(base_color)),
+            Arc::new(SolidTexture::new(sheen_color)),
+            Arc::new(SolidTexture::new(roughness)),
+        )
+    }
+
+    /// (base color, sheen color, roughness) at the hit
+    fn params(&self, info: &HitInfo) -> (Vec3, Vec3, Float) {
+        (
+            info.texture(&self.base_color),
+            info.texture(&self.sheen_color),
+            info.texture(&self.roughness).clamp(0.07, 1.0),
+        )
+    }
+}
+
+/// the Charlie distribution of fiber normals, `sin^(1/r)` around the normal
+fn charlie_d(h: Vec3, roughness: Float) -> Float {
+    let inv_r = 1.0 / roughness;
+    let sin_theta = (1.0 - h.z * h.z).max(0.0).sqrt();
+    (2.0 + inv_r) * sin_theta.powf(inv_r) / (2.0 * PI)
+}
+
+/// the shadowing of the fibers seen at cosine `mu`, the curve fitted in the paper
+fn charlie_lambda(mu: Float, roughness: Float) -> Float {
+    let t = (1.0 - roughness) * (1.0 - roughness);
+    let lerp = |a: Float, b: Float| t * a + (1.0 - t) * b;
+    let (a, b, c, d, e) = (
+        lerp(25.3245, 21.5473),
+        lerp(3.32435, 3.82987),
+        lerp(0.16801, 0.19823),
+        lerp(-1.27393, -1.97760),
+        lerp(-4.85967, -4.32054),
+    );
+    let l = |x: Float| a / (1.0 + b * x.powf(c)) + d * x + e;
+    if mu < 0.5 {
+        l(mu).exp()
+    } else {
+        (2.0 * l(0.5) - l(1.0 - mu)).exp()
+    }
+}
+
+/// the sheen for a white fiber color, without the cosine. `v` and `l` are in the local frame
+/// on the same side
+fn sheen(v: Vec3, l: Vec3, roughness: Float) -> Float {
+    let Some(h) = (v + l).try_normalize() else {
+        return 0.0;
+    };
+    let g = 1.0 / (1.0 + charlie_lambda(v.z, roughness) + charlie_lambda(l.z, roughness));
+    charlie_d(h, roughness) * g / (4.0 * v.z * l.z).max(1e-8)
+}
+
+/// the albedo of the white sheen over the cosine of the view angle and the roughness
+fn sheen_albedo() -> &'static AlbedoTable {
+    static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        AlbedoTable::tabulate(|cos_theta, roughness| {
+            let roughness = roughness.max(0.07);
+            let v = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+            let mut sum = 0.0;
+            let mut count = 0;
+            for (u1, u2) in stratified() {
+                let l = uniform_hemisphere(u1, u2);
+                sum += sheen(v, l, roughness) * l.z * 2.0 * PI;
+                count += 1;
+            }
+            sum / count as Float
+        })
+    })
+}
+
+fn uniform_hemisphere(u1: Float, u2: Float) -> Vec3 {
+    let r = (1.0 - u1 * u1).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), u1)
+}
+
+/// `v` and `l` in the local frame, flipped so `v` is above the surface. the cloth looks the
+/// same from both sides
+fn local(info: &HitInfo, view_dir: Vec3, light_dir: Vec3) -> (Vec3, Vec3) {
+    let frame = info.shading_frame();
+    let (v, l) = (
+        frame.to_local(view_dir.normalize()),
+        frame.to_local(light_dir),
+    );
+    if v.z < 0.0 {
+        (-v, -l)
+    } else {
+        (v, l)
+    }
+}
+
+/// what's left of the base under the sheen seen from `v`, and the chance of sampling the
+/// sheen instead of the base
+fn lobes(base: Vec3, sheen_color: Vec3, roughness: Float, v: Vec3) -> (Vec3, Float) {
+    let albedo = sheen_albedo().albedo(v.z, roughness);
+    let sheen = sheen_color * albedo;
+    let base = base * (1.0 - sheen_color.max_element() * albedo).max(0.0);
+    let total = sheen.luminance() + base.luminance();
+    let p_sheen = if total > 0.0 {
+        // the base always gets a few samples, in case the sheen is all there is
+        (sheen.luminance() / total).clamp(0.0, 0.9)
+    } else {
+        0.5
+    };
+    (base, p_sheen)
+}
+
+impl BxDFMaterial for ClothBSDF {
+    fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
+        let (base, sheen_color, roughness) = self.params(info);
+        let frame = info.shading_frame();
+        let v = frame.to_local(-ray.direction().normalize());
+        let side = v.z.signum();
+        let v = v * side;
+        let (_, p_sheen) = lobes(base, sheen_color, roughness, v);
+        let mut rng = rng();
+        // the sheen is spread too widely to be worth sampling as a microfacet lobe, uniform
+        // directions do better
+        let l = if rng.gen::<Float>() < p_sheen {
+            uniform_hemisphere(rng.gen(), rng.gen())
+        } else {
+            cosine_sample_hemisphere()
+        };
+        // back to the side the view came from
+        Some(frame.to_world(l * side))
+    }
+
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let (base, sheen_color, roughness) = self.params(info);
+        let (v, l) = local(info, view_dir, light_dir);
+        if l.z <= 0.0 {
+            return 0.0;
+        }
+        let (_, p_sheen) = lobes(base, sheen_color, roughness, v);
+        p_sheen / (2.0 * PI) + (1.0 - p_sheen) * l.z / PI
+    }
+
+    fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
+        let (base, sheen_color, roughness) = self.params(info);
+        let (v, l) = local(info, view_dir, light_dir);
+        if l.z <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let (base, _) = lobes(base, sheen_color, roughness, v);
+        (sheen_color * sheen(v, l, roughness) + base / PI) * l.z
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::{Arc, OnceLock};
+
+use rand::Rng;
+
+use crate::{
+    hittable::HitInfo,
+    integrator::AoSettings,
+    ray::Ray,
+    sampler::rng,
+    texture::{ImageTexture, SolidTexture, Texture},
+    vec3::{Float, Vec3},
+};
+
+use super::{
+    fresnel,
+    multiscatter::{self, AlbedoTable},
+    sampling::ggx,
+    sided::Sidedness,
+    BxDFMaterial, MatPtr, SPECULAR_ROUGHNESS,
+};
+
+/// A clear dielectric layer over any material, like varnish on wood or the clearcoat on car
+/// paint. the coat reflects with a GGX lobe and the base only gets the light the coat let
+/// through, looked up in a table of the coat's albedo, so nothing is reflected twice. the
+/// base is also tinted by the coat's `color` on the way through
+#[derive(Clone)]
+pub struct CoatMaterial {
+    base: MatPtr,
+    /// how much of the coat there is, from 0 to 1
+    weight: Arc<dyn Texture<Float>>,
+    roughness: Arc<dyn Texture<Float>>,
+    ior: Float,
+    color: Arc<dyn Texture<Vec3>>,
+    albedo: Arc<OnceLock<AlbedoTable>>,
+}
+
+impl CoatMaterial {
+    pub fn new(base: MatPtr, roughness: Arc<dyn Texture<Float>>, ior: Float) -> CoatMaterial {
+        CoatMaterial {
+            base,
+            weight: Arc::new(SolidTexture::new(1.0)),
+            roughness,
+            ior,
+            color: Arc::new(SolidTexture::new(Vec3::ONE)),
+            albedo: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn with_weight(mut self, weight: Arc<dyn Texture<Float>>) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_color(mut self, color: Arc<dyn Texture<Vec3>>) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// (weight, roughness) at the hit. the coat is only on the front of the surface
+    fn coat(&self, v: Vec3, info: &HitInfo) -> (Float, Float) {
+        let weight = if v.z > 0.0 {
+            info.texture(&self.weight).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (weight, info.texture(&self.roughness))
+    }
+
+    /// the light the coat reflects towards `v`
+    fn coat_albedo(&self, v: Vec3, roughness: Float) -> Float {
+        let table = self
+            .albedo
+            .get_or_init(|| multiscatter::dielectric_reflection_table(self.ior));
+        table.albedo(v.z, roughness)
+    }
+
+    /// chance of sampling the coat instead of the base. more than it reflects, its highlight
+    /// is sharper than anything under it
+    fn coat_probability(&self, weight: Float, albedo: Float) -> Float {
+        weight * (0.25 + 0.75 * albedo)
+    }
+}
+
+impl BxDFMaterial for CoatMaterial {
+    fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
+        let v = info.shading_frame().to_local(-ray.direction());
+        let (weight, roughness) = self.coat(v, info);
+        let p_coat = self.coat_probability(weight, self.coat_albedo(v, roughness));
+        if rng().gen::<Float>() >= p_coat {
+            return self.base.sample(ray, info);
+        }
+        let h = ggx::sample_microfacet_normal(v, roughness);
+        let l = (-v).reflect(h);
+        if l.z <= 0.0 {
+            None
+        } else {
+            Some(info.shading_frame().to_world(l))
+        }
+    }
+
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
+        let (weight, roughness) = self.coat(v, info);
+        let p_coat = self.coat_probability(weight, self.coat_albedo(v, roughness));
+        let base_pdf = if p_coat < 1.0 {
+            self.base.pdf(view_dir, light_dir, info)
+        } else {
+            0.0
+        };
+        let coat_pdf = if p_coat > 0.0 && l.z > 0.0 {
+            let h = (v + l).normalize();
+            ggx::G1(v, roughness) * v.dot(h).abs() * ggx::D(h, roughness)
+                / v.z
+                / (4.0 * l.dot(h).abs())
+        } else {
+            0.0
+        };
+        p_coat * coat_pdf + (1.0 - p_coat) * base_pdf
+    }
+
+    fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
+        let (weight, roughness) = self.coat(v, info);
+        let albedo = self.coat_albedo(v, roughness);
+        let through =
+            (1.0 - weight * albedo) * info.texture(&self.color).lerp(Vec3::ONE, 1.0 - weight);
+        let base = through * self.base.eval(view_dir, light_dir, info);
+        if weight <= 0.0 || l.z <= 0.0 {
+            return base;
+        }
+        let h = (v + l).normalize();
+        let f = fresnel::dielectric(v, h, 1.0, self.ior);
+        let coat = f * ggx::D(h, roughness) * ggx::G(v, l, roughness) / (4.0 * v.z);
+        base + Vec3::splat(weight * coat)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        self.base.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn normal_map(&self) -> Option<&ImageTexture> {
+        self.base.normal_map()
+    }
+
+    fn ao_settings(&self) -> Option<AoSettings> {
+        self.base.ao_settings()
+    }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        self.base.is_specular(info) && info.texture(&self.roughness) < SPECULAR_ROUGHNESS
+    }
+
+    fn opacity(&self, info: &HitInfo) -> Float {
+        self.base.opacity(info)
+    }
+
+    fn sidedness(&self) -> Sidedness {
+        self.base.sidedness()
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = self.base.problems();
+        if !(self.ior > 0.0 && self.ior.is_finite()) {
+            problems.push(format!("`ior` of the coat is {}", self.ior));
+        }
+        problems
+    }
+}
@@ -1,19 +1,18 @@
-use super::{
-    sampling::{cosine_sample_hemisphere, to_local, to_world},
-    BxDFMaterial, EPS,
-};
+use super::{sampling::cosine_sample_hemisphere, BxDFMaterial};
 use crate::{
     hittable::HitInfo,
     ray::Ray,
     texture::{ImageTexture, SolidTexture, Texture},
-    vec3::Vec3,
+    vec3::{consts::PI, Float, Vec3},
 };
-use std::{f64::consts::PI, sync::Arc};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct DiffuseBRDF {
     base_color: Arc<dyn Texture<Vec3>>,
     normal_map: Option<Arc<ImageTexture>>,
+    /// rough diffuse instead of lambertian where it's above 0, see `with_roughness`
+    roughness: Option<Arc<dyn Texture<Float>>>,
 }
 
 // Lambertian diffuse, NOT the one used in PrincipledBSDF
@@ -22,6 +21,7 @@ impl DiffuseBRDF {
         Self {
             base_color,
             normal_map: None,
+            roughness: None,
         }
     }
 
@@ -29,6 +29,7 @@ impl DiffuseBRDF {
         Self {
             base_color: Arc::new(SolidTexture::new(base_color)),
             normal_map: None,
+            roughness: None,
         }
     }
 
@@ -36,49 +37,115 @@ impl DiffuseBRDF {
         Self {
             base_color: Arc::new(SolidTexture::new(base_color)),
             normal_map: Some(Arc::new(normal_map)),
+            roughness: None,
         }
     }
 
-    pub fn from_textures(color_texture: Arc<dyn Texture<Vec3>>, normal_map: Option<ImageTexture>) -> Self {
+    pub fn from_textures(
+        color_texture: Arc<dyn Texture<Vec3>>,
+        normal_map: Option<Arc<ImageTexture>>,
+    ) -> Self {
         Self {
             base_color: color_texture,
-            normal_map: normal_map.map(Arc::new),
+            normal_map,
+            roughness: None,
         }
     }
+
+    /// Oren-Nayar style rough diffuse, which is brighter towards the light and flatter looking
+    /// than lambertian, like clay, plaster or cloth. `roughness` goes from 0 (lambertian) to 1
+    pub fn with_roughness(mut self, roughness: Arc<dyn Texture<Float>>) -> Self {
+        self.roughness = Some(roughness);
+        self
+    }
+}
+
+const FON_1: Float = 0.5 - 2.0 / (3.0 * PI);
+const FON_2: Float = 2.0 / 3.0 - 28.0 / (15.0 * PI);
+
+/// the albedo of the single scattering part of `eon` for a white surface, seen at cosine `mu`.
+/// the polynomial fit from the paper
+fn fon_albedo(mu: Float, roughness: Float) -> Float {
+    let m = 1.0 - mu;
+    let g_over_pi = m * (0.05710853 + m * (0.4918819 + m * (-0.3321814 + m * 0.071443)));
+    (1.0 + roughness * g_over_pi) / (1.0 + FON_1 * roughness)
+}
+
+/// Portsmouth et al., "EON: A practical energy-preserving rough diffuse BRDF" (2024). Fujii's
+/// improved Oren-Nayar plus a lobe for the light it loses to interreflections, so a white
+/// surface still reflects everything. `v` and `l` are in the local frame, without the cosine
+fn eon(color: Vec3, roughness: Float, v: Vec3, l: Vec3) -> Vec3 {
+    let (mu_i, mu_o) = (l.z.abs(), v.z.abs());
+    let s = l.dot(v) - l.z * v.z;
+    let s_over_t = if s > 0.0 { s / mu_i.max(mu_o) } else { s };
+    let a = 1.0 / (1.0 + FON_1 * roughness);
+    let single = color / PI * a * (1.0 + roughness * s_over_t);
+
+    let average = a * (1.0 + FON_2 * roughness);
+    let color_ms = color * color * average / (Vec3::ONE - color * (1.0 - average));
+    let eps = 1e-7;
+    let multiple = color_ms / PI
+        * (1.0 - fon_albedo(mu_o, roughness)).max(eps)
+        * (1.0 - fon_albedo(mu_i, roughness)).max(eps)
+        / (1.0 - average).max(eps);
+    single + multiple
 }
 
 impl BxDFMaterial for DiffuseBRDF {
     fn sample(&self, _ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let diffuse_dir_local = cosine_sample_hemisphere();
-        Some(to_world(info.shading_normal, diffuse_dir_local))
+        Some(info.shading_frame().to_world(diffuse_dir_local))
     }
 
-    fn pdf(&self, _view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64 {
-        let l = to_local(info.shading_normal, light_dir);
-        l.z.abs() / PI
+    fn pdf(&self, _view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let l = info.shading_frame().to_local(light_dir);
+        // `sample` only goes into the hemisphere above the surface
+        l.z.max(0.0) / PI
     }
 
-    fn eval(&self, _view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
-        let color = self.base_color.value(info.u, info.v, &info.point);
-        let l = to_local(info.shading_normal, light_dir);
-        l.z.abs() * (color / PI)
+    fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
+        let color = info.texture(&self.base_color);
+        let l = info.shading_frame().to_local(light_dir);
+        if l.z <= 0.0 {
+            return Vec3::ZERO;
+        }
+        match &self.roughness {
+            Some(roughness) => {
+                let v = info.shading_frame().to_local(view_dir.normalize());
+                let roughness = info.texture(roughness).clamp(0.0, 1.0);
+                l.z * eon(color, roughness, v, l)
+            }
+            None => l.z * (color / PI),
+        }
     }
 
     /// optimized version combining sample, pdf, and eval
     fn scatter(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vec3, Ray)> {
-        let color = self
-            .base_color
-            .value(hit_info.u, hit_info.v, &hit_info.point);
+        let color = hit_info.texture(&self.base_color);
         let dir = self.sample(ray, hit_info)?;
-        let next_ray = Ray::new(
-            hit_info.point + EPS * hit_info.geometric_normal,
-            dir,
-            ray.time(),
-        );
-        Some((color, next_ray))
+        let weight = match &self.roughness {
+            // the cosine and the pdf cancel out
+            Some(roughness) => {
+                let v = hit_info
+                    .shading_frame()
+                    .to_local(-ray.direction().normalize());
+                let l = hit_info.shading_frame().to_local(dir);
+                let roughness = hit_info.texture(roughness).clamp(0.0, 1.0);
+                PI * eon(color, roughness, v, l)
+            }
+            None => color,
+        };
+        let next_ray = hit_info.spawn_ray(dir, ray.time());
+        Some((weight, next_ray))
     }
 
     fn normal_map(&self) -> Option<&ImageTexture> {
         self.normal_map.as_deref()
     }
+
+    #[cfg(feature = "gpu")]
+    fn gpu_material(&self) -> Option<crate::gpu::GpuMaterial> {
+        let color = self.base_color.value(0.5, 0.5, &Vec3::ZERO);
+        Some(crate::gpu::GpuMaterial::Diffuse(color))
+    }
 }
@@ -4,51 +4,89 @@
 
 use std::sync::Arc;
 
-use super::{
-    sampling::{ggx, to_local, to_world},
-    BxDFMaterial, EPS,
-};
+use super::{multiscatter::DielectricTables, sampling::ggx, BxDFMaterial, SPECULAR_ROUGHNESS};
 use crate::{
     hittable::HitInfo,
     ray::Ray,
+    sampler::rng,
     texture::{SolidTexture, Texture},
-    vec3::Vec3,
+    vec3::{Float, Vec3},
 };
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 #[derive(Clone)]
 pub struct GlassBSDF {
     base_color: Arc<dyn Texture<Vec3>>,
-    roughness: Arc<dyn Texture<f64>>,
-    _anisotropic: f64,
-    ior: f64,
+    roughness: Arc<dyn Texture<Float>>,
+    _anisotropic: Float,
+    ior: Float,
+    /// absorption coefficient of the inside (sigma_a), per unit of distance
+    absorption: Vec3,
+    /// let shadow rays through, see `with_shadow_transmission`
+    shadow_transmission: bool,
+    /// albedo of the rough surface, to make up for the light lost to multiple scattering
+    multiscatter: Arc<DielectricTables>,
 }
 
 impl GlassBSDF {
     pub fn new(
         base_color: Arc<dyn Texture<Vec3>>,
-        roughness: Arc<dyn Texture<f64>>,
-        anisotropic: f64,
-        ior: f64,
+        roughness: Arc<dyn Texture<Float>>,
+        anisotropic: Float,
+        ior: Float,
     ) -> Self {
         Self {
             base_color,
             roughness,
             _anisotropic: anisotropic,
             ior,
+            absorption: Vec3::ZERO,
+            shadow_transmission: false,
+            multiscatter: Arc::new(DielectricTables::new(ior)),
         }
     }
 
-    pub fn basic(ior: f64) -> Self {
+    pub fn basic(ior: Float) -> Self {
         Self {
             base_color: Arc::new(SolidTexture::new(Vec3::ONE)),
             roughness: Arc::new(SolidTexture::new(0.001)),
             _anisotropic: 0.0,
             ior,
+            absorption: Vec3::ZERO,
+            shadow_transmission: false,
+            multiscatter: Arc::new(DielectricTables::new(ior)),
+        }
+    }
+
+    /// tint the inside by Beer-Lambert absorption, so that white light is `color` after going
+    /// `distance` through the glass and thick parts come out darker than thin ones
+    pub fn with_absorption(mut self, color: Vec3, distance: Float) -> Self {
+        let color = color.clamp(Vec3::splat(1e-6), Vec3::ONE);
+        self.absorption = -Vec3::new(color.x.ln(), color.y.ln(), color.z.ln()) / distance;
+        self
+    }
+
+    /// let shadow rays through, tinted by the color, the absorption and the fresnel of the
+    /// surfaces they cross, so the shadows of thin glass like windows aren't black. light
+    /// sampling through glass isn't refracted, so it's a cheat that also counts the light of
+    /// caustics twice; best for panes and bottles where the bending doesn't show
+    pub fn with_shadow_transmission(mut self, enabled: bool) -> Self {
+        self.shadow_transmission = enabled;
+        self
+    }
+
+    /// the share of light left after the segment inside the glass that ends at this hit.
+    /// hits on the back face are the end of a segment inside, which started at the previous
+    /// hit of the path, so this only holds for glass that nothing else is inside of
+    fn transmittance(&self, view_dir: Vec3, info: &HitInfo) -> Vec3 {
+        if info.front_face || self.absorption == Vec3::ZERO {
+            return Vec3::ONE;
         }
+        let distance = info.dist * view_dir.length();
+        (-self.absorption * distance).exp()
     }
 
-    fn dielectric_fresnel(&self, w: Vec3, h: Vec3, eta_i: f64, eta_o: f64) -> f64 {
+    fn dielectric_fresnel(&self, w: Vec3, h: Vec3, eta_i: Float, eta_o: Float) -> Float {
         let c = w.dot(h).abs();
         let g_squared = (eta_o / eta_i).powi(2) - 1.0 + c * c;
         if g_squared < 0.0 {
@@ -65,9 +103,9 @@ impl GlassBSDF {
 impl BxDFMaterial for GlassBSDF {
     fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let view_dir = -ray.direction();
-        let v = to_local(info.shading_normal, view_dir);
+        let v = info.shading_frame().to_local(view_dir);
 
-        let roughness = self.roughness.value(info.u, info.v, &info.point);
+        let roughness = info.texture(&self.roughness);
         let h = ggx::sample_microfacet_normal(v, roughness);
 
         let (eta_i, eta_o) = if info.front_face {
@@ -77,21 +115,21 @@ impl BxDFMaterial for GlassBSDF {
         };
 
         let f = self.dielectric_fresnel(v, h, eta_i, eta_o);
-        if thread_rng().gen::<f64>() < f {
-            let r = (-v).reflect(h);
-            Some(to_world(info.shading_normal, r))
+        let (l, reflected) = if rng().gen::<Float>() < f {
+            ((-v).reflect(h), true)
         } else {
-            let mut t = (-v).refract(h, eta_i / eta_o);
-            if t == Vec3::ZERO {
-                t = (-v).reflect(h);
-            }
-            Some(to_world(info.shading_normal, t))
+            ((-v).refract(h, eta_i / eta_o), false)
+        };
+        // a microfacet can send light to the wrong side of the surface, it's lost like in metal.rs
+        if l == Vec3::ZERO || (l.z * v.z > 0.0) != reflected {
+            return None;
         }
+        Some(info.shading_frame().to_world(l))
     }
 
-    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64 {
-        let v = to_local(info.shading_normal, view_dir);
-        let l = to_local(info.shading_normal, light_dir);
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
         let reflect = l.z * v.z > 0.0;
 
         let (eta_i, eta_o) = if info.front_face {
@@ -103,10 +141,13 @@ impl BxDFMaterial for GlassBSDF {
         let h = if reflect {
             (l + v).normalize() * v.z.signum()
         } else {
-            -(l * eta_o + v * eta_i).normalize()
+            ggx::refraction_half_vector(v, l, eta_i, eta_o)
         };
+        if !ggx::faces_microfacet(v, l, h) {
+            return 0.0;
+        }
 
-        let roughness = self.roughness.value(info.u, info.v, &info.point);
+        let roughness = info.texture(&self.roughness);
         let pdf_h = ggx::G1(v, roughness) * v.dot(h).abs() * ggx::D(h, roughness) / v.z.abs();
 
         let f = self.dielectric_fresnel(v, h, eta_i, eta_o);
@@ -123,8 +164,8 @@ impl BxDFMaterial for GlassBSDF {
     }
 
     fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
-        let v = to_local(info.shading_normal, view_dir);
-        let l = to_local(info.shading_normal, light_dir);
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
         let reflect = l.z * v.z > 0.0;
 
         let (eta_i, eta_o) = if info.front_face {
@@ -136,11 +177,14 @@ impl BxDFMaterial for GlassBSDF {
         let h = if reflect {
             (l + v).normalize() * v.z.signum()
         } else {
-            -(l * eta_o + v * eta_i).normalize()
+            ggx::refraction_half_vector(v, l, eta_i, eta_o)
         };
+        if !ggx::faces_microfacet(v, l, h) {
+            return Vec3::ZERO;
+        }
 
         // D term
-        let roughness = self.roughness.value(info.u, info.v, &info.point);
+        let roughness = info.texture(&self.roughness);
         let d = ggx::D(h, roughness);
 
         // G term
@@ -159,29 +203,71 @@ impl BxDFMaterial for GlassBSDF {
             let factor = term1 * term2 * (1.0 - f) * g * d;
             Vec3::splat(factor)
         };
-        result * l.z.abs()
+        let compensation = self
+            .multiscatter
+            .compensation(v.z, roughness, info.front_face);
+        let base_color = info.texture(&self.base_color);
+        result * l.z.abs() * base_color * compensation * self.transmittance(view_dir, info)
     }
 
     fn scatter(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vec3, Ray)> {
         let dir = self.sample(ray, hit_info)?;
 
         // simplified faster impl
-        let v = to_local(hit_info.shading_normal, -ray.direction());
-
-        let base_color = self
-            .base_color
-            .value(hit_info.u, hit_info.v, &hit_info.point);
-        let roughness = self
-            .roughness
-            .value(hit_info.u, hit_info.v, &hit_info.point);
-        let brdf_weight = base_color * ggx::G1(v, roughness);
-
-        let eps = EPS * dir.dot(hit_info.geometric_normal).signum();
-        let next_ray = Ray::new(
-            hit_info.point + eps * hit_info.geometric_normal,
-            dir,
-            ray.time(),
-        );
+        // with the normal sampled from the visible normals and reflection or refraction picked
+        // by the fresnel, everything but G1(l) cancels out
+        let v = hit_info.shading_frame().to_local(-ray.direction());
+        let l = hit_info.shading_frame().to_local(dir);
+
+        let base_color = hit_info.texture(&self.base_color);
+        let roughness = hit_info.texture(&self.roughness);
+        let compensation = self
+            .multiscatter
+            .compensation(v.z, roughness, hit_info.front_face);
+        let brdf_weight = base_color
+            * ggx::G1(l, roughness)
+            * compensation
+            * self.transmittance(-ray.direction(), hit_info);
+
+        let next_ray = hit_info.spawn_ray(dir, ray.time());
         Some((brdf_weight, next_ray))
     }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        info.texture(&self.roughness) < SPECULAR_ROUGHNESS
+    }
+
+    fn shadow_transmittance(&self, dir: Vec3, info: &HitInfo) -> Vec3 {
+        if !self.shadow_transmission {
+            return Vec3::ZERO;
+        }
+        let (eta_i, eta_o) = if info.front_face {
+            (1.0, self.ior)
+        } else {
+            (self.ior, 1.0)
+        };
+        let fresnel = self.dielectric_fresnel(dir, info.shading_normal, eta_i, eta_o);
+        info.texture(&self.base_color) * (1.0 - fresnel) * self.transmittance(dir, info)
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if !(self.ior > 0.0 && self.ior.is_finite()) {
+            problems.push(format!("`ior` is {}", self.ior));
+        }
+        if self.absorption.min_element() < 0.0 {
+            problems.push(format!("`absorption` {} is negative", self.absorption));
+        }
+        problems
+    }
+
+    #[cfg(feature = "gpu")]
+    fn gpu_material(&self) -> Option<crate::gpu::GpuMaterial> {
+        // the gpu only has smooth glass, without absorption
+        let color = self.base_color.value(0.5, 0.5, &Vec3::ZERO);
+        Some(crate::gpu::GpuMaterial::Glass {
+            color,
+            ior: self.ior,
+        })
+    }
 }
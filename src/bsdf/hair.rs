@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    sampler::rng,
+    texture::{SolidTexture, Texture},
+    vec3::{
+        consts::{FRAC_PI_2, PI},
+        Float, Vec3, VectorExt,
+    },
+};
+
+use super::BxDFMaterial;
+
+/// Hair and fur, shaded along the strand's tangent instead of around a normal: a Kajiya-Kay
+/// diffuse term plus two highlights in the style of Scheuermann's Kajiya-Kay variant, a white
+/// one from the surface of the strand shifted towards the root, and one tinted by the hair
+/// color from light that went through it shifted towards the tip.
+///
+/// Angles are measured from the plane across the strand. The highlights are gaussians around
+/// the mirror angle, spread evenly around the strand, and everything is normalized so the
+/// weights sum to at most 1 and no energy is created.
+#[derive(Clone)]
+pub struct HairBSDF {
+    color: Arc<dyn Texture<Vec3>>,
+    /// width of the highlights, from 0 to 1
+    roughness: Float,
+    /// weight of the white highlight, the tinted one gets as much times the color
+    specular: Float,
+    /// tilt of the scales on the strand in radians, which shifts the highlights
+    tilt: Float,
+}
+
+impl HairBSDF {
+    pub fn new(color: Arc<dyn Texture<Vec3>>, roughness: Float, specular: Float) -> Self {
+        Self {
+            color,
+            roughness: roughness.clamp(0.01, 1.0),
+            specular: specular.clamp(0.0, 0.5),
+            tilt: Float::to_radians(3.0),
+        }
+    }
+
+    pub fn from_rgb(color: Vec3, roughness: Float, specular: Float) -> Self {
+        Self::new(Arc::new(SolidTexture::new(color)), roughness, specular)
+    }
+
+    pub fn with_tilt(mut self, degrees: Float) -> Self {
+        self.tilt = degrees.to_radians();
+        self
+    }
+
+    /// (weight, center angle of the light, width) of the highlights, and the diffuse weight
+    fn lobes(&self, color: Vec3, view_angle: Float) -> ([(Vec3, Float, Float); 2], Vec3) {
+        let width = self.roughness * 0.5;
+        let primary = (
+            Vec3::splat(self.specular),
+            -view_angle - 2.0 * self.tilt,
+            width,
+        );
+        let secondary = (
+            self.specular * color,
+            -view_angle + 3.0 * self.tilt,
+            2.0 * width,
+        );
+        let diffuse = (1.0 - 2.0 * self.specular) * color;
+        ([primary, secondary], diffuse)
+    }
+
+    /// chance of sampling each highlight, and the diffuse term
+    fn lobe_weights(&self, color: Vec3) -> [Float; 3] {
+        let ([primary, secondary], diffuse) = self.lobes(color, 0.0);
+        let weights = [
+            primary.0.luminance(),
+            secondary.0.luminance(),
+            diffuse.luminance(),
+        ];
+        let total: Float = weights.iter().sum();
+        if total > 0.0 {
+            weights.map(|w| w / total)
+        } else {
+            [1.0, 0.0, 0.0]
+        }
+    }
+}
+
+/// the strand's direction and two directions across it
+fn strand_frame(info: &HitInfo) -> (Vec3, Vec3, Vec3) {
+    // any direction along the surface will do for shapes that aren't strands
+    let tangent = info
+        .tangent
+        .try_normalize()
+        .unwrap_or(info.geometric_frame().t);
+    let across = info.geometric_normal - tangent * tangent.dot(info.geometric_normal);
+    let across = across.try_normalize().unwrap_or(info.geometric_frame().s);
+    (tangent, across, tangent.cross(across))
+}
+
+/// angle of `dir` from the plane across the strand
+fn angle(dir: Vec3, tangent: Vec3) -> Float {
+    dir.dot(tangent).clamp(-1.0, 1.0).asin()
+}
+
+/// density of a gaussian folded back at the poles, so it lives on [-pi/2, pi/2]
+fn folded_gaussian(x: Float, mean: Float, width: Float) -> Float {
+    let gaussian = |x: Float| {
+        (-(x - mean) * (x - mean) / (2.0 * width * width)).exp() / (width * (2.0 * PI).sqrt())
+    };
+    (-1..=1)
+        .map(|k| {
+            let shift = 2.0 * PI * k as Float;
+            gaussian(x + shift) + gaussian(PI - x + shift)
+        })
+        .sum()
+}
+
+fn fold(mut x: Float) -> Float {
+    loop {
+        if x > FRAC_PI_2 {
+            x = PI - x;
+        } else if x < -FRAC_PI_2 {
+            x = -PI - x;
+        } else {
+            return x;
+        }
+    }
+}
+
+/// angle with density cos^2 / (pi / 2), which is the Kajiya-Kay diffuse term per unit angle
+fn sample_diffuse_angle(u: Float) -> Float {
+    // the cdf is (x + sin x cos x) / pi + 1/2, inverted by bisection
+    let (mut lo, mut hi) = (-FRAC_PI_2, FRAC_PI_2);
+    for _ in 0..32 {
+        let mid = 0.5 * (lo + hi);
+        if (mid + mid.sin() * mid.cos()) / PI + 0.5 < u {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    0.5 * (lo + hi)
+}
+
+impl BxDFMaterial for HairBSDF {
+    fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
+        let (tangent, across, side) = strand_frame(info);
+        let view_angle = angle(-ray.direction().normalize(), tangent);
+        let color = info.texture(&self.color);
+        let (highlights, _) = self.lobes(color, view_angle);
+        let weights = self.lobe_weights(color);
+
+        let mut rng = rng();
+        let pick: Float = rng.gen();
+        let theta = if pick < weights[0] + weights[1] {
+            let (_, mean, width) = highlights[usize::from(pick >= weights[0])];
+            // Box-Muller
+            let normal = (-2.0 * (1.0 - rng.gen::<Float>()).ln()).sqrt()
+                * (2.0 * PI * rng.gen::<Float>()).cos();
+            fold(mean + width * normal)
+        } else {
+            sample_diffuse_angle(rng.gen())
+        };
+        if theta.cos() < 1e-4 {
+            return None;
+        }
+        let phi = rng.gen_range(0.0..2.0 * PI);
+        Some(tangent * theta.sin() + theta.cos() * (phi.cos() * across + phi.sin() * side))
+    }
+
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let (tangent, _, _) = strand_frame(info);
+        let theta = angle(light_dir, tangent);
+        if theta.cos() < 1e-4 {
+            return 0.0;
+        }
+        let color = info.texture(&self.color);
+        let (highlights, _) = self.lobes(color, angle(view_dir, tangent));
+        let weights = self.lobe_weights(color);
+        // per unit solid angle, which is cos theta per unit angle and azimuth
+        let per_angle = weights[0] * folded_gaussian(theta, highlights[0].1, highlights[0].2)
+            + weights[1] * folded_gaussian(theta, highlights[1].1, highlights[1].2)
+            + weights[2] * theta.cos().powi(2) / FRAC_PI_2;
+        per_angle / (2.0 * PI * theta.cos())
+    }
+
+    fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
+        let (tangent, _, _) = strand_frame(info);
+        let theta = angle(light_dir, tangent);
+        if theta.cos() < 1e-4 {
+            return Vec3::ZERO;
+        }
+        let color = info.texture(&self.color);
+        let (highlights, diffuse) = self.lobes(color, angle(view_dir, tangent));
+        let per_angle: Vec3 = highlights
+            .iter()
+            .map(|&(weight, mean, width)| weight * folded_gaussian(theta, mean, width))
+            .sum::<Vec3>()
+            + diffuse * theta.cos().powi(2) / FRAC_PI_2;
+        per_angle / (2.0 * PI * theta.cos())
+    }
+}
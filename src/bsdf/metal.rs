@@ -4,30 +4,32 @@
 
 use std::sync::Arc;
 
+use super::multiscatter;
 use super::sampling::ggx;
-use super::EPS;
-use super::{
-    sampling::{to_local, to_world},
-    BxDFMaterial,
-};
+use super::BxDFMaterial;
+use super::SPECULAR_ROUGHNESS;
 use crate::texture::{SolidTexture, Texture};
-use crate::{hittable::HitInfo, ray::Ray, vec3::Vec3};
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    vec3::{Float, Vec3},
+};
 
 #[derive(Clone)]
 pub struct MetalBRDF {
     base_color: Arc<dyn Texture<Vec3>>,
-    roughness: Arc<dyn Texture<f64>>,
+    roughness: Arc<dyn Texture<Float>>,
 }
 
 impl MetalBRDF {
-    pub fn new(base_color: Arc<dyn Texture<Vec3>>, roughness: Arc<dyn Texture<f64>>) -> Self {
+    pub fn new(base_color: Arc<dyn Texture<Vec3>>, roughness: Arc<dyn Texture<Float>>) -> Self {
         Self {
             base_color,
             roughness,
         }
     }
 
-    pub fn from_rgb(base_color: Vec3, roughness: f64) -> Self {
+    pub fn from_rgb(base_color: Vec3, roughness: Float) -> Self {
         Self {
             base_color: Arc::new(SolidTexture::new(base_color)),
             roughness: Arc::new(SolidTexture::new(roughness)),
@@ -38,13 +40,13 @@ impl MetalBRDF {
 impl BxDFMaterial for MetalBRDF {
     fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let view_dir = -ray.direction();
-        let v = to_local(info.shading_normal, view_dir);
+        let v = info.shading_frame().to_local(view_dir);
 
-        let roughness = self.roughness.value(info.u, info.v, &info.point);
+        let roughness = info.texture(&self.roughness);
         let h = ggx::sample_microfacet_normal(v, roughness);
 
         let specular_dir_local = (-v).reflect(h);
-        let specular_dir = to_world(info.shading_normal, specular_dir_local);
+        let specular_dir = info.shading_frame().to_world(specular_dir_local);
 
         if specular_dir.dot(info.shading_normal) <= 0.0 {
             None
@@ -53,12 +55,16 @@ impl BxDFMaterial for MetalBRDF {
         }
     }
 
-    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64 {
-        let v = to_local(info.shading_normal, view_dir);
-        let l = to_local(info.shading_normal, light_dir);
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
+        // `sample` never goes below the surface
+        if l.z <= 0.0 {
+            return 0.0;
+        }
         let h = (v + l).normalize();
 
-        let roughness = self.roughness.value(info.u, info.v, &info.point);
+        let roughness = info.texture(&self.roughness);
         let pdf_h = ggx::G1(v, roughness) * v.dot(h).abs() * ggx::D(h, roughness) / v.z.abs();
 
         let jacobian = 1.0 / (4.0 * l.dot(h).abs());
@@ -67,47 +73,52 @@ impl BxDFMaterial for MetalBRDF {
     }
 
     fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
-        let v = to_local(info.shading_normal, view_dir);
-        let l = to_local(info.shading_normal, light_dir);
+        let v = info.shading_frame().to_local(view_dir);
+        let l = info.shading_frame().to_local(light_dir);
         let h = (v + l).normalize();
 
-        let roughness = self.roughness.value(info.u, info.v, &info.point);
-        let base_color = self.base_color.value(info.u, info.v, &info.point);
+        let roughness = info.texture(&self.roughness);
+        let base_color = info.texture(&self.base_color);
         let d = ggx::D(h, roughness);
         let g = ggx::G(v, l, roughness);
         let f = schlick_fresnel(base_color, l.dot(h));
-        l.z.abs() * (f * g * d / (4.0 * l.z.abs() * v.z.abs()))
+        let compensation = multiscatter::conductor(base_color, v.z, roughness);
+        l.z.abs() * (f * g * d / (4.0 * l.z.abs() * v.z.abs())) * compensation
     }
 
     fn scatter(&self, ray: &Ray, hit_info: &HitInfo) -> Option<(Vec3, Ray)> {
         let dir = self.sample(ray, hit_info)?;
 
         // simplified faster impl
-        let roughness = self
-            .roughness
-            .value(hit_info.u, hit_info.v, &hit_info.point);
-        let base_color = self
-            .base_color
-            .value(hit_info.u, hit_info.v, &hit_info.point);
-        let v = to_local(hit_info.shading_normal, -ray.direction());
-        let l = to_local(hit_info.shading_normal, dir);
+        let roughness = hit_info.texture(&self.roughness);
+        let base_color = hit_info.texture(&self.base_color);
+        let v = hit_info.shading_frame().to_local(-ray.direction());
+        let l = hit_info.shading_frame().to_local(dir);
         let h = (v + l).normalize();
-        let g = ggx::G(v, l, roughness);
 
-        // the simplified result of brdf / pdf
-        // note that f is not cancelled out like in glass.rs because it's not present in the pdf
+        // the simplified result of brdf / pdf, with the normal sampled from the visible normals
+        // everything but G1(l) cancels out. note that f is not cancelled out like in glass.rs
+        // because it's not present in the pdf
         let f = schlick_fresnel(base_color, l.dot(h));
-        let brdf_weight = f * v.dot(h).abs() * g / (v.z.abs() * h.z.abs());
+        let compensation = multiscatter::conductor(base_color, v.z, roughness);
+        let brdf_weight = f * ggx::G1(l, roughness) * compensation;
 
-        let next_ray = Ray::new(
-            hit_info.point + EPS * hit_info.geometric_normal,
-            dir,
-            ray.time(),
-        );
+        let next_ray = hit_info.spawn_ray(dir, ray.time());
         Some((brdf_weight, next_ray))
     }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        info.texture(&self.roughness) < SPECULAR_ROUGHNESS
+    }
+
+    #[cfg(feature = "gpu")]
+    fn gpu_material(&self) -> Option<crate::gpu::GpuMaterial> {
+        let color = self.base_color.value(0.5, 0.5, &Vec3::ZERO);
+        let roughness = self.roughness.value(0.5, 0.5, &Vec3::ZERO);
+        Some(crate::gpu::GpuMaterial::Metal { color, roughness })
+    }
 }
 
-fn schlick_fresnel(r0: Vec3, angle: f64) -> Vec3 {
+fn schlick_fresnel(r0: Vec3, angle: Float) -> Vec3 {
     r0 + (1.0 - r0) * (1.0 - angle).powi(5)
 }
@@ -1,18 +1,25 @@
 use std::sync::Arc;
 
-use crate::{hittable::HitInfo, ray::Ray, vec3::Vec3};
+use rand::Rng;
+
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    sampler::rng,
+    vec3::{Float, Vec3},
+};
 
 use super::BxDFMaterial;
 
 #[derive(Clone)]
 pub struct MixBxDf {
-    t: f64, // 0 = use mat1 entirely, 1 = use mat2 entirely
+    t: Float, // 0 = use mat1 entirely, 1 = use mat2 entirely
     bxdf1: Arc<dyn BxDFMaterial>,
     bxdf2: Arc<dyn BxDFMaterial>,
 }
 
 impl MixBxDf {
-    pub fn new(t: f64, bxdf1: Arc<dyn BxDFMaterial>, bxdf2: Arc<dyn BxDFMaterial>) -> MixBxDf {
+    pub fn new(t: Float, bxdf1: Arc<dyn BxDFMaterial>, bxdf2: Arc<dyn BxDFMaterial>) -> MixBxDf {
         Self {
             t: t.clamp(0.0, 1.0),
             bxdf1,
@@ -23,7 +30,7 @@ impl MixBxDf {
 
 impl BxDFMaterial for MixBxDf {
     fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
-        let p: f64 = rand::random();
+        let p: Float = rng().gen();
         if self.t < p {
             self.bxdf1.sample(ray, info)
         } else {
@@ -31,7 +38,7 @@ impl BxDFMaterial for MixBxDf {
         }
     }
 
-    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64 {
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
         let p1 = (1.0 - self.t) * self.bxdf1.pdf(view_dir, light_dir, info);
         let p2 = self.t * self.bxdf2.pdf(view_dir, light_dir, info);
         p1 + p2
@@ -42,4 +49,12 @@ impl BxDFMaterial for MixBxDf {
         let w2 = self.t * self.bxdf2.eval(view_dir, light_dir, info);
         w1 + w2
     }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        self.bxdf1.is_specular(info) && self.bxdf2.is_specular(info)
+    }
+
+    fn problems(&self) -> Vec<String> {
+        [self.bxdf1.problems(), self.bxdf2.problems()].concat()
+    }
 }
@@ -2,28 +2,40 @@ use std::sync::Arc;
 
 use crate::{
     hittable::HitInfo,
+    integrator::AoSettings,
     ray::Ray,
     texture::ImageTexture,
-    vec3::{Vec3, VectorExt},
+    vec3::{Float, Vec3, VectorExt},
 };
 
+pub mod ao_override;
+pub mod blend;
 pub mod clearcoat;
+pub mod cloth;
+pub mod coat;
+pub mod cutout;
 pub mod diffuse;
 pub mod glass;
+pub mod hair;
 pub mod metal;
 pub mod mix;
+pub mod multiscatter;
 pub mod principled;
 pub mod sampling;
 pub mod sheen;
+pub mod sided;
+pub mod testing;
+pub mod texture_set;
 
-pub(crate) const EPS: f64 = 1e-3;
+/// materials smoother than this count as specular, see `BxDFMaterial::is_specular`
+pub(crate) const SPECULAR_ROUGHNESS: Float = 0.1;
 
 pub trait BxDFMaterial: Send + Sync {
     /// Given the outgoing (view) ray and hit info, sample an incident (light) ray
     fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3>;
 
     /// Given an outgoing and incoming ray and hit info, compute the pdf of this incoming (light) ray
-    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64;
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float;
 
     /// Given an outgoing and incoming ray and hit info, compute the reflectance
     fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3;
@@ -34,16 +46,11 @@ pub trait BxDFMaterial: Send + Sync {
         let pdf = self.pdf(-ray.direction(), dir, hit_info);
         let brdf = self.eval(-ray.direction(), dir, hit_info);
         let brdf_weight = brdf / pdf;
-        let eps = EPS * dir.dot(hit_info.geometric_normal).signum();
-        let next_ray = Ray::new(
-            hit_info.point + eps * hit_info.geometric_normal,
-            dir,
-            ray.time(),
-        );
+        let next_ray = hit_info.spawn_ray(dir, ray.time());
         Some((brdf_weight, next_ray))
     }
 
-    fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
+    fn emitted(&self, _u: Float, _v: Float, _p: Vec3) -> Vec3 {
         Vec3::ZERO
     }
 
@@ -54,6 +61,57 @@ pub trait BxDFMaterial: Send + Sync {
     fn normal_map(&self) -> Option<&ImageTexture> {
         None
     }
+
+    /// ambient occlusion settings that replace the integrator's ones on this material
+    fn ao_settings(&self) -> Option<AoSettings> {
+        None
+    }
+
+    /// whether the surface is close enough to a mirror that it can't be shaded by estimating
+    /// the density of nearby photons, so photon mapping traces straight through it instead
+    fn is_specular(&self, _info: &HitInfo) -> bool {
+        false
+    }
+
+    /// how solid the surface is at the hit, from 0 to 1. rays pass straight through it with
+    /// probability `1 - opacity`, see `World::intersect_objects`
+    fn opacity(&self, _info: &HitInfo) -> Float {
+        1.0
+    }
+
+    /// the share of the light a shadow ray going in `dir` carries straight through the solid
+    /// part of the surface, on top of what its opacity lets through, see `World::transmittance`.
+    /// zero for anything that blocks light
+    fn shadow_transmittance(&self, _dir: Vec3, _info: &HitInfo) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    /// what the back faces look like, see `World::intersect_objects`
+    fn sidedness(&self) -> sided::Sidedness {
+        sided::Sidedness::Shaded
+    }
+
+    /// the closest material the GPU backend has, or None if it has nothing like it
+    #[cfg(feature = "gpu")]
+    fn gpu_material(&self) -> Option<crate::gpu::GpuMaterial> {
+        None
+    }
+
+    /// what's wrong with the material's parameters, e.g. weights outside [0, 1], one message
+    /// for each. see `World::validate`
+    fn problems(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// for messages about the material, the type name without its module path
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        let path = name.split('<').next().unwrap_or(name);
+        match path.rfind("::") {
+            Some(i) => &name[i + 2..],
+            None => name,
+        }
+    }
 }
 
 pub type MatPtr = Arc<dyn BxDFMaterial>;
@@ -67,14 +125,14 @@ pub fn tint(base_color: Vec3) -> Vec3 {
     }
 }
 
-pub fn r0(eta: f64) -> f64 {
+pub fn r0(eta: Float) -> Float {
     ((eta - 1.0) / (eta + 1.0)).powi(2)
 }
 
 pub mod fresnel {
-    use crate::vec3::Vec3;
+    use crate::vec3::{Float, Vec3};
 
-    pub fn dielectric(w: Vec3, h: Vec3, eta_i: f64, eta_o: f64) -> f64 {
+    pub fn dielectric(w: Vec3, h: Vec3, eta_i: Float, eta_o: Float) -> Float {
         let c = w.dot(h).abs();
         let g_squared = (eta_o / eta_i).powi(2) - 1.0 + c * c;
         if g_squared < 0.0 {
@@ -87,11 +145,11 @@ pub mod fresnel {
         0.5 * (gmc * gmc) / (gpc * gpc) * (1.0 + x * x)
     }
 
-    pub fn schlick(r0: Vec3, angle: f64) -> Vec3 {
+    pub fn schlick(r0: Vec3, angle: Float) -> Vec3 {
         r0 + (1.0 - r0) * (1.0 - angle).powi(5)
     }
 
-    pub fn schlick_weight(x: f64) -> f64 {
+    pub fn schlick_weight(x: Float) -> Float {
         (1.0 - x).clamp(0.0, 1.0).powi(5)
     }
 }
@@ -0,0 +1,167 @@
+//! Energy compensation for the light a GGX surface loses to multiple scattering. The
+//! microfacet model only counts light that leaves after one bounce off the microfacets, so
+//! rough metals and rough glass come out too dark. Following Turquin, "Practical multiple
+//! scattering compensation for microfacet models" (2019), the single scattering lobe is scaled
+//! up by how much energy it misses, looked up in tables of its directional albedo that are
+//! integrated the first time they're needed.
+
+use std::sync::OnceLock;
+
+use crate::vec3::{Float, Vec3};
+
+use super::{fresnel, sampling::ggx};
+
+const COS_BINS: usize = 32;
+const ROUGHNESS_BINS: usize = 32;
+/// samples per table entry are SAMPLE_GRID squared, stratified
+const SAMPLE_GRID: usize = 16;
+
+/// The directional albedo of a GGX lobe over the cosine of the view angle and the roughness
+#[derive(Debug, Clone)]
+pub struct AlbedoTable {
+    values: Vec<Float>,
+}
+
+impl AlbedoTable {
+    /// fill every entry with `albedo(cos_theta, roughness)`
+    pub fn tabulate(albedo: impl Fn(Float, Float) -> Float) -> AlbedoTable {
+        let mut values = Vec::with_capacity(COS_BINS * ROUGHNESS_BINS);
+        for j in 0..ROUGHNESS_BINS {
+            let roughness = j as Float / (ROUGHNESS_BINS - 1) as Float;
+            for i in 0..COS_BINS {
+                // the first entry at grazing angles would have no albedo at all
+                let cos_theta = (i as Float / (COS_BINS - 1) as Float).max(0.02);
+                values.push(albedo(cos_theta, roughness));
+            }
+        }
+        AlbedoTable { values }
+    }
+
+    /// integrate `weight(v, h)`, the sample weight of the normal `h` drawn from the visible
+    /// normals seen from `v`, for every entry
+    fn integrate(weight: impl Fn(Vec3, Vec3, Float) -> Float) -> AlbedoTable {
+        AlbedoTable::tabulate(|cos_theta, roughness| {
+            let v = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+            let mut sum = 0.0;
+            for (u1, u2) in stratified() {
+                let h = ggx::sample_vndf(v, roughness, u1, u2);
+                sum += weight(v, h, roughness);
+            }
+            sum / (SAMPLE_GRID * SAMPLE_GRID) as Float
+        })
+    }
+
+    /// the albedo at the view angle with cosine `cos_theta`, bilinear between the entries
+    pub fn albedo(&self, cos_theta: Float, roughness: Float) -> Float {
+        let x = cos_theta.abs().clamp(0.0, 1.0) * (COS_BINS - 1) as Float;
+        let y = roughness.clamp(0.0, 1.0) * (ROUGHNESS_BINS - 1) as Float;
+        let (i, j) = (
+            (x as usize).min(COS_BINS - 2),
+            (y as usize).min(ROUGHNESS_BINS - 2),
+        );
+        let (tx, ty) = (x - i as Float, y - j as Float);
+        let at = |i: usize, j: usize| self.values[j * COS_BINS + i];
+        let bottom = at(i, j) + (at(i + 1, j) - at(i, j)) * tx;
+        let top = at(i, j + 1) + (at(i + 1, j + 1) - at(i, j + 1)) * tx;
+        bottom + (top - bottom) * ty
+    }
+
+    /// how much to scale a lobe with this albedo by so it keeps all its energy
+    fn compensation(&self, cos_theta: Float, roughness: Float) -> Float {
+        1.0 / self.albedo(cos_theta, roughness).max(0.05)
+    }
+}
+
+/// the stratified samples over the unit square that every table entry is integrated with
+pub fn stratified() -> impl Iterator<Item = (Float, Float)> {
+    (0..SAMPLE_GRID * SAMPLE_GRID).map(|k| {
+        let (a, b) = (k / SAMPLE_GRID, k % SAMPLE_GRID);
+        (
+            (a as Float + 0.5) / SAMPLE_GRID as Float,
+            (b as Float + 0.5) / SAMPLE_GRID as Float,
+        )
+    })
+}
+
+/// the albedo of a GGX reflection with a Fresnel of 1
+pub fn conductor_table() -> &'static AlbedoTable {
+    static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        AlbedoTable::integrate(|v, h, roughness| {
+            let l = (-v).reflect(h);
+            if l.z > 0.0 {
+                ggx::G1(l, roughness)
+            } else {
+                0.0
+            }
+        })
+    })
+}
+
+/// the scale of a GGX reflection lobe with the reflectance `f0` at normal incidence, seen
+/// at the cosine `cos_theta`. a white lobe is scaled to an albedo of 1, darker ones lose
+/// more at every bounce between the microfacets
+pub fn conductor(f0: Vec3, cos_theta: Float, roughness: Float) -> Vec3 {
+    let missing = conductor_table().compensation(cos_theta, roughness) - 1.0;
+    Vec3::ONE + f0 * missing
+}
+
+/// the albedo of the GGX reflection off a dielectric of index `ior`, without the light that
+/// goes into it
+pub fn dielectric_reflection_table(ior: Float) -> AlbedoTable {
+    AlbedoTable::integrate(|v, h, roughness| {
+        let l = (-v).reflect(h);
+        if l.z > 0.0 {
+            fresnel::dielectric(v, h, 1.0, ior) * ggx::G1(l, roughness)
+        } else {
+            0.0
+        }
+    })
+}
+
+/// The albedo of a rough dielectric, reflection and transmission together, from outside
+/// (into a medium of index `ior`) and from inside (out of it)
+#[derive(Debug)]
+pub struct DielectricTables {
+    ior: Float,
+    tables: OnceLock<[AlbedoTable; 2]>,
+}
+
+impl DielectricTables {
+    pub fn new(ior: Float) -> DielectricTables {
+        DielectricTables {
+            ior,
+            tables: OnceLock::new(),
+        }
+    }
+
+    fn tables(&self) -> &[AlbedoTable; 2] {
+        self.tables.get_or_init(|| {
+            let table = |eta_i: Float, eta_o: Float| {
+                AlbedoTable::integrate(|v, h, roughness| {
+                    let f = fresnel::dielectric(v, h, eta_i, eta_o);
+                    let r = (-v).reflect(h);
+                    let t = (-v).refract(h, eta_i / eta_o);
+                    let reflected = if r.z > 0.0 {
+                        f * ggx::G1(r, roughness)
+                    } else {
+                        0.0
+                    };
+                    let transmitted = if t != Vec3::ZERO && t.z < 0.0 {
+                        (1.0 - f) * ggx::G1(t, roughness)
+                    } else {
+                        0.0
+                    };
+                    reflected + transmitted
+                })
+            };
+            [table(1.0, self.ior), table(self.ior, 1.0)]
+        })
+    }
+
+    /// the scale of both lobes together, `entering` from outside the medium
+    pub fn compensation(&self, cos_theta: Float, roughness: Float, entering: bool) -> Float {
+        let table = &self.tables()[if entering { 0 } else { 1 }];
+        table.compensation(cos_theta, roughness)
+    }
+}
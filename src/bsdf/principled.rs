@@ -1,14 +1,25 @@
-use std::{f64::consts::PI, sync::Arc};
+use std::{
+    borrow::Cow,
+    sync::{Arc, OnceLock},
+};
 
 use glam::FloatExt;
-
-use crate::{hittable::HitInfo, ray::Ray, texture::Texture, vec3::Vec3};
+use rand::Rng;
+
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    sampler::rng,
+    texture::{ImageTexture, Texture},
+    vec3::{consts::PI, Float, Vec3},
+};
 
 use super::{
     fresnel::{self, schlick_weight},
+    multiscatter::{self, AlbedoTable, DielectricTables},
     r0,
-    sampling::{cosine_sample_hemisphere, ggx, gtr1, to_local, to_world},
-    tint, BxDFMaterial,
+    sampling::{cosine_sample_hemisphere, ggx, gtr1},
+    tint, BxDFMaterial, SPECULAR_ROUGHNESS,
 };
 
 #[derive(Clone)]
@@ -23,38 +34,50 @@ use super::{
 pub struct PrincipledBSDF {
     base_color: Arc<dyn Texture<Vec3>>,
 
-    metallic: f64,
-    roughness: f64,
-    subsurface: f64,
-
-    specular: f64,
-    specular_tint: f64,
-
-    ior: f64,
-    spec_trans: f64,
-
-    // anisotropic: f64,
-    sheen: f64,
-    sheen_tint: f64,
-
-    clearcoat: f64,
-    clearcoat_gloss: f64,
+    metallic: Float,
+    roughness: Float,
+    subsurface: Float,
+
+    specular: Float,
+    specular_tint: Float,
+
+    ior: Float,
+    spec_trans: Float,
+
+    // anisotropic: Float,
+    sheen: Float,
+    sheen_tint: Float,
+
+    clearcoat: Float,
+    clearcoat_gloss: Float,
+
+    /// replace `roughness` and `metallic` where they're set
+    roughness_map: Option<Arc<dyn Texture<Float>>>,
+    metallic_map: Option<Arc<dyn Texture<Float>>>,
+    normal_map: Option<Arc<ImageTexture>>,
+    /// albedo of the glass lobe, to make up for the light lost to multiple scattering
+    multiscatter: Arc<DielectricTables>,
+    /// albedo of the dielectric specular lobe, the light the diffuse base never gets
+    reflection: Arc<OnceLock<AlbedoTable>>,
+    /// albedo of the white diffuse lobe, which goes over 1 with the retro-reflection
+    diffuse_albedo: Arc<OnceLock<AlbedoTable>>,
 }
 
 impl PrincipledBSDF {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         base_color: Arc<dyn Texture<Vec3>>,
-        metallic: f64,
-        roughness: f64,
-        subsurface: f64,
-        specular: f64,
-        specular_tint: f64,
-        ior: f64,
-        spec_trans: f64,
-        sheen: f64,
-        sheen_tint: f64,
-        clearcoat: f64,
-        clearcoat_gloss: f64,
+        metallic: Float,
+        roughness: Float,
+        subsurface: Float,
+        specular: Float,
+        specular_tint: Float,
+        ior: Float,
+        spec_trans: Float,
+        sheen: Float,
+        sheen_tint: Float,
+        clearcoat: Float,
+        clearcoat_gloss: Float,
     ) -> Self {
         Self {
             base_color,
@@ -69,14 +92,83 @@ impl PrincipledBSDF {
             sheen_tint,
             clearcoat,
             clearcoat_gloss,
+            roughness_map: None,
+            metallic_map: None,
+            normal_map: None,
+            multiscatter: Arc::new(DielectricTables::new(ior)),
+            reflection: Arc::new(OnceLock::new()),
+            diffuse_albedo: Arc::new(OnceLock::new()),
         }
     }
 
-    fn get_alpha_g(&self) -> f64 {
+    pub fn with_roughness_map(mut self, map: Arc<dyn Texture<Float>>) -> Self {
+        self.roughness_map = Some(map);
+        self
+    }
+
+    pub fn with_metallic_map(mut self, map: Arc<dyn Texture<Float>>) -> Self {
+        self.metallic_map = Some(map);
+        self
+    }
+
+    pub fn with_normal_map(mut self, map: Arc<ImageTexture>) -> Self {
+        self.normal_map = Some(map);
+        self
+    }
+
+    /// this material with the roughness and metallic maps looked up at the hit
+    fn resolve(&self, info: &HitInfo) -> Cow<'_, PrincipledBSDF> {
+        if self.roughness_map.is_none() && self.metallic_map.is_none() {
+            return Cow::Borrowed(self);
+        }
+        let mut resolved = self.clone();
+        if let Some(map) = &self.roughness_map {
+            resolved.roughness = info.texture(map).clamp(0.0, 1.0);
+        }
+        if let Some(map) = &self.metallic_map {
+            resolved.metallic = info.texture(map).clamp(0.0, 1.0);
+        }
+        Cow::Owned(resolved)
+    }
+
+    /// the light the dielectric specular lobe reflects towards `v`, without the compensation
+    fn reflection_albedo(&self, v: Vec3) -> Float {
+        let table = self
+            .reflection
+            .get_or_init(|| multiscatter::dielectric_reflection_table(self.ior));
+        table.albedo(v.z.abs(), self.roughness)
+    }
+
+    /// how much to scale the diffuse lobe by so it doesn't reflect more than it gets. the
+    /// retro-reflection of rough surfaces at grazing angles adds a few percent on top of
+    /// everything
+    fn diffuse_normalization(&self, v: Vec3) -> Float {
+        let table = self.diffuse_albedo.get_or_init(|| {
+            AlbedoTable::tabulate(|cos_theta, roughness| {
+                let this = PrincipledBSDF {
+                    roughness,
+                    ..self.clone()
+                };
+                let v = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+                let mut sum = 0.0;
+                // cosine weighted, which leaves PI times the lobe
+                for (u1, u2) in multiscatter::stratified() {
+                    let (r, phi) = (u1.sqrt(), 2.0 * PI * u2);
+                    let l = Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).sqrt());
+                    let h = (v + l).normalize();
+                    sum += PI * this.eval_diffuse(Vec3::ONE, v, l, h).x;
+                }
+                sum / multiscatter::stratified().count() as Float
+            })
+        });
+        1.0 / table.albedo(v.z.abs(), self.roughness).max(1.0)
+    }
+
+    fn get_alpha_g(&self) -> Float {
         (1.0 - self.clearcoat_gloss) * 0.1 + self.clearcoat_gloss * 0.001
     }
 
-    fn lobe_weights(&self) -> (f64, f64, f64, f64) {
+    fn lobe_weights(&self) -> (Float, Float, Float, Float) {
         let diffuse_wt = (1.0 - self.metallic) * (1.0 - self.spec_trans);
         let specular_wt = 1.0 - self.spec_trans * (1.0 - self.metallic);
         let glass_wt = self.spec_trans * (1.0 - self.metallic);
@@ -86,11 +178,11 @@ impl PrincipledBSDF {
 
     fn lobe_probabilities(
         &self,
-        diffuse_wt: f64,
-        specular_wt: f64,
-        glass_wt: f64,
-        clearcoat_wt: f64,
-    ) -> (f64, f64, f64, f64) {
+        diffuse_wt: Float,
+        specular_wt: Float,
+        glass_wt: Float,
+        clearcoat_wt: Float,
+    ) -> (Float, Float, Float, Float) {
         let inv_total = 1.0 / (diffuse_wt + specular_wt + glass_wt + clearcoat_wt);
         let diffuse_p = diffuse_wt * inv_total;
         let specular_p = specular_wt * inv_total;
@@ -100,15 +192,15 @@ impl PrincipledBSDF {
     }
 
     fn sample_diffuse(&self, info: &HitInfo) -> Option<Vec3> {
-        Some(to_world(info.geometric_normal, cosine_sample_hemisphere()))
+        Some(info.geometric_frame().to_world(cosine_sample_hemisphere()))
     }
 
     fn sample_specular(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let view_dir = -ray.direction();
-        let v = to_local(info.geometric_normal, view_dir);
+        let v = info.geometric_frame().to_local(view_dir);
         let h = ggx::sample_microfacet_normal(v, self.roughness);
         let specular_dir_local = (-v).reflect(h);
-        let specular_dir = to_world(info.geometric_normal, specular_dir_local);
+        let specular_dir = info.geometric_frame().to_world(specular_dir_local);
 
         if specular_dir.dot(info.geometric_normal) <= 0.0 {
             None
@@ -119,7 +211,7 @@ impl PrincipledBSDF {
 
     fn sample_glass(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let view_dir = -ray.direction();
-        let v = to_local(info.geometric_normal, view_dir);
+        let v = info.geometric_frame().to_local(view_dir);
         let h = ggx::sample_microfacet_normal(v, self.roughness);
 
         let (eta_i, eta_o) = if info.front_face {
@@ -129,24 +221,24 @@ impl PrincipledBSDF {
         };
 
         let f = fresnel::dielectric(v, h, eta_i, eta_o);
-        if rand::random::<f64>() < f {
-            let r = (-v).reflect(h);
-            Some(to_world(info.geometric_normal, r))
+        let (l, reflected) = if rng().gen::<Float>() < f {
+            ((-v).reflect(h), true)
         } else {
-            let mut t = (-v).refract(h, eta_i / eta_o);
-            if t == Vec3::ZERO {
-                t = (-v).reflect(h);
-            }
-            Some(to_world(info.geometric_normal, t))
+            ((-v).refract(h, eta_i / eta_o), false)
+        };
+        // a microfacet can send light to the wrong side of the surface, it's lost like in metal.rs
+        if l == Vec3::ZERO || (l.z * v.z > 0.0) != reflected {
+            return None;
         }
+        Some(info.geometric_frame().to_world(l))
     }
 
     fn sample_clearcoat(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let view_dir = -ray.direction();
-        let v = to_local(info.geometric_normal, view_dir);
-        let h = gtr1::sample_microfacet_normal(0.25);
+        let v = info.geometric_frame().to_local(view_dir);
+        let h = gtr1::sample_microfacet_normal(self.get_alpha_g());
         let specular_dir_local = (-v).reflect(h);
-        let specular_dir = to_world(info.geometric_normal, specular_dir_local);
+        let specular_dir = info.geometric_frame().to_world(specular_dir_local);
         if specular_dir.dot(info.geometric_normal) <= 0.0 {
             None
         } else {
@@ -154,11 +246,11 @@ impl PrincipledBSDF {
         }
     }
 
-    fn diffuse_pdf(&self, l: Vec3) -> f64 {
+    fn diffuse_pdf(&self, l: Vec3) -> Float {
         l.z.abs() / PI
     }
 
-    fn specular_pdf(&self, v: Vec3, l: Vec3, h: Vec3) -> f64 {
+    fn specular_pdf(&self, v: Vec3, l: Vec3, h: Vec3) -> Float {
         let pdf_h =
             ggx::G1(v, self.roughness) * v.dot(h).abs() * ggx::D(h, self.roughness) / v.z.abs();
 
@@ -167,7 +259,15 @@ impl PrincipledBSDF {
         pdf_h * jacobian
     }
 
-    fn glass_pdf(&self, v: Vec3, l: Vec3, h: Vec3, eta_i: f64, eta_o: f64, reflect: bool) -> f64 {
+    fn glass_pdf(
+        &self,
+        v: Vec3,
+        l: Vec3,
+        h: Vec3,
+        eta_i: Float,
+        eta_o: Float,
+        reflect: bool,
+    ) -> Float {
         let pdf_h =
             ggx::G1(v, self.roughness) * v.dot(h).abs() * ggx::D(h, self.roughness) / v.z.abs();
 
@@ -184,11 +284,8 @@ impl PrincipledBSDF {
         pdf_h * jacobian
     }
 
-    fn clearcoat_pdf(&self, v: Vec3, l: Vec3, h: Vec3) -> f64 {
-        let pdf_h = ggx::G1(v, 0.25) * v.dot(h).abs() * gtr1::D(l.dot(h).abs(), self.get_alpha_g())
-            / v.z.abs();
-        let jacobian = 1.0 / (4.0 * l.dot(h).abs());
-        pdf_h * jacobian
+    fn clearcoat_pdf(&self, l: Vec3, h: Vec3) -> Float {
+        gtr1::pdf(h, self.get_alpha_g()) / (4.0 * l.dot(h).abs())
     }
 
     // note that the evals here do not include the cosine, only the final public one in
@@ -223,7 +320,15 @@ impl PrincipledBSDF {
         fresnel * g * d / (4.0 * l.z.abs() * v.z.abs())
     }
 
-    fn eval_glass(&self, v: Vec3, l: Vec3, h: Vec3, eta_i: f64, eta_o: f64, reflect: bool) -> Vec3 {
+    fn eval_glass(
+        &self,
+        v: Vec3,
+        l: Vec3,
+        h: Vec3,
+        eta_i: Float,
+        eta_o: Float,
+        reflect: bool,
+    ) -> Vec3 {
         // D term
         let d = ggx::D(h, self.roughness);
 
@@ -246,7 +351,7 @@ impl PrincipledBSDF {
     }
 
     fn eval_clearcoat(&self, v: Vec3, l: Vec3, h: Vec3) -> Vec3 {
-        let d = gtr1::D(l.dot(h).abs(), self.get_alpha_g());
+        let d = gtr1::D(h.z.abs(), self.get_alpha_g());
 
         let g = ggx::G(v, l, 0.25);
 
@@ -260,108 +365,157 @@ impl PrincipledBSDF {
 
 impl BxDFMaterial for PrincipledBSDF {
     fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
-        let (diffuse_wt, specular_wt, glass_wt, clearcoat_wt) = self.lobe_weights();
+        let resolved = self.resolve(info);
+        let this = resolved.as_ref();
+        let (diffuse_wt, specular_wt, glass_wt, clearcoat_wt) = this.lobe_weights();
         let (diffuse_p, specular_p, glass_p, _) =
-            self.lobe_probabilities(diffuse_wt, specular_wt, glass_wt, clearcoat_wt);
+            this.lobe_probabilities(diffuse_wt, specular_wt, glass_wt, clearcoat_wt);
 
-        let r = rand::random::<f64>();
+        let r = rng().gen::<Float>();
         if r < diffuse_p {
-            self.sample_diffuse(info)
+            this.sample_diffuse(info)
         } else if r < diffuse_p + specular_p {
-            self.sample_specular(ray, info)
+            this.sample_specular(ray, info)
         } else if r < diffuse_p + specular_p + glass_p {
-            self.sample_glass(ray, info)
+            this.sample_glass(ray, info)
         } else {
-            self.sample_clearcoat(ray, info)
+            this.sample_clearcoat(ray, info)
         }
     }
 
-    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64 {
-        let (diffuse_wt, specular_wt, glass_wt, clearcoat_wt) = self.lobe_weights();
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let resolved = self.resolve(info);
+        let this = resolved.as_ref();
+        let (diffuse_wt, specular_wt, glass_wt, clearcoat_wt) = this.lobe_weights();
         let (diffuse_p, specular_p, glass_p, clearcoat_p) =
-            self.lobe_probabilities(diffuse_wt, specular_wt, glass_wt, clearcoat_wt);
+            this.lobe_probabilities(diffuse_wt, specular_wt, glass_wt, clearcoat_wt);
 
-        let v = to_local(info.geometric_normal, view_dir);
-        let l = to_local(info.geometric_normal, light_dir);
+        let v = info.geometric_frame().to_local(view_dir);
+        let l = info.geometric_frame().to_local(light_dir);
 
         let reflect = l.z * v.z > 0.0;
         let (eta_i, eta_o) = if info.front_face {
-            (1.0, self.ior)
+            (1.0, this.ior)
         } else {
-            (self.ior, 1.0)
+            (this.ior, 1.0)
         };
 
         let h = if reflect {
             (l + v).normalize() * v.z.signum()
         } else {
-            -(l * eta_o + v * eta_i).normalize()
+            ggx::refraction_half_vector(v, l, eta_i, eta_o)
         };
 
         let mut pdf = 0.0;
         if diffuse_p > 0.0 && reflect {
-            pdf += diffuse_p * self.diffuse_pdf(l)
+            pdf += diffuse_p * this.diffuse_pdf(l)
         }
         if specular_p > 0.0 && reflect {
-            pdf += specular_p * self.specular_pdf(v, l, h)
+            pdf += specular_p * this.specular_pdf(v, l, h)
         }
-        if glass_p > 0.0 {
-            pdf += glass_p * self.glass_pdf(v, l, h, eta_i, eta_o, reflect)
+        if glass_p > 0.0 && ggx::faces_microfacet(v, l, h) {
+            pdf += glass_p * this.glass_pdf(v, l, h, eta_i, eta_o, reflect)
         }
         if clearcoat_p > 0.0 && reflect {
-            pdf += clearcoat_p * self.clearcoat_pdf(v, l, h)
+            pdf += clearcoat_p * this.clearcoat_pdf(l, h)
         }
 
         pdf
     }
 
     fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
-        let base_color = self.base_color.value(info.u, info.v, &info.point);
-        let (diffuse_wt, specular_wt, glass_wt, clearcoat_wt) = self.lobe_weights();
+        let resolved = self.resolve(info);
+        let this = resolved.as_ref();
+        let base_color = info.texture(&this.base_color);
+        let (diffuse_wt, specular_wt, glass_wt, clearcoat_wt) = this.lobe_weights();
         let (diffuse_p, specular_p, glass_p, clearcoat_p) =
-            self.lobe_probabilities(diffuse_wt, specular_wt, glass_wt, clearcoat_wt);
+            this.lobe_probabilities(diffuse_wt, specular_wt, glass_wt, clearcoat_wt);
 
-        let v = to_local(info.geometric_normal, view_dir);
-        let l = to_local(info.geometric_normal, light_dir);
+        let v = info.geometric_frame().to_local(view_dir);
+        let l = info.geometric_frame().to_local(light_dir);
 
         let reflect = l.z * v.z > 0.0;
         let (eta_i, eta_o) = if info.front_face {
-            (1.0, self.ior)
+            (1.0, this.ior)
         } else {
-            (self.ior, 1.0)
+            (this.ior, 1.0)
         };
 
         let h = if reflect {
             (l + v).normalize() * v.z.signum()
         } else {
-            -(l * eta_o + v * eta_i).normalize()
+            ggx::refraction_half_vector(v, l, eta_i, eta_o)
         };
 
+        let c_tint = tint(base_color);
+        let ks = Vec3::ONE.lerp(c_tint, this.specular_tint);
+        let c0 = (this.specular * r0(eta_i / eta_o) * ks).lerp(base_color, this.metallic);
+        let compensation = multiscatter::conductor(c0, v.z, this.roughness);
+
         let mut brdf = Vec3::ZERO;
         if diffuse_p > 0.0 && reflect {
-            let c_tint = tint(base_color);
-            let c_sheen = Vec3::ONE.lerp(c_tint, self.sheen_tint);
-            let sheen_term = self.sheen * c_sheen * schlick_weight(l.dot(h).abs());
-            let diffuse_term = self.eval_diffuse(base_color, v, l, h);
+            let c_sheen = Vec3::ONE.lerp(c_tint, this.sheen_tint);
+            let sheen_term = this.sheen * c_sheen * schlick_weight(l.dot(h).abs());
+            // the diffuse base only gets the light the specular lobe on top of it lets through
+            let through = (Vec3::ONE - this.reflection_albedo(v) * compensation)
+                * this.diffuse_normalization(v);
+            let diffuse_term = this.eval_diffuse(base_color, v, l, h) * through;
             brdf += diffuse_wt * (diffuse_term + sheen_term)
         }
         if specular_p > 0.0 && reflect {
-            let c_tint = tint(base_color);
-            let ks = Vec3::ONE.lerp(c_tint, self.specular_tint);
-            let c0 = (self.specular * r0(eta_i / eta_o) * ks).lerp(base_color, self.metallic);
-
             let metallic_fresnel = fresnel::schlick(c0, l.dot(h));
             let dielectric_fresnel = Vec3::splat(fresnel::dielectric(v, h, eta_i, eta_o));
-            let fresnel = dielectric_fresnel.lerp(metallic_fresnel, self.metallic);
-
-            brdf += specular_wt * self.eval_specular(fresnel, v, l, h)
+            let fresnel = dielectric_fresnel.lerp(metallic_fresnel, this.metallic);
+            brdf += specular_wt * this.eval_specular(fresnel, v, l, h) * compensation
         }
-        if glass_p > 0.0 {
-            brdf += glass_wt * self.eval_glass(v, l, h, eta_i, eta_o, reflect)
+        if glass_p > 0.0 && ggx::faces_microfacet(v, l, h) {
+            let compensation = this
+                .multiscatter
+                .compensation(v.z, this.roughness, info.front_face);
+            brdf += glass_wt * this.eval_glass(v, l, h, eta_i, eta_o, reflect) * compensation
         }
         if clearcoat_p > 0.0 && reflect {
-            brdf += clearcoat_wt * self.eval_clearcoat(v, l, h)
+            brdf += clearcoat_wt * this.eval_clearcoat(v, l, h)
         }
 
         brdf * l.z.abs()
     }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        let resolved = self.resolve(info);
+        let this = resolved.as_ref();
+        // smooth metals and glass, but not a smooth coat over a diffuse base
+        let (diffuse_wt, ..) = this.lobe_weights();
+        this.roughness < SPECULAR_ROUGHNESS && diffuse_wt < 0.5
+    }
+
+    fn normal_map(&self) -> Option<&ImageTexture> {
+        self.normal_map.as_deref()
+    }
+
+    /// the weights mix lobes and only make sense from 0 to 1, outside it the material can
+    /// reflect more light than it gets
+    fn problems(&self) -> Vec<String> {
+        let weights = [
+            ("metallic", self.metallic),
+            ("roughness", self.roughness),
+            ("subsurface", self.subsurface),
+            ("specular", self.specular),
+            ("specular_tint", self.specular_tint),
+            ("spec_trans", self.spec_trans),
+            ("sheen", self.sheen),
+            ("sheen_tint", self.sheen_tint),
+            ("clearcoat", self.clearcoat),
+            ("clearcoat_gloss", self.clearcoat_gloss),
+        ];
+        let mut problems: Vec<String> = weights
+            .iter()
+            .filter(|(_, weight)| !(0.0..=1.0).contains(weight))
+            .map(|(name, weight)| format!("`{name}` is {weight}, outside [0, 1]"))
+            .collect();
+        if !(self.ior > 0.0 && self.ior.is_finite()) {
+            problems.push(format!("`ior` is {}", self.ior));
+        }
+        problems
+    }
 }
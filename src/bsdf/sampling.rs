@@ -1,24 +1,53 @@
-use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::{
+    sampler::rng,
+    vec3::{consts::PI, Float, Vec3},
+};
+
+/// Orthonormal basis around a normal, which becomes the +z axis of the local shading space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub s: Vec3,
+    pub t: Vec3,
+    pub n: Vec3,
+}
 
-use rand::{thread_rng, Rng};
+impl Frame {
+    /// branchless construction from Duff et al., "Building an Orthonormal Basis, Revisited" (2017)
+    pub fn from_normal(n: Vec3) -> Frame {
+        let sign = Float::copysign(1.0, n.z);
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+        Frame {
+            s: Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            t: Vec3::new(b, sign + n.y * n.y * a, -n.y),
+            n,
+        }
+    }
 
-use crate::vec3::{get_rotation_to_z, Vec3};
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.s), v.dot(self.t), v.dot(self.n))
+    }
 
-// transformations
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        self.s * v.x + self.t * v.y + self.n * v.z
+    }
+}
+
+// transformations, for when there is no cached frame to use
 pub fn to_local(normal: Vec3, input_world: Vec3) -> Vec3 {
-    let rot = get_rotation_to_z(normal);
-    rot * input_world
+    Frame::from_normal(normal).to_local(input_world)
 }
 
 pub fn to_world(normal: Vec3, input_local: Vec3) -> Vec3 {
-    let rot = get_rotation_to_z(normal).inverse();
-    rot * input_local
+    Frame::from_normal(normal).to_world(input_local)
 }
 
 pub fn cosine_sample_hemisphere() -> Vec3 {
-    let mut rng = thread_rng();
+    let mut rng = rng();
     let phi = rng.gen_range(0.0..=2.0 * PI);
-    let r2 = rng.gen::<f64>();
+    let r2 = rng.gen::<Float>();
     let r2s = r2.sqrt();
     Vec3::new(r2s * phi.cos(), r2s * phi.sin(), (1.0 - r2).sqrt())
 }
@@ -29,33 +58,45 @@ pub fn cosine_sample_hemisphere() -> Vec3 {
 /// https://hal.science/hal-01509746/document
 #[allow(non_snake_case)]
 pub mod ggx {
-    use std::f64::consts::PI;
-
-    use rand::{thread_rng, Rng};
+    use rand::Rng;
 
-    use crate::vec3::Vec3;
+    use crate::{
+        sampler::rng,
+        vec3::{consts::PI, Float, Vec3},
+    };
 
-    pub fn D(h: Vec3, roughness: f64) -> f64 {
+    pub fn D(h: Vec3, roughness: Float) -> Float {
         let cos_theta = h.z.max(0.001);
         let alpha2 = (roughness * roughness).max(0.001);
         let denom = (alpha2 - 1.0) * (cos_theta * cos_theta) + 1.0;
         alpha2 / (PI * denom * denom)
     }
 
-    pub fn G(v: Vec3, l: Vec3, roughness: f64) -> f64 {
+    pub fn G(v: Vec3, l: Vec3, roughness: Float) -> Float {
         let g1v = G1(v, roughness);
         let g1l = G1(l, roughness);
         g1v * g1l
     }
 
-    pub fn G1(w: Vec3, roughness: f64) -> f64 {
+    pub fn G1(w: Vec3, roughness: Float) -> Float {
         let alpha2 = (roughness * roughness).max(0.001);
         let cos_theta = w.z.abs();
         2.0 * cos_theta / (cos_theta + (cos_theta * cos_theta * (1.0 - alpha2) + alpha2).sqrt())
     }
 
-    pub fn sample_microfacet_normal(v: Vec3, roughness: f64) -> Vec3 {
-        let h = sample_ggx_vndf(v, roughness * roughness);
+    /// the distribution of normals visible from `v`, G1(v) max(0, v.h) D(h) / v.z, which is
+    /// what `sample_microfacet_normal` samples
+    pub fn D_visible(v: Vec3, h: Vec3, roughness: Float) -> Float {
+        if v.z <= 0.0 {
+            return 0.0;
+        }
+        G1(v, roughness) * v.dot(h).max(0.0) * D(h, roughness) / v.z
+    }
+
+    /// the microfacet normal that refracts `v` into `l` going from index `eta_i` to `eta_o`,
+    /// turned to the side of the surface normal like the ones `sample_vndf` returns
+    pub fn refraction_half_vector(v: Vec3, l: Vec3, eta_i: Float, eta_o: Float) -> Vec3 {
+        let h = -(l * eta_o + v * eta_i).normalize();
         if h.z < 0.0 {
             -h
         } else {
@@ -63,42 +104,56 @@ pub mod ggx {
         }
     }
 
-    fn sample_ggx_vndf(v: Vec3, a2: f64) -> Vec3 {
-        // stretch view
-        let v = Vec3::new(v.x * a2, v.y * a2, v.z).normalize();
+    /// whether `v` and `l` both see the microfacet `h` from the same side they see the surface
+    /// from, light can't scatter off the back of a microfacet
+    pub fn faces_microfacet(v: Vec3, l: Vec3, h: Vec3) -> bool {
+        v.dot(h) * v.z > 0.0 && l.dot(h) * l.z > 0.0
+    }
+
+    /// a normal from the distribution of normals visible from `v`. views from below the surface
+    /// are mirrored above it, so the normal is always in the upper hemisphere
+    pub fn sample_microfacet_normal(v: Vec3, roughness: Float) -> Vec3 {
+        let v = if v.z < 0.0 { -v } else { v };
+        let mut rng = rng();
+        sample_vndf(v, roughness, rng.gen(), rng.gen())
+    }
 
-        // orthonormal basis
-        let t1 = if v.z < 0.9999 {
-            v.cross(Vec3::Z).normalize()
+    /// Heitz, "Sampling the GGX Distribution of Visible Normals" (2018), for `v` above the
+    /// surface and two uniform random numbers
+    pub fn sample_vndf(v: Vec3, roughness: Float, u1: Float, u2: Float) -> Vec3 {
+        // the same clamp as D and G1
+        let alpha = (roughness * roughness).max(0.001).sqrt();
+
+        // stretch the view into the configuration where the distribution is a hemisphere
+        let vh = Vec3::new(alpha * v.x, alpha * v.y, v.z).normalize();
+
+        // orthonormal basis around it
+        let len_sq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len_sq > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / len_sq.sqrt()
         } else {
             Vec3::X
         };
-        let t2 = t1.cross(v);
-
-        // sample
-        let e1 = thread_rng().gen::<f64>();
-        let e2 = thread_rng().gen::<f64>();
-        let a = 1.0 / (1.0 + v.z);
-        let r = e1.sqrt();
-        let phi = if e2 < a {
-            e2 / a * PI
-        } else {
-            PI + (e2 - a) / (1.0 - a) * PI
-        };
+        let t2 = vh.cross(t1);
+
+        // a point on the projected disk, squashed on the side the hemisphere hides
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
         let p1 = r * phi.cos();
-        let p2 = r * phi.sin() * if e2 < a { 1.0 } else { v.z };
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
 
-        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
-        let unstretched = Vec3::new(a2 * n.x, a2 * n.y, n.z.max(0.0));
-        unstretched.normalize()
+        // back onto the hemisphere, then unstretch
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vec3::new(alpha * nh.x, alpha * nh.y, nh.z.max(0.0)).normalize()
     }
 
     #[allow(dead_code)]
     // keeping the ndf for reference
-    fn sample_ggx(_v: Vec3, a2: f64) -> Vec3 {
-        let mut rng = thread_rng();
-        let e1: f64 = rng.gen();
-        let e2: f64 = rng.gen();
+    fn sample_ggx(_v: Vec3, a2: Float) -> Vec3 {
+        let mut rng = rng();
+        let e1: Float = rng.gen();
+        let e2: Float = rng.gen();
 
         let theta = ((a2 * e1.sqrt()) / (1.0 - e1).sqrt()).atan();
         let phi = e2 * 2.0 * PI;
@@ -112,24 +167,30 @@ pub mod ggx {
 
 #[allow(non_snake_case)]
 pub mod gtr1 {
-    use std::f64::consts::PI;
-
-    use rand::{thread_rng, Rng};
+    use rand::Rng;
 
-    use crate::vec3::Vec3;
+    use crate::{
+        sampler::rng,
+        vec3::{consts::PI, Float, Vec3},
+    };
 
-    pub fn D(abs_cos_theta: f64, alpha_g: f64) -> f64 {
+    pub fn D(abs_cos_theta: Float, alpha_g: Float) -> Float {
         let alpha2 = alpha_g * alpha_g;
         let t = 1.0 + (alpha2 - 1.0) * abs_cos_theta * abs_cos_theta;
-        (alpha2 - 1.0) / (PI * t * alpha2.log2())
+        (alpha2 - 1.0) / (PI * t * alpha2.ln())
+    }
+
+    /// the pdf of the normals `sample_microfacet_normal` returns, D(h) cos(theta_h)
+    pub fn pdf(h: Vec3, alpha_g: Float) -> Float {
+        D(h.z.abs(), alpha_g) * h.z.abs()
     }
 
-    pub fn sample_microfacet_normal(alpha: f64) -> Vec3 {
-        let e1 = thread_rng().gen::<f64>();
-        let e2 = thread_rng().gen::<f64>();
+    pub fn sample_microfacet_normal(alpha: Float) -> Vec3 {
+        let e1 = rng().gen::<Float>();
+        let e2 = rng().gen::<Float>();
 
         let alpha2 = alpha * alpha;
-        let cos_theta = (1.0 - alpha2.powf(1.0 - e1)) / (1.0 - alpha2);
+        let cos_theta = ((1.0 - alpha2.powf(1.0 - e1)) / (1.0 - alpha2)).sqrt();
         let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
         let phi = 2.0 * PI * e2;
 
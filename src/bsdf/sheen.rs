@@ -1,20 +1,19 @@
-use std::f64::consts::PI;
-
-use crate::{hittable::HitInfo, ray::Ray, vec3::Vec3};
-
-use super::{
-    sampling::{cosine_sample_hemisphere, to_local, to_world},
-    tint, BxDFMaterial,
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    vec3::{consts::PI, Float, Vec3},
 };
 
+use super::{sampling::cosine_sample_hemisphere, tint, BxDFMaterial};
+
 #[derive(Clone)]
 pub struct SheenBRDF {
     base_color: Vec3,
-    sheen_tint: f64,
+    sheen_tint: Float,
 }
 
 impl SheenBRDF {
-    pub fn new(base_color: Vec3, sheen_tint: f64) -> Self {
+    pub fn new(base_color: Vec3, sheen_tint: Float) -> Self {
         Self {
             base_color,
             sheen_tint,
@@ -25,20 +24,24 @@ impl SheenBRDF {
 impl BxDFMaterial for SheenBRDF {
     fn sample(&self, _ray: &Ray, info: &HitInfo) -> Option<Vec3> {
         let dir_local = cosine_sample_hemisphere();
-        Some(to_world(info.geometric_normal, dir_local))
+        Some(info.geometric_frame().to_world(dir_local))
     }
 
-    fn pdf(&self, _view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> f64 {
-        let l = to_local(info.geometric_normal, light_dir);
-        l.z.abs() / PI
+    fn pdf(&self, _view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        let l = info.geometric_frame().to_local(light_dir);
+        // `sample` only goes into the hemisphere above the surface
+        l.z.max(0.0) / PI
     }
 
     fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
-        let v = to_local(info.geometric_normal, view_dir);
-        let l = to_local(info.geometric_normal, light_dir);
+        let v = info.geometric_frame().to_local(view_dir);
+        let l = info.geometric_frame().to_local(light_dir);
+        if l.z <= 0.0 {
+            return Vec3::ZERO;
+        }
         let h = (v + l).normalize();
         let c_tint = tint(self.base_color);
         let c_sheen = Vec3::ONE.lerp(c_tint, self.sheen_tint);
-        c_sheen * (1.0 - l.dot(h).abs()).powi(5) * (l.z.abs())
+        c_sheen * (1.0 - l.dot(h).abs()).powi(5) * l.z
     }
 }
@@ -0,0 +1,116 @@
+use std::sync::OnceLock;
+
+use crate::{
+    hittable::HitInfo,
+    integrator::AoSettings,
+    ray::Ray,
+    texture::ImageTexture,
+    vec3::{Float, Vec3},
+};
+
+use super::{BxDFMaterial, MatPtr};
+
+/// What the back of a surface looks like, the side facing away from its normal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sidedness {
+    /// shaded the same as the front
+    #[default]
+    Shaded,
+    /// absorbs everything and doesn't emit, e.g. the back of a light panel
+    Black,
+    /// rays go through it, e.g. to look into open meshes or out of a box the camera is in
+    Invisible,
+}
+
+/// Wraps a material to change how its back faces look, everything else is passed through
+#[derive(Clone)]
+pub struct Sided {
+    bxdf: MatPtr,
+    back: Sidedness,
+}
+
+impl Sided {
+    pub fn new(bxdf: MatPtr, back: Sidedness) -> Sided {
+        Sided { bxdf, back }
+    }
+}
+
+impl BxDFMaterial for Sided {
+    fn sample(&self, ray: &Ray, info: &HitInfo) -> Option<Vec3> {
+        self.bxdf.sample(ray, info)
+    }
+
+    fn pdf(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Float {
+        self.bxdf.pdf(view_dir, light_dir, info)
+    }
+
+    fn eval(&self, view_dir: Vec3, light_dir: Vec3, info: &HitInfo) -> Vec3 {
+        self.bxdf.eval(view_dir, light_dir, info)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: Vec3) -> Vec3 {
+        self.bxdf.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.bxdf.is_emissive()
+    }
+
+    fn normal_map(&self) -> Option<&ImageTexture> {
+        self.bxdf.normal_map()
+    }
+
+    fn ao_settings(&self) -> Option<AoSettings> {
+        self.bxdf.ao_settings()
+    }
+
+    fn is_specular(&self, info: &HitInfo) -> bool {
+        self.bxdf.is_specular(info)
+    }
+
+    fn shadow_transmittance(&self, dir: Vec3, info: &HitInfo) -> Vec3 {
+        self.bxdf.shadow_transmittance(dir, info)
+    }
+
+    fn opacity(&self, info: &HitInfo) -> Float {
+        self.bxdf.opacity(info)
+    }
+
+    fn sidedness(&self) -> Sidedness {
+        self.back
+    }
+
+    fn problems(&self) -> Vec<String> {
+        self.bxdf.problems()
+    }
+}
+
+/// A surface that ends every path that hits it, for the back of `Sidedness::Black` materials
+#[derive(Debug, Clone, Copy)]
+pub struct Black;
+
+impl Black {
+    /// one shared instance, so hits don't allocate a new one
+    pub fn shared() -> MatPtr {
+        static BLACK: OnceLock<MatPtr> = OnceLock::new();
+        BLACK.get_or_init(|| std::sync::Arc::new(Black)).clone()
+    }
+}
+
+impl BxDFMaterial for Black {
+    fn sample(&self, _ray: &Ray, _info: &HitInfo) -> Option<Vec3> {
+        None
+    }
+
+    fn pdf(&self, _view_dir: Vec3, _light_dir: Vec3, _info: &HitInfo) -> Float {
+        0.0
+    }
+
+    fn eval(&self, _view_dir: Vec3, _light_dir: Vec3, _info: &HitInfo) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    fn scatter(&self, _ray: &Ray, _hit_info: &HitInfo) -> Option<(Vec3, Ray)> {
+        None
+    }
+}
@@ -0,0 +1,536 @@
+//! Statistical checks for BSDF implementations.
+//!
+//! - the white furnace test lights a surface with a uniform white environment; the estimated albedo
+//!   `E[eval / pdf]` must not exceed 1 in any channel, or the material creates energy
+//! - the chi-squared test compares a histogram of `sample()` directions with the counts `pdf()`
+//!   predicts, so sampling and pdf that disagree show up as a tiny p-value
+//!
+//! `eval` is expected to include the cosine term, like the path integrator assumes.
+//!
+//! `SamplingCheck` runs the chi-squared test during a render, on the hits the integrator
+//! actually shades, to catch materials that only go wrong with real textures and normals.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use rand::Rng;
+
+use crate::{
+    hittable::HitInfo,
+    ray::Ray,
+    sampler::rng,
+    texture::{CheckerTexture, SolidTexture},
+    vec3::{consts::PI, Float, Vec3, VectorExt},
+};
+
+use super::{
+    blend::BlendMaterial, clearcoat::ClearcoatBRDF, cloth::ClothBSDF, coat::CoatMaterial,
+    diffuse::DiffuseBRDF, glass::GlassBSDF, hair::HairBSDF, metal::MetalBRDF, mix::MixBxDf,
+    principled::PrincipledBSDF, sampling::Frame, sheen::SheenBRDF, BxDFMaterial, MatPtr,
+};
+
+/// cosines of the view directions every test is run with, the last one is close to grazing
+pub const VIEW_COSINES: [Float; 3] = [0.9, 0.5, 0.1];
+
+const COS_THETA_BINS: usize = 16;
+const PHI_BINS: usize = 32;
+const MIN_EXPECTED: Float = 5.0;
+const SIGNIFICANCE: Float = 0.01;
+
+#[derive(Debug, Clone)]
+pub struct FurnaceResult {
+    /// cosine between the view direction and the normal, negative when viewed from inside
+    pub view_cos: Float,
+    pub albedo: Vec3,
+    /// samples whose weight was NaN or infinite
+    pub invalid: usize,
+}
+
+impl FurnaceResult {
+    pub fn passed(&self) -> bool {
+        // a little slack for the Monte Carlo noise
+        self.invalid == 0 && self.albedo.max_element() <= 1.01
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChiSquaredResult {
+    pub view_cos: Float,
+    pub chi2: Float,
+    pub dof: usize,
+    pub p_value: Float,
+    /// integral of the pdf over the sphere, should be 1 (less if `sample` can fail)
+    pub pdf_integral: Float,
+}
+
+impl ChiSquaredResult {
+    pub fn passed(&self) -> bool {
+        self.p_value >= SIGNIFICANCE
+    }
+}
+
+pub struct BsdfReport {
+    pub name: String,
+    pub furnace: Vec<FurnaceResult>,
+    pub chi_squared: Vec<ChiSquaredResult>,
+}
+
+impl BsdfReport {
+    pub fn passed(&self) -> bool {
+        self.furnace.iter().all(|r| r.passed()) && self.chi_squared.iter().all(|r| r.passed())
+    }
+}
+
+impl fmt::Display for BsdfReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = |passed: bool| if passed { "ok" } else { "FAILED" };
+        writeln!(f, "{}: {}", self.name, verdict(self.passed()))?;
+        for r in self.furnace.iter() {
+            writeln!(
+                f,
+                "  furnace  cos {:+.1}: albedo {:.3} {:.3} {:.3}, {} invalid samples  {}",
+                r.view_cos,
+                r.albedo.x,
+                r.albedo.y,
+                r.albedo.z,
+                r.invalid,
+                verdict(r.passed())
+            )?;
+        }
+        for r in self.chi_squared.iter() {
+            writeln!(
+                f,
+                "  chi2     cos {:+.1}: chi2 {:.1} with {} dof, p = {:.4}, pdf integral {:.3}  {}",
+                r.view_cos,
+                r.chi2,
+                r.dof,
+                r.p_value,
+                r.pdf_integral,
+                verdict(r.passed())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// hit at the origin of a surface with normal +z, seen from `view_dir`
+fn local_hit(material: &MatPtr, view_dir: Vec3) -> (Ray, HitInfo) {
+    let ray = Ray::new(view_dir, -view_dir, 0.0);
+    let info = HitInfo::new(&ray, Vec3::ZERO, Vec3::Z, 1.0, material.clone(), 0.5, 0.5);
+    (ray, info)
+}
+
+fn view_dir(view_cos: Float) -> Vec3 {
+    let sin = (1.0 - view_cos * view_cos).max(0.0).sqrt();
+    Vec3::new(sin, 0.0, view_cos)
+}
+
+fn direction(cos_theta: Float, phi: Float) -> Vec3 {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub fn white_furnace(material: &MatPtr, view_cos: Float, samples: usize) -> FurnaceResult {
+    let v = view_dir(view_cos);
+    let (ray, info) = local_hit(material, v);
+    let mut sum = Vec3::ZERO;
+    let mut invalid = 0;
+    for _ in 0..samples {
+        let Some(l) = material.sample(&ray, &info) else {
+            continue;
+        };
+        let pdf = material.pdf(v, l, &info);
+        if pdf <= 0.0 {
+            continue;
+        }
+        let weight = material.eval(v, l, &info) / pdf;
+        if weight.is_finite() {
+            sum += weight;
+        } else {
+            invalid += 1;
+        }
+    }
+    FurnaceResult {
+        view_cos,
+        albedo: sum / samples as Float,
+        invalid,
+    }
+}
+
+pub fn chi_squared(material: &MatPtr, view_cos: Float, samples: usize) -> ChiSquaredResult {
+    let v = view_dir(view_cos);
+    let (ray, info) = local_hit(material, v);
+    let frame = Frame {
+        s: Vec3::X,
+        t: Vec3::Y,
+        n: Vec3::Z,
+    };
+    chi_squared_in(material.as_ref(), &ray, &info, &frame, samples).0
+}
+
+/// the chi-squared test at any hit, binning directions by their angles in `frame`. also
+/// returns the fraction of the light `eval` lets through in directions the pdf never samples
+fn chi_squared_in(
+    material: &dyn BxDFMaterial,
+    ray: &Ray,
+    info: &HitInfo,
+    frame: &Frame,
+    samples: usize,
+) -> (ChiSquaredResult, Float) {
+    let v = -ray.direction();
+    let view_cos = frame.to_local(v).z;
+    let bin_of = |dir: Vec3| -> usize {
+        let dir = frame.to_local(dir);
+        let c = ((dir.z + 1.0) / 2.0 * COS_THETA_BINS as Float) as usize;
+        let phi = dir.y.atan2(dir.x).rem_euclid(2.0 * PI);
+        let p = (phi / (2.0 * PI) * PHI_BINS as Float) as usize;
+        c.min(COS_THETA_BINS - 1) * PHI_BINS + p.min(PHI_BINS - 1)
+    };
+
+    let mut observed = vec![0.0; COS_THETA_BINS * PHI_BINS];
+    for _ in 0..samples {
+        if let Some(l) = material.sample(ray, info) {
+            observed[bin_of(l.normalize())] += 1.0;
+        }
+    }
+
+    // integrate the pdf over each bin, cos theta and phi make an area preserving parameterization.
+    // fine enough for the narrow lobes of smooth materials seen at grazing angles
+    let sub = 32;
+    let d_cos = 2.0 / (COS_THETA_BINS * sub) as Float;
+    let d_phi = 2.0 * PI / (PHI_BINS * sub) as Float;
+    let mut expected = vec![0.0; COS_THETA_BINS * PHI_BINS];
+    let (mut eval_total, mut eval_unsampled) = (0.0, 0.0);
+    for i in 0..COS_THETA_BINS * sub {
+        for j in 0..PHI_BINS * sub {
+            let local = direction(
+                -1.0 + (i as Float + 0.5) * d_cos,
+                (j as Float + 0.5) * d_phi,
+            );
+            let l = frame.to_world(local);
+            let pdf = material.pdf(v, l, info);
+            if pdf.is_finite() {
+                expected[(i / sub) * PHI_BINS + j / sub] += pdf * d_cos * d_phi;
+            }
+            let eval = material.eval(v, l, info).luminance();
+            if eval.is_finite() {
+                eval_total += eval;
+                if pdf <= 0.0 {
+                    eval_unsampled += eval;
+                }
+            }
+        }
+    }
+    let unsampled = if eval_total > 0.0 {
+        eval_unsampled / eval_total
+    } else {
+        0.0
+    };
+    let pdf_integral: Float = expected.iter().sum();
+    expected.iter_mut().for_each(|e| *e *= samples as Float);
+
+    // bins with too few expected samples are pooled, as the statistic is unreliable for them
+    let (mut chi2, mut dof) = (0.0, 0usize);
+    let (mut pooled_obs, mut pooled_exp) = (0.0, 0.0);
+    for (obs, exp) in observed.iter().zip(expected.iter()) {
+        if *exp < MIN_EXPECTED {
+            pooled_obs += obs;
+            pooled_exp += exp;
+        } else {
+            chi2 += (obs - exp) * (obs - exp) / exp;
+            dof += 1;
+        }
+    }
+    if pooled_exp >= MIN_EXPECTED {
+        chi2 += (pooled_obs - pooled_exp) * (pooled_obs - pooled_exp) / pooled_exp;
+        dof += 1;
+    } else if pooled_obs > MIN_EXPECTED {
+        // samples landed where the pdf says they can't
+        chi2 = Float::INFINITY;
+    }
+    let dof = dof.saturating_sub(1).max(1);
+
+    let result = ChiSquaredResult {
+        view_cos,
+        chi2,
+        dof,
+        p_value: chi_squared_p_value(chi2, dof),
+        pdf_integral,
+    };
+    (result, unsampled)
+}
+
+/// Checks the sampling of materials while rendering: a random `rate` of the bounces run the
+/// chi-squared test at the hit, and materials that fail are logged, each type only once.
+/// slow, only meant for tracking down fireflies and bias
+#[derive(Debug, Clone)]
+pub struct SamplingCheck {
+    pub rate: Float,
+    /// directions sampled per check
+    pub samples: usize,
+    reported: Arc<Mutex<HashSet<&'static str>>>,
+}
+
+impl SamplingCheck {
+    pub fn new(rate: Float, samples: usize) -> SamplingCheck {
+        SamplingCheck {
+            rate,
+            samples,
+            reported: Arc::default(),
+        }
+    }
+
+    /// maybe check the material at `info`, seen along `ray`. returns the failure it logged
+    pub fn check(&self, ray: &Ray, info: &HitInfo) -> Option<String> {
+        let material = info.mat.as_ref();
+        // mirrors and clear glass have delta pdfs that no histogram can match
+        if rng().gen::<Float>() >= self.rate || material.is_specular(info) {
+            return None;
+        }
+        let name = material.name();
+        if self.reported.lock().unwrap().contains(name) {
+            return None;
+        }
+
+        let (result, unsampled) =
+            chi_squared_in(material, ray, info, info.shading_frame(), self.samples);
+        let mut problems = vec![];
+        if !result.passed() {
+            problems.push(format!(
+                "samples don't follow the pdf (chi2 {:.1} with {} dof, p = {:.4}), the pdf integrates to {:.3}",
+                result.chi2, result.dof, result.p_value, result.pdf_integral
+            ));
+        }
+        if unsampled > 0.01 {
+            problems.push(format!(
+                "{:.1}% of the reflected light is in directions with pdf 0",
+                100.0 * unsampled
+            ));
+        }
+        if problems.is_empty() || !self.reported.lock().unwrap().insert(name) {
+            return None;
+        }
+        let message = format!(
+            "Sampling check failed for {name} at {} with the view at cos {:.2}: {}",
+            info.point,
+            result.view_cos,
+            problems.join(", ")
+        );
+        log::warn!("{message}");
+        Some(message)
+    }
+}
+
+impl Default for SamplingCheck {
+    fn default() -> Self {
+        Self::new(1e-4, 20_000)
+    }
+}
+
+/// run both tests from every view angle, and from inside too when the material transmits light
+pub fn check_bsdf(name: &str, material: &MatPtr, transmissive: bool, samples: usize) -> BsdfReport {
+    let mut cosines = VIEW_COSINES.to_vec();
+    if transmissive {
+        cosines.extend(VIEW_COSINES.iter().map(|c| -c));
+    }
+    BsdfReport {
+        name: name.to_string(),
+        furnace: cosines
+            .iter()
+            .map(|&c| white_furnace(material, c, samples))
+            .collect(),
+        chi_squared: cosines
+            .iter()
+            .map(|&c| chi_squared(material, c, samples))
+            .collect(),
+    }
+}
+
+/// every built-in BSDF with a few representative parameters, as (name, material, transmissive)
+pub fn builtin_bsdfs() -> Vec<(String, MatPtr, bool)> {
+    let white = Vec3::ONE;
+    let tex = |c: Vec3| Arc::new(SolidTexture::new(c));
+    let tex1 = |x: Float| Arc::new(SolidTexture::new(x));
+    let principled = |metallic: Float, roughness: Float, spec_trans: Float| -> MatPtr {
+        Arc::new(PrincipledBSDF::new(
+            tex(white),
+            metallic,
+            roughness,
+            0.0,
+            0.5,
+            0.0,
+            1.5,
+            spec_trans,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+        ))
+    };
+    let diffuse: MatPtr = Arc::new(DiffuseBRDF::from_rgb(white));
+    let rough_metal: MatPtr = Arc::new(MetalBRDF::from_rgb(white, 0.5));
+    // the wrappers are tested over cloth, which samples only the side it's seen from
+    let cloth: MatPtr = Arc::new(ClothBSDF::from_rgb(white, white, 0.5));
+    vec![
+        (String::from("diffuse"), diffuse.clone(), false),
+        (
+            String::from("diffuse roughness 1"),
+            Arc::new(DiffuseBRDF::from_rgb(white).with_roughness(Arc::new(SolidTexture::new(1.0)))),
+            false,
+        ),
+        (
+            String::from("metal roughness 0.5"),
+            rough_metal.clone(),
+            false,
+        ),
+        (
+            String::from("metal roughness 0.1"),
+            Arc::new(MetalBRDF::from_rgb(white, 0.1)),
+            false,
+        ),
+        (
+            String::from("glass roughness 0.3"),
+            Arc::new(GlassBSDF::new(
+                tex(white),
+                Arc::new(SolidTexture::new(0.3)),
+                0.0,
+                1.5,
+            )),
+            true,
+        ),
+        (
+            String::from("clearcoat gloss 0.5"),
+            Arc::new(ClearcoatBRDF::new(0.5)),
+            false,
+        ),
+        (
+            String::from("sheen"),
+            Arc::new(SheenBRDF::new(white, 0.0)),
+            false,
+        ),
+        (String::from("cloth roughness 0.5"), cloth.clone(), false),
+        (
+            String::from("cloth roughness 0.1"),
+            Arc::new(ClothBSDF::from_rgb(Vec3::splat(0.5), white, 0.1)),
+            false,
+        ),
+        (
+            String::from("hair"),
+            Arc::new(HairBSDF::from_rgb(white, 0.3, 0.3)),
+            true,
+        ),
+        (
+            String::from("mix diffuse/metal"),
+            Arc::new(MixBxDf::new(0.5, diffuse.clone(), rough_metal.clone())),
+            false,
+        ),
+        (
+            String::from("blend cloth/metal by checker"),
+            Arc::new(BlendMaterial::new(
+                cloth.clone(),
+                rough_metal.clone(),
+                Arc::new(CheckerTexture::new(0.1, tex1(0.3), tex1(0.8))),
+            )),
+            false,
+        ),
+        (
+            String::from("coat roughness 0.2 over cloth"),
+            Arc::new(CoatMaterial::new(cloth.clone(), tex1(0.2), 1.5)),
+            false,
+        ),
+        (
+            String::from("principled dielectric"),
+            principled(0.0, 0.5, 0.0),
+            false,
+        ),
+        (
+            String::from("principled metallic"),
+            principled(1.0, 0.3, 0.0),
+            false,
+        ),
+        (
+            String::from("principled transmissive"),
+            principled(0.0, 0.3, 1.0),
+            true,
+        ),
+    ]
+}
+
+/// probability of a chi-squared statistic at least this large, if the pdf were right
+pub fn chi_squared_p_value(chi2: Float, dof: usize) -> Float {
+    if !chi2.is_finite() {
+        return 0.0;
+    }
+    regularized_gamma_q(dof as Float / 2.0, chi2 / 2.0)
+}
+
+// upper regularized incomplete gamma function Q(a, x), Numerical Recipes 6.2
+fn regularized_gamma_q(a: Float, x: Float) -> Float {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    if x < a + 1.0 {
+        // series for P(a, x)
+        let mut sum = 1.0 / a;
+        let mut term = sum;
+        let mut n = a;
+        for _ in 0..500 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-14 {
+                break;
+            }
+        }
+        1.0 - sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+    } else {
+        // continued fraction for Q(a, x), modified Lentz
+        let tiny = Float::MIN_POSITIVE;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..500 {
+            let an = -(i as Float) * (i as Float - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-14 {
+                break;
+            }
+        }
+        (-x + a * x.ln() - ln_gamma(a)).exp() * h
+    }
+}
+
+// Lanczos approximation, the coefficients are rounded with the `f32` feature
+#[allow(clippy::excessive_precision)]
+fn ln_gamma(x: Float) -> Float {
+    let coefficients = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for (i, c) in coefficients.iter().enumerate() {
+        series += c / (x + 1.0 + i as Float);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
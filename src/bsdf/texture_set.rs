@@ -0,0 +1,179 @@
+//! Materials from folders of PBR textures, as they come from texture libraries.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::imageops;
+
+use crate::{
+    color::ColorSpace,
+    texture::{decoded, ImageTexture, LuminanceTexture, SolidTexture, Texture},
+    texture_cache::TextureCache,
+    vec3::{Float, Vec3},
+};
+
+use super::principled::PrincipledBSDF;
+
+/// The maps of a PBR texture set, found in a folder by their file names. Understands the naming
+/// of Poliigon (`Bricks_COL_2K.jpg`, `_NRM`, `_ROUGHNESS`, `_GLOSS`, `_METALNESS`, `_AO`),
+/// ambientCG (`Bricks_2K_Color.jpg`, `_NormalGL`, `_NormalDX`, `_Roughness`, `_Metalness`,
+/// `_AmbientOcclusion`) and the usual generic names like `albedo`, `basecolor` or `diffuse`
+#[derive(Debug, Clone, Default)]
+pub struct TextureSet {
+    pub base_color: Option<PathBuf>,
+    pub normal: Option<PathBuf>,
+    /// a normal map with green pointing down (DirectX), only used without an OpenGL one
+    pub normal_dx: Option<PathBuf>,
+    pub roughness: Option<PathBuf>,
+    /// the inverse of roughness, only used without a roughness map
+    pub gloss: Option<PathBuf>,
+    pub metallic: Option<PathBuf>,
+    pub ambient_occlusion: Option<PathBuf>,
+    /// a height map, which the material doesn't use but a `Displacement` can
+    pub displacement: Option<PathBuf>,
+    /// the working space the base color is decoded into from sRGB, None reads it as is. the
+    /// other maps are data and always read as they are
+    pub color_space: Option<ColorSpace>,
+}
+
+impl TextureSet {
+    /// look through the images in `dir`. when several files fit a map, PNGs and JPEGs are
+    /// preferred since they're the most likely to load, then the first by name
+    pub fn find(dir: &Path) -> io::Result<TextureSet> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| image_rank(path).is_some())
+            .collect();
+        files.sort_by_key(|path| (image_rank(path), path.clone()));
+
+        let mut set = TextureSet::default();
+        for path in files {
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let slot = match map_name(stem) {
+                Some(Map::Color) => &mut set.base_color,
+                Some(Map::Normal) => &mut set.normal,
+                Some(Map::NormalDx) => &mut set.normal_dx,
+                Some(Map::Roughness) => &mut set.roughness,
+                Some(Map::Gloss) => &mut set.gloss,
+                Some(Map::Metallic) => &mut set.metallic,
+                Some(Map::Ao) => &mut set.ambient_occlusion,
+                Some(Map::Displacement) => &mut set.displacement,
+                None => continue,
+            };
+            slot.get_or_insert(path);
+        }
+        Ok(set)
+    }
+
+    /// a principled material with every map of the set wired up. ambient occlusion is multiplied
+    /// into the base color, everything the set has no map for gets the usual defaults
+    pub fn material(&self) -> PrincipledBSDF {
+        let base_color: Arc<dyn Texture<Vec3>> = match &self.base_color {
+            Some(path) => {
+                let color: Arc<dyn Texture<Vec3>> = if let Some(ao) = &self.ambient_occlusion {
+                    let mut color = edit(path);
+                    multiply(&mut color, &load(ao));
+                    Arc::new(color)
+                } else {
+                    load(path)
+                };
+                decoded(color, self.color_space)
+            }
+            None => Arc::new(SolidTexture::new(Vec3::splat(0.8))),
+        };
+        let mut material = PrincipledBSDF::new(
+            base_color, 0.0, 0.5, 0.0, 0.5, 0.0, 1.5, 0.0, 0.0, 0.0, 0.0, 0.0,
+        );
+
+        if let Some(path) = &self.roughness {
+            material = material.with_roughness_map(grey(load(path)));
+        } else if let Some(path) = &self.gloss {
+            let mut gloss = edit(path);
+            imageops::invert(&mut gloss.img);
+            material = material.with_roughness_map(grey(gloss));
+        }
+        if let Some(path) = &self.metallic {
+            material = material.with_metallic_map(grey(load(path)));
+        }
+        if let Some(path) = &self.normal {
+            material = material.with_normal_map(load(path));
+        } else if let Some(path) = &self.normal_dx {
+            let mut normal = edit(path);
+            for pixel in normal.img.pixels_mut() {
+                pixel[1] = 255 - pixel[1];
+            }
+            material = material.with_normal_map(Arc::new(normal));
+        }
+        material
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Map {
+    Color,
+    Normal,
+    NormalDx,
+    Roughness,
+    Gloss,
+    Metallic,
+    Ao,
+    Displacement,
+}
+
+/// the image at `path`, shared with other materials through the texture cache
+fn load(path: &Path) -> Arc<ImageTexture> {
+    TextureCache::global().get(&path.to_string_lossy())
+}
+
+/// a copy of the image at `path` to change, so the cached one stays as it is
+fn edit(path: &Path) -> ImageTexture {
+    ImageTexture::from_image(load(path).img.clone())
+}
+
+/// a map of single numbers, e.g. roughness
+fn grey(image: impl Into<Arc<ImageTexture>>) -> Arc<dyn Texture<Float>> {
+    Arc::new(LuminanceTexture::new(image.into()))
+}
+
+/// which map a file is for, going by the last word of its name that names one, since the
+/// name of the material comes first (`Metal_Plates_Color` is a color map)
+fn map_name(stem: &str) -> Option<Map> {
+    stem.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .rev()
+        .find_map(|word| match word {
+            "color" | "col" | "albedo" | "basecolor" | "diffuse" | "diff" => Some(Map::Color),
+            "normal" | "normalgl" | "nrm" | "nor" => Some(Map::Normal),
+            "normaldx" => Some(Map::NormalDx),
+            "roughness" | "rough" => Some(Map::Roughness),
+            "gloss" | "glossiness" => Some(Map::Gloss),
+            "metalness" | "metallic" => Some(Map::Metallic),
+            "ao" | "ambientocclusion" | "occlusion" => Some(Map::Ao),
+            "displacement" | "disp" | "height" => Some(Map::Displacement),
+            _ => None,
+        })
+}
+
+/// how much to prefer an image of this type, or none for files that aren't images
+fn image_rank(path: &Path) -> Option<usize> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    ["png", "jpg", "jpeg", "tga", "bmp", "tif", "tiff"]
+        .iter()
+        .position(|ext| *ext == extension)
+}
+
+/// multiply `color` by `mask`, stretching the mask if it's a different size
+fn multiply(color: &mut ImageTexture, mask: &ImageTexture) {
+    let (width, height) = color.img.dimensions();
+    let mask = imageops::resize(&mask.img, width, height, imageops::FilterType::Triangle);
+    for (pixel, mask) in color.img.pixels_mut().zip(mask.pixels()) {
+        for c in 0..3 {
+            pixel[c] = ((pixel[c] as u16 * mask[c] as u16) / 255) as u8;
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::ops::AddAssign;
+
+use crate::vec3::{Float, Vec3, VectorExt};
+
+/// Adaptive sampling settings. The image is rendered in square tiles, and a tile stops taking
+/// samples once its estimated noise drops below `threshold`, so `samples_per_pixel` becomes the
+/// most a tile can take.
+///
+/// The noise is measured on the displayed value (exposed, clamped and gamma corrected luminance),
+/// so the threshold means the same at any exposure and dark regions aren't starved of samples.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    pub tile_size: usize,
+    /// samples every tile takes before its noise is first estimated, and after each estimate
+    pub min_samples: usize,
+    /// standard error of the displayed value, averaged over the tile, where sampling stops
+    pub threshold: Float,
+}
+
+impl AdaptiveSampling {
+    pub fn new(tile_size: usize, min_samples: usize, threshold: Float) -> AdaptiveSampling {
+        AdaptiveSampling {
+            tile_size,
+            min_samples,
+            threshold,
+        }
+    }
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self::new(16, 16, 0.01)
+    }
+}
+
+/// Running sums of one pixel's samples
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PixelStats {
+    pub color: Vec3,
+    value: Float,
+    value_sq: Float,
+}
+
+impl PixelStats {
+    /// `display` is the sample as it will be seen, between 0 and 1
+    pub fn add(&mut self, color: Vec3, display: Float) {
+        self.color += color;
+        self.value += display;
+        self.value_sq += display * display;
+    }
+
+    /// variance of the mean displayed value after `n` samples, none before there are two
+    pub fn variance(&self, n: usize) -> Option<Float> {
+        if n < 2 {
+            return None;
+        }
+        let n = n as Float;
+        let mean = self.value / n;
+        let variance = (self.value_sq / n - mean * mean).max(0.0) * n / (n - 1.0);
+        Some(variance / n)
+    }
+
+    /// standard error of the mean displayed value after `n` samples
+    pub fn standard_error(&self, n: usize) -> Float {
+        self.variance(n).map_or(Float::INFINITY, Float::sqrt)
+    }
+}
+
+impl AddAssign for PixelStats {
+    fn add_assign(&mut self, other: PixelStats) {
+        self.color += other.color;
+        self.value += other.value;
+        self.value_sq += other.value_sq;
+    }
+}
+
+/// how a linear color ends up on screen, as a single value between 0 and 1
+pub(crate) fn display_value(color: Vec3) -> Float {
+    color.luminance().clamp(0.0, 1.0).sqrt()
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use crate::{
+    distribution::{Distribution1D, Distribution2D},
+    error::{open_image, Error},
+    vec3::{consts::PI, Float, Vec2},
+};
+
+/// Shape of the lens opening, which is also the shape out-of-focus highlights (bokeh) take on.
+/// Shapes are measured in units of the camera's lens radius, so a disk of radius 1 is the plain round lens.
+#[derive(Debug, Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Disk,
+    /// regular polygon with `blades` corners, like the iris of a real lens, rotated by `rotation` radians
+    Polygon { blades: usize, rotation: Float },
+    /// |x|^n + |y|^n <= 1, rounded squares for large n and star shapes for n < 1
+    Superellipse {
+        exponent: Float,
+        angles: Arc<Distribution1D>,
+    },
+    /// a grayscale mask stretched over [-1, 1]^2, brighter pixels let more light through
+    Image(Arc<Distribution2D>),
+}
+
+impl Aperture {
+    pub fn polygon(blades: usize, rotation: Float) -> Aperture {
+        Aperture::Polygon {
+            blades: blades.max(3),
+            rotation,
+        }
+    }
+
+    pub fn superellipse(exponent: Float) -> Aperture {
+        // the area swept per angle goes with the squared radius, tabulate it once here
+        let n = 512;
+        let weights = (0..n)
+            .map(|i| {
+                let theta = (i as Float + 0.5) / n as Float * 2.0 * PI;
+                Self::superellipse_radius(theta, exponent).powi(2)
+            })
+            .collect();
+        Aperture::Superellipse {
+            exponent,
+            angles: Arc::new(Distribution1D::new(weights)),
+        }
+    }
+
+    pub fn image(filename: &str) -> Result<Aperture, Error> {
+        let img = open_image(filename)?.to_luma32f();
+        let weights: Vec<Float> = img.pixels().map(|p| p.0[0] as Float).collect();
+        Ok(Aperture::Image(Arc::new(Distribution2D::new(
+            &weights,
+            img.width() as usize,
+            img.height() as usize,
+        ))))
+    }
+
+    fn superellipse_radius(theta: Float, exponent: Float) -> Float {
+        let (s, c) = theta.sin_cos();
+        (c.abs().powf(exponent) + s.abs().powf(exponent)).powf(-1.0 / exponent)
+    }
+
+    /// map a uniform sample in [0, 1)^2 to a uniformly distributed point on the aperture
+    pub fn sample(&self, u: Vec2) -> Vec2 {
+        match self {
+            Aperture::Disk => {
+                let radius = u.x.sqrt();
+                let angle = u.y * 2.0 * PI;
+                Vec2::new(radius * angle.cos(), radius * angle.sin())
+            }
+            Aperture::Polygon { blades, rotation } => {
+                // pick one of the triangles fanning out from the center, then a point inside it
+                let step = 2.0 * PI / *blades as Float;
+                let scaled = u.x * *blades as Float;
+                let i = (scaled as usize).min(blades - 1);
+                let a = rotation + i as Float * step;
+                let p0 = Vec2::new(a.cos(), a.sin());
+                let p1 = Vec2::new((a + step).cos(), (a + step).sin());
+
+                let su = (scaled - i as Float).sqrt();
+                // barycentric coordinates, the center corner has weight 1 - su
+                p0 * (su * u.y) + p1 * (su * (1.0 - u.y))
+            }
+            Aperture::Superellipse { exponent, angles } => {
+                let (t, _, _) = angles.sample_continuous(u.x);
+                let theta = t * 2.0 * PI;
+                let radius = Self::superellipse_radius(theta, *exponent) * u.y.sqrt();
+                Vec2::new(theta.cos(), theta.sin()) * radius
+            }
+            Aperture::Image(mask) => {
+                let (p, _) = mask.sample(u);
+                // the image covers [-1, 1]^2, and its rows go down while the lens y axis goes up
+                Vec2::new(2.0 * p.x - 1.0, 1.0 - 2.0 * p.y)
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::vec3::{Float, Vec2};
+
+/// Brown-Conrady radial distortion with the same k1, k2 as OpenCV's camera calibration,
+/// so coefficients measured on real footage can be used as they are.
+/// Positive k1 gives pincushion distortion, negative k1 gives barrel distortion.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LensDistortion {
+    pub k1: Float,
+    pub k2: Float,
+}
+
+impl LensDistortion {
+    pub fn new(k1: Float, k2: Float) -> LensDistortion {
+        LensDistortion { k1, k2 }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.k1 == 0.0 && self.k2 == 0.0
+    }
+
+    /// where an undistorted point (in units of the focal length) lands on the image
+    pub fn distort(&self, p: Vec2) -> Vec2 {
+        let r2 = p.length_squared();
+        p * (1.0 + self.k1 * r2 + self.k2 * r2 * r2)
+    }
+
+    /// which undistorted point lands on `p`, the inverse of `distort`
+    pub fn undistort(&self, p: Vec2) -> Vec2 {
+        // fixed point iteration, converges quickly for the small coefficients real lenses have
+        let mut q = p;
+        for _ in 0..20 {
+            let r2 = q.length_squared();
+            q = p / (1.0 + self.k1 * r2 + self.k2 * r2 * r2);
+        }
+        q
+    }
+}
@@ -0,0 +1,46 @@
+use crate::vec3::Float;
+
+/// Camera settings in photographic units. Together they decide how bright the image is and,
+/// through the f-stop, how large the lens aperture is for depth of field.
+#[derive(Debug, Clone, Copy)]
+pub struct Exposure {
+    pub iso: Float,
+    /// in seconds
+    pub shutter_speed: Float,
+    pub aperture_fstop: Float,
+}
+
+impl Exposure {
+    pub fn new(iso: Float, shutter_speed: Float, aperture_fstop: Float) -> Exposure {
+        Exposure {
+            iso,
+            shutter_speed,
+            aperture_fstop,
+        }
+    }
+
+    /// exposure value normalized to ISO 100
+    pub fn ev100(&self) -> Float {
+        let n2 = self.aperture_fstop * self.aperture_fstop;
+        (n2 / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    /// factor from scene radiance to the value written to the film, using the saturation based
+    /// sensitivity model (see "Moving Frostbite to Physically Based Rendering", Lagarde 2014)
+    pub fn brightness_scale(&self) -> Float {
+        let max_luminance = 1.2 * Float::powf(2.0, self.ev100());
+        1.0 / max_luminance
+    }
+
+    /// radius of the lens opening for a lens with the given focal length, in the same units
+    pub fn aperture_radius(&self, lens_focal_length: Float) -> Float {
+        lens_focal_length / (2.0 * self.aperture_fstop)
+    }
+}
+
+impl Default for Exposure {
+    /// the "sunny 16" rule
+    fn default() -> Self {
+        Exposure::new(100.0, 1.0 / 100.0, 16.0)
+    }
+}
@@ -0,0 +1,249 @@
+use std::{io, path::Path};
+
+use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage, Rgba, Rgba32FImage};
+
+use crate::{
+    color::ColorSpace,
+    image_metrics::heat_color,
+    metadata::{save_exr, save_image, save_image_with_alpha, RenderInfo},
+    vec3::{Float, Vec3},
+};
+
+use super::Region;
+
+/// The average color over some pixels and how far off it might be, for checking renders
+/// against known answers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilmStats {
+    pub mean: Vec3,
+    /// the standard error of the mean, from how much the pixels differ from each other. it
+    /// takes the pixels for independent estimates, which seeded renders are
+    pub std_error: Vec3,
+    pub pixels: usize,
+}
+
+impl FilmStats {
+    /// whether `expected` is within `sigmas` standard errors of the mean, or within `slack`
+    /// of it for renders without noise
+    pub fn agrees_with(&self, expected: Vec3, sigmas: Float, slack: Float) -> bool {
+        let bound = self.std_error * sigmas + Vec3::splat(slack);
+        (self.mean - expected).abs().cmple(bound).all()
+    }
+}
+
+/// A render in memory: the linear color of every pixel with the exposure applied, its alpha,
+/// how many samples went into it and how noisy it still is, for programs that show or process
+/// renders without going through a file. `Camera::render_to_buffer` renders into one and the `save_*` functions write
+/// it out
+#[derive(Debug, Clone)]
+pub struct FilmBuffer {
+    /// linear RGB with straight (not premultiplied) alpha
+    pub pixels: Rgba32FImage,
+    pub samples: ImageBuffer<Luma<u32>, Vec<u32>>,
+    /// the variance of every pixel's mean displayed value (see `AdaptiveSampling`), from how
+    /// much its samples differ. 0 where it wasn't measured: pixels with a single sample, and
+    /// transparent, GPU and distributed renders
+    pub variance: ImageBuffer<Luma<f32>, Vec<f32>>,
+    /// whether the alpha means anything, it's 1 everywhere unless the camera is `transparent`
+    pub has_alpha: bool,
+    /// the working space of the colors and how the 8 bit images are encoded, the camera's.
+    /// None is a plain gamma of 2
+    pub color_space: Option<ColorSpace>,
+}
+
+impl FilmBuffer {
+    /// black, opaque and without any samples
+    pub fn new(width: usize, height: usize) -> FilmBuffer {
+        let (width, height) = (width as u32, height as u32);
+        FilmBuffer {
+            pixels: ImageBuffer::from_pixel(width, height, Rgba([0.0, 0.0, 0.0, 1.0])),
+            samples: ImageBuffer::new(width, height),
+            variance: ImageBuffer::new(width, height),
+            has_alpha: false,
+            color_space: None,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.pixels.width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.height() as usize
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn set(&mut self, x: usize, y: usize, color: Vec3, alpha: Float, samples: usize) {
+        let (x, y) = (x as u32, y as u32);
+        let [r, g, b] = color.to_array().map(|c| c as f32);
+        self.pixels.put_pixel(x, y, Rgba([r, g, b, alpha as f32]));
+        self.samples.put_pixel(x, y, Luma([samples as u32]));
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn set_variance(&mut self, x: usize, y: usize, variance: Float) {
+        self.variance
+            .put_pixel(x as u32, y as u32, Luma([variance as f32]));
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn color(&self, x: usize, y: usize) -> Vec3 {
+        let [r, g, b, _] = self.pixels.get_pixel(x as u32, y as u32).0;
+        Vec3::new(r as Float, g as Float, b as Float)
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn alpha(&self, x: usize, y: usize) -> Float {
+        self.pixels.get_pixel(x as u32, y as u32).0[3] as Float
+    }
+
+    pub fn sample_count(&self, x: usize, y: usize) -> usize {
+        self.samples.get_pixel(x as u32, y as u32).0[0] as usize
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn variance(&self, x: usize, y: usize) -> Float {
+        self.variance.get_pixel(x as u32, y as u32).0[0] as Float
+    }
+
+    /// the average color over `region`, `Region::full` for the whole image
+    pub fn stats(&self, region: &Region) -> FilmStats {
+        let region = region.clamped(self.width(), self.height());
+        let pixels = region.width() * region.height();
+        let mut sum = Vec3::ZERO;
+        let mut sum_sq = Vec3::ZERO;
+        for y in region.y0..region.y1 {
+            for x in region.x0..region.x1 {
+                let color = self.color(x, y);
+                sum += color;
+                sum_sq += color * color;
+            }
+        }
+        let n = pixels.max(1) as Float;
+        let mean = sum / n;
+        let variance = (sum_sq / n - mean * mean).max(Vec3::ZERO) * n / (n - 1.0).max(1.0);
+        FilmStats {
+            mean,
+            std_error: (variance / n).map(Float::sqrt),
+            pixels,
+        }
+    }
+
+    /// the colors encoded to 8 bits, the way the PNGs are saved
+    pub fn to_rgb8(&self) -> RgbImage {
+        ImageBuffer::from_fn(self.pixels.width(), self.pixels.height(), |x, y| {
+            to_display(self.color(x as usize, y as usize), self.color_space)
+        })
+    }
+
+    pub fn alpha8(&self) -> GrayImage {
+        ImageBuffer::from_fn(self.pixels.width(), self.pixels.height(), |x, y| {
+            let alpha = self.alpha(x as usize, y as usize);
+            Luma([(alpha.clamp(0.0, 1.0) * 255.0).round() as u8])
+        })
+    }
+
+    /// how many samples each pixel took, from black for none to white for `max_samples`
+    pub fn sample_map(&self, max_samples: usize) -> RgbImage {
+        ImageBuffer::from_fn(self.pixels.width(), self.pixels.height(), |x, y| {
+            let samples = self.sample_count(x as usize, y as usize) as Float;
+            let shade = (samples / max_samples.max(1) as Float * 255.0) as u8;
+            Rgb([shade, shade, shade])
+        })
+    }
+
+    /// where the noise is, the standard error of every pixel from black for none through blue
+    /// and red to white for the noisiest one
+    #[allow(clippy::unnecessary_cast)]
+    pub fn variance_map(&self) -> RgbImage {
+        let max_error = self
+            .variance
+            .pixels()
+            .map(|pixel| (pixel.0[0] as Float).sqrt())
+            .fold(0.0, Float::max)
+            .max(1e-8);
+        ImageBuffer::from_fn(self.pixels.width(), self.pixels.height(), |x, y| {
+            let error = self.variance(x as usize, y as usize).sqrt();
+            let color = heat_color(error / max_error) * 255.0;
+            Rgb(color.to_array().map(|c| c as u8))
+        })
+    }
+
+    /// take the pixels of `display` that differ from `to_rgb8`, e.g. where an outline was drawn
+    /// over it, back into linear. the rest keep their colors beyond what 8 bits can hold
+    pub fn overlay(&mut self, display: &RgbImage) {
+        for (x, y, pixel) in display.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            if to_display(self.color(x, y), self.color_space) != *pixel {
+                let (alpha, samples) = (self.alpha(x, y), self.sample_count(x, y));
+                self.set(x, y, from_display(*pixel, self.color_space), alpha, samples);
+            }
+        }
+    }
+
+    /// cut everything down to `region`
+    pub fn crop(&mut self, region: &Region) {
+        self.pixels = region.crop(&self.pixels);
+        self.samples = region.crop(&self.samples);
+        self.variance = region.crop(&self.variance);
+    }
+
+    /// `.exr` files get `save_exr`, anything else `save_8bit`
+    pub fn save(&self, filename: &str, info: &RenderInfo) -> io::Result<()> {
+        let is_exr = Path::new(filename)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+        if is_exr {
+            self.save_exr(filename, info)
+        } else {
+            self.save_8bit(filename, info)
+        }
+    }
+
+    /// the 8 bit colors, with the alpha if it has one. PNGs get `info` as metadata
+    pub fn save_8bit(&self, filename: &str, info: &RenderInfo) -> io::Result<()> {
+        let rgb = self.to_rgb8();
+        if self.has_alpha {
+            save_image_with_alpha(&rgb, &self.alpha8(), filename, info)
+        } else {
+            save_image(&rgb, filename, info)
+        }
+    }
+
+    /// the linear colors (in the working space) and alpha as 32 bit floats, with `info` in the
+    /// header
+    pub fn save_exr(&self, filename: &str, info: &RenderInfo) -> io::Result<()> {
+        save_exr(&self.pixels, filename, info)
+    }
+}
+
+fn gamma_correct(x: Float) -> Float {
+    x.max(0.0).sqrt()
+}
+
+/// a linear color the way it's stored in an image, with the curve of `color_space` or a gamma
+/// of 2 without one
+pub(super) fn encode(color: Vec3, color_space: Option<ColorSpace>) -> Vec3 {
+    match color_space {
+        Some(color_space) => color_space.encode(color),
+        None => color.map(gamma_correct),
+    }
+}
+
+/// a linear color encoded and clamped to 8 bits
+pub(super) fn to_display(color: Vec3, color_space: Option<ColorSpace>) -> Rgb<u8> {
+    let encoded = encode(color, color_space);
+    Rgb(encoded
+        .to_array()
+        .map(|c| (c.clamp(0.0, 0.999) * 256.0) as u8))
+}
+
+/// the linear color in the middle of what `to_display` turns into `pixel`
+fn from_display(pixel: Rgb<u8>, color_space: Option<ColorSpace>) -> Vec3 {
+    let [r, g, b] = pixel.0.map(|c| (c as Float + 0.5) / 256.0);
+    let encoded = Vec3::new(r, g, b);
+    match color_space {
+        Some(color_space) => color_space.decode(encoded),
+        None => encoded * encoded,
+    }
+}
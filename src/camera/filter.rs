@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use crate::{
+    distribution::Distribution1D,
+    vec3::{consts::PI, Float, Vec2},
+};
+
+/// Shape of a pixel reconstruction filter, how much a sample counts towards a pixel by its
+/// distance from the pixel's center. all of them are separable, f(x, y) = f(x) f(y)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterShape {
+    Box,
+    Triangle,
+    /// falls to 0 at the radius, with a standard deviation of a third of it
+    Gaussian,
+    BlackmanHarris,
+    /// Mitchell-Netravali with B = C = 1/3, its negative lobes sharpen a little
+    Mitchell,
+}
+
+impl FilterShape {
+    /// the radius in pixels each shape is usually used with
+    pub fn default_radius(&self) -> Float {
+        match self {
+            FilterShape::Box => 0.5,
+            FilterShape::Triangle => 1.0,
+            FilterShape::Gaussian => 1.5,
+            FilterShape::BlackmanHarris | FilterShape::Mitchell => 2.0,
+        }
+    }
+
+    /// the filter along one axis at `x` pixels from the center
+    fn evaluate(&self, x: Float, radius: Float) -> Float {
+        let x = x.abs();
+        if x >= radius {
+            return 0.0;
+        }
+        match self {
+            FilterShape::Box => 1.0,
+            FilterShape::Triangle => radius - x,
+            FilterShape::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: Float| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            FilterShape::BlackmanHarris => {
+                let t = 0.5 + 0.5 * x / radius;
+                0.35875 - 0.48829 * (2.0 * PI * t).cos() + 0.14128 * (4.0 * PI * t).cos()
+                    - 0.01168 * (6.0 * PI * t).cos()
+            }
+            FilterShape::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+/// A pixel reconstruction filter. samples are placed around the pixel center in proportion to
+/// the filter's absolute value (filter importance sampling), so they all count the same, only
+/// the ones in negative lobes count negatively
+#[derive(Debug, Clone)]
+pub struct PixelFilter {
+    pub shape: FilterShape,
+    pub radius: Float,
+    /// |f| along one axis, tabulated over [-radius, radius]
+    distribution: Arc<Distribution1D>,
+    /// signs of the table
+    signs: Arc<Vec<Float>>,
+    /// the weight of a sample in a positive lobe along one axis, the integral of |f| over the
+    /// integral of f
+    weight: Float,
+}
+
+impl PixelFilter {
+    pub fn new(shape: FilterShape, radius: Float) -> PixelFilter {
+        let radius = radius.max(1e-3);
+        let n = 64;
+        let values: Vec<Float> = (0..n)
+            .map(|i| {
+                let x = ((i as Float + 0.5) / n as Float * 2.0 - 1.0) * radius;
+                shape.evaluate(x, radius)
+            })
+            .collect();
+        let signed: Float = values.iter().sum();
+        let absolute: Float = values.iter().map(|v| v.abs()).sum();
+        PixelFilter {
+            shape,
+            radius,
+            distribution: Arc::new(Distribution1D::new(
+                values.iter().map(|v| v.abs()).collect(),
+            )),
+            signs: Arc::new(values.iter().map(|v| v.signum()).collect()),
+            weight: absolute / signed,
+        }
+    }
+
+    /// an offset from the pixel center in pixels and the weight of the sample taken there
+    pub fn sample(&self, u: Vec2) -> (Vec2, Float) {
+        let (x, _, i) = self.distribution.sample_continuous(u.x);
+        let (y, _, j) = self.distribution.sample_continuous(u.y);
+        let offset = (Vec2::new(x, y) * 2.0 - Vec2::ONE) * self.radius;
+        (
+            offset,
+            self.signs[i] * self.signs[j] * self.weight * self.weight,
+        )
+    }
+}
+
+impl Default for PixelFilter {
+    fn default() -> Self {
+        PixelFilter::new(FilterShape::Box, FilterShape::Box.default_radius())
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    hittable::World,
+    integrator::Integrator,
+    interval::Interval,
+    ray::{Ray, RayType},
+    vec3::{Float, Vec2},
+};
+
+use super::{Camera, CameraSample};
+
+/// What the camera focuses on by itself when it starts rendering, instead of a distance
+/// worked out by hand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Autofocus {
+    /// the first thing in the way from `look_from` to `look_at`, or `look_at` itself
+    LookAt,
+    /// whatever is seen through this point of the image, in fractions of its width and height
+    /// from the top left corner, so it stays on the same thing at any resolution
+    Pick(Vec2),
+}
+
+impl<I: Integrator> Camera<I> {
+    /// the distance to the plane in focus along the view direction
+    pub fn focus_distance(&self) -> Float {
+        self.focus_distance.unwrap_or(self.focal_length)
+    }
+
+    /// set `focus_distance` to what `autofocus` points at and `init` again. called when a render
+    /// starts, after `init`. a pick that sees nothing leaves the focus where it was
+    pub fn autofocus(&mut self, world: &World) {
+        let Some(autofocus) = self.autofocus else {
+            return;
+        };
+        let ray = match autofocus {
+            Autofocus::LookAt => Ray::new(self.look_from, self.look_at - self.look_from, 0.5),
+            Autofocus::Pick(p) => {
+                let (x, y) = (
+                    p.x * self.image_width as Float,
+                    p.y * self.image_height as Float,
+                );
+                match self.ray_through(x, y, &CameraSample::center()) {
+                    Some(ray) => ray,
+                    None => {
+                        log::warn!("the autofocus pick is outside the image");
+                        return;
+                    }
+                }
+            }
+        };
+        // what the camera sees, not what only shows up in reflections
+        let ray = ray.with_type(RayType::Camera);
+        let hit = world.intersect_objects(&ray, Interval::FORWARD);
+        let point = match (hit, autofocus) {
+            (Some(hit), _) => ray.at(hit.dist),
+            (None, Autofocus::LookAt) => self.look_at,
+            (None, Autofocus::Pick(_)) => {
+                log::warn!("the autofocus pick doesn't see anything, keeping the focus");
+                return;
+            }
+        };
+        let distance = (point - self.look_from).dot(-self.forward);
+        if distance > 0.0 {
+            log::info!("autofocus at {distance:.3}");
+            self.focus_distance = Some(distance);
+            self.init();
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Instant;
+
+use crate::{
+    gpu::{GpuError, GpuRenderer, GpuScene, GpuView},
+    hittable::World,
+    integrator::Integrator,
+    overlay::burn_strip,
+};
+
+use super::{Camera, FilmBuffer};
+
+impl<I: Integrator> Camera<I> {
+    /// render with the experimental compute shader backend instead of the integrator. only the
+    /// pinhole part of the camera is used: no depth of field, distortion or vignetting
+    pub fn render_gpu(
+        &mut self,
+        world: &World,
+        filename: &str,
+        max_depth: usize,
+    ) -> Result<(), GpuError> {
+        let start = Instant::now();
+        let scene = GpuScene::new(world).ok_or(GpuError::UnsupportedScene)?;
+        let renderer = GpuRenderer::new()?;
+        let view = GpuView {
+            center: self.center,
+            pixel00: self.pixel00,
+            pixel_du: self.pixel_du,
+            pixel_dv: self.pixel_dv,
+            width: self.image_width,
+            height: self.image_height,
+            max_depth,
+        };
+        let colors = renderer.render(&scene, &view, self.samples_per_pixel)?;
+        // the whole image is always rendered, the region only decides what's kept of it
+        let region = self.region();
+        let mut film = FilmBuffer::new(self.image_width, self.image_height);
+        for (i, color) in colors.into_iter().enumerate() {
+            let (x, y) = (i % self.image_width, i / self.image_width);
+            if region.contains(x, y) {
+                film.set(
+                    x,
+                    y,
+                    color * self.exposure_scale,
+                    1.0,
+                    self.samples_per_pixel,
+                );
+            }
+        }
+        if let Some(region) = self.crop_region() {
+            film.crop(&region);
+        }
+
+        let mut info = self.render_info(start.elapsed().as_secs_f64());
+        info.integrator = String::from("gpu");
+        if self.stamp {
+            let mut imgbuf = film.to_rgb8();
+            burn_strip(&mut imgbuf, &info.summary());
+            film.overlay(&imgbuf);
+        }
+        if let Err(err) = film.save(filename, &info) {
+            log::error!("Failed to save image {err}");
+        }
+        Ok(())
+    }
+}
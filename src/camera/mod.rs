@@ -0,0 +1,1433 @@
+use rayon::prelude::*;
+use std::{
+    cell::OnceCell,
+    io,
+    ops::{ControlFlow, Range},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use crate::{
+    aov::{aov_filename, GBuffer, IdManifest, Outline},
+    blue_noise::BlueNoiseMask,
+    color::ColorSpace,
+    hittable::{OrientedBox, World},
+    integrator::{Integrator, PathIntegrator},
+    interval::Interval,
+    metadata::{save_exr_rgb, RenderInfo},
+    overlay::burn_strip,
+    ray::{Ray, RayType},
+    ray_stats::{self, RayKind, RayStats, RayStatsMap},
+    sampler::rng,
+    sampler::{mix_seed, seed_thread, start_dither, stop_dither, Sampler},
+    vec3::{consts::PI, Float, Quat, Vec2, Vec3},
+};
+use image::{ImageBuffer, RgbImage};
+use rand::Rng;
+
+pub mod adaptive;
+pub use self::adaptive::*;
+
+pub mod aperture;
+pub use self::aperture::*;
+
+pub mod cancel;
+pub use self::cancel::*;
+
+pub mod distortion;
+pub use self::distortion::*;
+
+pub mod exposure;
+pub use self::exposure::*;
+
+pub mod film;
+pub use self::film::*;
+
+pub mod filter;
+pub use self::filter::*;
+
+pub mod focus;
+pub use self::focus::*;
+
+#[cfg(feature = "gpu")]
+mod gpu;
+
+pub mod post;
+pub use self::post::*;
+
+pub mod priority;
+pub use self::priority::*;
+
+pub mod projection;
+pub use self::projection::*;
+
+pub mod region;
+pub use self::region::*;
+
+pub mod stereo;
+pub use self::stereo::*;
+
+mod transparent;
+
+#[derive(Debug, Clone)]
+pub struct Camera<I: Integrator = PathIntegrator> {
+    pub aspect_ratio: Float,
+    pub image_width: usize,
+    pub samples_per_pixel: usize,
+    pub integrator: I,
+    /// stop sampling tiles once they are clean enough, `samples_per_pixel` is then the maximum
+    pub adaptive: Option<AdaptiveSampling>,
+
+    pub vfov: Float,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub vup: Vec3,
+    pub projection: Projection,
+
+    /// the pixel reconstruction filter, a box over the pixel by default
+    pub filter: PixelFilter,
+    /// the focus when `focus_distance` isn't set, as it was before there was one, and how much
+    /// an orthographic camera sees: as much as a perspective one at this distance
+    pub focal_length: Float,
+    /// the distance to the plane in focus along the view direction, set by `autofocus` too
+    pub focus_distance: Option<Float>,
+    /// focus on what this points at when the render starts
+    pub autofocus: Option<Autofocus>,
+    pub defocus_angle: Float,
+    pub aperture: Aperture,
+    /// trace through the lens center and blur the image by depth afterwards instead, a quick
+    /// preview for setting the focus before rendering the real depth of field
+    pub defocus_preview: bool,
+
+    pub distortion: LensDistortion,
+    /// render a view for each eye into the two halves of the image
+    pub stereo: Option<Stereo>,
+    /// how strongly the image darkens towards the corners, 0 is none and 1 is the natural cos^4 falloff
+    pub vignetting: Float,
+
+    /// when set, the exposure decides the image brightness and the aperture size,
+    /// and `defocus_angle` is ignored
+    pub exposure: Option<Exposure>,
+    /// height of the sensor in scene units, the default is a full-frame sensor in a scene modeled in meters
+    pub sensor_height: Float,
+
+    /// effects run over the rendered film in this order, before anything is drawn over it
+    pub post_effects: Vec<PostEffect>,
+    /// draw ink lines along depth and normal discontinuities on top of the render
+    pub outline: Option<Outline>,
+    /// draw the box around every object over the render, for checking where instances end up
+    pub bounding_boxes: bool,
+    /// burn a strip with the render settings into the bottom of the image
+    pub stamp: bool,
+    /// identifies the scene description in the output's metadata, set by the scene loader
+    pub scene_hash: Option<u64>,
+    /// shown in the settings strip and the output's metadata, set by the scene loader
+    pub scene_name: Option<String>,
+    /// render on fewer cores at a lower priority, to keep the machine usable meanwhile
+    pub background: Option<BackgroundPriority>,
+    /// count the rays, BVH nodes and primitive tests of every pixel, print the totals and
+    /// save a heatmap of the traversal cost next to the render
+    pub ray_stats: bool,
+    /// save a heat map of how noisy every pixel still is next to the render, to see where more
+    /// samples would go. `FilmBuffer::variance` has the numbers
+    pub variance_map: bool,
+    /// save the render with an alpha channel: transparent where nothing was hit, and on shadow
+    /// catchers everywhere but the shadows and reflections on them. only for integrators that
+    /// render in one pass, without adaptive sampling
+    pub transparent: bool,
+    /// save the object and material ID passes next to the render, with the names of the IDs
+    /// the scene was tagged with. the IDs are those of the first hit through pixel centers
+    pub ids: Option<IdManifest>,
+    /// save how far the first hit through every pixel center moves over the shutter next to
+    /// the render, across the image and in world and object space, for temporal denoising
+    /// and motion blur in compositing
+    pub motion_vectors: bool,
+    /// save only the pixels of the region set with `set_region`, instead of the whole image
+    /// with the rest left black
+    pub crop: bool,
+    /// seed the random numbers of every pixel from this, so the same settings render the same
+    /// image. random when it's None
+    pub seed: Option<u64>,
+    /// the working space colors are shaded in and how the images are encoded. color textures
+    /// have to be decoded into it by whoever loads them (the scene loader does). None keeps to
+    /// the old ways: textures as they are and a gamma of 2
+    pub color_space: Option<ColorSpace>,
+    /// dither the camera's and the first bounce's random numbers with this mask, so the noise
+    /// of a render at a few samples per pixel is spread evenly instead of in blotches
+    pub blue_noise: Option<Arc<BlueNoiseMask>>,
+    /// stops the render early when it's cancelled, keeping what was rendered so far
+    pub cancel: Option<CancelToken>,
+
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+
+    /// the counts of the render in progress when `ray_stats` is on
+    stats_map: Option<Arc<RayStatsMap>>,
+    /// the only pixels that are rendered, all of them when it's None
+    region: Option<Region>,
+    image_height: usize,
+    exposure_scale: Float,
+    lens_radius: Float,
+    focal_length_px: Float,
+    center: Vec3,
+    pixel00: Vec3,
+    pixel_du: Vec3,
+    pixel_dv: Vec3,
+}
+
+impl<I: Integrator> Camera<I> {
+    pub fn with_integrator(integrator: I) -> Camera<I> {
+        Camera {
+            aspect_ratio: Default::default(),
+            image_width: Default::default(),
+            samples_per_pixel: Default::default(),
+            integrator,
+            adaptive: None,
+            vfov: Default::default(),
+            look_from: Default::default(),
+            look_at: Default::default(),
+            vup: Default::default(),
+            projection: Projection::default(),
+            filter: PixelFilter::default(),
+            focal_length: Default::default(),
+            focus_distance: None,
+            autofocus: None,
+            defocus_angle: Default::default(),
+            aperture: Aperture::default(),
+            defocus_preview: false,
+            distortion: LensDistortion::default(),
+            stereo: None,
+            vignetting: 0.0,
+            exposure: None,
+            sensor_height: 0.024,
+            post_effects: Vec::new(),
+            outline: None,
+            bounding_boxes: false,
+            stamp: false,
+            scene_hash: None,
+            scene_name: None,
+            background: None,
+            ray_stats: false,
+            variance_map: false,
+            transparent: false,
+            ids: None,
+            motion_vectors: false,
+            crop: false,
+            seed: None,
+            color_space: None,
+            blue_noise: None,
+            cancel: None,
+            forward: Default::default(),
+            right: Default::default(),
+            up: Default::default(),
+            stats_map: None,
+            region: None,
+            image_height: Default::default(),
+            exposure_scale: Default::default(),
+            lens_radius: Default::default(),
+            focal_length_px: Default::default(),
+            center: Default::default(),
+            pixel00: Default::default(),
+            pixel_du: Default::default(),
+            pixel_dv: Default::default(),
+        }
+    }
+
+    pub fn init(&mut self) {
+        // the epsilon keeps an aspect ratio of width / height from rounding to one pixel less
+        self.image_height = (self.image_width as Float / self.aspect_ratio + 1e-3) as usize;
+
+        self.center = self.look_from;
+
+        // the viewport is one unit in front of the camera, so the framing only depends on the
+        // field of view. the lens model scales it out to the plane in focus when it needs to
+        let theta = self.vfov.to_radians();
+        let h = (theta / 2.0).tan();
+        let viewport_height = 2.0 * h;
+        let (width, height) = self.eye_size();
+        let viewport_width = viewport_height * (width / height);
+
+        self.forward = (self.look_from - self.look_at).normalize(); // forward
+        self.right = self.vup.cross(self.forward).normalize(); // right
+        self.up = self.forward.cross(self.right); // up
+
+        let viewport_u = self.right * viewport_width;
+        let viewport_v = self.up * -viewport_height;
+
+        self.pixel_du = viewport_u / width;
+        self.pixel_dv = viewport_v / height;
+        self.focal_length_px = 0.5 * height / h;
+
+        let upperleft = self.center - self.forward - (viewport_u / 2.0) - (viewport_v / 2.0);
+        self.pixel00 = upperleft + (self.pixel_du + self.pixel_dv) * 0.5;
+
+        (self.exposure_scale, self.lens_radius) = match self.exposure {
+            Some(exposure) => {
+                // the focal length of a real lens with this field of view on this sensor
+                let lens_focal_length = 0.5 * self.sensor_height / h;
+                (
+                    exposure.brightness_scale(),
+                    exposure.aperture_radius(lens_focal_length),
+                )
+            }
+            None => (
+                1.0,
+                (self.defocus_angle / 2.0).to_radians().tan() * self.focus_distance(),
+            ),
+        };
+    }
+
+    /// point the camera at the middle of the scene and move it along its current view direction
+    /// until the whole scene fits in the image, leaving `padding` times the scene's size around
+    /// it. the focus is put on the middle too. call before `init`
+    pub fn frame(&mut self, world: &World, padding: Float) {
+        let (center, radius) = world.bounding_sphere();
+        if !radius.is_finite() {
+            return; // nothing in the scene
+        }
+        let dir = (self.look_from - self.look_at)
+            .try_normalize()
+            .unwrap_or(Vec3::Z);
+
+        // the narrower of the vertical and horizontal field of view decides
+        let half_vfov = 0.5 * self.vfov.to_radians();
+        let half_hfov = (half_vfov.tan() * self.aspect_ratio).atan();
+        let distance = radius * (1.0 + padding) / half_vfov.min(half_hfov).sin();
+
+        self.look_at = center;
+        self.look_from = center + dir * distance;
+        self.focal_length = distance;
+    }
+
+    /// only render the pixels from (x0, y0) up to (x1, y1), at the full resolution. the rest
+    /// of the image stays black, or is cut off with `crop`
+    pub fn set_region(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        self.region = Some(Region::new(x0, y0, x1, y1));
+    }
+
+    /// the pixels to render, inside the image
+    fn region(&self) -> Region {
+        let (width, height) = (self.image_width, self.image_height);
+        self.region.map_or(Region::full(width, height), |region| {
+            region.clamped(width, height)
+        })
+    }
+
+    /// what the output is cut down to, if it's cropped
+    fn crop_region(&self) -> Option<Region> {
+        (self.crop && self.region.is_some()).then(|| self.region())
+    }
+
+    pub fn render(&mut self, world: &World, filename: &str) {
+        self.in_background(|camera| camera.render_file(world, filename));
+    }
+
+    fn render_file(&mut self, world: &World, filename: &str) {
+        let start = Instant::now();
+        self.preprocess(world);
+        if self.ray_stats {
+            let map = RayStatsMap::new(self.image_width, self.image_height);
+            self.stats_map = Some(Arc::new(map));
+            ray_stats::set_enabled(true);
+        }
+        let film = self.render_pixels(world);
+        if self.cancelled() {
+            log::warn!("render cancelled, saving the pixels rendered so far");
+        }
+
+        if let Some(stats_map) = self.stats_map.take() {
+            ray_stats::set_enabled(false);
+            log::info!("ray stats:\n{}", stats_map.total());
+            if let Err(err) = stats_map.heatmap().save(aov_filename(filename, "cost")) {
+                log::error!("Failed to save traversal cost image {err}");
+            }
+        }
+
+        if let Err(err) = self.finish(world, film, filename, start) {
+            log::error!("Failed to save image {err}");
+        }
+        log::info!(
+            "rendered {filename} in {:.2}s",
+            start.elapsed().as_secs_f64()
+        );
+    }
+
+    /// save `film`, rendered since `start`, to `filename` with everything the camera adds to
+    /// it: the sample count, variance, ID and motion images, the overlays, the crop and the
+    /// settings strip
+    pub fn finish(
+        &mut self,
+        world: &World,
+        mut film: FilmBuffer,
+        filename: &str,
+        start: Instant,
+    ) -> io::Result<()> {
+        // only adaptive sampling takes a different number of samples in different places
+        if self.adaptive.is_some() && self.integrator.passes() == 1 {
+            let sample_map = film.sample_map(self.samples_per_pixel);
+            if let Err(err) = sample_map.save(aov_filename(filename, "samples")) {
+                log::error!("Failed to save sample count image {err}");
+            }
+        }
+        if self.variance_map {
+            if let Err(err) = film.variance_map().save(aov_filename(filename, "variance")) {
+                log::error!("Failed to save variance image {err}");
+            }
+        }
+
+        // traced once for all of the passes below that need it
+        let gbuffer = OnceCell::new();
+        let mut imgbuf = film.to_rgb8();
+        self.apply_defocus_preview(world, &gbuffer, &mut imgbuf);
+        if let Some(mask) = self.apply_outline(world, &gbuffer, &mut imgbuf) {
+            if let Err(err) = mask.save(aov_filename(filename, "outline")) {
+                log::error!("Failed to save outline image {err}");
+            }
+        }
+        self.apply_bounding_boxes(world, &mut imgbuf);
+        film.overlay(&imgbuf);
+        let info = self.render_info(start.elapsed().as_secs_f64());
+        self.save_ids(world, &gbuffer, filename, &info);
+        self.save_motion_vectors(world, &gbuffer, filename, &info);
+        if let Some(region) = self.crop_region() {
+            film.crop(&region);
+        }
+
+        if self.stamp {
+            let mut imgbuf = film.to_rgb8();
+            burn_strip(&mut imgbuf, &info.summary());
+            film.overlay(&imgbuf);
+        }
+        film.save(filename, &info)
+    }
+
+    /// render into memory instead of a file, with the outline but without the settings strip.
+    /// a `transparent` render comes back over black
+    pub fn render_image(&mut self, world: &World) -> RgbImage {
+        self.in_background(|camera| camera.render_to_image(world))
+    }
+
+    fn render_to_image(&mut self, world: &World) -> RgbImage {
+        self.preprocess(world);
+        let mut film = self.render_pixels(world);
+        if film.has_alpha {
+            for pixel in film.pixels.pixels_mut() {
+                let [r, g, b, a] = pixel.0;
+                pixel.0 = [r * a, g * a, b * a, 1.0];
+            }
+        }
+        let gbuffer = OnceCell::new();
+        let mut imgbuf = film.to_rgb8();
+        self.apply_defocus_preview(world, &gbuffer, &mut imgbuf);
+        self.apply_outline(world, &gbuffer, &mut imgbuf);
+        self.apply_bounding_boxes(world, &mut imgbuf);
+        match self.crop_region() {
+            Some(region) => region.crop(&imgbuf),
+            None => imgbuf,
+        }
+    }
+
+    /// render into memory with the linear colors and the number of samples of every pixel, for
+    /// embedding the renderer. the outline, bounding boxes and stamp are left out, they only go
+    /// into images
+    pub fn render_to_buffer(&mut self, world: &World) -> FilmBuffer {
+        self.in_background(|camera| {
+            camera.preprocess(world);
+            let mut film = camera.render_pixels(world);
+            if camera.defocus_preview {
+                let mut imgbuf = film.to_rgb8();
+                camera.apply_defocus_preview(world, &OnceCell::new(), &mut imgbuf);
+                film.overlay(&imgbuf);
+            }
+            if let Some(region) = camera.crop_region() {
+                film.crop(&region);
+            }
+            film
+        })
+    }
+
+    /// run `render` on the background thread pool if there is one, so all the parallel work
+    /// in it is limited to those threads
+    fn in_background<R: Send>(&mut self, render: impl FnOnce(&mut Self) -> R + Send) -> R {
+        let pool = match self.background.map(|background| background.thread_pool()) {
+            Some(Ok(pool)) => pool,
+            Some(Err(err)) => {
+                log::error!("Failed to start the background render threads {err}");
+                return render(self);
+            }
+            None => return render(self),
+        };
+        pool.install(|| render(self))
+    }
+
+    /// let other programs run between pixels when rendering in the background
+    fn yield_if_background(&self) {
+        if self.background.is_some() {
+            std::thread::yield_now();
+        }
+    }
+
+    /// the height in pixels `init` worked out from the width and aspect ratio
+    pub fn image_height(&self) -> usize {
+        self.image_height
+    }
+
+    pub fn render_info(&self, render_time: f64) -> RenderInfo {
+        RenderInfo {
+            width: self.image_width,
+            height: self.image_height,
+            samples_per_pixel: self.samples_per_pixel,
+            integrator: self.integrator.name().to_string(),
+            settings: self.integrator.settings(),
+            seed: self.seed,
+            scene_hash: self.scene_hash,
+            scene_name: self.scene_name.clone(),
+            render_time,
+        }
+    }
+
+    /// check the scene and get the integrator ready, from the seed if there is one
+    fn preprocess(&mut self, world: &World) {
+        let report = world.validate();
+        for problem in &report.problems {
+            log::warn!("{problem}");
+        }
+        log::info!("scene has {report}");
+        self.autofocus(world);
+        seed_thread(self.seed.map(|seed| mix_seed(seed, &[u64::MAX])));
+        self.integrator.preprocess(world);
+    }
+
+    /// draw the random numbers for pixel (`r`, `c`) from the seed, if there is one. `salt`
+    /// tells apart the passes or batches of samples over the same pixel
+    fn seed_pixel(&self, r: usize, c: usize, salt: usize) {
+        if let Some(seed) = self.seed {
+            seed_thread(Some(mix_seed(seed, &[r as u64, c as u64, salt as u64])));
+        }
+    }
+
+    fn render_pixels(&mut self, world: &World) -> FilmBuffer {
+        let mut rendered = self.render_seeded_pixels(world);
+        self.unseed_threads();
+        self.apply_post_effects(&mut rendered);
+        rendered
+    }
+
+    /// run the `post_effects` over a finished render, in the camera's color space
+    pub fn apply_post_effects(&self, film: &mut FilmBuffer) {
+        film.color_space = self.color_space;
+        for effect in &self.post_effects {
+            effect.apply(film);
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// back to random numbers for whatever runs on the render threads next
+    fn unseed_threads(&self) {
+        if self.seed.is_some() {
+            rayon::broadcast(|_| seed_thread(None));
+            seed_thread(None);
+        }
+    }
+
+    fn render_seeded_pixels(&mut self, world: &World) -> FilmBuffer {
+        let passes = self.integrator.passes();
+        if self.transparent {
+            if passes == 1 && self.adaptive.is_none() {
+                return self.render_transparent(world);
+            }
+            log::warn!("transparent renders need one pass and no adaptive sampling, rendering an opaque image");
+        }
+        if passes > 1 {
+            return self.render_passes(world, passes);
+        }
+
+        if let Some(adaptive) = self.adaptive {
+            return self.render_adaptive(world, &adaptive);
+        }
+
+        let (width, height) = (self.image_width, self.image_height);
+        let region = self.region();
+        let samples = self.samples_per_pixel;
+        let pixel_color = |i: usize| match (i / width, i % width) {
+            (r, c) if region.contains(c, r) && !self.cancelled() => {
+                self.seed_pixel(r, c, 0);
+                Some(self.pixel_color(r, c, world, samples))
+            }
+            _ => None,
+        };
+        let colors: Vec<Option<(Vec3, PixelStats)>> = if cfg!(debug_assertions) {
+            log::info!("rendering debug");
+            (0..width * height).map(pixel_color).collect()
+        } else {
+            log::info!("rendering production");
+            (0..width * height)
+                .into_par_iter()
+                .map(pixel_color)
+                .collect()
+        };
+
+        let mut film = FilmBuffer::new(width, height);
+        for (i, color) in colors.into_iter().enumerate() {
+            if let Some((color, stats)) = color {
+                let (x, y) = (i % width, i / width);
+                film.set(x, y, color, 1.0, samples);
+                film.set_variance(x, y, stats.variance(samples).unwrap_or(0.0));
+            }
+        }
+        film
+    }
+
+    /// render the whole image once per pass of the integrator and average the passes,
+    /// adaptive sampling isn't used here
+    fn render_passes(&mut self, world: &World, passes: usize) -> FilmBuffer {
+        self.accumulate_passes(world, passes, &mut |_| ControlFlow::Continue(()))
+    }
+
+    /// render in `passes` passes of `samples_per_pixel / passes` samples each, calling `on_pass`
+    /// with the average of everything rendered so far after each one, e.g. to show the render
+    /// as it cleans up. `ControlFlow::Break` stops after that pass and returns its average,
+    /// cropped like `render_to_buffer`. adaptive sampling and transparency aren't used here, and
+    /// the post effects only go over the film that's returned
+    pub fn render_progressive(
+        &mut self,
+        world: &World,
+        passes: usize,
+        mut on_pass: impl FnMut(&FilmBuffer) -> ControlFlow<()> + Send,
+    ) -> FilmBuffer {
+        if self.adaptive.is_some() || self.transparent {
+            log::warn!("progressive renders don't use adaptive sampling or transparency");
+        }
+        self.in_background(|camera| {
+            camera.preprocess(world);
+            let crop = camera.crop_region();
+            let mut film = camera.accumulate_passes(world, passes.max(1), &mut |film| match crop {
+                Some(region) => {
+                    let mut film = film.clone();
+                    film.crop(&region);
+                    on_pass(&film)
+                }
+                None => on_pass(film),
+            });
+            camera.unseed_threads();
+            camera.apply_post_effects(&mut film);
+            if let Some(region) = crop {
+                film.crop(&region);
+            }
+            film
+        })
+    }
+
+    /// the image averaged over `passes` renders of all of it. `on_pass` sees the average after
+    /// every pass and can stop there
+    fn accumulate_passes(
+        &mut self,
+        world: &World,
+        passes: usize,
+        on_pass: &mut dyn FnMut(&FilmBuffer) -> ControlFlow<()>,
+    ) -> FilmBuffer {
+        let samples = (self.samples_per_pixel / passes).max(1);
+        let (width, height) = (self.image_width, self.image_height);
+        let region = self.region();
+        // pixels can be a pass behind the others when the render is cancelled during a pass
+        let average = |sum: &[(Vec3, PixelStats, usize)]| {
+            let mut film = FilmBuffer::new(width, height);
+            film.color_space = self.color_space;
+            for (i, &(sum, stats, passes)) in sum.iter().enumerate() {
+                if passes > 0 {
+                    let (x, y) = (i % width, i / width);
+                    film.set(x, y, sum / passes as Float, 1.0, samples * passes);
+                    let variance = stats.variance(samples * passes);
+                    film.set_variance(x, y, variance.unwrap_or(0.0));
+                }
+            }
+            film
+        };
+
+        let mut sum = vec![(Vec3::ZERO, PixelStats::default(), 0); width * height];
+        let mut film = FilmBuffer::new(width, height);
+        for pass in 0..passes {
+            if self.cancelled() {
+                break;
+            }
+            let start = Instant::now();
+            seed_thread(
+                self.seed
+                    .map(|seed| mix_seed(seed, &[u64::MAX, pass as u64])),
+            );
+            self.integrator.begin_pass(world, pass);
+            let pixel_color = |i: usize| match (i / width, i % width) {
+                (r, c) if region.contains(c, r) && !self.cancelled() => {
+                    self.seed_pixel(r, c, pass);
+                    Some(self.pixel_color(r, c, world, samples))
+                }
+                _ => None,
+            };
+            let colors: Vec<Option<(Vec3, PixelStats)>> = if cfg!(debug_assertions) {
+                log::info!("rendering debug, pass {}/{passes}", pass + 1);
+                (0..width * height).map(pixel_color).collect()
+            } else {
+                log::info!("rendering production, pass {}/{passes}", pass + 1);
+                (0..width * height)
+                    .into_par_iter()
+                    .map(pixel_color)
+                    .collect()
+            };
+            for ((sum, sum_stats, passes), color) in sum.iter_mut().zip(colors) {
+                if let Some((color, stats)) = color {
+                    *sum += color;
+                    *sum_stats += stats;
+                    *passes += 1;
+                }
+            }
+            log::debug!(
+                "pass {}/{passes} took {:.2}s",
+                pass + 1,
+                start.elapsed().as_secs_f64()
+            );
+
+            film = average(&sum);
+            if on_pass(&film).is_break() {
+                log::info!("stopped after pass {}/{passes}", pass + 1);
+                break;
+            }
+        }
+        film
+    }
+
+    fn render_adaptive(&self, world: &World, adaptive: &AdaptiveSampling) -> FilmBuffer {
+        let tile_size = adaptive.tile_size.max(1);
+        let region = self.region();
+        let mut tiles = Vec::new();
+        for y in (region.y0..region.y1).step_by(tile_size) {
+            for x in (region.x0..region.x1).step_by(tile_size) {
+                tiles.push((x, y));
+            }
+        }
+
+        let render_tile = |&(x, y): &(usize, usize)| {
+            let width = tile_size.min(region.x1 - x);
+            let height = tile_size.min(region.y1 - y);
+            self.render_tile(world, adaptive, x, y, width, height)
+        };
+        let results: Vec<(Vec<PixelStats>, usize)> = if cfg!(debug_assertions) {
+            log::info!("rendering debug, adaptive");
+            tiles.iter().map(render_tile).collect()
+        } else {
+            log::info!("rendering production, adaptive");
+            tiles.par_iter().map(render_tile).collect()
+        };
+
+        let mut film = FilmBuffer::new(self.image_width, self.image_height);
+        for (&(x0, y0), (stats, samples)) in tiles.iter().zip(results) {
+            let width = tile_size.min(region.x1 - x0);
+            let scale = self.exposure_scale / samples.max(1) as Float;
+            for (i, stats) in stats.into_iter().enumerate() {
+                let (x, y) = (x0 + i % width, y0 + i / width);
+                film.set(x, y, stats.color * scale, 1.0, samples);
+                film.set_variance(x, y, stats.variance(samples).unwrap_or(0.0));
+            }
+        }
+        film
+    }
+
+    /// sample a tile in batches until it is clean enough, returns the sums of the pixels'
+    /// samples row by row and the number of samples every pixel took
+    fn render_tile(
+        &self,
+        world: &World,
+        adaptive: &AdaptiveSampling,
+        x0: usize,
+        y0: usize,
+        width: usize,
+        height: usize,
+    ) -> (Vec<PixelStats>, usize) {
+        let start = Instant::now();
+        let mut sampler = Sampler::new();
+        let mut stats = vec![PixelStats::default(); width * height];
+        let max_samples = self.samples_per_pixel.max(1);
+        let mut samples = 0;
+        while samples < max_samples && !self.cancelled() {
+            let batch = adaptive.min_samples.max(1).min(max_samples - samples);
+            for (i, pixel) in stats.iter_mut().enumerate() {
+                self.yield_if_background();
+                let (r, c) = (y0 + i / width, x0 + i % width);
+                self.seed_pixel(r, c, samples);
+                for color in self.pixel_samples(r, c, world, &mut sampler, samples..samples + batch)
+                {
+                    pixel.add(color, display_value(color * self.exposure_scale));
+                }
+            }
+            samples += batch;
+
+            let error = stats
+                .iter()
+                .map(|p| p.standard_error(samples))
+                .sum::<Float>()
+                / stats.len() as Float;
+            if error < adaptive.threshold {
+                break;
+            }
+        }
+
+        log::debug!(
+            "tile at ({x0}, {y0}) took {samples} samples in {:.3}s",
+            start.elapsed().as_secs_f64()
+        );
+        (stats, samples)
+    }
+
+    /// blur `imgbuf` by depth if the defocus preview is on. a thin lens spreads a point at
+    /// distance z in front of it over a circle of radius lens radius * |z - focal length| / z
+    /// on the focal plane
+    fn apply_defocus_preview(
+        &self,
+        world: &World,
+        gbuffer: &OnceCell<GBuffer>,
+        imgbuf: &mut RgbImage,
+    ) {
+        if !self.defocus_preview || self.projection != Projection::Perspective {
+            return;
+        }
+        // the size of a pixel on the plane in focus
+        let pixel_size = self.pixel_du.length() * self.focus_distance();
+        if self.lens_radius <= 0.0 || pixel_size <= 0.0 {
+            return;
+        }
+        let gbuffer = self.first_hits(world, gbuffer);
+        let mut radius = vec![self.lens_radius / pixel_size; gbuffer.depth.len()];
+        for (i, radius) in radius.iter_mut().enumerate() {
+            let (r, c) = (i / self.image_width, i % self.image_width);
+            let depth = gbuffer.depth[i];
+            let Some(ray) = self.generate_ray(r, c, &CameraSample::center()) else {
+                continue;
+            };
+            if depth.is_finite() {
+                let z = depth * -ray.direction().dot(self.forward);
+                *radius *= (1.0 - self.focus_distance() / z).abs();
+            }
+        }
+        *imgbuf = gbuffer.depth_of_field(imgbuf, &radius);
+    }
+
+    /// ink the outline into `imgbuf` if it is enabled, returning the outline on its own
+    fn apply_outline(
+        &self,
+        world: &World,
+        gbuffer: &OnceCell<GBuffer>,
+        imgbuf: &mut RgbImage,
+    ) -> Option<RgbImage> {
+        let outline = self.outline.as_ref()?;
+        let gbuffer = self.first_hits(world, gbuffer);
+        let edges = gbuffer.edges(outline);
+        let ink = film::encode(outline.color, self.color_space) * 255.0;
+        let mut mask: RgbImage = ImageBuffer::new(imgbuf.width(), imgbuf.height());
+        for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+            let edge = edges[y as usize * self.image_width + x as usize];
+            for (channel, ink) in pixel.0.iter_mut().zip(ink.to_array()) {
+                *channel = (*channel as Float * (1.0 - edge) + ink * edge) as u8;
+            }
+            let value = ((1.0 - edge) * 255.0) as u8;
+            mask.put_pixel(x, y, image::Rgb([value, value, value]));
+        }
+        Some(mask)
+    }
+
+    /// draw the oriented box of every object over `imgbuf` if that's enabled. the faces in front
+    /// of the scene are tinted, and the edges are solid where they're in view and faint where
+    /// something hides them
+    fn apply_bounding_boxes(&self, world: &World, imgbuf: &mut RgbImage) {
+        if !self.bounding_boxes {
+            return;
+        }
+        let boxes: Vec<OrientedBox> = (0..world.objects.len())
+            .map(|i| world.objects.get(i).oriented_bounding_box())
+            .collect();
+        let sample = CameraSample::center();
+        for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+            let (px, py) = (x as Float + 0.5, y as Float + 0.5);
+            let (Some(ray), Some(next)) = (
+                self.ray_through(px, py, &sample)
+                    .map(|ray| ray.with_type(RayType::Camera)),
+                self.ray_through(px + 1.0, py, &sample),
+            ) else {
+                continue;
+            };
+            // width of a pixel at distance t
+            let footprint = |t: Float| (next.at(t) - ray.at(t)).length();
+            let depth = world
+                .intersect_objects(&ray, Interval::FORWARD)
+                .map_or(Float::INFINITY, |hit| hit.dist);
+
+            let mut color = Vec3::from_array(pixel.0.map(Float::from)) / 255.0;
+            for (i, bbox) in boxes.iter().enumerate() {
+                let Some((enter, exit)) =
+                    bbox.intersects(&ray, Interval::new(0.0, Float::INFINITY))
+                else {
+                    continue;
+                };
+                let tint = box_color(i);
+                for t in [enter, exit].into_iter().filter(|&t| t > 0.0) {
+                    // on an edge where two of the coordinates are at the side of the box
+                    let p = bbox.to_unit(ray.at(t)).abs();
+                    let width = footprint(t) / bbox.half_extent;
+                    let sides = (0..3).filter(|&axis| p[axis] + width[axis] >= 1.0).count();
+                    let alpha = match (sides >= 2, t < depth) {
+                        (true, true) => 0.9,
+                        (true, false) => 0.35,
+                        (false, true) => 0.12,
+                        (false, false) => 0.0,
+                    };
+                    color = color * (1.0 - alpha) + tint * alpha;
+                }
+            }
+            pixel.0 = (color * 255.0).round().to_array().map(|c| c as u8);
+        }
+    }
+
+    /// the ID passes as previews, `<output>_object_id.png` and `<output>_material_id.png`, the
+    /// IDs themselves in `<output>_id.exr` and their names in `<output>_id.toml`
+    fn save_ids(
+        &self,
+        world: &World,
+        gbuffer: &OnceCell<GBuffer>,
+        filename: &str,
+        info: &RenderInfo,
+    ) {
+        let Some(manifest) = &self.ids else {
+            return;
+        };
+        let gbuffer = self.first_hits(world, gbuffer);
+        let id_file = |ext: &str| {
+            Path::new(&aov_filename(filename, "id"))
+                .with_extension(ext)
+                .to_string_lossy()
+                .into_owned()
+        };
+        let saved = gbuffer
+            .id_preview(&gbuffer.object_id)
+            .save(aov_filename(filename, "object_id"))
+            .and_then(|_| {
+                gbuffer
+                    .id_preview(&gbuffer.material_id)
+                    .save(aov_filename(filename, "material_id"))
+            })
+            .map_err(io::Error::other)
+            .and_then(|_| save_exr_rgb(&gbuffer.id_image(), &id_file("exr"), info));
+        if let Err(err) = saved {
+            log::error!("Failed to save ID passes {err}");
+        }
+        if let Err(err) = manifest.save(&id_file("toml")) {
+            log::error!("Failed to save ID manifest {err}");
+        }
+    }
+
+    /// the motion vectors as `<output>_motion.exr`, in pixels across the image in red and
+    /// green, and `<output>_world_motion.exr` and `<output>_object_motion.exr` in scene units
+    fn save_motion_vectors(
+        &self,
+        world: &World,
+        gbuffer: &OnceCell<GBuffer>,
+        filename: &str,
+        info: &RenderInfo,
+    ) {
+        if !self.motion_vectors {
+            return;
+        }
+        let gbuffer = self.first_hits(world, gbuffer);
+        let exr = |name: &str| {
+            Path::new(&aov_filename(filename, name))
+                .with_extension("exr")
+                .to_string_lossy()
+                .into_owned()
+        };
+        let saved = save_exr_rgb(&gbuffer.motion_image(), &exr("motion"), info)
+            .and_then(|_| {
+                let world_motion = gbuffer.vector_image(&gbuffer.world_motion);
+                save_exr_rgb(&world_motion, &exr("world_motion"), info)
+            })
+            .and_then(|_| {
+                let object_motion = gbuffer.vector_image(&gbuffer.object_motion);
+                save_exr_rgb(&object_motion, &exr("object_motion"), info)
+            });
+        if let Err(err) = saved {
+            log::error!("Failed to save motion vectors {err}");
+        }
+    }
+
+    /// the g-buffer in `cache`, rendered the first time it's needed
+    fn first_hits<'g>(&self, world: &World, cache: &'g OnceCell<GBuffer>) -> &'g GBuffer {
+        cache.get_or_init(|| self.render_gbuffer(world))
+    }
+
+    /// depth, normal, IDs and motion of the first hit through every pixel center, the rows in
+    /// parallel
+    pub fn render_gbuffer(&self, world: &World) -> GBuffer {
+        let sample = CameraSample::center();
+        let rows = (0..self.image_height).into_par_iter().map(|r| {
+            let mut row = GBuffer::new(self.image_width, 1);
+            for c in 0..self.image_width {
+                let Some(ray) = self.generate_ray(r, c, &sample) else {
+                    continue;
+                };
+                if let Some((hit, _)) = world.intersect_all(&ray, Interval::FORWARD) {
+                    row.set(c, 0, hit.dist, hit.shading_normal);
+                    row.set_ids(c, 0, hit.object_id, hit.material_id);
+                    // the hit is halfway through the shutter, and the camera stays put
+                    let (open, close) =
+                        (hit.point - 0.5 * hit.motion, hit.point + 0.5 * hit.motion);
+                    let (eye, _) = self.eye_at(c as Float + 0.5, r as Float + 0.5);
+                    let motion = match (self.project_from(eye, open), self.project_from(eye, close))
+                    {
+                        (Some(open), Some(close)) => close - open,
+                        _ => Vec2::ZERO,
+                    };
+                    row.set_motion(c, 0, motion, hit.motion, hit.object_motion);
+                }
+            }
+            row
+        });
+        GBuffer::from_rows(self.image_width, rows.collect())
+    }
+
+    /// where `point` shows up on the image, in pixels from the top left corner, the inverse
+    /// of the rays through the lens center. none behind a perspective camera or outside the
+    /// image circle of a fisheye. with stereo, where the left eye sees it
+    pub fn project(&self, point: Vec3) -> Option<Vec2> {
+        self.project_from(self.stereo.map(|_| Eye::Left), point)
+    }
+
+    /// where `eye` sees `point` on the image, in its half of a stereo image. the camera's
+    /// center without an eye or without stereo
+    pub fn project_from(&self, eye: Option<Eye>, point: Vec3) -> Option<Vec2> {
+        let (Some(eye), Some(stereo)) = (eye, self.stereo) else {
+            return self.project_center(point);
+        };
+        let (width, height) = (self.image_width as Float, self.image_height as Float);
+        let p = if self.projection == Projection::Equirectangular {
+            // the direction whose ray, starting to its side on the eye circle, passes through
+            // the point
+            let (eye_width, eye_height) = stereo.eye_size(width, height);
+            let to_point = point - self.center;
+            let (x, y, z) = (
+                to_point.dot(self.right),
+                to_point.dot(self.up),
+                -to_point.dot(self.forward),
+            );
+            let radius = 0.5 * stereo.interpupillary_distance;
+            let distance = x.hypot(z);
+            if distance <= radius {
+                return None;
+            }
+            let phi = x.atan2(z) - (eye.side() * radius / distance).asin();
+            let phi = (phi + PI).rem_euclid(2.0 * PI) - PI;
+            let theta = (distance * distance - radius * radius).sqrt().atan2(y);
+            Vec2::new(
+                (phi / (2.0 * PI) + 0.5) * eye_width,
+                theta / PI * eye_height,
+            )
+        } else {
+            // the point as the camera's center would see it if it were the eye
+            let (eye_center, rotation) = self.eye_frame(eye, stereo);
+            self.project_center(self.center + rotation.inverse() * (point - eye_center))?
+        };
+        Some(stereo.to_image(eye, p, width, height))
+    }
+
+    /// where the camera's center sees `point` on the view, in pixels from its top left corner
+    fn project_center(&self, point: Vec3) -> Option<Vec2> {
+        let to_point = point - self.center;
+        let local = Vec3::new(
+            to_point.dot(self.right),
+            to_point.dot(self.up),
+            -to_point.dot(self.forward),
+        );
+        let (width, height) = self.eye_size();
+        let on_image = |p: Vec2| {
+            // the distortion moves the point on the image the way `undistort` undoes
+            let p = if self.distortion.is_identity() {
+                p
+            } else {
+                self.distortion.distort(p)
+            };
+            Vec2::new(0.5 * width, 0.5 * height) + Vec2::new(p.x, -p.y) * self.focal_length_px
+        };
+        match self.projection {
+            Projection::Perspective => {
+                (local.z > 0.0).then(|| on_image(Vec2::new(local.x, local.y) / local.z))
+            }
+            Projection::Orthographic => {
+                Some(on_image(Vec2::new(local.x, local.y) / self.focal_length))
+            }
+            Projection::Fisheye(fov) => {
+                let dir = local.try_normalize()?;
+                let radius = dir.z.clamp(-1.0, 1.0).acos() / (0.5 * fov.to_radians());
+                if radius > 1.0 {
+                    return None;
+                }
+                let phi = dir.y.atan2(dir.x);
+                let half_extent = 0.5 * width.min(height);
+                Some(Vec2::new(
+                    0.5 * width + radius * phi.cos() * half_extent,
+                    0.5 * height - radius * phi.sin() * half_extent,
+                ))
+            }
+            Projection::Equirectangular => {
+                let dir = local.try_normalize()?;
+                // the panorama's y is up and its z is the camera's forward
+                let theta = dir.y.clamp(-1.0, 1.0).acos();
+                let phi = dir.x.atan2(dir.z);
+                Some(Vec2::new(
+                    (phi / (2.0 * PI) + 0.5) * width,
+                    theta / PI * height,
+                ))
+            }
+        }
+    }
+
+    /// where sample `i` of `n` through a pixel goes, in pixels from the pixel center, and how
+    /// much it counts. the first samples are jittered over an n by n grid of strata of the
+    /// filter, the ones that don't fill another row and column are placed at random
+    fn pixel_offset(&self, i: usize, n: usize) -> (Vec2, Float) {
+        let mut rng = rng();
+        let jitter = Vec2::new(rng.gen::<Float>(), rng.gen::<Float>());
+        let strata = (n as Float).sqrt() as usize;
+        let u = if i < strata * strata {
+            let stratum = Vec2::new((i % strata) as Float, (i / strata) as Float);
+            (stratum + jitter) / strata as Float
+        } else {
+            jitter
+        };
+        self.filter.sample(u)
+    }
+
+    /// the color of pixel (`r`, `c`) from `samples` samples, and their sums for its variance
+    fn pixel_color(&self, r: usize, c: usize, world: &World, samples: usize) -> (Vec3, PixelStats) {
+        self.yield_if_background();
+        let mut sampler = Sampler::new();
+        // TODO instead of multiple random rays per pixel, could try other Anti-Alias methods
+        let mut stats = PixelStats::default();
+        for color in self.pixel_samples(r, c, world, &mut sampler, 0..samples) {
+            stats.add(color, display_value(color * self.exposure_scale));
+        }
+        (stats.color * self.exposure_scale / samples as Float, stats)
+    }
+
+    // camera samples `indices` through pixel (`r`, `c`), before the exposure is applied. the
+    // rays are all generated first and handed to the integrator as one batch
+    fn pixel_samples(
+        &self,
+        r: usize,
+        c: usize,
+        world: &World,
+        sampler: &mut Sampler,
+        indices: Range<usize>,
+    ) -> Vec<Vec3> {
+        if self.stats_map.is_some() {
+            // start the pixel's counts from zero
+            RayStats::take();
+        }
+        let (start, samples) = (indices.start, indices.len());
+        let mut colors: Vec<Vec3> = match &self.blue_noise {
+            // a dithered sample draws the numbers of its ray and its first bounce together, so
+            // the samples go through the integrator one at a time
+            Some(mask) => indices
+                .filter_map(|index| {
+                    start_dither(mask, c, r, index);
+                    let color = self.camera_ray(r, c, sampler, index - start, samples).map(
+                        |(ray, weight)| {
+                            let color = self.integrator.li_batch(&[ray], world, sampler)[0];
+                            color * self.vignette(&ray) * weight
+                        },
+                    );
+                    stop_dither();
+                    color
+                })
+                .collect(),
+            None => {
+                let (rays, weights): (Vec<Ray>, Vec<Float>) = (0..samples)
+                    .filter_map(|i| self.camera_ray(r, c, sampler, i, samples))
+                    .unzip();
+                self.integrator
+                    .li_batch(&rays, world, sampler)
+                    .into_iter()
+                    .zip(rays.iter().zip(weights))
+                    .map(|(color, (ray, weight))| color * self.vignette(ray) * weight)
+                    .collect()
+            }
+        };
+        ray_stats::count_rays(RayKind::Camera, colors.len());
+        if let Some(stats_map) = &self.stats_map {
+            stats_map.add(r, c, RayStats::take());
+        }
+        // samples outside the image circle of a fisheye are black
+        colors.resize(samples, Vec3::ZERO);
+        colors
+    }
+
+    /// the ray of sample `i` of `n` through pixel (`r`, `c`) and how much it counts, none
+    /// outside the image circle of a fisheye
+    fn camera_ray(
+        &self,
+        r: usize,
+        c: usize,
+        sampler: &mut Sampler,
+        i: usize,
+        n: usize,
+    ) -> Option<(Ray, Float)> {
+        let lens = sampler.next_2d();
+        let (pixel_offset, weight) = self.pixel_offset(i, n);
+        let sample = CameraSample {
+            pixel_offset,
+            lens: (!self.defocus_preview).then_some(lens),
+            time: rng().gen::<Float>(),
+        };
+        Some((self.generate_ray(r, c, &sample)?, weight))
+    }
+
+    /// ray through pixel (`r`, `c`), where `sample` says where in the pixel and on the lens it starts
+    pub fn generate_ray(&self, r: usize, c: usize, sample: &CameraSample) -> Option<Ray> {
+        // position on the image in pixels, measured from the top left corner
+        let x = c as Float + 0.5 + sample.pixel_offset.x;
+        let y = r as Float + 0.5 + sample.pixel_offset.y;
+        let (eye, Vec2 { x, y }) = self.eye_at(x, y);
+        let ray = self.eye_ray(eye, x, y, sample)?.with_type(RayType::Camera);
+
+        // rays one pixel over through the same point on the lens, for filtering textures. with
+        // many samples each one only has to cover part of the pixel (pbrt's 1 / sqrt(spp)).
+        // they stay in the same eye's view at the edge of a stereo image
+        match (
+            self.eye_ray(eye, x + 1.0, y, sample),
+            self.eye_ray(eye, x, y + 1.0, sample),
+        ) {
+            (Some(rx), Some(ry)) => {
+                let scale = (self.samples_per_pixel.max(1) as Float)
+                    .sqrt()
+                    .recip()
+                    .max(0.125);
+                Some(ray.with_differential(&rx, &ry).scale_differential(scale))
+            }
+            _ => Some(ray),
+        }
+    }
+
+    /// ray through the point (`x`, `y`) on the image, in pixels from the top left corner
+    fn ray_through(&self, x: Float, y: Float, sample: &CameraSample) -> Option<Ray> {
+        let (eye, Vec2 { x, y }) = self.eye_at(x, y);
+        self.eye_ray(eye, x, y, sample)
+    }
+
+    /// the size of the view of each eye, the whole image without stereo
+    fn eye_size(&self) -> (Float, Float) {
+        let (width, height) = (self.image_width as Float, self.image_height as Float);
+        match self.stereo {
+            Some(stereo) => stereo.eye_size(width, height),
+            None => (width, height),
+        }
+    }
+
+    /// the eye that sees the point (`x`, `y`) of the image and where it is in its view, none
+    /// without stereo
+    fn eye_at(&self, x: Float, y: Float) -> (Option<Eye>, Vec2) {
+        let (width, height) = (self.image_width as Float, self.image_height as Float);
+        match self.stereo {
+            Some(stereo) => {
+                let (eye, p) = stereo.eye_at(Vec2::new(x, y), width, height);
+                (Some(eye), p)
+            }
+            None => (None, Vec2::new(x, y)),
+        }
+    }
+
+    /// ray through the point (`x`, `y`) of `eye`'s view, from the camera's center without one
+    fn eye_ray(&self, eye: Option<Eye>, x: Float, y: Float, sample: &CameraSample) -> Option<Ray> {
+        let ray = self.center_ray(x, y, sample)?;
+        let (Some(eye), Some(stereo)) = (eye, self.stereo) else {
+            return Some(ray);
+        };
+        let half_ipd = 0.5 * stereo.interpupillary_distance * eye.side();
+        if self.projection == Projection::Equirectangular {
+            // omni-directional stereo: the eye sits on a circle, to the side of the direction
+            // it looks in
+            let dir = ray.direction();
+            let (x, z) = (dir.dot(self.right), -dir.dot(self.forward));
+            let side = (self.right * z + self.forward * x)
+                .try_normalize()
+                .unwrap_or(Vec3::ZERO);
+            return Some(Ray::new(ray.origin() + side * half_ipd, dir, ray.time()));
+        }
+        let (eye_center, rotation) = self.eye_frame(eye, stereo);
+        Some(Ray::new(
+            eye_center + rotation * (ray.origin() - self.center),
+            rotation * ray.direction(),
+            ray.time(),
+        ))
+    }
+
+    /// where `eye` is and how it turns from the camera's view direction, toed in to look at
+    /// the point of convergence
+    fn eye_frame(&self, eye: Eye, stereo: Stereo) -> (Vec3, Quat) {
+        let eye_center =
+            self.center + self.right * (0.5 * stereo.interpupillary_distance * eye.side());
+        let rotation = match stereo.convergence {
+            Some(distance) => {
+                let target = self.center - self.forward * distance;
+                let to_target = (target - eye_center)
+                    .try_normalize()
+                    .unwrap_or(-self.forward);
+                Quat::from_rotation_arc(-self.forward, to_target)
+            }
+            None => Quat::IDENTITY,
+        };
+        (eye_center, rotation)
+    }
+
+    /// ray through the point (`x`, `y`) of the view, in pixels from its top left corner, as
+    /// seen from the camera's center
+    fn center_ray(&self, x: Float, y: Float, sample: &CameraSample) -> Option<Ray> {
+        let ray_time = sample.time;
+        match self.projection {
+            Projection::Perspective => {
+                let (x, y) = self.undistort(x, y);
+                let sample_location =
+                    self.pixel00 + (self.pixel_dv * (y - 0.5)) + (self.pixel_du * (x - 0.5));
+
+                let dof_offset_right = self.right * self.lens_radius;
+                let dof_offset_up = self.up * self.lens_radius;
+                let p = match sample.lens {
+                    Some(u) => self.aperture.sample(u),
+                    None => Vec2::ZERO,
+                };
+
+                let ray_origin = self.center + (dof_offset_right * p.x) + (dof_offset_up * p.y);
+                // every ray through the lens meets the one through its center where that crosses
+                // the plane in focus. a pinhole doesn't need the focus at all
+                let ray_direction = if self.lens_radius > 0.0 {
+                    let focus_point =
+                        self.center + (sample_location - self.center) * self.focus_distance();
+                    focus_point - ray_origin
+                } else {
+                    sample_location - ray_origin
+                };
+                Some(Ray::new(ray_origin, ray_direction, ray_time))
+            }
+            Projection::Orthographic => {
+                // the parallel rays cover what the perspective viewport would at `focal_length`
+                let (x, y) = self.undistort(x, y);
+                let sample_location =
+                    self.pixel00 + (self.pixel_dv * (y - 0.5)) + (self.pixel_du * (x - 0.5));
+                let offset = (sample_location - self.center + self.forward) * self.focal_length;
+                Some(Ray::new(self.center + offset, -self.forward, ray_time))
+            }
+            Projection::Fisheye(fov) => {
+                // the image circle touches the shorter side of the image
+                let (width, height) = self.eye_size();
+                let half_extent = 0.5 * width.min(height);
+                let nx = (x - 0.5 * width) / half_extent;
+                let ny = (0.5 * height - y) / half_extent;
+                let radius = (nx * nx + ny * ny).sqrt();
+                if radius > 1.0 {
+                    return None;
+                }
+                let theta = radius * 0.5 * fov.to_radians();
+                let phi = ny.atan2(nx);
+                let dir = self.local_to_world(Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ));
+                Some(Ray::new(self.center, dir, ray_time))
+            }
+            Projection::Equirectangular => {
+                let (width, height) = self.eye_size();
+                let phi = (x / width - 0.5) * 2.0 * PI;
+                let theta = y / height * PI;
+                let dir = self.local_to_world(Vec3::new(
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                    theta.sin() * phi.cos(),
+                ));
+                Some(Ray::new(self.center, dir, ray_time))
+            }
+        }
+    }
+
+    /// position on the image in pixels that the lens distortion moves to (`x`, `y`)
+    fn undistort(&self, x: Float, y: Float) -> (Float, Float) {
+        if self.distortion.is_identity() {
+            return (x, y);
+        }
+        let (width, height) = self.eye_size();
+        let center = Vec2::new(width, height) * 0.5;
+        let p = (Vec2::new(x, y) - center) / self.focal_length_px;
+        let p = self.distortion.undistort(p) * self.focal_length_px + center;
+        (p.x, p.y)
+    }
+
+    /// light falloff towards the edges of the image, scaling the radiance carried by `ray`
+    fn vignette(&self, ray: &Ray) -> Float {
+        if self.vignetting == 0.0 {
+            return 1.0;
+        }
+        let cos = (-ray.direction().dot(self.forward)).max(0.0);
+        1.0 - self.vignetting + self.vignetting * cos.powi(4)
+    }
+
+    /// camera space has x to the right, y up, and z looking into the scene
+    fn local_to_world(&self, dir: Vec3) -> Vec3 {
+        self.right * dir.x + self.up * dir.y - self.forward * dir.z
+    }
+}
+
+/// Where a camera ray starts within its pixel and on the lens, and when it is fired
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSample {
+    /// offset from the pixel center in pixels, picked by the pixel filter
+    pub pixel_offset: Vec2,
+    /// uniform sample in [0, 1)^2 that picks the point on the aperture, `None` goes through the lens center
+    pub lens: Option<Vec2>,
+    pub time: Float,
+}
+
+impl CameraSample {
+    /// ray through the pixel center and the center of the lens, halfway through the shutter
+    pub fn center() -> CameraSample {
+        CameraSample {
+            pixel_offset: Vec2::ZERO,
+            lens: None,
+            time: 0.5,
+        }
+    }
+}
+
+impl<I: Integrator + Default> Default for Camera<I> {
+    fn default() -> Self {
+        Self::with_integrator(I::default())
+    }
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Self::default()
+    }
+}
+
+/// a different bright color for each box, going around the hue circle by the golden ratio
+fn box_color(i: usize) -> Vec3 {
+    let hue = (i as Float * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    Vec3::new(r, g, b) * 0.8 + Vec3::splat(0.2)
+}
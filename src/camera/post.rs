@@ -0,0 +1,236 @@
+//! Effects run over the finished film for stylized final images, on the linear colors before
+//! they're encoded, in the order they're listed in `Camera::post_effects`.
+
+use image::Rgba;
+
+use crate::{
+    sampler::mix_seed,
+    vec3::{consts, Float, Vec2, Vec3, VectorExt},
+};
+
+use super::FilmBuffer;
+
+/// One pass over the film
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostEffect {
+    /// lateral chromatic aberration of a cheap lens: red is magnified by `strength` about the
+    /// image center and blue shrunk by it, so edges get colored fringes towards the corners
+    ChromaticAberration { strength: Float },
+    /// grains about `size` pixels across that make each pixel up to `amount` brighter or
+    /// darker, most in the midtones. the same `seed` gives the same grain
+    FilmGrain {
+        amount: Float,
+        size: Float,
+        seed: u64,
+    },
+    /// the light of pixels brighter than `threshold` spread over `radius` pixels around them by
+    /// `kernel`, the way a lens scatters a bit of the light of everything. only what's over the
+    /// threshold is spread, and `intensity` of it leaves the pixel for its neighbours, so a light
+    /// glows instead of clipping to a flat white disk and the total light stays the same
+    Bloom {
+        threshold: Float,
+        radius: Float,
+        intensity: Float,
+        kernel: BloomKernel,
+    },
+}
+
+/// How the bloom spreads the light of a bright pixel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BloomKernel {
+    /// a soft round glow
+    Gaussian,
+    /// streaks in `points` directions fading out over the radius, the glare of a lens with
+    /// straight aperture blades
+    Star { points: usize },
+}
+
+impl PostEffect {
+    pub fn apply(&self, film: &mut FilmBuffer) {
+        match *self {
+            PostEffect::ChromaticAberration { strength } => chromatic_aberration(film, strength),
+            PostEffect::FilmGrain { amount, size, seed } => film_grain(film, amount, size, seed),
+            PostEffect::Bloom {
+                threshold,
+                radius,
+                intensity,
+                kernel,
+            } => bloom(film, threshold, radius, intensity, kernel),
+        }
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn chromatic_aberration(film: &mut FilmBuffer, strength: Float) {
+    if strength == 0.0 {
+        return;
+    }
+    let source = film.pixels.clone();
+    let (width, height) = (film.width(), film.height());
+    let center = Vec2::new(width as Float, height as Float) * 0.5;
+    // channel `c` of the image at `p` in pixels, bilinear between the pixel centers
+    let channel = |p: Vec2, c: usize| {
+        let p = (p - 0.5).clamp(Vec2::ZERO, center * 2.0 - 1.0);
+        let (x0, y0) = (p.x.floor(), p.y.floor());
+        let (tx, ty) = (p.x - x0, p.y - y0);
+        let (x0, y0) = (x0 as u32, y0 as u32);
+        let (x1, y1) = (
+            (x0 + 1).min(width as u32 - 1),
+            (y0 + 1).min(height as u32 - 1),
+        );
+        let at = |x, y| source.get_pixel(x, y).0[c] as Float;
+        let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+        let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    };
+    for (x, y, pixel) in film.pixels.enumerate_pixels_mut() {
+        let offset = Vec2::new(x as Float, y as Float) + 0.5 - center;
+        let red = channel(center + offset * (1.0 - strength), 0);
+        let blue = channel(center + offset * (1.0 + strength), 2);
+        let Rgba([_, g, _, a]) = *pixel;
+        *pixel = Rgba([red as f32, g, blue as f32, a]);
+    }
+}
+
+fn film_grain(film: &mut FilmBuffer, amount: Float, size: Float, seed: u64) {
+    if amount == 0.0 {
+        return;
+    }
+    let size = size.max(1e-3);
+    // a random value between -1 and 1 at every corner of a grid of grains
+    let corner = |x: i64, y: i64| {
+        let hash = mix_seed(seed, &[x as u64, y as u64]);
+        (hash >> 11) as Float / (1u64 << 53) as Float * 2.0 - 1.0
+    };
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            let p = (Vec2::new(x as Float, y as Float) + 0.5) / size;
+            let (x0, y0) = (p.x.floor(), p.y.floor());
+            // smoothstepped, so the grains don't show the grid
+            let t = (p - Vec2::new(x0, y0)).map(|t| t * t * (3.0 - 2.0 * t));
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let top = corner(x0, y0) * (1.0 - t.x) + corner(x0 + 1, y0) * t.x;
+            let bottom = corner(x0, y0 + 1) * (1.0 - t.x) + corner(x0 + 1, y0 + 1) * t.x;
+            let grain = top * (1.0 - t.y) + bottom * t.y;
+
+            let color = film.color(x, y);
+            // as strong as it gets at mid grey on screen, and gone in black and white
+            let display = color.luminance().clamp(0.0, 1.0).sqrt();
+            let midtones = 4.0 * display * (1.0 - display);
+            let color: Vec3 = color * (1.0 + amount * grain * midtones).max(0.0);
+            let (alpha, samples) = (film.alpha(x, y), film.sample_count(x, y));
+            film.set(x, y, color, alpha, samples);
+        }
+    }
+}
+
+fn bloom(
+    film: &mut FilmBuffer,
+    threshold: Float,
+    radius: Float,
+    intensity: Float,
+    kernel: BloomKernel,
+) {
+    if intensity == 0.0 || radius <= 0.0 {
+        return;
+    }
+    let (width, height) = (film.width(), film.height());
+    // the light over the threshold, keeping its hue
+    let excess: Vec<Vec3> = (0..width * height)
+        .map(|i| {
+            let color = film.color(i % width, i / width);
+            let luminance = color.luminance();
+            match luminance - threshold {
+                over if over > 0.0 => color * (over / luminance),
+                _ => Vec3::ZERO,
+            }
+        })
+        .collect();
+    if excess.iter().all(|&excess| excess == Vec3::ZERO) {
+        return;
+    }
+    let spread = match kernel {
+        BloomKernel::Gaussian => gaussian_blur(&excess, width, height, radius),
+        BloomKernel::Star { points } => star_glare(&excess, width, height, radius, points.max(1)),
+    };
+    for (i, (excess, spread)) in excess.into_iter().zip(spread).enumerate() {
+        let (x, y) = (i % width, i / width);
+        let color = film.color(x, y) + (spread - excess) * intensity;
+        let (alpha, samples) = (film.alpha(x, y), film.sample_count(x, y));
+        film.set(x, y, color, alpha, samples);
+    }
+}
+
+/// `image` blurred by a gaussian that reaches `radius` pixels (three standard deviations),
+/// along the rows and then the columns. the light blurred past the edges is lost
+fn gaussian_blur(image: &[Vec3], width: usize, height: usize, radius: Float) -> Vec<Vec3> {
+    let sigma = (radius / 3.0).max(0.5);
+    let reach = radius.ceil() as isize;
+    let weights: Vec<Float> = (-reach..=reach)
+        .map(|d| (-0.5 * (d as Float / sigma).powi(2)).exp())
+        .collect();
+    let total: Float = weights.iter().sum();
+    let blur = |image: &[Vec3], step: (usize, usize)| {
+        let mut blurred = vec![Vec3::ZERO; image.len()];
+        for y in 0..height {
+            for x in 0..width {
+                if image[y * width + x] == Vec3::ZERO {
+                    continue;
+                }
+                for (d, weight) in (-reach..=reach).zip(&weights) {
+                    let (nx, ny) = (
+                        x as isize + d * step.0 as isize,
+                        y as isize + d * step.1 as isize,
+                    );
+                    if (0..width as isize).contains(&nx) && (0..height as isize).contains(&ny) {
+                        blurred[ny as usize * width + nx as usize] +=
+                            image[y * width + x] * (weight / total);
+                    }
+                }
+            }
+        }
+        blurred
+    };
+    blur(&blur(image, (1, 0)), (0, 1))
+}
+
+/// the light of every pixel of `image` split between itself and `points` streaks fading out
+/// over `radius` pixels
+fn star_glare(
+    image: &[Vec3],
+    width: usize,
+    height: usize,
+    radius: Float,
+    points: usize,
+) -> Vec<Vec3> {
+    let reach = radius.ceil() as usize;
+    // exponential falloff, down to about 2% at the end of a streak
+    let falloff: Vec<Float> = (1..=reach)
+        .map(|t| (-4.0 * t as Float / radius).exp())
+        .collect();
+    let total = 1.0 + points as Float * falloff.iter().sum::<Float>();
+    let directions: Vec<Vec2> = (0..points)
+        .map(|k| {
+            let angle = consts::PI * (0.25 + 2.0 * k as Float / points as Float);
+            Vec2::new(angle.cos(), angle.sin())
+        })
+        .collect();
+    let mut spread = vec![Vec3::ZERO; image.len()];
+    for (i, &light) in image.iter().enumerate() {
+        if light == Vec3::ZERO {
+            continue;
+        }
+        spread[i] += light / total;
+        let p = Vec2::new((i % width) as Float, (i / width) as Float) + 0.5;
+        for dir in &directions {
+            for (t, weight) in falloff.iter().enumerate() {
+                let q = p + *dir * (t + 1) as Float;
+                if q.x < 0.0 || q.y < 0.0 || q.x >= width as Float || q.y >= height as Float {
+                    break;
+                }
+                spread[q.y as usize * width + q.x as usize] += light * (weight / total);
+            }
+        }
+    }
+    spread
+}
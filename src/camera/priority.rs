@@ -0,0 +1,61 @@
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+use crate::vec3::Float;
+
+/// Rendering in the background of a workstation: on a share of the cores, with the render
+/// threads at a low OS priority and yielding after every pixel, so long renders don't make
+/// everything else sluggish
+#[derive(Debug, Clone, Copy)]
+pub struct BackgroundPriority {
+    /// share of the cores to render on, at least one is always used
+    pub cpu_fraction: Float,
+    /// unix niceness of the render threads, from 0 to 19. higher gives way to other programs more
+    pub niceness: i32,
+}
+
+impl BackgroundPriority {
+    pub fn new(cpu_fraction: Float) -> BackgroundPriority {
+        BackgroundPriority {
+            cpu_fraction: cpu_fraction.clamp(0.0, 1.0),
+            niceness: 10,
+        }
+    }
+
+    pub fn with_niceness(mut self, niceness: i32) -> Self {
+        self.niceness = niceness.clamp(0, 19);
+        self
+    }
+
+    pub fn threads(&self) -> usize {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        ((cores as Float * self.cpu_fraction).floor() as usize).clamp(1, cores)
+    }
+
+    /// a pool of `threads()` render threads running at the lower priority
+    pub fn thread_pool(&self) -> Result<ThreadPool, ThreadPoolBuildError> {
+        let niceness = self.niceness;
+        ThreadPoolBuilder::new()
+            .num_threads(self.threads())
+            .thread_name(|i| format!("background render {i}"))
+            .start_handler(move |_| lower_priority(niceness))
+            .build()
+    }
+}
+
+impl Default for BackgroundPriority {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+#[cfg(unix)]
+fn lower_priority(niceness: i32) {
+    // on linux this only changes the calling thread, the rest of the program keeps its priority.
+    // a failure leaves the thread as it was, which is fine
+    unsafe {
+        libc::nice(niceness);
+    }
+}
+
+#[cfg(not(unix))]
+fn lower_priority(_niceness: i32) {}
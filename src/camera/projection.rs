@@ -0,0 +1,15 @@
+use crate::vec3::Float;
+
+/// How points on the image map to ray directions
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Projection {
+    /// thin lens perspective camera
+    #[default]
+    Perspective,
+    /// parallel rays, covering the same area as the perspective viewport at the focal plane
+    Orthographic,
+    /// equidistant fisheye covering this many degrees across the image circle
+    Fisheye(Float),
+    /// full 360 x 180 degree lat-long panorama, best rendered at a 2:1 aspect ratio
+    Equirectangular,
+}
@@ -0,0 +1,59 @@
+use image::{imageops, ImageBuffer, Pixel};
+
+/// A rectangle of pixels to render, from (x0, y0) up to but not including (x1, y1), for
+/// iterating on one part of an image without rendering all of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Region {
+    /// the corners can be given either way round
+    pub fn new(x0: usize, y0: usize, x1: usize, y1: usize) -> Region {
+        Region {
+            x0: x0.min(x1),
+            y0: y0.min(y1),
+            x1: x0.max(x1),
+            y1: y0.max(y1),
+        }
+    }
+
+    pub fn full(width: usize, height: usize) -> Region {
+        Region::new(0, 0, width, height)
+    }
+
+    /// the part of the region inside an image of this size
+    pub fn clamped(&self, width: usize, height: usize) -> Region {
+        Region::new(
+            self.x0.min(width),
+            self.y0.min(height),
+            self.x1.min(width),
+            self.y1.min(height),
+        )
+    }
+
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x0..self.x1).contains(&x) && (self.y0..self.y1).contains(&y)
+    }
+
+    /// the region cut out of `image`
+    pub fn crop<P: Pixel + 'static>(
+        &self,
+        image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let (x, y) = (self.x0 as u32, self.y0 as u32);
+        let (width, height) = (self.width() as u32, self.height() as u32);
+        imageops::crop_imm(image, x, y, width, height).to_image()
+    }
+}
@@ -0,0 +1,88 @@
+use crate::vec3::{Float, Vec2};
+
+/// How the views of the two eyes share the image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// the left eye in the left half of the image, the right eye in the right half
+    #[default]
+    SideBySide,
+    /// the left eye in the top half, the right eye below it, the usual layout of 360 video
+    TopBottom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    /// which way along the camera's right the eye sits, -1 for the left one
+    pub fn side(self) -> Float {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+/// Renders the scene for two eyes `interpupillary_distance` apart into the two halves of the
+/// image. `image_width` and `aspect_ratio` are those of the whole image, each eye sees the
+/// field of view of the camera over its half. with the equirectangular projection the eyes
+/// go around a circle instead, omni-directional stereo: every ray starts from where an eye
+/// looking its way would be, so the panorama is stereo in every direction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+    /// the distance between the eyes in scene units, 0.064 for a scene modeled in meters
+    pub interpupillary_distance: Float,
+    /// how far in front of the camera the eyes' view axes cross, toeing them in so things at
+    /// that distance line up in both views. None keeps them parallel, which puts everything
+    /// in front of the screen. the equirectangular projection always keeps them parallel
+    pub convergence: Option<Float>,
+    pub layout: StereoLayout,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Stereo {
+            interpupillary_distance: 0.064,
+            convergence: None,
+            layout: StereoLayout::default(),
+        }
+    }
+}
+
+impl Stereo {
+    /// the size of each eye's view of an image `width` by `height` pixels
+    pub fn eye_size(&self, width: Float, height: Float) -> (Float, Float) {
+        match self.layout {
+            StereoLayout::SideBySide => (0.5 * width, height),
+            StereoLayout::TopBottom => (width, 0.5 * height),
+        }
+    }
+
+    /// the eye that sees the point `p` of an image `width` by `height` pixels, and where the
+    /// point is in its view
+    pub fn eye_at(&self, p: Vec2, width: Float, height: Float) -> (Eye, Vec2) {
+        let (eye_width, eye_height) = self.eye_size(width, height);
+        let (beyond, offset) = match self.layout {
+            StereoLayout::SideBySide => (p.x >= eye_width, Vec2::new(eye_width, 0.0)),
+            StereoLayout::TopBottom => (p.y >= eye_height, Vec2::new(0.0, eye_height)),
+        };
+        if beyond {
+            (Eye::Right, p - offset)
+        } else {
+            (Eye::Left, p)
+        }
+    }
+
+    /// where the point `p` of `eye`'s view is in the whole image, the inverse of `eye_at`
+    pub fn to_image(&self, eye: Eye, p: Vec2, width: Float, height: Float) -> Vec2 {
+        let (eye_width, eye_height) = self.eye_size(width, height);
+        match (eye, self.layout) {
+            (Eye::Left, _) => p,
+            (Eye::Right, StereoLayout::SideBySide) => p + Vec2::new(eye_width, 0.0),
+            (Eye::Right, StereoLayout::TopBottom) => p + Vec2::new(0.0, eye_height),
+        }
+    }
+}
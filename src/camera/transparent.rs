@@ -0,0 +1,144 @@
+//! Renders with a transparent background, for compositing over photos. Camera samples that
+//! miss everything are transparent and the ones that hit an object are opaque. Shadow
+//! catchers (see `World::add_shadow_catcher`) are transparent too, except for the shadows and
+//! reflections the other objects leave on them: each catcher pixel is also rendered in a world
+//! with only the catchers in it, and how much darker it is with the objects there becomes its
+//! alpha.
+
+use rand::Rng;
+use rayon::prelude::*;
+
+use crate::{
+    hittable::World,
+    integrator::Integrator,
+    interval::Interval,
+    sampler::rng,
+    sampler::Sampler,
+    vec3::{Float, Vec3, VectorExt},
+};
+
+use super::{Camera, CameraSample, FilmBuffer};
+
+/// The samples of a pixel, split by what they hit first
+#[derive(Debug, Clone, Copy, Default)]
+struct Coverage {
+    samples: usize,
+    /// samples that hit an object and their colors
+    opaque: usize,
+    color: Vec3,
+    /// samples that hit a shadow catcher, and their colors with and without the objects
+    caught: usize,
+    with_objects: Vec3,
+    without_objects: Vec3,
+}
+
+impl Coverage {
+    /// the color over an alpha of 0 (transparent) to 1, premultiplied
+    fn premultiplied(&self) -> (Vec3, Float) {
+        let n = self.samples.max(1) as Float;
+        // the catchers are as transparent as they are bright compared to how they'd be
+        let lit = match self.without_objects.luminance() {
+            bare if bare > 0.0 => (self.with_objects.luminance() / bare).clamp(0.0, 1.0),
+            _ => 1.0,
+        };
+        let reflected = (self.with_objects - lit * self.without_objects).max(Vec3::ZERO);
+        let alpha = (self.opaque as Float + self.caught as Float * (1.0 - lit)) / n;
+        ((self.color + reflected) / n, alpha)
+    }
+}
+
+impl<I: Integrator> Camera<I> {
+    /// the image with its alpha. the colors are straight, not premultiplied, which is what PNGs
+    /// store. only for integrators that render in one pass, and without adaptive sampling
+    pub(super) fn render_transparent(&self, world: &World) -> FilmBuffer {
+        let catchers = world.shadow_catcher_world();
+        let (width, height) = (self.image_width, self.image_height);
+        let region = self.region();
+        let pixel = |i: usize| {
+            if !region.contains(i % width, i / width) || self.cancelled() {
+                return None;
+            }
+            self.yield_if_background();
+            self.seed_pixel(i / width, i % width, 0);
+            let coverage = self.pixel_coverage(i / width, i % width, world, catchers.as_ref());
+            let (color, alpha) = coverage.premultiplied();
+            let color = match alpha {
+                // a color without anything covering it can't be seen, e.g. only reflections
+                // on a catcher
+                a if a > 0.0 => color / a,
+                _ => Vec3::ZERO,
+            };
+            Some((color * self.exposure_scale, alpha))
+        };
+        let pixels: Vec<Option<(Vec3, Float)>> = if cfg!(debug_assertions) {
+            log::info!("rendering debug, transparent");
+            (0..width * height).map(pixel).collect()
+        } else {
+            log::info!("rendering production, transparent");
+            (0..width * height).into_par_iter().map(pixel).collect()
+        };
+
+        let mut film = FilmBuffer::new(width, height);
+        film.has_alpha = true;
+        for (i, pixel) in pixels.into_iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            match pixel {
+                Some((color, alpha)) => film.set(x, y, color, alpha, self.samples_per_pixel),
+                None => film.set(x, y, Vec3::ZERO, 0.0, 0),
+            }
+        }
+        film
+    }
+
+    fn pixel_coverage(
+        &self,
+        r: usize,
+        c: usize,
+        world: &World,
+        catchers: Option<&World>,
+    ) -> Coverage {
+        let mut sampler = Sampler::new();
+        let mut coverage = Coverage::default();
+        let samples = self.samples_per_pixel.max(1);
+        for i in 0..samples {
+            coverage.samples += 1;
+            let lens = sampler.next_2d();
+            let (pixel_offset, weight) = self.pixel_offset(i, samples);
+            let sample = CameraSample {
+                pixel_offset,
+                lens: (!self.defocus_preview).then_some(lens),
+                time: rng().gen::<Float>(),
+            };
+            let Some(ray) = self.generate_ray(r, c, &sample) else {
+                continue;
+            };
+            let ray_t = Interval::FORWARD;
+            let Some((hit, _)) = world.intersect_all(&ray, ray_t) else {
+                continue; // the background
+            };
+            let color =
+                self.integrator.li(&ray, world, &mut sampler) * self.vignette(&ray) * weight;
+
+            // the first hit is a catcher if the world with only the catchers has it too
+            let catcher = catchers.and_then(|catchers| {
+                let (catcher_hit, is_light) = catchers.intersect_all(&ray, ray_t)?;
+                let same = (catcher_hit.dist - hit.dist).abs() <= 1e-4 * hit.dist.max(1.0);
+                (same && !is_light).then_some(catchers)
+            });
+            match catcher {
+                Some(catchers) => {
+                    coverage.caught += 1;
+                    coverage.with_objects += color;
+                    coverage.without_objects += self.integrator.li(&ray, catchers, &mut sampler)
+                        * self.vignette(&ray)
+                        * weight;
+                }
+                None => {
+                    coverage.opaque += 1;
+                    coverage.color += color;
+                }
+            }
+        }
+        coverage
+    }
+}
@@ -0,0 +1,321 @@
+//! A small C API for embedding the renderer in C, C++ or C# programs and benchmark harnesses,
+//! behind the `capi` feature. build it as a shared library with
+//! `cargo rustc --release --lib --features capi --crate-type cdylib`, the declarations are in
+//! `include/path_tracer.h`.
+//!
+//! a world is built up from spheres and quads with a handful of simple materials, and
+//! `pt_render` renders it into a buffer of linear RGBA floats that the caller frees with
+//! `pt_buffer_free`. the structs start with their own `size`, which callers set to `sizeof` the
+//! struct, and only ever grow at the end: fields past the end of an older caller's struct keep
+//! their defaults, so programs built against an older header keep working.
+
+use std::{
+    ffi::c_int,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::Arc,
+};
+
+use crate::{
+    bsdf::{diffuse::DiffuseBRDF, glass::GlassBSDF, metal::MetalBRDF, MatPtr},
+    camera::Camera,
+    environment::EnvironmentType,
+    hittable::{Hittable, Quad, Sphere, World},
+    material::DiffuseLight,
+    texture::SolidTexture,
+    vec3::{Float, Vec3},
+};
+
+pub const PT_OK: c_int = 0;
+/// a null pointer, a number out of range such as a negative radius, or a struct whose `size`
+/// is smaller than the first version of it
+pub const PT_INVALID_ARGUMENT: c_int = -1;
+/// the renderer panicked, the world may be left half changed
+pub const PT_PANIC: c_int = -2;
+
+pub const PT_DIFFUSE: c_int = 0;
+pub const PT_METAL: c_int = 1;
+pub const PT_GLASS: c_int = 2;
+/// emits `color`, objects with it are sampled as lights
+pub const PT_LIGHT: c_int = 3;
+
+/// A material by its kind and the few parameters the kinds share. `roughness` is used by metal
+/// and glass, `ior` only by glass
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PtMaterial {
+    /// `sizeof(PtMaterial)` in the caller's header
+    pub size: usize,
+    pub kind: c_int,
+    pub color: [f64; 3],
+    pub roughness: f64,
+    pub ior: f64,
+}
+
+/// Where the camera is and how much to render. the aspect ratio follows from the image size
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PtCamera {
+    /// `sizeof(PtCamera)` in the caller's header
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub look_from: [f64; 3],
+    pub look_at: [f64; 3],
+    pub vup: [f64; 3],
+    /// vertical field of view in degrees
+    pub vfov: f64,
+    /// 0 for a different image every time
+    pub seed: u64,
+}
+
+/// `PtMaterial` the way the first header declares it. frozen, the smallest struct a caller can
+/// pass is this one whatever is added to `PtMaterial` later
+#[repr(C)]
+struct PtMaterialV1 {
+    _size: usize,
+    _kind: c_int,
+    _color: [f64; 3],
+    _roughness: f64,
+    _ior: f64,
+}
+
+/// `PtCamera` the way the first header declares it, frozen like `PtMaterialV1`
+#[repr(C)]
+struct PtCameraV1 {
+    _size: usize,
+    _width: u32,
+    _height: u32,
+    _samples_per_pixel: u32,
+    _max_depth: u32,
+    _look_from: [f64; 3],
+    _look_at: [f64; 3],
+    _vup: [f64; 3],
+    _vfov: f64,
+    _seed: u64,
+}
+
+const MATERIAL_V1_SIZE: usize = mem::size_of::<PtMaterialV1>();
+const CAMERA_V1_SIZE: usize = mem::size_of::<PtCameraV1>();
+
+/// The opaque handle C sees. the BVH is built again before a render if objects were added
+pub struct PtWorld {
+    world: World,
+    dirty: bool,
+}
+
+impl PtWorld {
+    fn add(&mut self, object: impl Hittable + 'static, is_light: bool) {
+        if is_light {
+            self.world.add_light(object);
+        } else {
+            self.world.add_object(object);
+        }
+        self.dirty = true;
+    }
+}
+
+/// a new empty world under a black sky, free it with `pt_world_free`
+#[no_mangle]
+pub extern "C" fn pt_world_new() -> *mut PtWorld {
+    let mut world = World::new();
+    world.environment = EnvironmentType::Color(Vec3::ZERO);
+    Box::into_raw(Box::new(PtWorld { world, dirty: true }))
+}
+
+/// # Safety
+/// `world` must come from `pt_world_new` and not be used afterwards. null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn pt_world_free(world: *mut PtWorld) {
+    if !world.is_null() {
+        drop(Box::from_raw(world));
+    }
+}
+
+/// the color rays that hit nothing see
+///
+/// # Safety
+/// `world` must come from `pt_world_new`, or be null
+#[no_mangle]
+pub unsafe extern "C" fn pt_set_background(world: *mut PtWorld, r: f64, g: f64, b: f64) -> c_int {
+    let Some(world) = world.as_mut() else {
+        return PT_INVALID_ARGUMENT;
+    };
+    world.world.environment = EnvironmentType::Color(vec3([r, g, b]));
+    PT_OK
+}
+
+/// # Safety
+/// `world` must come from `pt_world_new`, `center` point to 3 doubles and `material` to a
+/// `PtMaterial` at least as big as its `size`, or any of them be null
+#[no_mangle]
+pub unsafe extern "C" fn pt_add_sphere(
+    world: *mut PtWorld,
+    center: *const f64,
+    radius: f64,
+    material: *const PtMaterial,
+) -> c_int {
+    let (Some(world), Some(center), Some(material)) = (
+        world.as_mut(),
+        read_vec3(center),
+        read_sized(material, MATERIAL_V1_SIZE),
+    ) else {
+        return PT_INVALID_ARGUMENT;
+    };
+    if radius.is_nan() || radius <= 0.0 {
+        return PT_INVALID_ARGUMENT;
+    }
+    let Some(bxdf) = to_material(&material) else {
+        return PT_INVALID_ARGUMENT;
+    };
+    catch(|| {
+        let sphere = Sphere::new_still(radius as Float, center, bxdf);
+        world.add(sphere, material.kind == PT_LIGHT);
+    })
+}
+
+/// a parallelogram from corner `q` along the edges `u` and `v`, facing `u × v`
+///
+/// # Safety
+/// `world` must come from `pt_world_new`, `q`, `u` and `v` point to 3 doubles each and
+/// `material` to a `PtMaterial` at least as big as its `size`, or any of them be null
+#[no_mangle]
+pub unsafe extern "C" fn pt_add_quad(
+    world: *mut PtWorld,
+    q: *const f64,
+    u: *const f64,
+    v: *const f64,
+    material: *const PtMaterial,
+) -> c_int {
+    let (Some(world), Some(q), Some(u), Some(v), Some(material)) = (
+        world.as_mut(),
+        read_vec3(q),
+        read_vec3(u),
+        read_vec3(v),
+        read_sized(material, MATERIAL_V1_SIZE),
+    ) else {
+        return PT_INVALID_ARGUMENT;
+    };
+    if u.cross(v).length_squared() <= 0.0 {
+        return PT_INVALID_ARGUMENT;
+    }
+    let Some(bxdf) = to_material(&material) else {
+        return PT_INVALID_ARGUMENT;
+    };
+    catch(|| world.add(Quad::new(q, u, v, bxdf), material.kind == PT_LIGHT))
+}
+
+/// render `world` with the path integrator into `width * height * 4` floats, the linear RGBA
+/// of every pixel row by row from the top left. null if an argument is invalid or the renderer
+/// panicked. the buffer belongs to the caller, free it with `pt_buffer_free`
+///
+/// # Safety
+/// `world` must come from `pt_world_new` and `camera` point to a `PtCamera` at least as big as
+/// its `size`, or either be null
+#[no_mangle]
+pub unsafe extern "C" fn pt_render(world: *mut PtWorld, camera: *const PtCamera) -> *mut f32 {
+    let (Some(world), Some(settings)) = (world.as_mut(), read_sized(camera, CAMERA_V1_SIZE)) else {
+        return ptr::null_mut();
+    };
+    if settings.width == 0 || settings.height == 0 || settings.vfov.is_nan() || settings.vfov <= 0.0
+    {
+        return ptr::null_mut();
+    }
+    let render = panic::catch_unwind(AssertUnwindSafe(|| {
+        if world.dirty {
+            world.world.build_bvh();
+            world.dirty = false;
+        }
+        let mut camera = Camera::new();
+        camera.image_width = settings.width as usize;
+        camera.aspect_ratio = settings.width as Float / settings.height as Float;
+        camera.samples_per_pixel = settings.samples_per_pixel.max(1) as usize;
+        camera.integrator.max_depth = settings.max_depth as usize;
+        camera.look_from = vec3(settings.look_from);
+        camera.look_at = vec3(settings.look_at);
+        camera.vup = vec3(settings.vup);
+        camera.vfov = settings.vfov as Float;
+        camera.focal_length = 1.0;
+        camera.seed = (settings.seed != 0).then_some(settings.seed);
+        camera.init();
+        camera.render_to_buffer(&world.world)
+    }));
+    match render {
+        Ok(film)
+            if film.width() == settings.width as usize
+                && film.height() == settings.height as usize =>
+        {
+            let pixels = film.pixels.into_raw().into_boxed_slice();
+            Box::into_raw(pixels) as *mut f32
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `buffer` must come from `pt_render` with the same `width` and `height`, and not be used
+/// afterwards. null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn pt_buffer_free(buffer: *mut f32, width: u32, height: u32) {
+    if !buffer.is_null() {
+        let len = width as usize * height as usize * 4;
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, len)));
+    }
+}
+
+fn catch(add: impl FnOnce()) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(add)) {
+        Ok(()) => PT_OK,
+        Err(_) => PT_PANIC,
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn to_material(material: &PtMaterial) -> Option<MatPtr> {
+    let color = vec3(material.color);
+    let roughness = material.roughness as Float;
+    let bxdf: MatPtr = match material.kind {
+        PT_DIFFUSE => Arc::new(DiffuseBRDF::from_rgb(color)),
+        PT_METAL => Arc::new(MetalBRDF::from_rgb(color, roughness)),
+        PT_GLASS if material.ior > 0.0 => Arc::new(GlassBSDF::new(
+            Arc::new(SolidTexture::new(color)),
+            Arc::new(SolidTexture::new(roughness.max(0.001))),
+            0.0,
+            material.ior as Float,
+        )),
+        PT_LIGHT => Arc::new(DiffuseLight::from_rgb(color)),
+        _ => return None,
+    };
+    Some(bxdf)
+}
+
+/// the struct `value` points to, which starts with its `size`. what's past the end of a smaller
+/// struct from an older header keeps its default. None if it's null or smaller than `min_size`
+unsafe fn read_sized<T: Copy + Default>(value: *const T, min_size: usize) -> Option<T> {
+    if value.is_null() {
+        return None;
+    }
+    let size = ptr::read(value as *const usize);
+    if size < min_size {
+        return None;
+    }
+    let mut read = T::default();
+    let len = size.min(mem::size_of::<T>());
+    ptr::copy_nonoverlapping(value as *const u8, &mut read as *mut T as *mut u8, len);
+    Some(read)
+}
+
+unsafe fn read_vec3(v: *const f64) -> Option<Vec3> {
+    if v.is_null() {
+        return None;
+    }
+    Some(vec3(ptr::read(v as *const [f64; 3])))
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn vec3([x, y, z]: [f64; 3]) -> Vec3 {
+    Vec3::new(x as Float, y as Float, z as Float)
+}
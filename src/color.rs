@@ -0,0 +1,130 @@
+//! Color management. Without it the renderer does what it always did: the bytes of textures are
+//! taken as linear values and the output is gamma corrected with a plain square root. With a
+//! `ColorSpace`, 8 bit textures are decoded from sRGB to linear when they're looked up, all the
+//! shading happens in the space's linear working space, and the images are encoded with its
+//! transfer function (OETF). Colors written in scene files are taken to be in the working
+//! space already.
+
+use crate::vec3::{Float, Vec3};
+
+/// The working space shading happens in, and how the images are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// linear Rec.709 primaries, encoded with the sRGB curve. what monitors expect
+    Srgb,
+    /// linear Rec.709 primaries, encoded with the BT.709 camera curve, for video
+    Rec709,
+    /// the wider ACES AP1 primaries for shading, which mixes saturated colors more like light
+    /// does. converted to Rec.709 and encoded with the sRGB curve for display
+    AcesCg,
+}
+
+// linear Rec.709 to ACEScg and back, with the Bradford adaptation from D65 to the ACES white
+const REC709_TO_ACESCG: [Vec3; 3] = [
+    Vec3::new(0.613_097, 0.339_523, 0.047_379),
+    Vec3::new(0.070_194, 0.916_354, 0.013_452),
+    Vec3::new(0.020_616, 0.109_570, 0.869_815),
+];
+const ACESCG_TO_REC709: [Vec3; 3] = [
+    Vec3::new(1.705_051, -0.621_792, -0.083_259),
+    Vec3::new(-0.130_257, 1.140_805, -0.010_548),
+    Vec3::new(-0.024_003, -0.128_969, 1.152_972),
+];
+
+fn transform(rows: &[Vec3; 3], color: Vec3) -> Vec3 {
+    Vec3::new(rows[0].dot(color), rows[1].dot(color), rows[2].dot(color))
+}
+
+impl ColorSpace {
+    /// "srgb", "rec709" or "acescg"
+    pub fn from_name(name: &str) -> Option<ColorSpace> {
+        match name {
+            "srgb" => Some(ColorSpace::Srgb),
+            "rec709" => Some(ColorSpace::Rec709),
+            "acescg" => Some(ColorSpace::AcesCg),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Rec709 => "rec709",
+            ColorSpace::AcesCg => "acescg",
+        }
+    }
+
+    /// a linear color with Rec.709 primaries in the working space
+    pub fn from_rec709(&self, color: Vec3) -> Vec3 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::Rec709 => color,
+            ColorSpace::AcesCg => transform(&REC709_TO_ACESCG, color),
+        }
+    }
+
+    /// a color of the working space with linear Rec.709 primaries
+    pub fn to_rec709(&self, color: Vec3) -> Vec3 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::Rec709 => color,
+            ColorSpace::AcesCg => transform(&ACESCG_TO_REC709, color),
+        }
+    }
+
+    /// the value of an sRGB encoded texel (between 0 and 1) in the working space
+    pub fn decode_texel(&self, encoded: Vec3) -> Vec3 {
+        self.from_rec709(encoded.map(srgb_to_linear))
+    }
+
+    /// a color of the working space the way it's stored in an image, between 0 and 1
+    pub fn encode(&self, color: Vec3) -> Vec3 {
+        let color = self.to_rec709(color).clamp(Vec3::ZERO, Vec3::ONE);
+        match self {
+            ColorSpace::Srgb | ColorSpace::AcesCg => color.map(linear_to_srgb),
+            ColorSpace::Rec709 => color.map(rec709_oetf),
+        }
+    }
+
+    /// the color of the working space that `encode` turns into `encoded`
+    pub fn decode(&self, encoded: Vec3) -> Vec3 {
+        let linear = match self {
+            ColorSpace::Srgb | ColorSpace::AcesCg => encoded.map(srgb_to_linear),
+            ColorSpace::Rec709 => encoded.map(rec709_inverse_oetf),
+        };
+        self.from_rec709(linear)
+    }
+}
+
+/// the sRGB decoding curve (EOTF), from an encoded value between 0 and 1 to linear
+pub fn srgb_to_linear(encoded: Float) -> Float {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// the sRGB encoding curve, from linear between 0 and 1 to the encoded value
+pub fn linear_to_srgb(linear: Float) -> Float {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// the BT.709 camera curve, from linear between 0 and 1 to the encoded value
+pub fn rec709_oetf(linear: Float) -> Float {
+    if linear < 0.018 {
+        4.5 * linear
+    } else {
+        1.099 * linear.powf(0.45) - 0.099
+    }
+}
+
+pub fn rec709_inverse_oetf(encoded: Float) -> Float {
+    if encoded < 0.081 {
+        encoded / 4.5
+    } else {
+        ((encoded + 0.099) / 1.099).powf(1.0 / 0.45)
+    }
+}
@@ -0,0 +1,160 @@
+//! Contact sheets: a labeled grid of renders, usually of the same scene with one parameter swept.
+
+use std::{fs, path::Path};
+
+use image::{Rgb, RgbImage};
+
+use crate::{
+    overlay::{draw_text, text_size},
+    scene::{parse_scene_with_overrides, MaterialRegistry, SceneError},
+    vec3::Float,
+};
+
+const PADDING: u32 = 4;
+
+/// Collects labeled images and lays them out in a grid
+pub struct ContactSheet {
+    /// cells per row, 0 picks a roughly square grid
+    pub columns: usize,
+    pub background: Rgb<u8>,
+    cells: Vec<(String, RgbImage)>,
+}
+
+impl ContactSheet {
+    pub fn new(columns: usize) -> ContactSheet {
+        ContactSheet {
+            columns,
+            background: Rgb([24, 24, 24]),
+            cells: vec![],
+        }
+    }
+
+    pub fn add(&mut self, label: &str, image: RgbImage) {
+        self.cells.push((label.to_string(), image));
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// every cell gets the size of the largest image, with its label written underneath
+    pub fn assemble(&self) -> RgbImage {
+        let columns = match self.columns {
+            0 => (self.cells.len() as Float).sqrt().ceil() as usize,
+            n => n,
+        }
+        .max(1);
+        let rows = self.cells.len().div_ceil(columns).max(1);
+
+        let image_width = self
+            .cells
+            .iter()
+            .map(|(_, img)| img.width())
+            .max()
+            .unwrap_or(0);
+        let image_height = self
+            .cells
+            .iter()
+            .map(|(_, img)| img.height())
+            .max()
+            .unwrap_or(0);
+        // small thumbnails get small labels, and cells grow to fit labels that are still too long
+        let label_scale = if image_width >= 200 { 2 } else { 1 };
+        let label_width = self
+            .cells
+            .iter()
+            .map(|(label, _)| text_size(label, label_scale).0)
+            .max()
+            .unwrap_or(0);
+        let (_, label_height) = text_size("", label_scale);
+        let content_width = image_width.max(label_width);
+        let cell_width = content_width + PADDING;
+        let cell_height = image_height + label_height + 2 * PADDING;
+
+        let mut sheet = RgbImage::from_pixel(
+            columns as u32 * cell_width + PADDING,
+            rows as u32 * cell_height + PADDING,
+            self.background,
+        );
+        for (i, (label, img)) in self.cells.iter().enumerate() {
+            let x = (i % columns) as u32 * cell_width + PADDING;
+            let y = (i / columns) as u32 * cell_height + PADDING;
+            let image_offset = (content_width - img.width()) / 2;
+            image::imageops::replace(&mut sheet, img, (x + image_offset) as i64, y as i64);
+
+            let (text_width, _) = text_size(label, label_scale);
+            let text_offset = (content_width - text_width) / 2;
+            draw_text(
+                &mut sheet,
+                x + text_offset,
+                y + image_height + PADDING,
+                label,
+                label_scale,
+                Rgb([230, 230, 230]),
+            );
+        }
+        sheet
+    }
+}
+
+/// `count` evenly spaced values from `start` to `end` inclusive
+pub fn linspace(start: Float, end: Float, count: usize) -> Vec<Float> {
+    match count {
+        0 => vec![],
+        1 => vec![start],
+        _ => (0..count)
+            .map(|i| start + (end - start) * i as Float / (count - 1) as Float)
+            .collect(),
+    }
+}
+
+/// render one cell per value, each labeled `name = value`
+pub fn sweep<F>(name: &str, values: &[Float], mut render: F) -> ContactSheet
+where
+    F: FnMut(Float) -> RgbImage,
+{
+    let mut sheet = ContactSheet::new(0);
+    for &value in values {
+        sheet.add(&format!("{name} = {}", format_value(value)), render(value));
+    }
+    sheet
+}
+
+/// sweep the number at the dotted path `param` of a scene file, see `parse_scene_with_overrides`
+pub fn sweep_scene_file(
+    path: &str,
+    param: &str,
+    values: &[Float],
+) -> Result<ContactSheet, SceneError> {
+    let text = fs::read_to_string(path)?;
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    let materials = MaterialRegistry::with_builtins();
+
+    // load everything up front so a typo fails before any time is spent rendering
+    let scenes = values
+        .iter()
+        .map(|&value| parse_scene_with_overrides(&text, base_dir, &materials, &[(param, value)]))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // label with the last part of the path, `objects.2.material.roughness` becomes `roughness`
+    let name = param.rsplit('.').next().unwrap_or(param);
+    let mut sheet = ContactSheet::new(0);
+    for (mut scene, &value) in scenes.into_iter().zip(values) {
+        scene.camera.init();
+        let img = scene.camera.render_image(&scene.world);
+        sheet.add(&format!("{name} = {}", format_value(value)), img);
+    }
+    Ok(sheet)
+}
+
+fn format_value(value: Float) -> String {
+    if value.fract() == 0.0 {
+        format!("{value}")
+    } else {
+        format!("{value:.2}")
+    }
+}
@@ -0,0 +1,472 @@
+//! Rendering one image on several machines. `serve` runs a worker that waits for a coordinator
+//! to connect, and `render_distributed` sends the scene to every worker, hands out bands of
+//! rows to whichever worker is free and puts the bands together into one image.
+//!
+//! the scene travels as the text of its file. the images and meshes it points to are loaded by
+//! every worker from the same paths, so the workers need the same files, e.g. on a shared drive
+//! or in the same checkout. everything goes over TCP as little endian numbers and length
+//! prefixed strings: the coordinator sends the job, the worker answers with the image size or
+//! why it couldn't load the scene, then the coordinator sends bands and gets their pixels back
+//! until it says it's done. the workers leave out the post effects, which are up to whoever
+//! puts the bands together.
+
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use image::{Luma, Rgba};
+
+use crate::{
+    camera::FilmBuffer,
+    scene::{parse_scene_with_overrides, MaterialRegistry, Scene},
+    vec3::Float,
+};
+
+// sent first by both sides, so a stray connection is turned away instead of misread
+const MAGIC: &[u8; 4] = b"PTD1";
+
+// coordinator to worker
+const BAND: u8 = 1;
+const DONE: u8 = 2;
+
+// worker to coordinator
+const READY: u8 = 1;
+const FAILED: u8 = 2;
+const PIXELS: u8 = 3;
+
+// the largest images and sample counts a worker renders and a coordinator puts together, so a
+// broken job or answer is turned away instead of allocating without end
+const MAX_SIZE: usize = 1 << 15;
+const MAX_SAMPLES: usize = 1 << 20;
+
+/// What every worker renders: the scene file and the settings that were changed over it
+#[derive(Debug, Clone)]
+pub struct RenderJob {
+    /// the contents of the scene file
+    pub scene: String,
+    /// where the workers resolve the relative paths in the scene from
+    pub base_dir: PathBuf,
+    /// numbers replaced in the scene file, see `parse_scene_with_overrides`
+    pub overrides: Vec<(String, Float)>,
+    pub image_width: usize,
+    pub aspect_ratio: Float,
+    pub samples_per_pixel: usize,
+    /// the same for every worker, so the image comes out the same however the bands are split.
+    /// each worker draws its own random numbers when it's None
+    pub seed: Option<u64>,
+}
+
+impl RenderJob {
+    /// the scene the way the worker renders it
+    pub fn load(&self, materials: &MaterialRegistry) -> Result<Scene, crate::scene::SceneError> {
+        let overrides: Vec<(&str, Float)> = self
+            .overrides
+            .iter()
+            .map(|(path, value)| (path.as_str(), *value))
+            .collect();
+        let mut scene =
+            parse_scene_with_overrides(&self.scene, &self.base_dir, materials, &overrides)?;
+        scene.camera.image_width = self.image_width;
+        scene.camera.aspect_ratio = self.aspect_ratio;
+        scene.camera.samples_per_pixel = self.samples_per_pixel;
+        scene.camera.seed = self.seed;
+        scene.camera.init();
+        Ok(scene)
+    }
+
+    /// why the job can't be rendered, if it can't
+    pub fn check(&self) -> Result<(), String> {
+        if !(self.aspect_ratio.is_finite() && self.aspect_ratio > 0.0) {
+            return Err(format!(
+                "aspect ratio {} isn't a positive number",
+                self.aspect_ratio
+            ));
+        }
+        let height = self.image_width as Float / self.aspect_ratio;
+        if !(1..=MAX_SIZE).contains(&self.image_width)
+            || !(1.0..=MAX_SIZE as Float).contains(&height)
+        {
+            return Err(format!(
+                "a {} by {height:.0} image is empty or bigger than {MAX_SIZE} pixels on a side",
+                self.image_width
+            ));
+        }
+        if !(1..=MAX_SAMPLES).contains(&self.samples_per_pixel) {
+            return Err(format!(
+                "{} samples per pixel isn't between 1 and {MAX_SAMPLES}",
+                self.samples_per_pixel
+            ));
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        write_str(out, &self.scene)?;
+        write_str(out, &self.base_dir.to_string_lossy())?;
+        write_u64(out, self.overrides.len() as u64)?;
+        for (path, value) in &self.overrides {
+            write_str(out, path)?;
+            write_f64(out, *value as f64)?;
+        }
+        write_u64(out, self.image_width as u64)?;
+        write_f64(out, self.aspect_ratio as f64)?;
+        write_u64(out, self.samples_per_pixel as u64)?;
+        write_u8(out, self.seed.is_some() as u8)?;
+        write_u64(out, self.seed.unwrap_or(0))
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn read(input: &mut impl Read) -> io::Result<RenderJob> {
+        let scene = read_str(input)?;
+        let base_dir = PathBuf::from(read_str(input)?);
+        let mut overrides = Vec::new();
+        for _ in 0..read_u64(input)? {
+            let path = read_str(input)?;
+            overrides.push((path, read_f64(input)? as Float));
+        }
+        let image_width = read_u64(input)? as usize;
+        let aspect_ratio = read_f64(input)? as Float;
+        let samples_per_pixel = read_u64(input)? as usize;
+        let has_seed = read_u8(input)? != 0;
+        let seed = read_u64(input)?;
+        Ok(RenderJob {
+            scene,
+            base_dir,
+            overrides,
+            image_width,
+            aspect_ratio,
+            samples_per_pixel,
+            seed: has_seed.then_some(seed),
+        })
+    }
+}
+
+/// wait for coordinators on `addr` and render what they send, one job after the other. only
+/// returns if it can't listen
+pub fn serve(addr: impl ToSocketAddrs, materials: &MaterialRegistry) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    log::info!("waiting for jobs on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Failed to accept a connection {err}");
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or(String::from("?"), |addr| addr.to_string());
+        log::info!("job from {peer}");
+        match work(stream, materials) {
+            Ok(bands) => log::info!("rendered {bands} bands for {peer}"),
+            Err(err) => log::error!("Job from {peer} failed: {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// render one job, returns how many bands it rendered
+fn work(stream: TcpStream, materials: &MaterialRegistry) -> io::Result<usize> {
+    stream.set_nodelay(true)?;
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut out = BufWriter::new(stream);
+    read_magic(&mut input)?;
+    out.write_all(MAGIC)?;
+    let job = RenderJob::read(&mut input)?;
+
+    let loaded = job
+        .check()
+        .and_then(|()| job.load(materials).map_err(|err| err.to_string()));
+    let mut scene = match loaded {
+        Ok(scene) => scene,
+        Err(err) => {
+            write_u8(&mut out, FAILED)?;
+            write_str(&mut out, &err)?;
+            out.flush()?;
+            return Err(io::Error::other(err));
+        }
+    };
+    // bloom and chromatic aberration read the pixels around each one, so they'd leave seams
+    // between the bands. the coordinator runs them once over the whole image instead
+    scene.camera.post_effects.clear();
+    let (width, height) = (scene.camera.image_width, scene.camera.image_height());
+    write_u8(&mut out, READY)?;
+    write_u64(&mut out, width as u64)?;
+    write_u64(&mut out, height as u64)?;
+    write_u8(&mut out, scene.camera.transparent as u8)?;
+    out.flush()?;
+
+    let mut bands = 0;
+    loop {
+        match read_u8(&mut input)? {
+            BAND => {
+                let y0 = (read_u64(&mut input)? as usize).min(height);
+                let y1 = (read_u64(&mut input)? as usize).clamp(y0, height);
+                log::debug!("rendering rows {y0} to {y1}");
+                scene.camera.set_region(0, y0, width, y1);
+                scene.camera.crop = true;
+                let film = scene.camera.render_to_buffer(&scene.world);
+                write_u8(&mut out, PIXELS)?;
+                write_u64(&mut out, y0 as u64)?;
+                write_u64(&mut out, y1 as u64)?;
+                for value in film.pixels.as_raw() {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+                for samples in film.samples.as_raw() {
+                    out.write_all(&samples.to_le_bytes())?;
+                }
+                out.flush()?;
+                bands += 1;
+            }
+            DONE => return Ok(bands),
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown message {tag}"),
+                ))
+            }
+        }
+    }
+}
+
+/// A worker the coordinator is connected to
+struct Connection {
+    addr: String,
+    input: BufReader<TcpStream>,
+    out: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    /// connect and send the job, returns the image size and whether it has alpha
+    fn open(addr: &str, job: &RenderJob) -> io::Result<(Connection, (usize, usize, bool))> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            addr: addr.to_string(),
+            input: BufReader::new(stream.try_clone()?),
+            out: BufWriter::new(stream),
+        };
+        connection.out.write_all(MAGIC)?;
+        job.write(&mut connection.out)?;
+        connection.out.flush()?;
+        read_magic(&mut connection.input)?;
+        match read_u8(&mut connection.input)? {
+            READY => {
+                let width = read_u64(&mut connection.input)? as usize;
+                let height = read_u64(&mut connection.input)? as usize;
+                let has_alpha = read_u8(&mut connection.input)? != 0;
+                if !(1..=MAX_SIZE).contains(&width) || !(1..=MAX_SIZE).contains(&height) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("the worker renders a {width} by {height} image"),
+                    ));
+                }
+                Ok((connection, (width, height, has_alpha)))
+            }
+            FAILED => Err(io::Error::other(read_str(&mut connection.input)?)),
+            tag => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown message {tag}"),
+            )),
+        }
+    }
+
+    /// render the rows from `y0` up to `y1` into `film`
+    fn render_band(&mut self, y0: usize, y1: usize, film: &Mutex<FilmBuffer>) -> io::Result<()> {
+        write_u8(&mut self.out, BAND)?;
+        write_u64(&mut self.out, y0 as u64)?;
+        write_u64(&mut self.out, y1 as u64)?;
+        self.out.flush()?;
+        if read_u8(&mut self.input)? != PIXELS
+            || read_u64(&mut self.input)? != y0 as u64
+            || read_u64(&mut self.input)? != y1 as u64
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "got pixels for another band",
+            ));
+        }
+        let width = film.lock().unwrap().width();
+        let mut pixels = vec![Rgba([0.0; 4]); width * (y1 - y0)];
+        for pixel in &mut pixels {
+            for value in &mut pixel.0 {
+                *value = f32::from_le_bytes(read_array(&mut self.input)?);
+            }
+        }
+        let mut samples = vec![0; pixels.len()];
+        for count in &mut samples {
+            *count = u32::from_le_bytes(read_array(&mut self.input)?);
+        }
+        let mut film = film.lock().unwrap();
+        for (i, (pixel, count)) in pixels.into_iter().zip(samples).enumerate() {
+            let (x, y) = ((i % width) as u32, (y0 + i / width) as u32);
+            film.pixels.put_pixel(x, y, pixel);
+            film.samples.put_pixel(x, y, Luma([count]));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        write_u8(&mut self.out, DONE)?;
+        self.out.flush()
+    }
+}
+
+/// The bands no worker has finished yet
+struct Bands {
+    todo: Vec<(usize, usize)>,
+    in_progress: usize,
+}
+
+/// render `job` on the `workers` (`host:port` of machines running `serve`) in bands of
+/// `rows_per_band` rows. a worker that fails is dropped and its band goes to another one, the
+/// render only fails if all of them do
+pub fn render_distributed(
+    job: &RenderJob,
+    workers: &[String],
+    rows_per_band: usize,
+) -> io::Result<FilmBuffer> {
+    job.check()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut connections = Vec::new();
+    let mut size = None;
+    for addr in workers {
+        match Connection::open(addr, job) {
+            Ok((connection, worker_size)) => {
+                if size.is_some_and(|size| size != worker_size) {
+                    log::error!("{addr} renders a different image size than the other workers");
+                    continue;
+                }
+                size = Some(worker_size);
+                connections.push(connection);
+            }
+            Err(err) => log::error!("Failed to start the job on {addr}: {err}"),
+        }
+    }
+    let Some((width, height, has_alpha)) = size else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "no worker took the job",
+        ));
+    };
+    log::info!("rendering on {} workers", connections.len());
+
+    let mut film = FilmBuffer::new(width, height);
+    film.has_alpha = has_alpha;
+    let film = Mutex::new(film);
+    let rows = rows_per_band.max(1);
+    // popped from the back, so the top of the image comes first
+    let todo = (0..height).step_by(rows).rev();
+    let bands = Mutex::new(Bands {
+        todo: todo.map(|y0| (y0, (y0 + rows).min(height))).collect(),
+        in_progress: 0,
+    });
+
+    thread::scope(|scope| {
+        for mut connection in connections {
+            let (film, bands) = (&film, &bands);
+            scope.spawn(move || {
+                loop {
+                    let band = {
+                        let mut bands = bands.lock().unwrap();
+                        match bands.todo.pop() {
+                            Some(band) => {
+                                bands.in_progress += 1;
+                                band
+                            }
+                            // a band that's still rendering may fail and come back
+                            None if bands.in_progress > 0 => {
+                                drop(bands);
+                                thread::sleep(Duration::from_millis(50));
+                                continue;
+                            }
+                            None => break,
+                        }
+                    };
+                    let result = connection.render_band(band.0, band.1, film);
+                    let mut bands = bands.lock().unwrap();
+                    bands.in_progress -= 1;
+                    if let Err(err) = result {
+                        log::error!("{} failed, dropping it: {err}", connection.addr);
+                        bands.todo.push(band);
+                        return;
+                    }
+                    log::debug!("{} rendered rows {} to {}", connection.addr, band.0, band.1);
+                }
+                if let Err(err) = connection.finish() {
+                    log::warn!("Failed to tell {} the job is done: {err}", connection.addr);
+                }
+            });
+        }
+    });
+
+    let left = bands.into_inner().unwrap().todo.len();
+    if left > 0 {
+        return Err(io::Error::other(format!(
+            "every worker failed with {left} bands left"
+        )));
+    }
+    Ok(film.into_inner().unwrap())
+}
+
+fn read_magic(input: &mut impl Read) -> io::Result<()> {
+    if read_array(input)? != *MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a path tracer worker or coordinator",
+        ));
+    }
+    Ok(())
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn write_u8(out: &mut impl Write, value: u8) -> io::Result<()> {
+    out.write_all(&[value])
+}
+
+fn read_u8(input: &mut impl Read) -> io::Result<u8> {
+    Ok(read_array::<1>(input)?[0])
+}
+
+fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(read_array(input)?))
+}
+
+fn write_f64(out: &mut impl Write, value: f64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    Ok(f64::from_le_bytes(read_array(input)?))
+}
+
+fn write_str(out: &mut impl Write, value: &str) -> io::Result<()> {
+    write_u64(out, value.len() as u64)?;
+    out.write_all(value.as_bytes())
+}
+
+fn read_str(input: &mut impl Read) -> io::Result<String> {
+    let len = read_u64(input)?;
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -0,0 +1,131 @@
+//! Piecewise constant distributions for importance sampling tabulated functions.
+
+use crate::vec3::{Float, Vec2};
+
+/// Samples an index (or a continuous position in [0, 1)) proportional to a list of weights
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<Float>,
+    cdf: Vec<Float>,
+    integral: Float,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<Float>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as Float;
+        }
+        let integral = cdf[n];
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            // all zero, fall back to uniform
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as Float / n as Float;
+            }
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    /// average of the function over [0, 1)
+    pub fn integral(&self) -> Float {
+        self.integral
+    }
+
+    /// returns the position in [0, 1), its pdf, and the index of the bucket it fell in
+    pub fn sample_continuous(&self, u: Float) -> (Float, Float, usize) {
+        // last cdf entry that is <= u
+        let i = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .clamp(1, self.func.len())
+            - 1;
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 {
+            (u - self.cdf[i]) / width
+        } else {
+            0.0
+        };
+        let pdf = if self.integral > 0.0 {
+            self.func[i] / self.integral
+        } else {
+            1.0
+        };
+        ((i as Float + du) / self.len() as Float, pdf, i)
+    }
+
+    /// returns the index and its probability
+    pub fn sample_discrete(&self, u: Float) -> (usize, Float) {
+        let (_, _, i) = self.sample_continuous(u);
+        (i, self.probability(i))
+    }
+
+    /// the probability of `sample_discrete` picking index `i`
+    pub fn probability(&self, i: usize) -> Float {
+        self.cdf[i + 1] - self.cdf[i]
+    }
+
+    /// pdf of the continuous position `x` in [0, 1)
+    pub fn pdf(&self, x: Float) -> Float {
+        let i = ((x * self.len() as Float) as usize).min(self.len() - 1);
+        if self.integral > 0.0 {
+            self.func[i] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Samples a position in [0, 1)^2 proportional to a grid of weights, stored row by row
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[Float], width: usize, height: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+        Distribution2D {
+            conditional,
+            marginal,
+        }
+    }
+
+    /// returns (x, y) in [0, 1)^2, with y going down the rows, and its pdf
+    pub fn sample(&self, u: Vec2) -> (Vec2, Float) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u.y);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u.x);
+        (Vec2::new(x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, p: Vec2) -> Float {
+        let row =
+            ((p.y * self.conditional.len() as Float) as usize).min(self.conditional.len() - 1);
+        if self.marginal.integral() > 0.0 {
+            self.conditional[row].pdf(p.x) * self.conditional[row].integral()
+                / self.marginal.integral()
+        } else {
+            1.0
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use crate::{
+    color::ColorSpace,
+    sky::Sky,
+    texture::ImageTexture,
+    vec3::{consts::PI, Float, Vec3},
+};
+
+#[derive(Debug, Clone)]
+pub enum EnvironmentType {
+    Color(Vec3),
+    Map(Arc<ImageTexture>),
+    /// a map whose texels are sRGB encoded, decoded into the working space of the color space
+    DecodedMap(Arc<ImageTexture>, ColorSpace),
+    /// a clear daylight sky, see `SunSky`
+    Sky(Sky),
+}
+
+impl EnvironmentType {
+    /// radiance arriving from infinitely far away along `direction`
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        match self {
+            EnvironmentType::Color(color) => *color,
+            EnvironmentType::Map(env_map) => lookup(env_map, direction),
+            EnvironmentType::DecodedMap(env_map, color_space) => {
+                color_space.decode_texel(lookup(env_map, direction))
+            }
+            EnvironmentType::Sky(sky) => sky.radiance(direction),
+        }
+    }
+}
+
+/// bilinear lat-long lookup that wraps around the phi = ±π seam and blends into the
+/// average of the first/last row near the poles, where every texel meets in one point
+fn lookup(env_map: &ImageTexture, direction: Vec3) -> Vec3 {
+    let (width, height) = env_map.img.dimensions();
+    if width == 0 || height == 0 {
+        return Vec3::new(0.0, 1.0, 1.0);
+    }
+
+    let direction = direction.normalize();
+    let theta = direction.y.clamp(-1.0, 1.0).acos();
+    let phi = direction.z.atan2(direction.x);
+
+    // continuous texel coordinates, texel centers sit at integers. row 0 is the +y pole
+    let x = (phi + PI) / (2.0 * PI) * width as Float - 0.5;
+    let y = theta / PI * height as Float - 0.5;
+
+    let last_row = height as Float - 1.0;
+    if y < 0.0 {
+        let t = (-y / 0.5).min(1.0);
+        row_lerp(env_map, x, 0).lerp(row_average(env_map, 0), t)
+    } else if y > last_row {
+        let t = ((y - last_row) / 0.5).min(1.0);
+        row_lerp(env_map, x, height - 1).lerp(row_average(env_map, height - 1), t)
+    } else {
+        let y0 = y.floor();
+        let row = y0 as u32;
+        let next = (row + 1).min(height - 1);
+        row_lerp(env_map, x, row).lerp(row_lerp(env_map, x, next), y - y0)
+    }
+}
+
+// interpolate along a row, wrapping around horizontally
+fn row_lerp(env_map: &ImageTexture, x: Float, row: u32) -> Vec3 {
+    let x0 = x.floor();
+    let i = x0 as i64;
+    texel(env_map, i, row).lerp(texel(env_map, i + 1, row), x - x0)
+}
+
+fn row_average(env_map: &ImageTexture, row: u32) -> Vec3 {
+    let width = env_map.img.width();
+    let sum: Vec3 = (0..width as i64).map(|i| texel(env_map, i, row)).sum();
+    sum / width as Float
+}
+
+fn texel(env_map: &ImageTexture, i: i64, row: u32) -> Vec3 {
+    let i = i.rem_euclid(env_map.img.width() as i64) as u32;
+    let pixel = env_map.img.get_pixel(i, row);
+    Vec3::new(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float) / 255.0
+}
+
+impl Default for EnvironmentType {
+    fn default() -> Self {
+        EnvironmentType::Color(Vec3::ZERO)
+    }
+}
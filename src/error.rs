@@ -0,0 +1,65 @@
+//! Errors from loading assets. Loaders return them instead of panicking, and the scene loader
+//! reports them and carries on with a stand-in (the missing texture or a placeholder cube), so
+//! a typo in a path shows up when the scene is built and not in the middle of a render.
+
+use std::fmt;
+
+use crate::scene::SceneError;
+
+#[derive(Debug)]
+pub enum Error {
+    Image {
+        path: String,
+        source: image::ImageError,
+    },
+    Mesh {
+        path: String,
+        source: tobj::LoadError,
+    },
+    /// a PLY file that can't be read, and what is wrong with it
+    Ply {
+        path: String,
+        message: String,
+    },
+    Scene(SceneError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Image { path, source } => write!(f, "failed to load image {path}: {source}"),
+            Error::Mesh { path, source } => write!(f, "failed to load mesh {path}: {source}"),
+            Error::Ply { path, message } => write!(f, "failed to load mesh {path}: {message}"),
+            Error::Scene(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Image { source, .. } => Some(source),
+            Error::Mesh { source, .. } => Some(source),
+            Error::Ply { .. } => None,
+            Error::Scene(err) => Some(err),
+        }
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(err: SceneError) -> Self {
+        Error::Scene(err)
+    }
+}
+
+/// open and decode the image at `path`
+pub fn open_image(path: &str) -> Result<image::DynamicImage, Error> {
+    let image_error = |source| Error::Image {
+        path: path.to_string(),
+        source,
+    };
+    image::ImageReader::open(path)
+        .map_err(|err| image_error(image::ImageError::IoError(err)))?
+        .decode()
+        .map_err(image_error)
+}
@@ -0,0 +1,14 @@
+//! Experimental path tracer running as a WGSL compute shader through wgpu, behind the `gpu`
+//! feature. The world is flattened into a BVH of triangles and spheres with a table of simple
+//! materials, each sample is one dispatch that adds to an accumulation buffer, and the average
+//! is read back at the end.
+//!
+//! Only a subset of the CPU renderer is there: diffuse, metal, smooth glass and emissive
+//! materials with textures read at their center, constant or image environments, and a pinhole
+//! camera. There is no light sampling, so small lights converge slowly.
+
+pub mod renderer;
+pub use self::renderer::*;
+
+pub mod scene;
+pub use self::scene::*;
@@ -0,0 +1,296 @@
+// one sample of a path traced image per dispatch, added to the accumulation buffer. the whole
+// path is traced in one invocation, with the same diffuse, metal, glass and light materials as
+// GpuMaterial and no light sampling
+
+struct Params {
+    center: vec4<f32>,
+    pixel00: vec4<f32>,
+    pixel_du: vec4<f32>,
+    pixel_dv: vec4<f32>,
+    background: vec4<f32>,
+    // width, height, index of the sample, max depth
+    image: vec4<u32>,
+    // width and height of the environment map (0 without one), and the number of nodes
+    sizes: vec4<u32>,
+}
+
+struct Primitive {
+    a: vec4<f32>,
+    b: vec4<f32>,
+    c: vec4<f32>,
+    // kind (0 triangle, 1 sphere) and material
+    ids: vec4<u32>,
+}
+
+struct Node {
+    min: vec4<f32>,
+    max: vec4<f32>,
+    // leaves: first primitive and count, internal nodes: right child and 0
+    ids: vec4<u32>,
+}
+
+struct Material {
+    // color, and roughness for metal or ior for glass
+    color: vec4<f32>,
+    // 0 diffuse, 1 metal, 2 glass, 3 light
+    kind: vec4<u32>,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> nodes: array<Node>;
+@group(0) @binding(2) var<storage, read> primitives: array<Primitive>;
+@group(0) @binding(3) var<storage, read> materials: array<Material>;
+// rgba8 texels of the lat-long environment map
+@group(0) @binding(4) var<storage, read> environment: array<u32>;
+@group(0) @binding(5) var<storage, read_write> accumulation: array<vec4<f32>>;
+
+const PI: f32 = 3.14159265;
+const T_MIN: f32 = 0.001;
+const INFINITY: f32 = 1e30;
+
+var<private> rng_state: u32;
+
+// pcg hash
+fn random_u32() -> u32 {
+    rng_state = rng_state * 747796405u + 2891336453u;
+    let word = ((rng_state >> ((rng_state >> 28u) + 4u)) ^ rng_state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random() -> f32 {
+    return f32(random_u32() >> 8u) / 16777216.0;
+}
+
+fn random_unit_vector() -> vec3<f32> {
+    let z = 2.0 * random() - 1.0;
+    let phi = 2.0 * PI * random();
+    let r = sqrt(max(0.0, 1.0 - z * z));
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+struct Hit {
+    t: f32,
+    normal: vec3<f32>,
+    material: u32,
+}
+
+fn hit_triangle(p: Primitive, origin: vec3<f32>, dir: vec3<f32>, t_max: f32) -> f32 {
+    let e1 = p.b.xyz - p.a.xyz;
+    let e2 = p.c.xyz - p.a.xyz;
+    let h = cross(dir, e2);
+    let a = dot(e1, h);
+    if abs(a) < 1e-8 {
+        return INFINITY;
+    }
+    let f = 1.0 / a;
+    let s = origin - p.a.xyz;
+    let u = f * dot(s, h);
+    let q = cross(s, e1);
+    let v = f * dot(dir, q);
+    let t = f * dot(e2, q);
+    if u < 0.0 || v < 0.0 || u + v > 1.0 || t < T_MIN || t > t_max {
+        return INFINITY;
+    }
+    return t;
+}
+
+fn hit_sphere(p: Primitive, origin: vec3<f32>, dir: vec3<f32>, t_max: f32) -> f32 {
+    let oc = p.a.xyz - origin;
+    let a = dot(dir, dir);
+    let h = dot(dir, oc);
+    let c = dot(oc, oc) - p.b.x * p.b.x;
+    let discriminant = h * h - a * c;
+    if discriminant < 0.0 {
+        return INFINITY;
+    }
+    let root = sqrt(discriminant);
+    var t = (h - root) / a;
+    if t < T_MIN {
+        t = (h + root) / a;
+    }
+    if t < T_MIN || t > t_max {
+        return INFINITY;
+    }
+    return t;
+}
+
+fn hit_box(node: Node, origin: vec3<f32>, inv_dir: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (node.min.xyz - origin) * inv_dir;
+    let t1 = (node.max.xyz - origin) * inv_dir;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), T_MIN));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), min(max(t0.z, t1.z), t_max));
+    return near <= far;
+}
+
+fn intersect(origin: vec3<f32>, dir: vec3<f32>) -> Hit {
+    var hit = Hit(INFINITY, vec3<f32>(0.0), 0u);
+    if params.sizes.z == 0u {
+        return hit;
+    }
+    var closest = 0u;
+    let inv_dir = 1.0 / dir;
+    var stack: array<u32, 64>;
+    stack[0] = 0u;
+    var top = 1u;
+    while top > 0u {
+        top -= 1u;
+        let index = stack[top];
+        let node = nodes[index];
+        if !hit_box(node, origin, inv_dir, hit.t) {
+            continue;
+        }
+        if node.ids.y > 0u {
+            for (var i = node.ids.x; i < node.ids.x + node.ids.y; i++) {
+                let p = primitives[i];
+                var t: f32;
+                if p.ids.x == 0u {
+                    t = hit_triangle(p, origin, dir, hit.t);
+                } else {
+                    t = hit_sphere(p, origin, dir, hit.t);
+                }
+                if t < hit.t {
+                    hit.t = t;
+                    closest = i;
+                }
+            }
+        } else if top + 2u <= 64u {
+            // left child right after the node, right child at ids.x
+            stack[top] = node.ids.x;
+            stack[top + 1u] = index + 1u;
+            top += 2u;
+        }
+    }
+    if hit.t < INFINITY {
+        let p = primitives[closest];
+        let point = origin + hit.t * dir;
+        if p.ids.x == 0u {
+            hit.normal = normalize(cross(p.b.xyz - p.a.xyz, p.c.xyz - p.a.xyz));
+        } else {
+            hit.normal = (point - p.a.xyz) / p.b.x;
+        }
+        hit.material = p.ids.y;
+    }
+    return hit;
+}
+
+fn environment_texel(x: u32, y: u32) -> vec3<f32> {
+    let texel = environment[y * params.sizes.x + x];
+    return vec3<f32>(
+        f32(texel & 0xffu),
+        f32((texel >> 8u) & 0xffu),
+        f32((texel >> 16u) & 0xffu),
+    ) / 255.0;
+}
+
+// bilinear lat-long lookup, like EnvironmentType::sample without the blending at the poles
+fn background(dir: vec3<f32>) -> vec3<f32> {
+    if params.sizes.x == 0u {
+        return params.background.xyz;
+    }
+    let size = vec2<f32>(params.sizes.xy);
+    let d = normalize(dir);
+    let theta = acos(clamp(d.y, -1.0, 1.0));
+    let phi = atan2(d.z, d.x);
+    let x = (phi + PI) / (2.0 * PI) * size.x - 0.5;
+    let y = clamp(theta / PI * size.y - 0.5, 0.0, size.y - 1.0);
+    let x0 = floor(x);
+    let y0 = floor(y);
+    let w = params.sizes.x;
+    let i0 = u32(i32(x0) + i32(w)) % w;
+    let i1 = (i0 + 1u) % w;
+    let j0 = u32(y0);
+    let j1 = min(j0 + 1u, params.sizes.y - 1u);
+    let top = mix(environment_texel(i0, j0), environment_texel(i1, j0), x - x0);
+    let bottom = mix(environment_texel(i0, j1), environment_texel(i1, j1), x - x0);
+    return mix(top, bottom, y - y0);
+}
+
+fn schlick(cosine: f32, eta: f32) -> f32 {
+    let r0 = (1.0 - eta) / (1.0 + eta);
+    let r = r0 * r0;
+    return r + (1.0 - r) * pow(1.0 - cosine, 5.0);
+}
+
+fn trace(camera_origin: vec3<f32>, camera_dir: vec3<f32>) -> vec3<f32> {
+    var origin = camera_origin;
+    var dir = camera_dir;
+    var throughput = vec3<f32>(1.0);
+    var radiance = vec3<f32>(0.0);
+    for (var depth = 0u; depth < params.image.w; depth++) {
+        let hit = intersect(origin, dir);
+        if hit.t >= INFINITY {
+            radiance += throughput * background(dir);
+            break;
+        }
+        let material = materials[hit.material];
+        let color = material.color.xyz;
+        let point = origin + hit.t * dir;
+        let unit_dir = normalize(dir);
+        let front_face = dot(unit_dir, hit.normal) < 0.0;
+        let normal = select(-hit.normal, hit.normal, front_face);
+
+        // break inside a switch only leaves the switch
+        var done = false;
+        switch material.kind.x {
+            case 0u: {
+                dir = normal + random_unit_vector();
+                if dot(dir, dir) < 1e-8 {
+                    dir = normal;
+                }
+            }
+            case 1u: {
+                dir = reflect(unit_dir, normal) + material.color.w * random_unit_vector();
+                done = dot(dir, normal) <= 0.0;
+            }
+            case 2u: {
+                let eta = select(material.color.w, 1.0 / material.color.w, front_face);
+                let cos_theta = min(dot(-unit_dir, normal), 1.0);
+                let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+                if eta * sin_theta > 1.0 || schlick(cos_theta, eta) > random() {
+                    dir = reflect(unit_dir, normal);
+                } else {
+                    dir = refract(unit_dir, normal, eta);
+                }
+            }
+            default: {
+                radiance += throughput * color;
+                done = true;
+            }
+        }
+        if done {
+            break;
+        }
+        throughput *= color;
+        origin = point;
+
+        // russian roulette once the path has had a few bounces
+        if depth > 3u {
+            let survive = clamp(max(throughput.x, max(throughput.y, throughput.z)), 0.05, 1.0);
+            if random() > survive {
+                break;
+            }
+            throughput /= survive;
+        }
+    }
+    return radiance;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let width = params.image.x;
+    let height = params.image.y;
+    if id.x >= width || id.y >= height {
+        return;
+    }
+    let pixel = id.y * width + id.x;
+    rng_state = pixel * 9781u + params.image.z * 6271u + 1u;
+    _ = random_u32();
+
+    let offset = vec2<f32>(random() - 0.5, random() - 0.5);
+    let pixel_point = params.pixel00.xyz
+        + (f32(id.x) + offset.x) * params.pixel_du.xyz
+        + (f32(id.y) + offset.y) * params.pixel_dv.xyz;
+    let origin = params.center.xyz;
+    let color = trace(origin, pixel_point - origin);
+    accumulation[pixel] += vec4<f32>(color, 1.0);
+}
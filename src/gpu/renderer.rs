@@ -0,0 +1,243 @@
+use std::fmt;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::vec3::{Float, Vec3};
+
+use super::{scene::vec4, GpuScene};
+
+/// Why a GPU render didn't happen
+#[derive(Debug)]
+pub enum GpuError {
+    NoAdapter,
+    Device(wgpu::RequestDeviceError),
+    /// the world has a shape or material the backend can't draw
+    UnsupportedScene,
+    Readback(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuError::NoAdapter => write!(f, "no GPU adapter found"),
+            GpuError::Device(err) => write!(f, "failed to open the GPU: {err}"),
+            GpuError::UnsupportedScene => write!(
+                f,
+                "the scene has shapes or materials the GPU backend can't draw"
+            ),
+            GpuError::Readback(err) => write!(f, "failed to read the image back: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+/// Where the pinhole camera is and how its pixels are laid out, in the same terms as `Camera`
+#[derive(Debug, Clone, Copy)]
+pub struct GpuView {
+    pub center: Vec3,
+    pub pixel00: Vec3,
+    pub pixel_du: Vec3,
+    pub pixel_dv: Vec3,
+    pub width: usize,
+    pub height: usize,
+    pub max_depth: usize,
+}
+
+// matches Params in path_tracer.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct Params {
+    center: [f32; 4],
+    pixel00: [f32; 4],
+    pixel_du: [f32; 4],
+    pixel_dv: [f32; 4],
+    background: [f32; 4],
+    image: [u32; 4],
+    sizes: [u32; 4],
+}
+
+const WORKGROUP_SIZE: u32 = 8;
+
+/// A device with the path tracing shader compiled on it, reusable across renders
+pub struct GpuRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuRenderer {
+    pub fn new() -> Result<GpuRenderer, GpuError> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .ok_or(GpuError::NoAdapter)?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("path tracer"),
+                required_limits: adapter.limits(),
+                ..Default::default()
+            },
+            None,
+        ))
+        .map_err(GpuError::Device)?;
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("path_tracer.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("path tracer"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Ok(GpuRenderer {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    /// `samples` paths through every pixel, averaged. one dispatch per sample, so a single
+    /// submission never runs long enough for the driver to give up on it
+    pub fn render(
+        &self,
+        scene: &GpuScene,
+        view: &GpuView,
+        samples: usize,
+    ) -> Result<Vec<Vec3>, GpuError> {
+        if samples == 0 {
+            return Ok(vec![Vec3::ZERO; view.width * view.height]);
+        }
+        let (width, height) = (view.width as u32, view.height as u32);
+        let (environment, env_width, env_height) = match &scene.environment_map {
+            Some(map) => {
+                let texels = map
+                    .pixels()
+                    .map(|p| u32::from_le_bytes([p[0], p[1], p[2], 255]))
+                    .collect();
+                (texels, map.width(), map.height())
+            }
+            None => (vec![0], 0, 0),
+        };
+        let mut params = Params {
+            center: vec4(view.center, 0.0),
+            pixel00: vec4(view.pixel00, 0.0),
+            pixel_du: vec4(view.pixel_du, 0.0),
+            pixel_dv: vec4(view.pixel_dv, 0.0),
+            background: vec4(scene.background, 0.0),
+            image: [width, height, 0, view.max_depth as u32],
+            sizes: [env_width, env_height, scene.nodes.len() as u32, 0],
+        };
+
+        let params_buffer = self.buffer("params", &[params], wgpu::BufferUsages::UNIFORM);
+        // bindings can't be empty, an empty scene gets one unused element of each
+        let storage = wgpu::BufferUsages::STORAGE;
+        let nodes = self.buffer("nodes", &non_empty(&scene.nodes), storage);
+        let primitives = self.buffer("primitives", &non_empty(&scene.primitives), storage);
+        let materials = self.buffer("materials", &non_empty(&scene.materials), storage);
+        let environment = self.buffer("environment", &environment, storage);
+        let size = (width * height) as u64 * 16;
+        let accumulation = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("accumulation"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("path tracer"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                &params_buffer,
+                &nodes,
+                &primitives,
+                &materials,
+                &environment,
+                &accumulation,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        for sample in 0..samples {
+            params.image[2] = sample as u32;
+            self.queue
+                .write_buffer(&params_buffer, 0, bytemuck::bytes_of(&params));
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(
+                    width.div_ceil(WORKGROUP_SIZE),
+                    height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            }
+            if sample + 1 == samples {
+                encoder.copy_buffer_to_buffer(&accumulation, 0, &readback, 0, size);
+            }
+            self.queue.submit([encoder.finish()]);
+        }
+        let slice = readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("map_async callback dropped")
+            .map_err(GpuError::Readback)?;
+
+        let data = slice.get_mapped_range();
+        let sums: &[[f32; 4]] = bytemuck::cast_slice(&data);
+        let colors = sums
+            .iter()
+            .map(|sum| {
+                let color = Vec3::new(sum[0] as Float, sum[1] as Float, sum[2] as Float);
+                color / sum[3].max(1.0) as Float
+            })
+            .collect();
+        drop(data);
+        readback.unmap();
+        Ok(colors)
+    }
+
+    fn buffer<T: Pod>(
+        &self,
+        label: &str,
+        contents: &[T],
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(contents),
+                usage: usage | wgpu::BufferUsages::COPY_DST,
+            })
+    }
+}
+
+fn non_empty<T: Pod>(items: &[T]) -> Vec<T> {
+    if items.is_empty() {
+        vec![T::zeroed()]
+    } else {
+        items.to_vec()
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    bsdf::BxDFMaterial,
+    environment::EnvironmentType,
+    hittable::{bvh::centroid_axis, Hittable, World, AABB},
+    vec3::{Float, Mat4, Vec3},
+};
+
+/// The materials the GPU backend can draw. Textures are read once, at their center
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GpuMaterial {
+    Diffuse(Vec3),
+    /// a mirror blurred by `roughness`
+    Metal {
+        color: Vec3,
+        roughness: Float,
+    },
+    /// smooth glass tinted by `color`
+    Glass {
+        color: Vec3,
+        ior: Float,
+    },
+    Light(Vec3),
+}
+
+impl GpuMaterial {
+    fn to_gpu(self) -> Material {
+        let (kind, color, param) = match self {
+            GpuMaterial::Diffuse(color) => (0, color, 0.0),
+            GpuMaterial::Metal { color, roughness } => (1, color, roughness),
+            GpuMaterial::Glass { color, ior } => (2, color, ior),
+            GpuMaterial::Light(color) => (3, color, 0.0),
+        };
+        Material {
+            color: vec4(color, param),
+            kind: [kind, 0, 0, 0],
+        }
+    }
+}
+
+// the layouts below match the structs in path_tracer.wgsl, everything is in vec4s so there is
+// no padding to get wrong
+
+/// a triangle (kind 0) with vertices `a`, `b` and `c`, or a sphere (kind 1) around `a` with
+/// radius `b.x`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub(super) struct Primitive {
+    a: [f32; 4],
+    b: [f32; 4],
+    c: [f32; 4],
+    /// kind and material
+    ids: [u32; 4],
+}
+
+/// leaves hold `count` primitives from `index` on, internal nodes have their left child right
+/// after them and their right child at `index`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub(super) struct Node {
+    min: [f32; 4],
+    max: [f32; 4],
+    /// index and count
+    ids: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub(super) struct Material {
+    /// color, and the roughness or ior
+    color: [f32; 4],
+    kind: [u32; 4],
+}
+
+// already f32 with the `f32` feature
+#[allow(clippy::unnecessary_cast)]
+pub(super) fn vec4(v: Vec3, w: Float) -> [f32; 4] {
+    [v.x as f32, v.y as f32, v.z as f32, w as f32]
+}
+
+/// Collects the shapes and materials of a world as the GPU backend sees them, see
+/// `Hittable::add_to_gpu_scene`
+pub struct GpuSceneBuilder {
+    primitives: Vec<(Primitive, AABB)>,
+    materials: Vec<Material>,
+    /// materials already added, by address
+    material_ids: HashMap<*const (), u32>,
+    transform: Mat4,
+}
+
+impl GpuSceneBuilder {
+    pub fn new() -> GpuSceneBuilder {
+        GpuSceneBuilder {
+            primitives: vec![],
+            materials: vec![],
+            material_ids: HashMap::new(),
+            transform: Mat4::IDENTITY,
+        }
+    }
+
+    fn material_id(&mut self, material: &dyn BxDFMaterial) -> Option<u32> {
+        let key = material as *const dyn BxDFMaterial as *const ();
+        if let Some(&id) = self.material_ids.get(&key) {
+            return Some(id);
+        }
+        let id = self.materials.len() as u32;
+        self.materials.push(material.gpu_material()?.to_gpu());
+        self.material_ids.insert(key, id);
+        Some(id)
+    }
+
+    /// false if the material can't be drawn
+    pub fn add_triangle(&mut self, vertices: [Vec3; 3], material: &dyn BxDFMaterial) -> bool {
+        let Some(id) = self.material_id(material) else {
+            return false;
+        };
+        let [a, b, c] = vertices.map(|v| self.transform.transform_point3(v));
+        let primitive = Primitive {
+            a: vec4(a, 0.0),
+            b: vec4(b, 0.0),
+            c: vec4(c, 0.0),
+            ids: [0, id, 0, 0],
+        };
+        let bbox = AABB::new(a.min(b).min(c), a.max(b).max(c));
+        self.primitives.push((primitive, bbox));
+        true
+    }
+
+    /// false if the material can't be drawn
+    pub fn add_sphere(&mut self, center: Vec3, radius: Float, material: &dyn BxDFMaterial) -> bool {
+        let Some(id) = self.material_id(material) else {
+            return false;
+        };
+        // instances only rotate and move, so the radius stays the same
+        let center = self.transform.transform_point3(center);
+        let primitive = Primitive {
+            a: vec4(center, 0.0),
+            b: vec4(Vec3::splat(radius), 0.0),
+            c: [0.0; 4],
+            ids: [1, id, 0, 0],
+        };
+        let bbox = AABB::new(center - Vec3::splat(radius), center + Vec3::splat(radius));
+        self.primitives.push((primitive, bbox));
+        true
+    }
+
+    /// `add` with everything it adds moved by `transform` first
+    pub fn with_transform(&mut self, transform: Mat4, add: impl FnOnce(&mut Self) -> bool) -> bool {
+        let outer = self.transform;
+        self.transform = outer * transform;
+        let added = add(self);
+        self.transform = outer;
+        added
+    }
+
+    fn build(self, environment: &EnvironmentType) -> GpuScene {
+        let boxes: Vec<AABB> = self.primitives.iter().map(|(_, bbox)| *bbox).collect();
+        let mut items: Vec<usize> = (0..boxes.len()).collect();
+        let mut nodes = vec![];
+        let mut order = vec![];
+        if !items.is_empty() {
+            build_node(&mut items, &boxes, &mut nodes, &mut order);
+        }
+        let primitives = order.iter().map(|&i| self.primitives[i].0).collect();
+
+        let (background, environment_map) = match environment {
+            EnvironmentType::Color(color) => (*color, None),
+            // no sky on the GPU, the color of the sky around the horizon stands in for it
+            EnvironmentType::Sky(sky) => (sky.radiance(Vec3::new(1.0, 0.3, 0.0)), None),
+            EnvironmentType::Map(map) | EnvironmentType::DecodedMap(map, _) => {
+                (Vec3::ZERO, Some(map.img.clone()))
+            }
+        };
+        GpuScene {
+            nodes,
+            primitives,
+            materials: self.materials,
+            background,
+            environment_map,
+        }
+    }
+}
+
+impl Default for GpuSceneBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const LEAF_SIZE: usize = 4;
+
+/// median split along the axis the centroids spread the most on, depth first so left children
+/// come right after their parents
+fn build_node(items: &mut [usize], boxes: &[AABB], nodes: &mut Vec<Node>, order: &mut Vec<usize>) {
+    let item_boxes: Vec<AABB> = items.iter().map(|&i| boxes[i]).collect();
+    let bbox = item_boxes
+        .iter()
+        .fold(AABB::default(), |acc, &b| acc.union(b));
+    let index = nodes.len();
+    nodes.push(Node {
+        min: vec4(bbox.min(), 0.0),
+        max: vec4(bbox.max(), 0.0),
+        ids: [order.len() as u32, items.len() as u32, 0, 0],
+    });
+    if items.len() <= LEAF_SIZE {
+        order.extend_from_slice(items);
+        return;
+    }
+
+    let axis = centroid_axis(&item_boxes);
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |&a, &b| {
+        boxes[a].centroid()[axis].total_cmp(&boxes[b].centroid()[axis])
+    });
+    let (left, right) = items.split_at_mut(mid);
+    build_node(left, boxes, nodes, order);
+    let right_index = nodes.len() as u32;
+    build_node(right, boxes, nodes, order);
+    nodes[index].ids = [right_index, 0, 0, 0];
+}
+
+/// A world flattened into the buffers the shader reads
+pub struct GpuScene {
+    pub(super) nodes: Vec<Node>,
+    pub(super) primitives: Vec<Primitive>,
+    pub(super) materials: Vec<Material>,
+    pub(super) background: Vec3,
+    pub(super) environment_map: Option<image::RgbImage>,
+}
+
+impl GpuScene {
+    /// None if the world has a shape or a material the GPU backend can't draw
+    pub fn new(world: &World) -> Option<GpuScene> {
+        let mut builder = GpuSceneBuilder::new();
+        for list in [&world.objects, &world.lights] {
+            if !list.add_to_gpu_scene(&mut builder) {
+                return None;
+            }
+        }
+        Some(builder.build(&world.environment))
+    }
+
+    pub fn primitive_count(&self) -> usize {
+        self.primitives.len()
+    }
+}
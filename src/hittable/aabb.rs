@@ -2,8 +2,8 @@ use std::ops::Add;
 
 use crate::{
     interval::Interval,
-    ray::Ray,
-    vec3::{Mat4, Vec3},
+    ray::{Ray, RELATIVE_ERROR},
+    vec3::{Float, Mat4, Quat, Vec3},
 };
 
 #[derive(Clone, Copy)]
@@ -14,26 +14,35 @@ pub struct AABB {
 
 impl AABB {
     pub fn new(a: Vec3, b: Vec3) -> AABB {
-        let delta = Vec3::splat(1e-3); // make sure the bounding box isn't too flat on any of the axis
-        let min = a.min(b) - delta;
-        let max = a.max(b) + delta;
+        // make sure the bounding box isn't too flat on any of the axis, by an amount that goes
+        // with the size and position of the box so it works at any scene scale
+        let (min, max) = (a.min(b), a.max(b));
+        let scale = (max - min).max_element() + min.abs().max(max.abs()).max_element();
+        let delta = Vec3::splat(scale * RELATIVE_ERROR);
+        let min = min - delta;
+        let max = max + delta;
         AABB { min, max }
     }
 
     pub fn union(self, other: AABB) -> AABB {
-        AABB::new(self.min.min(other.min), self.max.max(other.max))
+        // both boxes are padded already, going through new() would pad again on every union
+        AABB {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
     }
 
     pub fn centroid(&self) -> Vec3 {
         0.5 * (self.min + self.max)
     }
 
-    pub fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<f64> {
+    pub fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<Float> {
         let m = ray.direction().recip();
         let t1 = (self.min - ray.origin()) * m;
         let t2 = (self.max - ray.origin()) * m;
         let t_near = t1.min(t2).max_element();
-        let t_far = t1.max(t2).min_element();
+        // a little further, so the rounding of the distances can't miss what's just inside
+        let t_far = t1.max(t2).min_element() * (1.0 + RELATIVE_ERROR);
         if t_near <= t_far && t_far >= ray_t.min && t_near <= ray_t.max {
             Some(t_near.max(ray_t.min))
         } else {
@@ -41,12 +50,31 @@ impl AABB {
         }
     }
 
+    /// whether the box goes on forever along some axis, like the box of an infinite plane, or
+    /// isn't a box at all after turning one of those. empty boxes are bounded
+    pub fn is_unbounded(&self) -> bool {
+        !(self.max - self.min).cmplt(Vec3::INFINITY).all()
+    }
+
+    /// whether `point` is inside, padding included
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    pub fn min(&self) -> Vec3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.max
+    }
+
     pub fn extent(&self) -> Vec3 {
         self.max - self.min
     }
 
     /// technically, half of this AABB's surface area
-    pub fn surface_area(&self) -> f64 {
+    pub fn surface_area(&self) -> Float {
         let e = self.extent();
         e.x * e.y + e.x * e.z + e.y * e.z
     }
@@ -67,8 +95,8 @@ impl AABB {
             .iter()
             .map(|&corner| mat.transform_point3(corner))
             .collect();
-        let mut new_min = Vec3::splat(f64::INFINITY);
-        let mut new_max = Vec3::splat(f64::NEG_INFINITY);
+        let mut new_min = Vec3::splat(Float::INFINITY);
+        let mut new_max = Vec3::splat(Float::NEG_INFINITY);
         for corner in transformed_corners {
             new_min = new_min.min(corner);
             new_max = new_max.max(corner);
@@ -78,6 +106,84 @@ impl AABB {
     }
 }
 
+/// A box that can be turned any way, e.g. the bounds of a rotated instance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrientedBox {
+    pub center: Vec3,
+    /// directions of the box's edges, as unit vectors
+    pub axes: [Vec3; 3],
+    /// half the length of the edges along each axis
+    pub half_extent: Vec3,
+}
+
+impl OrientedBox {
+    /// `bbox` rotated about its center, then moved by `translation`
+    pub fn new(bbox: &AABB, rotation: Quat, translation: Vec3) -> OrientedBox {
+        OrientedBox {
+            center: rotation * bbox.centroid() + translation,
+            axes: [rotation * Vec3::X, rotation * Vec3::Y, rotation * Vec3::Z],
+            half_extent: 0.5 * bbox.extent(),
+        }
+    }
+
+    /// `point` in the box's frame, where the box goes from -1 to 1 along each axis
+    pub fn to_unit(&self, point: Vec3) -> Vec3 {
+        let offset = point - self.center;
+        Vec3::new(
+            offset.dot(self.axes[0]),
+            offset.dot(self.axes[1]),
+            offset.dot(self.axes[2]),
+        ) / self.half_extent
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        self.to_unit(point).abs().max_element() <= 1.0
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        std::array::from_fn(|i| {
+            let sign = |bit: usize| if i & (1 << bit) == 0 { -1.0 } else { 1.0 };
+            self.center
+                + sign(0) * self.half_extent.x * self.axes[0]
+                + sign(1) * self.half_extent.y * self.axes[1]
+                + sign(2) * self.half_extent.z * self.axes[2]
+        })
+    }
+
+    /// the axis aligned box around it
+    pub fn aabb(&self) -> AABB {
+        let corners = self.corners();
+        let (min, max) = corners[1..]
+            .iter()
+            .fold((corners[0], corners[0]), |(min, max), &c| {
+                (min.min(c), max.max(c))
+            });
+        AABB { min, max }
+    }
+
+    /// distances to where the ray enters and leaves the box, within `ray_t`
+    pub fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<(Float, Float)> {
+        let offset = ray.origin() - self.center;
+        let mut t_near = ray_t.min;
+        let mut t_far = ray_t.max;
+        for axis in 0..3 {
+            let origin = offset.dot(self.axes[axis]);
+            let inv_dir = ray.direction().dot(self.axes[axis]).recip();
+            let t1 = (-self.half_extent[axis] - origin) * inv_dir;
+            let t2 = (self.half_extent[axis] - origin) * inv_dir;
+            t_near = t_near.max(t1.min(t2));
+            t_far = t_far.min(t1.max(t2));
+        }
+        (t_near <= t_far).then_some((t_near, t_far))
+    }
+}
+
+impl From<AABB> for OrientedBox {
+    fn from(bbox: AABB) -> Self {
+        OrientedBox::new(&bbox, Quat::IDENTITY, Vec3::ZERO)
+    }
+}
+
 impl Default for AABB {
     fn default() -> AABB {
         Self {
@@ -94,3 +200,65 @@ impl Add<Vec3> for AABB {
         AABB::new(self.min + rhs, self.max + rhs)
     }
 }
+
+/// Up to four boxes stored axis by axis, so a ray is tested against all of them with straight
+/// loops over the lanes that the compiler can turn into SIMD.
+#[derive(Clone, Copy)]
+pub struct AABB4 {
+    min: [[Float; 4]; 3],
+    max: [[Float; 4]; 3],
+    len: usize,
+}
+
+impl AABB4 {
+    pub const LANES: usize = 4;
+
+    pub fn new(boxes: &[AABB]) -> AABB4 {
+        assert!(boxes.len() <= Self::LANES, "AABB4 holds at most 4 boxes");
+        let mut min = [[0.0; 4]; 3];
+        let mut max = [[0.0; 4]; 3];
+        for (lane, bbox) in boxes.iter().enumerate() {
+            for axis in 0..3 {
+                min[axis][lane] = bbox.min[axis];
+                max[axis][lane] = bbox.max[axis];
+            }
+        }
+        AABB4 {
+            min,
+            max,
+            len: boxes.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// distance to each box like `AABB::intersects`, or infinity where the ray misses.
+    /// `inv_dir` is the reciprocal of the ray direction, computed once per ray by the caller
+    pub fn intersects(&self, origin: Vec3, inv_dir: Vec3, ray_t: Interval) -> [Float; 4] {
+        let mut t_near = [ray_t.min; 4];
+        let mut t_far = [ray_t.max; 4];
+        for axis in 0..3 {
+            let (o, m) = (origin[axis], inv_dir[axis]);
+            for lane in 0..4 {
+                let t1 = (self.min[axis][lane] - o) * m;
+                let t2 = (self.max[axis][lane] - o) * m;
+                t_near[lane] = t_near[lane].max(t1.min(t2));
+                t_far[lane] = t_far[lane].min(t1.max(t2));
+            }
+        }
+
+        let mut dist = [Float::INFINITY; 4];
+        for lane in 0..4 {
+            if lane < self.len && t_near[lane] <= t_far[lane] {
+                dist[lane] = t_near[lane];
+            }
+        }
+        dist
+    }
+}
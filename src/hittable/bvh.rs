@@ -1,15 +1,26 @@
-use crate::{bsdf::BxDFMaterial, hittable::HitInfo, interval::Interval, ray::Ray, vec3::Vec3};
+use crate::{
+    bsdf::BxDFMaterial,
+    hittable::HitInfo,
+    interval::Interval,
+    ray::Ray,
+    ray_stats,
+    vec3::{Float, Vec3},
+};
 use std::{cmp::Ordering, sync::Arc};
 
-use super::{Hittable, AABB};
+use super::{Hittable, AABB, AABB4};
 
 pub enum BVHNode {
     Leaf {
         bbox: AABB,
+        /// boxes of the hittables, tested together before any of the hittables are
+        boxes: AABB4,
         hittables: Vec<Arc<dyn Hittable>>,
     },
     Internal {
         bbox: AABB,
+        /// boxes of the two children, tested together
+        children: AABB4,
         left: Box<BVHNode>,
         right: Box<BVHNode>,
     },
@@ -27,18 +38,19 @@ impl BVH {
 
     fn build_recursive(hittables: Vec<Arc<dyn Hittable>>) -> BVHNode {
         if hittables.len() <= Self::MAX_HITTABLES_PER_LEAF {
-            let bbox = hittables
-                .iter()
-                .fold(AABB::default(), |acc, e| acc.union(e.bounding_box()));
-            return BVHNode::Leaf { bbox, hittables };
+            let boxes: Vec<AABB> = hittables.iter().map(|e| e.bounding_box()).collect();
+            let bbox = boxes.iter().fold(AABB::default(), |acc, &e| acc.union(e));
+            return BVHNode::Leaf {
+                bbox,
+                boxes: AABB4::new(&boxes),
+                hittables,
+            };
         }
 
-        let (left_list, right_list) = Self::find_best_split(&hittables);
+        let (mut left_list, mut right_list) = Self::find_best_split(&hittables);
         if left_list.is_empty() || right_list.is_empty() {
-            let bbox = hittables
-                .iter()
-                .fold(AABB::default(), |acc, e| acc.union(e.bounding_box()));
-            return BVHNode::Leaf { bbox, hittables };
+            // SAH found nothing better than one big leaf, but leaves have to fit in an AABB4
+            (left_list, right_list) = Self::split_in_half(hittables);
         }
 
         let left_node = Self::build_recursive(left_list);
@@ -46,88 +58,113 @@ impl BVH {
         let bbox = AABB::union(left_node.bounding_box(), right_node.bounding_box());
         BVHNode::Internal {
             bbox,
+            children: AABB4::new(&[left_node.bounding_box(), right_node.bounding_box()]),
             left: Box::new(left_node),
             right: Box::new(right_node),
         }
     }
 
-    fn find_best_split(hittables: &[Arc<dyn Hittable>]) -> (HitList, HitList) {
-        let parent_bbox = hittables
-            .iter()
-            .fold(AABB::default(), |acc, obj| acc.union(obj.bounding_box()));
-        let mut best_cost = f64::INFINITY;
-        let mut best_axis = 0;
-        let mut best_split_pos = 0.0;
-
-        for axis in 0..3 {
-            let mut positions: Vec<f64> = hittables
-                .iter()
-                .map(|obj| obj.bounding_box().centroid()[axis])
-                .collect();
-            positions.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
-            for split_pos in positions {
-                let cost = Self::evaluate_sah(axis, split_pos, parent_bbox, hittables);
-                if cost < best_cost {
-                    best_cost = cost;
-                    best_axis = axis;
-                    best_split_pos = split_pos;
-                }
-            }
-        }
+    /// median split along the longest axis of the centroids
+    fn split_in_half(mut hittables: HitList) -> (HitList, HitList) {
+        let boxes: Vec<AABB> = hittables.iter().map(|obj| obj.bounding_box()).collect();
+        let axis = centroid_axis(&boxes);
+        hittables.sort_by(|a, b| {
+            let a = a.bounding_box().centroid()[axis];
+            let b = b.bounding_box().centroid()[axis];
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        });
+        let right = hittables.split_off(hittables.len() / 2);
+        (hittables, right)
+    }
 
-        let (left, right): (Vec<_>, Vec<_>) = hittables
+    fn find_best_split(hittables: &[Arc<dyn Hittable>]) -> (HitList, HitList) {
+        let boxes: Vec<AABB> = hittables.iter().map(|obj| obj.bounding_box()).collect();
+        let Some((axis, split_pos)) = sah_split(&boxes) else {
+            return (vec![], hittables.to_vec());
+        };
+        hittables
             .iter()
             .cloned()
-            .partition(|obj| obj.bounding_box().centroid()[best_axis] < best_split_pos);
-
-        (left, right)
+            .partition(|obj| obj.bounding_box().centroid()[axis] < split_pos)
     }
+}
 
-    fn evaluate_sah(
-        axis: usize,
-        split_pos: f64,
-        parent_bbox: AABB,
-        hittables: &[Arc<dyn Hittable>],
-    ) -> f64 {
-        let mut left_bbox = AABB::default();
-        let mut left_count = 0;
+/// the longest axis of the box around the centroids of `boxes`
+pub(crate) fn centroid_axis(boxes: &[AABB]) -> usize {
+    let centroids = boxes.iter().fold(AABB::default(), |acc, b| {
+        let c = b.centroid();
+        acc.union(AABB::new(c, c))
+    });
+    let extent = centroids.extent();
+    if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    }
+}
 
+/// the axis and position of the split with the lowest surface area heuristic cost, everything
+/// with its centroid below the position goes left. `None` if no split beats a single leaf.
+/// sweeps every centroid on every axis as a split position, with the bounds of everything
+/// to the right of each position worked out in one pass so this stays n log n
+pub(crate) fn sah_split(boxes: &[AABB]) -> Option<(usize, Float)> {
+    let parent_bbox = boxes.iter().fold(AABB::default(), |acc, &b| acc.union(b));
+    let parent_cost = parent_bbox.surface_area() * boxes.len() as Float;
+    let mut best_cost = Float::INFINITY;
+    let mut best = None;
+
+    let n = boxes.len();
+    for axis in 0..3 {
+        let centroid = |i: usize| boxes[i].centroid()[axis];
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&a, &b| {
+            centroid(a)
+                .partial_cmp(&centroid(b))
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let mut right_areas = vec![0.0; n];
         let mut right_bbox = AABB::default();
-        let mut right_count = 0;
-
-        for obj in hittables {
-            if obj.bounding_box().centroid()[axis] < split_pos {
-                left_bbox = left_bbox.union(obj.bounding_box());
-                left_count += 1;
-            } else {
-                right_bbox = right_bbox.union(obj.bounding_box());
-                right_count += 1;
-            }
-        }
-
-        if left_count == 0 || right_count == 0 {
-            return f64::INFINITY;
+        for i in (0..n).rev() {
+            right_bbox = right_bbox.union(boxes[order[i]]);
+            right_areas[i] = right_bbox.surface_area();
         }
 
-        let cost = left_bbox.surface_area() * left_count as f64
-            + right_bbox.surface_area() * right_count as f64;
-        let parent_cost = parent_bbox.surface_area() * hittables.len() as f64;
-        if cost > 0.0 && cost < parent_cost {
-            cost
-        } else {
-            f64::INFINITY
+        // splitting at the centroid of order[i] puts everything before it on the left
+        let mut left_bbox = AABB::default();
+        for i in 1..n {
+            left_bbox = left_bbox.union(boxes[order[i - 1]]);
+            let split_pos = centroid(order[i]);
+            if centroid(order[i - 1]) >= split_pos {
+                continue; // same centroid, the split was already tried at the first of them
+            }
+            let cost = left_bbox.surface_area() * i as Float + right_areas[i] * (n - i) as Float;
+            if cost > 0.0 && cost < parent_cost && cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, split_pos));
+            }
         }
     }
+    best
 }
 
-impl Hittable for BVHNode {
-    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
-        self.bounding_box().intersects(ray, ray_t)?;
+impl BVHNode {
+    /// closest hit below this node, the caller has already tested the node's own box
+    fn traverse(&self, ray: &Ray, inv_dir: Vec3, ray_t: Interval) -> Option<HitInfo> {
+        ray_stats::count_node();
         match self {
-            BVHNode::Leaf { hittables, .. } => {
+            BVHNode::Leaf {
+                boxes, hittables, ..
+            } => {
+                let dists = boxes.intersects(ray.origin(), inv_dir, ray_t);
                 let mut hit_info: Option<HitInfo> = None;
                 let mut closest_hit = ray_t.max;
-                for p in hittables {
+                for (p, &dist) in hittables.iter().zip(dists.iter()) {
+                    if dist >= closest_hit {
+                        continue;
+                    }
                     if let Some(info) = p.intersects(ray, Interval::new(ray_t.min, closest_hit)) {
                         closest_hit = info.dist;
                         hit_info = Some(info);
@@ -135,33 +172,45 @@ impl Hittable for BVHNode {
                 }
                 hit_info
             }
-            BVHNode::Internal { left, right, .. } => {
-                let left_hit = left.bounding_box().intersects(ray, ray_t);
-                let right_hit = right.bounding_box().intersects(ray, ray_t);
-                match (left_hit, right_hit) {
-                    (None, None) => None,
-                    (None, Some(_)) => right.intersects(ray, ray_t),
-                    (Some(_), None) => left.intersects(ray, ray_t),
-                    (Some(_), Some(_)) => {
-                        let left_hit = left.intersects(ray, ray_t);
-                        let right_hit = right.intersects(ray, ray_t);
-                        match (left_hit, right_hit) {
-                            (None, None) => None,
-                            (None, Some(right_hit)) => Some(right_hit),
-                            (Some(left_hit), None) => Some(left_hit),
-                            (Some(left_hit), Some(right_hit)) => {
-                                if left_hit.dist < right_hit.dist {
-                                    Some(left_hit)
-                                } else {
-                                    Some(right_hit)
-                                }
-                            }
-                        }
+            BVHNode::Internal {
+                children,
+                left,
+                right,
+                ..
+            } => {
+                let [left_dist, right_dist, ..] = children.intersects(ray.origin(), inv_dir, ray_t);
+                // visit the nearer child first, its hit can rule out the other child
+                let (near, near_dist, far, far_dist) = if left_dist <= right_dist {
+                    (left, left_dist, right, right_dist)
+                } else {
+                    (right, right_dist, left, left_dist)
+                };
+
+                let mut hit_info = None;
+                let mut closest_hit = ray_t.max;
+                if near_dist < closest_hit {
+                    hit_info = near.traverse(ray, inv_dir, ray_t);
+                    if let Some(ref info) = hit_info {
+                        closest_hit = info.dist;
                     }
                 }
+                if far_dist < closest_hit {
+                    let far_hit = far.traverse(ray, inv_dir, Interval::new(ray_t.min, closest_hit));
+                    if far_hit.is_some() {
+                        hit_info = far_hit;
+                    }
+                }
+                hit_info
             }
         }
     }
+}
+
+impl Hittable for BVHNode {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        self.bounding_box().intersects(ray, ray_t)?;
+        self.traverse(ray, ray.direction().recip(), ray_t)
+    }
 
     fn bounding_box(&self) -> AABB {
         match self {
@@ -173,12 +222,4 @@ impl Hittable for BVHNode {
     fn material(&self) -> Option<&dyn BxDFMaterial> {
         None
     }
-
-    fn sample(&self, _origin: Vec3, _time: f64) -> Option<crate::vec3::Vec3> {
-        None
-    }
-
-    fn pdf(&self, _origin: crate::vec3::Vec3, _direction: crate::vec3::Vec3, _time: f64) -> f64 {
-        0.0
-    }
 }
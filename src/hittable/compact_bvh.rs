@@ -0,0 +1,388 @@
+use std::{fmt, mem::size_of, sync::Arc};
+
+use crate::{
+    interval::Interval,
+    ray::Ray,
+    ray_stats,
+    vec3::{Float, Vec3},
+};
+
+use super::{bvh::centroid_axis, bvh::sah_split, BVHNode, HitInfo, Hittable, AABB};
+
+/// How a BVH is laid out in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BvhLayout {
+    /// a tree of separately allocated nodes that keep their children's boxes together, about five
+    /// times the memory of the compact layouts
+    #[default]
+    Tree,
+    /// 32 byte nodes in one array, with the boxes rounded outwards to f32, for big meshes
+    Compact,
+    /// 20 byte nodes, with the boxes rounded outwards to a 16 bit grid over the whole BVH, so
+    /// they're a little looser
+    CompactReduced,
+}
+
+/// A BVH flattened into an arena of small nodes, for scenes too big for `BVHNode`. Nodes are
+/// stored depth first, so the first child of an interior node is the next node and only the
+/// second needs an index; the hittables of each leaf are a range of one shared list
+pub struct CompactBVH {
+    nodes: Nodes,
+    hittables: Vec<Arc<dyn Hittable>>,
+    bbox: AABB,
+    depth: usize,
+}
+
+enum Nodes {
+    Full(Vec<Node<[f32; 6]>>),
+    Reduced(Vec<Node<[u16; 6]>>),
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Node<B> {
+    bounds: B,
+    /// first hittable of a leaf, or the second child of an interior node
+    offset: u32,
+    /// hittables in a leaf, 0 for interior nodes
+    count: u16,
+    /// the axis interior nodes are split along
+    axis: u8,
+    _pad: u8,
+}
+
+const _: () = assert!(size_of::<Node<[f32; 6]>>() == 32);
+const _: () = assert!(size_of::<Node<[u16; 6]>>() == 20);
+
+/// Boxes stored in fewer bits, always rounded so they still hold what they bound
+trait NodeBounds: Copy + Send + Sync {
+    fn encode(bbox: &AABB, grid: &AABB) -> Self;
+    /// the corners of the box
+    fn decode(&self, grid: &AABB) -> (Vec3, Vec3);
+}
+
+impl NodeBounds for [f32; 6] {
+    // already f32 with the `f32` feature
+    #[allow(clippy::unnecessary_cast)]
+    fn encode(bbox: &AABB, _grid: &AABB) -> Self {
+        let down = |x: Float| {
+            let y = x as f32;
+            if y as Float > x {
+                y.next_down()
+            } else {
+                y
+            }
+        };
+        let up = |x: Float| {
+            let y = x as f32;
+            if (y as Float) < x {
+                y.next_up()
+            } else {
+                y
+            }
+        };
+        [
+            down(bbox.min().x),
+            down(bbox.min().y),
+            down(bbox.min().z),
+            up(bbox.max().x),
+            up(bbox.max().y),
+            up(bbox.max().z),
+        ]
+    }
+
+    fn decode(&self, _grid: &AABB) -> (Vec3, Vec3) {
+        let v = self.map(Float::from);
+        (Vec3::new(v[0], v[1], v[2]), Vec3::new(v[3], v[4], v[5]))
+    }
+}
+
+impl NodeBounds for [u16; 6] {
+    fn encode(bbox: &AABB, grid: &AABB) -> Self {
+        let cell = grid.extent() / u16::MAX as Float;
+        let quantize = |x: Float, axis: usize, round: fn(Float) -> Float| {
+            if cell[axis] <= 0.0 {
+                return 0;
+            }
+            round((x - grid.min()[axis]) / cell[axis]).clamp(0.0, u16::MAX as Float) as u16
+        };
+        [
+            quantize(bbox.min().x, 0, Float::floor),
+            quantize(bbox.min().y, 1, Float::floor),
+            quantize(bbox.min().z, 2, Float::floor),
+            quantize(bbox.max().x, 0, Float::ceil),
+            quantize(bbox.max().y, 1, Float::ceil),
+            quantize(bbox.max().z, 2, Float::ceil),
+        ]
+    }
+
+    fn decode(&self, grid: &AABB) -> (Vec3, Vec3) {
+        let cell = grid.extent() / u16::MAX as Float;
+        let q = self.map(Float::from);
+        (
+            grid.min() + Vec3::new(q[0], q[1], q[2]) * cell,
+            grid.min() + Vec3::new(q[3], q[4], q[5]) * cell,
+        )
+    }
+}
+
+/// Sizes of a BVH, to see what a big scene costs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub hittables: usize,
+    pub depth: usize,
+    /// memory of the nodes, including the lists of hittables in them
+    pub bytes: usize,
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} leaves with {:.1} hittables each, depth {}, {:.2} MiB",
+            self.nodes,
+            self.leaves,
+            self.hittables as Float / self.leaves.max(1) as Float,
+            self.depth,
+            self.bytes as Float / (1024.0 * 1024.0)
+        )
+    }
+}
+
+impl BvhStats {
+    /// stats of a tree of `BVHNode`s
+    pub fn of_tree(node: &BVHNode) -> BvhStats {
+        let mut stats = BvhStats::default();
+        let mut stack = vec![(node, 1)];
+        while let Some((node, depth)) = stack.pop() {
+            stats.nodes += 1;
+            stats.bytes += size_of::<BVHNode>();
+            stats.depth = stats.depth.max(depth);
+            match node {
+                BVHNode::Leaf { hittables, .. } => {
+                    stats.leaves += 1;
+                    stats.hittables += hittables.len();
+                    stats.bytes += hittables.capacity() * size_of::<Arc<dyn Hittable>>();
+                }
+                BVHNode::Internal { left, right, .. } => {
+                    stack.push((left, depth + 1));
+                    stack.push((right, depth + 1));
+                }
+            }
+        }
+        stats
+    }
+}
+
+impl CompactBVH {
+    const MAX_HITTABLES_PER_LEAF: usize = 4;
+
+    /// `layout` should be one of the compact ones, `Tree` builds a `Compact` BVH
+    pub fn build(hittables: &[Arc<dyn Hittable>], layout: BvhLayout) -> CompactBVH {
+        let boxes: Vec<AABB> = hittables.iter().map(|obj| obj.bounding_box()).collect();
+        let bbox = boxes.iter().fold(AABB::default(), |acc, &b| acc.union(b));
+        let mut builder = Builder {
+            source: hittables,
+            boxes: &boxes,
+            grid: bbox,
+            hittables: Vec::with_capacity(hittables.len()),
+            depth: 0,
+        };
+        let mut items: Vec<usize> = (0..hittables.len()).collect();
+        let nodes = match layout {
+            BvhLayout::CompactReduced => {
+                let mut nodes = vec![];
+                builder.build(&mut nodes, &mut items, 1);
+                Nodes::Reduced(nodes)
+            }
+            BvhLayout::Tree | BvhLayout::Compact => {
+                let mut nodes = vec![];
+                builder.build(&mut nodes, &mut items, 1);
+                Nodes::Full(nodes)
+            }
+        };
+        CompactBVH {
+            nodes,
+            depth: builder.depth,
+            hittables: builder.hittables,
+            bbox,
+        }
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let (nodes, leaves, node_size) = match &self.nodes {
+            Nodes::Full(nodes) => (
+                nodes.len(),
+                count_leaves(nodes),
+                size_of::<Node<[f32; 6]>>(),
+            ),
+            Nodes::Reduced(nodes) => (
+                nodes.len(),
+                count_leaves(nodes),
+                size_of::<Node<[u16; 6]>>(),
+            ),
+        };
+        BvhStats {
+            nodes,
+            leaves,
+            hittables: self.hittables.len(),
+            depth: self.depth,
+            bytes: nodes * node_size + self.hittables.len() * size_of::<Arc<dyn Hittable>>(),
+        }
+    }
+
+    fn traverse<B: NodeBounds>(
+        &self,
+        nodes: &[Node<B>],
+        ray: &Ray,
+        ray_t: Interval,
+    ) -> Option<HitInfo> {
+        let inv_dir = ray.direction().recip();
+        let negative = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+        let mut closest = ray_t.max;
+        let mut hit_info = None;
+        let mut stack = Vec::with_capacity(64);
+        let mut current = 0;
+        loop {
+            ray_stats::count_node();
+            let node = &nodes[current];
+            let (min, max) = node.bounds.decode(&self.bbox);
+            if hits_box(
+                min,
+                max,
+                ray.origin(),
+                inv_dir,
+                Interval::new(ray_t.min, closest),
+            ) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for hittable in &self.hittables[start..start + node.count as usize] {
+                        if let Some(info) =
+                            hittable.intersects(ray, Interval::new(ray_t.min, closest))
+                        {
+                            closest = info.dist;
+                            hit_info = Some(info);
+                        }
+                    }
+                } else if negative[node.axis as usize] {
+                    // the second child is nearer along the split axis, visit it first
+                    stack.push(current + 1);
+                    current = node.offset as usize;
+                    continue;
+                } else {
+                    stack.push(node.offset as usize);
+                    current += 1;
+                    continue;
+                }
+            }
+            match stack.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        hit_info
+    }
+}
+
+fn count_leaves<B>(nodes: &[Node<B>]) -> usize {
+    nodes.iter().filter(|node| node.count > 0).count()
+}
+
+/// slab test like `AABB::intersects`, with the reciprocal direction worked out once per ray
+fn hits_box(min: Vec3, max: Vec3, origin: Vec3, inv_dir: Vec3, ray_t: Interval) -> bool {
+    let t1 = (min - origin) * inv_dir;
+    let t2 = (max - origin) * inv_dir;
+    let near = t1.min(t2).max_element().max(ray_t.min);
+    let far = t1.max(t2).min_element().min(ray_t.max);
+    near <= far
+}
+
+struct Builder<'a> {
+    source: &'a [Arc<dyn Hittable>],
+    boxes: &'a [AABB],
+    grid: AABB,
+    hittables: Vec<Arc<dyn Hittable>>,
+    depth: usize,
+}
+
+impl Builder<'_> {
+    /// append the nodes for `items`, returning the index of the first
+    fn build<B: NodeBounds>(
+        &mut self,
+        nodes: &mut Vec<Node<B>>,
+        items: &mut [usize],
+        depth: usize,
+    ) -> usize {
+        self.depth = self.depth.max(depth);
+        let bbox = items
+            .iter()
+            .fold(AABB::default(), |acc, &i| acc.union(self.boxes[i]));
+        let index = nodes.len();
+        nodes.push(Node {
+            bounds: B::encode(&bbox, &self.grid),
+            offset: 0,
+            count: 0,
+            axis: 0,
+            _pad: 0,
+        });
+
+        if items.len() <= CompactBVH::MAX_HITTABLES_PER_LEAF {
+            nodes[index].offset = self.hittables.len() as u32;
+            nodes[index].count = items.len() as u16;
+            self.hittables
+                .extend(items.iter().map(|&i| self.source[i].clone()));
+            return index;
+        }
+
+        let item_boxes: Vec<AABB> = items.iter().map(|&i| self.boxes[i]).collect();
+        let centroid = |i: usize, axis: usize| self.boxes[i].centroid()[axis];
+        let (axis, mid) = match sah_split(&item_boxes) {
+            Some((axis, split_pos)) => {
+                // move everything left of the split to the front
+                let mut mid = 0;
+                for j in 0..items.len() {
+                    if centroid(items[j], axis) < split_pos {
+                        items.swap(mid, j);
+                        mid += 1;
+                    }
+                }
+                (axis, mid)
+            }
+            None => {
+                // nothing beats one big leaf, but leaves have to stay small
+                let axis = centroid_axis(&item_boxes);
+                let mid = items.len() / 2;
+                items.select_nth_unstable_by(mid, |&a, &b| {
+                    centroid(a, axis).total_cmp(&centroid(b, axis))
+                });
+                (axis, mid)
+            }
+        };
+
+        let (left, right) = items.split_at_mut(mid);
+        self.build(nodes, left, depth + 1);
+        let second = self.build(nodes, right, depth + 1);
+        nodes[index].offset = second as u32;
+        nodes[index].axis = axis as u8;
+        index
+    }
+}
+
+impl Hittable for CompactBVH {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        match &self.nodes {
+            Nodes::Full(nodes) => self.traverse(nodes, ray, ray_t),
+            Nodes::Reduced(nodes) => self.traverse(nodes, ray, ray_t),
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn material(&self) -> Option<&dyn crate::bsdf::BxDFMaterial> {
+        None
+    }
+}
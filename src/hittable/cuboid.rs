@@ -1,56 +1,106 @@
-use crate::{bsdf::MatPtr, vec3::Vec3};
+use crate::{
+    bsdf::MatPtr,
+    vec3::{Float, Vec2, Vec3},
+};
 
-use super::{Hittable, HittableList, Quad};
+use super::{Hittable, HittableList, LightSample, Quad, QuadUv};
 
 pub struct Cuboid {
     sides: HittableList,
     material: MatPtr,
 }
 
+/// How the texture coordinates are laid out over the faces of a box. on every face u goes to
+/// the right and v up, seen from outside with the sides upright and the top and bottom turned
+/// towards the front, so a texture is never mirrored or on its side
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CuboidUv {
+    /// the whole texture on each face, stretched to its shape
+    #[default]
+    Faces,
+    /// the box unfolded into a cross over a grid of 4 by 3 cells, the sides along the middle
+    /// row from left to back, the top above the front and the bottom under it, like the
+    /// unwrapped cube textures of modeling tools
+    Cross,
+    /// the same cross in scene units over tiles of this size, so the texture is as big on
+    /// every face of any box and runs on across the edges between the sides
+    Tile(Float),
+}
+
 impl Cuboid {
     pub fn new(a: Vec3, b: Vec3, mat: MatPtr) -> Cuboid {
+        Self::with_uv(a, b, mat, CuboidUv::default())
+    }
+
+    pub fn with_uv(a: Vec3, b: Vec3, mat: MatPtr, uv: CuboidUv) -> Cuboid {
         let mut sides = HittableList::new();
         let min = a.min(b);
         let max = a.max(b);
         let dx = Vec3::ZERO.with_x(max.x - min.x);
         let dy = Vec3::ZERO.with_y(max.y - min.y);
         let dz = Vec3::ZERO.with_z(max.z - min.z);
-        sides.add(Quad::new(
+        // where each face goes in the cross, in cells and in scene units
+        let (w, h, d) = (dx.x, dy.y, dz.z);
+        let layout = |cell: Vec2, corner: Vec2, size: Vec2| match uv {
+            CuboidUv::Faces => QuadUv::default(),
+            CuboidUv::Cross => QuadUv {
+                origin: cell / Vec2::new(4.0, 3.0),
+                scale: Vec2::new(0.25, 1.0 / 3.0),
+                rotation: 0.0,
+            },
+            CuboidUv::Tile(tile) => QuadUv {
+                origin: corner / tile,
+                scale: size / tile,
+                rotation: 0.0,
+            },
+        };
+        let mut add = |q: Vec3, u: Vec3, v: Vec3, cell: Vec2, corner: Vec2| {
+            let size = Vec2::new(u.length(), v.length());
+            let uv = layout(cell, corner, size);
+            sides.add(Quad::new(q, u, v, mat.clone()).with_uv(uv));
+        };
+        add(
             Vec3::new(min.x, min.y, max.z),
             dx,
             dy,
-            mat.clone(),
-        )); // front
-        sides.add(Quad::new(
+            Vec2::new(1.0, 1.0),
+            Vec2::new(d, d),
+        ); // front
+        add(
             Vec3::new(max.x, min.y, max.z),
             -dz,
             dy,
-            mat.clone(),
-        )); // right
-        sides.add(Quad::new(
+            Vec2::new(2.0, 1.0),
+            Vec2::new(d + w, d),
+        ); // right
+        add(
             Vec3::new(max.x, min.y, min.z),
             -dx,
             dy,
-            mat.clone(),
-        )); // back
-        sides.add(Quad::new(
+            Vec2::new(3.0, 1.0),
+            Vec2::new(2.0 * d + w, d),
+        ); // back
+        add(
             Vec3::new(min.x, min.y, min.z),
             dz,
             dy,
-            mat.clone(),
-        )); // left
-        sides.add(Quad::new(
+            Vec2::new(0.0, 1.0),
+            Vec2::new(0.0, d),
+        ); // left
+        add(
             Vec3::new(min.x, max.y, max.z),
             dx,
             -dz,
-            mat.clone(),
-        )); // top
-        sides.add(Quad::new(
+            Vec2::new(1.0, 2.0),
+            Vec2::new(d, d + h),
+        ); // top
+        add(
             Vec3::new(min.x, min.y, min.z),
             dx,
             dz,
-            mat.clone(),
-        )); // bottom
+            Vec2::new(1.0, 0.0),
+            Vec2::new(d, 0.0),
+        ); // bottom
         Cuboid {
             sides,
             material: mat,
@@ -75,11 +125,16 @@ impl Hittable for Cuboid {
         Some(self.material.as_ref())
     }
 
-    fn sample(&self, origin:Vec3, time: f64) -> Option<Vec3> {
-        self.sides.sample(origin, time)
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        self.sides.sample_light(origin, time)
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        self.sides.light_pdf(origin, point, time)
     }
 
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        self.sides.pdf(origin, direction, time)
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        self.sides.add_to_gpu_scene(scene)
     }
 }
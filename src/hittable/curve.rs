@@ -0,0 +1,215 @@
+use crate::{
+    bsdf::{sampling::Frame, BxDFMaterial, MatPtr},
+    interval::Interval,
+    ray::Ray,
+    ray_stats,
+    vec3::{Float, Vec3},
+};
+
+use super::{HitInfo, Hittable, AABB};
+
+/// How the width of a curve is turned into a surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CurveShape {
+    /// a flat strip that always faces the ray, cheap and fine for thin hair and fur
+    #[default]
+    Ribbon,
+    /// a tube, with normals that wrap around it, for strands that are thick on screen
+    Round,
+}
+
+/// A cubic Bézier strand, e.g. a hair or a blade of grass, whose width goes linearly from
+/// `width0` at the root to `width1` at the tip. Intersected the way pbrt does it: the curve is
+/// projected onto a plane facing the ray and split in halves until the pieces are nearly
+/// straight, then the closest point on each piece is tested against the width
+#[derive(Clone)]
+pub struct Curve {
+    points: [Vec3; 4],
+    width0: Float,
+    width1: Float,
+    shape: CurveShape,
+    material: MatPtr,
+    bbox: AABB,
+}
+
+impl Curve {
+    pub fn new(
+        points: [Vec3; 4],
+        width0: Float,
+        width1: Float,
+        shape: CurveShape,
+        material: MatPtr,
+    ) -> Curve {
+        // the control points' box holds the whole curve
+        let half = Vec3::splat(0.5 * width0.max(width1));
+        let (min, max) = points[1..]
+            .iter()
+            .fold((points[0], points[0]), |(min, max), &p| {
+                (min.min(p), max.max(p))
+            });
+        Curve {
+            points,
+            width0,
+            width1,
+            shape,
+            material,
+            bbox: AABB::new(min - half, max + half),
+        }
+    }
+
+    pub fn point(&self, u: Float) -> Vec3 {
+        bezier(&self.points, u)
+    }
+
+    /// direction of the curve at `u`, from root to tip
+    pub fn tangent(&self, u: Float) -> Vec3 {
+        bezier_derivative(&self.points, u).normalize_or_zero()
+    }
+
+    pub fn width(&self, u: Float) -> Float {
+        self.width0 + (self.width1 - self.width0) * u
+    }
+
+    /// find the closest hit in pieces of the curve from `u0` to `u1`, given by `cp` in the
+    /// ray's space. `best` is (distance along the ray, u, the hit's offset from the center)
+    fn intersect_piece(
+        &self,
+        cp: &[Vec3; 4],
+        u0: Float,
+        u1: Float,
+        depth: u32,
+        z_range: Interval,
+        best: &mut Option<(Float, Float, Vec3)>,
+    ) {
+        let half = 0.5 * self.width(u0).max(self.width(u1));
+        let (min, max) = cp[1..]
+            .iter()
+            .fold((cp[0], cp[0]), |(min, max), &p| (min.min(p), max.max(p)));
+        let z_max = best.map_or(z_range.max, |(z, _, _)| z);
+        if min.x - half > 0.0
+            || max.x + half < 0.0
+            || min.y - half > 0.0
+            || max.y + half < 0.0
+            || min.z - half > z_max
+            || max.z + half < z_range.min
+        {
+            return;
+        }
+
+        if depth > 0 {
+            let (a, b) = split(cp);
+            let u_mid = 0.5 * (u0 + u1);
+            self.intersect_piece(&a, u0, u_mid, depth - 1, z_range, best);
+            self.intersect_piece(&b, u_mid, u1, depth - 1, z_range, best);
+            return;
+        }
+
+        // the ray must pass between the lines through the ends, perpendicular to the curve,
+        // or neighbouring pieces would both count the hit
+        let start = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        let end = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if start < 0.0 || end < 0.0 {
+            return;
+        }
+
+        // the closest point to the ray on the line through the ends
+        let segment = (cp[3] - cp[0]).truncate();
+        let length_squared = segment.length_squared();
+        if length_squared == 0.0 {
+            return;
+        }
+        let w = (-cp[0].truncate().dot(segment) / length_squared).clamp(0.0, 1.0);
+        let center = bezier(cp, w);
+        let u = u0 + (u1 - u0) * w;
+        let radius = 0.5 * self.width(u);
+        if center.x * center.x + center.y * center.y > radius * radius {
+            return;
+        }
+        if center.z < z_range.min || center.z > z_max {
+            return;
+        }
+        *best = Some((center.z, u, -center));
+    }
+}
+
+impl Hittable for Curve {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        ray_stats::count_primitives(1);
+        // the ray's space, where it starts at the origin and points down +z
+        let length = ray.direction().length();
+        let dir = ray.direction() / length;
+        let frame = Frame::from_normal(dir);
+        let cp = self.points.map(|p| frame.to_local(p - ray.origin()));
+
+        // enough splits that the pieces are within a 20th of the width from straight lines
+        let bend = (0..2)
+            .map(|i| (cp[i] - 2.0 * cp[i + 1] + cp[i + 2]).abs().max_element())
+            .fold(0.0, Float::max);
+        let tolerance = 0.05 * self.width0.max(self.width1);
+        let depth = if tolerance > 0.0 && bend > 0.0 {
+            ((crate::vec3::consts::SQRT_2 * 6.0 * bend / (8.0 * tolerance)).log2() / 2.0)
+                .ceil()
+                .clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+
+        let z_range = Interval::new(ray_t.min * length, ray_t.max * length);
+        let mut best = None;
+        self.intersect_piece(&cp, 0.0, 1.0, depth, z_range, &mut best);
+        let (z, u, offset) = best?;
+
+        // offset is from the center of the curve to the ray in the ray's space
+        let tangent = self.tangent(u);
+        let facing = (tangent * tangent.dot(dir) - dir).normalize_or_zero();
+        let side = tangent.cross(facing);
+        let radius = 0.5 * self.width(u);
+        let across = (frame.to_world(offset).dot(side) / radius).clamp(-1.0, 1.0);
+        let (z, normal) = match self.shape {
+            CurveShape::Ribbon => (z, facing),
+            CurveShape::Round => {
+                // move the hit out of the center plane onto the tube
+                let out = (1.0 - across * across).sqrt();
+                (z - radius * out, facing * out + side * across)
+            }
+        };
+        let dist = z / length;
+        if !ray_t.surrounds(dist) {
+            return None;
+        }
+
+        let v = 0.5 * (across + 1.0);
+        let mut info = HitInfo::new(ray, ray.at(dist), normal, dist, self.material.clone(), u, v);
+        info.tangent = tangent;
+        // far enough that rays leaving the strand don't hit it again
+        info.epsilon = 2.0 * radius;
+        Some(info)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn material(&self) -> Option<&dyn BxDFMaterial> {
+        Some(self.material.as_ref())
+    }
+}
+
+fn bezier(cp: &[Vec3; 4], u: Float) -> Vec3 {
+    let s = 1.0 - u;
+    s * s * s * cp[0] + 3.0 * s * s * u * cp[1] + 3.0 * s * u * u * cp[2] + u * u * u * cp[3]
+}
+
+fn bezier_derivative(cp: &[Vec3; 4], u: Float) -> Vec3 {
+    let s = 1.0 - u;
+    3.0 * (s * s * (cp[1] - cp[0]) + 2.0 * s * u * (cp[2] - cp[1]) + u * u * (cp[3] - cp[2]))
+}
+
+/// de Casteljau's split of a curve into its halves
+fn split(cp: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let mid = |a: Vec3, b: Vec3| 0.5 * (a + b);
+    let (p01, p12, p23) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let center = mid(p012, p123);
+    ([cp[0], p01, p012, center], [center, p123, p23, cp[3]])
+}
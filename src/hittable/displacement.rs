@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use crate::{
+    bsdf::sampling::Frame,
+    texture::Texture,
+    vec3::{Float, Vec3},
+};
+
+use super::MeshBuffers;
+
+/// What moves the vertices of a displaced mesh
+#[derive(Clone)]
+pub enum DisplacementMap {
+    /// moves vertices along their normal by the height, from 0 to 1
+    Height(Arc<dyn Texture<Float>>),
+    /// moves vertices by a vector in the space of their tangent (along u), bitangent and normal,
+    /// stored like a normal map with each component going from -1 at 0 to 1 at 1
+    Vector(Arc<dyn Texture<Vec3>>),
+}
+
+/// Real geometry from a height or vector displacement map, applied when a mesh is loaded: the
+/// triangles are subdivided `subdivisions` times and every vertex is moved by the map times
+/// `scale`, so the detail shows on silhouettes and in shadows, not just in the shading
+#[derive(Clone)]
+pub struct Displacement {
+    pub map: DisplacementMap,
+    pub scale: Float,
+    pub subdivisions: usize,
+}
+
+impl Displacement {
+    pub fn height(map: Arc<dyn Texture<Float>>, scale: Float, subdivisions: usize) -> Displacement {
+        Displacement {
+            map: DisplacementMap::Height(map),
+            scale,
+            subdivisions,
+        }
+    }
+
+    pub fn vector(map: Arc<dyn Texture<Vec3>>, scale: Float, subdivisions: usize) -> Displacement {
+        Displacement {
+            map: DisplacementMap::Vector(map),
+            scale,
+            subdivisions,
+        }
+    }
+}
+
+impl MeshBuffers {
+    /// the mesh subdivided and displaced by `displacement`, with normals worked out again from
+    /// the displaced surface
+    pub fn displaced(&self, displacement: &Displacement) -> MeshBuffers {
+        let mut mesh = self.clone();
+        for _ in 0..displacement.subdivisions {
+            mesh = mesh.subdivide();
+        }
+
+        let normals = if mesh.normals.is_empty() {
+            mesh.smooth_normals()
+        } else {
+            mesh.normals.clone()
+        };
+        let uv = |i: usize| mesh.uvs.get(i).copied().unwrap_or_default();
+        let offsets: Vec<Vec3> = match &displacement.map {
+            DisplacementMap::Height(map) => (0..mesh.vertices.len())
+                .map(|i| {
+                    let (u, v) = uv(i);
+                    normals[i] * map.value(u, v, &mesh.vertices[i])
+                })
+                .collect(),
+            DisplacementMap::Vector(map) => {
+                let tangents = mesh.tangents();
+                (0..mesh.vertices.len())
+                    .map(|i| {
+                        let (u, v) = uv(i);
+                        let local = 2.0 * map.value(u, v, &mesh.vertices[i]) - Vec3::ONE;
+                        tangent_frame(normals[i], tangents[i]).to_world(local)
+                    })
+                    .collect()
+            }
+        };
+
+        for (vertex, offset) in mesh.vertices.iter_mut().zip(offsets) {
+            *vertex += displacement.scale * offset;
+        }
+        mesh.normals = mesh.smooth_normals();
+        mesh
+    }
+
+    /// per vertex direction of increasing u, averaged over the triangles around it
+    fn tangents(&self) -> Vec<Vec3> {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        if self.uvs.is_empty() {
+            return tangents;
+        }
+        for [a, b, c] in self.triangles() {
+            let (dp1, dp2) = (
+                self.vertices[b] - self.vertices[a],
+                self.vertices[c] - self.vertices[a],
+            );
+            let (du1, dv1) = (self.uvs[b].0 - self.uvs[a].0, self.uvs[b].1 - self.uvs[a].1);
+            let (du2, dv2) = (self.uvs[c].0 - self.uvs[a].0, self.uvs[c].1 - self.uvs[a].1);
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < 1e-12 {
+                continue;
+            }
+            let dpdu = (dv2 * dp1 - dv1 * dp2) / det;
+            for i in [a, b, c] {
+                tangents[i] += dpdu;
+            }
+        }
+        tangents
+    }
+}
+
+/// the tangent made perpendicular to the normal, or any tangent if there is none
+fn tangent_frame(normal: Vec3, tangent: Vec3) -> Frame {
+    let s = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+    if s == Vec3::ZERO {
+        return Frame::from_normal(normal);
+    }
+    Frame {
+        s,
+        t: normal.cross(s),
+        n: normal,
+    }
+}
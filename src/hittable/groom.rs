@@ -0,0 +1,116 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    bsdf::MatPtr,
+    vec3::{Float, Vec3},
+};
+
+use super::{BvhLayout, Curve, CurveShape, HittableList, MeshBuffers};
+
+/// Settings for growing hair or fur on a mesh: strands are rooted at random points spread
+/// evenly over its area, grow out along the surface normal and bend down under gravity
+#[derive(Debug, Clone, Copy)]
+pub struct Groom {
+    pub strands: usize,
+    pub length: Float,
+    pub root_width: Float,
+    pub tip_width: Float,
+    /// how far the strands bend towards -y, 0 keeps them straight
+    pub droop: Float,
+    /// randomness of the strands' directions and lengths, from 0 to 1
+    pub jitter: Float,
+    pub shape: CurveShape,
+    /// the same seed grows the same strands
+    pub seed: u64,
+    /// layout of the BVH over the strands, a compact one for millions of them
+    pub bvh: BvhLayout,
+}
+
+impl Groom {
+    pub fn new(strands: usize, length: Float, root_width: Float, tip_width: Float) -> Groom {
+        Groom {
+            strands,
+            length,
+            root_width,
+            tip_width,
+            droop: 0.3,
+            jitter: 0.2,
+            shape: CurveShape::Ribbon,
+            seed: 0,
+            bvh: BvhLayout::Tree,
+        }
+    }
+
+    /// the strands on `surface`, with a BVH over them
+    pub fn grow(&self, surface: &MeshBuffers, material: MatPtr) -> HittableList {
+        let mut strands = HittableList::new();
+        let triangles: Vec<[usize; 3]> = surface.triangles().collect();
+        let v = &surface.vertices;
+
+        // running total of the triangle areas, to pick triangles by area
+        let mut areas = Vec::with_capacity(triangles.len());
+        let mut total = 0.0;
+        for &[a, b, c] in triangles.iter() {
+            total += 0.5 * (v[b] - v[a]).cross(v[c] - v[a]).length();
+            areas.push(total);
+        }
+        if total <= 0.0 {
+            return strands;
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        for _ in 0..self.strands {
+            let target = rng.gen::<Float>() * total;
+            let i = areas
+                .partition_point(|&area| area < target)
+                .min(triangles.len() - 1);
+            let [a, b, c] = triangles[i];
+
+            // uniform point on the triangle
+            let (r1, r2): (Float, Float) = (rng.gen::<Float>().sqrt(), rng.gen());
+            let (wa, wb, wc) = (1.0 - r1, r1 * (1.0 - r2), r1 * r2);
+            let root = wa * v[a] + wb * v[b] + wc * v[c];
+            let normal = if surface.normals.is_empty() {
+                (v[b] - v[a]).cross(v[c] - v[a])
+            } else {
+                let n = &surface.normals;
+                wa * n[a] + wb * n[b] + wc * n[c]
+            };
+            let Some(normal) = normal.try_normalize() else {
+                continue;
+            };
+
+            let dir = (normal + self.jitter * random_unit(&mut rng)).normalize_or_zero();
+            let step = self.length * (1.0 - 0.5 * self.jitter * rng.gen::<Float>()) / 3.0;
+            let gravity = Vec3::new(0.0, -self.droop, 0.0);
+            let p1 = root + step * dir;
+            let p2 = p1 + step * (dir + gravity).normalize_or_zero();
+            let p3 = p2 + step * (dir + 2.0 * gravity).normalize_or_zero();
+            strands.add(Curve::new(
+                [root, p1, p2, p3],
+                self.root_width,
+                self.tip_width,
+                self.shape,
+                material.clone(),
+            ));
+        }
+        strands.build_bvh_with(self.bvh);
+        strands
+    }
+}
+
+impl Default for Groom {
+    fn default() -> Self {
+        Self::new(10000, 0.1, 0.004, 0.001)
+    }
+}
+
+/// a random point in the unit ball
+fn random_unit(rng: &mut StdRng) -> Vec3 {
+    loop {
+        let p = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - Vec3::ONE;
+        if p.length_squared() <= 1.0 {
+            return p;
+        }
+    }
+}
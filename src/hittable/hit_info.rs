@@ -1,15 +1,55 @@
-use crate::{bsdf::MatPtr, ray::Ray, texture::Texture, vec3::Vec3};
+use std::sync::Arc;
+
+use crate::{
+    bsdf::{sampling::Frame, MatPtr},
+    ray::{offset_origin, rounding_error, Ray},
+    texture::{Footprint, Texture},
+    vec3::{Float, Vec3},
+};
+
+use super::{LightLink, ShadingContext, TextureValue, Visibility};
 
 #[derive(Clone)]
 pub struct HitInfo {
     pub point: Vec3,
     pub geometric_normal: Vec3,
     pub shading_normal: Vec3,
-    pub dist: f64,
+    context: ShadingContext,
+    pub dist: Float,
     pub front_face: bool,
     pub mat: MatPtr,
-    pub u: f64,
-    pub v: f64,
+    pub u: Float,
+    pub v: Float,
+    /// area of the surface a pixel covers, only for hits of camera rays
+    pub footprint: Option<Box<Footprint>>,
+    /// bound on the rounding error of each coordinate of `point`, which rays leaving the
+    /// surface start outside of
+    pub error: Vec3,
+    /// how much further than that they start from it, for shapes that need more room, like
+    /// thin strands
+    pub epsilon: Float,
+    /// the hit moved onto the smooth surface the shading normals describe, which reflected
+    /// rays start from so they don't fall back into the flat triangle (the shadow terminator)
+    pub terminator_point: Option<Vec3>,
+    /// direction the surface runs in, for materials that depend on it like hair. zero when
+    /// the shape doesn't have one
+    pub tangent: Vec3,
+    /// the color baked into the vertices of the mesh that was hit, interpolated to the hit,
+    /// if it has vertex colors
+    pub vertex_color: Option<Vec3>,
+    /// how far the point moves from shutter open to close, in world space, zero for things
+    /// that stay put
+    pub motion: Vec3,
+    /// the same in the space of the object itself, before instances move it
+    pub object_motion: Vec3,
+    /// the kinds of rays the object that was hit is seen by
+    pub visibility: Visibility,
+    /// IDs of the object and its material for the ID passes, 0 when they aren't rendered
+    /// and the object isn't named by a `LightLink`
+    pub object_id: u32,
+    pub material_id: u32,
+    /// the objects the light that was hit lights, none for every object
+    pub light_link: Option<Arc<LightLink>>,
 }
 
 impl HitInfo {
@@ -17,10 +57,10 @@ impl HitInfo {
         ray: &Ray,
         point: Vec3,
         geometric_normal: Vec3,
-        dist: f64,
+        dist: Float,
         mat: MatPtr,
-        u: f64,
-        v: f64,
+        u: Float,
+        v: Float,
     ) -> HitInfo {
         let front_face = ray.direction().dot(geometric_normal) < 0.0;
         let geometric_normal = if front_face {
@@ -46,12 +86,112 @@ impl HitInfo {
             point,
             geometric_normal,
             shading_normal,
+            context: ShadingContext::default(),
             dist,
             front_face,
             mat,
             u,
             v,
+            footprint: None,
+            error: rounding_error(ray.origin().abs() + point.abs()),
+            epsilon: 0.0,
+            terminator_point: None,
+            tangent: Vec3::ZERO,
+            vertex_color: None,
+            motion: Vec3::ZERO,
+            object_motion: Vec3::ZERO,
+            visibility: Visibility::ALL,
+            object_id: 0,
+            material_id: 0,
+            light_link: None,
+        }
+    }
+
+    /// work out the footprint if `ray` has differentials (Igehy's ray differentials, the way
+    /// pbrt does it): the neighbouring rays are intersected with the tangent plane at the hit,
+    /// and the offsets to those points are projected onto the derivatives of the point with
+    /// respect to u and v, which `tangents` only works out when they're needed
+    pub fn compute_differential(&mut self, ray: &Ray, tangents: impl FnOnce() -> (Vec3, Vec3)) {
+        let Some(diff) = ray.differential() else {
+            return;
+        };
+        let (dpdu, dpdv) = tangents();
+        let n = self.geometric_normal;
+        let d = n.dot(self.point);
+        let tx = (d - n.dot(diff.rx_origin)) / n.dot(diff.rx_direction);
+        let ty = (d - n.dot(diff.ry_origin)) / n.dot(diff.ry_direction);
+        if !tx.is_finite() || !ty.is_finite() {
+            return;
         }
+        let dpdx = diff.rx_origin + tx * diff.rx_direction - self.point;
+        let dpdy = diff.ry_origin + ty * diff.ry_direction - self.point;
+
+        // least squares fit of dpdx = dpdu * dudx + dpdv * dvdx, and the same for y
+        let (a00, a01, a11) = (dpdu.dot(dpdu), dpdu.dot(dpdv), dpdv.dot(dpdv));
+        let det = a00 * a11 - a01 * a01;
+        let solve = |dp: Vec3| {
+            if det.abs() < 1e-12 {
+                return (0.0, 0.0);
+            }
+            let (b0, b1) = (dpdu.dot(dp), dpdv.dot(dp));
+            ((a11 * b0 - a01 * b1) / det, (a00 * b1 - a01 * b0) / det)
+        };
+        let (dudx, dvdx) = solve(dpdx);
+        let (dudy, dvdy) = solve(dpdy);
+
+        self.footprint = Some(Box::new(Footprint {
+            dpdx,
+            dpdy,
+            dudx,
+            dudy,
+            dvdx,
+            dvdy,
+        }));
+    }
+
+    /// where a ray leaving the surface in `dir` should start, pushed off the surface on the
+    /// side `dir` is going to just past the rounding error, so it works at any scene scale
+    pub fn spawn_origin(&self, dir: Vec3) -> Vec3 {
+        let side = dir.dot(self.geometric_normal);
+        let point = match self.terminator_point {
+            Some(point) if side > 0.0 => point,
+            _ => self.point,
+        };
+        let normal = side.signum() * self.geometric_normal;
+        offset_origin(point, self.error, normal) + self.epsilon * normal
+    }
+
+    /// how far `spawn_origin` moves rays off the surface, at most
+    pub fn offset(&self) -> Float {
+        self.geometric_normal.abs().dot(self.error) + self.epsilon
+    }
+
+    /// a ray leaving the surface in `dir`, see `spawn_origin`
+    pub fn spawn_ray(&self, dir: Vec3, time: Float) -> Ray {
+        Ray::new(self.spawn_origin(dir), dir, time)
+    }
+
+    /// local shading space around the geometric normal, which is its +z axis
+    pub fn geometric_frame(&self) -> &Frame {
+        self.context.geometric_frame(self.geometric_normal)
+    }
+
+    /// local shading space around the shading normal, which is its +z axis
+    pub fn shading_frame(&self) -> &Frame {
+        self.context.shading_frame(self.shading_normal)
+    }
+
+    /// value of a material's texture at this hit, looked up once and then reused.
+    /// filtered over the pixel's footprint when there is one
+    pub fn texture<T: TextureValue>(&self, texture: &Arc<dyn Texture<T>>) -> T {
+        self.context.texture(texture, || texture.value_at_hit(self))
+    }
+
+    /// change the normals, e.g. when transforming the hit, keeping the frames in sync
+    pub fn set_normals(&mut self, geometric_normal: Vec3, shading_normal: Vec3) {
+        self.geometric_normal = geometric_normal;
+        self.shading_normal = shading_normal;
+        self.context = ShadingContext::default();
     }
 }
 
@@ -2,11 +2,11 @@ use std::sync::Arc;
 
 use crate::{
     interval::Interval,
-    ray::Ray,
-    vec3::{Mat4, Quat, Vec3},
+    ray::{rounding_error, Ray},
+    vec3::{Float, Mat4, Quat, Vec3},
 };
 
-use super::{HitInfo, Hittable, AABB};
+use super::{HitInfo, Hittable, LightSample, OrientedBox, SceneReport, SurfaceSample, AABB};
 
 // rotate then translate
 pub struct Instance {
@@ -14,10 +14,12 @@ pub struct Instance {
     bbox: AABB,
     rotation: Quat,
     transform: Mat4,
+    /// how far it's moved from shutter open to close, see `with_motion`
+    motion: Vec3,
 }
 
 impl Instance {
-    pub fn new(object: Arc<dyn Hittable>, axis: Vec3, angle: f64, translation: Vec3) -> Instance {
+    pub fn new(object: Arc<dyn Hittable>, axis: Vec3, angle: Float, translation: Vec3) -> Instance {
         let rotation = Quat::from_axis_angle(axis, angle);
         let transform = Mat4::from_rotation_translation(rotation, translation);
         let bbox = object.bounding_box().transform(transform);
@@ -26,51 +28,162 @@ impl Instance {
             bbox,
             rotation,
             transform,
+            motion: Vec3::ZERO,
         }
     }
+
+    /// move it on a straight line to `translation2` at shutter close, like a moving sphere.
+    /// the rotation stays the same
+    pub fn with_motion(mut self, translation2: Vec3) -> Instance {
+        self.motion = translation2 - self.transform.w_axis.truncate();
+        let close = self.transform_at(1.0);
+        self.bbox = self.bbox.union(self.object.bounding_box().transform(close));
+        self
+    }
+
+    /// from the object's space to world space at `time` of the shutter
+    fn transform_at(&self, time: Float) -> Mat4 {
+        if self.motion == Vec3::ZERO {
+            return self.transform;
+        }
+        Mat4::from_translation(self.motion * time) * self.transform
+    }
+
+    /// from the object's space to world space
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    /// the box around the object in its own space, before it's rotated and moved
+    pub fn object_bounding_box(&self) -> AABB {
+        self.object.bounding_box()
+    }
+
+    pub fn object_to_world(&self, point: Vec3) -> Vec3 {
+        self.transform.transform_point3(point)
+    }
+
+    pub fn world_to_object(&self, point: Vec3) -> Vec3 {
+        self.transform.inverse().transform_point3(point)
+    }
 }
 
 impl Hittable for Instance {
     fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
         // translate ray to local coords
-        let local_origin = self.transform.inverse().transform_point3(ray.origin());
-        let local_dir = self.transform.inverse().transform_vector3(ray.direction());
-        let local_ray = Ray::new(local_origin, local_dir, ray.time());
+        let transform = self.transform_at(ray.time());
+        let inverse = transform.inverse();
+        let local_origin = inverse.transform_point3(ray.origin());
+        let local_dir = inverse.transform_vector3(ray.direction());
+        let mut local_ray = Ray::new(local_origin, local_dir, ray.time());
+        if let Some(diff) = ray.differential() {
+            let to_local = |origin, dir| {
+                Ray::new(
+                    inverse.transform_point3(origin),
+                    inverse.transform_vector3(dir),
+                    ray.time(),
+                )
+            };
+            local_ray = local_ray.with_differential(
+                &to_local(diff.rx_origin, diff.rx_direction),
+                &to_local(diff.ry_origin, diff.ry_direction),
+            );
+        }
 
         // ray collision
-        let info = self.object.intersects(&local_ray, ray_t)?;
+        let mut info = self.object.intersects(&local_ray, ray_t)?;
 
-        // transform hit collision back to world coordinates
-        let world_point = self.transform.transform_point3(info.point);
+        // transform hit collision back to world coordinates. the error the point had in the
+        // object's space turns with it, and moving it adds the error of the world coordinates
+        info.point = transform.transform_point3(info.point);
+        info.error = Vec3::splat(info.error.length())
+            + rounding_error(ray.origin().abs() + info.point.abs());
+        if let Some(footprint) = info.footprint.as_mut() {
+            footprint.dpdx = transform.transform_vector3(footprint.dpdx);
+            footprint.dpdy = transform.transform_vector3(footprint.dpdy);
+        }
+        info.terminator_point = info
+            .terminator_point
+            .map(|point| transform.transform_point3(point));
+        info.tangent = self.rotation * info.tangent;
+        // the object's own motion turns with it, and the instance's moves it along on top
+        info.motion = self.rotation * info.motion + self.motion;
         let normal_mat = Mat4::from_quat(self.rotation).inverse().transpose();
-        let world_normal = normal_mat
-            .transform_vector3(info.geometric_normal)
-            .normalize();
-        Some(HitInfo {
-            point: world_point,
-            geometric_normal: world_normal,
-            ..info
-        })
+        let to_world = |normal: Vec3| normal_mat.transform_vector3(normal).normalize();
+        info.set_normals(
+            to_world(info.geometric_normal),
+            to_world(info.shading_normal),
+        );
+        Some(info)
     }
 
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
 
+    fn oriented_bounding_box(&self) -> OrientedBox {
+        // boxes of instances of instances are turned along, instead of growing at each level
+        let inner = self.object.oriented_bounding_box();
+        OrientedBox {
+            center: self.transform.transform_point3(inner.center),
+            axes: inner.axes.map(|axis| self.rotation * axis),
+            half_extent: inner.half_extent,
+        }
+    }
+
+    fn validate(&self, report: &mut SceneReport) {
+        if !self.transform.is_finite() || !self.rotation.is_normalized() {
+            report.problem(format!(
+                "instance moved to {}: rotated about a zero or NaN axis",
+                self.transform.w_axis.truncate()
+            ));
+        }
+        self.object.validate(report)
+    }
+
     fn material(&self) -> Option<&dyn crate::bsdf::BxDFMaterial> {
         self.object.material()
     }
 
-    fn sample(&self, origin: Vec3, time: f64) -> Option<Vec3> {
-        let local_origin = self.transform.inverse().transform_point3(origin);
-        let local_dir = self.object.sample(local_origin, time);
-        let world_dir = local_dir.map(|dir| self.transform.transform_vector3(dir));
-        world_dir
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        // rotations and translations keep areas, so the pdf stays the same
+        let transform = self.transform_at(time);
+        let local_origin = transform.inverse().transform_point3(origin);
+        let sample = self.object.sample_light(local_origin, time)?;
+        Some(LightSample {
+            point: transform.transform_point3(sample.point),
+            normal: self.rotation * sample.normal,
+            ..sample
+        })
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        let inverse = self.transform_at(time).inverse();
+        let (local_origin, local_point) = (
+            inverse.transform_point3(origin),
+            inverse.transform_point3(point),
+        );
+        self.object.light_pdf(local_origin, local_point, time)
+    }
+
+    fn illuminates(&self, object_id: u32) -> bool {
+        self.object.illuminates(object_id)
+    }
+
+    fn sample_surface(&self, time: Float) -> Option<SurfaceSample> {
+        // rotations and translations keep areas, so the pdf stays the same
+        let sample = self.object.sample_surface(time)?;
+        Some(SurfaceSample {
+            point: self.transform_at(time).transform_point3(sample.point),
+            normal: self.rotation * sample.normal,
+            ..sample
+        })
     }
 
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        let local_origin = self.transform.inverse().transform_point3(origin);
-        let local_dir = self.transform.inverse().transform_vector3(direction);
-        self.object.pdf(local_origin, local_dir, time)
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        // the GPU backend has no motion blur
+        self.motion == Vec3::ZERO
+            && scene.with_transform(self.transform, |scene| self.object.add_to_gpu_scene(scene))
     }
 }
@@ -1,5 +1,89 @@
-use crate::{hittable::Hittable, vec3::Vec3};
+use crate::{
+    bsdf::{sided::Sidedness, BxDFMaterial},
+    vec3::{consts::PI, Float, Vec3},
+};
 
+/// A point picked on a light to light a shading point with, see `Hittable::sample_light`
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// the pdf of picking the point, per unit area of the light
+    pub pdf_area: Float,
+    /// what the light emits from the point towards the shading point
+    pub emission: Vec3,
+}
+
+impl LightSample {
+    /// the emission of `material` at the point, none from the back of one-sided materials the
+    /// same as for rays that hit it there
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        origin: Vec3,
+        point: Vec3,
+        normal: Vec3,
+        u: Float,
+        v: Float,
+        pdf_area: Float,
+        material: &dyn BxDFMaterial,
+    ) -> LightSample {
+        let front = normal.dot(origin - point) >= 0.0;
+        let emission = if front || material.sidedness() == Sidedness::Shaded {
+            material.emitted(u, v, point)
+        } else {
+            Vec3::ZERO
+        };
+        LightSample {
+            point,
+            normal,
+            pdf_area,
+            emission,
+        }
+    }
+
+    /// the pdf per unit solid angle as seen from `origin`
+    pub fn pdf_solid_angle(&self, origin: Vec3) -> Float {
+        area_to_solid_angle(self.pdf_area, origin, self.point, self.normal)
+    }
+}
+
+/// turn a pdf per unit area at `point` into one per unit solid angle from `origin`
+pub fn area_to_solid_angle(pdf_area: Float, origin: Vec3, point: Vec3, normal: Vec3) -> Float {
+    let to_light = point - origin;
+    let dist_sq = to_light.length_squared();
+    let cos = normal.dot(to_light).abs() / dist_sq.sqrt();
+    if cos <= 0.0 {
+        return 0.0;
+    }
+    pdf_area * dist_sq / cos
+}
+
+/// The light arriving at a point from a `DeltaLight`
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaLightSample {
+    /// where the light comes from, the end of the shadow ray
+    pub point: Vec3,
+    /// normalized, from the lit point towards the light
+    pub direction: Vec3,
+    pub radiance: Vec3,
+}
+
+/// A light that shines from a single point (or a single direction), which no BSDF sampled ray
+/// can ever hit and so has no pdf to weigh with MIS. it stays out of `World::lights`; the
+/// integrators add its light at every bounce with a shadow ray instead, see
+/// `World::delta_lighting`
+pub trait DeltaLight: Send + Sync {
+    /// the light arriving at `origin`, before the BSDF and the shadow ray. None if it doesn't
+    /// reach it
+    fn sample_li(&self, origin: Vec3) -> Option<DeltaLightSample>;
+
+    /// whether the light lights the object with `object_id`, see `LightLink`
+    fn illuminates(&self, _object_id: u32) -> bool {
+        true
+    }
+}
+
+/// A point shining the same in every direction, with `power` the total power it emits
 #[derive(Debug, Clone, Copy)]
 pub struct PointLight {
     pub position: Vec3,
@@ -10,33 +94,55 @@ impl PointLight {
     pub fn new(position: Vec3, power: Vec3) -> PointLight {
         PointLight { position, power }
     }
-}
 
-impl Hittable for PointLight {
-    fn intersects(
-        &self,
-        _ray: &crate::ray::Ray,
-        _ray_t: crate::interval::Interval,
-    ) -> Option<crate::hittable::HitInfo> {
-        None
+    /// the power per unit solid angle
+    pub fn intensity(&self) -> Vec3 {
+        self.power / (4.0 * PI)
     }
+}
 
-    fn bounding_box(&self) -> crate::hittable::AABB {
-        crate::hittable::AABB::default()
+impl DeltaLight for PointLight {
+    fn sample_li(&self, origin: Vec3) -> Option<DeltaLightSample> {
+        let to_light = self.position - origin;
+        let dist_sq = to_light.length_squared();
+        if dist_sq <= 0.0 {
+            return None;
+        }
+        Some(DeltaLightSample {
+            point: self.position,
+            direction: to_light / dist_sq.sqrt(),
+            radiance: self.intensity() / dist_sq,
+        })
     }
+}
 
-    fn material(&self) -> Option<&dyn crate::bsdf::BxDFMaterial> {
-        None
-    }
+/// Light coming from a single direction from infinitely far away, like the sun's, with
+/// `irradiance` the light it puts on a surface facing it
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    /// normalized, towards the light
+    pub direction: Vec3,
+    pub irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    /// how far the shadow rays go towards the light, further than any scene is wide
+    const DISTANCE: Float = 1e5;
 
-    fn sample(&self, origin: Vec3, _time: f64) -> Option<Vec3> {
-        Some((self.position - origin).normalize())
+    pub fn new(direction: Vec3, irradiance: Vec3) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.normalize(),
+            irradiance,
+        }
     }
+}
 
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        let _ = time;
-        let _ = direction;
-        let _ = origin;
-        todo!()
+impl DeltaLight for DirectionalLight {
+    fn sample_li(&self, origin: Vec3) -> Option<DeltaLightSample> {
+        Some(DeltaLightSample {
+            point: origin + self.direction * Self::DISTANCE,
+            direction: self.direction,
+            radiance: self.irradiance,
+        })
     }
 }
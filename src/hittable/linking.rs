@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use crate::{
+    bsdf::BxDFMaterial,
+    interval::Interval,
+    ray::Ray,
+    vec3::{Float, Vec3},
+};
+
+use super::{
+    DeltaLight, DeltaLightSample, HitInfo, Hittable, LightSample, OrientedBox, SceneReport,
+    SurfaceSample, AABB,
+};
+
+/// Which objects a light lights, by the object IDs on their hits (see `Tagged`), for cheating
+/// the lighting of one object without touching the rest. objects without an ID are 0
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LightLink {
+    /// only these objects, or all of them when None
+    pub include: Option<Vec<u32>>,
+    /// never these objects, even when they're included
+    pub exclude: Vec<u32>,
+}
+
+impl LightLink {
+    pub fn lights(&self, object_id: u32) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|include| include.contains(&object_id));
+        included && !self.exclude.contains(&object_id)
+    }
+}
+
+/// Wraps a light to only light the objects its `LightLink` allows. the others get none of its
+/// light, neither from the shadow rays towards it nor from bounces that run into it, though
+/// the camera and reflections of the objects it does light still see it
+pub struct Linked {
+    object: Arc<dyn Hittable>,
+    link: Arc<LightLink>,
+}
+
+impl Linked {
+    pub fn new(object: Arc<dyn Hittable>, link: LightLink) -> Linked {
+        Linked {
+            object,
+            link: Arc::new(link),
+        }
+    }
+}
+
+impl Hittable for Linked {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        let mut info = self.object.intersects(ray, ray_t)?;
+        info.light_link = Some(self.link.clone());
+        Some(info)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.object.bounding_box()
+    }
+
+    fn oriented_bounding_box(&self) -> OrientedBox {
+        self.object.oriented_bounding_box()
+    }
+
+    fn validate(&self, report: &mut SceneReport) {
+        self.object.validate(report)
+    }
+
+    fn material(&self) -> Option<&dyn BxDFMaterial> {
+        self.object.material()
+    }
+
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        self.object.sample_light(origin, time)
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        self.object.light_pdf(origin, point, time)
+    }
+
+    fn illuminates(&self, object_id: u32) -> bool {
+        self.link.lights(object_id) && self.object.illuminates(object_id)
+    }
+
+    fn sample_surface(&self, time: Float) -> Option<SurfaceSample> {
+        self.object.sample_surface(time)
+    }
+
+    /// the GPU backend lights every object the same, so it can't leave any out
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        *self.link == LightLink::default() && self.object.add_to_gpu_scene(scene)
+    }
+}
+
+/// Wraps a delta light to only light the objects its `LightLink` allows, like `Linked`
+pub struct LinkedDelta {
+    light: Arc<dyn DeltaLight>,
+    link: LightLink,
+}
+
+impl LinkedDelta {
+    pub fn new(light: Arc<dyn DeltaLight>, link: LightLink) -> LinkedDelta {
+        LinkedDelta { light, link }
+    }
+}
+
+impl DeltaLight for LinkedDelta {
+    fn sample_li(&self, origin: Vec3) -> Option<DeltaLightSample> {
+        self.light.sample_li(origin)
+    }
+
+    fn illuminates(&self, object_id: u32) -> bool {
+        self.link.lights(object_id) && self.light.illuminates(object_id)
+    }
+}
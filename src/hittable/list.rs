@@ -1,37 +1,110 @@
 use std::sync::Arc;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
-use crate::{interval::Interval, vec3::Vec3};
+use crate::{
+    interval::Interval,
+    sampler::rng,
+    vec3::{Float, Vec3},
+};
 
-use super::{BVHNode, Hittable, AABB, BVH};
+use super::{
+    BVHNode, BvhLayout, BvhStats, CompactBVH, Hittable, LightSample, SceneReport, SurfaceSample,
+    AABB, BVH,
+};
 
 pub struct HittableList {
     objects: Vec<Arc<dyn Hittable>>,
+    /// objects without bounds, like infinite planes, which are kept out of the BVH and the
+    /// bounding box and tested on every ray
+    unbounded: Vec<Arc<dyn Hittable>>,
     bbox: AABB,
-    bvh: Option<BVHNode>,
+    bvh: Option<Bvh>,
+}
+
+enum Bvh {
+    Tree(BVHNode),
+    Compact(CompactBVH),
 }
 
 impl HittableList {
     pub fn new() -> HittableList {
         HittableList {
             objects: vec![],
+            unbounded: vec![],
             bbox: AABB::default(),
             bvh: None,
         }
     }
 
     pub fn add<T: Hittable + 'static>(&mut self, object: T) {
-        self.bbox = AABB::union(self.bbox, object.bounding_box());
-        self.objects.push(Arc::new(object));
+        self.add_shared(Arc::new(object));
+    }
+
+    pub fn add_shared(&mut self, object: Arc<dyn Hittable>) {
+        let bbox = object.bounding_box();
+        if bbox.is_unbounded() {
+            self.unbounded.push(object);
+            return;
+        }
+        self.bbox = AABB::union(self.bbox, bbox);
+        self.objects.push(object);
     }
 
     pub fn build_bvh(&mut self) {
-        if !self.objects.is_empty() {
-            self.bvh = Some(BVH::build(self.objects.clone()));
+        self.build_bvh_with(BvhLayout::Tree);
+    }
+
+    /// `sample_light` with the light picked by `u` in [0, 1) instead of at random, so samples
+    /// with `u` spread over strata spread over the lights
+    pub fn sample_light_with(&self, u: Float, origin: Vec3, time: Float) -> Option<LightSample> {
+        self.sample_light_for(u, origin, time, None)
+    }
+
+    /// `sample_light_with` for a point on the object with `object_id`, none when the light `u`
+    /// picks doesn't light it, see `LightLink`. the lights keep their odds either way, so
+    /// leaving one out doesn't make the others brighter
+    pub fn sample_light_for(
+        &self,
+        u: Float,
+        origin: Vec3,
+        time: Float,
+        object_id: Option<u32>,
+    ) -> Option<LightSample> {
+        if self.is_empty() {
+            return None;
+        }
+        let i = ((u * self.objects.len() as Float) as usize).min(self.objects.len() - 1);
+        if object_id.is_some_and(|id| !self.objects[i].illuminates(id)) {
+            return None;
+        }
+        let sample = self.objects[i].sample_light(origin, time)?;
+        Some(LightSample {
+            pdf_area: sample.pdf_area / self.objects.len() as Float,
+            ..sample
+        })
+    }
+
+    /// `build_bvh` with a smaller layout, for lists of millions of hittables
+    pub fn build_bvh_with(&mut self, layout: BvhLayout) {
+        if self.objects.is_empty() {
+            return;
+        }
+        self.bvh = Some(match layout {
+            BvhLayout::Tree => Bvh::Tree(BVH::build(self.objects.clone())),
+            _ => Bvh::Compact(CompactBVH::build(&self.objects, layout)),
+        });
+    }
+
+    /// sizes of the BVH, if it's been built
+    pub fn bvh_stats(&self) -> Option<BvhStats> {
+        match self.bvh.as_ref()? {
+            Bvh::Tree(node) => Some(BvhStats::of_tree(node)),
+            Bvh::Compact(bvh) => Some(bvh.stats()),
         }
     }
 
+    /// the `i`th of the objects with bounds, which are the ones that can be lights
     pub fn get(&self, i: usize) -> &Arc<dyn Hittable> {
         &self.objects[i]
     }
@@ -43,6 +116,16 @@ impl HittableList {
     pub fn is_empty(&self) -> bool {
         self.objects.is_empty()
     }
+
+    /// the objects without bounds, see `AABB::is_unbounded`
+    pub fn unbounded(&self) -> &[Arc<dyn Hittable>] {
+        &self.unbounded
+    }
+
+    /// every object, the ones without bounds last
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Hittable>> {
+        self.objects.iter().chain(&self.unbounded)
+    }
 }
 
 impl Hittable for HittableList {
@@ -51,8 +134,11 @@ impl Hittable for HittableList {
         ray: &crate::ray::Ray,
         ray_t: crate::interval::Interval,
     ) -> Option<super::HitInfo> {
-        if let Some(ref bvh) = self.bvh {
-            bvh.intersects(ray, ray_t)
+        let mut hit_info = if let Some(ref bvh) = self.bvh {
+            match bvh {
+                Bvh::Tree(node) => node.intersects(ray, ray_t),
+                Bvh::Compact(bvh) => bvh.intersects(ray, ray_t),
+            }
         } else {
             let mut closest_hit = ray_t.max;
             let mut hit_info = None;
@@ -64,35 +150,71 @@ impl Hittable for HittableList {
             }
 
             hit_info
+        };
+        for obj in &self.unbounded {
+            let closest_hit = hit_info.as_ref().map_or(ray_t.max, |info| info.dist);
+            if let Some(info) = obj.intersects(ray, Interval::new(ray_t.min, closest_hit)) {
+                hit_info = Some(info);
+            }
         }
+        hit_info
     }
 
+    /// the box around the objects with bounds, the unbounded ones are left out
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
 
+    fn validate(&self, report: &mut SceneReport) {
+        for object in self.iter() {
+            object.validate(report);
+        }
+        if let Some(stats) = self.bvh_stats() {
+            report.bvh_depth = report.bvh_depth.max(stats.depth);
+            report.geometry_bytes += stats.bytes;
+        }
+    }
+
     fn material(&self) -> Option<&dyn crate::bsdf::BxDFMaterial> {
         None
     }
 
-    fn sample(&self, origin: Vec3, time: f64) -> Option<crate::vec3::Vec3> {
+    /// one of the objects picked uniformly, then a point on it
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        self.sample_light_with(rng().gen(), origin, time)
+    }
+
+    /// only the objects whose boxes hold `point` are asked, a point is on very few of them
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        self.objects
+            .iter()
+            .filter(|obj| obj.bounding_box().contains(point))
+            .map(|obj| obj.light_pdf(origin, point, time))
+            .sum::<Float>()
+            / self.objects.len() as Float
+    }
+
+    fn sample_surface(&self, time: Float) -> Option<SurfaceSample> {
         if self.is_empty() {
             return None;
         }
-        let i = thread_rng().gen_range(0..self.objects.len());
-        self.objects[i].sample(origin, time)
+        let i = rng().gen_range(0..self.objects.len());
+        let sample = self.objects[i].sample_surface(time)?;
+        Some(SurfaceSample {
+            pdf: sample.pdf / self.objects.len() as Float,
+            ..sample
+        })
     }
 
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        if self.objects.is_empty() {
-            0.0
-        } else {
-            self.objects
-                .iter()
-                .map(|obj| obj.pdf(origin, direction, time))
-                .sum::<f64>()
-                / self.objects.len() as f64
-        }
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        self.objects
+            .iter()
+            .chain(&self.unbounded)
+            .all(|obj| obj.add_to_gpu_scene(scene))
     }
 }
 
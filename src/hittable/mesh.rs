@@ -1,53 +1,150 @@
 // src/hittable/mesh.rs
+use rand::Rng;
 use std::sync::Arc;
 use tobj::{LoadError, Mesh};
 
 use crate::bsdf::{BxDFMaterial, MatPtr};
-use crate::hittable::{HitInfo, Hittable, AABB};
-use crate::{interval::Interval, ray::Ray, vec3::Vec3};
+use crate::distribution::Distribution1D;
+use crate::hittable::{HitInfo, Hittable, LightSample, SceneReport, AABB};
+use crate::sampler::rng;
+use crate::{
+    interval::Interval,
+    ray::Ray,
+    ray_stats,
+    vec3::{Float, Vec3, VectorExt},
+};
 
-use super::HittableList;
+use super::{
+    BvhLayout, BvhStats, HittableList, MeshBuffers, MeshImportOptions, Triangle4,
+    TriangleIntersection,
+};
+
+/// Per mesh settings for the problems low poly meshes have
+#[derive(Debug, Clone, Copy)]
+pub struct MeshOptions {
+    /// ignore hits on the back of triangles, going by their winding
+    pub backface_culling: bool,
+    /// start reflected rays from the smooth surface the vertex normals describe instead of the
+    /// flat triangle (Hanika's "Hacking the shadow terminator"), only for meshes with normals
+    pub shadow_terminator_fix: bool,
+    /// how much further rays leaving the mesh start from it than its rounding error needs,
+    /// for meshes whose normals don't quite match their triangles
+    pub epsilon: Float,
+    /// layout of the BVH over the triangles, a compact one for meshes of millions of triangles
+    pub bvh: BvhLayout,
+    /// how many triangles are tested at once, and in what precision
+    pub intersection: TriangleIntersection,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            backface_culling: false,
+            shadow_terminator_fix: false,
+            epsilon: 0.0,
+            bvh: BvhLayout::Tree,
+            intersection: TriangleIntersection::default(),
+        }
+    }
+}
 
 // i'm pretty sure this approach is bad for cache locality but i cant be bothered to implement
 // a flat array like what TOBJ is doing (and make it work with my BVH)
+#[derive(Clone)]
 pub struct Triangle {
     vertices: [Vec3; 3],
     normals: Option<[Vec3; 3]>,
-    uvs: Option<[(f64, f64); 3]>,
+    uvs: Option<[(Float, Float); 3]>,
+    colors: Option<[Vec3; 3]>,
     material: MatPtr,
     bbox: AABB,
+    options: MeshOptions,
 }
 
 impl Triangle {
     fn new(
-        v0: Vec3,
-        v1: Vec3,
-        v2: Vec3,
+        vertices: [Vec3; 3],
         normals: Option<[Vec3; 3]>,
-        uvs: Option<[(f64, f64); 3]>,
+        uvs: Option<[(Float, Float); 3]>,
+        colors: Option<[Vec3; 3]>,
         material: MatPtr,
+        options: MeshOptions,
     ) -> Self {
+        let [v0, v1, v2] = vertices;
         let min_v = v0.min(v1).min(v2);
         let max_v = v0.max(v1).max(v2);
         let bbox = AABB::new(min_v, max_v);
         Self {
-            vertices: [v0, v1, v2],
+            vertices,
             normals,
             uvs,
+            colors,
             material,
             bbox,
+            options,
         }
     }
 
-    pub fn area(&self) -> f64 {
+    pub fn area(&self) -> Float {
         let edge1 = self.vertices[1] - self.vertices[0];
         let edge2 = self.vertices[2] - self.vertices[0];
         0.5 * edge1.cross(edge2).length()
     }
-}
 
-impl Hittable for Triangle {
-    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+    pub(super) fn vertices(&self) -> [Vec3; 3] {
+        self.vertices
+    }
+
+    pub(super) fn options(&self) -> MeshOptions {
+        self.options
+    }
+
+    /// the texture coordinates at barycentrics `u` and `v`, which are their own without uvs
+    fn texcoords(&self, u: Float, v: Float) -> (Float, Float) {
+        let Some([uv0, uv1, uv2]) = self.uvs else {
+            return (u, v);
+        };
+        let w = 1.0 - u - v;
+        (
+            uv0.0 * w + uv1.0 * u + uv2.0 * v,
+            uv0.1 * w + uv1.1 * u + uv2.1 * v,
+        )
+    }
+
+    /// how much light the triangle gives off, its area times the luminance of its emission
+    /// averaged over its centroid and corners
+    fn power(&self) -> Float {
+        let corners = [(1.0 / 3.0, 1.0 / 3.0), (0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+        let luminance = corners
+            .iter()
+            .map(|&(u, v)| {
+                let [v0, v1, v2] = self.vertices;
+                let point = v0 * (1.0 - u - v) + v1 * u + v2 * v;
+                let (tu, tv) = self.texcoords(u, v);
+                self.material.emitted(tu, tv, point).luminance().max(0.0)
+            })
+            .sum::<Float>()
+            / corners.len() as Float;
+        self.area() * luminance
+    }
+
+    /// the barycentrics of `point`, if it's on the triangle
+    fn barycentrics(&self, point: Vec3) -> Option<(Float, Float)> {
+        let [v0, v1, v2] = self.vertices;
+        let (edge1, edge2) = (v1 - v0, v2 - v0);
+        let n = edge1.cross(edge2);
+        let p = point - v0;
+        let n_len_sq = n.length_squared();
+        if n_len_sq == 0.0 || p.dot(n).abs() > 1e-3 * n_len_sq.sqrt() {
+            return None;
+        }
+        let u = p.cross(edge2).dot(n) / n_len_sq;
+        let v = edge1.cross(p).dot(n) / n_len_sq;
+        (u >= 0.0 && v >= 0.0 && u + v <= 1.0).then_some((u, v))
+    }
+
+    /// distance and barycentrics of the hit, Möller–Trumbore
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<(Float, Float, Float)> {
         let v0 = self.vertices[0];
         let v1 = self.vertices[1];
         let v2 = self.vertices[2];
@@ -60,6 +157,9 @@ impl Hittable for Triangle {
         if a.abs() < 1e-8 {
             return None; // Ray parallel to triangle
         }
+        if self.options.backface_culling && a < 0.0 {
+            return None; // the ray is hitting the back, a is -dot(direction, face normal)
+        }
 
         let f = 1.0 / a;
         let s = ray.origin() - v0;
@@ -80,6 +180,14 @@ impl Hittable for Triangle {
         if !ray_t.contains(t) {
             return None;
         }
+        Some((t, u, v))
+    }
+
+    /// the hit at distance `t` and barycentrics `u`, `v`
+    pub(super) fn hit_info(&self, ray: &Ray, t: Float, u: Float, v: Float) -> HitInfo {
+        let [v0, v1, v2] = self.vertices;
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
 
         let w = 1.0 - u - v;
         let normal = if let Some(normals) = self.normals {
@@ -88,27 +196,61 @@ impl Hittable for Triangle {
             edge1.cross(edge2).normalize()
         };
 
-        let (u, v) = if let Some(uvs) = self.uvs {
-            let uv0 = uvs[0];
-            let uv1 = uvs[1];
-            let uv2 = uvs[2];
-            (
-                uv0.0 * w + uv1.0 * u + uv2.0 * v,
-                uv0.1 * w + uv1.1 * u + uv2.1 * v,
-            )
-        } else {
-            (u, v)
+        let point = ray.at(t);
+        let terminator_point = match self.normals {
+            Some(normals) if self.options.shadow_terminator_fix => {
+                // push the point out of each vertex's tangent plane it is below, and blend
+                let side = if normal.dot(ray.direction()) < 0.0 {
+                    1.0
+                } else {
+                    -1.0
+                };
+                let lift = |vertex: Vec3, normal: Vec3| {
+                    let normal = side * normal.normalize();
+                    let offset = point - vertex;
+                    point - offset.dot(normal).min(0.0) * normal
+                };
+                Some(lift(v0, normals[0]) * w + lift(v1, normals[1]) * u + lift(v2, normals[2]) * v)
+            }
+            _ => None,
         };
 
-        Some(HitInfo::new(
-            ray,
-            ray.at(t),
-            normal,
-            t,
-            self.material.clone(),
-            u,
-            v,
-        ))
+        let vertex_color = self
+            .colors
+            .map(|colors| colors[0] * w + colors[1] * u + colors[2] * v);
+        let (u, v) = self.texcoords(u, v);
+
+        let mut info = HitInfo::new(ray, point, normal, t, self.material.clone(), u, v);
+        info.epsilon = self.options.epsilon;
+        info.terminator_point = terminator_point;
+        info.vertex_color = vertex_color;
+        // without uvs the barycentrics are the texture coordinates, and the edges their derivatives
+        info.compute_differential(ray, || match self.uvs {
+            Some(uvs) => {
+                let (du02, dv02) = (uvs[0].0 - uvs[2].0, uvs[0].1 - uvs[2].1);
+                let (du12, dv12) = (uvs[1].0 - uvs[2].0, uvs[1].1 - uvs[2].1);
+                let (dp02, dp12) = (v0 - v2, v1 - v2);
+                let det = du02 * dv12 - dv02 * du12;
+                if det.abs() < 1e-12 {
+                    (Vec3::ZERO, Vec3::ZERO)
+                } else {
+                    (
+                        (dv12 * dp02 - dv02 * dp12) / det,
+                        (du02 * dp12 - du12 * dp02) / det,
+                    )
+                }
+            }
+            None => (edge1, edge2),
+        });
+        info
+    }
+}
+
+impl Hittable for Triangle {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        ray_stats::count_primitives(1);
+        let (t, u, v) = self.hit(ray, ray_t)?;
+        Some(self.hit_info(ray, t, u, v))
     }
 
     fn bounding_box(&self) -> AABB {
@@ -119,59 +261,166 @@ impl Hittable for Triangle {
         Some(self.material.as_ref())
     }
 
-    fn sample(&self,origin: Vec3, _time: f64) -> Option<Vec3> {
-        let u: f64 = rand::random();
-        let v: f64 = rand::random();
-        let w = 1.0 - u - v;
-        let point = self.vertices[0] * w + self.vertices[1] * u + self.vertices[2] * v;
-        let dir = (point - origin).normalize();
-        Some(dir)
-    }
-
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        let ray = Ray::new(origin, direction, time);
-        if let Some(hit) = self.intersects(&ray, Interval::new(0.0, f64::INFINITY)) {
-            let area = self.area();
-            let dist = hit.dist;
-            let cos_theta = direction.dot(hit.shading_normal).abs();
-            dist * dist / (cos_theta * area)
-        } else {
-            0.0
+    /// uniform by area
+    fn sample_light(&self, origin: Vec3, _time: Float) -> Option<LightSample> {
+        let r = rng().gen::<Float>().sqrt();
+        let s: Float = rng().gen();
+        let (u, v) = (r * (1.0 - s), r * s);
+        let [v0, v1, v2] = self.vertices;
+        let point = v0 * (1.0 - u - v) + v1 * u + v2 * v;
+        let normal = (v1 - v0).cross(v2 - v0).normalize();
+        let (tu, tv) = self.texcoords(u, v);
+        Some(LightSample::new(
+            origin,
+            point,
+            normal,
+            tu,
+            tv,
+            1.0 / self.area(),
+            self.material.as_ref(),
+        ))
+    }
+
+    fn light_pdf(&self, _origin: Vec3, point: Vec3, _time: Float) -> Float {
+        match self.barycentrics(point) {
+            Some(_) => 1.0 / self.area(),
+            None => 0.0,
         }
     }
+
+    /// degenerate triangles are only counted, a mesh can have millions of them
+    fn validate(&self, report: &mut SceneReport) {
+        report.triangles += 1;
+        let area = self.area();
+        if !(area > 0.0 && area.is_finite()) {
+            report.degenerate_triangles += 1;
+        }
+        report.add_primitive(Some(self.material.as_ref()), std::mem::size_of_val(self));
+    }
+
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        scene.add_triangle(self.vertices, self.material.as_ref())
+    }
 }
 
 pub struct TriangleMesh {
     triangles: HittableList,
+    /// the triangles again for meshes with an emissive material, to be sampled as a light
+    emitters: Option<MeshEmitters>,
+}
+
+/// The triangles of an emissive mesh with a table to pick them by how much light they give off
+struct MeshEmitters {
+    triangles: Vec<Triangle>,
+    distribution: Distribution1D,
+}
+
+impl MeshEmitters {
+    /// none if the mesh gives off no light at all
+    fn new(triangles: &[Triangle]) -> Option<MeshEmitters> {
+        let power: Vec<Float> = triangles.iter().map(Triangle::power).collect();
+        if !power.iter().any(|&p| p > 0.0) {
+            return None;
+        }
+        Some(MeshEmitters {
+            triangles: triangles.to_vec(),
+            distribution: Distribution1D::new(power),
+        })
+    }
 }
 
 impl TriangleMesh {
-    pub fn from_obj(scale: f64, mesh: &Mesh, material: Arc<dyn BxDFMaterial>) -> Result<Self, LoadError> {
-        // get vertices
-        let vertices: Vec<Vec3> = mesh
-            .positions
-            .chunks(3)
-            .map(|v| Vec3::new(v[0] as f64, v[1] as f64, v[2] as f64) * scale)
-            .collect();
-
-        // get normals
-        let normals: Vec<Vec3> = mesh
-            .normals
-            .chunks(3)
-            .map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))
-            .collect();
-
-        // get UVs
-        let uvs: Vec<(f64, f64)> = mesh
-            .texcoords
-            .chunks(2)
-            .map(|uv| (uv[0] as f64, uv[1] as f64))
-            .collect();
-
-        // let mut triangles: Vec<Triangle> = Vec::new();
-        let mut triangles = HittableList::new();
-        for chunk in mesh.indices.chunks(3) {
-            let [i0, i1, i2] = [chunk[0] as usize, chunk[1] as usize, chunk[2] as usize];
+    pub fn from_obj(
+        scale: Float,
+        mesh: &Mesh,
+        material: Arc<dyn BxDFMaterial>,
+    ) -> Result<Self, LoadError> {
+        Self::from_obj_with(scale, mesh, material, MeshOptions::default())
+    }
+
+    /// `from_obj` with culling, the terminator fix or a different epsilon
+    pub fn from_obj_with(
+        scale: Float,
+        mesh: &Mesh,
+        material: Arc<dyn BxDFMaterial>,
+        options: MeshOptions,
+    ) -> Result<Self, LoadError> {
+        Self::from_obj_with_options(mesh, material, &MeshImportOptions::scaled(scale), options)
+    }
+
+    /// `from_obj_with`, placed by `import` instead of only scaled
+    pub fn from_obj_with_options(
+        mesh: &Mesh,
+        material: Arc<dyn BxDFMaterial>,
+        import: &MeshImportOptions,
+        options: MeshOptions,
+    ) -> Result<Self, LoadError> {
+        let mut buffers = MeshBuffers::from_obj(1.0, mesh);
+        import.apply(&mut [&mut buffers]);
+        Ok(Self::from_mesh_buffers(&buffers, material, options))
+    }
+
+    /// build a mesh from vertex buffers, every 3 indices make a triangle.
+    /// normals and uvs are per vertex and can be left empty
+    pub fn from_buffers(
+        vertices: &[Vec3],
+        normals: &[Vec3],
+        uvs: &[(Float, Float)],
+        indices: &[usize],
+        material: MatPtr,
+    ) -> Self {
+        Self::from_buffers_with(
+            vertices,
+            normals,
+            uvs,
+            indices,
+            material,
+            MeshOptions::default(),
+        )
+    }
+
+    /// build a mesh from `MeshBuffers`, e.g. after displacing them, with their vertex colors
+    pub fn from_mesh_buffers(
+        buffers: &MeshBuffers,
+        material: MatPtr,
+        options: MeshOptions,
+    ) -> Self {
+        Self::build(
+            &buffers.vertices,
+            &buffers.normals,
+            &buffers.uvs,
+            &buffers.colors,
+            &buffers.indices,
+            material,
+            options,
+        )
+    }
+
+    /// `from_buffers` with culling, the terminator fix or a different epsilon
+    pub fn from_buffers_with(
+        vertices: &[Vec3],
+        normals: &[Vec3],
+        uvs: &[(Float, Float)],
+        indices: &[usize],
+        material: MatPtr,
+        options: MeshOptions,
+    ) -> Self {
+        Self::build(vertices, normals, uvs, &[], indices, material, options)
+    }
+
+    fn build(
+        vertices: &[Vec3],
+        normals: &[Vec3],
+        uvs: &[(Float, Float)],
+        colors: &[Vec3],
+        indices: &[usize],
+        material: MatPtr,
+        options: MeshOptions,
+    ) -> Self {
+        let mut mesh_triangles = Vec::with_capacity(indices.len() / 3);
+        for chunk in indices.chunks(3) {
+            let [i0, i1, i2] = [chunk[0], chunk[1], chunk[2]];
             let normals = if normals.is_empty() {
                 None
             } else {
@@ -182,18 +431,46 @@ impl TriangleMesh {
             } else {
                 Some([uvs[i0], uvs[i1], uvs[i2]])
             };
-            triangles.add(Triangle::new(
-                vertices[i0],
-                vertices[i1],
-                vertices[i2],
+            let colors = if colors.is_empty() {
+                None
+            } else {
+                Some([colors[i0], colors[i1], colors[i2]])
+            };
+            mesh_triangles.push(Triangle::new(
+                [vertices[i0], vertices[i1], vertices[i2]],
                 normals,
                 uvs,
+                colors,
                 material.clone(),
+                options,
             ));
         }
 
-        triangles.build_bvh();
-        Ok(Self { triangles })
+        let emitters = if material.is_emissive() {
+            MeshEmitters::new(&mesh_triangles)
+        } else {
+            None
+        };
+        let mut triangles = HittableList::new();
+        if options.intersection == TriangleIntersection::Scalar {
+            for triangle in mesh_triangles {
+                triangles.add(triangle);
+            }
+        } else {
+            for packet in Triangle4::packets(mesh_triangles, options.intersection) {
+                triangles.add(packet);
+            }
+        }
+
+        triangles.build_bvh_with(options.bvh);
+        Self {
+            triangles,
+            emitters,
+        }
+    }
+
+    pub fn bvh_stats(&self) -> Option<BvhStats> {
+        self.triangles.bvh_stats()
     }
 }
 
@@ -210,11 +487,53 @@ impl Hittable for TriangleMesh {
         None
     }
 
-    fn sample(&self, origin: Vec3, time: f64) -> Option<Vec3> {
-        self.triangles.sample(origin,time)
+    /// emissive meshes pick a triangle by its area times its emission, so the bright and big
+    /// parts of a neon sign get the samples, then a point on it uniformly
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        let Some(emitters) = &self.emitters else {
+            return self.triangles.sample_light(origin, time);
+        };
+        let (i, probability) = emitters.distribution.sample_discrete(rng().gen());
+        let sample = emitters.triangles[i].sample_light(origin, time)?;
+        Some(LightSample {
+            pdf_area: sample.pdf_area * probability,
+            ..sample
+        })
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        let Some(emitters) = &self.emitters else {
+            return self.triangles.light_pdf(origin, point, time);
+        };
+        if !self.bounding_box().contains(point) {
+            return 0.0;
+        }
+        emitters
+            .triangles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.bounding_box().contains(point))
+            .map(|(i, t)| t.light_pdf(origin, point, time) * emitters.distribution.probability(i))
+            .sum()
+    }
+
+    fn validate(&self, report: &mut SceneReport) {
+        let degenerate = report.degenerate_triangles;
+        self.triangles.validate(report);
+        if let Some(emitters) = &self.emitters {
+            report.geometry_bytes += emitters.triangles.len() * std::mem::size_of::<Triangle>();
+        }
+        let degenerate = report.degenerate_triangles - degenerate;
+        if degenerate > 0 {
+            report.problem(format!(
+                "mesh around {}: {degenerate} triangles with no area or NaN corners",
+                self.bounding_box().centroid()
+            ));
+        }
     }
 
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        self.triangles.pdf(origin, direction, time)
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        self.triangles.add_to_gpu_scene(scene)
     }
 }
@@ -0,0 +1,353 @@
+use std::{collections::HashMap, hash::Hash, path::Path};
+
+use tobj::{Material, Mesh};
+
+use crate::{
+    error::Error,
+    vec3::{Float, Quat, Vec3},
+};
+
+/// Vertex buffers of a triangle mesh before it is turned into triangles, for changing the
+/// geometry at load time. Every 3 indices make a triangle, normals, uvs and colors are per
+/// vertex and can be empty
+#[derive(Debug, Clone, Default)]
+pub struct MeshBuffers {
+    pub vertices: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(Float, Float)>,
+    /// colors baked into the vertices, as scanned meshes have them
+    pub colors: Vec<Vec3>,
+    pub indices: Vec<usize>,
+}
+
+/// One model of an OBJ file, or the whole of a PLY file
+#[derive(Debug, Clone)]
+pub struct ObjModel {
+    pub name: String,
+    pub buffers: MeshBuffers,
+    /// index of its material in the materials of the OBJ's MTL file
+    pub material: Option<usize>,
+}
+
+/// Where a mesh goes when it's loaded, baked into its vertices: it's moved so the center of its
+/// bounds is at the origin if `recenter`, shrunk to fit a unit box if `normalize_to_unit`,
+/// then scaled, rotated and translated, in that order. for assets modeled in other units or
+/// around some far away origin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshImportOptions {
+    pub scale: Float,
+    pub rotation: Quat,
+    pub translation: Vec3,
+    pub recenter: bool,
+    /// scale the longest side of the bounds to 1 before `scale`
+    pub normalize_to_unit: bool,
+    /// give models without normals smooth ones, so they don't look faceted
+    pub smooth_normals: bool,
+    /// with `smooth_normals`, edges where the faces meet at more than this many degrees stay
+    /// sharp. None smooths over every edge
+    pub crease_angle: Option<Float>,
+}
+
+impl Default for MeshImportOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            rotation: Quat::IDENTITY,
+            translation: Vec3::ZERO,
+            recenter: false,
+            normalize_to_unit: false,
+            smooth_normals: false,
+            crease_angle: None,
+        }
+    }
+}
+
+impl MeshImportOptions {
+    /// only scaled, the way meshes were always loaded
+    pub fn scaled(scale: Float) -> Self {
+        Self {
+            scale,
+            ..Default::default()
+        }
+    }
+
+    /// move every vertex and normal of `meshes` together, so the models of a file keep their
+    /// places relative to each other, and smooth the normals of those without any
+    pub fn apply(&self, meshes: &mut [&mut MeshBuffers]) {
+        let (min, max) = meshes
+            .iter()
+            .flat_map(|mesh| &mesh.vertices)
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            });
+        if min.cmpgt(max).any() {
+            return; // no vertices
+        }
+        let center = if self.recenter {
+            0.5 * (min + max)
+        } else {
+            Vec3::ZERO
+        };
+        let longest = (max - min).max_element();
+        let scale = match self.normalize_to_unit {
+            true if longest > 0.0 => self.scale / longest,
+            _ => self.scale,
+        };
+        for mesh in meshes {
+            for v in &mut mesh.vertices {
+                *v = self.rotation * ((*v - center) * scale) + self.translation;
+            }
+            for n in &mut mesh.normals {
+                *n = self.rotation * *n;
+            }
+            if self.smooth_normals && mesh.normals.is_empty() {
+                **mesh = mesh.with_smooth_normals(self.crease_angle);
+            }
+        }
+    }
+}
+
+impl MeshBuffers {
+    pub fn from_obj(scale: Float, mesh: &Mesh) -> MeshBuffers {
+        let vertices = mesh
+            .positions
+            .chunks(3)
+            .map(|v| Vec3::new(v[0] as Float, v[1] as Float, v[2] as Float) * scale)
+            .collect();
+        let normals = mesh
+            .normals
+            .chunks(3)
+            .map(|n| Vec3::new(n[0] as Float, n[1] as Float, n[2] as Float))
+            .collect();
+        let uvs = mesh
+            .texcoords
+            .chunks(2)
+            .map(|uv| (uv[0] as Float, uv[1] as Float))
+            .collect();
+        let colors = mesh
+            .vertex_color
+            .chunks(3)
+            .map(|c| Vec3::new(c[0] as Float, c[1] as Float, c[2] as Float))
+            .collect();
+        let indices = mesh.indices.iter().map(|&i| i as usize).collect();
+        MeshBuffers {
+            vertices,
+            normals,
+            uvs,
+            colors,
+            indices,
+        }
+    }
+
+    /// the models and materials of the mesh file at `path`, read as PLY for `.ply` files and
+    /// as OBJ otherwise, and placed by `import`. a PLY file is a single model without materials
+    pub fn load(
+        path: &str,
+        import: &MeshImportOptions,
+    ) -> Result<(Vec<ObjModel>, Vec<Material>), Error> {
+        let is_ply = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ply"));
+        let (mut models, materials) = if is_ply {
+            let model = ObjModel {
+                name: Path::new(path)
+                    .file_stem()
+                    .map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+                buffers: Self::load_ply(path, 1.0)?,
+                material: None,
+            };
+            (vec![model], vec![])
+        } else {
+            Self::load_obj(path, 1.0)?
+        };
+        let mut buffers: Vec<_> = models.iter_mut().map(|model| &mut model.buffers).collect();
+        import.apply(&mut buffers);
+        Ok((models, materials))
+    }
+
+    /// every model in the OBJ file at `path`, and the materials of its MTL file. a broken MTL
+    /// file is logged and leaves the models without materials
+    pub fn load_obj(path: &str, scale: Float) -> Result<(Vec<ObjModel>, Vec<Material>), Error> {
+        let (models, materials) = tobj::load_obj(path, &tobj::OFFLINE_RENDERING_LOAD_OPTIONS)
+            .map_err(|source| Error::Mesh {
+                path: path.to_string(),
+                source,
+            })?;
+        let models = models
+            .iter()
+            .map(|model| ObjModel {
+                name: model.name.clone(),
+                buffers: Self::from_obj(scale, &model.mesh),
+                material: model.mesh.material_id,
+            })
+            .collect();
+        let materials = materials.unwrap_or_else(|err| {
+            log::warn!("failed to load the materials of {path}: {err}");
+            vec![]
+        });
+        Ok((models, materials))
+    }
+
+    /// a cube `size` wide around the origin, to stand in for meshes that failed to load
+    pub fn placeholder(size: Float) -> MeshBuffers {
+        let vertices = (0..8)
+            .map(|i| {
+                let corner = Vec3::new((i & 1) as Float, (i >> 1 & 1) as Float, (i >> 2) as Float);
+                (corner - 0.5) * size
+            })
+            .collect();
+        #[rustfmt::skip]
+        let indices = vec![
+            0, 2, 1, 1, 2, 3, // -z
+            4, 5, 6, 5, 7, 6, // +z
+            0, 1, 4, 1, 5, 4, // -y
+            2, 6, 3, 3, 6, 7, // +y
+            0, 4, 2, 2, 4, 6, // -x
+            1, 3, 5, 3, 7, 5, // +x
+        ];
+        MeshBuffers {
+            vertices,
+            indices,
+            ..Default::default()
+        }
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.indices.chunks(3).map(|t| [t[0], t[1], t[2]])
+    }
+
+    /// split every triangle into 4 at the middle of its edges, without moving anything.
+    /// triangles sharing an edge share its new vertex, so there are no cracks
+    pub fn subdivide(&self) -> MeshBuffers {
+        self.split_edges(|a, b| 0.5 * (self.vertices[a] + self.vertices[b]))
+    }
+
+    /// split every triangle into 4 with a new vertex on each edge at `edge_point(a, b)`,
+    /// normals, uvs and colors are interpolated to the middle of the edge
+    pub(crate) fn split_edges(
+        &self,
+        mut edge_point: impl FnMut(usize, usize) -> Vec3,
+    ) -> MeshBuffers {
+        let mut mesh = self.clone();
+        mesh.indices.clear();
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |mesh: &mut MeshBuffers, a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                mesh.vertices.push(edge_point(a, b));
+                if !self.normals.is_empty() {
+                    let n = self.normals[a] + self.normals[b];
+                    mesh.normals.push(n.normalize_or_zero());
+                }
+                if !self.uvs.is_empty() {
+                    let (uv_a, uv_b) = (self.uvs[a], self.uvs[b]);
+                    mesh.uvs
+                        .push((0.5 * (uv_a.0 + uv_b.0), 0.5 * (uv_a.1 + uv_b.1)));
+                }
+                if !self.colors.is_empty() {
+                    mesh.colors.push(0.5 * (self.colors[a] + self.colors[b]));
+                }
+                mesh.vertices.len() - 1
+            })
+        };
+
+        for [a, b, c] in self.triangles() {
+            let ab = midpoint(&mut mesh, a, b);
+            let bc = midpoint(&mut mesh, b, c);
+            let ca = midpoint(&mut mesh, c, a);
+            mesh.indices
+                .extend([a, ab, ca, ab, b, bc, ca, bc, c, ab, bc, ca]);
+        }
+        mesh
+    }
+
+    /// for every vertex, the index of the first vertex at the same place, so meshes split along
+    /// uv seams can be treated as connected
+    pub(crate) fn welded(&self) -> Vec<usize> {
+        let mut first = HashMap::new();
+        self.vertices
+            .iter()
+            .enumerate()
+            .map(|(i, p)| *first.entry(position_key(*p)).or_insert(i))
+            .collect()
+    }
+
+    /// smooth per vertex normals, averaging the normals of the triangles around each vertex
+    /// weighted by their area. vertices at the same place share a normal even if they are
+    /// separate in the buffers, e.g. along uv seams, so moving them along it opens no cracks
+    pub fn smooth_normals(&self) -> Vec<Vec3> {
+        let mut sums: HashMap<_, Vec3> = HashMap::new();
+        for [a, b, c] in self.triangles() {
+            let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+            // the cross product is twice the area long
+            let normal = (v1 - v0).cross(v2 - v0);
+            for v in [v0, v1, v2] {
+                *sums.entry(position_key(v)).or_default() += normal;
+            }
+        }
+        self.vertices
+            .iter()
+            .map(|&v| {
+                sums.get(&position_key(v))
+                    .copied()
+                    .unwrap_or_default()
+                    .normalize_or_zero()
+            })
+            .collect()
+    }
+
+    /// the mesh with area weighted smooth normals at every vertex. where triangles meet at more
+    /// than `crease_angle` degrees they don't share normals, and the vertices along the edge
+    /// are split into one for each side, so hard edges stay hard
+    pub fn with_smooth_normals(&self, crease_angle: Option<Float>) -> MeshBuffers {
+        let Some(crease_angle) = crease_angle else {
+            return MeshBuffers {
+                normals: self.smooth_normals(),
+                ..self.clone()
+            };
+        };
+        let cos_crease = crease_angle.to_radians().cos();
+        let v = &self.vertices;
+        // the cross products are twice the areas long
+        let faces: Vec<Vec3> = self
+            .triangles()
+            .map(|[a, b, c]| (v[b] - v[a]).cross(v[c] - v[a]))
+            .collect();
+        let mut around: HashMap<_, Vec<usize>> = HashMap::new();
+        for (face, corners) in self.triangles().enumerate() {
+            for i in corners {
+                around.entry(position_key(v[i])).or_default().push(face);
+            }
+        }
+
+        let mut mesh = MeshBuffers::default();
+        let mut split = HashMap::new();
+        for (face, corners) in self.triangles().enumerate() {
+            let own = faces[face].normalize_or_zero();
+            for i in corners {
+                let normal = around[&position_key(v[i])]
+                    .iter()
+                    .map(|&other| faces[other])
+                    .filter(|n| n.normalize_or_zero().dot(own) >= cos_crease)
+                    .sum::<Vec3>()
+                    .normalize_or_zero();
+                let index = *split.entry((i, position_key(normal))).or_insert_with(|| {
+                    mesh.vertices.push(v[i]);
+                    mesh.normals.push(normal);
+                    if !self.uvs.is_empty() {
+                        mesh.uvs.push(self.uvs[i]);
+                    }
+                    if !self.colors.is_empty() {
+                        mesh.colors.push(self.colors[i]);
+                    }
+                    mesh.vertices.len() - 1
+                });
+                mesh.indices.push(index);
+            }
+        }
+        mesh
+    }
+}
+
+fn position_key(p: Vec3) -> impl Hash + Eq {
+    p.to_array().map(Float::to_bits)
+}
@@ -1,5 +1,5 @@
-use crate::bsdf::BxDFMaterial;
-use crate::vec3::Vec3;
+use crate::bsdf::{BxDFMaterial, MatPtr};
+use crate::vec3::{Float, Vec3};
 use crate::{interval::Interval, ray::Ray};
 
 pub mod aabb;
@@ -11,12 +11,21 @@ pub use self::cuboid::*;
 pub mod bvh;
 pub use self::bvh::*;
 
+pub mod compact_bvh;
+pub use self::compact_bvh::*;
+
 pub mod hit_info;
 pub use self::hit_info::*;
 
+pub mod shading_context;
+pub use self::shading_context::*;
+
 pub mod quad;
 pub use self::quad::*;
 
+pub mod plane;
+pub use self::plane::*;
+
 pub mod sphere;
 pub use self::sphere::*;
 
@@ -35,14 +44,92 @@ pub use self::list::*;
 pub mod mesh;
 pub use self::mesh::*;
 
+pub mod triangle4;
+pub use self::triangle4::*;
+
+pub mod mesh_buffers;
+pub use self::mesh_buffers::*;
+
+pub mod ply;
+
+pub mod displacement;
+pub use self::displacement::*;
+
+pub mod subdivision;
+pub use self::subdivision::*;
+
+pub mod curve;
+pub use self::curve::*;
+
+pub mod groom;
+pub use self::groom::*;
+
+pub mod visibility;
+pub use self::visibility::*;
+
+pub mod tagged;
+pub use self::tagged::*;
+
+pub mod linking;
+pub use self::linking::*;
+
+pub mod validation;
+pub use self::validation::*;
+
+/// A point picked on a surface, with the pdf measured per unit area
+#[derive(Clone)]
+pub struct SurfaceSample {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub u: Float,
+    pub v: Float,
+    pub pdf: Float,
+    pub material: MatPtr,
+}
+
 pub trait Hittable: Send + Sync {
     fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo>;
     fn bounding_box(&self) -> AABB;
     fn material(&self) -> Option<&dyn BxDFMaterial>;
 
-    /// sample a point P on the surface of the hittable
-    fn sample(&self, origin: Vec3, time: f64) -> Option<Vec3>;
+    /// pick a point on the surface to light `origin` with, together with its normal, pdf and
+    /// emission. None for shapes that can't be lights
+    fn sample_light(&self, _origin: Vec3, _time: Float) -> Option<LightSample> {
+        None
+    }
+
+    /// the pdf per unit area of `sample_light` picking `point` from `origin`, 0 if `point` isn't
+    /// on the surface. integrators convert it to solid angle themselves
+    fn light_pdf(&self, _origin: Vec3, _point: Vec3, _time: Float) -> Float {
+        0.0
+    }
+
+    /// whether the light lights the object with `object_id`, see `LightLink`
+    fn illuminates(&self, _object_id: u32) -> bool {
+        true
+    }
+
+    /// sample a point uniformly by area, for emitting light from the surface.
+    /// shapes that don't support it can't emit photons
+    fn sample_surface(&self, _time: Float) -> Option<SurfaceSample> {
+        None
+    }
+
+    /// the tightest box around the hittable in world space, `bounding_box` unless it's rotated
+    fn oriented_bounding_box(&self) -> OrientedBox {
+        self.bounding_box().into()
+    }
+
+    /// count what the hittable is made of into `report` and add what's wrong with it, see
+    /// `World::validate`. one primitive with its material unless it says otherwise
+    fn validate(&self, report: &mut SceneReport) {
+        report.add_primitive(self.material(), std::mem::size_of_val(self));
+    }
 
-    /// pdf of point P on surface
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64;
+    /// add the hittable to the GPU backend's scene, false if it has a shape or material that
+    /// backend can't draw
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, _scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        false
+    }
 }
@@ -0,0 +1,123 @@
+use crate::{
+    bsdf::MatPtr,
+    interval::Interval,
+    ray::Ray,
+    ray_stats,
+    vec3::{Float, Vec2, Vec3},
+};
+
+use super::{hit_info::HitInfo, Hittable, AABB};
+
+/// An infinite plane through `point` facing `normal`, for grounds and walls that go on to the
+/// horizon without a huge sphere or quad. it has no bounds, so lists test it on every ray next
+/// to their BVH instead of putting it in. `with_extent` cuts it down to a square
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    /// directions of the texture's u and v along the plane
+    tangent: Vec3,
+    bitangent: Vec3,
+    /// half the side of the square it's cut down to, around `point`
+    extent: Option<Float>,
+    uv_scale: Vec2,
+    material: MatPtr,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: MatPtr) -> Plane {
+        let normal = normal.normalize();
+        // u along x and v away from the camera on a floor, u along x and v up on a wall
+        let tangent = if normal.y.abs() < 0.999 {
+            Vec3::Y.cross(normal).normalize()
+        } else {
+            Vec3::X
+        };
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent: normal.cross(tangent),
+            extent: None,
+            uv_scale: Vec2::ONE,
+            material,
+        }
+    }
+
+    /// the plane cut down to a square `2 * extent` wide around its point
+    pub fn with_extent(self, extent: Float) -> Plane {
+        Plane {
+            extent: Some(extent),
+            ..self
+        }
+    }
+
+    /// texture coordinates per unit of length along the plane, 1 by default so a texture
+    /// repeats every unit and the uv checker has squares as big as those of the point checker
+    pub fn with_uv_scale(self, uv_scale: Vec2) -> Plane {
+        Plane { uv_scale, ..self }
+    }
+
+    /// where `point` is on the plane, in units along the tangent and bitangent from its point
+    fn local(&self, point: Vec3) -> Vec2 {
+        let offset = point - self.point;
+        Vec2::new(offset.dot(self.tangent), offset.dot(self.bitangent))
+    }
+}
+
+impl Hittable for Plane {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        ray_stats::count_primitives(1);
+        let nd = self.normal.dot(ray.direction());
+        if nd == 0.0 {
+            return None;
+        }
+        // measured from the plane's own point, which stays accurate far from the origin
+        let t = (self.point - ray.origin()).dot(self.normal) / nd;
+        if !ray_t.contains(t) {
+            return None;
+        }
+
+        // put the point back on the plane, exactly for axis aligned ones
+        let point = ray.at(t);
+        let point = point - self.normal * (point - self.point).dot(self.normal);
+        let local = self.local(point);
+        if let Some(extent) = self.extent {
+            if local.abs().max_element() > extent {
+                return None;
+            }
+        }
+
+        let uv = local * self.uv_scale;
+        let mut info = HitInfo::new(
+            ray,
+            point,
+            self.normal,
+            t,
+            self.material.clone(),
+            uv.x,
+            uv.y,
+        );
+        info.compute_differential(ray, || {
+            (
+                self.tangent / self.uv_scale.x,
+                self.bitangent / self.uv_scale.y,
+            )
+        });
+        Some(info)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        match self.extent {
+            Some(extent) => {
+                let (u, v) = (self.tangent * extent, self.bitangent * extent);
+                AABB::new(self.point - u - v, self.point + u + v)
+                    .union(AABB::new(self.point - u + v, self.point + u - v))
+            }
+            None => AABB::new(Vec3::NEG_INFINITY, Vec3::INFINITY),
+        }
+    }
+
+    fn material(&self) -> Option<&dyn crate::bsdf::BxDFMaterial> {
+        Some(self.material.as_ref())
+    }
+}
@@ -0,0 +1,281 @@
+//! A loader for PLY files, the format scanners and photogrammetry tools write meshes with
+//! baked vertex colors in. ASCII and binary files are read, with positions, normals,
+//! texture coordinates and colors per vertex, and faces of any number of vertices, which are
+//! split into fans of triangles. other elements and properties are skipped.
+
+use std::str::SplitAsciiWhitespace;
+
+use crate::{
+    error::Error,
+    vec3::{Float, Vec3},
+};
+
+use super::MeshBuffers;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// what 1 is for colors stored in this type, 255 for bytes
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.0,
+            Scalar::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    /// a list with its length stored as the first type and its items as the second
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// the data after the header, read one number at a time
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or("the file ends early")?;
+                token
+                    .parse()
+                    .map_err(|_| format!("`{token}` is not a number"))
+            }
+            Body::Binary { bytes, big_endian } => {
+                if bytes.len() < scalar.size() {
+                    return Err("the file ends early".to_string());
+                }
+                let (value, rest) = bytes.split_at(scalar.size());
+                *bytes = rest;
+                let mut raw = [0; 8];
+                raw[..value.len()].copy_from_slice(value);
+                if *big_endian {
+                    raw[..value.len()].reverse();
+                }
+                Ok(match scalar {
+                    Scalar::I8 => i8::from_le_bytes([raw[0]]) as f64,
+                    Scalar::U8 => raw[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(raw),
+                })
+            }
+        }
+    }
+}
+
+impl MeshBuffers {
+    /// the mesh in the PLY file at `path`, with its vertex colors in `colors` if it has them
+    pub fn load_ply(path: &str, scale: Float) -> Result<MeshBuffers, Error> {
+        let error = |message: String| Error::Ply {
+            path: path.to_string(),
+            message,
+        };
+        let bytes = std::fs::read(path).map_err(|err| error(err.to_string()))?;
+        parse(&bytes, scale).map_err(error)
+    }
+}
+
+/// the header's elements, the format and where the data starts
+fn parse_header(bytes: &[u8]) -> Result<(Vec<Element>, Format, usize), String> {
+    let mut elements: Vec<Element> = vec![];
+    let mut format = None;
+    let mut lines = bytes.split(|&b| b == b'\n');
+    let first = lines.next().unwrap_or_default();
+    if first.trim_ascii() != b"ply" {
+        return Err("not a PLY file".to_string());
+    }
+    let mut offset = first.len() + 1;
+    for line in lines {
+        offset += line.len() + 1;
+        let line = std::str::from_utf8(line).map_err(|_| "the header is not text")?;
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => {
+                let format = format.ok_or("the header has no format")?;
+                return Ok((elements, format, offset));
+            }
+            ["format", name, _] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    other => return Err(format!("unknown format `{other}`")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("bad element count `{count}`"))?,
+                properties: vec![],
+            }),
+            ["property", rest @ ..] => {
+                let scalar = |name: &str| {
+                    Scalar::from_name(name).ok_or_else(|| format!("unknown type `{name}`"))
+                };
+                let property = match rest {
+                    ["list", count, item, name] => {
+                        Property::List(name.to_string(), scalar(count)?, scalar(item)?)
+                    }
+                    [ty, name] => Property::Scalar(name.to_string(), scalar(ty)?),
+                    _ => return Err(format!("bad property `{line}`")),
+                };
+                elements
+                    .last_mut()
+                    .ok_or("a property before any element")?
+                    .properties
+                    .push(property);
+            }
+            _ => {} // comments and obj_info
+        }
+    }
+    Err("the header doesn't end".to_string())
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn parse(bytes: &[u8], scale: Float) -> Result<MeshBuffers, String> {
+    let (elements, format, offset) = parse_header(bytes)?;
+    let data = &bytes[offset.min(bytes.len())..];
+    let mut body = match format {
+        Format::Ascii => Body::Ascii(
+            std::str::from_utf8(data)
+                .map_err(|_| "the data is not text")?
+                .split_ascii_whitespace(),
+        ),
+        Format::LittleEndian | Format::BigEndian => Body::Binary {
+            bytes: data,
+            big_endian: format == Format::BigEndian,
+        },
+    };
+
+    let mut mesh = MeshBuffers::default();
+    for element in &elements {
+        for _ in 0..element.count {
+            // the values of one vertex by property name, the vertices of one face
+            let mut values = vec![];
+            let mut face = vec![];
+            for property in &element.properties {
+                match property {
+                    Property::Scalar(name, scalar) => {
+                        values.push((name.as_str(), *scalar, body.read(*scalar)?));
+                    }
+                    Property::List(name, count, item) => {
+                        let count = body.read(*count)? as usize;
+                        for _ in 0..count {
+                            let index = body.read(*item)?;
+                            if name == "vertex_indices" || name == "vertex_index" {
+                                face.push(index as usize);
+                            }
+                        }
+                    }
+                }
+            }
+            match element.name.as_str() {
+                "vertex" => add_vertex(&mut mesh, &values, scale),
+                "face" => {
+                    for i in 1..face.len().saturating_sub(1) {
+                        mesh.indices.extend([face[0], face[i], face[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let count = mesh.vertices.len();
+    if let Some(&i) = mesh.indices.iter().find(|&&i| i >= count) {
+        return Err(format!("a face uses vertex {i} of {count}"));
+    }
+    for (name, len) in [
+        ("normals", mesh.normals.len()),
+        ("uvs", mesh.uvs.len()),
+        ("colors", mesh.colors.len()),
+    ] {
+        if len != 0 && len != count {
+            return Err(format!("only some vertices have {name}"));
+        }
+    }
+    Ok(mesh)
+}
+
+/// `values` are the name, type and value of each property of the vertex
+#[allow(clippy::unnecessary_cast)]
+fn add_vertex(mesh: &mut MeshBuffers, values: &[(&str, Scalar, f64)], scale: Float) {
+    let find = |names: &[&str]| values.iter().find(|(name, ..)| names.contains(name));
+    let value = |names: &[&str]| find(names).map(|&(_, _, value)| value as Float);
+    // colors stored as integers go from 0 to their largest value
+    let color =
+        |names: &[&str]| find(names).map(|&(_, ty, value)| (value / ty.color_scale()) as Float);
+    let (x, y, z) = (value(&["x"]), value(&["y"]), value(&["z"]));
+    mesh.vertices
+        .push(Vec3::new(x.unwrap_or(0.0), y.unwrap_or(0.0), z.unwrap_or(0.0)) * scale);
+    if let (Some(x), Some(y), Some(z)) = (value(&["nx"]), value(&["ny"]), value(&["nz"])) {
+        mesh.normals.push(Vec3::new(x, y, z));
+    }
+    let u = value(&["u", "s", "texture_u", "texture_s"]);
+    let v = value(&["v", "t", "texture_v", "texture_t"]);
+    if let (Some(u), Some(v)) = (u, v) {
+        mesh.uvs.push((u, v));
+    }
+    let r = color(&["red", "r", "diffuse_red"]);
+    let g = color(&["green", "g", "diffuse_green"]);
+    let b = color(&["blue", "b", "diffuse_blue"]);
+    if let (Some(r), Some(g), Some(b)) = (r, g, b) {
+        mesh.colors.push(Vec3::new(r, g, b));
+    }
+}
@@ -1,6 +1,18 @@
-use crate::{bsdf::MatPtr, interval::Interval, ray::Ray, vec3::Vec3};
+use std::sync::OnceLock;
 
-use super::{hit_info::HitInfo, Hittable, AABB};
+use rand::Rng;
+
+use crate::{
+    bsdf::MatPtr,
+    distribution::Distribution2D,
+    interval::Interval,
+    ray::Ray,
+    ray_stats,
+    sampler::rng,
+    vec3::{consts::PI, Float, Vec2, Vec3, VectorExt},
+};
+
+use super::{hit_info::HitInfo, Hittable, LightSample, SceneReport, SurfaceSample, AABB};
 
 pub struct Quad {
     q: Vec3, // origin
@@ -8,9 +20,57 @@ pub struct Quad {
     v: Vec3, // side 2
     w: Vec3,
     normal: Vec3,
-    d: f64,
+    d: Float,
     bbox: AABB,
     material: MatPtr,
+    uv: QuadUv,
+    /// where a textured light emits, over the quad's coordinates along its sides. built on the
+    /// first light sample, none when the emission is the same all over
+    emission: OnceLock<Option<Distribution2D>>,
+}
+
+/// cells along each side of the table a textured light is sampled from
+const EMISSION_GRID: usize = 128;
+
+/// How a quad's coordinates along its sides, each from 0 to 1, turn into texture coordinates:
+/// scaled by `scale`, turned `rotation` radians counterclockwise and moved by `origin`. the
+/// default keeps them as they are, a `scale` of 3 tiles the texture three times across
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuadUv {
+    pub origin: Vec2,
+    pub scale: Vec2,
+    pub rotation: Float,
+}
+
+impl Default for QuadUv {
+    fn default() -> Self {
+        Self {
+            origin: Vec2::ZERO,
+            scale: Vec2::ONE,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl QuadUv {
+    /// the texture coordinates at `alpha` along the first side and `beta` along the second
+    pub fn apply(&self, alpha: Float, beta: Float) -> (Float, Float) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (x, y) = (alpha * self.scale.x, beta * self.scale.y);
+        (
+            self.origin.x + cos * x - sin * y,
+            self.origin.y + sin * x + cos * y,
+        )
+    }
+
+    /// how far the quad's coordinates move per unit of the texture's u, and of its v
+    fn inverse(&self) -> (Vec2, Vec2) {
+        let (sin, cos) = self.rotation.sin_cos();
+        (
+            Vec2::new(cos / self.scale.x, -sin / self.scale.y),
+            Vec2::new(sin / self.scale.x, cos / self.scale.y),
+        )
+    }
 }
 
 impl Quad {
@@ -19,10 +79,11 @@ impl Quad {
         let b2 = AABB::new(q + u, q + v);
         let bbox = b1.union(b2);
 
+        // a quad with parallel sides has no normal and is never hit, `validate` reports it
         let n = u.cross(v);
-        let normal = n.normalize();
+        let normal = n.normalize_or_zero();
         let d = normal.dot(q);
-        let w = n / n.length_squared();
+        let w = n / n.length_squared().max(Float::MIN_POSITIVE);
         Quad {
             q,
             u,
@@ -32,12 +93,164 @@ impl Quad {
             d,
             bbox,
             material,
+            uv: QuadUv::default(),
+            emission: OnceLock::new(),
+        }
+    }
+
+    /// the same quad with its texture coordinates laid out by `uv`
+    pub fn with_uv(self, uv: QuadUv) -> Quad {
+        Quad { uv, ..self }
+    }
+
+    fn area(&self) -> Float {
+        self.u.cross(self.v).length()
+    }
+
+    /// the coordinates of `point` along the sides, from 0 to 1 on the quad
+    fn coords(&self, point: Vec3) -> (Float, Float) {
+        let p = point - self.q;
+        (self.w.dot(p.cross(self.v)), self.w.dot(self.u.cross(p)))
+    }
+
+    /// the luminance of the emission over a grid of cells along the sides, for lights with an
+    /// emission texture to be sampled where they're brightest
+    #[allow(clippy::unnecessary_cast)]
+    fn emission_distribution(&self) -> Option<&Distribution2D> {
+        self.emission
+            .get_or_init(|| {
+                if !self.material.is_emissive() {
+                    return None;
+                }
+                let n = EMISSION_GRID;
+                let luminance: Vec<Float> = (0..n * n)
+                    .map(|i| {
+                        let alpha = ((i % n) as Float + 0.5) / n as Float;
+                        let beta = ((i / n) as Float + 0.5) / n as Float;
+                        let (u, v) = self.uv.apply(alpha, beta);
+                        let point = self.q + self.u * alpha + self.v * beta;
+                        self.material.emitted(u, v, point).luminance().max(0.0)
+                    })
+                    .collect();
+                let (min, max) = luminance
+                    .iter()
+                    .fold((Float::INFINITY, 0.0 as Float), |(min, max), &l| {
+                        (min.min(l), max.max(l))
+                    });
+                (max - min > 1e-3 * max).then(|| Distribution2D::new(&luminance, n, n))
+            })
+            .as_ref()
+    }
+
+    /// the pdf per unit area of sampling the spherical rectangle uniformly, at `point`
+    fn rect_pdf_area(&self, rect: &SphericalRect, origin: Vec3, point: Vec3) -> Float {
+        let to_point = point - origin;
+        let dist_sq = to_point.length_squared();
+        let cos = self.normal.dot(to_point).abs() / dist_sq.sqrt();
+        cos / (dist_sq * rect.solid_angle)
+    }
+
+    /// the quad as seen from `origin`, for sampling it by solid angle. none for parallelograms
+    /// that aren't rectangles, and for quads so small or so edge on that sampling their area
+    /// is just as good
+    fn spherical_rect(&self, origin: Vec3) -> Option<SphericalRect> {
+        let (u_len, v_len) = (self.u.length(), self.v.length());
+        if self.u.dot(self.v).abs() > 1e-6 * u_len * v_len {
+            return None;
+        }
+        let rect = SphericalRect::new(self.q, self.u / u_len, self.v / v_len, u_len, v_len, origin);
+        (rect.solid_angle > 1e-4).then_some(rect)
+    }
+}
+
+/// A rectangle projected onto the unit sphere around a point, sampled uniformly by solid angle
+/// as in Ureña et al., "An Area-Preserving Parametrization for Spherical Rectangles" (2013)
+struct SphericalRect {
+    origin: Vec3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    /// corners in the local frame of `x`, `y` and `z`, with the rectangle at `z0` below the origin
+    x0: Float,
+    x1: Float,
+    y0: Float,
+    y1: Float,
+    z0: Float,
+    b0: Float,
+    b1: Float,
+    k: Float,
+    solid_angle: Float,
+}
+
+impl SphericalRect {
+    fn new(corner: Vec3, x: Vec3, y: Vec3, width: Float, height: Float, origin: Vec3) -> Self {
+        let mut z = x.cross(y);
+        let d = corner - origin;
+        let (x0, y0) = (d.dot(x), d.dot(y));
+        let mut z0 = d.dot(z);
+        if z0 > 0.0 {
+            z0 = -z0;
+            z = -z;
+        }
+        let (x1, y1) = (x0 + width, y0 + height);
+
+        let v00 = Vec3::new(x0, y0, z0);
+        let v01 = Vec3::new(x0, y1, z0);
+        let v10 = Vec3::new(x1, y0, z0);
+        let v11 = Vec3::new(x1, y1, z0);
+        // normals of the planes through the origin and each edge
+        let n0 = v00.cross(v10).normalize_or_zero();
+        let n1 = v10.cross(v11).normalize_or_zero();
+        let n2 = v11.cross(v01).normalize_or_zero();
+        let n3 = v01.cross(v00).normalize_or_zero();
+        // the angles between them
+        let angle = |a: Vec3, b: Vec3| (-a.dot(b)).clamp(-1.0, 1.0).acos();
+        let (g0, g1) = (angle(n0, n1), angle(n1, n2));
+        let (g2, g3) = (angle(n2, n3), angle(n3, n0));
+        let k = 2.0 * PI - g2 - g3;
+
+        SphericalRect {
+            origin,
+            x,
+            y,
+            z,
+            x0,
+            x1,
+            y0,
+            y1,
+            z0,
+            b0: n0.z,
+            b1: n2.z,
+            k,
+            solid_angle: g0 + g1 - k,
         }
     }
+
+    /// the point on the rectangle for the random numbers `u` and `v`
+    fn sample(&self, u: Float, v: Float) -> Vec3 {
+        // pick the x of the point so the area to the left of it is u of the solid angle
+        let au = u * self.solid_angle + self.k;
+        let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+        let cu = (1.0 / (fu * fu + self.b0 * self.b0).sqrt()).copysign(fu);
+        let cu = cu.clamp(-1.0, 1.0);
+        let xu = (-(cu * self.z0) / (1.0 - cu * cu).max(1e-12).sqrt()).clamp(self.x0, self.x1);
+        // then its y, uniform in solid angle along the column
+        let d = (xu * xu + self.z0 * self.z0).sqrt();
+        let h0 = self.y0 / (d * d + self.y0 * self.y0).sqrt();
+        let h1 = self.y1 / (d * d + self.y1 * self.y1).sqrt();
+        let hv = h0 + v * (h1 - h0);
+        let yv = if hv * hv < 1.0 - 1e-6 {
+            (hv * d) / (1.0 - hv * hv).sqrt()
+        } else {
+            self.y1
+        };
+        self.origin + self.x * xu + self.y * yv + self.z * self.z0
+    }
 }
 
 impl Hittable for Quad {
     fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        ray_stats::count_primitives(1);
         let eps = 1e-8;
         let nd = self.normal.dot(ray.direction());
 
@@ -58,42 +271,106 @@ impl Hittable for Quad {
             return None;
         }
 
-        Some(HitInfo::new(
-            ray,
-            ray.at(t),
-            self.normal,
-            t,
-            self.material.clone(),
-            alpha,
-            beta,
-        ))
+        let (u, v) = self.uv.apply(alpha, beta);
+        let mut info = HitInfo::new(ray, ray.at(t), self.normal, t, self.material.clone(), u, v);
+        info.compute_differential(ray, || {
+            let (du, dv) = self.uv.inverse();
+            (self.u * du.x + self.v * du.y, self.u * dv.x + self.v * dv.y)
+        });
+        Some(info)
     }
 
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
 
+    fn validate(&self, report: &mut SceneReport) {
+        let area = self.u.cross(self.v).length();
+        if !(area > 0.0 && area.is_finite() && self.q.is_finite()) {
+            report.problem(format!(
+                "quad at {} with sides {} and {} has no area",
+                self.q, self.u, self.v
+            ));
+        }
+        report.add_primitive(Some(self.material.as_ref()), std::mem::size_of_val(self));
+    }
+
     fn material(&self) -> Option<&dyn crate::bsdf::BxDFMaterial> {
         Some(self.material.as_ref())
     }
 
-    fn sample(&self, origin: Vec3, _time: f64) -> Option<Vec3> {
-        let u: f64 = rand::random();
-        let v: f64 = rand::random();
-        let point = self.q + self.u * u + self.v * v;
-        let dir = (point - origin).normalize();
-        Some(dir)
+    /// lights with an emission texture are sampled in proportion to how bright it is, so hot
+    /// spots like the picture on a screen get the samples. otherwise rectangles are sampled by
+    /// solid angle, which matters for big lights close to the shading point, other quads by area
+    fn sample_light(&self, origin: Vec3, _time: Float) -> Option<LightSample> {
+        let u: Float = rng().gen();
+        let v: Float = rng().gen();
+        let emission = self.emission_distribution();
+        let (point, pdf_area) = match (emission, self.spherical_rect(origin)) {
+            (Some(emission), _) => {
+                let (p, pdf) = emission.sample(Vec2::new(u, v));
+                (self.q + self.u * p.x + self.v * p.y, pdf / self.area())
+            }
+            (None, Some(rect)) => {
+                let point = rect.sample(u, v);
+                (point, self.rect_pdf_area(&rect, origin, point))
+            }
+            (None, None) => (self.q + self.u * u + self.v * v, 1.0 / self.area()),
+        };
+        let (alpha, beta) = self.coords(point);
+        let (u, v) = self.uv.apply(alpha, beta);
+        Some(LightSample::new(
+            origin,
+            point,
+            self.normal,
+            u,
+            v,
+            pdf_area,
+            self.material.as_ref(),
+        ))
     }
 
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        let ray = Ray::new(origin, direction, time);
-        if let Some(hit) = self.intersects(&ray, Interval::new(0.0, f64::INFINITY)) {
-            let area = self.u.cross(self.v).length();
-            let dist = hit.dist;
-            let cos_theta = ray.direction().dot(hit.shading_normal).abs();
-            (dist * dist) / (cos_theta * area)
-        } else {
-            0.0
+    fn light_pdf(&self, origin: Vec3, point: Vec3, _time: Float) -> Float {
+        let (u, v) = self.coords(point);
+        // relative to the coordinates the point was worked out from, so lights far from the
+        // origin or in scenes of tiny units are found on their plane alike
+        let tolerance = 1e-6 * (origin.abs() + point.abs()).max_element();
+        let on_plane = (self.normal.dot(point) - self.d).abs() <= tolerance;
+        if !on_plane || !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return 0.0;
+        }
+        if let Some(emission) = self.emission_distribution() {
+            return emission.pdf(Vec2::new(u, v)) / self.area();
         }
+        match self.spherical_rect(origin) {
+            Some(rect) => self.rect_pdf_area(&rect, origin, point),
+            None => 1.0 / self.area(),
+        }
+    }
+
+    fn sample_surface(&self, _time: Float) -> Option<SurfaceSample> {
+        let alpha: Float = rng().gen();
+        let beta: Float = rng().gen();
+        let (u, v) = self.uv.apply(alpha, beta);
+        Some(SurfaceSample {
+            point: self.q + self.u * alpha + self.v * beta,
+            normal: self.normal,
+            u,
+            v,
+            pdf: 1.0 / self.area(),
+            material: self.material.clone(),
+        })
+    }
+
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        let [a, b, c, d] = [
+            self.q,
+            self.q + self.u,
+            self.q + self.u + self.v,
+            self.q + self.v,
+        ];
+        let material = self.material.as_ref();
+        scene.add_triangle([a, b, c], material) && scene.add_triangle([a, c, d], material)
     }
 }
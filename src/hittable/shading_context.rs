@@ -0,0 +1,85 @@
+use std::{
+    cell::{OnceCell, RefCell},
+    sync::Arc,
+};
+
+use crate::{
+    bsdf::sampling::Frame,
+    texture::Texture,
+    vec3::{Float, Vec3},
+};
+
+// enough for the color and roughness of two mixed materials
+const CACHED_TEXTURES: usize = 4;
+
+/// Per-hit values that sample, pdf and eval would otherwise each derive again: the local frames
+/// around the normals and the texture values at the hit point. Everything is computed on first
+/// use, since most hits found while traversing the BVH are never shaded.
+#[derive(Clone, Default)]
+pub struct ShadingContext {
+    geometric_frame: OnceCell<Frame>,
+    shading_frame: OnceCell<Frame>,
+    textures: RefCell<[Option<(usize, Vec3)>; CACHED_TEXTURES]>,
+}
+
+impl ShadingContext {
+    pub fn geometric_frame(&self, normal: Vec3) -> &Frame {
+        self.geometric_frame
+            .get_or_init(|| Frame::from_normal(normal))
+    }
+
+    pub fn shading_frame(&self, normal: Vec3) -> &Frame {
+        self.shading_frame
+            .get_or_init(|| Frame::from_normal(normal))
+    }
+
+    /// the texture's value from the cache, or from `lookup` the first time it's asked for
+    pub fn texture<T: TextureValue>(
+        &self,
+        texture: &Arc<dyn Texture<T>>,
+        lookup: impl FnOnce() -> T,
+    ) -> T {
+        // textures are told apart by address, the hit point is the same for every lookup
+        let key = Arc::as_ptr(texture) as *const () as usize;
+        let mut textures = self.textures.borrow_mut();
+        for slot in textures.iter_mut() {
+            match slot {
+                Some((k, value)) if *k == key => return T::from_vec3(*value),
+                Some(_) => continue,
+                None => {
+                    let value = lookup();
+                    *slot = Some((key, value.to_vec3()));
+                    return value;
+                }
+            }
+        }
+        // full, so this texture isn't cached
+        lookup()
+    }
+}
+
+/// Texture values that fit into the cache of a `ShadingContext`
+pub trait TextureValue: Copy + Send + Sync {
+    fn to_vec3(self) -> Vec3;
+    fn from_vec3(value: Vec3) -> Self;
+}
+
+impl TextureValue for Vec3 {
+    fn to_vec3(self) -> Vec3 {
+        self
+    }
+
+    fn from_vec3(value: Vec3) -> Self {
+        value
+    }
+}
+
+impl TextureValue for Float {
+    fn to_vec3(self) -> Vec3 {
+        Vec3::splat(self)
+    }
+
+    fn from_vec3(value: Vec3) -> Self {
+        value.x
+    }
+}
@@ -1,17 +1,22 @@
-use std::f64::consts::PI;
+use rand::Rng;
 
-use crate::bsdf::MatPtr;
+use crate::bsdf::{sampling::Frame, MatPtr};
 use crate::interval::Interval;
-use crate::ray::Ray;
-use crate::vec3::Vec3;
+use crate::ray::{Ray, RELATIVE_ERROR};
+use crate::ray_stats;
+use crate::sampler::rng;
+use crate::vec3::{consts::PI, Float, Vec3};
 
 use super::hit_info::HitInfo;
 use super::Hittable;
+use super::LightSample;
+use super::SceneReport;
+use super::SurfaceSample;
 use super::AABB;
 
 #[derive(Clone)]
 pub struct Sphere {
-    radius: f64,
+    radius: Float,
     position1: Vec3,
     position2: Vec3,
     material: MatPtr,
@@ -19,7 +24,7 @@ pub struct Sphere {
 }
 
 impl Sphere {
-    pub fn new_still(radius: f64, position: Vec3, material: MatPtr) -> Sphere {
+    pub fn new_still(radius: Float, position: Vec3, material: MatPtr) -> Sphere {
         let rvec = Vec3::new(radius, radius, radius);
         let bbox = AABB::new(position - rvec, position + rvec);
         Sphere {
@@ -31,7 +36,7 @@ impl Sphere {
         }
     }
 
-    pub fn new_moving(radius: f64, position1: Vec3, position2: Vec3, material: MatPtr) -> Sphere {
+    pub fn new_moving(radius: Float, position1: Vec3, position2: Vec3, material: MatPtr) -> Sphere {
         let rvec = Vec3::new(radius, radius, radius);
         let box1 = AABB::new(position1 - rvec, position1 + rvec);
         let box2 = AABB::new(position2 - rvec, position2 + rvec);
@@ -45,23 +50,38 @@ impl Sphere {
         }
     }
 
-    pub fn radius(&self) -> f64 {
+    pub fn radius(&self) -> Float {
         self.radius
     }
 
-    fn get_uv(p: &Vec3) -> (f64, f64) {
+    fn get_uv(p: &Vec3) -> (Float, Float) {
         let theta = (-p.y).acos();
-        let phi = f64::atan2(-p.z, p.x) + PI;
+        let phi = Float::atan2(-p.z, p.x) + PI;
         (phi / (2.0 * PI), theta / PI)
     }
 
-    fn get_position(&self, t: f64) -> Vec3 {
+    fn get_position(&self, t: Float) -> Vec3 {
         self.position1 + (self.position2 - self.position1) * t
     }
+
+    /// the direction to the center and 1 - cos of the half angle of the cone the sphere covers
+    /// from `origin`, none from inside
+    fn cone(&self, origin: Vec3, time: Float) -> Option<(Vec3, Float)> {
+        let to_center = self.get_position(time) - origin;
+        let dist2 = to_center.length_squared();
+        let sin2_max = self.radius * self.radius / dist2;
+        if sin2_max >= 1.0 {
+            return None;
+        }
+        // written this way it doesn't cancel to 0 for small or far spheres
+        let one_minus_cos_max = sin2_max / (1.0 + (1.0 - sin2_max).sqrt());
+        Some((to_center / dist2.sqrt(), one_minus_cos_max))
+    }
 }
 
 impl Hittable for Sphere {
     fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        ray_stats::count_primitives(1);
         let current_center = self.get_position(ray.time());
         let l = current_center - ray.origin();
         let s = Vec3::dot(l, ray.direction());
@@ -88,49 +108,128 @@ impl Hittable for Sphere {
         let point = ray.at(intersect);
         let normal = (point - current_center).normalize();
         let (u, v) = Self::get_uv(&normal);
-        Some(HitInfo::new(
-            ray,
-            point,
-            normal,
-            intersect,
-            self.material.clone(),
-            u,
-            v,
-        ))
+        let mut info = HitInfo::new(ray, point, normal, intersect, self.material.clone(), u, v);
+        info.motion = self.position2 - self.position1;
+        info.object_motion = info.motion;
+        // l2 - s * s cancels, and the square root makes it worse where the ray grazes the
+        // sphere and q is small, so the distance can be much further off than the point is
+        let d2_error = RELATIVE_ERROR * l2;
+        let q_error = (d2_error / (2.0 * q)).min(d2_error.sqrt());
+        info.error += Vec3::splat(q_error + RELATIVE_ERROR * s.abs());
+        info.compute_differential(ray, || {
+            // derivatives for the parametrization in get_uv, phi = 2 pi u and theta = pi v
+            let (sin_phi, cos_phi) = (2.0 * PI * u).sin_cos();
+            let (sin_theta, cos_theta) = (PI * v).sin_cos();
+            let r = self.radius();
+            (
+                Vec3::new(sin_theta * sin_phi, 0.0, sin_theta * cos_phi) * (2.0 * PI * r),
+                Vec3::new(-cos_theta * cos_phi, sin_theta, cos_theta * sin_phi) * (PI * r),
+            )
+        });
+        Some(info)
     }
 
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
 
+    fn validate(&self, report: &mut SceneReport) {
+        let finite = self.position1.is_finite() && self.position2.is_finite();
+        if !(self.radius > 0.0 && self.radius.is_finite() && finite) {
+            report.problem(format!(
+                "sphere at {} has radius {}",
+                self.position1, self.radius
+            ));
+        }
+        report.add_primitive(Some(self.material.as_ref()), std::mem::size_of_val(self));
+    }
+
     fn material(&self) -> Option<&dyn crate::bsdf::BxDFMaterial> {
         Some(self.material.as_ref())
     }
 
-    fn sample(&self, origin: Vec3, time: f64) -> Option<Vec3> {
-        let u: f64 = rand::random();
-        let v: f64 = rand::random();
-        let theta = 2.0 * PI * u;
-        let phi = f64::acos(2.0 * v - 1.0);
-        let x = phi.sin() * theta.cos();
-        let y = phi.sin() * theta.sin();
-        let z = phi.cos();
-        let point = self.get_position(time) + Vec3::new(x, y, z) * self.radius;
-        let dir = (point - origin).normalize();
-        Some(dir)
+    /// points in the cone the sphere fills as seen from `origin`, so none are wasted on the far
+    /// side. from inside, every direction hits it
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        let u: Float = rng().gen();
+        let v: Float = rng().gen();
+        let phi = 2.0 * PI * v;
+        let center = self.get_position(time);
+        let (dir, pdf_dir) = match self.cone(origin, time) {
+            Some((axis, one_minus_cos_max)) => {
+                let cos_theta = 1.0 - u * one_minus_cos_max;
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                let dir = Frame::from_normal(axis).to_world(local);
+                (dir, 1.0 / (2.0 * PI * one_minus_cos_max))
+            }
+            None => {
+                let z = 1.0 - 2.0 * u;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                (Vec3::new(r * phi.cos(), r * phi.sin(), z), 1.0 / (4.0 * PI))
+            }
+        };
+
+        // where the direction meets the sphere, on the near side from outside
+        let oc = origin - center;
+        let b = dir.dot(oc);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let root = (b * b - c).max(0.0).sqrt();
+        let t = if c > 0.0 { -b - root } else { -b + root };
+        if t <= 0.0 {
+            return None;
+        }
+        let point = origin + t * dir;
+        let normal = (point - center) / self.radius;
+        let pdf_area = pdf_dir * normal.dot(dir).abs() / (t * t);
+        let (u, v) = Self::get_uv(&normal);
+        Some(LightSample::new(
+            origin,
+            point,
+            normal,
+            u,
+            v,
+            pdf_area,
+            self.material.as_ref(),
+        ))
     }
 
-    fn pdf(&self, origin: Vec3, direction: Vec3, time: f64) -> f64 {
-        if let Some(_hit) = self.intersects(
-            &Ray::new(origin, direction, time),
-            Interval::new(0.0, f64::INFINITY),
-        ) {
-            let r2 = self.radius * self.radius;
-            let solid_angle =
-                2.0 * PI * (1.0 - r2 / (self.get_position(time) - origin).length_squared()).sqrt();
-            1.0 / solid_angle
-        } else {
-            0.0
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        let normal = (point - self.get_position(time)) / self.radius;
+        if (normal.length() - 1.0).abs() > 1e-3 {
+            return 0.0;
+        }
+        let to_point = point - origin;
+        let dist_sq = to_point.length_squared();
+        let cos = normal.dot(to_point) / dist_sq.sqrt();
+        match self.cone(origin, time) {
+            // the far side is never picked
+            Some(_) if cos >= 0.0 => 0.0,
+            Some((_, one_minus_cos_max)) => -cos / (2.0 * PI * one_minus_cos_max * dist_sq),
+            None => cos.abs() / (4.0 * PI * dist_sq),
         }
     }
+
+    fn sample_surface(&self, time: Float) -> Option<SurfaceSample> {
+        let u: Float = rng().gen();
+        let v: Float = rng().gen();
+        let theta = 2.0 * PI * u;
+        let phi = Float::acos(2.0 * v - 1.0);
+        let normal = Vec3::new(phi.sin() * theta.cos(), phi.sin() * theta.sin(), phi.cos());
+        let (u, v) = Self::get_uv(&normal);
+        Some(SurfaceSample {
+            point: self.get_position(time) + normal * self.radius,
+            normal,
+            u,
+            v,
+            pdf: 1.0 / (4.0 * PI * self.radius * self.radius),
+            material: self.material.clone(),
+        })
+    }
+
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        // moving spheres are drawn where they start
+        scene.add_sphere(self.position1, self.radius, self.material.as_ref())
+    }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use tobj::Mesh;
+
+use crate::{
+    bsdf::{BxDFMaterial, MatPtr},
+    interval::Interval,
+    ray::Ray,
+    vec3::{Float, Vec3},
+};
+
+use super::{
+    HitInfo, Hittable, LightSample, MeshBuffers, MeshOptions, SceneReport, TriangleMesh, AABB,
+};
+
+/// A smooth surface from a coarse control cage, e.g. a low poly character like `spot.obj`,
+/// refined with Loop subdivision when it's loaded so it doesn't show facets
+pub struct SubdivisionMesh {
+    mesh: TriangleMesh,
+}
+
+impl SubdivisionMesh {
+    /// subdivide `cage` `iterations` times, each makes 4 times as many triangles. edges where
+    /// the faces meet at more than `crease_angle` degrees stay sharp
+    pub fn new(
+        cage: &MeshBuffers,
+        material: MatPtr,
+        iterations: usize,
+        crease_angle: Option<Float>,
+    ) -> SubdivisionMesh {
+        let buffers = cage.loop_subdivided(iterations, crease_angle);
+        SubdivisionMesh {
+            mesh: TriangleMesh::from_mesh_buffers(&buffers, material, MeshOptions::default()),
+        }
+    }
+
+    pub fn from_obj(
+        scale: Float,
+        mesh: &Mesh,
+        material: MatPtr,
+        iterations: usize,
+    ) -> SubdivisionMesh {
+        Self::new(
+            &MeshBuffers::from_obj(scale, mesh),
+            material,
+            iterations,
+            None,
+        )
+    }
+}
+
+impl Hittable for SubdivisionMesh {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        self.mesh.intersects(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.mesh.bounding_box()
+    }
+
+    fn validate(&self, report: &mut SceneReport) {
+        self.mesh.validate(report)
+    }
+
+    fn material(&self) -> Option<&dyn BxDFMaterial> {
+        None
+    }
+
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        self.mesh.sample_light(origin, time)
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        self.mesh.light_pdf(origin, point, time)
+    }
+}
+
+impl MeshBuffers {
+    /// `iterations` steps of Loop subdivision, with smooth normals for the result.
+    /// see `SubdivisionMesh::new` for `crease_angle`
+    pub fn loop_subdivided(&self, iterations: usize, crease_angle: Option<Float>) -> MeshBuffers {
+        let mut mesh = self.clone();
+        for _ in 0..iterations {
+            mesh = mesh.loop_step(crease_angle.map(|angle| angle.to_radians().cos()));
+        }
+        mesh.normals = mesh.smooth_normals();
+        mesh
+    }
+
+    /// one step of Loop subdivision: every triangle is split into 4, and the old and new
+    /// vertices are moved to weighted averages of their neighbours. the mesh is welded first,
+    /// so vertices split along uv seams move together. boundaries and creases use the curve
+    /// rules, which keeps them from shrinking and rounding off
+    fn loop_step(&self, cos_crease: Option<Float>) -> MeshBuffers {
+        let weld = self.welded();
+        let v = &self.vertices;
+        let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+
+        // the faces on each edge, as their vertex opposite the edge and their normal
+        let mut edges: HashMap<(usize, usize), Vec<(usize, Vec3)>> = HashMap::new();
+        let mut neighbours = vec![vec![]; v.len()];
+        for [a, b, c] in self.triangles() {
+            let [a, b, c] = [weld[a], weld[b], weld[c]];
+            if a == b || b == c || c == a {
+                continue; // collapsed triangles would make vertices their own neighbours
+            }
+            let normal = (v[b] - v[a]).cross(v[c] - v[a]).normalize_or_zero();
+            for (x, y, opposite) in [(a, b, c), (b, c, a), (c, a, b)] {
+                edges
+                    .entry(edge(x, y))
+                    .or_default()
+                    .push((opposite, normal));
+                if !neighbours[x].contains(&y) {
+                    neighbours[x].push(y);
+                    neighbours[y].push(x);
+                }
+            }
+        }
+        // boundaries, non-manifold edges and edges folded past the crease angle
+        let faces = |a: usize, b: usize| edges.get(&edge(a, b)).map_or(&[][..], Vec::as_slice);
+        let is_sharp = |a: usize, b: usize| match faces(a, b) {
+            [(_, n0), (_, n1)] => cos_crease.is_some_and(|cos| n0.dot(*n1) < cos),
+            _ => true,
+        };
+
+        let moved: Vec<Vec3> = (0..v.len())
+            .map(|i| {
+                if weld[i] != i {
+                    return Vec3::ZERO; // only the first vertex at each place is used
+                }
+                let sharp: Vec<usize> = neighbours[i]
+                    .iter()
+                    .copied()
+                    .filter(|&j| is_sharp(i, j))
+                    .collect();
+                match sharp.len() {
+                    // on a crease or boundary curve
+                    2 => 0.75 * v[i] + 0.125 * (v[sharp[0]] + v[sharp[1]]),
+                    // corners where several creases meet stay put
+                    3.. => v[i],
+                    _ => {
+                        let n = neighbours[i].len();
+                        if n == 0 {
+                            return v[i];
+                        }
+                        let beta = if n == 3 {
+                            3.0 / 16.0
+                        } else {
+                            3.0 / (8.0 * n as Float)
+                        };
+                        let sum: Vec3 = neighbours[i].iter().map(|&j| v[j]).sum();
+                        (1.0 - n as Float * beta) * v[i] + beta * sum
+                    }
+                }
+            })
+            .collect();
+
+        let mut mesh = self.split_edges(|a, b| {
+            let (a, b) = (weld[a], weld[b]);
+            match faces(a, b) {
+                [(c, _), (d, _)] if !is_sharp(a, b) => {
+                    0.375 * (v[a] + v[b]) + 0.125 * (v[*c] + v[*d])
+                }
+                _ => 0.5 * (v[a] + v[b]),
+            }
+        });
+        for i in 0..v.len() {
+            mesh.vertices[i] = moved[weld[i]];
+        }
+        mesh
+    }
+}
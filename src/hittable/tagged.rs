@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use crate::{
+    bsdf::BxDFMaterial,
+    interval::Interval,
+    ray::Ray,
+    vec3::{Float, Vec3},
+};
+
+use super::{HitInfo, Hittable, LightSample, OrientedBox, SceneReport, SurfaceSample, AABB};
+
+/// Wraps an object to put the IDs of the ID passes on its hits, see `aov::IdManifest`. a
+/// material ID set further in, like on the models of a mesh, is kept
+pub struct Tagged {
+    object: Arc<dyn Hittable>,
+    object_id: Option<u32>,
+    material_id: Option<u32>,
+}
+
+impl Tagged {
+    pub fn new(object: Arc<dyn Hittable>) -> Tagged {
+        Tagged {
+            object,
+            object_id: None,
+            material_id: None,
+        }
+    }
+
+    pub fn with_object_id(mut self, id: u32) -> Self {
+        self.object_id = Some(id);
+        self
+    }
+
+    pub fn with_material_id(mut self, id: u32) -> Self {
+        self.material_id = Some(id);
+        self
+    }
+}
+
+impl Hittable for Tagged {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        let mut info = self.object.intersects(ray, ray_t)?;
+        if let Some(id) = self.object_id {
+            info.object_id = id;
+        }
+        if let (Some(id), 0) = (self.material_id, info.material_id) {
+            info.material_id = id;
+        }
+        Some(info)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.object.bounding_box()
+    }
+
+    fn oriented_bounding_box(&self) -> OrientedBox {
+        self.object.oriented_bounding_box()
+    }
+
+    fn validate(&self, report: &mut SceneReport) {
+        self.object.validate(report)
+    }
+
+    fn material(&self) -> Option<&dyn BxDFMaterial> {
+        self.object.material()
+    }
+
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        self.object.sample_light(origin, time)
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        self.object.light_pdf(origin, point, time)
+    }
+
+    fn illuminates(&self, object_id: u32) -> bool {
+        self.object.illuminates(object_id)
+    }
+
+    fn sample_surface(&self, time: Float) -> Option<SurfaceSample> {
+        self.object.sample_surface(time)
+    }
+
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        self.object.add_to_gpu_scene(scene)
+    }
+}
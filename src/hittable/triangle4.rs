@@ -0,0 +1,346 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use rand::Rng;
+
+use crate::{
+    bsdf::BxDFMaterial,
+    interval::Interval,
+    ray::Ray,
+    ray_stats,
+    sampler::rng,
+    vec3::{Float, Vec3},
+};
+
+use super::{
+    bvh::centroid_axis, mesh::Triangle, HitInfo, Hittable, LightSample, SceneReport, AABB,
+};
+
+/// How the triangles of a mesh are intersected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TriangleIntersection {
+    /// one triangle at a time
+    Scalar,
+    /// four nearby triangles at once, stored axis by axis like `AABB4` so the test is SIMD
+    #[default]
+    Wide,
+    /// `Wide` in f32, so the four lanes fit in one SSE register. it only rules triangles out,
+    /// with some slack, and the few left are tested again in f64 so the hits are the same
+    WideF32,
+}
+
+/// the float type the lanes are computed in, whichever precision the rest of the crate is in
+trait Lane:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const INFINITY: Self;
+    fn from_float(x: Float) -> Self;
+    fn to_float(self) -> Float;
+    fn abs(self) -> Self;
+}
+
+impl Lane for f64 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const INFINITY: Self = f64::INFINITY;
+    // a no-op unless the crate is built with the `f32` feature
+    #[allow(clippy::unnecessary_cast)]
+    fn from_float(x: Float) -> Self {
+        x as f64
+    }
+    fn to_float(self) -> Float {
+        self as Float
+    }
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+}
+
+impl Lane for f32 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const INFINITY: Self = f32::INFINITY;
+    // a no-op with the `f32` feature
+    #[allow(clippy::unnecessary_cast)]
+    fn from_float(x: Float) -> Self {
+        x as f32
+    }
+    fn to_float(self) -> Float {
+        self as Float
+    }
+    fn abs(self) -> Self {
+        f32::abs(self)
+    }
+}
+
+/// first vertex and edges of four triangles, axis by axis
+#[derive(Clone, Copy)]
+struct Lanes<F> {
+    v0: [[F; 4]; 3],
+    e1: [[F; 4]; 3],
+    e2: [[F; 4]; 3],
+}
+
+/// Möller–Trumbore on all four lanes
+struct LaneTest<F> {
+    /// lanes with a smaller determinant are parallel to the ray
+    parallel: F,
+    /// how far outside the triangle or the ray's interval still counts as a hit
+    slack: F,
+    backface_culling: bool,
+}
+
+impl<F: Lane> Lanes<F> {
+    fn new(triangles: &[Triangle]) -> Lanes<F> {
+        let mut lanes = Lanes {
+            v0: [[F::ZERO; 4]; 3],
+            e1: [[F::ZERO; 4]; 3],
+            e2: [[F::ZERO; 4]; 3],
+        };
+        for (lane, triangle) in triangles.iter().enumerate() {
+            let [v0, v1, v2] = triangle.vertices();
+            let (e1, e2) = (v1 - v0, v2 - v0);
+            for axis in 0..3 {
+                lanes.v0[axis][lane] = F::from_float(v0[axis]);
+                lanes.e1[axis][lane] = F::from_float(e1[axis]);
+                lanes.e2[axis][lane] = F::from_float(e2[axis]);
+            }
+        }
+        lanes
+    }
+
+    /// distance, u and v of each lane, with an infinite distance where the ray misses. the
+    /// arithmetic is the same as `Triangle::hit`'s, so in f64 without slack so are the results
+    fn intersect(&self, ray: &Ray, ray_t: Interval, len: usize, test: &LaneTest<F>) -> [[F; 4]; 3] {
+        let o = ray.origin().to_array().map(F::from_float);
+        let d = ray.direction().to_array().map(F::from_float);
+        let t_min = F::from_float(ray_t.min);
+        let t_max = F::from_float(ray_t.max);
+        let slack = test.slack;
+        let (lo, hi) = (F::ZERO - slack, F::ONE + slack);
+        // without slack the bounds are left alone, an infinite t_max times 0 would be NaN
+        let widen = |t: F| {
+            if slack > F::ZERO {
+                slack * (F::ONE + t.abs())
+            } else {
+                F::ZERO
+            }
+        };
+        let (t_lo, t_hi) = (t_min - widen(t_min), t_max + widen(t_max));
+
+        // straight-line code over the lanes with no early outs, so it vectorizes
+        let mut t = [F::INFINITY; 4];
+        let mut u = [F::ZERO; 4];
+        let mut v = [F::ZERO; 4];
+        for lane in 0..4 {
+            let [e1x, e1y, e1z] = [0, 1, 2].map(|axis| self.e1[axis][lane]);
+            let [e2x, e2y, e2z] = [0, 1, 2].map(|axis| self.e2[axis][lane]);
+            let [sx, sy, sz] = [0, 1, 2].map(|axis| o[axis] - self.v0[axis][lane]);
+
+            // h = d x e2, a = e1 . h
+            let (hx, hy, hz) = (
+                d[1] * e2z - d[2] * e2y,
+                d[2] * e2x - d[0] * e2z,
+                d[0] * e2y - d[1] * e2x,
+            );
+            let a = e1x * hx + e1y * hy + e1z * hz;
+            let f = F::ONE / a;
+            let lane_u = f * (sx * hx + sy * hy + sz * hz);
+
+            // q = s x e1
+            let (qx, qy, qz) = (
+                sy * e1z - sz * e1y,
+                sz * e1x - sx * e1z,
+                sx * e1y - sy * e1x,
+            );
+            let lane_v = f * (d[0] * qx + d[1] * qy + d[2] * qz);
+            let lane_t = f * (e2x * qx + e2y * qy + e2z * qz);
+
+            let hit = (lane < len)
+                & (a.abs() >= test.parallel)
+                & !(test.backface_culling & (a < F::ZERO))
+                & (lane_u >= lo)
+                & (lane_u <= hi)
+                & (lane_v >= lo)
+                & (lane_u + lane_v <= hi)
+                & (lane_t >= t_lo)
+                & (lane_t <= t_hi);
+            t[lane] = if hit { lane_t } else { F::INFINITY };
+            u[lane] = lane_u;
+            v[lane] = lane_v;
+        }
+        [t, u, v]
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Precision {
+    F64(Lanes<f64>),
+    F32(Lanes<f32>),
+}
+
+/// Up to four triangles of a mesh intersected together
+pub struct Triangle4 {
+    triangles: Vec<Triangle>,
+    lanes: Precision,
+    bbox: AABB,
+}
+
+impl Triangle4 {
+    pub const LANES: usize = 4;
+
+    /// all the triangles should have the same options
+    pub fn new(triangles: Vec<Triangle>, precision: TriangleIntersection) -> Triangle4 {
+        assert!(
+            triangles.len() <= Self::LANES,
+            "Triangle4 holds at most 4 triangles"
+        );
+        let bbox = triangles
+            .iter()
+            .fold(AABB::default(), |acc, t| acc.union(t.bounding_box()));
+        let lanes = match precision {
+            TriangleIntersection::WideF32 => Precision::F32(Lanes::new(&triangles)),
+            _ => Precision::F64(Lanes::new(&triangles)),
+        };
+        Triangle4 {
+            triangles,
+            lanes,
+            bbox,
+        }
+    }
+
+    /// groups of up to four triangles that are close together, by splitting them at the median
+    /// along the longest axis until they fit. the splits fall on multiples of four so nearly
+    /// every group is full
+    pub fn packets(triangles: Vec<Triangle>, precision: TriangleIntersection) -> Vec<Triangle4> {
+        let boxes: Vec<AABB> = triangles.iter().map(|t| t.bounding_box()).collect();
+        let mut items: Vec<usize> = (0..triangles.len()).collect();
+        let mut groups = vec![];
+        group(&mut items, &boxes, &mut groups);
+
+        let mut triangles: Vec<Option<Triangle>> = triangles.into_iter().map(Some).collect();
+        groups
+            .into_iter()
+            .map(|group| {
+                let members = group.iter().filter_map(|&i| triangles[i].take()).collect();
+                Triangle4::new(members, precision)
+            })
+            .collect()
+    }
+}
+
+fn group(items: &mut [usize], boxes: &[AABB], groups: &mut Vec<Vec<usize>>) {
+    if items.len() <= Triangle4::LANES {
+        groups.push(items.to_vec());
+        return;
+    }
+    let item_boxes: Vec<AABB> = items.iter().map(|&i| boxes[i]).collect();
+    let axis = centroid_axis(&item_boxes);
+    let mid = (items.len() / 2).next_multiple_of(Triangle4::LANES);
+    items.select_nth_unstable_by(mid, |&a, &b| {
+        boxes[a].centroid()[axis].total_cmp(&boxes[b].centroid()[axis])
+    });
+    let (left, right) = items.split_at_mut(mid);
+    group(left, boxes, groups);
+    group(right, boxes, groups);
+}
+
+impl Hittable for Triangle4 {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        let backface_culling = self.triangles.first()?.options().backface_culling;
+        ray_stats::count_primitives(self.triangles.len());
+        match &self.lanes {
+            Precision::F64(lanes) => {
+                let test = LaneTest {
+                    parallel: 1e-8,
+                    slack: 0.0,
+                    backface_culling,
+                };
+                let [t, u, v] = lanes.intersect(ray, ray_t, self.triangles.len(), &test);
+                let mut lane = 0;
+                for i in 1..4 {
+                    if t[i] < t[lane] {
+                        lane = i;
+                    }
+                }
+                if t[lane] == f64::INFINITY {
+                    return None;
+                }
+                let [t, u, v] = [t[lane], u[lane], v[lane]].map(Lane::to_float);
+                Some(self.triangles[lane].hit_info(ray, t, u, v))
+            }
+            Precision::F32(lanes) => {
+                let test = LaneTest {
+                    parallel: 0.0,
+                    slack: 1e-3,
+                    backface_culling,
+                };
+                let [t, _, _] = lanes.intersect(ray, ray_t, self.triangles.len(), &test);
+                // the lanes that might be hit, tested again exactly
+                let mut closest = ray_t.max;
+                let mut hit_info = None;
+                for (lane, &t) in t.iter().enumerate() {
+                    if t == f32::INFINITY {
+                        continue;
+                    }
+                    let triangle = &self.triangles[lane];
+                    if let Some(info) = triangle.intersects(ray, Interval::new(ray_t.min, closest))
+                    {
+                        closest = info.dist;
+                        hit_info = Some(info);
+                    }
+                }
+                hit_info
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn validate(&self, report: &mut SceneReport) {
+        report.geometry_bytes += std::mem::size_of_val(self);
+        for triangle in &self.triangles {
+            triangle.validate(report);
+        }
+    }
+
+    fn material(&self) -> Option<&dyn BxDFMaterial> {
+        None
+    }
+
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        if self.triangles.is_empty() {
+            return None;
+        }
+        let i = rng().gen_range(0..self.triangles.len());
+        let sample = self.triangles[i].sample_light(origin, time)?;
+        Some(LightSample {
+            pdf_area: sample.pdf_area / self.triangles.len() as Float,
+            ..sample
+        })
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        if self.triangles.is_empty() {
+            return 0.0;
+        }
+        self.triangles
+            .iter()
+            .map(|t| t.light_pdf(origin, point, time))
+            .sum::<Float>()
+            / self.triangles.len() as Float
+    }
+
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        self.triangles.iter().all(|t| t.add_to_gpu_scene(scene))
+    }
+}
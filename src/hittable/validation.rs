@@ -0,0 +1,80 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{bsdf::BxDFMaterial, vec3::Float};
+
+/// What `World::validate` finds in a scene: what's wrong with it, one message each, and how
+/// big it is
+#[derive(Debug, Default)]
+pub struct SceneReport {
+    pub problems: Vec<String>,
+    /// the objects and lights the world holds, before looking into lists and meshes
+    pub objects: usize,
+    pub lights: usize,
+    pub delta_lights: usize,
+    /// the shapes at the bottom of the lists, meshes and instances, triangles included
+    pub primitives: usize,
+    pub triangles: usize,
+    /// triangles with zero area or NaN or infinite corners, which no ray can hit
+    pub degenerate_triangles: usize,
+    /// distinct materials
+    pub materials: usize,
+    /// the deepest of the BVHs, the world's and the ones inside meshes and lists
+    pub bvh_depth: usize,
+    /// memory of the primitives and their BVHs
+    pub geometry_bytes: usize,
+    /// memory of the textures, and their mipmaps once they're built
+    pub texture_bytes: usize,
+    seen_materials: HashSet<usize>,
+}
+
+impl SceneReport {
+    pub fn new() -> SceneReport {
+        Self::default()
+    }
+
+    pub fn problem(&mut self, problem: String) {
+        self.problems.push(problem);
+    }
+
+    /// count a primitive with `material` and `bytes` of memory
+    pub fn add_primitive(&mut self, material: Option<&dyn BxDFMaterial>, bytes: usize) {
+        self.primitives += 1;
+        self.geometry_bytes += bytes;
+        if let Some(material) = material {
+            self.add_material(material);
+        }
+    }
+
+    /// count `material` and check its parameters, the first time it's seen. materials are
+    /// shared between primitives, so they're told apart by their address
+    pub fn add_material(&mut self, material: &dyn BxDFMaterial) {
+        let address = material as *const dyn BxDFMaterial as *const () as usize;
+        if !self.seen_materials.insert(address) {
+            return;
+        }
+        self.materials += 1;
+        for problem in material.problems() {
+            self.problems
+                .push(format!("{}: {problem}", material.name()));
+        }
+    }
+}
+
+impl fmt::Display for SceneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} objects, {} lights and {} point lights, {} primitives ({} triangles), \
+             {} materials, BVH depth {}, {:.1} MiB of geometry and {:.1} MiB of textures",
+            self.objects,
+            self.lights,
+            self.delta_lights,
+            self.primitives,
+            self.triangles,
+            self.materials,
+            self.bvh_depth,
+            self.geometry_bytes as Float / (1024.0 * 1024.0),
+            self.texture_bytes as Float / (1024.0 * 1024.0)
+        )
+    }
+}
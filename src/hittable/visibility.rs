@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use crate::{
+    bsdf::BxDFMaterial,
+    interval::Interval,
+    ray::{Ray, RayType},
+    vec3::{Float, Vec3},
+};
+
+use super::{HitInfo, Hittable, LightSample, OrientedBox, SceneReport, SurfaceSample, AABB};
+
+/// Which kinds of rays see an object. rays that don't go straight through it, the same as
+/// through the holes of a cutout, see `World::intersect_objects`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Visibility {
+    /// seen directly by the camera
+    pub camera: bool,
+    /// blocks shadow rays, so it casts shadows
+    pub shadow: bool,
+    /// seen by bounces, so it shows up in reflections and lights other objects
+    pub indirect: bool,
+}
+
+impl Visibility {
+    pub const ALL: Visibility = Visibility {
+        camera: true,
+        shadow: true,
+        indirect: true,
+    };
+
+    pub fn sees(&self, ray_type: RayType) -> bool {
+        match ray_type {
+            RayType::Camera => self.camera,
+            RayType::Shadow => self.shadow,
+            RayType::Indirect => self.indirect,
+        }
+    }
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::ALL
+    }
+}
+
+/// Wraps an object to hide it from some kinds of rays, e.g. a light the camera shouldn't see
+/// or a ground plane that doesn't show up in reflections. everything else is passed through
+pub struct Visible {
+    object: Arc<dyn Hittable>,
+    visibility: Visibility,
+}
+
+impl Visible {
+    pub fn new(object: Arc<dyn Hittable>, visibility: Visibility) -> Visible {
+        Visible { object, visibility }
+    }
+}
+
+impl Hittable for Visible {
+    fn intersects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
+        let mut info = self.object.intersects(ray, ray_t)?;
+        info.visibility = self.visibility;
+        Some(info)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.object.bounding_box()
+    }
+
+    fn oriented_bounding_box(&self) -> OrientedBox {
+        self.object.oriented_bounding_box()
+    }
+
+    fn validate(&self, report: &mut SceneReport) {
+        self.object.validate(report)
+    }
+
+    fn material(&self) -> Option<&dyn BxDFMaterial> {
+        self.object.material()
+    }
+
+    /// lights hidden from bounces don't light anything, the same as when bounces found them
+    fn sample_light(&self, origin: Vec3, time: Float) -> Option<LightSample> {
+        if !self.visibility.indirect {
+            return None;
+        }
+        self.object.sample_light(origin, time)
+    }
+
+    fn light_pdf(&self, origin: Vec3, point: Vec3, time: Float) -> Float {
+        if !self.visibility.indirect {
+            return 0.0;
+        }
+        self.object.light_pdf(origin, point, time)
+    }
+
+    fn illuminates(&self, object_id: u32) -> bool {
+        self.object.illuminates(object_id)
+    }
+
+    fn sample_surface(&self, time: Float) -> Option<SurfaceSample> {
+        self.object.sample_surface(time)
+    }
+
+    /// the GPU backend traces every object the same, so it can't hide any
+    #[cfg(feature = "gpu")]
+    fn add_to_gpu_scene(&self, scene: &mut crate::gpu::GpuSceneBuilder) -> bool {
+        self.visibility == Visibility::ALL && self.object.add_to_gpu_scene(scene)
+    }
+}
@@ -1,10 +1,31 @@
-use crate::{interval::Interval, ray::Ray, vec3::Vec3};
+use std::{path::PathBuf, sync::Arc};
 
-use super::{HitInfo, Hittable, HittableList};
+use rand::Rng;
+
+use crate::{
+    bsdf::sided::{Black, Sidedness},
+    environment::EnvironmentType,
+    interval::Interval,
+    ray::{Ray, RayType, RELATIVE_ERROR},
+    ray_stats::{self, RayKind},
+    sampler::rng,
+    texture_cache::TextureCache,
+    vec3::{Float, Vec3},
+};
+
+use super::{BvhLayout, DeltaLight, HitInfo, Hittable, HittableList, SceneReport};
 
 pub struct World {
     pub objects: HittableList,
     pub lights: HittableList,
+    /// point lights and the like, which can't be hit and aren't part of `lights`
+    pub delta_lights: Vec<Arc<dyn DeltaLight>>,
+    pub environment: EnvironmentType,
+    /// the objects that are shadow catchers, which are in `objects` too
+    shadow_catchers: Vec<Arc<dyn Hittable>>,
+    /// the files the world was loaded from, textures among them, which `validate` looks up in
+    /// `TextureCache::global`
+    pub files: Vec<PathBuf>,
 }
 
 impl World {
@@ -12,6 +33,10 @@ impl World {
         World {
             objects: HittableList::new(),
             lights: HittableList::new(),
+            delta_lights: vec![],
+            environment: EnvironmentType::default(),
+            shadow_catchers: vec![],
+            files: vec![],
         }
     }
 
@@ -19,34 +44,248 @@ impl World {
         self.lights.add(light);
     }
 
+    pub fn add_delta_light<T: DeltaLight + 'static>(&mut self, light: T) {
+        self.delta_lights.push(Arc::new(light));
+    }
+
+    pub fn add_shared_delta_light(&mut self, light: Arc<dyn DeltaLight>) {
+        self.delta_lights.push(light);
+    }
+
     pub fn add_object<T: Hittable + 'static>(&mut self, object: T) {
         self.objects.add(object);
     }
 
+    pub fn add_shared_light(&mut self, light: Arc<dyn Hittable>) {
+        self.lights.add_shared(light);
+    }
+
+    pub fn add_shared_object(&mut self, object: Arc<dyn Hittable>) {
+        self.objects.add_shared(object);
+    }
+
+    /// add an object that only shows the shadows and reflections of the others when the
+    /// camera renders a transparent background, see `Camera::transparent`. it's rendered like
+    /// any other object otherwise
+    pub fn add_shadow_catcher(&mut self, object: Arc<dyn Hittable>) {
+        self.shadow_catchers.push(object.clone());
+        self.objects.add_shared(object);
+    }
+
+    /// the same lights and environment with only the shadow catchers in it, which is what the
+    /// catchers would look like without the objects on them. None without catchers
+    pub fn shadow_catcher_world(&self) -> Option<World> {
+        if self.shadow_catchers.is_empty() {
+            return None;
+        }
+        let mut world = World::new();
+        for catcher in &self.shadow_catchers {
+            world.add_shared_object(catcher.clone());
+        }
+        for i in 0..self.lights.len() {
+            world.add_shared_light(self.lights.get(i).clone());
+        }
+        world.delta_lights = self.delta_lights.clone();
+        world.environment = self.environment.clone();
+        world.build_bvh();
+        Some(world)
+    }
+
     pub fn build_bvh(&mut self) {
-        self.objects.build_bvh();
-        self.lights.build_bvh();
+        self.build_bvh_with(BvhLayout::Tree);
     }
 
-    pub fn shadow_ray(&self, origin: Vec3, light_pos: Vec3, time: f64) -> bool {
-        let dir = (light_pos - origin).normalize();
-        let max_dist = (light_pos - origin).length();
-        self.intersect_objects(&Ray::new(origin, dir, time), Interval::new(1e-3, max_dist))
-            .is_none()
+    pub fn build_bvh_with(&mut self, layout: BvhLayout) {
+        self.objects.build_bvh_with(layout);
+        self.lights.build_bvh_with(layout);
     }
 
-    /// intersect with t in (t_min, t_max)
+    /// look for what would render wrong or not at all before the render starts, and count what
+    /// the scene is made of: broken shapes and degenerate triangles, material parameters out
+    /// of range, lights that give off nothing or objects that do without being lights, and
+    /// textures in `files` that failed to load. cameras run it before every render
+    pub fn validate(&self) -> SceneReport {
+        let mut report = SceneReport::new();
+        report.objects = self.objects.iter().count();
+        report.lights = self.lights.iter().count();
+        report.delta_lights = self.delta_lights.len();
+        self.objects.validate(&mut report);
+        self.lights.validate(&mut report);
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let bbox = object.bounding_box();
+            if bbox.min().is_nan() || bbox.max().is_nan() {
+                report.problem(format!("object {i} has NaN in its bounds"));
+            }
+            if object
+                .material()
+                .is_some_and(|material| material.is_emissive())
+            {
+                report.problem(format!(
+                    "object {i} gives off light but isn't one of the lights, only the paths that \
+                     run into it see its light"
+                ));
+            }
+        }
+        let (center, _) = self.bounding_sphere();
+        for (i, light) in self.lights.iter().enumerate() {
+            let emits = (0..16).any(|_| {
+                light
+                    .sample_light(center, 0.5)
+                    .is_some_and(|sample| sample.emission.max_element() > 0.0)
+            });
+            if !emits {
+                report.problem(format!(
+                    "light {i} gives off no light towards the middle of the objects"
+                ));
+            }
+        }
+        let dark = match &self.environment {
+            EnvironmentType::Color(color) => color.max_element() <= 0.0,
+            _ => false,
+        };
+        if dark && report.lights == 0 && report.delta_lights == 0 {
+            report.problem("the scene has no lights and a black environment".to_string());
+        }
+
+        let textures = TextureCache::global();
+        let missing = textures.missing();
+        for file in self.files.iter().filter(|file| missing.contains(file)) {
+            report.problem(format!(
+                "texture {} is missing and renders as magenta checkers",
+                file.display()
+            ));
+        }
+        report.texture_bytes = self
+            .files
+            .iter()
+            .filter_map(|file| textures.peek(&file.to_string_lossy()))
+            .map(|texture| texture.memory_usage())
+            .sum();
+        report
+    }
+
+    /// center and radius of a sphere around all the objects (not the lights)
+    pub fn bounding_sphere(&self) -> (Vec3, Float) {
+        let bbox = self.objects.bounding_box();
+        (bbox.centroid(), 0.5 * bbox.extent().length())
+    }
+
+    /// whether anything blocks the light between `origin` and `light_pos`, see `transmittance`
+    pub fn shadow_ray(&self, origin: Vec3, light_pos: Vec3, time: Float) -> bool {
+        self.transmittance(origin, light_pos, time) != Vec3::ZERO
+    }
+
+    /// the share of the light that makes it from `target` to `origin`. instead of stopping at
+    /// the first surface in the way, it goes on through what lets light through and multiplies
+    /// in what each surface lets pass: the part of it its opacity cuts out, and what's left of
+    /// the rest after `BxDFMaterial::shadow_transmittance`, which is how glass tints shadows.
+    /// surfaces a shadow ray can't see and invisible back faces let everything through, black
+    /// back faces nothing. there are no participating media in the scene, the absorption inside
+    /// glass is the only volume it goes through
+    pub fn transmittance(&self, origin: Vec3, target: Vec3, time: Float) -> Vec3 {
+        let mut throughput = Vec3::ONE;
+        let mut origin = origin;
+        loop {
+            let Some(dir) = (target - origin).try_normalize() else {
+                return throughput;
+            };
+            let max_dist = (target - origin).length();
+            ray_stats::count_rays(RayKind::Shadow, 1);
+            let ray = Ray::new(origin, dir, time).with_type(RayType::Shadow);
+            let Some(hit) = self.objects.intersects(&ray, Interval::new(0.0, max_dist)) else {
+                return throughput;
+            };
+            let back = if hit.front_face {
+                Sidedness::Shaded
+            } else {
+                hit.mat.sidedness()
+            };
+            match back {
+                Sidedness::Black => return Vec3::ZERO,
+                Sidedness::Invisible => {}
+                _ if !hit.visibility.sees(RayType::Shadow) => {}
+                _ => {
+                    let opacity = hit.mat.opacity(&hit).clamp(0.0, 1.0);
+                    throughput *=
+                        (1.0 - opacity) + opacity * hit.mat.shadow_transmittance(dir, &hit);
+                    if throughput == Vec3::ZERO {
+                        return Vec3::ZERO;
+                    }
+                }
+            }
+            // on from just behind the surface, as a new ray so the next hit's distance is
+            // the length of the segment behind it, which is what absorption goes by
+            origin = hit.spawn_origin(dir);
+        }
+    }
+
+    /// the light of every delta light linked to the object of `hit_info` reflected off its
+    /// surface towards `wo`, each behind its own shadow ray
+    pub fn delta_lighting(&self, hit_info: &HitInfo, wo: Vec3, time: Float) -> Vec3 {
+        let mut radiance = Vec3::ZERO;
+        for light in &self.delta_lights {
+            if !light.illuminates(hit_info.object_id) {
+                continue;
+            }
+            let Some(sample) = light.sample_li(hit_info.point) else {
+                continue;
+            };
+            let f = hit_info.mat.eval(wo, sample.direction, hit_info);
+            if f == Vec3::ZERO {
+                continue;
+            }
+            let origin = hit_info.spawn_origin(sample.direction);
+            radiance += f * sample.radiance * self.transmittance(origin, sample.point, time);
+        }
+        radiance
+    }
+
+    /// intersect with t in (t_min, t_max). surfaces with an opacity below 1 are skipped with
+    /// probability `1 - opacity` and invisible back faces always, which goes for shadow rays
+    /// too. so are objects whose `Visibility` hides them from the ray's `RayType`. black back
+    /// faces come back with the `Black` material
     pub fn intersect_objects(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
-        self.objects.intersects(ray, ray_t)
+        ray_stats::count_rays(RayKind::ClosestHit, 1);
+        Self::intersect_visible(&self.objects, ray, ray_t)
     }
 
     pub fn intersect_lights(&self, ray: &Ray, ray_t: Interval) -> Option<HitInfo> {
-        self.lights.intersects(ray, ray_t)
+        ray_stats::count_rays(RayKind::ClosestHit, 1);
+        Self::intersect_visible(&self.lights, ray, ray_t)
+    }
+
+    fn intersect_visible(list: &HittableList, ray: &Ray, mut ray_t: Interval) -> Option<HitInfo> {
+        loop {
+            let mut hit = list.intersects(ray, ray_t)?;
+            let back = if hit.front_face {
+                Sidedness::Shaded
+            } else {
+                hit.mat.sidedness()
+            };
+            let opacity = match back {
+                Sidedness::Invisible => 0.0,
+                _ if !hit.visibility.sees(ray.ray_type()) => 0.0,
+                _ => hit.mat.opacity(&hit),
+            };
+            if opacity >= 1.0 || rng().gen::<Float>() < opacity {
+                if back == Sidedness::Black {
+                    hit.mat = Black::shared();
+                }
+                return Some(hit);
+            }
+            // carry on along the same ray from just behind the surface, past the band its
+            // rounding error leaves around it, which is wider along rays that graze it
+            let cos = ray.direction().normalize().dot(hit.geometric_normal).abs();
+            ray_t.min =
+                hit.dist + hit.offset() / (cos.max(RELATIVE_ERROR) * ray.direction().length());
+        }
     }
 
     pub fn intersect_all(&self, ray: &Ray, ray_t: Interval) -> Option<(HitInfo, bool)> {
-        let light_hit = self.intersect_lights(ray, ray_t);
-        let obj_hit = self.intersect_objects(ray, ray_t);
+        ray_stats::count_rays(RayKind::ClosestHit, 1);
+        let light_hit = Self::intersect_visible(&self.lights, ray, ray_t);
+        let obj_hit = Self::intersect_visible(&self.objects, ray, ray_t);
         match (light_hit, obj_hit) {
             (None, None) => None,
             (None, Some(obj)) => Some((obj, false)),
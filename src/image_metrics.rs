@@ -0,0 +1,165 @@
+//! Error metrics between a render and a reference, for checking that integrator changes converge
+//! to the same image.
+
+use std::fmt;
+
+use image::{Rgb, RgbImage};
+
+use crate::vec3::{Float, Vec3, VectorExt};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageMetrics {
+    /// root mean squared error, over all channels with values in [0, 1]
+    pub rmse: Float,
+    /// mean of (test - ref)^2 / (ref^2 + 0.01), which weighs errors in dark regions more
+    pub rel_mse: Float,
+    /// structural similarity of the luminance, 1 for identical images
+    pub ssim: Float,
+}
+
+impl fmt::Display for ImageMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RMSE {:.6}  relMSE {:.6}  SSIM {:.4}",
+            self.rmse, self.rel_mse, self.ssim
+        )
+    }
+}
+
+/// compare `test` against `reference`, which must have the same size
+pub fn compare(reference: &RgbImage, test: &RgbImage) -> Result<ImageMetrics, String> {
+    if reference.dimensions() != test.dimensions() {
+        return Err(format!(
+            "image sizes differ, {:?} vs {:?}",
+            reference.dimensions(),
+            test.dimensions()
+        ));
+    }
+    Ok(ImageMetrics {
+        rmse: rmse(reference, test),
+        rel_mse: rel_mse(reference, test),
+        ssim: ssim(reference, test),
+    })
+}
+
+fn to_vec3(pixel: &Rgb<u8>) -> Vec3 {
+    Vec3::new(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float) / 255.0
+}
+
+pub fn rmse(reference: &RgbImage, test: &RgbImage) -> Float {
+    let sum: Float = reference
+        .pixels()
+        .zip(test.pixels())
+        .map(|(r, t)| (to_vec3(t) - to_vec3(r)).length_squared())
+        .sum();
+    (sum / (3 * reference.pixels().len()).max(1) as Float).sqrt()
+}
+
+pub fn rel_mse(reference: &RgbImage, test: &RgbImage) -> Float {
+    let sum: Float = reference
+        .pixels()
+        .zip(test.pixels())
+        .map(|(r, t)| {
+            let (r, t) = (to_vec3(r), to_vec3(t));
+            ((t - r) * (t - r) / (r * r + 0.01)).element_sum()
+        })
+        .sum();
+    sum / (3 * reference.pixels().len()).max(1) as Float
+}
+
+/// mean SSIM over 11x11 gaussian windows (sigma 1.5), as in Wang et al. 2004
+pub fn ssim(reference: &RgbImage, test: &RgbImage) -> Float {
+    let (width, height) = (reference.width() as usize, reference.height() as usize);
+    let luminance =
+        |img: &RgbImage| -> Vec<Float> { img.pixels().map(|p| to_vec3(p).luminance()).collect() };
+    let x = luminance(reference);
+    let y = luminance(test);
+    let product =
+        |a: &[Float], b: &[Float]| -> Vec<Float> { a.iter().zip(b).map(|(a, b)| a * b).collect() };
+
+    let mu_x = gaussian_blur(&x, width, height);
+    let mu_y = gaussian_blur(&y, width, height);
+    let xx = gaussian_blur(&product(&x, &x), width, height);
+    let yy = gaussian_blur(&product(&y, &y), width, height);
+    let xy = gaussian_blur(&product(&x, &y), width, height);
+
+    let c1 = 0.01 * 0.01;
+    let c2 = 0.03 * 0.03;
+    let sum: Float = (0..width * height)
+        .map(|i| {
+            let (mx, my) = (mu_x[i], mu_y[i]);
+            let var_x = xx[i] - mx * mx;
+            let var_y = yy[i] - my * my;
+            let cov = xy[i] - mx * my;
+            ((2.0 * mx * my + c1) * (2.0 * cov + c2))
+                / ((mx * mx + my * my + c1) * (var_x + var_y + c2))
+        })
+        .sum();
+    sum / (width * height).max(1) as Float
+}
+
+/// separable blur, the kernel is renormalized where it hangs over the border
+fn gaussian_blur(values: &[Float], width: usize, height: usize) -> Vec<Float> {
+    let radius = 5_isize;
+    let sigma = 1.5;
+    let kernel: Vec<Float> = (-radius..=radius)
+        .map(|i| (-(i * i) as Float / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    let pass = |src: &[Float], horizontal: bool| -> Vec<Float> {
+        let mut dst = vec![0.0; src.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let (mut sum, mut weight) = (0.0, 0.0);
+                for (k, w) in kernel.iter().enumerate() {
+                    let offset = k as isize - radius;
+                    let (sx, sy) = if horizontal {
+                        (x as isize + offset, y as isize)
+                    } else {
+                        (x as isize, y as isize + offset)
+                    };
+                    if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize {
+                        continue;
+                    }
+                    sum += w * src[sy as usize * width + sx as usize];
+                    weight += w;
+                }
+                dst[y * width + x] = sum / weight;
+            }
+        }
+        dst
+    };
+    pass(&pass(values, true), false)
+}
+
+/// per pixel absolute error mapped from black (no error) through red to white (the largest error)
+pub fn error_heatmap(reference: &RgbImage, test: &RgbImage) -> RgbImage {
+    let errors: Vec<Float> = reference
+        .pixels()
+        .zip(test.pixels())
+        .map(|(r, t)| (to_vec3(t) - to_vec3(r)).abs().max_element())
+        .collect();
+    let max_error = errors.iter().cloned().fold(0.0, Float::max).max(1e-8);
+
+    let mut heatmap = RgbImage::new(reference.width(), reference.height());
+    for (pixel, error) in heatmap.pixels_mut().zip(errors) {
+        let color = heat_color(error / max_error) * 255.0;
+        *pixel = Rgb([color.x as u8, color.y as u8, color.z as u8]);
+    }
+    heatmap
+}
+
+// black -> blue -> red -> yellow -> white
+pub(crate) fn heat_color(t: Float) -> Vec3 {
+    let stops = [
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.1, 0.1, 0.6),
+        Vec3::new(0.9, 0.1, 0.1),
+        Vec3::new(1.0, 0.9, 0.1),
+        Vec3::new(1.0, 1.0, 1.0),
+    ];
+    let scaled = t.clamp(0.0, 1.0) * (stops.len() - 1) as Float;
+    let i = (scaled as usize).min(stops.len() - 2);
+    stops[i].lerp(stops[i + 1], scaled - i as Float)
+}
@@ -0,0 +1,198 @@
+//! Quick diagnostic renders of a single asset, to catch broken meshes and textures before they
+//! end up in a long render.
+
+use std::{path::Path, sync::Arc};
+
+use image::{imageops, RgbImage};
+
+use crate::{
+    bsdf::diffuse::DiffuseBRDF,
+    camera::Camera,
+    contact_sheet::ContactSheet,
+    hittable::{Hittable, HittableList, TriangleMesh, World},
+    integrator::{AoIntegrator, AoSettings, DebugIntegrator, DebugView, Integrator},
+    lookdev::render_material_preview_with,
+    texture::ImageTexture,
+    vec3::{Float, Vec3, VectorExt},
+};
+
+/// What was found out about an asset, and a contact sheet of diagnostic renders
+pub struct AssetReport {
+    pub summary: Vec<String>,
+    pub image: RgbImage,
+}
+
+/// inspect a mesh (`.obj`) or a texture (any image format), with every render `size` pixels wide
+pub fn inspect_asset(path: &str, size: usize) -> Result<AssetReport, String> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "obj" => inspect_mesh(path, size),
+        _ => inspect_texture(path, size),
+    }
+}
+
+/// renders the mesh with ambient occlusion, its normals, which side faces the camera, and a uv checker
+pub fn inspect_mesh(path: &str, size: usize) -> Result<AssetReport, String> {
+    let (models, _) = tobj::load_obj(path, &tobj::OFFLINE_RENDERING_LOAD_OPTIONS)
+        .map_err(|err| format!("{path}: {err}"))?;
+
+    let mut summary = vec![];
+    let material = Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(0.8)));
+    let mut meshes = HittableList::new();
+    let (mut triangles, mut degenerate, mut non_finite) = (0, 0, 0);
+    for model in models.iter() {
+        let mesh = &model.mesh;
+        let positions: Vec<Vec3> = mesh
+            .positions
+            .chunks(3)
+            .map(|v| Vec3::new(v[0] as Float, v[1] as Float, v[2] as Float))
+            .collect();
+        non_finite += positions.iter().filter(|p| !p.is_finite()).count();
+        for tri in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| positions[tri[i] as usize]);
+            if (b - a).cross(c - a).length_squared() == 0.0 {
+                degenerate += 1;
+            }
+        }
+        triangles += mesh.indices.len() / 3;
+        summary.push(format!(
+            "model `{}`: {} vertices, {} triangles, normals: {}, uvs: {}",
+            model.name,
+            positions.len(),
+            mesh.indices.len() / 3,
+            if mesh.normals.is_empty() { "no" } else { "yes" },
+            if mesh.texcoords.is_empty() {
+                "no"
+            } else {
+                "yes"
+            },
+        ));
+        let mesh = TriangleMesh::from_obj(1.0, mesh, material.clone())
+            .map_err(|err| format!("{path}: {err}"))?;
+        meshes.add(mesh);
+    }
+    if meshes.is_empty() {
+        return Err(format!("{path}: no models"));
+    }
+    summary.push(format!("{} models, {triangles} triangles", models.len()));
+    if degenerate > 0 {
+        summary.push(format!(
+            "warning: {degenerate} degenerate (zero area) triangles"
+        ));
+    }
+    if non_finite > 0 {
+        summary.push(format!(
+            "warning: {non_finite} vertices with NaN or infinite positions"
+        ));
+    }
+
+    meshes.build_bvh();
+    let bbox = meshes.bounding_box();
+    let extent = bbox.extent();
+    summary.push(format!(
+        "bounding box size {:.3} x {:.3} x {:.3}",
+        extent.x, extent.y, extent.z
+    ));
+
+    let mut world = World::new();
+    world.add_object(meshes);
+    world.build_bvh();
+
+    let radius = 0.5 * extent.length();
+    let mut ao = AoIntegrator::new(AoSettings::new(radius, 1.0, false), 4);
+    ao.background = Vec3::splat(0.05);
+    let mut sheet = ContactSheet::new(0);
+    sheet.add("ao", render_framed(ao, &world, size, 16));
+    let views = [
+        ("normals", DebugView::Normal),
+        ("facing", DebugView::Facing),
+        ("uv", DebugView::UvChecker { scale: 8.0 }),
+    ];
+    for (label, view) in views {
+        sheet.add(
+            label,
+            render_framed(DebugIntegrator::new(view), &world, size, 4),
+        );
+    }
+
+    Ok(AssetReport {
+        summary,
+        image: sheet.assemble(),
+    })
+}
+
+/// the texture itself, tiled 2 x 2 to show seams, and on the shader ball
+pub fn inspect_texture(path: &str, size: usize) -> Result<AssetReport, String> {
+    let img = image::open(path)
+        .map_err(|err| format!("{path}: {err}"))?
+        .to_rgb8();
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return Err(format!("{path}: empty image"));
+    }
+
+    let mean = img
+        .pixels()
+        .map(|p| Vec3::new(p[0] as Float, p[1] as Float, p[2] as Float) / 255.0)
+        .sum::<Vec3>()
+        / (width * height) as Float;
+    let mut summary = vec![
+        format!("{width} x {height}"),
+        format!(
+            "mean color {:.3} {:.3} {:.3}, luminance {:.3}",
+            mean.x,
+            mean.y,
+            mean.z,
+            mean.luminance()
+        ),
+    ];
+    if (mean - Vec3::new(0.5, 0.5, 1.0)).length() < 0.15 {
+        summary.push(String::from("looks like a tangent space normal map"));
+    }
+    if !width.is_power_of_two() || !height.is_power_of_two() {
+        summary.push(String::from("note: size is not a power of two"));
+    }
+
+    let size = size as u32;
+    let thumbnail = imageops::resize(&img, size, size, imageops::FilterType::Triangle);
+    let half = imageops::resize(&img, size / 2, size / 2, imageops::FilterType::Triangle);
+    let mut tiled = RgbImage::new(size / 2 * 2, size / 2 * 2);
+    for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        imageops::replace(
+            &mut tiled,
+            &half,
+            (x * size / 2) as i64,
+            (y * size / 2) as i64,
+        );
+    }
+    let material = Arc::new(DiffuseBRDF::new(Arc::new(ImageTexture::new(path))));
+    let preview = render_material_preview_with(material, 0.0, size as usize, 16);
+
+    let mut sheet = ContactSheet::new(0);
+    sheet.add("texture", thumbnail);
+    sheet.add("tiled", tiled);
+    sheet.add("shader ball", preview);
+    Ok(AssetReport {
+        summary,
+        image: sheet.assemble(),
+    })
+}
+
+/// square render looking at everything in `world` from the front, slightly from above
+fn render_framed<I: Integrator>(integrator: I, world: &World, size: usize, spp: usize) -> RgbImage {
+    let mut camera = Camera::with_integrator(integrator);
+    camera.aspect_ratio = 1.0;
+    camera.image_width = size;
+    camera.samples_per_pixel = spp;
+    camera.vfov = 35.0;
+    camera.look_from = Vec3::new(0.6, 0.4, 1.0);
+    camera.look_at = Vec3::ZERO;
+    camera.vup = Vec3::Y;
+    camera.frame(world, 0.0);
+    camera.init();
+    camera.render_image(world)
+}
@@ -0,0 +1,130 @@
+use crate::{
+    bsdf::sampling::to_world,
+    hittable::World,
+    interval::Interval,
+    ray::{Ray, RayType},
+    sampler::Sampler,
+    vec3::{consts::PI, Float, Vec3},
+};
+
+use super::Integrator;
+
+/// How a surface gets darkened by nearby geometry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AoSettings {
+    /// occluders further away than this are ignored
+    pub distance: Float,
+    /// an occluder at distance `t` blocks `(1 - t / distance)^falloff` of the light,
+    /// 0 gives the classic all-or-nothing occlusion
+    pub falloff: Float,
+    /// only surfaces facing up receive occlusion, which keeps the contact shadow on the floor
+    /// without darkening the crevices of the object standing on it
+    pub ground_only: bool,
+}
+
+impl AoSettings {
+    pub fn new(distance: Float, falloff: Float, ground_only: bool) -> AoSettings {
+        AoSettings {
+            distance,
+            falloff,
+            ground_only,
+        }
+    }
+}
+
+impl Default for AoSettings {
+    fn default() -> Self {
+        Self::new(Float::INFINITY, 0.0, false)
+    }
+}
+
+/// Ambient occlusion, renders every surface as white shaded by how much of its hemisphere is blocked.
+/// Materials can replace the scene wide `settings` with their own, see `BxDFMaterial::ao_settings`.
+#[derive(Debug, Clone)]
+pub struct AoIntegrator {
+    pub settings: AoSettings,
+    /// occlusion rays per camera ray
+    pub samples: usize,
+    /// which way is up for `ground_only`
+    pub up: Vec3,
+    /// returned for rays that hit nothing
+    pub background: Vec3,
+}
+
+impl AoIntegrator {
+    pub fn new(settings: AoSettings, samples: usize) -> AoIntegrator {
+        AoIntegrator {
+            settings,
+            samples,
+            up: Vec3::Y,
+            background: Vec3::ONE,
+        }
+    }
+
+    fn occlusion(&self, t: Float, settings: &AoSettings) -> Float {
+        if !settings.distance.is_finite() || settings.falloff == 0.0 {
+            1.0
+        } else {
+            (1.0 - t / settings.distance)
+                .max(0.0)
+                .powf(settings.falloff)
+        }
+    }
+}
+
+impl Default for AoIntegrator {
+    fn default() -> Self {
+        Self::new(AoSettings::default(), 4)
+    }
+}
+
+impl Integrator for AoIntegrator {
+    fn name(&self) -> &str {
+        "ao"
+    }
+
+    fn settings(&self) -> String {
+        format!(
+            "{} samples, distance {}",
+            self.samples, self.settings.distance
+        )
+    }
+
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut Sampler) -> Vec3 {
+        let Some((hit_info, is_light)) = world.intersect_all(ray, Interval::FORWARD) else {
+            return self.background;
+        };
+        if is_light {
+            return Vec3::ONE;
+        }
+
+        let settings = hit_info.mat.ao_settings().unwrap_or(self.settings);
+        let normal = hit_info.shading_normal;
+        if settings.ground_only && normal.dot(self.up) < 0.5 {
+            return Vec3::ONE;
+        }
+
+        let origin = hit_info.spawn_origin(hit_info.geometric_normal);
+        let mut visibility = 0.0;
+        for _ in 0..self.samples.max(1) {
+            // cosine weighted, so an unblocked ray simply counts as one
+            let u = sampler.next_2d();
+            let phi = 2.0 * PI * u.x;
+            let local = Vec3::new(
+                u.y.sqrt() * phi.cos(),
+                u.y.sqrt() * phi.sin(),
+                (1.0 - u.y).sqrt(),
+            );
+            let dir = to_world(normal, local);
+            let occluder = world.intersect_objects(
+                &Ray::new(origin, dir, ray.time()).with_type(RayType::Shadow),
+                Interval::new(0.0, settings.distance),
+            );
+            visibility += match occluder {
+                Some(occluder) => 1.0 - self.occlusion(occluder.dist, &settings),
+                None => 1.0,
+            };
+        }
+        Vec3::splat(visibility / self.samples.max(1) as Float)
+    }
+}
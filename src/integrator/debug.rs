@@ -0,0 +1,83 @@
+use crate::{
+    hittable::World,
+    interval::Interval,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Float, Vec3},
+};
+
+use super::Integrator;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugView {
+    /// world space normal as the surface defines it (not flipped towards the viewer),
+    /// mapped to colors like an OpenGL normal map: +x red, +y green, +z blue
+    Normal,
+    /// checkerboard over the uv coordinates with `scale` squares per unit, u tinted red and v green,
+    /// to spot stretching and seams
+    UvChecker { scale: Float },
+    /// green where the camera sees the side the normal points to, red where it sees the back
+    Facing,
+}
+
+/// Shows one property of the first surface hit instead of shading it, for checking assets
+#[derive(Debug, Clone)]
+pub struct DebugIntegrator {
+    pub view: DebugView,
+}
+
+impl DebugIntegrator {
+    pub fn new(view: DebugView) -> DebugIntegrator {
+        DebugIntegrator { view }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn name(&self) -> &str {
+        "debug"
+    }
+
+    fn settings(&self) -> String {
+        match self.view {
+            DebugView::Normal => String::from("normal"),
+            DebugView::UvChecker { scale } => format!("uv checker, scale {scale}"),
+            DebugView::Facing => String::from("facing"),
+        }
+    }
+
+    fn li(&self, ray: &Ray, world: &World, _sampler: &mut Sampler) -> Vec3 {
+        let Some((hit_info, _)) = world.intersect_all(ray, Interval::FORWARD) else {
+            return Vec3::splat(0.05);
+        };
+
+        // the colors are meant to be read as they are, undo the gamma correction the camera applies
+        let color = match self.view {
+            DebugView::Normal => {
+                let normal = if hit_info.front_face {
+                    hit_info.shading_normal
+                } else {
+                    -hit_info.shading_normal
+                };
+                normal * 0.5 + 0.5
+            }
+            DebugView::UvChecker { scale } => {
+                let (u, v) = (hit_info.u * scale, hit_info.v * scale);
+                let even = (u.floor() + v.floor()) as i64 % 2 == 0;
+                let base = if even { 0.85 } else { 0.25 };
+                Vec3::new(
+                    base * (0.6 + 0.4 * hit_info.u.fract()),
+                    base * (0.6 + 0.4 * hit_info.v.fract()),
+                    base * 0.6,
+                )
+            }
+            DebugView::Facing => {
+                if hit_info.front_face {
+                    Vec3::new(0.2, 0.8, 0.2)
+                } else {
+                    Vec3::new(0.8, 0.2, 0.2)
+                }
+            }
+        };
+        color * color
+    }
+}
@@ -0,0 +1,421 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    hittable::{Hittable, World},
+    vec3::{consts::PI, Float, Vec2, Vec3},
+};
+
+/// split a spatial leaf once this many path vertices went through it in one pass
+const SPLIT_SAMPLES: u64 = 4000;
+/// split a direction quadrant that got more than this fraction of the energy
+const SPLIT_ENERGY: f64 = 0.01;
+const MAX_DIRECTION_DEPTH: usize = 20;
+const MAX_SPATIAL_DEPTH: usize = 48;
+
+/// Practical path guiding (Müller et al. 2017): an SD-tree learns where the light arriving at
+/// every part of the scene comes from, and the path integrator samples its bounces from it as
+/// well as from the BSDF.
+///
+/// The scene's box is split in halves (the spatial binary tree) wherever many paths go, and
+/// every cell has a quadtree over the sphere of directions that is finer where more light
+/// arrives from. it learns over `passes` passes of the image: the light the paths of one pass
+/// find trains the trees the next pass samples from, the first pass only samples the BSDFs.
+/// the camera averages the passes, which are all unbiased, the later ones just less noisy.
+#[derive(Debug, Clone)]
+pub struct PathGuide {
+    pub passes: usize,
+    /// the share of the bounces that sample the guide instead of the BSDF, once it's trained
+    pub mix: Float,
+
+    tree: SdTree,
+}
+
+impl PathGuide {
+    pub fn new(passes: usize) -> PathGuide {
+        PathGuide {
+            passes,
+            mix: 0.5,
+            tree: SdTree::default(),
+        }
+    }
+
+    /// start learning from scratch over `world`
+    pub fn reset(&mut self, world: &World) {
+        let bbox = world.objects.bounding_box();
+        // a little bigger so points on the box's faces are inside
+        let margin = 1e-3 * bbox.extent().length() + 1e-3;
+        self.tree = SdTree {
+            min: bbox.min() - Vec3::splat(margin),
+            max: bbox.max() + Vec3::splat(margin),
+            nodes: vec![SpatialNode::Leaf(Box::default())],
+        };
+    }
+
+    /// sample from what the last pass learned and learn anew from the next one
+    pub fn refine(&mut self) {
+        self.tree.refine();
+    }
+
+    /// the cell holding `point`, to sample, evaluate and record in
+    pub fn cell(&self, point: Vec3) -> GuideCell<'_> {
+        GuideCell(self.tree.leaf(point))
+    }
+}
+
+/// The distributions of one cell of the SD-tree
+pub struct GuideCell<'a>(&'a Leaf);
+
+// the trees add up in f64 whatever the Float
+#[allow(clippy::unnecessary_cast)]
+impl GuideCell<'_> {
+    /// whether the last pass taught it anything to sample
+    pub fn is_trained(&self) -> bool {
+        self.0.sampling.total() > 0.0
+    }
+
+    /// a direction with a density close to that of the light arriving from it
+    pub fn sample(&self, u: Vec2) -> Vec3 {
+        to_direction(self.0.sampling.sample(u))
+    }
+
+    /// the density of `sample` picking `dir`, per solid angle
+    pub fn pdf(&self, dir: Vec3) -> Float {
+        (self.0.sampling.pdf(to_square(dir)) / (4.0 * PI as f64)) as Float
+    }
+
+    /// `radiance` arrived from `dir` at a point in the cell, sampled with density `pdf`
+    pub fn record(&self, dir: Vec3, radiance: Float, pdf: Float) {
+        self.0.samples.fetch_add(1, Ordering::Relaxed);
+        if radiance > 0.0 && radiance.is_finite() && pdf > 0.0 {
+            self.0
+                .collecting
+                .record(to_square(dir), (radiance / pdf) as f64);
+        }
+    }
+}
+
+// directions map to the unit square by the height and angle around the z axis, which keeps
+// areas: a patch of the square covers 4 pi times its area of the sphere
+
+#[allow(clippy::unnecessary_cast)]
+fn to_square(dir: Vec3) -> Vec2 {
+    let phi = dir.y.atan2(dir.x);
+    let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+    Vec2::new(
+        ((dir.z.clamp(-1.0, 1.0) + 1.0) / 2.0) as Float,
+        (phi / (2.0 * PI)) as Float,
+    )
+}
+
+fn to_direction(p: Vec2) -> Vec3 {
+    let cos = 2.0 * p.x - 1.0;
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let phi = 2.0 * PI * p.y;
+    Vec3::new(sin * phi.cos(), sin * phi.sin(), cos)
+}
+
+/// An f64 that threads can add to
+#[derive(Debug, Default)]
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn new(value: f64) -> AtomicF64 {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, value: f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+}
+
+/// A quadtree over the square of directions. every node has the energy of its four quadrants,
+/// and the index of the node that splits each one further, or 0 where it isn't split
+#[derive(Debug)]
+struct DirectionTree {
+    nodes: Vec<DirectionNode>,
+}
+
+#[derive(Debug, Default)]
+struct DirectionNode {
+    sums: [AtomicF64; 4],
+    children: [usize; 4],
+}
+
+impl DirectionNode {
+    fn sums(&self) -> [f64; 4] {
+        self.sums.each_ref().map(AtomicF64::get)
+    }
+}
+
+/// the quadrant `p` is in, x first, and `p` within it
+fn quadrant(p: Vec2) -> (usize, Vec2) {
+    let (x, y) = ((p.x >= 0.5) as usize, (p.y >= 0.5) as usize);
+    let within = 2.0 * p - Vec2::new(x as Float, y as Float);
+    (
+        x + 2 * y,
+        within.clamp(Vec2::ZERO, Vec2::splat(1.0 - Float::EPSILON)),
+    )
+}
+
+impl Default for DirectionTree {
+    fn default() -> Self {
+        DirectionTree {
+            nodes: vec![DirectionNode::default()],
+        }
+    }
+}
+
+impl Clone for DirectionTree {
+    fn clone(&self) -> Self {
+        DirectionTree {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| DirectionNode {
+                    sums: node.sums().map(AtomicF64::new),
+                    children: node.children,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+impl DirectionTree {
+    fn total(&self) -> f64 {
+        self.nodes[0].sums().iter().sum()
+    }
+
+    fn sample(&self, mut u: Vec2) -> Vec2 {
+        let (mut origin, mut size) = (Vec2::ZERO, 1.0);
+        let mut node = &self.nodes[0];
+        loop {
+            let s = node.sums();
+            let total: f64 = s.iter().sum();
+            if total <= 0.0 {
+                return origin + u * size;
+            }
+            // pick the half along x, then the quadrant in it along y
+            let left = (s[0] + s[2]) / total;
+            let x = if (u.x as f64) < left {
+                u.x = (u.x as f64 / left) as Float;
+                0
+            } else {
+                u.x = ((u.x as f64 - left) / (1.0 - left)) as Float;
+                1
+            };
+            let column = s[x] + s[x + 2];
+            let bottom = s[x] / column;
+            let y = if (u.y as f64) < bottom {
+                u.y = (u.y as f64 / bottom) as Float;
+                0
+            } else {
+                u.y = ((u.y as f64 - bottom) / (1.0 - bottom)) as Float;
+                1
+            };
+            u = u.clamp(Vec2::ZERO, Vec2::splat(1.0 - Float::EPSILON));
+            size /= 2.0;
+            origin += Vec2::new(x as Float, y as Float) * size;
+            match node.children[x + 2 * y] {
+                0 => return origin + u * size,
+                child => node = &self.nodes[child],
+            }
+        }
+    }
+
+    /// the density of `sample` over the square at `p`
+    fn pdf(&self, mut p: Vec2) -> f64 {
+        let mut pdf = 1.0;
+        let mut node = &self.nodes[0];
+        loop {
+            let s = node.sums();
+            let total: f64 = s.iter().sum();
+            if total <= 0.0 {
+                return pdf;
+            }
+            let (i, within) = quadrant(p);
+            pdf *= 4.0 * s[i] / total;
+            p = within;
+            match node.children[i] {
+                0 => return pdf,
+                child => node = &self.nodes[child],
+            }
+        }
+    }
+
+    fn record(&self, mut p: Vec2, value: f64) {
+        let mut node = &self.nodes[0];
+        loop {
+            let (i, within) = quadrant(p);
+            node.sums[i].add(value);
+            p = within;
+            match node.children[i] {
+                0 => return,
+                child => node = &self.nodes[child],
+            }
+        }
+    }
+
+    /// an empty tree split where this one got more than `SPLIT_ENERGY` of the energy, and
+    /// merged where it got less
+    fn refined(&self) -> DirectionTree {
+        let total = self.total();
+        if total <= 0.0 {
+            let mut empty = self.clone();
+            empty.nodes.iter_mut().for_each(|node| {
+                node.sums = Default::default();
+            });
+            return empty;
+        }
+        let mut refined = DirectionTree { nodes: vec![] };
+        self.refine_node(Some(0), self.nodes[0].sums(), 0, total, &mut refined);
+        refined
+    }
+
+    /// add the refined copy of `node` (None for a quadrant that wasn't split, with its energy
+    /// spread evenly) and return its index
+    fn refine_node(
+        &self,
+        node: Option<usize>,
+        sums: [f64; 4],
+        depth: usize,
+        total: f64,
+        refined: &mut DirectionTree,
+    ) -> usize {
+        let index = refined.nodes.len();
+        refined.nodes.push(DirectionNode::default());
+        for (i, sum) in sums.into_iter().enumerate() {
+            if sum / total <= SPLIT_ENERGY || depth >= MAX_DIRECTION_DEPTH {
+                continue;
+            }
+            let child = node.map_or(0, |node| self.nodes[node].children[i]);
+            let (child, child_sums) = match child {
+                0 => (None, [sum / 4.0; 4]),
+                child => (Some(child), self.nodes[child].sums()),
+            };
+            let child = self.refine_node(child, child_sums, depth + 1, total, refined);
+            refined.nodes[index].children[i] = child;
+        }
+        index
+    }
+}
+
+#[derive(Debug, Default)]
+struct Leaf {
+    sampling: DirectionTree,
+    collecting: DirectionTree,
+    /// the path vertices that went through the leaf in this pass
+    samples: AtomicU64,
+}
+
+impl Clone for Leaf {
+    fn clone(&self) -> Self {
+        Leaf {
+            sampling: self.sampling.clone(),
+            collecting: self.collecting.clone(),
+            samples: AtomicU64::new(self.samples.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum SpatialNode {
+    /// split in half across `axis`
+    Inner {
+        axis: usize,
+        children: [usize; 2],
+    },
+    Leaf(Box<Leaf>),
+}
+
+#[derive(Debug, Clone)]
+struct SdTree {
+    min: Vec3,
+    max: Vec3,
+    nodes: Vec<SpatialNode>,
+}
+
+impl Default for SdTree {
+    fn default() -> Self {
+        SdTree {
+            min: Vec3::ZERO,
+            max: Vec3::ZERO,
+            nodes: vec![SpatialNode::Leaf(Box::default())],
+        }
+    }
+}
+
+impl SdTree {
+    fn leaf(&self, point: Vec3) -> &Leaf {
+        let (mut min, mut max) = (self.min, self.max);
+        let mut node = 0;
+        loop {
+            match &self.nodes[node] {
+                SpatialNode::Leaf(leaf) => return leaf,
+                SpatialNode::Inner { axis, children } => {
+                    let mid = 0.5 * (min[*axis] + max[*axis]);
+                    if point[*axis] < mid {
+                        max[*axis] = mid;
+                        node = children[0];
+                    } else {
+                        min[*axis] = mid;
+                        node = children[1];
+                    }
+                }
+            }
+        }
+    }
+
+    /// the collected trees become the ones to sample, busy leaves are split in two and every
+    /// leaf gets a refined empty tree to collect into
+    fn refine(&mut self) {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        self.refine_node(0, 0, &mut nodes);
+        self.nodes = nodes;
+    }
+
+    fn refine_node(&self, node: usize, depth: usize, nodes: &mut Vec<SpatialNode>) -> usize {
+        let index = nodes.len();
+        match &self.nodes[node] {
+            SpatialNode::Inner { axis, children } => {
+                nodes.push(SpatialNode::Inner {
+                    axis: *axis,
+                    children: [0, 0],
+                });
+                let children = children.map(|child| self.refine_node(child, depth + 1, nodes));
+                nodes[index] = SpatialNode::Inner {
+                    axis: *axis,
+                    children,
+                };
+            }
+            SpatialNode::Leaf(leaf) => {
+                let refined = Leaf {
+                    sampling: leaf.collecting.clone(),
+                    collecting: leaf.collecting.refined(),
+                    samples: AtomicU64::default(),
+                };
+                if leaf.samples.load(Ordering::Relaxed) > SPLIT_SAMPLES && depth < MAX_SPATIAL_DEPTH
+                {
+                    let axis = depth % 3;
+                    nodes.push(SpatialNode::Inner {
+                        axis,
+                        children: [index + 1, index + 2],
+                    });
+                    nodes.push(SpatialNode::Leaf(Box::new(refined.clone())));
+                    nodes.push(SpatialNode::Leaf(Box::new(refined)));
+                } else {
+                    nodes.push(SpatialNode::Leaf(Box::new(refined)));
+                }
+            }
+        }
+        index
+    }
+}
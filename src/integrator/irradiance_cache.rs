@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use rand::Rng;
+
+use crate::{
+    bsdf::sampling::Frame,
+    hittable::{HitInfo, Hittable, World},
+    ray::Ray,
+    sampler::rng,
+    vec3::{consts::PI, Float, Vec3},
+};
+
+/// Ward's irradiance cache, for the path integrator's diffuse bounces after the first.
+///
+/// The indirect irradiance is only computed at a few points, each from a hemisphere of rays,
+/// and interpolated between them with its gradients (Ward and Heckbert 1992). points are added
+/// as the render runs, wherever the ones around are too far away or face another way: more of
+/// them in corners and near other objects, where the light changes quickly, and few on open
+/// walls. the interpolation blurs the indirect light a little, it's meant for quick previews
+/// of interiors where most of the light has bounced.
+///
+/// which points get a record depends on the order the threads ask for them, so seeded renders
+/// with the cache can differ slightly between runs.
+#[derive(Debug, Clone)]
+pub struct IrradianceCache {
+    /// the largest interpolation error to allow, Ward's a. smaller is more records and less blur
+    pub error: Float,
+    /// rays over the hemisphere of every record
+    pub rays: usize,
+
+    /// the smallest and largest radius a record covers, from the size of the scene
+    radius_range: (Float, Float),
+    records: Arc<RwLock<RecordGrid>>,
+}
+
+/// The indirect irradiance at a point and how it changes around it
+#[derive(Debug, Clone)]
+struct Record {
+    point: Vec3,
+    normal: Vec3,
+    irradiance: Vec3,
+    /// harmonic mean distance to what the hemisphere sees, how far the record reaches
+    radius: Float,
+    /// the gradients of each color channel, for turning the normal and moving the point
+    rotation: [Vec3; 3],
+    translation: [Vec3; 3],
+}
+
+#[derive(Debug, Default)]
+struct RecordGrid {
+    cell_size: Float,
+    cells: HashMap<(i64, i64, i64), Vec<Record>>,
+    len: usize,
+}
+
+impl IrradianceCache {
+    pub fn new(error: Float, rays: usize) -> IrradianceCache {
+        IrradianceCache {
+            error,
+            rays,
+            radius_range: (0.0, Float::INFINITY),
+            records: Arc::default(),
+        }
+    }
+
+    /// the number of records so far
+    pub fn len(&self) -> usize {
+        self.records.read().unwrap().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// forget the records of the last render and fit the record sizes to `world`
+    pub fn reset(&mut self, world: &World) {
+        let size = world.objects.bounding_box().extent().length();
+        self.radius_range = (0.005 * size, 0.1 * size);
+        // a record can't reach further than its cell's neighbours
+        let cell_size = (self.error * self.radius_range.1).max(1e-6);
+        self.records = Arc::new(RwLock::new(RecordGrid {
+            cell_size,
+            ..Default::default()
+        }));
+    }
+
+    /// the indirect irradiance at `hit_info` arriving around `normal`, interpolated from the
+    /// records nearby, or from a new one when they're too far. `estimate` returns the indirect
+    /// light along a ray and how far it went before hitting something
+    pub fn irradiance(
+        &self,
+        hit_info: &HitInfo,
+        normal: Vec3,
+        time: Float,
+        estimate: impl FnMut(&Ray) -> (Vec3, Float),
+    ) -> Vec3 {
+        if let Some(irradiance) = self.interpolate(hit_info.point, normal) {
+            return irradiance;
+        }
+        let record = self.compute(hit_info, normal, time, estimate);
+        let irradiance = record.irradiance;
+        self.records.write().unwrap().insert(record);
+        irradiance
+    }
+
+    fn interpolate(&self, point: Vec3, normal: Vec3) -> Option<Vec3> {
+        let records = self.records.read().unwrap();
+        let mut sum = Vec3::ZERO;
+        let mut total_weight = 0.0;
+        records.for_each_near(point, |record| {
+            let cos = normal.dot(record.normal);
+            if cos <= 0.01 {
+                return;
+            }
+            // a record in front of the point sees light the point doesn't
+            let offset = point - record.point;
+            if offset.dot(0.5 * (normal + record.normal)) < -0.05 * record.radius {
+                return;
+            }
+            let weight =
+                1.0 / (offset.length() / record.radius + (1.0 - cos).max(0.0).sqrt()).max(1e-6);
+            if weight <= 1.0 / self.error {
+                return;
+            }
+            let turn = record.normal.cross(normal);
+            let extrapolated = Vec3::new(
+                turn.dot(record.rotation[0]) + offset.dot(record.translation[0]),
+                turn.dot(record.rotation[1]) + offset.dot(record.translation[1]),
+                turn.dot(record.rotation[2]) + offset.dot(record.translation[2]),
+            );
+            sum += weight * (record.irradiance + extrapolated).max(Vec3::ZERO);
+            total_weight += weight;
+        });
+        (total_weight > 0.0).then(|| sum / total_weight)
+    }
+
+    /// a new record from `rays` rays stratified over the cosine weighted hemisphere, `m` rings
+    /// of `n` each
+    fn compute(
+        &self,
+        hit_info: &HitInfo,
+        normal: Vec3,
+        time: Float,
+        mut estimate: impl FnMut(&Ray) -> (Vec3, Float),
+    ) -> Record {
+        let m = ((self.rays as Float / PI).sqrt().round() as usize).max(2);
+        let n = (self.rays / m).max(3);
+        let frame = Frame::from_normal(normal);
+        let mut rng = rng();
+
+        // the light, distance and sine of every stratum, ring by ring
+        let mut light = vec![Vec3::ZERO; m * n];
+        let mut dist = vec![Float::INFINITY; m * n];
+        let mut sin = vec![0.0; m * n];
+        for j in 0..m {
+            for k in 0..n {
+                let sin2 = (j as Float + rng.gen::<Float>()) / m as Float;
+                let phi = 2.0 * PI * (k as Float + rng.gen::<Float>()) / n as Float;
+                let (s, c) = (sin2.sqrt(), (1.0 - sin2).max(0.0).sqrt());
+                let dir = frame.to_world(Vec3::new(s * phi.cos(), s * phi.sin(), c));
+                let (radiance, d) = estimate(&hit_info.spawn_ray(dir, time));
+                light[j * n + k] = radiance;
+                dist[j * n + k] = d;
+                sin[j * n + k] = s;
+            }
+        }
+
+        let strata = (m * n) as Float;
+        let irradiance = light.iter().copied().sum::<Vec3>() * PI / strata;
+        let inverse_sum: Float = dist.iter().map(|d| 1.0 / d).sum();
+        let radius = (strata / inverse_sum).clamp(self.radius_range.0, self.radius_range.1);
+
+        let mut rotation = [Vec3::ZERO; 3];
+        let mut translation = [Vec3::ZERO; 3];
+        for k in 0..n {
+            let phi = 2.0 * PI * (k as Float + 0.5) / n as Float;
+            let phi_edge = 2.0 * PI * k as Float / n as Float;
+            let u = frame.to_world(Vec3::new(phi.cos(), phi.sin(), 0.0));
+            let v = frame.to_world(Vec3::new(-phi.sin(), phi.cos(), 0.0));
+            let v_edge = frame.to_world(Vec3::new(-phi_edge.sin(), phi_edge.cos(), 0.0));
+            let previous_k = (k + n - 1) % n;
+
+            let mut turning = Vec3::ZERO;
+            let mut across_rings = Vec3::ZERO;
+            let mut across_wedges = Vec3::ZERO;
+            for j in 0..m {
+                let i = j * n + k;
+                let cos = (1.0 - sin[i] * sin[i]).max(1e-6).sqrt();
+                turning -= sin[i] / cos * light[i];
+
+                // between this ring and the one inside it
+                if j > 0 {
+                    let below = (j - 1) * n + k;
+                    let sin2 = j as Float / m as Float;
+                    let weight = sin2.sqrt() * (1.0 - sin2) / dist[i].min(dist[below]);
+                    across_rings += weight * (light[i] - light[below]);
+                }
+                // between this wedge and the previous one
+                let beside = j * n + previous_k;
+                let cos_inner = (1.0 - j as Float / m as Float).sqrt();
+                let cos_outer = (1.0 - (j + 1) as Float / m as Float).max(0.0).sqrt();
+                let weight =
+                    (cos_inner - cos_outer) / (sin[i].max(1e-6) * dist[i].min(dist[beside]));
+                across_wedges += weight * (light[i] - light[beside]);
+            }
+            across_rings *= 2.0 * PI / n as Float;
+            turning *= PI / strata;
+            for c in 0..3 {
+                rotation[c] += turning[c] * v;
+                translation[c] += across_rings[c] * u + across_wedges[c] * v_edge;
+            }
+        }
+
+        Record {
+            point: hit_info.point,
+            normal,
+            irradiance,
+            radius,
+            rotation,
+            translation,
+        }
+    }
+}
+
+impl Default for IrradianceCache {
+    fn default() -> Self {
+        Self::new(0.5, 128)
+    }
+}
+
+impl RecordGrid {
+    fn cell(&self, point: Vec3) -> (i64, i64, i64) {
+        let p = (point / self.cell_size).floor();
+        (p.x as i64, p.y as i64, p.z as i64)
+    }
+
+    fn insert(&mut self, record: Record) {
+        let cell = self.cell(record.point);
+        self.cells.entry(cell).or_default().push(record);
+        self.len += 1;
+    }
+
+    fn for_each_near(&self, point: Vec3, mut f: impl FnMut(&Record)) {
+        if self.cells.is_empty() {
+            return;
+        }
+        let (x, y, z) = self.cell(point);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(records) = self.cells.get(&(x + dx, y + dy, z + dz)) {
+                        records.iter().for_each(&mut f);
+                    }
+                }
+            }
+        }
+    }
+}
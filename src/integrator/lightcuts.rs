@@ -0,0 +1,403 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use rand::Rng;
+
+use crate::{
+    bsdf::sampling::Frame,
+    hittable::{HitInfo, World},
+    interval::Interval,
+    ray::Ray,
+    sampler::rng,
+    sampler::Sampler,
+    vec3::{Float, Vec3, VectorExt},
+};
+
+use super::Integrator;
+
+/// Direct light only, for fast previews of scenes with many lights.
+///
+/// Every light is replaced by `points_per_light` virtual point lights, which get clustered into a
+/// binary tree once before rendering. Each shading point then picks a cut through the tree
+/// (Walter et al.'s lightcuts): a cluster is lit by a single representative light scaled to the
+/// whole cluster's intensity, and gets split into its children while its error bound is more than
+/// `error` of the total. The point lights never move, so the noise is frozen into the image
+/// instead of flickering between samples.
+///
+/// Mirrors and glass are followed until the path reaches something rough, and the environment is
+/// picked up by one BSDF sample. Point lights already are points, they stay out of the tree and
+/// every shading point gets the light of each of them.
+#[derive(Debug, Clone)]
+pub struct LightcutsIntegrator {
+    pub points_per_light: usize,
+    /// relative error a cluster may contribute before it gets split, the paper uses 2%
+    pub error: Float,
+    /// most clusters evaluated per shading point
+    pub max_cut: usize,
+    pub max_depth: usize,
+
+    tree: Vec<LightNode>,
+}
+
+impl LightcutsIntegrator {
+    pub fn new(points_per_light: usize, error: Float) -> LightcutsIntegrator {
+        LightcutsIntegrator {
+            points_per_light,
+            error,
+            max_cut: 256,
+            max_depth: 16,
+            tree: Vec::new(),
+        }
+    }
+
+    /// cluster the lights top down, splitting along the longest axis at the median.
+    /// returns the index of the new node, children are always pushed before their parent
+    fn build(&mut self, mut lights: Vec<VirtualLight>) -> usize {
+        let (min, max) = lights
+            .iter()
+            .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), light| {
+                (min.min(light.point), max.max(light.point))
+            });
+        let intensity = lights.iter().map(|light| light.intensity).sum();
+        let normal = lights[0].normal;
+        let normal = lights
+            .iter()
+            .all(|light| light.normal.dot(normal).abs() > 0.9999)
+            .then_some(normal);
+
+        if lights.len() == 1 {
+            self.tree.push(LightNode {
+                min,
+                max,
+                intensity,
+                normal,
+                light: lights[0],
+                children: None,
+            });
+            return self.tree.len() - 1;
+        }
+
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        lights.sort_by(|a, b| a.point[axis].total_cmp(&b.point[axis]));
+        let right_lights = lights.split_off(lights.len() / 2);
+        let left = self.build(lights);
+        let right = self.build(right_lights);
+
+        // the representative is picked once, proportional to intensity, so it stays the same
+        // for every shading point
+        let (left_weight, right_weight) = (
+            self.tree[left].intensity.luminance(),
+            self.tree[right].intensity.luminance(),
+        );
+        let light = if rng().gen::<Float>() * (left_weight + right_weight) < left_weight {
+            self.tree[left].light
+        } else {
+            self.tree[right].light
+        };
+        self.tree.push(LightNode {
+            min,
+            max,
+            intensity,
+            normal,
+            light,
+            children: Some((left, right)),
+        });
+        self.tree.len() - 1
+    }
+
+    /// light arriving from `light` per unit of intensity, including the BSDF and visibility
+    fn unit_contribution(
+        &self,
+        light: &VirtualLight,
+        hit_info: &HitInfo,
+        view_dir: Vec3,
+        world: &World,
+        time: Float,
+    ) -> Vec3 {
+        let to_light = light.point - hit_info.point;
+        let dist_sq = to_light.length_squared();
+        let dir = to_light / dist_sq.sqrt();
+        let f = hit_info.mat.eval(view_dir, dir, hit_info);
+        if f == Vec3::ZERO {
+            return Vec3::ZERO;
+        }
+
+        let transmittance = world.transmittance(hit_info.spawn_origin(dir), light.point, time);
+        if transmittance == Vec3::ZERO {
+            return Vec3::ZERO;
+        }
+        // lights are two sided
+        f * transmittance * light.normal.dot(dir).abs() / dist_sq.max(1e-8)
+    }
+
+    /// rough upper bound of eval / cos over the sphere, from the BSDF towards the normal, away
+    /// from it and in the mirror direction, which covers diffuse, transmissive and glossy lobes
+    fn material_bound(&self, hit_info: &HitInfo, view_dir: Vec3) -> Float {
+        let normal = hit_info.shading_normal;
+        let mirror = 2.0 * view_dir.dot(normal) * normal - view_dir;
+        [normal, -normal, mirror]
+            .into_iter()
+            .map(|dir| {
+                let cos = dir.dot(normal).abs().max(1e-2);
+                hit_info.mat.eval(view_dir, dir, hit_info).max_element() / cos
+            })
+            .fold(0.0, Float::max)
+    }
+
+    /// upper bound on what the cluster could contribute: the brightest BSDF value, the largest
+    /// cosines at both ends and the distance to the closest point of the box
+    fn error_bound(&self, node: &LightNode, hit_info: &HitInfo, material_bound: Float) -> Float {
+        if node.children.is_none() {
+            return 0.0;
+        }
+        let closest = hit_info.point.clamp(node.min, node.max);
+        let dist_sq = closest.distance_squared(hit_info.point);
+        if dist_sq < 1e-8 {
+            return Float::INFINITY;
+        }
+
+        let cos = cos_bound(hit_info.shading_frame(), node, hit_info.point);
+        // lights are two sided, so only clusters facing one way bound their own cosine
+        let light_cos = node.normal.map_or(1.0, |normal| {
+            cos_bound(&Frame::from_normal(normal), node, hit_info.point)
+        });
+
+        node.intensity.max_element() * material_bound * cos * light_cos / dist_sq
+    }
+
+    /// direct light from the tree of virtual lights
+    fn lightcut(&self, hit_info: &HitInfo, view_dir: Vec3, world: &World, time: Float) -> Vec3 {
+        let Some(root) = self.tree.last() else {
+            return Vec3::ZERO;
+        };
+
+        let material_bound = self.material_bound(hit_info, view_dir);
+        let unit = self.unit_contribution(&root.light, hit_info, view_dir, world, time);
+        let mut total = unit * root.intensity;
+        let mut cut = BinaryHeap::new();
+        cut.push(CutEntry {
+            error: self.error_bound(root, hit_info, material_bound),
+            node: self.tree.len() - 1,
+            unit,
+        });
+
+        while cut.len() < self.max_cut {
+            let Some(entry) = cut.peek() else {
+                break;
+            };
+            if entry.error <= self.error * total.luminance() {
+                break;
+            }
+            let Some(entry) = cut.pop() else {
+                break;
+            };
+            let node = &self.tree[entry.node];
+            let Some((left, right)) = node.children else {
+                // leaves have no error, this only happens once the total is zero
+                cut.push(entry);
+                break;
+            };
+
+            total -= entry.unit * node.intensity;
+            for child in [left, right] {
+                let child_node = &self.tree[child];
+                // one of the children has the same representative, which saves a shadow ray
+                let unit = if child_node.light.point == node.light.point {
+                    entry.unit
+                } else {
+                    self.unit_contribution(&child_node.light, hit_info, view_dir, world, time)
+                };
+                total += unit * child_node.intensity;
+                cut.push(CutEntry {
+                    error: self.error_bound(child_node, hit_info, material_bound),
+                    node: child,
+                    unit,
+                });
+            }
+        }
+        total.max(Vec3::ZERO)
+    }
+
+    /// one BSDF sample which can only pick up the environment
+    fn environment_light(
+        &self,
+        hit_info: &HitInfo,
+        view_dir: Vec3,
+        world: &World,
+        time: Float,
+    ) -> Vec3 {
+        let ray = Ray::new(hit_info.point, -view_dir, time);
+        let Some(dir) = hit_info.mat.sample(&ray, hit_info) else {
+            return Vec3::ZERO;
+        };
+        let pdf = hit_info.mat.pdf(view_dir, dir, hit_info);
+        let ray = hit_info.spawn_ray(dir, time);
+        if pdf > 0.0 && world.intersect_all(&ray, Interval::FORWARD).is_none() {
+            hit_info.mat.eval(view_dir, dir, hit_info) / pdf * world.environment.sample(dir)
+        } else {
+            Vec3::ZERO
+        }
+    }
+}
+
+impl Default for LightcutsIntegrator {
+    fn default() -> Self {
+        Self::new(64, 0.02)
+    }
+}
+
+impl Integrator for LightcutsIntegrator {
+    fn name(&self) -> &str {
+        "lightcuts"
+    }
+
+    fn settings(&self) -> String {
+        format!(
+            "{} points per light, error {}, max cut {}, max depth {}",
+            self.points_per_light, self.error, self.max_cut, self.max_depth
+        )
+    }
+
+    fn preprocess(&mut self, world: &World) {
+        let n = self.points_per_light.max(1);
+        let mut lights = Vec::new();
+        for i in 0..world.lights.len() {
+            let light = world.lights.get(i);
+            for _ in 0..n {
+                let Some(sample) = light.sample_surface(rng().gen()) else {
+                    break;
+                };
+                let emission = sample.material.emitted(sample.u, sample.v, sample.point);
+                if sample.pdf > 0.0 && emission != Vec3::ZERO {
+                    lights.push(VirtualLight {
+                        point: sample.point,
+                        normal: sample.normal,
+                        intensity: emission / (sample.pdf * n as Float),
+                    });
+                }
+            }
+        }
+
+        self.tree.clear();
+        if !lights.is_empty() {
+            self.build(lights);
+        }
+    }
+
+    fn li(&self, ray: &Ray, world: &World, _sampler: &mut Sampler) -> Vec3 {
+        let mut radiance = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        let mut ray = *ray;
+        for _ in 0..self.max_depth {
+            let Some((hit_info, is_light)) = world.intersect_all(&ray, Interval::FORWARD) else {
+                radiance += throughput * world.environment.sample(ray.direction());
+                break;
+            };
+
+            let emission = hit_info.mat.emitted(hit_info.u, hit_info.v, hit_info.point);
+            radiance += throughput * emission;
+            if is_light {
+                break;
+            }
+
+            let view_dir = -ray.direction();
+            if !hit_info.mat.is_specular(&hit_info) {
+                let time = ray.time();
+                let direct = self.lightcut(&hit_info, view_dir, world, time)
+                    + world.delta_lighting(&hit_info, view_dir, time)
+                    + self.environment_light(&hit_info, view_dir, world, time);
+                radiance += throughput * direct;
+                break;
+            }
+
+            let Some(dir) = hit_info.mat.sample(&ray, &hit_info) else {
+                break;
+            };
+            let pdf = hit_info.mat.pdf(view_dir, dir, &hit_info);
+            if pdf <= 0.0 {
+                break;
+            }
+            throughput *= hit_info.mat.eval(view_dir, dir, &hit_info) / pdf;
+            ray = hit_info.spawn_ray(dir, ray.time());
+        }
+        radiance
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VirtualLight {
+    point: Vec3,
+    normal: Vec3,
+    /// emitted radiance times the area this point stands in for
+    intensity: Vec3,
+}
+
+/// A cluster of virtual lights, leaves hold a single light
+#[derive(Debug, Clone)]
+struct LightNode {
+    min: Vec3,
+    max: Vec3,
+    intensity: Vec3,
+    /// normal shared by all of the lights, if they are on the same plane
+    normal: Option<Vec3>,
+    /// representative light, one of the lights in the cluster
+    light: VirtualLight,
+    children: Option<(usize, usize)>,
+}
+
+/// largest |cos| between the z axis of `frame` and the direction from `point` to anywhere in
+/// the cluster's box, found from the box's bounds in the frame
+fn cos_bound(frame: &Frame, node: &LightNode, point: Vec3) -> Float {
+    let (min, max) = (0..8)
+        .map(|i| {
+            let corner = Vec3::new(
+                if i & 1 == 0 { node.min.x } else { node.max.x },
+                if i & 2 == 0 { node.min.y } else { node.max.y },
+                if i & 4 == 0 { node.min.z } else { node.max.z },
+            );
+            frame.to_local(corner - point)
+        })
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), p| {
+            (min.min(p), max.max(p))
+        });
+    let z = max.z.max(-min.z);
+    let xy = Vec3::ZERO.clamp(min, max).truncate().length_squared();
+    if xy > 0.0 {
+        z / (xy + z * z).sqrt()
+    } else {
+        1.0
+    }
+}
+
+/// A cluster in the cut, ordered by its error bound so the worst one gets split first
+struct CutEntry {
+    error: Float,
+    node: usize,
+    unit: Vec3,
+}
+
+impl PartialEq for CutEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CutEntry {}
+
+impl PartialOrd for CutEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CutEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.error.total_cmp(&other.error)
+    }
+}
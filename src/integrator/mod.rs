@@ -0,0 +1,96 @@
+use crate::{hittable::World, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+pub mod ao;
+pub use self::ao::*;
+
+pub mod debug;
+pub use self::debug::*;
+
+pub mod guiding;
+pub use self::guiding::*;
+
+pub mod irradiance_cache;
+pub use self::irradiance_cache::*;
+
+pub mod lightcuts;
+pub use self::lightcuts::*;
+
+pub mod path;
+pub use self::path::*;
+
+pub mod sppm;
+pub use self::sppm::*;
+
+pub mod toon;
+pub use self::toon::*;
+
+pub mod wavefront;
+pub use self::wavefront::*;
+
+/// A light transport algorithm. The camera generates rays and the integrator estimates how much
+/// light arrives along each of them, so downstream crates can plug their own algorithms
+/// (AO-only, stylized shading, debug views) into the existing scene and BVH infrastructure.
+pub trait Integrator: Send + Sync {
+    /// Short name recorded in the output's metadata
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    /// The main parameters in a few words, for the settings strip and the output's metadata
+    fn settings(&self) -> String {
+        String::new()
+    }
+
+    /// Called once before rendering starts, after the world's BVH has been built
+    fn preprocess(&mut self, _world: &World) {}
+
+    /// How many times the camera renders the whole image and averages the results, with
+    /// `samples_per_pixel` spread over the passes
+    fn passes(&self) -> usize {
+        1
+    }
+
+    /// Called before each pass, e.g. to trace a new photon map
+    fn begin_pass(&mut self, _world: &World, _pass: usize) {}
+
+    /// Estimate the radiance arriving at the ray's origin from the ray's direction
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut Sampler) -> Vec3;
+
+    /// `li` for many rays at once, the camera passes all the samples of a pixel together.
+    /// integrators that work in stages over the whole batch override it
+    fn li_batch(&self, rays: &[Ray], world: &World, sampler: &mut Sampler) -> Vec<Vec3> {
+        rays.iter()
+            .map(|ray| self.li(ray, world, sampler))
+            .collect()
+    }
+}
+
+impl Integrator for Box<dyn Integrator> {
+    fn name(&self) -> &str {
+        self.as_ref().name()
+    }
+
+    fn settings(&self) -> String {
+        self.as_ref().settings()
+    }
+
+    fn preprocess(&mut self, world: &World) {
+        self.as_mut().preprocess(world)
+    }
+
+    fn passes(&self) -> usize {
+        self.as_ref().passes()
+    }
+
+    fn begin_pass(&mut self, world: &World, pass: usize) {
+        self.as_mut().begin_pass(world, pass)
+    }
+
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut Sampler) -> Vec3 {
+        self.as_ref().li(ray, world, sampler)
+    }
+
+    fn li_batch(&self, rays: &[Ray], world: &World, sampler: &mut Sampler) -> Vec<Vec3> {
+        self.as_ref().li_batch(rays, world, sampler)
+    }
+}
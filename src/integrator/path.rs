@@ -0,0 +1,398 @@
+use crate::{
+    bsdf::testing::SamplingCheck,
+    hittable::{area_to_solid_angle, HitInfo, Hittable, World},
+    interval::Interval,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Float, Vec3, VectorExt},
+};
+
+use super::{Integrator, IrradianceCache, PathGuide};
+
+/// Unidirectional path tracer, with light sampling for the direct light at every bounce and BSDF
+/// sampling for the next bounce, weighed against each other with MIS
+#[derive(Debug, Clone)]
+pub struct PathIntegrator {
+    /// limit on the total number of bounces
+    pub max_depth: usize,
+    /// limits on the bounces of each kind, so glass can get many bounces without paying for
+    /// as many diffuse interreflections
+    pub max_diffuse_bounces: usize,
+    pub max_specular_bounces: usize,
+    pub max_transmission_bounces: usize,
+    /// shadow rays towards the lights at every bounce, spread over the lights in strata. more
+    /// of them is cheaper than more samples per pixel when most of the light is direct
+    pub light_samples: usize,
+    /// test the sampling of the materials that get hit and log the ones that are off
+    pub sampling_check: Option<SamplingCheck>,
+    /// interpolate the indirect light of the bounces after the first from sparse records
+    pub irradiance_cache: Option<IrradianceCache>,
+    /// learn where the light comes from over a few passes and sample the bounces towards it
+    pub guiding: Option<PathGuide>,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: usize) -> PathIntegrator {
+        PathIntegrator {
+            max_depth,
+            max_diffuse_bounces: max_depth,
+            max_specular_bounces: max_depth,
+            max_transmission_bounces: max_depth,
+            light_samples: 1,
+            sampling_check: None,
+            irradiance_cache: None,
+            guiding: None,
+        }
+    }
+
+    fn bounce_limit(&self, lobe: Lobe) -> usize {
+        match lobe {
+            Lobe::Diffuse => self.max_diffuse_bounces,
+            Lobe::Specular => self.max_specular_bounces,
+            Lobe::Transmission => self.max_transmission_bounces,
+        }
+    }
+
+    /// the radiance along `ray` and how far it went before its first hit. `indirect` leaves
+    /// out the lights it hits first, for the records of the irradiance cache, which count them
+    /// with light sampling at the point they're used
+    fn trace(
+        &self,
+        ray: &Ray,
+        world: &World,
+        sampler: &mut Sampler,
+        indirect: bool,
+    ) -> (Vec3, Float) {
+        let min_bounces = 5; // TODO make min_bounces a parameter
+
+        let mut radiance = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        let mut ray = *ray;
+        // bounces of each kind of lobe so far
+        let mut counts = [0; 3];
+        let mut bounce = None;
+        let mut first_dist = Float::INFINITY;
+        // the bounces to teach the guide the light that came back along them
+        let mut guided = vec![];
+        for bounces in 0..self.max_depth {
+            let Some((hit_info, is_light)) = world.intersect_all(&ray, Interval::FORWARD) else {
+                radiance += throughput * world.environment.sample(ray.direction());
+                break;
+            };
+            if bounces == 0 {
+                first_dist = hit_info.dist;
+            }
+
+            // emission from object that we just hit, less what light sampling already counted
+            let emission = hit_info.mat.emitted(hit_info.u, hit_info.v, hit_info.point);
+            if emission != Vec3::ZERO && !(indirect && bounces == 0 && is_light) {
+                let weight = emission_weight(bounce, &hit_info, is_light, world, ray.time());
+                radiance += throughput * emission * weight;
+            }
+
+            // delta lights can't be reached by the scattered ray, they get a shadow ray of their own
+            radiance += throughput * world.delta_lighting(&hit_info, -ray.direction(), ray.time());
+
+            if let Some(check) = &self.sampling_check {
+                check.check(&ray, &hit_info);
+            }
+
+            // past the first bounce, rough surfaces take the light that bounced before reaching
+            // them from the irradiance cache, and only the direct light is traced
+            if let Some(cache) = self.irradiance_cache.as_ref().filter(|_| {
+                !indirect
+                    && bounces > 0
+                    && bounces + 1 < self.max_depth
+                    && !hit_info.mat.is_specular(&hit_info)
+            }) {
+                let direct = self.direct_light(&hit_info, &ray, world, sampler, &counts, None);
+                let normal = hit_info.shading_normal;
+                let f = hit_info.mat.eval(-ray.direction(), normal, &hit_info);
+                let irradiance = cache.irradiance(&hit_info, normal, ray.time(), |ray| {
+                    self.trace(ray, world, &mut Sampler::new(), true)
+                });
+                radiance += throughput * (direct + f * irradiance);
+                break;
+            }
+
+            // russian roulette
+            if bounces > min_bounces {
+                let p = throughput.luminance().clamp(0.01, 1.0);
+                if sampler.next_1d() > p {
+                    break;
+                }
+                throughput /= p;
+            }
+
+            let view_dir = -ray.direction();
+            let guide = self
+                .guiding
+                .as_ref()
+                .filter(|_| !indirect && !hit_info.mat.is_specular(&hit_info))
+                .map(|guide| (guide.cell(hit_info.point), guide.mix));
+            // the guide only samples once it learned something there
+            let mix = guide
+                .as_ref()
+                .filter(|(cell, _)| cell.is_trained())
+                .map(|(_, mix)| *mix);
+            // the materials only make sense on the side their samples go, so the guide's samples
+            // through the surface end the path and only the BSDF gets there
+            let bsdf_pdf = |dir: Vec3| {
+                let pdf = hit_info.mat.pdf(view_dir, dir, &hit_info);
+                match (&guide, mix) {
+                    (Some(_), Some(mix)) if dir.dot(hit_info.geometric_normal) < 0.0 => {
+                        (1.0 - mix) * pdf
+                    }
+                    (Some((cell, _)), Some(mix)) => mix * cell.pdf(dir) + (1.0 - mix) * pdf,
+                    _ => pdf,
+                }
+            };
+
+            // the light that light sampling finds is what the next bounce would have hit, so it
+            // keeps to the same limits
+            if bounces + 1 < self.max_depth {
+                let direct =
+                    self.direct_light(&hit_info, &ray, world, sampler, &counts, Some(&bsdf_pdf));
+                radiance += throughput * direct;
+            }
+
+            let dir = match (&guide, mix) {
+                (Some((cell, _)), Some(mix)) if sampler.next_1d() < mix => {
+                    Some(cell.sample(sampler.next_2d()))
+                        .filter(|dir| dir.dot(hit_info.geometric_normal) > 0.0)
+                }
+                _ => hit_info.mat.sample(&ray, &hit_info),
+            };
+            let Some(dir) = dir else {
+                break;
+            };
+            let lobe = Lobe::classify(&hit_info, dir);
+            counts[lobe as usize] += 1;
+            if counts[lobe as usize] > self.bounce_limit(lobe) {
+                break;
+            }
+            let bsdf_pdf = bsdf_pdf(dir);
+            if bsdf_pdf <= 0.0 {
+                break;
+            }
+            let brdf = hit_info.mat.eval(view_dir, dir, &hit_info);
+            if brdf == Vec3::ZERO {
+                break;
+            }
+            throughput *= brdf / bsdf_pdf;
+            if let Some((cell, _)) = guide {
+                guided.push((cell, dir, bsdf_pdf, throughput, radiance));
+            }
+            bounce = Some(Bounce {
+                origin: hit_info.point,
+                object_id: hit_info.object_id,
+                bsdf_pdf,
+                light_samples: self.light_samples.max(1),
+            });
+            ray = hit_info.spawn_ray(dir, ray.time());
+        }
+
+        // what came back along a bounce is what the path found after it, over its throughput
+        for (cell, dir, pdf, throughput, before) in guided {
+            let throughput = throughput.luminance();
+            if throughput > 0.0 {
+                cell.record(dir, (radiance - before).luminance() / throughput, pdf);
+            }
+        }
+        (radiance, first_dist)
+    }
+
+    /// the direct light at `hit_info` from `light_samples` shadow rays, one stratum of the
+    /// lights each, weighed against the next bounce picking the same direction with `bsdf_pdf`.
+    /// without it it's all of the light, for when the path ends there
+    fn direct_light(
+        &self,
+        hit_info: &HitInfo,
+        ray: &Ray,
+        world: &World,
+        sampler: &mut Sampler,
+        counts: &[usize; 3],
+        bsdf_pdf: Option<&dyn Fn(Vec3) -> Float>,
+    ) -> Vec3 {
+        let light_samples = self.light_samples.max(1);
+        let mut radiance = Vec3::ZERO;
+        for i in 0..light_samples {
+            let u = (i as Float + sampler.next_1d()) / light_samples as Float;
+            let direct = sample_direct_light(
+                hit_info,
+                -ray.direction(),
+                world,
+                ray.time(),
+                u,
+                light_samples,
+                bsdf_pdf,
+            );
+            if let Some((dir, light)) = direct {
+                let lobe = Lobe::classify(hit_info, dir);
+                if counts[lobe as usize] < self.bounce_limit(lobe) {
+                    radiance += light;
+                }
+            }
+        }
+        radiance
+    }
+}
+
+/// Where a path bounced from, how likely the BSDF was to pick its direction and how many light
+/// samples were taken there, for weighing the light it runs into against light sampling
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Bounce {
+    pub origin: Vec3,
+    /// the object it bounced off, which the light it runs into might not light
+    pub object_id: u32,
+    pub bsdf_pdf: Float,
+    pub light_samples: usize,
+}
+
+/// the direct light at `hit_info` from a point on the light `u` picks behind a shadow ray,
+/// weighed against the bounce picking the same direction with `bsdf_pdf` with the balance
+/// heuristic (when there is one), as one of `light_samples` samples. the direction comes back
+/// too, none without a light or when it's blocked
+pub(super) fn sample_direct_light(
+    hit_info: &HitInfo,
+    view_dir: Vec3,
+    world: &World,
+    time: Float,
+    u: Float,
+    light_samples: usize,
+    bsdf_pdf: Option<&dyn Fn(Vec3) -> Float>,
+) -> Option<(Vec3, Vec3)> {
+    let sample =
+        world
+            .lights
+            .sample_light_for(u, hit_info.point, time, Some(hit_info.object_id))?;
+    let light_pdf = sample.pdf_solid_angle(hit_info.point);
+    if sample.emission == Vec3::ZERO || !(light_pdf > 0.0 && light_pdf.is_finite()) {
+        return None;
+    }
+    let dir = (sample.point - hit_info.point).try_normalize()?;
+    let f = hit_info.mat.eval(view_dir, dir, hit_info);
+    if f == Vec3::ZERO {
+        return None;
+    }
+    let f = f * world.transmittance(hit_info.spawn_origin(dir), sample.point, time);
+    if f == Vec3::ZERO {
+        return None;
+    }
+    let bsdf_pdf = bsdf_pdf.map_or(0.0, |pdf| pdf(dir));
+    let light_samples = light_samples as Float;
+    Some((
+        dir,
+        f * sample.emission / (light_samples * light_pdf + bsdf_pdf),
+    ))
+}
+
+/// how much of the light the path finds at `hit_info` is left to count after light sampling
+/// counted its share at the last bounce. all of it for camera rays and emitters that aren't
+/// lights, none when the light doesn't light the object the path bounced off
+pub(super) fn emission_weight(
+    bounce: Option<Bounce>,
+    hit_info: &HitInfo,
+    is_light: bool,
+    world: &World,
+    time: Float,
+) -> Float {
+    let Some(bounce) = bounce.filter(|_| is_light) else {
+        return 1.0;
+    };
+    if let Some(link) = &hit_info.light_link {
+        if !link.lights(bounce.object_id) {
+            return 0.0;
+        }
+    }
+    let pdf_area = world.lights.light_pdf(bounce.origin, hit_info.point, time);
+    let light_pdf = area_to_solid_angle(
+        pdf_area,
+        bounce.origin,
+        hit_info.point,
+        hit_info.geometric_normal,
+    );
+    bounce.bsdf_pdf / (bounce.bsdf_pdf + bounce.light_samples as Float * light_pdf)
+}
+
+/// The kind of scattering a bounce went through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lobe {
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+impl Lobe {
+    /// classifies the bounce from `hit_info` towards `dir`: leaving through the surface is
+    /// transmission, and otherwise it depends on whether the material is near-mirror smooth
+    pub fn classify(hit_info: &HitInfo, dir: Vec3) -> Lobe {
+        if dir.dot(hit_info.geometric_normal) < 0.0 {
+            Lobe::Transmission
+        } else if hit_info.mat.is_specular(hit_info) {
+            Lobe::Specular
+        } else {
+            Lobe::Diffuse
+        }
+    }
+}
+
+impl Default for PathIntegrator {
+    fn default() -> Self {
+        Self::new(50)
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn name(&self) -> &str {
+        "path"
+    }
+
+    fn preprocess(&mut self, world: &World) {
+        if let Some(cache) = &mut self.irradiance_cache {
+            cache.reset(world);
+        }
+        if let Some(guide) = &mut self.guiding {
+            guide.reset(world);
+        }
+    }
+
+    fn passes(&self) -> usize {
+        self.guiding.as_ref().map_or(1, |guide| guide.passes.max(1))
+    }
+
+    fn begin_pass(&mut self, _world: &World, pass: usize) {
+        if let Some(guide) = self.guiding.as_mut().filter(|_| pass > 0) {
+            guide.refine();
+        }
+    }
+
+    fn settings(&self) -> String {
+        let mut settings = format!("max depth {}", self.max_depth);
+        if self.light_samples > 1 {
+            settings += &format!(", {} light samples", self.light_samples);
+        }
+        if let Some(cache) = &self.irradiance_cache {
+            settings += &format!(", irradiance cache error {}", cache.error);
+        }
+        if let Some(guide) = &self.guiding {
+            settings += &format!(", guided over {} passes", guide.passes);
+        }
+        let limits = [
+            self.max_diffuse_bounces,
+            self.max_specular_bounces,
+            self.max_transmission_bounces,
+        ];
+        if limits.iter().any(|&limit| limit < self.max_depth) {
+            settings += &format!(
+                ", diffuse {} specular {} transmission {}",
+                limits[0], limits[1], limits[2]
+            );
+        }
+        settings
+    }
+
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut Sampler) -> Vec3 {
+        self.trace(ray, world, sampler, false).0
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::{
+    bsdf::sampling::{cosine_sample_hemisphere, to_world},
+    hittable::{HitInfo, Hittable, World},
+    interval::Interval,
+    ray::{offset_origin, rounding_error, Ray},
+    sampler::Sampler,
+    vec3::{consts::PI, Float, Vec3, VectorExt},
+};
+
+use super::Integrator;
+
+/// Stochastic progressive photon mapping, for caustics the path tracer can't converge.
+///
+/// Every pass traces a new photon map from the lights, and camera paths are followed through
+/// specular surfaces until they reach something rough enough to estimate the photon density on.
+/// The gather radius shrinks after every pass so the blur from density estimation fades as the
+/// passes are averaged (Knaus and Zwicker's probabilistic formulation, which needs no per-pixel state).
+///
+/// Direct light is sampled at the gather point and only photons that bounced at least once are
+/// stored. The environment and point lights only light the gather point directly, they don't
+/// emit photons, so there are no caustics of point lights.
+#[derive(Debug, Clone)]
+pub struct SppmIntegrator {
+    pub photons_per_pass: usize,
+    pub passes: usize,
+    /// gather radius of the first pass, by default 1/100 of the size of the scene
+    pub initial_radius: Option<Float>,
+    /// between 0 and 1, the fraction of photons each pass keeps, smaller shrinks the radius faster
+    pub alpha: Float,
+    pub max_depth: usize,
+
+    radius: Float,
+    photons: PhotonGrid,
+}
+
+impl SppmIntegrator {
+    pub fn new(photons_per_pass: usize, passes: usize) -> SppmIntegrator {
+        SppmIntegrator {
+            photons_per_pass,
+            passes,
+            initial_radius: None,
+            alpha: 2.0 / 3.0,
+            max_depth: 16,
+            radius: 0.0,
+            photons: PhotonGrid::default(),
+        }
+    }
+
+    /// follow a photon from a random point on a light, returning every diffuse hit after the first
+    fn trace_photon(&self, world: &World, sampler: &mut Sampler) -> Vec<Photon> {
+        let mut photons = Vec::new();
+        let time = sampler.next_1d();
+        let Some(light) = world.lights.sample_surface(time) else {
+            return photons;
+        };
+
+        // lights emit from both sides, so each side gets half of the photons
+        let normal = if sampler.next_1d() < 0.5 {
+            light.normal
+        } else {
+            -light.normal
+        };
+        let dir = to_world(normal, cosine_sample_hemisphere());
+        let emission = light.material.emitted(light.u, light.v, light.point);
+        // emission * cos / (area pdf * cos / pi / 2)
+        let mut flux = emission * 2.0 * PI / light.pdf;
+        let origin = offset_origin(light.point, rounding_error(light.point), normal);
+        let mut ray = Ray::new(origin, dir, time);
+
+        for bounce in 0..self.max_depth {
+            let Some((hit_info, is_light)) = world.intersect_all(&ray, Interval::FORWARD) else {
+                break;
+            };
+            if is_light {
+                break;
+            }
+            if bounce > 0 && !hit_info.mat.is_specular(&hit_info) {
+                photons.push(Photon {
+                    point: hit_info.point,
+                    dir: ray.direction(),
+                    flux,
+                });
+            }
+
+            let Some(dir) = hit_info.mat.sample(&ray, &hit_info) else {
+                break;
+            };
+            let pdf = hit_info.mat.pdf(-ray.direction(), dir, &hit_info);
+            let weight = hit_info.mat.eval(-ray.direction(), dir, &hit_info) / pdf;
+            if pdf <= 0.0 || !weight.is_finite() {
+                break;
+            }
+
+            // russian roulette, keeping the flux of the surviving photons about the same
+            let survive = weight.luminance().clamp(0.0, 1.0);
+            if sampler.next_1d() >= survive {
+                break;
+            }
+            flux *= weight / survive;
+
+            ray = hit_info.spawn_ray(dir, time);
+        }
+        photons
+    }
+
+    /// light sampling for direct light and the point lights, plus one BSDF sample that can only
+    /// pick up the environment
+    fn direct_light(&self, hit_info: &HitInfo, view_dir: Vec3, world: &World, time: Float) -> Vec3 {
+        let mut light = world.delta_lighting(hit_info, view_dir, time);
+
+        if let Some(sample) = world.lights.sample_light(hit_info.point, time) {
+            let pdf = sample.pdf_solid_angle(hit_info.point);
+            let dir = (sample.point - hit_info.point).normalize();
+            if pdf > 0.0 {
+                let origin = hit_info.spawn_origin(dir);
+                let transmittance = world.transmittance(origin, sample.point, time);
+                light +=
+                    hit_info.mat.eval(view_dir, dir, hit_info) * transmittance * sample.emission
+                        / pdf;
+            }
+        }
+
+        let ray = Ray::new(hit_info.point, -view_dir, time);
+        if let Some(dir) = hit_info.mat.sample(&ray, hit_info) {
+            let pdf = hit_info.mat.pdf(view_dir, dir, hit_info);
+            let ray = hit_info.spawn_ray(dir, time);
+            if pdf > 0.0 && world.intersect_all(&ray, Interval::FORWARD).is_none() {
+                let weight = hit_info.mat.eval(view_dir, dir, hit_info) / pdf;
+                light += weight * world.environment.sample(dir);
+            }
+        }
+        light
+    }
+
+    /// radiance from the photons within the gather radius
+    fn photon_density(&self, hit_info: &HitInfo, view_dir: Vec3) -> Vec3 {
+        let mut sum = Vec3::ZERO;
+        self.photons
+            .for_each_near(hit_info.point, self.radius, |photon| {
+                let light_dir = -photon.dir;
+                // photons on the other side of the surface don't count
+                if light_dir.dot(hit_info.geometric_normal) <= 0.0 {
+                    return;
+                }
+                // eval includes the cosine, which the photon density already accounts for
+                let cos = light_dir.dot(hit_info.shading_normal).abs();
+                if cos > 1e-4 {
+                    sum += hit_info.mat.eval(view_dir, light_dir, hit_info) / cos * photon.flux;
+                }
+            });
+        sum / (PI * self.radius * self.radius * self.photons_per_pass.max(1) as Float)
+    }
+}
+
+impl Default for SppmIntegrator {
+    fn default() -> Self {
+        Self::new(100_000, 16)
+    }
+}
+
+impl Integrator for SppmIntegrator {
+    fn name(&self) -> &str {
+        "sppm"
+    }
+
+    fn settings(&self) -> String {
+        format!(
+            "{} passes of {} photons, alpha {}, max depth {}",
+            self.passes, self.photons_per_pass, self.alpha, self.max_depth
+        )
+    }
+
+    fn preprocess(&mut self, world: &World) {
+        self.radius = self
+            .initial_radius
+            .unwrap_or_else(|| 0.01 * world.objects.bounding_box().extent().length());
+    }
+
+    fn passes(&self) -> usize {
+        self.passes.max(1)
+    }
+
+    fn begin_pass(&mut self, world: &World, pass: usize) {
+        if pass > 0 {
+            let i = pass as Float;
+            self.radius *= ((i + self.alpha) / (i + 1.0)).sqrt();
+        }
+
+        let photons: Vec<Photon> = (0..self.photons_per_pass)
+            .into_par_iter()
+            .flat_map_iter(|_| self.trace_photon(world, &mut Sampler::new()))
+            .collect();
+        self.photons = PhotonGrid::new(photons, self.radius);
+    }
+
+    fn li(&self, ray: &Ray, world: &World, _sampler: &mut Sampler) -> Vec3 {
+        let mut radiance = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        let mut ray = *ray;
+        for _ in 0..self.max_depth {
+            let Some((hit_info, is_light)) = world.intersect_all(&ray, Interval::FORWARD) else {
+                radiance += throughput * world.environment.sample(ray.direction());
+                break;
+            };
+
+            let emission = hit_info.mat.emitted(hit_info.u, hit_info.v, hit_info.point);
+            radiance += throughput * emission;
+            if is_light {
+                break;
+            }
+
+            let view_dir = -ray.direction();
+            if !hit_info.mat.is_specular(&hit_info) {
+                let direct = self.direct_light(&hit_info, view_dir, world, ray.time());
+                radiance += throughput * (direct + self.photon_density(&hit_info, view_dir));
+                break;
+            }
+
+            // mirrors and glass are followed until the path reaches something rough
+            let Some(dir) = hit_info.mat.sample(&ray, &hit_info) else {
+                break;
+            };
+            let pdf = hit_info.mat.pdf(view_dir, dir, &hit_info);
+            if pdf <= 0.0 {
+                break;
+            }
+            throughput *= hit_info.mat.eval(view_dir, dir, &hit_info) / pdf;
+            ray = hit_info.spawn_ray(dir, ray.time());
+        }
+        radiance
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Photon {
+    point: Vec3,
+    /// direction the photon was travelling in when it landed
+    dir: Vec3,
+    flux: Vec3,
+}
+
+/// Photons hashed into cubic cells as large as the gather radius, so a lookup visits 27 cells
+#[derive(Debug, Clone, Default)]
+struct PhotonGrid {
+    cell_size: Float,
+    cells: HashMap<(i64, i64, i64), Vec<Photon>>,
+}
+
+impl PhotonGrid {
+    fn new(photons: Vec<Photon>, cell_size: Float) -> PhotonGrid {
+        let mut grid = PhotonGrid {
+            cell_size,
+            cells: HashMap::new(),
+        };
+        for photon in photons {
+            let cell = grid.cell(photon.point);
+            grid.cells.entry(cell).or_default().push(photon);
+        }
+        grid
+    }
+
+    fn cell(&self, point: Vec3) -> (i64, i64, i64) {
+        let p = (point / self.cell_size).floor();
+        (p.x as i64, p.y as i64, p.z as i64)
+    }
+
+    fn for_each_near(&self, point: Vec3, radius: Float, mut f: impl FnMut(&Photon)) {
+        if self.cells.is_empty() {
+            return;
+        }
+        let (x, y, z) = self.cell(point);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(photons) = self.cells.get(&(x + dx, y + dy, z + dz)) else {
+                        continue;
+                    };
+                    for photon in photons {
+                        if photon.point.distance_squared(point) <= radius * radius {
+                            f(photon);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
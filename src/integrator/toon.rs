@@ -0,0 +1,124 @@
+use crate::{
+    hittable::World,
+    interval::Interval,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{consts::PI, Float, Vec3},
+};
+
+use super::Integrator;
+
+/// Cel shading: the direct lighting from each light is quantized into a few flat bands.
+/// Every light is treated as a point light at the center of its bounding box, so the bands stay
+/// crisp instead of turning into noise. Mirrors and glass are followed until something diffuse is hit.
+#[derive(Debug, Clone)]
+pub struct ToonIntegrator {
+    pub bands: usize,
+    /// brightness of the parts facing away from every light
+    pub ambient: Float,
+    /// direction towards the key light, used when the world has no lights
+    pub key_direction: Vec3,
+    pub max_depth: usize,
+}
+
+impl ToonIntegrator {
+    pub fn new(bands: usize, ambient: Float) -> ToonIntegrator {
+        ToonIntegrator {
+            bands,
+            ambient,
+            key_direction: Vec3::new(1.0, 2.0, 1.0).normalize(),
+            max_depth: 8,
+        }
+    }
+
+    fn quantize(&self, x: Float) -> Float {
+        let bands = self.bands.max(1) as Float;
+        (x.clamp(0.0, 1.0) * bands).ceil() / bands
+    }
+
+    fn key_light(&self, point: Vec3, normal: Vec3, world: &World, time: Float) -> Vec3 {
+        if world.lights.is_empty() && world.delta_lights.is_empty() {
+            return Vec3::ONE * self.quantize(normal.dot(self.key_direction));
+        }
+
+        let mut light = Vec3::ZERO;
+        for i in 0..world.lights.len() {
+            let object = world.lights.get(i);
+            let center = object.bounding_box().centroid();
+            let cos = normal.dot((center - point).normalize());
+            if cos <= 0.0 || !world.shadow_ray(point, center, time) {
+                continue;
+            }
+            // only the hue of the light matters, its strength is replaced by the band
+            let emission = object
+                .material()
+                .map_or(Vec3::ONE, |mat| mat.emitted(0.5, 0.5, center));
+            let tint = emission / emission.max_element().max(1e-8);
+            light += tint * self.quantize(cos);
+        }
+        for object in &world.delta_lights {
+            let Some(sample) = object.sample_li(point) else {
+                continue;
+            };
+            let cos = normal.dot(sample.direction);
+            if cos <= 0.0 || !world.shadow_ray(point, sample.point, time) {
+                continue;
+            }
+            let tint = sample.radiance / sample.radiance.max_element().max(1e-8);
+            light += tint * self.quantize(cos);
+        }
+        light
+    }
+}
+
+impl Default for ToonIntegrator {
+    fn default() -> Self {
+        Self::new(3, 0.2)
+    }
+}
+
+impl Integrator for ToonIntegrator {
+    fn name(&self) -> &str {
+        "toon"
+    }
+
+    fn settings(&self) -> String {
+        format!("{} bands, max depth {}", self.bands, self.max_depth)
+    }
+
+    fn li(&self, ray: &Ray, world: &World, _sampler: &mut Sampler) -> Vec3 {
+        let mut ray = *ray;
+        for _ in 0..self.max_depth {
+            let Some((hit_info, is_light)) = world.intersect_all(&ray, Interval::FORWARD) else {
+                return world.environment.sample(ray.direction());
+            };
+
+            if is_light || hit_info.mat.is_emissive() {
+                let emission = hit_info.mat.emitted(hit_info.u, hit_info.v, hit_info.point);
+                return emission / emission.max_element().max(1.0);
+            }
+
+            // flat color of the surface, as the reflectance towards the viewer under head-on light
+            let view_dir = -ray.direction();
+            let albedo = (hit_info
+                .mat
+                .eval(view_dir, hit_info.shading_normal, &hit_info)
+                * PI)
+                .clamp(Vec3::ZERO, Vec3::ONE);
+
+            if albedo.max_element() < 1e-3 {
+                // a perfectly specular surface, keep following the reflected or refracted ray
+                let Some(dir) = hit_info.mat.sample(&ray, &hit_info) else {
+                    return Vec3::ZERO;
+                };
+                ray = hit_info.spawn_ray(dir, ray.time());
+                continue;
+            }
+
+            let origin = hit_info.spawn_origin(hit_info.geometric_normal);
+            let light = self.key_light(origin, hit_info.shading_normal, world, ray.time());
+            return albedo * (Vec3::splat(self.ambient) + light * (1.0 - self.ambient));
+        }
+        Vec3::ZERO
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use crate::{
+    hittable::{HitInfo, World},
+    interval::Interval,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Vec3, VectorExt},
+};
+
+use super::{
+    path::{emission_weight, sample_direct_light, Bounce},
+    Integrator,
+};
+
+/// The same estimator as `PathIntegrator` (MIS between light and BSDF sampling, russian
+/// roulette after a few bounces), but run on a whole batch of camera rays at once. Every
+/// bounce goes through the stages one after the other: intersect all the live paths, shade the
+/// hits grouped by material, then compact the queue down to the paths that are still going.
+/// Keeping each stage in its own tight loop is kinder to the caches than following one path to
+/// the end, and the stages are what a SIMD or GPU version would share.
+#[derive(Debug, Clone)]
+pub struct WavefrontIntegrator {
+    /// limit on the total number of bounces
+    pub max_depth: usize,
+}
+
+/// one path in flight
+#[derive(Clone, Copy)]
+struct PathState {
+    ray: Ray,
+    throughput: Vec3,
+    /// index of the camera ray the path started as
+    index: usize,
+    /// none for camera rays
+    bounce: Option<Bounce>,
+}
+
+const MIN_BOUNCES: usize = 5;
+
+impl WavefrontIntegrator {
+    pub fn new(max_depth: usize) -> WavefrontIntegrator {
+        WavefrontIntegrator { max_depth }
+    }
+
+    /// closest hit of every path and whether it's a light, none for the ones that escape
+    fn intersect(&self, paths: &[PathState], world: &World) -> Vec<Option<(HitInfo, bool)>> {
+        paths
+            .iter()
+            .map(|path| world.intersect_all(&path.ray, Interval::FORWARD))
+            .collect()
+    }
+
+    /// add what the path sees at `hit_info` to its radiance and pick the next bounce, none
+    /// once the path ends
+    fn shade(
+        &self,
+        mut path: PathState,
+        (hit_info, is_light): (&HitInfo, bool),
+        bounces: usize,
+        world: &World,
+        sampler: &mut Sampler,
+        radiance: &mut Vec3,
+    ) -> Option<PathState> {
+        let ray = path.ray;
+        let emission = hit_info.mat.emitted(hit_info.u, hit_info.v, hit_info.point);
+        if emission != Vec3::ZERO {
+            let weight = emission_weight(path.bounce, hit_info, is_light, world, ray.time());
+            *radiance += path.throughput * emission * weight;
+        }
+        *radiance += path.throughput * world.delta_lighting(hit_info, -ray.direction(), ray.time());
+
+        if bounces > MIN_BOUNCES {
+            let p = path.throughput.luminance().clamp(0.01, 1.0);
+            if sampler.next_1d() > p {
+                return None;
+            }
+            path.throughput /= p;
+        }
+
+        if bounces + 1 < self.max_depth {
+            let u = sampler.next_1d();
+            let view_dir = -ray.direction();
+            let bsdf_pdf = |dir| hit_info.mat.pdf(view_dir, dir, hit_info);
+            if let Some((_, light)) =
+                sample_direct_light(hit_info, view_dir, world, ray.time(), u, 1, Some(&bsdf_pdf))
+            {
+                *radiance += path.throughput * light;
+            }
+        }
+
+        let dir = hit_info.mat.sample(&ray, hit_info)?;
+        let bsdf_pdf = hit_info.mat.pdf(-ray.direction(), dir, hit_info);
+        if bsdf_pdf <= 0.0 {
+            return None;
+        }
+        let brdf = hit_info.mat.eval(-ray.direction(), dir, hit_info);
+        path.throughput *= brdf / bsdf_pdf;
+        path.bounce = Some(Bounce {
+            origin: hit_info.point,
+            object_id: hit_info.object_id,
+            bsdf_pdf,
+            light_samples: 1,
+        });
+        path.ray = hit_info.spawn_ray(dir, ray.time());
+        Some(path)
+    }
+}
+
+impl Default for WavefrontIntegrator {
+    fn default() -> Self {
+        Self::new(50)
+    }
+}
+
+impl Integrator for WavefrontIntegrator {
+    fn name(&self) -> &str {
+        "wavefront"
+    }
+
+    fn settings(&self) -> String {
+        format!("max depth {}", self.max_depth)
+    }
+
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut Sampler) -> Vec3 {
+        self.li_batch(std::slice::from_ref(ray), world, sampler)[0]
+    }
+
+    fn li_batch(&self, rays: &[Ray], world: &World, sampler: &mut Sampler) -> Vec<Vec3> {
+        let mut radiance = vec![Vec3::ZERO; rays.len()];
+        // generate
+        let mut queue: Vec<PathState> = rays
+            .iter()
+            .enumerate()
+            .map(|(index, &ray)| PathState {
+                ray,
+                throughput: Vec3::ONE,
+                index,
+                bounce: None,
+            })
+            .collect();
+
+        for bounces in 0..self.max_depth {
+            if queue.is_empty() {
+                break;
+            }
+            let hits = self.intersect(&queue, world);
+
+            // escaped paths pick up the environment and are done
+            let mut shading = Vec::with_capacity(queue.len());
+            for (path, hit) in queue.drain(..).zip(hits) {
+                match hit {
+                    Some(hit) => shading.push((path, hit)),
+                    None => {
+                        let environment = world.environment.sample(path.ray.direction());
+                        radiance[path.index] += path.throughput * environment;
+                    }
+                }
+            }
+
+            // hits on the same material are shaded one after another
+            shading.sort_unstable_by_key(|(_, (hit_info, _))| {
+                Arc::as_ptr(&hit_info.mat) as *const () as usize
+            });
+            for (path, (hit_info, is_light)) in shading {
+                let index = path.index;
+                let next = self.shade(
+                    path,
+                    (&hit_info, is_light),
+                    bounces,
+                    world,
+                    sampler,
+                    &mut radiance[index],
+                );
+                // compact: only the paths that go on stay in the queue
+                queue.extend(next);
+            }
+        }
+        radiance
+    }
+}
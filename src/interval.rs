@@ -1,37 +1,42 @@
+use crate::vec3::Float;
+
 #[derive(Copy, Clone)]
 pub struct Interval {
-    pub min: f64,
-    pub max: f64,
+    pub min: Float,
+    pub max: Float,
 }
 
 impl Interval {
-    pub const EMPTY: Interval = Interval::new(f64::INFINITY, f64::NEG_INFINITY);
-    pub const UNIVERSE: Interval = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+    pub const EMPTY: Interval = Interval::new(Float::INFINITY, Float::NEG_INFINITY);
+    pub const UNIVERSE: Interval = Interval::new(Float::NEG_INFINITY, Float::INFINITY);
+    /// all of a ray in front of its origin. rays leaving a surface start off it already (see
+    /// `HitInfo::spawn_origin`), so they don't need to skip a bit of it
+    pub const FORWARD: Interval = Interval::new(0.0, Float::INFINITY);
 
-    pub const fn new(min: f64, max: f64) -> Interval {
+    pub const fn new(min: Float, max: Float) -> Interval {
         Interval { min, max }
     }
 
     pub fn bound(a: Interval, b: Interval) -> Interval {
         Interval {
-            min: f64::min(a.min, b.min),
-            max: f64::max(a.max, b.max),
+            min: Float::min(a.min, b.min),
+            max: Float::max(a.max, b.max),
         }
     }
 
-    pub fn size(&self) -> f64 {
+    pub fn size(&self) -> Float {
         self.max - self.min
     }
 
-    pub fn contains(&self, x: f64) -> bool {
+    pub fn contains(&self, x: Float) -> bool {
         self.min <= x && x <= self.max
     }
 
-    pub fn surrounds(&self, x: f64) -> bool {
+    pub fn surrounds(&self, x: Float) -> bool {
         self.min < x && x < self.max
     }
 
-    pub fn expand(&self, delta: f64) -> Interval {
+    pub fn expand(&self, delta: Float) -> Interval {
         let padding = delta / 2.0;
         Interval {
             min: self.min - padding,
@@ -43,8 +48,8 @@ impl Interval {
 impl Default for Interval {
     fn default() -> Interval {
         Interval {
-            min: f64::NEG_INFINITY,
-            max: f64::INFINITY,
+            min: Float::NEG_INFINITY,
+            max: Float::INFINITY,
         }
     }
 }
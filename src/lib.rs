@@ -1,10 +1,43 @@
+pub mod aov;
+pub mod bench;
+pub mod blue_noise;
 pub mod bsdf;
 pub mod camera;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod color;
+pub mod contact_sheet;
+pub mod distributed;
+pub mod distribution;
+pub mod environment;
+pub mod error;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod hittable;
+pub mod image_metrics;
+pub mod inspect;
+pub mod integrator;
 pub mod interval;
+pub mod light_rig;
+pub mod lookdev;
 pub mod material;
+pub mod metadata;
+pub mod overlay;
 pub mod ray;
+pub mod ray_stats;
+pub mod sampler;
+pub mod scene;
+pub mod shadow_map;
+pub mod sky;
 pub mod texture;
+pub mod texture_cache;
+pub mod texture_nodes;
 pub mod utils;
 pub mod vec3;
-pub mod volume;
+
+// what most programs using the crate start from
+pub use camera::{Camera, CancelToken, FilmBuffer};
+pub use hittable::World;
+pub use integrator::Integrator;
+pub use scene::{load_scene, parse_scene, MaterialRegistry, Scene};
+pub use vec3::{Float, Vec3};
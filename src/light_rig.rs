@@ -0,0 +1,100 @@
+//! A conventional three-point lighting setup, as a starting point for lighting imported models.
+
+use std::sync::Arc;
+
+use crate::{
+    camera::Camera,
+    hittable::{Hittable, Quad, World},
+    integrator::Integrator,
+    material::DiffuseLight,
+    vec3::{Float, Vec3},
+};
+
+/// Key, fill and rim area lights around a target, placed relative to the camera: the key light
+/// in front from the left and above, a larger and dimmer fill light from the right, and a rim
+/// light high up behind the target, out of view, to separate it from the background.
+///
+/// Distances and sizes are in multiples of the radius of the target's bounding sphere, so the
+/// lighting looks the same whatever the scale of the model.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreePointRig {
+    /// radiance of the key light
+    pub key_intensity: Float,
+    /// fill light radiance as a fraction of the key's
+    pub fill_ratio: Float,
+    /// rim light radiance as a fraction of the key's
+    pub rim_ratio: Float,
+    /// how far the lights are from the target's center, in target radii
+    pub distance: Float,
+}
+
+impl ThreePointRig {
+    pub fn new(key_intensity: Float, fill_ratio: Float, rim_ratio: Float) -> ThreePointRig {
+        ThreePointRig {
+            key_intensity,
+            fill_ratio,
+            rim_ratio,
+            distance: 4.0,
+        }
+    }
+
+    /// the key, fill and rim lights for `target` seen from `camera`
+    pub fn lights<I: Integrator>(&self, target: &dyn Hittable, camera: &Camera<I>) -> [Quad; 3] {
+        let bbox = target.bounding_box();
+        let center = bbox.centroid();
+        let radius = (0.5 * bbox.extent().length()).max(1e-3);
+
+        // the camera's frame, with forward pointing from the target to the camera
+        let forward = (camera.look_from - center)
+            .try_normalize()
+            .unwrap_or(Vec3::Z);
+        let right = camera.vup.cross(forward).try_normalize().unwrap_or(Vec3::X);
+        let up = forward.cross(right);
+
+        // (direction from the target, size in radii, radiance)
+        let lights = [
+            (forward - right + 0.8 * up, 1.5, self.key_intensity),
+            (
+                forward + 1.2 * right + 0.2 * up,
+                2.5,
+                self.key_intensity * self.fill_ratio,
+            ),
+            (
+                -forward + 0.3 * right + 1.5 * up,
+                1.0,
+                self.key_intensity * self.rim_ratio,
+            ),
+        ];
+        lights.map(|(dir, size, intensity)| {
+            let dir = dir.normalize();
+            // a square facing the target, lights are two sided so its winding doesn't matter
+            let u = up.cross(dir).normalize() * size * radius;
+            let v = dir.cross(u).normalize() * size * radius;
+            let q = center + dir * self.distance * radius - 0.5 * (u + v);
+            Quad::new(
+                q,
+                u,
+                v,
+                Arc::new(DiffuseLight::from_rgb(Vec3::splat(intensity))),
+            )
+        })
+    }
+
+    /// add the lights for `target` to `world`, call before building its BVH
+    pub fn add_to<I: Integrator>(
+        &self,
+        world: &mut World,
+        target: &dyn Hittable,
+        camera: &Camera<I>,
+    ) {
+        for light in self.lights(target, camera) {
+            world.add_light(light);
+        }
+    }
+}
+
+impl Default for ThreePointRig {
+    fn default() -> Self {
+        Self::new(12.0, 0.4, 1.5)
+    }
+}
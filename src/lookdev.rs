@@ -0,0 +1,196 @@
+//! Look development: a standard shader ball in a neutral studio, for judging a material on its own.
+
+use std::sync::Arc;
+
+use image::RgbImage;
+
+use crate::{
+    bsdf::{diffuse::DiffuseBRDF, principled::PrincipledBSDF, MatPtr},
+    camera::Camera,
+    environment::EnvironmentType,
+    hittable::{HittableList, Instance, Quad, TriangleMesh, World},
+    material::DiffuseLight,
+    texture::SolidTexture,
+    vec3::{consts::PI, Float, Vec2, Vec3},
+};
+
+/// bright magenta, for the placeholders of assets that failed to load
+pub fn missing_material() -> MatPtr {
+    Arc::new(DiffuseBRDF::from_rgb(Vec3::new(1.0, 0.0, 1.0)))
+}
+
+/// the neutral 18% grey, a plain dielectric principled material, that meshes without a material
+/// of their own get
+pub fn default_material() -> MatPtr {
+    Arc::new(PrincipledBSDF::new(
+        Arc::new(SolidTexture::new(Vec3::splat(0.18))),
+        0.0,
+        0.5,
+        0.0,
+        0.5,
+        0.0,
+        1.5,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+    ))
+}
+
+/// revolve a profile of (radius, height) points around the y axis, from bottom to top.
+/// normals are smoothed along the profile, so sharp corners need two points at the same place
+pub fn lathe(profile: &[Vec2], segments: usize, material: MatPtr) -> TriangleMesh {
+    let rings = profile.len();
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    for s in 0..=segments {
+        let phi = s as Float / segments as Float * 2.0 * PI;
+        let (sin, cos) = phi.sin_cos();
+        for i in 0..rings {
+            let p = profile[i];
+            let tangent = profile[(i + 1).min(rings - 1)] - profile[i.saturating_sub(1)];
+            // outward for a profile that goes up
+            let n = Vec2::new(tangent.y, -tangent.x).normalize_or_zero();
+            vertices.push(Vec3::new(p.x * cos, p.y, p.x * sin));
+            normals.push(Vec3::new(n.x * cos, n.y, n.x * sin));
+            uvs.push((
+                s as Float / segments as Float,
+                i as Float / (rings - 1) as Float,
+            ));
+        }
+    }
+
+    let mut indices = vec![];
+    for s in 0..segments {
+        for i in 0..rings - 1 {
+            let a = s * rings + i;
+            let b = (s + 1) * rings + i;
+            indices.extend([a, a + 1, b, b, a + 1, b + 1]);
+        }
+    }
+    TriangleMesh::from_buffers(&vertices, &normals, &uvs, &indices, material)
+}
+
+/// a ball with a groove around it on a small pedestal, standing on y = 0 and about 2.2 units tall.
+/// the groove shows how the material looks in occluded creases and reflecting itself
+pub fn shader_ball(material: MatPtr, pedestal: MatPtr) -> HittableList {
+    let radius = 1.0;
+    let center = 1.2;
+    let mut ball = vec![];
+    for i in 0..=64 {
+        let angle = (i as Float / 64.0 - 0.5) * PI;
+        let groove = (10.0..20.0).contains(&angle.to_degrees());
+        let r = if groove { 0.9 * radius } else { radius };
+        ball.push(Vec2::new(r * angle.cos(), center + r * angle.sin()));
+    }
+    let base = [
+        Vec2::new(0.0, 0.0),
+        Vec2::new(0.7, 0.0),
+        Vec2::new(0.7, 0.08),
+        Vec2::new(0.7, 0.08),
+        Vec2::new(0.4, 0.15),
+        Vec2::new(0.3, 0.25),
+        Vec2::new(0.3, 0.5),
+        Vec2::new(0.0, 0.5),
+    ];
+
+    let mut objects = HittableList::new();
+    objects.add(lathe(&ball, 96, material));
+    objects.add(lathe(&base, 48, pedestal));
+    objects
+}
+
+/// floor curving up into a back wall, so there is no horizon line behind the ball
+pub fn backdrop(material: MatPtr) -> TriangleMesh {
+    let (half_width, depth, cove, height) = (8.0, 4.0, 2.0, 8.0);
+    // profile in (z, y), from the front of the floor to the top of the wall
+    let mut profile = vec![Vec2::new(depth, 0.0)];
+    for i in 0..=16 {
+        let angle = i as Float / 16.0 * 0.5 * PI;
+        profile.push(Vec2::new(
+            -depth + cove - cove * angle.sin(),
+            cove - cove * angle.cos(),
+        ));
+    }
+    profile.push(Vec2::new(-depth, height));
+
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for (i, p) in profile.iter().enumerate() {
+        vertices.push(Vec3::new(-half_width, p.y, p.x));
+        vertices.push(Vec3::new(half_width, p.y, p.x));
+        if i > 0 {
+            let (a, b) = (2 * (i - 1), 2 * i);
+            indices.extend([a, a + 1, b, b, a + 1, b + 1]);
+        }
+    }
+    TriangleMesh::from_buffers(&vertices, &[], &[], &indices, material)
+}
+
+/// the shader ball turned by `angle` radians, on a grey backdrop under a soft key light and a neutral grey sky
+pub fn lookdev_world(material: MatPtr, angle: Float) -> World {
+    let mut world = World::new();
+    let grey = Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(0.5)));
+    let dark = Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(0.1)));
+
+    let ball = shader_ball(material, dark);
+    world.add_object(Instance::new(Arc::new(ball), Vec3::Y, angle, Vec3::ZERO));
+    world.add_object(backdrop(grey));
+
+    let key = Arc::new(DiffuseLight::from_rgb(Vec3::splat(8.0)));
+    world.add_light(Quad::new(
+        Vec3::new(-4.0, 6.0, 1.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, -1.0, 2.0),
+        key,
+    ));
+    world.environment = EnvironmentType::Color(Vec3::splat(0.3));
+    world.build_bvh();
+    world
+}
+
+/// square camera framing the shader ball
+pub fn lookdev_camera(size: usize, spp: usize) -> Camera {
+    let mut camera = Camera::new();
+    camera.aspect_ratio = 1.0;
+    camera.image_width = size;
+    camera.samples_per_pixel = spp;
+    camera.integrator.max_depth = 16;
+    camera.vfov = 30.0;
+    camera.look_from = Vec3::new(0.0, 2.0, 6.5);
+    camera.look_at = Vec3::new(0.0, 1.1, 0.0);
+    camera.vup = Vec3::Y;
+    camera.focal_length = 6.5;
+    camera.defocus_angle = 0.0;
+    camera.init();
+    camera
+}
+
+/// render the standard 256 x 256 preview of `material`
+pub fn render_material_preview(material: MatPtr) -> RgbImage {
+    render_material_preview_with(material, 0.0, 256, 64)
+}
+
+/// render a preview with the ball turned by `angle` radians, e.g. to make a turntable
+pub fn render_material_preview_with(
+    material: MatPtr,
+    angle: Float,
+    size: usize,
+    spp: usize,
+) -> RgbImage {
+    let world = lookdev_world(material, angle);
+    let mut camera = lookdev_camera(size, spp);
+    camera.render_image(&world)
+}
+
+/// `frames` previews with the ball making one full turn
+pub fn render_turntable(material: MatPtr, frames: usize, size: usize, spp: usize) -> Vec<RgbImage> {
+    (0..frames)
+        .map(|i| {
+            let angle = i as Float / frames as Float * 2.0 * PI;
+            render_material_preview_with(material.clone(), angle, size, spp)
+        })
+        .collect()
+}
@@ -1,17 +1,47 @@
-use clap::Parser;
-use std::{env, sync::Arc};
+use clap::{Parser, Subcommand};
+use std::{
+    env,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
 
 use path_tracer::{
-    bsdf::{diffuse::DiffuseBRDF, glass::GlassBSDF, metal::MetalBRDF, principled::PrincipledBSDF},
-    camera::{Camera, EnvironmentType},
-    hittable::{Cuboid, Instance, Quad, Sphere, TriangleMesh, World},
+    aov::aov_filename,
+    bench::run_bench,
+    bsdf::{
+        diffuse::DiffuseBRDF,
+        glass::GlassBSDF,
+        metal::MetalBRDF,
+        principled::PrincipledBSDF,
+        testing::{builtin_bsdfs, check_bsdf},
+        BxDFMaterial,
+    },
+    camera::{Autofocus, BackgroundPriority, Camera, CancelToken},
+    contact_sheet::{linspace, sweep_scene_file, ContactSheet},
+    distributed::{render_distributed, serve, RenderJob},
+    environment::EnvironmentType,
+    hittable::{Cuboid, Hittable, Instance, Quad, Sphere, TriangleMesh, World},
+    image_metrics,
+    inspect::inspect_asset,
+    integrator::Integrator,
+    lookdev::render_material_preview_with,
     material::DiffuseLight,
+    metadata::RenderInfo,
+    scene::{
+        load_scene, load_scene_with_overrides, reload_scene, FileWatcher, MaterialRegistry, Scene,
+    },
+    shadow_map::{DepthMap, LightView},
     texture::{CheckerTexture, ImageTexture, SolidTexture},
-    vec3::{random_vector, random_vector_range, Vec3},
+    texture_cache::TextureCache,
+    vec3::{consts::PI, random_vector, random_vector_range, Float, Vec2, Vec3},
 };
 use rand::{thread_rng, Rng};
+use rayon::ThreadPoolBuilder;
 
-fn balls_scene(width: usize, spp: usize) {
+fn balls_scene(settings: &Overrides) {
     let mut world = World::new();
 
     let tex1 = SolidTexture::new(Vec3::new(0.2, 0.3, 0.1));
@@ -36,10 +66,14 @@ fn balls_scene(width: usize, spp: usize) {
     world.add_object(Sphere::new_still(1.0, Vec3::new(4.0, 1.0, 0.0), mat3));
 
     let mut rng = rand::thread_rng();
-    for a in (-11..11).map(|x| x as f64) {
-        for b in (-11..11).map(|x| x as f64) {
-            let choose_mat = rng.gen::<f64>();
-            let center = Vec3::new(a + 0.9 * rng.gen::<f64>(), 0.2, b + 0.9 * rng.gen::<f64>());
+    for a in (-11..11).map(|x| x as Float) {
+        for b in (-11..11).map(|x| x as Float) {
+            let choose_mat = rng.gen::<Float>();
+            let center = Vec3::new(
+                a + 0.9 * rng.gen::<Float>(),
+                0.2,
+                b + 0.9 * rng.gen::<Float>(),
+            );
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     let albedo = random_vector() * random_vector();
@@ -62,26 +96,24 @@ fn balls_scene(width: usize, spp: usize) {
 
     let mut camera = Camera::new();
     camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = width;
-    camera.samples_per_pixel = spp;
-    camera.max_depth = 50;
+    camera.integrator.max_depth = settings.max_depth.unwrap_or(50);
 
     camera.vfov = 20.0;
     camera.look_from = Vec3::new(13.0, 2.0, 3.0);
     camera.look_at = Vec3::ZERO;
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
 
-    camera.blur_strength = 0.5;
     camera.focal_length = 10.0;
     camera.defocus_angle = 0.6;
 
-    camera.environment = EnvironmentType::Color(Vec3::new(0.7, 0.8, 1.0));
+    world.environment = EnvironmentType::Color(Vec3::new(0.7, 0.8, 1.0));
 
+    settings.apply(&mut camera);
     camera.init();
-    camera.render(&world, "demo/balls.png");
+    camera.render(&world, &settings.output("demo/balls.png"));
 }
 
-fn earth_scene(width: usize, spp: usize) {
+fn earth_scene(settings: &Overrides) {
     let mut world = World::new();
 
     let earth_texture = ImageTexture::new("assets/earthmap.jpg");
@@ -112,26 +144,26 @@ fn earth_scene(width: usize, spp: usize) {
 
     let mut camera = Camera::new();
     camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = width;
-    camera.samples_per_pixel = spp;
-    camera.max_depth = 50;
+    camera.integrator.max_depth = settings.max_depth.unwrap_or(50);
 
     camera.vfov = 28.0;
     camera.look_from = Vec3::new(8.8, 2.0, 3.0);
     camera.look_at = Vec3::ZERO;
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
 
-    camera.blur_strength = 0.5;
-    camera.focal_length = 2.869817807;
+    camera.focal_length = 2.869818;
+    // focus on the earth wherever it is
+    camera.autofocus = Some(Autofocus::Pick(Vec2::new(0.13, 0.6)));
     camera.defocus_angle = 2.5;
 
-    camera.environment = EnvironmentType::Color(Vec3::new(0.85, 0.85, 1.0));
+    world.environment = EnvironmentType::Color(Vec3::new(0.85, 0.85, 1.0));
 
+    settings.apply(&mut camera);
     camera.init();
-    camera.render(&world, "demo/earth.png");
+    camera.render(&world, &settings.output("demo/earth.png"));
 }
 
-fn cornell_box_scene(width: usize, spp: usize) {
+fn cornell_box_scene(settings: &Overrides) {
     let mut world = World::new();
 
     let red = Arc::new(DiffuseBRDF::from_rgb(Vec3::new(0.65, 0.05, 0.05)));
@@ -216,26 +248,24 @@ fn cornell_box_scene(width: usize, spp: usize) {
     world.build_bvh();
     let mut camera = Camera::new();
     camera.aspect_ratio = 1.0;
-    camera.image_width = width;
-    camera.samples_per_pixel = spp;
-    camera.max_depth = 50;
+    camera.integrator.max_depth = settings.max_depth.unwrap_or(50);
 
     camera.vfov = 40.0;
     camera.look_from = Vec3::new(278.0, 278.0, -800.0);
     camera.look_at = Vec3::new(278.0, 278.0, 0.0);
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
 
-    camera.blur_strength = 0.5;
     camera.focal_length = 10.0;
     camera.defocus_angle = 0.0;
 
-    camera.environment = EnvironmentType::Color(Vec3::ZERO);
+    world.environment = EnvironmentType::Color(Vec3::ZERO);
 
+    settings.apply(&mut camera);
     camera.init();
-    camera.render(&world, "demo/cornell.png");
+    camera.render(&world, &settings.output("demo/cornell.png"));
 }
 
-fn environment_map_scene(width: usize, spp: usize) {
+fn environment_map_scene(settings: &Overrides) {
     let mut world = World::new();
 
     let my_mat = Arc::new(MetalBRDF::from_rgb(Vec3::ONE, 0.001));
@@ -253,33 +283,31 @@ fn environment_map_scene(width: usize, spp: usize) {
 
     let mut camera = Camera::new();
     camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = width;
-    camera.samples_per_pixel = spp;
-    camera.max_depth = 50;
+    camera.integrator.max_depth = settings.max_depth.unwrap_or(50);
 
     camera.vfov = 90.0;
     camera.look_from = Vec3::new(0.0, 3.0, 17.0);
     camera.look_at = Vec3::new(0.0, 2.0, 0.0);
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
 
-    camera.blur_strength = 0.5;
     camera.focal_length = 17.0;
     camera.defocus_angle = 1.5;
 
     let env_map = ImageTexture::new("assets/grace_probe_latlong.hdr");
-    camera.environment = EnvironmentType::Map(Arc::new(env_map));
+    world.environment = EnvironmentType::Map(Arc::new(env_map));
 
+    settings.apply(&mut camera);
     camera.init();
-    camera.render(&world, "demo/lights.png");
+    camera.render(&world, &settings.output("demo/lights.png"));
 }
 
-fn bsdf_demo_scene(width: usize, spp: usize) {
+fn bsdf_demo_scene(settings: &Overrides) {
     let mut world = World::new();
 
     // Diffuse with varying roughness
     for i in 0..5 {
         let color_tex = Arc::new(SolidTexture::new(Vec3::new(0.65, 0.05, 0.05)));
-        let roughness = 0.1 + 0.2 * i as f64;
+        let roughness = 0.1 + 0.2 * i as Float;
         let mat = Arc::new(PrincipledBSDF::new(
             color_tex, // base_color,
             0.00,      // metallic,
@@ -294,7 +322,7 @@ fn bsdf_demo_scene(width: usize, spp: usize) {
             0.01,      // clearcoat,
             0.01,      // clearcoat_gloss,
         ));
-        let position = Vec3::new(-4.0 + i as f64, 1.0, -5.0);
+        let position = Vec3::new(-4.0 + i as Float, 1.0, -5.0);
         let sphere = Sphere::new_still(0.5, position, mat);
         world.add_object(sphere);
     }
@@ -302,7 +330,7 @@ fn bsdf_demo_scene(width: usize, spp: usize) {
     // Metal with varying roughness
     for i in 0..5 {
         let color_tex = Arc::new(SolidTexture::new(Vec3::new(0.05, 0.65, 0.05)));
-        let roughness = 0.1 + 0.2 * i as f64;
+        let roughness = 0.1 + 0.2 * i as Float;
         let mat = Arc::new(PrincipledBSDF::new(
             color_tex, // base_color,
             0.99,      // metallic,
@@ -317,7 +345,7 @@ fn bsdf_demo_scene(width: usize, spp: usize) {
             0.01,      // clearcoat,
             0.01,      // clearcoat_gloss,
         ));
-        let position = Vec3::new(-4.0 + i as f64, 2.0, -5.0);
+        let position = Vec3::new(-4.0 + i as Float, 2.0, -5.0);
         let sphere = Sphere::new_still(0.5, position, mat);
         world.add_object(sphere);
     }
@@ -325,7 +353,7 @@ fn bsdf_demo_scene(width: usize, spp: usize) {
     // Glass with varying roughness
     for i in 0..5 {
         let color_tex = Arc::new(SolidTexture::new(Vec3::new(0.25, 0.05, 0.65)));
-        let roughness = (0.1 + 0.2 * i as f64) * 0.3;
+        let roughness = (0.1 + 0.2 * i as Float) * 0.3;
         let mat = Arc::new(PrincipledBSDF::new(
             color_tex, // base_color,
             0.01,      // metallic,
@@ -340,7 +368,7 @@ fn bsdf_demo_scene(width: usize, spp: usize) {
             0.01,      // clearcoat,
             0.01,      // clearcoat_gloss,
         ));
-        let position = Vec3::new(-4.0 + i as f64, 3.0, -5.0);
+        let position = Vec3::new(-4.0 + i as Float, 3.0, -5.0);
         let sphere = Sphere::new_still(0.5, position, mat);
         world.add_object(sphere);
     }
@@ -349,26 +377,24 @@ fn bsdf_demo_scene(width: usize, spp: usize) {
 
     let mut camera = Camera::new();
     camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = width;
-    camera.samples_per_pixel = spp;
-    camera.max_depth = 50;
+    camera.integrator.max_depth = settings.max_depth.unwrap_or(50);
 
     camera.vfov = 60.0;
     camera.look_from = Vec3::new(-2.0, 2.0, -1.0);
     camera.look_at = camera.look_from + Vec3::new(0.0, 0.0, -1000.0);
     camera.vup = Vec3::Y;
 
-    camera.blur_strength = 0.5;
     camera.focal_length = 5.0;
     camera.defocus_angle = 0.0;
 
-    camera.environment = EnvironmentType::Map(Arc::new(ImageTexture::new("assets/envmap.jpg")));
+    world.environment = EnvironmentType::Map(Arc::new(ImageTexture::new("assets/envmap.jpg")));
 
+    settings.apply(&mut camera);
     camera.init();
-    camera.render(&world, "demo/bsdf.png");
+    camera.render(&world, &settings.output("demo/bsdf.png"));
 }
 
-fn everything_scene(width: usize, spp: usize) {
+fn everything_scene(settings: &Overrides) {
     let mut world = World::new();
 
     let tex1 = SolidTexture::new(Vec3::new(0.2, 0.3, 0.1));
@@ -426,7 +452,7 @@ fn everything_scene(width: usize, spp: usize) {
     world.add_object(Instance::new(
         Arc::new(TriangleMesh::from_obj(10.0, bunny_mesh, bunny_material).unwrap()),
         Vec3::Y,
-        3.14,
+        PI,
         Vec3::new(0.1, -0.327, 5.0),
     ));
 
@@ -509,33 +535,31 @@ fn everything_scene(width: usize, spp: usize) {
 
     let mut camera = Camera::new();
     camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = width;
-    camera.samples_per_pixel = spp;
-    camera.max_depth = 50;
+    camera.integrator.max_depth = settings.max_depth.unwrap_or(50);
 
     camera.vfov = 60.0;
     camera.look_from = Vec3::new(0.0, 1.5, 0.0);
     camera.look_at = Vec3::new(0.0, 1.5, 100000.0);
     camera.vup = Vec3::Y;
 
-    camera.blur_strength = 0.5;
     camera.focal_length = 6.0;
     camera.defocus_angle = 1.0;
 
-    camera.environment = EnvironmentType::Map(Arc::new(ImageTexture::new(
+    world.environment = EnvironmentType::Map(Arc::new(ImageTexture::new(
         "assets/grace_probe_latlong.hdr",
         // "assets/envmap.jpg",
     )));
 
+    settings.apply(&mut camera);
     camera.init();
-    camera.render(&world, "demo/scene6.png");
+    camera.render(&world, &settings.output("demo/scene6.png"));
 }
 
-fn normal_demo_scene(width: usize, spp: usize) {
+fn normal_demo_scene(settings: &Overrides) {
     let mut world = World::new();
 
     let bricks_albedo = Arc::new(ImageTexture::new("assets/bricks/color.png"));
-    let bricks_normal = ImageTexture::new("assets/bricks/normal.png");
+    let bricks_normal = Arc::new(ImageTexture::new("assets/bricks/normal.png"));
     let material_with_normal = Arc::new(DiffuseBRDF::from_textures(
         bricks_albedo.clone(),
         Some(bricks_normal),
@@ -598,48 +622,803 @@ fn normal_demo_scene(width: usize, spp: usize) {
     world.build_bvh();
     let mut camera = Camera::new();
     camera.aspect_ratio = 1.0;
-    camera.image_width = width;
-    camera.samples_per_pixel = spp;
-    camera.max_depth = 50;
+    camera.integrator.max_depth = settings.max_depth.unwrap_or(50);
 
     camera.vfov = 40.0;
     camera.look_from = Vec3::new(278.0, 278.0, -800.0);
     camera.look_at = Vec3::new(278.0, 278.0, 0.0);
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
 
-    camera.blur_strength = 0.5;
     camera.focal_length = 10.0;
     camera.defocus_angle = 0.0;
 
-    camera.environment = EnvironmentType::Color(Vec3::ZERO);
+    world.environment = EnvironmentType::Color(Vec3::ZERO);
 
+    settings.apply(&mut camera);
     camera.init();
-    camera.render(&world, "demo/normals.png");
+    camera.render(&world, &settings.output("demo/normals.png"));
+}
+
+fn lookdev_scene(settings: &Overrides) {
+    let materials: Vec<(&str, Arc<dyn BxDFMaterial>)> = vec![
+        (
+            "diffuse",
+            Arc::new(DiffuseBRDF::from_rgb(Vec3::new(0.65, 0.05, 0.05))),
+        ),
+        (
+            "metal",
+            Arc::new(MetalBRDF::new(
+                Arc::new(SolidTexture::new(Vec3::new(0.9, 0.6, 0.3))),
+                Arc::new(SolidTexture::new(0.2)),
+            )),
+        ),
+        (
+            "glass",
+            Arc::new(GlassBSDF::new(
+                Arc::new(SolidTexture::new(Vec3::ONE)),
+                Arc::new(SolidTexture::new(0.001)),
+                0.0,
+                1.5,
+            )),
+        ),
+        (
+            "checker",
+            Arc::new(DiffuseBRDF::new(Arc::new(CheckerTexture::new(
+                0.2,
+                Arc::new(SolidTexture::new(Vec3::splat(0.1))),
+                Arc::new(SolidTexture::new(Vec3::splat(0.9))),
+            )))),
+        ),
+    ];
+
+    let width = settings.width.unwrap_or(600);
+    let spp = settings.spp.unwrap_or(100);
+    let mut sheet = ContactSheet::new(materials.len());
+    for (name, mat) in materials {
+        let preview = render_material_preview_with(mat, 0.0, width / 2, spp);
+        sheet.add(name, preview);
+    }
+    if let Err(err) = sheet.assemble().save(settings.output("demo/lookdev.png")) {
+        log::error!("Failed to save image {err}");
+    }
+}
+
+/// Render settings from the command line, applied over the ones the scene sets
+#[derive(Debug, Clone, Default)]
+struct Overrides {
+    width: Option<usize>,
+    height: Option<usize>,
+    aspect: Option<Float>,
+    spp: Option<usize>,
+    max_depth: Option<usize>,
+    output: Option<String>,
+    seed: Option<u64>,
+    stamp: bool,
+    crop: Option<Vec<usize>>,
+    keep_size: bool,
+    /// numbers of the scene's named materials, as `gold.roughness` and the value
+    materials: Vec<(String, Float)>,
+    cancel: Option<CancelToken>,
+}
+
+impl Overrides {
+    fn new(args: &Args) -> Overrides {
+        Overrides {
+            width: args.width,
+            height: args.height,
+            aspect: args.aspect,
+            spp: args.spp,
+            max_depth: args.max_depth,
+            output: args.output.clone(),
+            seed: args.seed,
+            stamp: args.stamp,
+            crop: args.crop.clone(),
+            keep_size: args.keep_size,
+            materials: args.set_material.clone(),
+            cancel: None,
+        }
+    }
+
+    /// the numbers the integrator is built from, which have to be replaced in the scene file
+    /// before it's loaded
+    fn scene_overrides(&self) -> Vec<(String, Float)> {
+        let mut overrides = Vec::new();
+        if let Some(max_depth) = self.max_depth {
+            overrides.push(("camera.max_depth".to_string(), max_depth as Float));
+        }
+        for (param, value) in &self.materials {
+            overrides.push((format!("materials.{param}"), *value));
+        }
+        overrides
+    }
+
+    fn apply<I: Integrator>(&self, camera: &mut Camera<I>) {
+        if let Some(width) = self.width {
+            camera.image_width = width;
+        }
+        if let Some(height) = self.height {
+            camera.aspect_ratio = camera.image_width as Float / height.max(1) as Float;
+        } else if let Some(aspect) = self.aspect {
+            camera.aspect_ratio = aspect;
+        }
+        if let Some(spp) = self.spp {
+            camera.samples_per_pixel = spp;
+        }
+        camera.seed = self.seed.or(camera.seed);
+        camera.stamp |= self.stamp;
+        if let Some(&[x0, y0, x1, y1]) = self.crop.as_deref() {
+            camera.set_region(x0, y0, x1, y1);
+            camera.crop = !self.keep_size;
+        }
+        if let Some(cancel) = &self.cancel {
+            camera.cancel = Some(cancel.clone());
+        }
+    }
+
+    /// where to save the image, `--output` or else where the scene saves it
+    fn output(&self, default: &str) -> String {
+        self.output.clone().unwrap_or_else(|| String::from(default))
+    }
+}
+
+/// `--set-material gold.roughness=0.2`, a number of one of the scene's named materials
+fn material_override(arg: &str) -> Result<(String, Float), String> {
+    let (param, value) = arg
+        .split_once('=')
+        .filter(|(param, _)| param.contains('.'))
+        .ok_or_else(|| format!("should look like material.param=value, got {arg}"))?;
+    let value = value
+        .parse()
+        .map_err(|_| format!("{value} is not a number"))?;
+    Ok((param.to_string(), value))
+}
+
+/// the overrides as the scene loader takes them
+fn borrowed(overrides: &[(String, Float)]) -> Vec<(&str, Float)> {
+    overrides
+        .iter()
+        .map(|(path, value)| (path.as_str(), *value))
+        .collect()
+}
+
+/// returns the path of the rendered image
+fn file_scene(path: &str, background: Option<Float>, settings: &Overrides) -> Option<String> {
+    let materials = MaterialRegistry::with_builtins();
+    let overrides = settings.scene_overrides();
+    let mut scene = match load_scene_with_overrides(path, &materials, &borrowed(&overrides)) {
+        Ok(scene) => scene,
+        Err(err) => {
+            log::error!("Failed to load scene {path}: {err}");
+            return None;
+        }
+    };
+    let output = settings.output(scene.output.as_deref().unwrap_or("out.png"));
+    if let Some(cpu_fraction) = background {
+        scene.camera.background = Some(BackgroundPriority::new(cpu_fraction));
+    }
+    settings.apply(&mut scene.camera);
+    scene.camera.init();
+    scene.camera.render(&scene.world, &output);
+    Some(output)
+}
+
+/// rows each worker renders at a time, small enough to keep all of them busy until the end
+const ROWS_PER_BAND: usize = 16;
+
+/// render the scene file on the `workers`, returns the path of the rendered image
+fn distributed_scene(path: &str, workers: &[String], settings: &Overrides) -> Option<String> {
+    let materials = MaterialRegistry::with_builtins();
+    let overrides = settings.scene_overrides();
+    // loaded here as well for the settings and to catch errors before the workers see them
+    let mut scene = match load_scene_with_overrides(path, &materials, &borrowed(&overrides)) {
+        Ok(scene) => scene,
+        Err(err) => {
+            log::error!("Failed to load scene {path}: {err}");
+            return None;
+        }
+    };
+    let output = settings.output(scene.output.as_deref().unwrap_or("out.png"));
+    if settings.crop.is_some() {
+        log::warn!("--crop is ignored with --workers, rendering the whole image");
+    }
+    // the region would crop the assembled image, which is the whole of it
+    let settings = Overrides {
+        crop: None,
+        ..settings.clone()
+    };
+    settings.apply(&mut scene.camera);
+    scene.camera.init();
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            log::error!("Failed to read scene {path}: {err}");
+            return None;
+        }
+    };
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    let job = RenderJob {
+        scene: text,
+        // absolute, so workers started in another directory find the same files
+        base_dir: base_dir.canonicalize().unwrap_or(base_dir.to_path_buf()),
+        overrides,
+        image_width: scene.camera.image_width,
+        aspect_ratio: scene.camera.aspect_ratio,
+        samples_per_pixel: scene.camera.samples_per_pixel,
+        // the same random numbers on every worker, whichever bands they get
+        seed: Some(scene.camera.seed.unwrap_or_else(|| thread_rng().gen())),
+    };
+
+    let start = Instant::now();
+    let mut film = match render_distributed(&job, workers, ROWS_PER_BAND) {
+        Ok(film) => film,
+        Err(err) => {
+            log::error!("Distributed render failed: {err}");
+            return None;
+        }
+    };
+    // the workers leave them out, they need the whole image
+    scene.camera.apply_post_effects(&mut film);
+    scene.camera.seed = job.seed;
+    if let Err(err) = scene.camera.finish(&scene.world, film, &output, start) {
+        log::error!("Failed to save {output}: {err}");
+        return None;
+    }
+    log::info!("rendered {output} in {:.2}s", start.elapsed().as_secs_f64());
+    Some(output)
+}
+
+/// passes of a watched render, each saved as it finishes
+const WATCH_PASSES: usize = 8;
+
+/// render the scene file, and render it again from the start whenever it or one of its files
+/// changes, until ctrl-c. when only the scene file's camera changed, the world is kept
+fn watch_scene(path: &str, background: Option<Float>, settings: &Overrides) {
+    let materials = MaterialRegistry::with_builtins();
+    let overrides = settings.scene_overrides();
+    let cancelled = || settings.cancel.as_ref().is_some_and(|c| c.is_cancelled());
+    // the last scene, kept while nothing but the scene file changed
+    let mut previous: Option<Scene> = None;
+    let only_scene_file = |changed: &[PathBuf]| changed.iter().all(|file| file == Path::new(path));
+    loop {
+        let loaded = match previous.take() {
+            Some(previous) => reload_scene(path, &materials, &borrowed(&overrides), previous),
+            None => load_scene_with_overrides(path, &materials, &borrowed(&overrides)),
+        };
+        let (mut watcher, scene) = match loaded {
+            Ok(mut scene) => {
+                let mut watcher = FileWatcher::new(scene.files.clone());
+                let output = settings.output(scene.output.as_deref().unwrap_or("out.png"));
+                if let Some(cpu_fraction) = background {
+                    scene.camera.background = Some(BackgroundPriority::new(cpu_fraction));
+                }
+                settings.apply(&mut scene.camera);
+                scene.camera.init();
+                let info = scene.camera.render_info(0.0);
+                let start = Instant::now();
+                let mut changed = Vec::new();
+                scene
+                    .camera
+                    .render_progressive(&scene.world, WATCH_PASSES, |film| {
+                        let info = RenderInfo {
+                            render_time: start.elapsed().as_secs_f64(),
+                            ..info.clone()
+                        };
+                        if let Err(err) = film.save(&output, &info) {
+                            log::error!("Failed to save {output}: {err}");
+                        }
+                        changed = watcher.changed();
+                        if !changed.is_empty() {
+                            ControlFlow::Break(())
+                        } else {
+                            ControlFlow::Continue(())
+                        }
+                    });
+                if !changed.is_empty() {
+                    log::info!("{} changed, starting over", changed[0].display());
+                    forget_textures(&changed);
+                    previous = only_scene_file(&changed).then_some(scene);
+                    continue;
+                }
+                log::info!("rendered {output}, waiting for changes");
+                (watcher, Some(scene))
+            }
+            Err(err) => {
+                log::error!("Failed to load scene {path}: {err}");
+                (FileWatcher::new([PathBuf::from(path)]), None)
+            }
+        };
+        let changed = loop {
+            if cancelled() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(250));
+            let changed = watcher.changed();
+            if !changed.is_empty() {
+                break changed;
+            }
+        };
+        log::info!("{} changed, loading it again", changed[0].display());
+        forget_textures(&changed);
+        previous = scene.filter(|_| only_scene_file(&changed));
+    }
+}
+
+/// edited images are read again, the others stay cached
+fn forget_textures(changed: &[PathBuf]) {
+    for file in changed {
+        TextureCache::global().forget(&file.to_string_lossy());
+    }
+}
+
+/// bounces before the GPU backend gives up on a path, same as the default of the path integrator
+#[cfg(feature = "gpu")]
+const GPU_MAX_DEPTH: usize = 50;
+
+#[cfg(feature = "gpu")]
+fn gpu_scene(path: &str, settings: &Overrides) {
+    let mut scene = match load_scene(path) {
+        Ok(scene) => scene,
+        Err(err) => {
+            log::error!("Failed to load scene {path}: {err}");
+            return;
+        }
+    };
+    let output = settings.output(scene.output.as_deref().unwrap_or("out.png"));
+    settings.apply(&mut scene.camera);
+    scene.camera.init();
+    let max_depth = settings.max_depth.unwrap_or(GPU_MAX_DEPTH);
+    if let Err(err) = scene.camera.render_gpu(&scene.world, &output, max_depth) {
+        log::error!("Failed to render {path} on the GPU: {err}");
+    }
+}
+
+/// cancelled by the first ctrl-c, so the render stops and saves what it has. a second one
+/// quits right away
+fn interrupt_token() -> CancelToken {
+    static TOKEN: OnceLock<CancelToken> = OnceLock::new();
+    let token = TOKEN.get_or_init(CancelToken::new).clone();
+
+    #[cfg(unix)]
+    {
+        extern "C" fn on_interrupt(_: libc::c_int) {
+            if let Some(token) = TOKEN.get() {
+                token.cancel();
+            }
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+            }
+        }
+        unsafe {
+            let handler: extern "C" fn(libc::c_int) = on_interrupt;
+            libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        }
+    }
+    token
+}
+
+/// run the benchmark suite and print the report, or save it to `output`
+fn bench(runs: usize, output: Option<&str>) {
+    let report = match run_bench(runs) {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!("Failed to load a benchmark scene: {err}");
+            return;
+        }
+    };
+    for result in &report.results {
+        log::info!(
+            "{}: render {:.3}s, BVH {:.4}s, {:.2} Mrays/s",
+            result.name,
+            result.stages.render,
+            result.stages.bvh,
+            result.rays_per_second() / 1e6
+        );
+    }
+    match output {
+        Some(path) => {
+            if let Err(err) = std::fs::write(path, report.to_json()) {
+                log::error!("Failed to save benchmark report {path}: {err}");
+            }
+        }
+        None => print!("{}", report.to_json()),
+    }
+}
+
+/// print how far `test` is from `reference` and save where the differences are next to `test`
+fn compare_images(reference: &str, test: &str) {
+    let open = |path: &str| match image::open(path) {
+        Ok(img) => Some(img.to_rgb8()),
+        Err(err) => {
+            log::error!("Failed to open image {path}: {err}");
+            None
+        }
+    };
+    let (Some(reference_img), Some(test_img)) = (open(reference), open(test)) else {
+        return;
+    };
+    match image_metrics::compare(&reference_img, &test_img) {
+        Ok(metrics) => println!("{test} vs {reference}: {metrics}"),
+        Err(err) => {
+            log::error!("Cannot compare {test} with {reference}: {err}");
+            return;
+        }
+    }
+    let heatmap = image_metrics::error_heatmap(&reference_img, &test_img);
+    if let Err(err) = heatmap.save(aov_filename(test, "heatmap")) {
+        log::error!("Failed to save image {err}");
+    }
+}
+
+/// `param=start:end:count`, e.g. `objects.2.material.roughness=0:1:5`
+fn sweep_scene(path: &str, sweep: &str) {
+    let parsed = sweep.split_once('=').and_then(|(param, range)| {
+        let range: Vec<&str> = range.split(':').collect();
+        match range[..] {
+            [start, end, count] => Some((
+                param,
+                start.parse().ok()?,
+                end.parse().ok()?,
+                count.parse().ok()?,
+            )),
+            _ => None,
+        }
+    });
+    let Some((param, start, end, count)) = parsed else {
+        log::error!("Sweep should look like param=start:end:count, got {sweep}");
+        return;
+    };
+
+    let sheet = match sweep_scene_file(path, param, &linspace(start, end, count)) {
+        Ok(sheet) => sheet,
+        Err(err) => {
+            log::error!("Failed to load scene {path}: {err}");
+            return;
+        }
+    };
+    // scenes/cornell.toml is saved as scenes/cornell_sweep.png
+    let output = Path::new(path).with_extension("png");
+    if let Err(err) = sheet
+        .assemble()
+        .save(aov_filename(&output.to_string_lossy(), "sweep"))
+    {
+        log::error!("Failed to save image {err}");
+    }
+}
+
+fn inspect(file: &str, size: usize, output: Option<String>) {
+    let report = match inspect_asset(file, size) {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!("Failed to inspect {err}");
+            return;
+        }
+    };
+    for line in report.summary.iter() {
+        println!("{line}");
+    }
+    // assets/bunny.obj is saved as bunny_inspect.png in the current directory
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(file).file_stem().unwrap_or_default();
+        format!("{}_inspect.png", stem.to_string_lossy())
+    });
+    if let Err(err) = report.image.save(&output) {
+        log::error!("Failed to save image {err}");
+    }
+}
+
+fn shadow_map(
+    file: &str,
+    light: usize,
+    direction: Option<Vec<Float>>,
+    size: usize,
+    heightmap: bool,
+    output: Option<String>,
+) {
+    let scene = match load_scene(file) {
+        Ok(scene) => scene,
+        Err(err) => {
+            log::error!("Failed to load scene {file}: {err}");
+            return;
+        }
+    };
+    let view = match direction.as_deref() {
+        Some(&[x, y, z]) => LightView::fit(&scene.world.objects.bounding_box(), Vec3::new(x, y, z)),
+        Some(_) => {
+            log::error!("Direction should look like x,y,z");
+            return;
+        }
+        None => match LightView::from_light(&scene.world, light) {
+            Some(view) => view,
+            None => {
+                log::error!("Scene {file} has no light {light}, pass a --direction instead");
+                return;
+            }
+        },
+    };
+    let map = DepthMap::render(&scene.world, &view, size);
+
+    // scenes/cornell.toml is saved as scenes/cornell_shadow.png, next to scenes/cornell_shadow.toml
+    let output = output.unwrap_or_else(|| {
+        let image = Path::new(file).with_extension("png");
+        aov_filename(&image.to_string_lossy(), "shadow")
+    });
+    if let Err(err) = map.save(&output, heightmap) {
+        log::error!("Failed to save image {err}");
+    }
+    let view_file = Path::new(&output).with_extension("toml");
+    if let Err(err) = map.save_view(&view_file.to_string_lossy()) {
+        log::error!("Failed to save light view {err}");
+    }
+}
+
+fn bsdf_check(samples: usize) -> bool {
+    let mut all_passed = true;
+    for (name, material, transmissive) in builtin_bsdfs() {
+        let report = check_bsdf(&name, &material, transmissive, samples);
+        print!("{report}");
+        all_passed &= report.passed();
+    }
+    all_passed
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// render diagnostic images (AO, normals, facing, uv checker) of a mesh, or previews of a texture
+    Inspect {
+        file: String,
+        /// width of each diagnostic image
+        #[arg(long, default_value_t = 256)]
+        size: usize,
+        /// where to save the contact sheet, defaults to <file stem>_inspect.png
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// render a depth map of a scene file from a light's point of view with parallel rays, to bake
+    /// shadow maps or heightmaps. the view is saved next to it as a .toml file
+    ShadowMap {
+        file: String,
+        /// which of the scene's lights to look from
+        #[arg(long, default_value_t = 0)]
+        light: usize,
+        /// look along this direction instead, as x,y,z. `0,-1,0` looks straight down
+        #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+        direction: Option<Vec<Float>>,
+        /// pixels along the longer side
+        #[arg(long, default_value_t = 1024)]
+        size: usize,
+        /// save heights instead of depths, with the surfaces closest to the light brightest
+        #[arg(long, default_value_t = false)]
+        heightmap: bool,
+        /// a .png gets 16 bit depths from 0 to 1 and an .exr the distances, defaults to
+        /// <file stem>_shadow.png next to the scene
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// render bands of images for a coordinator started with --workers, until stopped
+    Worker {
+        /// the address and port to wait for the coordinator on. only this machine can connect
+        /// by default, anyone who can reach the port can make the worker read files
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+    },
+    /// run the white furnace and chi-squared sampling tests on every built-in BSDF
+    BsdfCheck {
+        /// samples per view direction and test
+        #[arg(long, default_value_t = 200_000)]
+        samples: usize,
+    },
 }
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long, default_value_t = false)]
     quality: bool,
     #[arg(short, long, default_value_t = 1)]
     scene: usize,
+    /// render a scene description file instead of one of the numbered scenes
+    #[arg(short, long)]
+    file: Option<String>,
+    /// with --file, render a contact sheet sweeping one number of the scene file, as param=start:end:count
+    #[arg(long)]
+    sweep: Option<String>,
+    /// with --file, keep running and render the scene again whenever it or a file it uses
+    /// changes, saving the image after every pass
+    #[arg(long)]
+    watch: bool,
+    /// with --file, render on the machines running `worker` at these addresses, e.g.
+    /// `--workers 10.0.0.2:7878,10.0.0.3:7878`
+    #[arg(long, value_delimiter = ',')]
+    workers: Option<Vec<String>>,
+    /// `--compare ref.png image.png` prints error metrics and writes a heatmap next to the image.
+    /// with --file and only the reference, the scene's render is compared against it
+    #[arg(long, num_args = 1..=2, value_names = ["REFERENCE", "IMAGE"])]
+    compare: Option<Vec<String>>,
+    /// with --file, render on this share of the cores at a low priority, so the machine stays
+    /// usable. without a value half of them
+    #[arg(long, num_args = 0..=1, default_missing_value = "0.5", value_name = "CPU_FRACTION")]
+    background: Option<Float>,
+    /// with --file, burn a footer with the scene name, resolution, samples, render time and
+    /// integrator settings into the image, like `stamp = true` in the scene
+    #[arg(long, default_value_t = false)]
+    stamp: bool,
+    /// with --file, only render the pixels from (X0, Y0) up to (X1, Y1) and save just those
+    #[arg(long, num_args = 4, value_names = ["X0", "Y0", "X1", "Y1"])]
+    crop: Option<Vec<usize>>,
+    /// with --crop, save the full image with the pixels outside the region left black
+    #[arg(long, default_value_t = false, requires = "crop")]
+    keep_size: bool,
+    /// image width in pixels, over the scene's
+    #[arg(long)]
+    width: Option<usize>,
+    /// image height in pixels, sets the aspect ratio together with the width
+    #[arg(long, conflicts_with = "aspect")]
+    height: Option<usize>,
+    /// aspect ratio, width over height
+    #[arg(long)]
+    aspect: Option<Float>,
+    /// samples per pixel
+    #[arg(long)]
+    spp: Option<usize>,
+    /// bounces before a path is cut off
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// replace a number of one of the scene's named materials, e.g.
+    /// `--set-material gold.roughness=0.2`. can be given several times
+    #[arg(long, value_name = "MATERIAL.PARAM=VALUE", value_parser = material_override)]
+    set_material: Vec<(String, Float)>,
+    /// where to save the image, over the scene's `output`
+    #[arg(short, long)]
+    output: Option<String>,
+    /// render on this many threads instead of one per core
+    #[arg(long)]
+    threads: Option<usize>,
+    /// seed the random numbers, so rendering the same scene with the same settings gives the
+    /// same image
+    #[arg(long)]
+    seed: Option<u64>,
+    /// render the built-in benchmark scenes this many times (3 without a value) and print the
+    /// timings and ray counts as JSON, or save them to --output
+    #[arg(long, num_args = 0..=1, default_missing_value = "3", value_name = "RUNS")]
+    bench: Option<usize>,
+    /// log more about the render: -v adds pass and tile timings, -vv everything
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// only log warnings and errors
+    #[arg(long, default_value_t = false, conflicts_with = "verbose")]
+    quiet: bool,
+    /// with --file, render with the experimental compute shader backend
+    #[cfg(feature = "gpu")]
+    #[arg(long)]
+    gpu: bool,
+}
+
+/// log to stderr at the level the flags ask for, other crates only log their warnings.
+/// `RUST_LOG` can still override it per module
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => log::LevelFilter::Warn,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .filter_module("path_tracer", level)
+        .format_target(false)
+        .format_timestamp(None)
+        .parse_default_env()
+        .init();
 }
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "full");
     let args = Args::parse();
-    let quality = args.quality;
-    let (width, spp) = if quality { (1920, 4000) } else { (600, 100) };
+    init_logging(args.verbose, args.quiet);
+    if let Some(threads) = args.threads {
+        if let Err(err) = ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            log::error!("Failed to start {threads} render threads: {err}");
+        }
+    }
+    let settings = Overrides::new(&args);
+
+    if let Some(runs) = args.bench {
+        bench(runs, args.output.as_deref());
+        return;
+    }
+
+    match args.command {
+        Some(Command::Inspect { file, size, output }) => {
+            inspect(&file, size, output);
+            return;
+        }
+        Some(Command::ShadowMap {
+            file,
+            light,
+            direction,
+            size,
+            heightmap,
+            output,
+        }) => {
+            shadow_map(&file, light, direction, size, heightmap, output);
+            return;
+        }
+        Some(Command::Worker { listen }) => {
+            if let Err(err) = serve(&listen, &MaterialRegistry::with_builtins()) {
+                log::error!("Failed to listen on {listen}: {err}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::BsdfCheck { samples }) => {
+            if !bsdf_check(samples) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => (),
+    }
+
+    if let Some(compare) = &args.compare {
+        if let [reference, image] = &compare[..] {
+            compare_images(reference, image);
+            return;
+        }
+    }
+
+    let settings = Overrides {
+        cancel: Some(interrupt_token()),
+        ..settings
+    };
+
+    #[cfg(feature = "gpu")]
+    if let (true, Some(path)) = (args.gpu, &args.file) {
+        gpu_scene(path, &settings);
+        return;
+    }
+
+    if let Some(path) = args.file {
+        if let Some(sweep) = args.sweep {
+            sweep_scene(&path, &sweep);
+        } else if let Some(workers) = &args.workers {
+            if let Some(output) = distributed_scene(&path, workers, &settings) {
+                if let Some(reference) = args.compare.as_ref().and_then(|c| c.first()) {
+                    compare_images(reference, &output);
+                }
+            }
+        } else if args.watch {
+            watch_scene(&path, args.background, &settings);
+        } else if let Some(output) = file_scene(&path, args.background, &settings) {
+            if let Some(reference) = args.compare.as_ref().and_then(|c| c.first()) {
+                compare_images(reference, &output);
+            }
+        }
+        return;
+    }
 
+    // the numbered scenes have no settings of their own to fall back on
+    let (width, spp) = if args.quality {
+        (1920, 4000)
+    } else {
+        (600, 100)
+    };
+    let settings = Overrides {
+        width: settings.width.or(Some(width)),
+        spp: settings.spp.or(Some(spp)),
+        ..settings
+    };
     match args.scene {
-        1 => balls_scene(width, spp),
-        2 => earth_scene(width, spp),
-        3 => cornell_box_scene(width, spp),
-        4 => environment_map_scene(width, spp),
-        5 => bsdf_demo_scene(width, spp),
-        6 => everything_scene(width, spp),
-        7 => normal_demo_scene(width, spp),
+        1 => balls_scene(&settings),
+        2 => earth_scene(&settings),
+        3 => cornell_box_scene(&settings),
+        4 => environment_map_scene(&settings),
+        5 => bsdf_demo_scene(&settings),
+        6 => everything_scene(&settings),
+        7 => normal_demo_scene(&settings),
+        8 => lookdev_scene(&settings),
         _ => (),
     }
 }
@@ -0,0 +1,202 @@
+//! Render settings stored alongside the image, so an output can always be traced back to how it was made.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use exr::{
+    image::{Image, SpecificChannels},
+    meta::attribute::{AttributeValue, Text},
+    prelude::WritableImage,
+};
+use image::{GrayImage, Rgb32FImage, RgbImage, Rgba, Rgba32FImage, RgbaImage};
+
+/// What went into a render, written as PNG text chunks and EXR header attributes and
+/// optionally burned into the image
+#[derive(Debug, Clone, Default)]
+pub struct RenderInfo {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub integrator: String,
+    /// the integrator's main parameters, see `Integrator::settings`
+    pub settings: String,
+    /// `None` when the render used unseeded random numbers
+    pub seed: Option<u64>,
+    /// `None` for the scenes built in code
+    pub scene_hash: Option<u64>,
+    /// `None` for the scenes built in code
+    pub scene_name: Option<String>,
+    /// in seconds
+    pub render_time: f64,
+}
+
+impl RenderInfo {
+    /// (keyword, value) pairs, the keywords follow the PNG convention of capitalized words
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "Software",
+                format!("path-tracer {}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("Resolution", format!("{}x{}", self.width, self.height)),
+            ("Samples", self.samples_per_pixel.to_string()),
+            ("Integrator", self.integrator.clone()),
+            ("Integrator settings", self.settings.clone()),
+            (
+                "Seed",
+                self.seed.map_or(String::from("random"), |s| s.to_string()),
+            ),
+            (
+                "Scene",
+                self.scene_name
+                    .clone()
+                    .unwrap_or_else(|| String::from("none")),
+            ),
+            (
+                "Scene hash",
+                self.scene_hash
+                    .map_or(String::from("none"), |h| format!("{h:016x}")),
+            ),
+            ("Render time", format!("{:.2}s", self.render_time)),
+        ]
+    }
+
+    /// two line summary for the overlay strip: the scene and how long it took, then the
+    /// integrator and its settings
+    pub fn summary(&self) -> String {
+        let mut summary = match &self.scene_name {
+            Some(name) => format!("{name}  "),
+            None => String::new(),
+        };
+        summary += &format!(
+            "{}x{}  {} spp  {:.1}s",
+            self.width, self.height, self.samples_per_pixel, self.render_time
+        );
+        if let Some(seed) = self.seed {
+            summary += &format!("  seed {seed}");
+        }
+        if let Some(hash) = self.scene_hash {
+            summary += &format!("  scene {:08x}", hash >> 32);
+        }
+        summary += &format!("\n{}", self.integrator);
+        if !self.settings.is_empty() {
+            summary += &format!(": {}", self.settings);
+        }
+        summary
+    }
+}
+
+/// FNV-1a, stable across platforms and compiler versions unlike `DefaultHasher`
+pub fn hash_scene(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// save `img`, PNGs get `info` embedded as text chunks and other formats are saved as they are
+pub fn save_image(img: &RgbImage, filename: &str, info: &RenderInfo) -> io::Result<()> {
+    if !is_png(filename) {
+        return img.save(filename).map_err(io::Error::other);
+    }
+    write_png(
+        filename,
+        img.dimensions(),
+        png::ColorType::Rgb,
+        img.as_raw(),
+        info,
+    )
+}
+
+/// `save_image` with an alpha channel, for formats that have one
+pub fn save_image_with_alpha(
+    img: &RgbImage,
+    alpha: &GrayImage,
+    filename: &str,
+    info: &RenderInfo,
+) -> io::Result<()> {
+    let rgba = RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b] = img.get_pixel(x, y).0;
+        Rgba([r, g, b, alpha.get_pixel(x, y).0[0]])
+    });
+    if !is_png(filename) {
+        return rgba.save(filename).map_err(io::Error::other);
+    }
+    write_png(
+        filename,
+        rgba.dimensions(),
+        png::ColorType::Rgba,
+        rgba.as_raw(),
+        info,
+    )
+}
+
+/// save linear colors and alpha as an OpenEXR file with `info` in its header, one text
+/// attribute for each of its `entries`
+pub fn save_exr(img: &Rgba32FImage, filename: &str, info: &RenderInfo) -> io::Result<()> {
+    let channels = SpecificChannels::rgba(|pos: exr::math::Vec2<usize>| {
+        let [r, g, b, a] = img.get_pixel(pos.x() as u32, pos.y() as u32).0;
+        (r, g, b, a)
+    });
+    let size = (img.width() as usize, img.height() as usize);
+    let mut image = Image::from_channels(size, channels);
+    image.layer_data.attributes.other = exr_attributes(info);
+    image.write().to_file(filename).map_err(io::Error::other)
+}
+
+/// `save_exr` without an alpha channel, for the passes that aren't colors
+pub fn save_exr_rgb(img: &Rgb32FImage, filename: &str, info: &RenderInfo) -> io::Result<()> {
+    let channels = SpecificChannels::rgb(|pos: exr::math::Vec2<usize>| {
+        let [r, g, b] = img.get_pixel(pos.x() as u32, pos.y() as u32).0;
+        (r, g, b)
+    });
+    let size = (img.width() as usize, img.height() as usize);
+    let mut image = Image::from_channels(size, channels);
+    image.layer_data.attributes.other = exr_attributes(info);
+    image.write().to_file(filename).map_err(io::Error::other)
+}
+
+/// EXR text is Latin-1, other characters of a scene name become `?`
+fn exr_attributes(info: &RenderInfo) -> HashMap<Text, AttributeValue> {
+    let text = |text: &str| {
+        let latin1: String = text
+            .chars()
+            .map(|c| if (c as u32) < 256 { c } else { '?' })
+            .collect();
+        Text::new_or_none(latin1).unwrap_or_default()
+    };
+    info.entries()
+        .into_iter()
+        .map(|(keyword, value)| (text(keyword), AttributeValue::Text(text(&value))))
+        .collect()
+}
+
+fn is_png(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+}
+
+fn write_png(
+    filename: &str,
+    (width, height): (u32, u32),
+    color: png::ColorType,
+    data: &[u8],
+    info: &RenderInfo,
+) -> io::Result<()> {
+    let file = BufWriter::new(File::create(filename)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in info.entries() {
+        encoder
+            .add_text_chunk(keyword.to_string(), text)
+            .map_err(io::Error::other)?;
+    }
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
@@ -1,10 +1,34 @@
-use crate::vec3::Vec3;
+use crate::vec3::{Float, Vec3};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
-    time: f64,
+    time: Float,
+    differential: Option<RayDifferential>,
+    ray_type: RayType,
+}
+
+/// What a ray is traced for, which decides the objects it can see, see `Visibility`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RayType {
+    /// from the camera to the first hit
+    Camera,
+    /// every bounce after that, and photons
+    #[default]
+    Indirect,
+    /// testing whether a point can see a light, or is occluded
+    Shadow,
+}
+
+/// Rays through the neighbouring pixels to the right (x) and below (y), so a hit can tell how much
+/// of the surface a pixel covers and filter its textures to match. Only camera rays carry them
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferential {
+    pub rx_origin: Vec3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Vec3,
+    pub ry_direction: Vec3,
 }
 
 impl Ray {
@@ -16,19 +40,93 @@ impl Ray {
         self.direction
     }
 
-    pub fn time(&self) -> f64 {
+    pub fn time(&self) -> Float {
         self.time
     }
 
-    pub fn new(origin: Vec3, direction: Vec3, time: f64) -> Ray {
+    pub fn differential(&self) -> Option<&RayDifferential> {
+        self.differential.as_ref()
+    }
+
+    pub fn ray_type(&self) -> RayType {
+        self.ray_type
+    }
+
+    pub fn new(origin: Vec3, direction: Vec3, time: Float) -> Ray {
         Ray {
             origin,
             direction: direction.normalize(),
             time,
+            differential: None,
+            ray_type: RayType::Indirect,
+        }
+    }
+
+    pub fn with_type(self, ray_type: RayType) -> Ray {
+        Ray { ray_type, ..self }
+    }
+
+    /// this ray with `rx` and `ry` as the rays through the neighbouring pixels
+    pub fn with_differential(self, rx: &Ray, ry: &Ray) -> Ray {
+        Ray {
+            differential: Some(RayDifferential {
+                rx_origin: rx.origin,
+                rx_direction: rx.direction,
+                ry_origin: ry.origin,
+                ry_direction: ry.direction,
+            }),
+            ..self
+        }
+    }
+
+    /// move the neighbouring rays closer by `scale`, e.g. when each of many samples per pixel
+    /// only stands for a part of the pixel
+    pub fn scale_differential(self, scale: Float) -> Ray {
+        let Some(diff) = self.differential else {
+            return self;
+        };
+        let (o, d) = (self.origin, self.direction);
+        Ray {
+            differential: Some(RayDifferential {
+                rx_origin: o + (diff.rx_origin - o) * scale,
+                rx_direction: d + (diff.rx_direction - d) * scale,
+                ry_origin: o + (diff.ry_origin - o) * scale,
+                ry_direction: d + (diff.ry_direction - d) * scale,
+            }),
+            ..self
         }
     }
 
-    pub fn at(&self, t: f64) -> Vec3 {
+    pub fn at(&self, t: Float) -> Vec3 {
         self.origin + self.direction * t
     }
 }
+
+/// bound on the rounding error of the points the intersection tests work out, relative to the
+/// size of the coordinates that go into them. a generous multiple of the machine epsilon, none
+/// of the tests takes more than a few dozen operations
+pub const RELATIVE_ERROR: Float = 64.0 * Float::EPSILON;
+
+/// bound on the error of each coordinate of a point worked out from coordinates as big as
+/// `magnitude`, e.g. the ray's origin and the point itself added up
+pub fn rounding_error(magnitude: Vec3) -> Vec3 {
+    magnitude.abs() * RELATIVE_ERROR
+}
+
+/// `point` pushed along `normal` past its rounding `error`, so a ray starting there can't hit
+/// the surface it's on again however far from the origin it is, and rounded away from it
+/// (the way pbrt offsets ray origins). `normal` points to the side the ray leaves towards
+pub fn offset_origin(point: Vec3, error: Vec3, normal: Vec3) -> Vec3 {
+    let offset = normal * normal.abs().dot(error);
+    let round = |p: Float, offset: Float| match offset {
+        o if o > 0.0 => p.next_up(),
+        o if o < 0.0 => p.next_down(),
+        _ => p,
+    };
+    let p = point + offset;
+    Vec3::new(
+        round(p.x, offset.x),
+        round(p.y, offset.y),
+        round(p.z, offset.z),
+    )
+}
@@ -0,0 +1,208 @@
+//! Counters of the work that goes into tracing rays: BVH nodes visited, primitives tested and
+//! rays traced by kind. They are only counted while enabled and kept per thread so counting
+//! stays cheap, and the camera takes them after every pixel when `Camera::ray_stats` is on, to
+//! report totals and render a map of where the traversal is expensive.
+
+use std::{
+    cell::Cell,
+    fmt,
+    ops::AddAssign,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use image::{Rgb, RgbImage};
+
+use crate::{image_metrics::heat_color, vec3::Float};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RayStats {
+    pub camera_rays: u64,
+    /// closest hit queries, the camera rays and every bounce after them
+    pub closest_hit_rays: u64,
+    /// visibility tests between two points
+    pub shadow_rays: u64,
+    pub nodes_visited: u64,
+    pub primitive_tests: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RayKind {
+    Camera,
+    ClosestHit,
+    Shadow,
+}
+
+struct Counters {
+    camera_rays: Cell<u64>,
+    closest_hit_rays: Cell<u64>,
+    shadow_rays: Cell<u64>,
+    nodes_visited: Cell<u64>,
+    primitive_tests: Cell<u64>,
+}
+
+thread_local! {
+    static COUNTERS: Counters = const {
+        Counters {
+            camera_rays: Cell::new(0),
+            closest_hit_rays: Cell::new(0),
+            shadow_rays: Cell::new(0),
+            nodes_visited: Cell::new(0),
+            primitive_tests: Cell::new(0),
+        }
+    };
+}
+
+/// counting is off unless someone asked for the numbers, it slows the traversal down
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// turn counting on or off for all threads
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn bump(counter: &Cell<u64>, n: u64) {
+    counter.set(counter.get() + n);
+}
+
+#[inline]
+pub fn count_rays(kind: RayKind, n: usize) {
+    if !is_enabled() {
+        return;
+    }
+    COUNTERS.with(|counters| {
+        let counter = match kind {
+            RayKind::Camera => &counters.camera_rays,
+            RayKind::ClosestHit => &counters.closest_hit_rays,
+            RayKind::Shadow => &counters.shadow_rays,
+        };
+        bump(counter, n as u64);
+    });
+}
+
+#[inline]
+pub fn count_node() {
+    if !is_enabled() {
+        return;
+    }
+    COUNTERS.with(|counters| bump(&counters.nodes_visited, 1));
+}
+
+#[inline]
+pub fn count_primitives(n: usize) {
+    if !is_enabled() {
+        return;
+    }
+    COUNTERS.with(|counters| bump(&counters.primitive_tests, n as u64));
+}
+
+impl RayStats {
+    /// what this thread counted since the last take, and start again from zero
+    pub fn take() -> RayStats {
+        COUNTERS.with(|counters| RayStats {
+            camera_rays: counters.camera_rays.take(),
+            closest_hit_rays: counters.closest_hit_rays.take(),
+            shadow_rays: counters.shadow_rays.take(),
+            nodes_visited: counters.nodes_visited.take(),
+            primitive_tests: counters.primitive_tests.take(),
+        })
+    }
+
+    /// nodes visited plus primitives tested, roughly the time spent traversing
+    pub fn traversal_cost(&self) -> u64 {
+        self.nodes_visited + self.primitive_tests
+    }
+}
+
+impl AddAssign for RayStats {
+    fn add_assign(&mut self, other: RayStats) {
+        self.camera_rays += other.camera_rays;
+        self.closest_hit_rays += other.closest_hit_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.nodes_visited += other.nodes_visited;
+        self.primitive_tests += other.primitive_tests;
+    }
+}
+
+impl fmt::Display for RayStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rays = (self.closest_hit_rays + self.shadow_rays).max(1) as Float;
+        writeln!(f, "camera rays:      {}", self.camera_rays)?;
+        writeln!(f, "closest hit rays: {}", self.closest_hit_rays)?;
+        writeln!(f, "shadow rays:      {}", self.shadow_rays)?;
+        writeln!(
+            f,
+            "nodes visited:    {} ({:.1} per ray)",
+            self.nodes_visited,
+            self.nodes_visited as Float / rays
+        )?;
+        write!(
+            f,
+            "primitive tests:  {} ({:.1} per ray)",
+            self.primitive_tests,
+            self.primitive_tests as Float / rays
+        )
+    }
+}
+
+/// The counts of every pixel of a render
+#[derive(Debug)]
+pub struct RayStatsMap {
+    width: usize,
+    pixels: Vec<Mutex<RayStats>>,
+}
+
+impl RayStatsMap {
+    pub fn new(width: usize, height: usize) -> RayStatsMap {
+        RayStatsMap {
+            width,
+            pixels: (0..width * height).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    pub fn add(&self, r: usize, c: usize, stats: RayStats) {
+        if let Some(pixel) = self.pixels.get(r * self.width + c) {
+            *pixel.lock().unwrap() += stats;
+        }
+    }
+
+    pub fn get(&self, r: usize, c: usize) -> RayStats {
+        *self.pixels[r * self.width + c].lock().unwrap()
+    }
+
+    pub fn total(&self) -> RayStats {
+        let mut total = RayStats::default();
+        for pixel in &self.pixels {
+            total += *pixel.lock().unwrap();
+        }
+        total
+    }
+
+    /// traversal cost per camera ray of every pixel, from black (cheapest) through blue and
+    /// red to white (the most expensive)
+    pub fn heatmap(&self) -> RgbImage {
+        let costs: Vec<Float> = self
+            .pixels
+            .iter()
+            .map(|pixel| {
+                let stats = pixel.lock().unwrap();
+                stats.traversal_cost() as Float / stats.camera_rays.max(1) as Float
+            })
+            .collect();
+        let max_cost = costs.iter().cloned().fold(0.0, Float::max).max(1e-8);
+
+        let height = self.pixels.len() / self.width.max(1);
+        let mut heatmap = RgbImage::new(self.width as u32, height as u32);
+        for (pixel, cost) in heatmap.pixels_mut().zip(costs) {
+            let color = heat_color(cost / max_cost) * 255.0;
+            *pixel = Rgb([color.x as u8, color.y as u8, color.z as u8]);
+        }
+        heatmap
+    }
+}
@@ -0,0 +1,156 @@
+use std::{cell::RefCell, sync::OnceLock};
+
+use rand::{rngs::StdRng, thread_rng, Rng, RngCore, SeedableRng};
+
+use crate::{
+    blue_noise::BlueNoiseMask,
+    vec3::{Float, Vec2},
+};
+
+/// how many of the first random numbers of a sample `start_dither` dithers: those of the camera
+/// and about those of the first bounce
+pub const DITHER_DIMS: usize = 16;
+
+thread_local! {
+    /// the generator of a seeded render running on this thread
+    static SEEDED: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+    /// the dithered sample being drawn on this thread, see `start_dither`
+    static DITHER: RefCell<Option<Dither>> = const { RefCell::new(None) };
+}
+
+/// Sample `index` of the sequence every pixel shares, shifted by the mask at the pixel
+struct Dither {
+    shifts: [f64; DITHER_DIMS],
+    index: u64,
+    dim: usize,
+}
+
+/// The random numbers everything in a render draws from: `thread_rng`, unless `seed_thread`
+/// seeded this thread, which makes what it renders reproducible
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderRng;
+
+impl RenderRng {
+    fn with<R>(f: impl FnOnce(&mut dyn RngCore) -> R) -> R {
+        SEEDED.with_borrow_mut(|seeded| match seeded {
+            Some(rng) => f(rng),
+            None => f(&mut thread_rng()),
+        })
+    }
+}
+
+impl RngCore for RenderRng {
+    fn next_u32(&mut self) -> u32 {
+        match next_dithered() {
+            Some(u) => (u * 4294967296.0) as u32,
+            None => Self::with(|rng| rng.next_u32()),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match next_dithered() {
+            Some(u) => (u * 18446744073709551616.0) as u64,
+            None => Self::with(|rng| rng.next_u64()),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        Self::with(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        Self::with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+/// the random numbers of the current thread, see `RenderRng`
+pub fn rng() -> RenderRng {
+    RenderRng
+}
+
+/// draw this thread's random numbers from `seed` from now on, or from `thread_rng` again
+/// with None
+pub fn seed_thread(seed: Option<u64>) {
+    SEEDED.with_borrow_mut(|seeded| *seeded = seed.map(StdRng::seed_from_u64));
+}
+
+/// draw the next `DITHER_DIMS` random numbers of this thread from sample `index` of a low
+/// discrepancy sequence (the R sequence), shifted by `mask` at pixel (`x`, `y`) with a
+/// different shift for every number. what comes after them is random again, until
+/// `stop_dither`
+#[allow(clippy::unnecessary_cast)]
+pub fn start_dither(mask: &BlueNoiseMask, x: usize, y: usize, index: usize) {
+    let shifts = std::array::from_fn(|dim| mask.value(x, y, dim) as f64);
+    DITHER.with_borrow_mut(|dither| {
+        *dither = Some(Dither {
+            shifts,
+            index: index as u64,
+            dim: 0,
+        })
+    });
+}
+
+/// back to random numbers after `start_dither`
+pub fn stop_dither() {
+    DITHER.with_borrow_mut(|dither| *dither = None);
+}
+
+/// the dithered number to draw next, if a dithered sample has numbers left
+fn next_dithered() -> Option<f64> {
+    DITHER.with_borrow_mut(|dither| {
+        let dither = dither.as_mut().filter(|dither| dither.dim < DITHER_DIMS)?;
+        let u = dither.index as f64 * r_sequence()[dither.dim] + dither.shifts[dither.dim];
+        dither.dim += 1;
+        Some(u.fract())
+    })
+}
+
+/// the steps of the R sequence in `DITHER_DIMS` dimensions, powers of one over the root of
+/// x^(d + 1) = x + 1 (Roberts 2018)
+fn r_sequence() -> &'static [f64; DITHER_DIMS] {
+    static ALPHAS: OnceLock<[f64; DITHER_DIMS]> = OnceLock::new();
+    ALPHAS.get_or_init(|| {
+        let d = DITHER_DIMS as i32;
+        let mut g: f64 = 1.5;
+        for _ in 0..32 {
+            g -= (g.powi(d + 1) - g - 1.0) / ((d + 1) as f64 * g.powi(d) - 1.0);
+        }
+        std::array::from_fn(|k| g.powi(-(k as i32 + 1)).fract())
+    })
+}
+
+/// a seed for one part of a seeded render, e.g. a pass over one pixel, mixed from `seed` and
+/// the numbers that tell the part apart (splitmix64)
+pub fn mix_seed(seed: u64, parts: &[u64]) -> u64 {
+    parts.iter().fold(seed, |seed, &part| {
+        let mut z = (seed ^ part).wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    })
+}
+
+/// Source of the random numbers an integrator consumes while estimating a single camera sample
+pub struct Sampler {
+    rng: RenderRng,
+}
+
+impl Sampler {
+    pub fn new() -> Sampler {
+        Sampler { rng: rng() }
+    }
+
+    pub fn next_1d(&mut self) -> Float {
+        self.rng.gen()
+    }
+
+    pub fn next_2d(&mut self) -> Vec2 {
+        Vec2::new(self.rng.gen(), self.rng.gen())
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,1506 @@
+//! Loading scenes from TOML scene description files.
+//!
+//! ```toml
+//! output = "demo/cornell.png"
+//!
+//! [camera]
+//! image_width = 600
+//! samples_per_pixel = 100
+//! look_from = [278, 278, -800]
+//! look_at = [278, 278, 0]
+//!
+//! [environment]
+//! color = [0.0, 0.0, 0.0] # or map = "../assets/envmap.jpg"
+//!
+//! [[objects]]
+//! type = "sphere"
+//! center = [113, 170, 372]
+//! radius = 135
+//! material = { type = "glass", ior = 1.5 }
+//!
+//! [[lights]]
+//! type = "quad"
+//! q = [343, 554, 332]
+//! u = [-130, 0, 0]
+//! v = [0, 0, -105]
+//! material = { type = "light", color = [25, 25, 25] }
+//! ```
+//!
+//! The `[camera]` table picks the integrator with `integrator = "path"` (the default, with
+//! `max_depth` and the per-kind limits `max_diffuse_bounces`, `max_specular_bounces` and
+//! `max_transmission_bounces`, `light_samples` for several shadow rays at every bounce spread
+//! over the lights, `irradiance_cache = true` or `irradiance_cache = { error = 0.5, rays = 128 }`
+//! to interpolate the indirect light after the first bounce between sparse points,
+//! `guiding = true` or `guiding = { passes = 4, mix = 0.5 }` to learn where the light comes
+//! from over that many passes and sample part of the bounces towards it, and
+//! `check_sampling = true` or
+//! `check_sampling = { rate = 0.0001, samples = 20000 }` to test the sampling of the materials
+//! at that fraction of the bounces and log the ones whose pdf doesn't match their samples),
+//! `integrator = "toon"` (with `bands`, `ambient` and `key_direction`) or `integrator = "ao"`
+//! (with `ao_samples` and `ao = { distance = 100, falloff = 1, ground_only = false }`). Any
+//! material can carry its own `ao = { ... }` table which takes over from the camera's.
+//! `integrator = "sppm"` renders caustics with progressive photon mapping, taking `photons` per
+//! pass, `passes`, the first pass' gather `radius` and `alpha`; `samples_per_pixel` is spread
+//! over the passes.
+//! `integrator = "wavefront"` estimates the same thing as `"path"`, with only `max_depth`, but
+//! traces all the samples of a pixel together one bounce at a time.
+//! `integrator = "lightcuts"` is a direct light preview for scenes with many lights, replacing
+//! each light with `light_points` point lights and evaluating clusters of them up to a relative
+//! `error`, with at most `max_cut` clusters per shading point.
+//! `aperture = { shape = "polygon", blades = 6, rotation = 0 }` (or `"superellipse"` with an
+//! `exponent`, or `"image"` with a `file`) shapes the bokeh of out-of-focus highlights.
+//! `defocus_preview = true` fakes the depth of field by blurring a sharp render by depth, which
+//! is much faster for finding the right `focal_length`.
+//! `focus_distance = 3.0` focuses that far in front of the camera instead of at `focal_length`
+//! (only kept for the focus of older scenes and the size of an orthographic view),
+//! and `autofocus = true` on the first thing on the way to `look_at`, or `autofocus = [0.3, 0.6]`
+//! on whatever is seen at that point of the image (in fractions of its width and height).
+//! `filter = "gaussian"` (or `"box"`, the default, `"triangle"`, `"blackman_harris"`,
+//! `"mitchell"`, or a table with a `type` and a `radius` in pixels) reconstructs pixels with
+//! that filter. the samples of a pixel are jittered over a grid across it.
+//! `k1` and `k2` add radial lens distortion (OpenCV's convention) and `vignetting` from 0 to 1
+//! darkens the corners.
+//! `frame = true` (or `frame = 0.2` for more room around it) ignores `look_at` and moves the
+//! camera along its view direction until all the objects fit in the image, for meshes of
+//! unknown size.
+//! `bounding_boxes = true` draws the box around every object over the render, turned with
+//! rotated instances, to check where things were placed.
+//! `ray_stats = true` counts the rays, BVH nodes visited and primitives tested, prints the
+//! totals and saves the traversal cost of every pixel as `<output>_cost.png`.
+//! `variance_map = true` saves how noisy every pixel still is as a heat map `<output>_variance.png`.
+//! `motion_vectors = true` saves how far what every pixel sees moves over the shutter, across
+//! the image in `<output>_motion.exr` and in the scene in `<output>_world_motion.exr` and
+//! `<output>_object_motion.exr`.
+//! `stereo = true` or `stereo = { ipd = 0.064, convergence = 2, layout = "top_bottom" }` renders
+//! a view for each eye side by side (or one above the other), omni-directional stereo with
+//! `projection = "equirectangular"`, for VR headsets.
+//! `stamp = true` burns a footer with the scene's `name` (a top level key, the file name by
+//! default), resolution, samples, render time and integrator settings into the image.
+//! `outline = true` or `outline = { depth_threshold = 0.1, normal_threshold = 30, color = [0, 0, 0] }`
+//! draws ink lines over the render and saves them as a separate `<output>_outline.png`.
+//! `adaptive = true` or `adaptive = { tile_size = 16, min_samples = 16, threshold = 0.01 }` stops
+//! sampling tiles once they are clean, treating `samples_per_pixel` as the maximum, and saves
+//! how many samples each tile took as `<output>_samples.png`.
+//! `background = true` or `background = { cpu_fraction = 0.5, niceness = 10 }` renders on that
+//! share of the cores at a lower priority, to keep the machine usable during long renders.
+//! `ids = true` saves object and material ID passes for picking objects in a compositor:
+//! color coded previews, the IDs in `<output>_id.exr` and their names in `<output>_id.toml`.
+//! objects and materials are called by their `name` when they have one.
+//! `seed = 1` renders the same image every time with the same settings.
+//! `blue_noise = true` dithers the first random numbers of every sample with a blue noise mask,
+//! for evenly spread noise in previews at a few samples per pixel. `blue_noise = "mask.png"`
+//! tiles that image instead of a generated one.
+//! `post = [{ type = "chromatic_aberration", strength = 0.003 }, { type = "film_grain",
+//! amount = 0.1, size = 1.5 }]` runs those effects over the render in that order, before the
+//! outline and the rest are drawn over it. `{ type = "bloom", threshold = 1, radius = 16,
+//! intensity = 0.1 }` spreads the light over the threshold around bright pixels, with
+//! `kernel = "star"` (and `points = 6`) in streaks instead of a round glow.
+//! `color_space = "srgb"` (or `"rec709"`, `"acescg"`) manages the colors: color textures are
+//! decoded from sRGB, shading happens in that linear working space (ACEScg's wider primaries
+//! for `"acescg"`) and the output is encoded with the space's curve. colors in the scene file
+//! are taken to be in the working space. without it textures are read as they are and the
+//! output is gamma corrected with a square root, like older versions did.
+//! `transparent = true` saves a PNG with an alpha channel that is transparent wherever the
+//! camera sees the background or an object with `shadow_catcher = true`, except for the
+//! shadows and reflections on the catchers, for compositing renders over photos.
+//!
+//! `light_rig = true` (or `light_rig = { key = 12, fill = 0.4, rim = 1.5, distance = 4 }`) adds
+//! a key, fill and rim light around all the objects, placed relative to the camera.
+//!
+//! `[sun_sky]` with a `latitude` and `longitude` (degrees, north and east positive), a `date`
+//! (`[2024, 6, 21]`) and a local clock `time` in hours lights the scene with the sun and the
+//! sky of that place and moment, replacing the `[environment]`. It takes `utc_offset` (hours,
+//! the longitude's time zone by default), `turbidity` (3), `intensity` (0.03) and the `north`
+//! of the scene (`[0, 0, -1]`). The sun is a directional light, which only the integrators
+//! that take point lights see.
+//!
+//! `[[lights]]` with `type = "point"`, a `position` and a `power` (the total it emits, not
+//! the light per area of the other lights) is a point light. It can't be hit, the `"path"`,
+//! `"wavefront"` and `"toon"` integrators light every bounce with it through a shadow ray of
+//! its own, the others leave it out.
+//!
+//! Quads take `uv_scale = [2, 2]` to tile their texture, `uv_rotation` (radians) and
+//! `uv_origin` to turn and move it. A `cuboid` (`min` and `max`) puts the whole texture on each
+//! face by default, `uv = "cross"` the faces of an unfolded box texture of 4 by 3 squares, and
+//! `uv = 0.5` tiles of that size that run on around the box.
+//!
+//! `type = "plane"` is an infinite plane through `point` facing `normal` (up by default), for
+//! grounds that reach the horizon, or a square `extent` units from its point to each side.
+//! Its texture coordinates are its coordinates in scene units times `uv_scale`.
+//!
+//! Any object can be moved with `axis`, `angle` (radians) and `translate`, which wraps it in an
+//! `Instance`, and `translate2` moves it on to there over the shutter, for motion blur. Meshes
+//! (`type = "mesh"` with a `file` and `scale`, OBJ or PLY by the file's extension) take
+//! `recenter = true` to move the middle of their bounds to the origin,
+//! `normalize = true` to fit them into a unit box before they're scaled, `backface_culling`,
+//! `terminator_fix` to start bounces from the smooth surface the vertex normals describe, which
+//! hides the jagged shadow terminator of low poly meshes, and `epsilon`, an extra ray offset on
+//! top of the one worked out from the rounding error of each hit.
+//! `intersection = "wide"` (the default) tests four triangles at once, `"wide_f32"` does the
+//! same in f32 with an exact Float test of the hits, and `"scalar"` one triangle at a time.
+//! Meshes can leave out `material`, their OBJ models then get a diffuse material from the `Kd`
+//! or `map_Kd` of their MTL material, or else the registry's `mesh_material` by name (an 18%
+//! grey principled unless a hook or default is set), and
+//! `materials = { Body = { ... } }` gives models materials by name.
+//! `smooth_normals = true` gives models without normals smooth ones when they're loaded, and
+//! `subdivide = 2` smooths a coarse mesh with that many steps of Loop subdivision, both keeping
+//! edges sharper than `crease_angle` degrees as creases.
+//! `displacement = { height = { type = "image", file = "..." }, scale = 0.1, subdivisions = 2 }`
+//! subdivides a mesh and moves its vertices along their normals when it is loaded (or by a
+//! tangent space vector with `vector = <texture>` instead of `height`).
+//! `type = "hair"` grows `strands` Bézier strands over the mesh in `file`, with `length`,
+//! `width = [root, tip]`, `droop`, `jitter`, `seed` and `shape = "ribbon"` or `"round"`;
+//! `type = "curve"` is a single strand through 4 control `points`. Use `type = "hair"`
+//! materials on them.
+//! Materials are looked up by `type` in a `MaterialRegistry`, so custom materials
+//! can be registered and then used from scene files.
+//! A top level `[materials]` table defines materials once by name, e.g.
+//! `[materials.gold]` with `type = "metal"`, which objects, lights and the `materials` of meshes
+//! then use as `material = "gold"`, as do the `a`, `b` and `base` of other materials, library
+//! ones included. each is built once and shared by everything using it. Overrides reach them as
+//! `materials.gold.roughness`.
+//! Colors and numbers in materials can also be textures: `"image"`, `"checker"` and the nodes
+//! `"mix"` (`a`, `b`, `factor`), `"multiply"` (`a`, `b`), `"ramp"` (`input`, `positions` and
+//! `colors` or `values`), `"noise"` (`scale`, `octaves`, `seed`, `space = "uv"`) and
+//! `"uv_warp"` (`texture`, `scale`, `offset`, `warp`, `strength`), whose inputs nest.
+//! Colors can also be `"vertex_color"`, the colors baked into the vertices of a mesh, with a
+//! `fallback` color or texture for whatever has none.
+//! Any material can take an `opacity` number or texture, and rays pass through it unshaded
+//! where it's below 1, e.g. `opacity = { type = "image", file = "leaf_alpha.png" }`.
+//! `sidedness = "black"` makes the back faces of a material absorb everything (one-sided light
+//! panels) and `sidedness = "invisible"` lets rays through them, the default is `"shaded"`.
+//! Objects and lights take `visibility = { camera = false, shadow = false, indirect = false }`
+//! to hide them from camera rays, shadow rays or bounces, each `true` when it's left out.
+//! Lights take `light_linking = { include = ["statue"], exclude = ["floor"] }` to light only
+//! the objects `include` names (all of them when it's left out) and never the ones `exclude`
+//! names, by their `name`, or their type and place in the list (`"sphere0"`). names no object
+//! goes by are warned about. The `"path"` and `"wavefront"` integrators leave both the light's
+//! shadow rays and the bounces that hit it out for the other objects.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use crate::{
+    aov::{id_hash, IdManifest, Outline},
+    blue_noise::BlueNoiseMask,
+    bsdf::testing::SamplingCheck,
+    bsdf::{diffuse::DiffuseBRDF, MatPtr},
+    camera::{
+        AdaptiveSampling, Aperture, Autofocus, BackgroundPriority, BloomKernel, Camera, Exposure,
+        FilterShape, LensDistortion, PixelFilter, PostEffect, Projection, Stereo, StereoLayout,
+    },
+    color::ColorSpace,
+    environment::EnvironmentType,
+    hittable::{
+        BvhLayout, Cuboid, CuboidUv, Curve, CurveShape, DeltaLight, Displacement, Groom, Hittable,
+        HittableList, Instance, LightLink, Linked, LinkedDelta, MeshBuffers, MeshImportOptions,
+        MeshOptions, ObjModel, Plane, PointLight, Quad, QuadUv, Sphere, Tagged,
+        TriangleIntersection, TriangleMesh, Visibility, Visible, World,
+    },
+    integrator::{
+        AoIntegrator, Integrator, IrradianceCache, LightcutsIntegrator, PathGuide, PathIntegrator,
+        SppmIntegrator, ToonIntegrator, WavefrontIntegrator,
+    },
+    light_rig::ThreePointRig,
+    lookdev,
+    metadata::hash_scene,
+    sky::SunSky,
+    texture::{decoded, SolidTexture, Texture},
+    texture_cache::TextureCache,
+    vec3::{Float, Vec2, Vec3},
+};
+
+pub mod params;
+pub use self::params::*;
+
+pub mod registry;
+pub use self::registry::*;
+
+pub mod watch;
+pub use self::watch::*;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(String),
+    MissingParam(String),
+    InvalidParam {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    UnknownType {
+        category: &'static str,
+        name: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "failed to read scene file: {err}"),
+            SceneError::Parse(msg) => write!(f, "failed to parse scene file: {msg}"),
+            SceneError::MissingParam(name) => write!(f, "missing parameter `{name}`"),
+            SceneError::InvalidParam {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "parameter `{name}` should be a {expected}, found a {found}"
+            ),
+            SceneError::UnknownType { category, name } => {
+                write!(f, "unknown {category} type `{name}`")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+pub struct Scene {
+    pub world: World,
+    pub camera: Camera<Box<dyn Integrator>>,
+    pub output: Option<String>,
+    /// seconds it took to build the BVH
+    pub bvh_time: f64,
+    /// the scene file, if it was loaded from one, and every file it pointed to, e.g. to reload
+    /// the scene when one of them changes
+    pub files: Vec<PathBuf>,
+    /// what the world was built from, see `world_source`
+    world_source: toml::Table,
+}
+
+/// load a scene file using the built-in materials
+pub fn load_scene(path: &str) -> Result<Scene, SceneError> {
+    load_scene_with(path, &MaterialRegistry::with_builtins())
+}
+
+/// load a scene file, resolving materials through `materials`
+pub fn load_scene_with(path: &str, materials: &MaterialRegistry) -> Result<Scene, SceneError> {
+    load_scene_with_overrides(path, materials, &[])
+}
+
+/// load a scene file with the numbers at the dotted `overrides` paths replaced, see
+/// `parse_scene_with_overrides`
+pub fn load_scene_with_overrides(
+    path: &str,
+    materials: &MaterialRegistry,
+    overrides: &[(&str, Float)],
+) -> Result<Scene, SceneError> {
+    load(path, materials, overrides, None)
+}
+
+/// load a scene file again after it changed, like `load_scene_with_overrides`. when nothing
+/// but the `[camera]` table changed since `previous` was loaded, its world and BVH are kept
+/// instead of built again. the files the world points to aren't looked at, so don't pass a
+/// `previous` whose images or meshes changed
+pub fn reload_scene(
+    path: &str,
+    materials: &MaterialRegistry,
+    overrides: &[(&str, Float)],
+    previous: Scene,
+) -> Result<Scene, SceneError> {
+    load(path, materials, overrides, Some(previous))
+}
+
+fn load(
+    path: &str,
+    materials: &MaterialRegistry,
+    overrides: &[(&str, Float)],
+    previous: Option<Scene>,
+) -> Result<Scene, SceneError> {
+    let text = fs::read_to_string(path)?;
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    let mut scene = build_scene(&text, base_dir, materials, overrides, previous)?;
+    scene.files.insert(0, PathBuf::from(path));
+    if scene.camera.scene_name.is_none() {
+        scene.camera.scene_name = Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+    }
+    Ok(scene)
+}
+
+/// build a scene from the contents of a scene file, with relative paths resolved against `base_dir`
+pub fn parse_scene(
+    text: &str,
+    base_dir: &Path,
+    materials: &MaterialRegistry,
+) -> Result<Scene, SceneError> {
+    parse_scene_with_overrides(text, base_dir, materials, &[])
+}
+
+/// like `parse_scene`, but first replaces the numbers at the dotted `overrides` paths,
+/// e.g. `camera.samples_per_pixel` or `objects.2.material.roughness` (list entries count from 0)
+pub fn parse_scene_with_overrides(
+    text: &str,
+    base_dir: &Path,
+    materials: &MaterialRegistry,
+    overrides: &[(&str, Float)],
+) -> Result<Scene, SceneError> {
+    build_scene(text, base_dir, materials, overrides, None)
+}
+
+/// the scene in `text`, with the world of `previous` if it was built from the same things
+fn build_scene(
+    text: &str,
+    base_dir: &Path,
+    materials: &MaterialRegistry,
+    overrides: &[(&str, Float)],
+    previous: Option<Scene>,
+) -> Result<Scene, SceneError> {
+    let mut table: toml::Table = text
+        .parse()
+        .map_err(|err: toml::de::Error| SceneError::Parse(err.to_string()))?;
+    // a scene without a camera table has the default camera, whose settings can be overridden
+    // all the same
+    if !overrides.is_empty() {
+        table
+            .entry("camera")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    }
+    let mut root = toml::Value::Table(table);
+    let mut hashed = text.to_string();
+    for (path, value) in overrides {
+        let keys: Vec<&str> = path.split('.').collect();
+        if !set_number(&mut root, &keys, *value) {
+            return Err(SceneError::MissingParam(path.to_string()));
+        }
+        hashed += &format!("\n{path} = {value}");
+    }
+    let toml::Value::Table(table) = root else {
+        unreachable!()
+    };
+    let world_source = world_source(&table);
+    let root = ParamMap::from(table);
+    let (bvh, bvh_stats) = match root.get("bvh") {
+        None => (BvhLayout::Tree, false),
+        Some(_) => {
+            let params = root.require_table("bvh")?;
+            let layout = if params.bool_or("reduced_precision", false)? {
+                BvhLayout::CompactReduced
+            } else if params.bool_or("compact", false)? {
+                BvhLayout::Compact
+            } else {
+                BvhLayout::Tree
+            };
+            (layout, params.bool_or("stats", false)?)
+        }
+    };
+    // textures are decoded as they're loaded, before the camera
+    let (ids, color_space) = match root.get("camera") {
+        Some(ParamValue::Table(camera)) => (camera.bool_or("ids", false)?, color_space(camera)?),
+        _ => (false, None),
+    };
+    let ctx = LoadContext {
+        base_dir,
+        materials,
+        bvh,
+        bvh_stats,
+        ids: ids.then(|| RefCell::new(IdManifest::default())),
+        color_space,
+        files: RefCell::default(),
+        library: match root.get("materials") {
+            Some(_) => Some(root.require_table("materials")?),
+            None => None,
+        },
+        built: RefCell::default(),
+    };
+
+    let mut camera = match root.get("camera") {
+        Some(ParamValue::Table(params)) => build_camera(params, &ctx)?,
+        _ => build_camera(&ParamMap::new(), &ctx)?,
+    };
+    let previous = previous.filter(|previous| previous.world_source == world_source);
+    let (mut world, bvh_time, ids) = match previous {
+        Some(previous) => {
+            log::info!("only the camera changed, keeping the world and its BVH");
+            frame_camera(&root, &mut camera, &previous.world)?;
+            (previous.world, previous.bvh_time, previous.camera.ids)
+        }
+        None => {
+            let (world, bvh_time) = build_world(&root, &ctx, &mut camera)?;
+            (world, bvh_time, ctx.ids.map(RefCell::into_inner))
+        }
+    };
+    camera.scene_hash = Some(hash_scene(&hashed));
+    camera.ids = ids;
+    // a kept world already has the files it was built from
+    let mut files = world.files.clone();
+    for file in ctx.files.into_inner() {
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    world.files = files.clone();
+    if root.get("name").is_some() {
+        camera.scene_name = Some(root.require_str("name")?.to_string());
+    }
+
+    let output = match root.get("output") {
+        Some(_) => Some(root.require_str("output")?.to_string()),
+        None => None,
+    };
+
+    Ok(Scene {
+        world,
+        camera,
+        output,
+        bvh_time,
+        files,
+        world_source,
+    })
+}
+
+/// the objects, lights and environment of the scene with its BVH, and the seconds it took to
+/// build the BVH. `camera` is framed on them, and a light rig placed from it
+fn build_world(
+    root: &ParamMap,
+    ctx: &LoadContext,
+    camera: &mut Camera<Box<dyn Integrator>>,
+) -> Result<(World, f64), SceneError> {
+    // the objects the lights are linked to need IDs for it even without the ID passes
+    let mut linked = HashSet::new();
+    for entry in list(root, "lights")? {
+        if let Some(link) = light_link(entry)? {
+            linked.extend(link.names.into_iter().map(str::to_string));
+        }
+    }
+
+    let mut world = World::new();
+    let mut names = HashSet::new();
+    for (i, entry) in list(root, "objects")?.into_iter().enumerate() {
+        let name = format!("{}{i}", entry.require_str("type")?);
+        names.insert(entry.str_or("name", &name)?.to_string());
+        let object = tag_ids(build_object(entry, ctx)?, entry, &name, &linked, ctx)?;
+        if entry.bool_or("shadow_catcher", false)? {
+            world.add_shadow_catcher(object);
+        } else {
+            world.add_shared_object(object);
+        }
+    }
+    for (i, entry) in list(root, "lights")?.into_iter().enumerate() {
+        if entry.require_str("type")? == "point" {
+            let light: Arc<dyn DeltaLight> = Arc::new(PointLight::new(
+                entry.require_vec3("position")?,
+                entry.require_vec3("power")?,
+            ));
+            match light_link(entry)? {
+                Some(link) => {
+                    world.add_shared_delta_light(Arc::new(LinkedDelta::new(light, link.link)))
+                }
+                None => world.add_shared_delta_light(light),
+            }
+            continue;
+        }
+        let name = format!("light{i}");
+        names.insert(entry.str_or("name", &name)?.to_string());
+        let light = build_object(entry, ctx)?;
+        let light = tag_ids(light, entry, &name, &linked, ctx)?;
+        match light_link(entry)? {
+            Some(link) => world.add_shared_light(Arc::new(Linked::new(light, link.link))),
+            None => world.add_shared_light(light),
+        }
+    }
+    let mut unknown: Vec<&String> = linked.difference(&names).collect();
+    unknown.sort();
+    for name in unknown {
+        log::warn!("light_linking names `{name}`, but no object is called that");
+    }
+    if let Some(ParamValue::Table(env)) = root.get("environment") {
+        world.environment = if env.contains("map") {
+            let map = ctx.image(env.require_str("map")?);
+            match ctx.color_space {
+                Some(color_space) => EnvironmentType::DecodedMap(map, color_space),
+                None => EnvironmentType::Map(map),
+            }
+        } else {
+            EnvironmentType::Color(env.vec3_or("color", Vec3::ZERO)?)
+        };
+    }
+    if let Some(ParamValue::Table(params)) = root.get("sun_sky") {
+        let date = params.require_vec3("date")?;
+        let mut sun_sky = SunSky::new(
+            params.require_f64("latitude")?,
+            params.require_f64("longitude")?,
+            (date.x as i32, date.y as u32, date.z as u32),
+            params.require_f64("time")?,
+        );
+        sun_sky.utc_offset = params.f64_or("utc_offset", sun_sky.utc_offset)?;
+        sun_sky.turbidity = params.f64_or("turbidity", sun_sky.turbidity)?;
+        sun_sky.intensity = params.f64_or("intensity", sun_sky.intensity)?;
+        sun_sky.north = params.vec3_or("north", sun_sky.north)?;
+        sun_sky.color_space = ctx.color_space;
+        sun_sky.add_to(&mut world);
+    }
+
+    frame_camera(root, camera, &world)?;
+    let rig = match root.get("light_rig") {
+        None | Some(ParamValue::Bool(false)) => None,
+        Some(ParamValue::Bool(true)) => Some(ThreePointRig::default()),
+        Some(_) => {
+            let params = root.require_table("light_rig")?;
+            let default = ThreePointRig::default();
+            Some(ThreePointRig {
+                key_intensity: params.f64_or("key", default.key_intensity)?,
+                fill_ratio: params.f64_or("fill", default.fill_ratio)?,
+                rim_ratio: params.f64_or("rim", default.rim_ratio)?,
+                distance: params.f64_or("distance", default.distance)?,
+            })
+        }
+    };
+    if let (Some(rig), false) = (rig, world.objects.is_empty()) {
+        for light in rig.lights(&world.objects, camera) {
+            world.add_light(light);
+        }
+    }
+    let start = Instant::now();
+    world.build_bvh_with(ctx.bvh);
+    let bvh_time = start.elapsed().as_secs_f64();
+    log::info!("built the BVH in {bvh_time:.3}s");
+    if let (true, Some(stats)) = (ctx.bvh_stats, world.objects.bvh_stats()) {
+        log::info!("scene BVH: {stats}");
+    }
+    let textures = TextureCache::global().report();
+    if textures.textures > 0 {
+        log::info!("texture cache: {textures}");
+    }
+    Ok((world, bvh_time))
+}
+
+/// point the camera at the whole world if the scene asks for it with `frame`
+fn frame_camera(
+    root: &ParamMap,
+    camera: &mut Camera<Box<dyn Integrator>>,
+    world: &World,
+) -> Result<(), SceneError> {
+    if let Some(ParamValue::Table(params)) = root.get("camera") {
+        match params.get("frame") {
+            None | Some(ParamValue::Bool(false)) => (),
+            Some(ParamValue::Bool(true)) => camera.frame(world, 0.1),
+            Some(_) => camera.frame(world, params.require_f64("frame")?),
+        }
+    }
+    Ok(())
+}
+
+/// the parts of the scene file the world is built from: all of it but the output, the name
+/// and the camera settings besides `ids` and `color_space`, which the objects are loaded with.
+/// a light rig is placed from the camera, so then all of the camera counts
+fn world_source(table: &toml::Table) -> toml::Table {
+    let mut source = table.clone();
+    source.remove("output");
+    source.remove("name");
+    if !source.contains_key("light_rig") {
+        if let Some(toml::Value::Table(camera)) = source.get_mut("camera") {
+            camera.retain(|key, _| key == "ids" || key == "color_space");
+        }
+    }
+    source
+}
+
+/// a missing last key is added to its table, so values left at their defaults can be overridden too
+fn set_number(value: &mut toml::Value, keys: &[&str], number: Float) -> bool {
+    let Some((key, rest)) = keys.split_first() else {
+        *value = toml::Value::from(number);
+        return true;
+    };
+    match value {
+        toml::Value::Table(table) if rest.is_empty() => {
+            table.insert(key.to_string(), toml::Value::from(number));
+            true
+        }
+        toml::Value::Table(table) => table
+            .get_mut(*key)
+            .is_some_and(|value| set_number(value, rest, number)),
+        toml::Value::Array(array) => key
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| array.get_mut(i))
+            .is_some_and(|value| set_number(value, rest, number)),
+        _ => false,
+    }
+}
+
+fn list<'a>(root: &'a ParamMap, name: &str) -> Result<Vec<&'a ParamMap>, SceneError> {
+    match root.get(name) {
+        None => Ok(vec![]),
+        Some(ParamValue::List(entries)) => entries
+            .iter()
+            .map(|e| {
+                e.as_table().ok_or_else(|| SceneError::InvalidParam {
+                    name: name.to_string(),
+                    expected: "table",
+                    found: e.type_name(),
+                })
+            })
+            .collect(),
+        Some(v) => Err(SceneError::InvalidParam {
+            name: name.to_string(),
+            expected: "list of tables",
+            found: v.type_name(),
+        }),
+    }
+}
+
+/// `{ height = <texture>, scale = 0.1, subdivisions = 2 }`, or `vector = <color texture>`
+/// instead of `height` for vector displacement
+/// a diffuse material with the color (`Kd`) or color texture (`map_Kd`) of an MTL material, none
+/// if it has neither. textures are relative to the OBJ file and go through the texture cache
+fn mtl_material(
+    material: &tobj::Material,
+    obj_dir: &Path,
+    color_space: Option<ColorSpace>,
+) -> Option<MatPtr> {
+    let color: Arc<dyn Texture<Vec3>> = match (&material.diffuse_texture, material.diffuse) {
+        (Some(file), _) => decoded(
+            TextureCache::global().get(&obj_dir.join(file).to_string_lossy()),
+            color_space,
+        ),
+        (None, Some([r, g, b])) => Arc::new(SolidTexture::new(Vec3::new(
+            r as Float, g as Float, b as Float,
+        ))),
+        (None, None) => return None,
+    };
+    Some(Arc::new(DiffuseBRDF::new(color)))
+}
+
+fn build_displacement(params: &ParamMap, ctx: &LoadContext) -> Result<Displacement, SceneError> {
+    let scale = params.f64_or("scale", 1.0)?;
+    let subdivisions = params.f64_or("subdivisions", 2.0)? as usize;
+    if params.contains("vector") {
+        let map = ctx.vec3_texture(params, "vector", Vec3::splat(0.5))?;
+        Ok(Displacement::vector(map, scale, subdivisions))
+    } else {
+        let map = ctx.f64_texture(params, "height", 0.0)?;
+        Ok(Displacement::height(map, scale, subdivisions))
+    }
+}
+
+/// `width = 0.01` for the same width along the whole strand, or `width = [root, tip]`
+fn widths(params: &ParamMap, default: (Float, Float)) -> Result<(Float, Float), SceneError> {
+    match params.get("width") {
+        None => Ok(default),
+        Some(ParamValue::List(list)) if list.len() == 2 => {
+            match (list[0].as_f64(), list[1].as_f64()) {
+                (Some(root), Some(tip)) => Ok((root, tip)),
+                _ => Err(SceneError::InvalidParam {
+                    name: "width".to_string(),
+                    expected: "number or [root, tip]",
+                    found: "list",
+                }),
+            }
+        }
+        Some(_) => {
+            let width = params.require_f64("width")?;
+            Ok((width, width))
+        }
+    }
+}
+
+/// `shape = "ribbon"` (the default) or `"round"`
+fn curve_shape(params: &ParamMap) -> Result<CurveShape, SceneError> {
+    match params.str_or("shape", "ribbon")? {
+        "ribbon" => Ok(CurveShape::Ribbon),
+        "round" => Ok(CurveShape::Round),
+        other => Err(SceneError::UnknownType {
+            category: "curve shape",
+            name: other.to_string(),
+        }),
+    }
+}
+
+/// `scale`, `recenter`, `normalize`, `smooth_normals` and `crease_angle` of a mesh file
+fn mesh_import(params: &ParamMap) -> Result<MeshImportOptions, SceneError> {
+    Ok(MeshImportOptions {
+        scale: params.f64_or("scale", 1.0)?,
+        recenter: params.bool_or("recenter", false)?,
+        normalize_to_unit: params.bool_or("normalize", false)?,
+        smooth_normals: params.bool_or("smooth_normals", false)?,
+        crease_angle: match params.get("crease_angle") {
+            Some(_) => Some(params.require_f64("crease_angle")?),
+            None => None,
+        },
+        ..Default::default()
+    })
+}
+
+/// `intersection = "wide"` (the default), `"wide_f32"` or `"scalar"`
+fn triangle_intersection(params: &ParamMap) -> Result<TriangleIntersection, SceneError> {
+    match params.str_or("intersection", "wide")? {
+        "scalar" => Ok(TriangleIntersection::Scalar),
+        "wide" => Ok(TriangleIntersection::Wide),
+        "wide_f32" => Ok(TriangleIntersection::WideF32),
+        other => Err(SceneError::UnknownType {
+            category: "triangle intersection",
+            name: other.to_string(),
+        }),
+    }
+}
+
+/// `uv = "faces"` (the default), `"cross"`, or the size of the tiles
+fn cuboid_uv(params: &ParamMap) -> Result<CuboidUv, SceneError> {
+    match params.get("uv") {
+        None => Ok(CuboidUv::Faces),
+        Some(ParamValue::Number(size)) => Ok(CuboidUv::Tile(*size)),
+        Some(_) => match params.require_str("uv")? {
+            "faces" => Ok(CuboidUv::Faces),
+            "cross" => Ok(CuboidUv::Cross),
+            other => Err(SceneError::UnknownType {
+                category: "cuboid uv layout",
+                name: other.to_string(),
+            }),
+        },
+    }
+}
+
+fn build_object(params: &ParamMap, ctx: &LoadContext) -> Result<Arc<dyn Hittable>, SceneError> {
+    let kind = params.require_str("type")?;
+    let material = match params.get("material") {
+        Some(_) => ctx.material_at(params, "material")?,
+        // meshes can go without, their models then get materials by name from the registry
+        None if kind == "mesh" => ctx.materials.default_material(),
+        None => return Err(SceneError::MissingParam("material".to_string())),
+    };
+    let object: Arc<dyn Hittable> = match kind {
+        "sphere" => {
+            let radius = params.require_f64("radius")?;
+            let center = params.require_vec3("center")?;
+            match params.get("center2") {
+                Some(_) => Arc::new(Sphere::new_moving(
+                    radius,
+                    center,
+                    params.require_vec3("center2")?,
+                    material,
+                )),
+                None => Arc::new(Sphere::new_still(radius, center, material)),
+            }
+        }
+        "quad" => {
+            let uv = QuadUv {
+                origin: params.vec2_or("uv_origin", Vec2::ZERO)?,
+                scale: params.vec2_or("uv_scale", Vec2::ONE)?,
+                rotation: params.f64_or("uv_rotation", 0.0)?,
+            };
+            let quad = Quad::new(
+                params.require_vec3("q")?,
+                params.require_vec3("u")?,
+                params.require_vec3("v")?,
+                material,
+            );
+            Arc::new(quad.with_uv(uv))
+        }
+        "plane" => {
+            let plane = Plane::new(
+                params.vec3_or("point", Vec3::ZERO)?,
+                params.vec3_or("normal", Vec3::Y)?,
+                material,
+            )
+            .with_uv_scale(params.vec2_or("uv_scale", Vec2::ONE)?);
+            match params.get("extent") {
+                Some(_) => Arc::new(plane.with_extent(params.require_f64("extent")?)),
+                None => Arc::new(plane),
+            }
+        }
+        "cuboid" => Arc::new(Cuboid::with_uv(
+            params.require_vec3("min")?,
+            params.require_vec3("max")?,
+            material,
+            cuboid_uv(params)?,
+        )),
+        "mesh" => {
+            let file = ctx.resolve(params.require_str("file")?);
+            let import = mesh_import(params)?;
+            let defaults = MeshOptions::default();
+            let options = MeshOptions {
+                backface_culling: params.bool_or("backface_culling", defaults.backface_culling)?,
+                shadow_terminator_fix: params
+                    .bool_or("terminator_fix", defaults.shadow_terminator_fix)?,
+                epsilon: params.f64_or("epsilon", defaults.epsilon)?,
+                bvh: ctx.bvh,
+                intersection: triangle_intersection(params)?,
+            };
+            let subdivisions = params.f64_or("subdivide", 0.0)? as usize;
+            let crease_angle = import.crease_angle;
+            let displacement = match params.get("displacement") {
+                Some(_) => Some(build_displacement(
+                    params.require_table("displacement")?,
+                    ctx,
+                )?),
+                None => None,
+            };
+            // `materials = { Body = { ... } }` for the OBJ models by name
+            let mut by_name = HashMap::new();
+            if params.contains("materials") {
+                let table = params.require_table("materials")?;
+                for name in table.keys() {
+                    by_name.insert(name.to_string(), ctx.material_at(table, name)?);
+                }
+            }
+            let (models, mtl, missing) = match MeshBuffers::load(&file, &import) {
+                Ok((models, mtl)) => (models, mtl, false),
+                Err(err) => {
+                    log::error!("{err}, using a placeholder cube instead");
+                    let placeholder = ObjModel {
+                        name: String::new(),
+                        buffers: MeshBuffers::placeholder(import.scale),
+                        material: None,
+                    };
+                    (vec![placeholder], vec![], true)
+                }
+            };
+            // models sharing an MTL material share the built material, and its textures
+            let obj_dir = Path::new(&file).parent().unwrap_or(Path::new(""));
+            let mut from_mtl = HashMap::new();
+            let mut meshes = HittableList::new();
+            for ObjModel {
+                name,
+                mut buffers,
+                material: mtl_id,
+            } in models
+            {
+                let mtl_material = mtl_id.and_then(|id| {
+                    from_mtl
+                        .entry(id)
+                        .or_insert_with(|| {
+                            mtl.get(id)
+                                .and_then(|m| mtl_material(m, obj_dir, ctx.color_space))
+                        })
+                        .clone()
+                });
+                // the ID passes tell the models apart by the names of their materials
+                let material_id = match (by_name.contains_key(&name), &mtl_material) {
+                    _ if missing => ctx.material_id("missing"),
+                    (false, _) if params.contains("material") => {
+                        ctx.material_id(&material_name(params, ctx)?)
+                    }
+                    (false, Some(_)) => mtl_id
+                        .and_then(|id| mtl.get(id))
+                        .and_then(|m| ctx.material_id(&m.name)),
+                    _ => ctx.material_id(&name),
+                };
+                let material = match (by_name.get(&name), mtl_material) {
+                    _ if missing => lookdev::missing_material(),
+                    (Some(material), _) => material.clone(),
+                    (None, _) if params.contains("material") => material.clone(),
+                    (None, Some(material)) => material,
+                    (None, None) => ctx.materials.mesh_material(&name),
+                };
+                if subdivisions > 0 {
+                    buffers = buffers.loop_subdivided(subdivisions, crease_angle);
+                }
+                if let Some(displacement) = &displacement {
+                    buffers = buffers.displaced(displacement);
+                }
+                let mesh = TriangleMesh::from_mesh_buffers(&buffers, material, options);
+                if let (true, Some(stats)) = (ctx.bvh_stats, mesh.bvh_stats()) {
+                    log::info!("BVH of {file}: {stats}");
+                }
+                match material_id {
+                    Some(id) => meshes.add(Tagged::new(Arc::new(mesh)).with_material_id(id)),
+                    None => meshes.add(mesh),
+                }
+            }
+            meshes.build_bvh();
+            Arc::new(meshes)
+        }
+        "curve" => {
+            let points = match params.get("points") {
+                Some(ParamValue::List(list)) => {
+                    list.iter().filter_map(ParamValue::as_vec3).collect()
+                }
+                Some(_) => vec![],
+                None => return Err(SceneError::MissingParam("points".to_string())),
+            };
+            let points = <[Vec3; 4]>::try_from(points).map_err(|_| SceneError::InvalidParam {
+                name: "points".to_string(),
+                expected: "list of 4 points",
+                found: params
+                    .get("points")
+                    .map_or("nothing", ParamValue::type_name),
+            })?;
+            let (root_width, tip_width) = widths(params, (0.01, 0.01))?;
+            Arc::new(Curve::new(
+                points,
+                root_width,
+                tip_width,
+                curve_shape(params)?,
+                material,
+            ))
+        }
+        "hair" => {
+            let file = ctx.resolve(params.require_str("file")?);
+            let import = mesh_import(params)?;
+            let default = Groom::default();
+            let (root_width, tip_width) = widths(params, (default.root_width, default.tip_width))?;
+            let groom = Groom {
+                strands: params.f64_or("strands", default.strands as Float)? as usize,
+                length: params.f64_or("length", default.length)?,
+                root_width,
+                tip_width,
+                droop: params.f64_or("droop", default.droop)?,
+                jitter: params.f64_or("jitter", default.jitter)?,
+                shape: curve_shape(params)?,
+                seed: params.f64_or("seed", default.seed as Float)? as u64,
+                bvh: ctx.bvh,
+            };
+            let models = match MeshBuffers::load(&file, &import) {
+                Ok((models, _)) => models.into_iter().map(|model| model.buffers).collect(),
+                Err(err) => {
+                    log::error!("{err}, growing the hair on a placeholder cube instead");
+                    vec![MeshBuffers::placeholder(import.scale)]
+                }
+            };
+            let mut surface = MeshBuffers::default();
+            for buffers in models {
+                let offset = surface.vertices.len();
+                if buffers.normals.len() == buffers.vertices.len() {
+                    surface.normals.extend(buffers.normals);
+                }
+                surface.vertices.extend(buffers.vertices);
+                surface
+                    .indices
+                    .extend(buffers.indices.iter().map(|i| i + offset));
+            }
+            if surface.normals.len() != surface.vertices.len() {
+                surface.normals = surface.smooth_normals();
+            }
+            let strands = groom.grow(&surface, material);
+            if let (true, Some(stats)) = (ctx.bvh_stats, strands.bvh_stats()) {
+                log::info!("BVH of the hair on {file}: {stats}");
+            }
+            Arc::new(strands)
+        }
+        other => {
+            return Err(SceneError::UnknownType {
+                category: "object",
+                name: other.to_string(),
+            })
+        }
+    };
+
+    let object: Arc<dyn Hittable> = if ["axis", "angle", "translate", "translate2"]
+        .iter()
+        .any(|key| params.contains(key))
+    {
+        let translation = params.vec3_or("translate", Vec3::ZERO)?;
+        let instance = Instance::new(
+            object,
+            params.vec3_or("axis", Vec3::Y)?.normalize(),
+            params.f64_or("angle", 0.0)?,
+            translation,
+        );
+        Arc::new(instance.with_motion(params.vec3_or("translate2", translation)?))
+    } else {
+        object
+    };
+
+    match params.get("visibility") {
+        Some(_) => {
+            let table = params.require_table("visibility")?;
+            let visibility = Visibility {
+                camera: table.bool_or("camera", true)?,
+                shadow: table.bool_or("shadow", true)?,
+                indirect: table.bool_or("indirect", true)?,
+            };
+            Ok(Arc::new(Visible::new(object, visibility)))
+        }
+        None => Ok(object),
+    }
+}
+
+/// tag an object and its material for the ID passes, or only the object when a light is
+/// `linked` to it. objects are called `name` unless they have one, and so are materials, or
+/// else after their parameters
+fn tag_ids(
+    object: Arc<dyn Hittable>,
+    params: &ParamMap,
+    name: &str,
+    linked: &HashSet<String>,
+    ctx: &LoadContext,
+) -> Result<Arc<dyn Hittable>, SceneError> {
+    let name = params.str_or("name", name)?;
+    let object_id = ctx
+        .object_id(name)
+        .or_else(|| linked.contains(name).then(|| id_hash(name)));
+    let Some(object_id) = object_id else {
+        return Ok(object);
+    };
+    let mut tagged = Tagged::new(object).with_object_id(object_id);
+    if params.contains("material") {
+        if let Some(id) = ctx.material_id(&material_name(params, ctx)?) {
+            tagged = tagged.with_material_id(id);
+        }
+    }
+    Ok(Arc::new(tagged))
+}
+
+/// A light's `light_linking` table, with the names of the objects in it
+struct NamedLightLink<'a> {
+    link: LightLink,
+    names: Vec<&'a str>,
+}
+
+/// `light_linking = { include = [...], exclude = [...] }` of a light, objects by name
+fn light_link(params: &ParamMap) -> Result<Option<NamedLightLink<'_>>, SceneError> {
+    if !params.contains("light_linking") {
+        return Ok(None);
+    }
+    let table = params.require_table("light_linking")?;
+    let names = |key: &str| -> Result<Option<Vec<&str>>, SceneError> {
+        if !table.contains(key) {
+            return Ok(None);
+        }
+        table
+            .require_list(key)?
+            .iter()
+            .map(|name| {
+                name.as_str().ok_or_else(|| SceneError::InvalidParam {
+                    name: key.to_string(),
+                    expected: "list of object names",
+                    found: name.type_name(),
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    };
+    let (include, exclude) = (names("include")?, names("exclude")?.unwrap_or_default());
+    let ids = |names: &[&str]| names.iter().map(|name| id_hash(name)).collect();
+    Ok(Some(NamedLightLink {
+        link: LightLink {
+            include: include.as_deref().map(ids),
+            exclude: ids(&exclude),
+        },
+        names: include.into_iter().flatten().chain(exclude).collect(),
+    }))
+}
+
+/// the name of the `material` of `params`: a library material goes by its name in the library,
+/// unless it has a `name` of its own
+fn material_name(params: &ParamMap, ctx: &LoadContext) -> Result<String, SceneError> {
+    match params.get("material") {
+        Some(ParamValue::String(name)) => Ok(ctx.library_name(name).to_string()),
+        _ => table_material_name(params.require_table("material")?),
+    }
+}
+
+/// the material's `name`, or its type and a hash of its parameters, so materials that are
+/// written the same get the same ID
+fn table_material_name(params: &ParamMap) -> Result<String, SceneError> {
+    match params.get("name") {
+        Some(_) => Ok(params.require_str("name")?.to_string()),
+        None => Ok(format!(
+            "{}-{:08x}",
+            params.require_str("type")?,
+            id_hash(&canonical(params))
+        )),
+    }
+}
+
+/// the parameters as text with the keys sorted, which the hash map doesn't keep
+fn canonical(params: &ParamMap) -> String {
+    fn value(param: &ParamValue) -> String {
+        match param {
+            ParamValue::Table(table) => canonical(table),
+            ParamValue::List(list) => {
+                let items: Vec<String> = list.iter().map(value).collect();
+                format!("[{}]", items.join(","))
+            }
+            other => format!("{other:?}"),
+        }
+    }
+    let mut keys: Vec<&str> = params.keys().collect();
+    keys.sort_unstable();
+    let entries: Vec<String> = keys
+        .into_iter()
+        .filter_map(|key| Some(format!("{key}={}", value(params.get(key)?))))
+        .collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+/// `radius` in pixels, or the one the shape is usually used with
+fn pixel_filter(shape: &str, radius: Option<Float>) -> Result<PixelFilter, SceneError> {
+    let shape = match shape {
+        "box" => FilterShape::Box,
+        "triangle" => FilterShape::Triangle,
+        "gaussian" => FilterShape::Gaussian,
+        "blackman_harris" => FilterShape::BlackmanHarris,
+        "mitchell" => FilterShape::Mitchell,
+        other => {
+            return Err(SceneError::UnknownType {
+                category: "filter",
+                name: other.to_string(),
+            })
+        }
+    };
+    Ok(PixelFilter::new(
+        shape,
+        radius.unwrap_or(shape.default_radius()),
+    ))
+}
+
+fn build_integrator(params: &ParamMap) -> Result<Box<dyn Integrator>, SceneError> {
+    match params.str_or("integrator", "path")? {
+        "path" => {
+            let mut path = PathIntegrator::new(params.f64_or("max_depth", 50.0)? as usize);
+            path.max_diffuse_bounces =
+                params.f64_or("max_diffuse_bounces", path.max_depth as Float)? as usize;
+            path.max_specular_bounces =
+                params.f64_or("max_specular_bounces", path.max_depth as Float)? as usize;
+            path.max_transmission_bounces =
+                params.f64_or("max_transmission_bounces", path.max_depth as Float)? as usize;
+            path.light_samples = params.f64_or("light_samples", 1.0)?.max(1.0) as usize;
+            path.irradiance_cache = match params.get("irradiance_cache") {
+                None | Some(ParamValue::Bool(false)) => None,
+                Some(ParamValue::Bool(true)) => Some(IrradianceCache::default()),
+                Some(_) => {
+                    let cache = params.require_table("irradiance_cache")?;
+                    let default = IrradianceCache::default();
+                    Some(IrradianceCache::new(
+                        cache.f64_or("error", default.error)?,
+                        cache.f64_or("rays", default.rays as Float)? as usize,
+                    ))
+                }
+            };
+            path.guiding = match params.get("guiding") {
+                None | Some(ParamValue::Bool(false)) => None,
+                Some(ParamValue::Bool(true)) => Some(PathGuide::new(4)),
+                Some(_) => {
+                    let guiding = params.require_table("guiding")?;
+                    let mut guide =
+                        PathGuide::new(guiding.f64_or("passes", 4.0)?.max(1.0) as usize);
+                    guide.mix = guiding.f64_or("mix", guide.mix)?.clamp(0.0, 1.0);
+                    Some(guide)
+                }
+            };
+            path.sampling_check = match params.get("check_sampling") {
+                None | Some(ParamValue::Bool(false)) => None,
+                Some(ParamValue::Bool(true)) => Some(SamplingCheck::default()),
+                Some(_) => {
+                    let check = params.require_table("check_sampling")?;
+                    let default = SamplingCheck::default();
+                    Some(SamplingCheck::new(
+                        check.f64_or("rate", default.rate)?,
+                        check.f64_or("samples", default.samples as Float)? as usize,
+                    ))
+                }
+            };
+            Ok(Box::new(path))
+        }
+        "toon" => {
+            let default = ToonIntegrator::default();
+            let mut toon = ToonIntegrator::new(
+                params.f64_or("bands", default.bands as Float)? as usize,
+                params.f64_or("ambient", default.ambient)?,
+            );
+            toon.key_direction = params.vec3_or("key_direction", default.key_direction)?;
+            toon.max_depth = params.f64_or("max_depth", default.max_depth as Float)? as usize;
+            Ok(Box::new(toon))
+        }
+        "ao" => {
+            let default = AoIntegrator::default();
+            let settings = match params.get("ao") {
+                Some(_) => ao_settings(params.require_table("ao")?, default.settings)?,
+                None => default.settings,
+            };
+            let mut ao = AoIntegrator::new(
+                settings,
+                params.f64_or("ao_samples", default.samples as Float)? as usize,
+            );
+            ao.up = params.vec3_or("vup", default.up)?;
+            Ok(Box::new(ao))
+        }
+        "sppm" => {
+            let default = SppmIntegrator::default();
+            let mut sppm = SppmIntegrator::new(
+                params.f64_or("photons", default.photons_per_pass as Float)? as usize,
+                params.f64_or("passes", default.passes as Float)? as usize,
+            );
+            if params.contains("radius") {
+                sppm.initial_radius = Some(params.require_f64("radius")?);
+            }
+            sppm.alpha = params.f64_or("alpha", default.alpha)?;
+            sppm.max_depth = params.f64_or("max_depth", default.max_depth as Float)? as usize;
+            Ok(Box::new(sppm))
+        }
+        "wavefront" => {
+            let default = WavefrontIntegrator::default();
+            let max_depth = params.f64_or("max_depth", default.max_depth as Float)? as usize;
+            Ok(Box::new(WavefrontIntegrator::new(max_depth)))
+        }
+        "lightcuts" => {
+            let default = LightcutsIntegrator::default();
+            let mut lightcuts = LightcutsIntegrator::new(
+                params.f64_or("light_points", default.points_per_light as Float)? as usize,
+                params.f64_or("error", default.error)?,
+            );
+            lightcuts.max_cut = params.f64_or("max_cut", default.max_cut as Float)? as usize;
+            lightcuts.max_depth = params.f64_or("max_depth", default.max_depth as Float)? as usize;
+            Ok(Box::new(lightcuts))
+        }
+        other => Err(SceneError::UnknownType {
+            category: "integrator",
+            name: other.to_string(),
+        }),
+    }
+}
+
+/// `color_space = "srgb"`, `"rec709"` or `"acescg"`, none without it
+fn color_space(params: &ParamMap) -> Result<Option<ColorSpace>, SceneError> {
+    match params.get("color_space") {
+        None => Ok(None),
+        Some(_) => {
+            let name = params.require_str("color_space")?;
+            ColorSpace::from_name(name)
+                .map(Some)
+                .ok_or_else(|| SceneError::UnknownType {
+                    category: "color space",
+                    name: name.to_string(),
+                })
+        }
+    }
+}
+
+fn build_camera(
+    params: &ParamMap,
+    ctx: &LoadContext,
+) -> Result<Camera<Box<dyn Integrator>>, SceneError> {
+    let mut camera = Camera::with_integrator(build_integrator(params)?);
+    camera.aspect_ratio = params.f64_or("aspect_ratio", 16.0 / 9.0)?;
+    camera.image_width = params.f64_or("image_width", 600.0)? as usize;
+    camera.samples_per_pixel = params.f64_or("samples_per_pixel", 100.0)? as usize;
+
+    camera.vfov = params.f64_or("vfov", 40.0)?;
+    camera.look_from = params.vec3_or("look_from", Vec3::ZERO)?;
+    camera.look_at = params.vec3_or("look_at", Vec3::NEG_Z)?;
+    camera.vup = params.vec3_or("vup", Vec3::Y)?;
+    camera.projection = match params.str_or("projection", "perspective")? {
+        "perspective" => Projection::Perspective,
+        "orthographic" => Projection::Orthographic,
+        "fisheye" => Projection::Fisheye(params.f64_or("fisheye_fov", 180.0)?),
+        "equirectangular" => Projection::Equirectangular,
+        other => {
+            return Err(SceneError::UnknownType {
+                category: "projection",
+                name: other.to_string(),
+            })
+        }
+    };
+
+    if params.contains("blur_strength") {
+        log::warn!("`blur_strength` is ignored, antialiasing is set with `filter` now");
+    }
+    camera.filter = match params.get("filter") {
+        None => PixelFilter::default(),
+        Some(ParamValue::String(_)) => pixel_filter(params.require_str("filter")?, None)?,
+        Some(_) => {
+            let filter = params.require_table("filter")?;
+            let radius = match filter.get("radius") {
+                Some(_) => Some(filter.require_f64("radius")?),
+                None => None,
+            };
+            pixel_filter(filter.require_str("type")?, radius)?
+        }
+    };
+    camera.focal_length = params.f64_or("focal_length", 10.0)?;
+    camera.focus_distance = match params.get("focus_distance") {
+        Some(_) => Some(params.require_f64("focus_distance")?),
+        None => None,
+    };
+    camera.autofocus = match params.get("autofocus") {
+        None | Some(ParamValue::Bool(false)) => None,
+        Some(ParamValue::Bool(true)) => Some(Autofocus::LookAt),
+        Some(value) => Some(Autofocus::Pick(value.as_vec2().ok_or_else(|| {
+            SceneError::InvalidParam {
+                name: "autofocus".to_string(),
+                expected: "bool or [x, y]",
+                found: value.type_name(),
+            }
+        })?)),
+    };
+    camera.defocus_angle = params.f64_or("defocus_angle", 0.0)?;
+    camera.defocus_preview = params.bool_or("defocus_preview", false)?;
+    camera.bounding_boxes = params.bool_or("bounding_boxes", false)?;
+    if let Some(ParamValue::Table(aperture)) = params.get("aperture") {
+        camera.aperture = match aperture.require_str("shape")? {
+            "disk" => Aperture::Disk,
+            "polygon" => Aperture::polygon(
+                aperture.f64_or("blades", 6.0)? as usize,
+                aperture.f64_or("rotation", 0.0)?,
+            ),
+            "superellipse" => Aperture::superellipse(aperture.f64_or("exponent", 4.0)?),
+            "image" => {
+                Aperture::image(&ctx.resolve(aperture.require_str("file")?)).unwrap_or_else(|err| {
+                    log::error!("{err}, using a round aperture instead");
+                    Aperture::Disk
+                })
+            }
+            other => {
+                return Err(SceneError::UnknownType {
+                    category: "aperture",
+                    name: other.to_string(),
+                })
+            }
+        };
+    }
+
+    if let Some(ParamValue::Table(exposure)) = params.get("exposure") {
+        let default = Exposure::default();
+        camera.exposure = Some(Exposure::new(
+            exposure.f64_or("iso", default.iso)?,
+            exposure.f64_or("shutter_speed", default.shutter_speed)?,
+            exposure.f64_or("aperture_fstop", default.aperture_fstop)?,
+        ));
+    }
+    camera.sensor_height = params.f64_or("sensor_height", camera.sensor_height)?;
+    camera.distortion = LensDistortion::new(params.f64_or("k1", 0.0)?, params.f64_or("k2", 0.0)?);
+    camera.vignetting = params.f64_or("vignetting", 0.0)?;
+    camera.stamp = params.bool_or("stamp", false)?;
+    camera.ray_stats = params.bool_or("ray_stats", false)?;
+    camera.variance_map = params.bool_or("variance_map", false)?;
+    camera.motion_vectors = params.bool_or("motion_vectors", false)?;
+    camera.transparent = params.bool_or("transparent", false)?;
+    camera.color_space = ctx.color_space;
+    camera.seed = match params.get("seed") {
+        Some(_) => Some(params.require_f64("seed")? as u64),
+        None => None,
+    };
+    camera.blue_noise = match params.get("blue_noise") {
+        None | Some(ParamValue::Bool(false)) => None,
+        Some(ParamValue::Bool(true)) => Some(Arc::new(BlueNoiseMask::generate(64))),
+        Some(_) => {
+            let file = ctx.resolve(params.require_str("blue_noise")?);
+            match BlueNoiseMask::from_image(&file) {
+                Ok(mask) => Some(Arc::new(mask)),
+                Err(err) => {
+                    log::error!("{err}, using a generated blue noise mask instead");
+                    Some(Arc::new(BlueNoiseMask::generate(64)))
+                }
+            }
+        }
+    };
+
+    camera.adaptive = match params.get("adaptive") {
+        None | Some(ParamValue::Bool(false)) => None,
+        Some(ParamValue::Bool(true)) => Some(AdaptiveSampling::default()),
+        Some(_) => {
+            let adaptive = params.require_table("adaptive")?;
+            let default = AdaptiveSampling::default();
+            Some(AdaptiveSampling::new(
+                adaptive.f64_or("tile_size", default.tile_size as Float)? as usize,
+                adaptive.f64_or("min_samples", default.min_samples as Float)? as usize,
+                adaptive.f64_or("threshold", default.threshold)?,
+            ))
+        }
+    };
+
+    camera.background = match params.get("background") {
+        None | Some(ParamValue::Bool(false)) => None,
+        Some(ParamValue::Bool(true)) => Some(BackgroundPriority::default()),
+        Some(_) => {
+            let background = params.require_table("background")?;
+            let default = BackgroundPriority::default();
+            Some(
+                BackgroundPriority::new(background.f64_or("cpu_fraction", default.cpu_fraction)?)
+                    .with_niceness(
+                        background.f64_or("niceness", default.niceness as Float)? as i32,
+                    ),
+            )
+        }
+    };
+
+    camera.stereo = match params.get("stereo") {
+        None | Some(ParamValue::Bool(false)) => None,
+        Some(ParamValue::Bool(true)) => Some(Stereo::default()),
+        Some(_) => {
+            let stereo = params.require_table("stereo")?;
+            let default = Stereo::default();
+            Some(Stereo {
+                interpupillary_distance: stereo.f64_or("ipd", default.interpupillary_distance)?,
+                convergence: match stereo.get("convergence") {
+                    Some(_) => Some(stereo.require_f64("convergence")?),
+                    None => None,
+                },
+                layout: match stereo.str_or("layout", "side_by_side")? {
+                    "side_by_side" => StereoLayout::SideBySide,
+                    "top_bottom" => StereoLayout::TopBottom,
+                    other => {
+                        return Err(SceneError::UnknownType {
+                            category: "stereo layout",
+                            name: other.to_string(),
+                        })
+                    }
+                },
+            })
+        }
+    };
+
+    camera.outline = match params.get("outline") {
+        None | Some(ParamValue::Bool(false)) => None,
+        Some(ParamValue::Bool(true)) => Some(Outline::default()),
+        Some(_) => {
+            let outline = params.require_table("outline")?;
+            let default = Outline::default();
+            Some(Outline::new(
+                outline.f64_or("depth_threshold", default.depth_threshold)?,
+                outline.f64_or("normal_threshold", default.normal_threshold)?,
+                outline.vec3_or("color", default.color)?,
+            ))
+        }
+    };
+    camera.post_effects = list(params, "post")?
+        .into_iter()
+        .map(post_effect)
+        .collect::<Result<_, _>>()?;
+    Ok(camera)
+}
+
+/// `{ type = "chromatic_aberration", strength = 0.003 }` or
+/// `{ type = "film_grain", amount = 0.1, size = 1.5, seed = 0 }` or
+/// `{ type = "bloom", threshold = 1, radius = 16, intensity = 0.1, kernel = "star", points = 6 }`
+fn post_effect(params: &ParamMap) -> Result<PostEffect, SceneError> {
+    match params.require_str("type")? {
+        "chromatic_aberration" => Ok(PostEffect::ChromaticAberration {
+            strength: params.f64_or("strength", 0.003)?,
+        }),
+        "film_grain" => Ok(PostEffect::FilmGrain {
+            amount: params.f64_or("amount", 0.1)?,
+            size: params.f64_or("size", 1.5)?,
+            seed: params.f64_or("seed", 0.0)? as u64,
+        }),
+        "bloom" => Ok(PostEffect::Bloom {
+            threshold: params.f64_or("threshold", 1.0)?,
+            radius: params.f64_or("radius", 16.0)?,
+            intensity: params.f64_or("intensity", 0.1)?,
+            kernel: match params.str_or("kernel", "gaussian")? {
+                "gaussian" => BloomKernel::Gaussian,
+                "star" => BloomKernel::Star {
+                    points: params.f64_or("points", 6.0)? as usize,
+                },
+                other => {
+                    return Err(SceneError::UnknownType {
+                        category: "bloom kernel",
+                        name: other.to_string(),
+                    })
+                }
+            },
+        }),
+        other => Err(SceneError::UnknownType {
+            category: "post effect",
+            name: other.to_string(),
+        }),
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use crate::vec3::{Float, Vec2, Vec3};
+
+use super::SceneError;
+
+/// A single value from a scene file, independent of the file format it was parsed from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Bool(bool),
+    Number(Float),
+    String(String),
+    List(Vec<ParamValue>),
+    Table(ParamMap),
+}
+
+impl ParamValue {
+    pub fn as_f64(&self) -> Option<Float> {
+        match self {
+            ParamValue::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec3(&self) -> Option<Vec3> {
+        match self {
+            ParamValue::Number(x) => Some(Vec3::splat(*x)),
+            ParamValue::List(list) if list.len() == 3 => Some(Vec3::new(
+                list[0].as_f64()?,
+                list[1].as_f64()?,
+                list[2].as_f64()?,
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec2(&self) -> Option<Vec2> {
+        match self {
+            ParamValue::Number(x) => Some(Vec2::splat(*x)),
+            ParamValue::List(list) if list.len() == 2 => {
+                Some(Vec2::new(list[0].as_f64()?, list[1].as_f64()?))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[ParamValue]> {
+        match self {
+            ParamValue::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ParamValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&ParamMap> {
+        match self {
+            ParamValue::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ParamValue::Bool(_) => "bool",
+            ParamValue::Number(_) => "number",
+            ParamValue::String(_) => "string",
+            ParamValue::List(_) => "list",
+            ParamValue::Table(_) => "table",
+        }
+    }
+}
+
+impl From<toml::Value> for ParamValue {
+    fn from(value: toml::Value) -> Self {
+        match value {
+            toml::Value::String(s) => ParamValue::String(s),
+            toml::Value::Integer(i) => ParamValue::Number(i as Float),
+            toml::Value::Float(f) => ParamValue::Number(f as Float),
+            toml::Value::Boolean(b) => ParamValue::Bool(b),
+            toml::Value::Datetime(d) => ParamValue::String(d.to_string()),
+            toml::Value::Array(array) => {
+                ParamValue::List(array.into_iter().map(ParamValue::from).collect())
+            }
+            toml::Value::Table(table) => ParamValue::Table(ParamMap::from(table)),
+        }
+    }
+}
+
+/// Named parameters of a scene file entry (a material, object, camera...).
+/// Getters return an error naming the offending key so scene files are easy to fix.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParamMap {
+    values: HashMap<String, ParamValue>,
+}
+
+impl ParamMap {
+    pub fn new() -> ParamMap {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, value: ParamValue) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ParamValue> {
+        self.values.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    pub fn f64_or(&self, name: &str, default: Float) -> Result<Float, SceneError> {
+        self.get(name)
+            .map_or(Ok(default), |v| Self::typed(name, v, "number", v.as_f64()))
+    }
+
+    pub fn require_f64(&self, name: &str) -> Result<Float, SceneError> {
+        let v = self.require(name)?;
+        Self::typed(name, v, "number", v.as_f64())
+    }
+
+    pub fn vec3_or(&self, name: &str, default: Vec3) -> Result<Vec3, SceneError> {
+        self.get(name).map_or(Ok(default), |v| {
+            Self::typed(name, v, "[x, y, z]", v.as_vec3())
+        })
+    }
+
+    pub fn require_vec3(&self, name: &str) -> Result<Vec3, SceneError> {
+        let v = self.require(name)?;
+        Self::typed(name, v, "[x, y, z]", v.as_vec3())
+    }
+
+    pub fn vec2_or(&self, name: &str, default: Vec2) -> Result<Vec2, SceneError> {
+        self.get(name)
+            .map_or(Ok(default), |v| Self::typed(name, v, "[x, y]", v.as_vec2()))
+    }
+
+    pub fn require_list(&self, name: &str) -> Result<&[ParamValue], SceneError> {
+        let v = self.require(name)?;
+        Self::typed(name, v, "list", v.as_list())
+    }
+
+    pub fn str_or<'a>(&'a self, name: &str, default: &'a str) -> Result<&'a str, SceneError> {
+        self.get(name)
+            .map_or(Ok(default), |v| Self::typed(name, v, "string", v.as_str()))
+    }
+
+    pub fn require_str(&self, name: &str) -> Result<&str, SceneError> {
+        let v = self.require(name)?;
+        Self::typed(name, v, "string", v.as_str())
+    }
+
+    pub fn require_table(&self, name: &str) -> Result<&ParamMap, SceneError> {
+        let v = self.require(name)?;
+        Self::typed(name, v, "table", v.as_table())
+    }
+
+    pub fn bool_or(&self, name: &str, default: bool) -> Result<bool, SceneError> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(ParamValue::Bool(b)) => Ok(*b),
+            Some(v) => Err(SceneError::InvalidParam {
+                name: name.to_string(),
+                expected: "bool",
+                found: v.type_name(),
+            }),
+        }
+    }
+
+    fn require(&self, name: &str) -> Result<&ParamValue, SceneError> {
+        self.get(name)
+            .ok_or_else(|| SceneError::MissingParam(name.to_string()))
+    }
+
+    fn typed<T>(
+        name: &str,
+        value: &ParamValue,
+        expected: &'static str,
+        converted: Option<T>,
+    ) -> Result<T, SceneError> {
+        converted.ok_or_else(|| SceneError::InvalidParam {
+            name: name.to_string(),
+            expected,
+            found: value.type_name(),
+        })
+    }
+}
+
+impl From<toml::Table> for ParamMap {
+    fn from(table: toml::Table) -> Self {
+        ParamMap {
+            values: table
+                .into_iter()
+                .map(|(k, v)| (k, ParamValue::from(v)))
+                .collect(),
+        }
+    }
+}
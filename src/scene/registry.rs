@@ -0,0 +1,525 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    aov::IdManifest,
+    bsdf::{
+        ao_override::AoOverride,
+        blend::BlendMaterial,
+        cloth::ClothBSDF,
+        coat::CoatMaterial,
+        cutout::Cutout,
+        diffuse::DiffuseBRDF,
+        glass::GlassBSDF,
+        hair::HairBSDF,
+        metal::MetalBRDF,
+        mix::MixBxDf,
+        principled::PrincipledBSDF,
+        sided::{Sided, Sidedness},
+        texture_set::TextureSet,
+        MatPtr,
+    },
+    color::ColorSpace,
+    hittable::BvhLayout,
+    integrator::AoSettings,
+    lookdev,
+    material::DiffuseLight,
+    texture::{
+        decoded, CheckerTexture, ImageTexture, LuminanceTexture, SolidTexture, Texture,
+        VertexColorTexture,
+    },
+    texture_cache::TextureCache,
+    texture_nodes::{MixTexture, MultiplyTexture, NoiseTexture, RampTexture, UvWarpTexture},
+    vec3::{Float, Vec2, Vec3},
+};
+
+use super::{ParamMap, ParamValue, SceneError};
+
+/// Builds a material from the parameters of a scene file entry
+pub type MaterialFactory =
+    Arc<dyn Fn(&ParamMap, &LoadContext) -> Result<MatPtr, SceneError> + Send + Sync>;
+
+/// Picks the material of a mesh that the scene file gives none, from the name of its OBJ model.
+/// `None` leaves it to the registry's default material
+pub type MeshMaterialHook = Arc<dyn Fn(&str) -> Option<MatPtr> + Send + Sync>;
+
+/// Maps the `type = "..."` of a material entry to the function that constructs it.
+/// Downstream crates can `register` their own BxDFMaterials to make them usable from scene files.
+#[derive(Clone, Default)]
+pub struct MaterialRegistry {
+    factories: HashMap<String, MaterialFactory>,
+    default_material: Option<MatPtr>,
+    mesh_material_hook: Option<MeshMaterialHook>,
+}
+
+impl MaterialRegistry {
+    /// an empty registry, see `with_builtins` for one that knows the crate's own materials
+    pub fn new() -> MaterialRegistry {
+        Self::default()
+    }
+
+    pub fn with_builtins() -> MaterialRegistry {
+        let mut registry = Self::new();
+        registry.register("diffuse", |params, ctx| {
+            let color = ctx.vec3_texture(params, "color", Vec3::splat(0.5))?;
+            let normal_map = match params.get("normal_map") {
+                Some(_) => Some(ctx.image(params.require_str("normal_map")?)),
+                None => None,
+            };
+            let diffuse = DiffuseBRDF::from_textures(color, normal_map);
+            match params.get("roughness") {
+                Some(_) => Ok(Arc::new(diffuse.with_roughness(ctx.f64_texture(
+                    params,
+                    "roughness",
+                    0.0,
+                )?))),
+                None => Ok(Arc::new(diffuse)),
+            }
+        });
+        registry.register("metal", |params, ctx| {
+            let color = ctx.vec3_texture(params, "color", Vec3::ONE)?;
+            let roughness = ctx.f64_texture(params, "roughness", 0.0)?;
+            Ok(Arc::new(MetalBRDF::new(color, roughness)))
+        });
+        registry.register("glass", |params, ctx| {
+            let color = ctx.vec3_texture(params, "color", Vec3::ONE)?;
+            let roughness = ctx.f64_texture(params, "roughness", 0.001)?;
+            let ior = params.f64_or("ior", 1.5)?;
+            let mut glass = GlassBSDF::new(color, roughness, 0.0, ior);
+            if params.contains("absorption_color") {
+                glass = glass.with_absorption(
+                    params.require_vec3("absorption_color")?,
+                    params.f64_or("absorption_distance", 1.0)?,
+                );
+            }
+            let glass =
+                glass.with_shadow_transmission(params.bool_or("shadow_transmission", false)?);
+            Ok(Arc::new(glass))
+        });
+        registry.register("principled", |params, ctx| {
+            Ok(Arc::new(PrincipledBSDF::new(
+                ctx.vec3_texture(params, "color", Vec3::splat(0.8))?,
+                params.f64_or("metallic", 0.0)?,
+                params.f64_or("roughness", 0.5)?,
+                params.f64_or("subsurface", 0.0)?,
+                params.f64_or("specular", 0.5)?,
+                params.f64_or("specular_tint", 0.0)?,
+                params.f64_or("ior", 1.5)?,
+                params.f64_or("spec_trans", 0.0)?,
+                params.f64_or("sheen", 0.0)?,
+                params.f64_or("sheen_tint", 0.0)?,
+                params.f64_or("clearcoat", 0.0)?,
+                params.f64_or("clearcoat_gloss", 0.0)?,
+            )))
+        });
+        registry.register("texture_set", |params, ctx| {
+            let dir = ctx.resolve(params.require_str("dir")?);
+            let mut set = TextureSet::find(Path::new(&dir))
+                .map_err(|err| SceneError::Parse(format!("{dir}: {err}")))?;
+            set.color_space = ctx.color_space;
+            Ok(Arc::new(set.material()))
+        });
+        registry.register("hair", |params, ctx| {
+            let color = ctx.vec3_texture(params, "color", Vec3::new(0.4, 0.25, 0.15))?;
+            let hair = HairBSDF::new(
+                color,
+                params.f64_or("roughness", 0.3)?,
+                params.f64_or("specular", 0.2)?,
+            );
+            Ok(Arc::new(hair.with_tilt(params.f64_or("tilt", 3.0)?)))
+        });
+        registry.register("cloth", |params, ctx| {
+            Ok(Arc::new(ClothBSDF::new(
+                ctx.vec3_texture(params, "color", Vec3::splat(0.5))?,
+                ctx.vec3_texture(params, "sheen_color", Vec3::ONE)?,
+                ctx.f64_texture(params, "roughness", 0.5)?,
+            )))
+        });
+        registry.register("light", |params, ctx| {
+            let emission = ctx.vec3_texture(params, "color", Vec3::ONE)?;
+            Ok(Arc::new(DiffuseLight::new(emission)))
+        });
+        registry.register("mix", |params, ctx| {
+            let t = params.f64_or("t", 0.5)?;
+            let a = ctx.material_at(params, "a")?;
+            let b = ctx.material_at(params, "b")?;
+            Ok(Arc::new(MixBxDf::new(t, a, b)))
+        });
+        registry.register("blend", |params, ctx| {
+            let a = ctx.material_at(params, "a")?;
+            let b = ctx.material_at(params, "b")?;
+            let mask = ctx.f64_texture(params, "mask", 0.5)?;
+            Ok(Arc::new(BlendMaterial::new(a, b, mask)))
+        });
+        registry.register("coat", |params, ctx| {
+            let base = ctx.material_at(params, "base")?;
+            let coat = CoatMaterial::new(
+                base,
+                ctx.f64_texture(params, "roughness", 0.05)?,
+                params.f64_or("ior", 1.5)?,
+            );
+            Ok(Arc::new(
+                coat.with_weight(ctx.f64_texture(params, "weight", 1.0)?)
+                    .with_color(ctx.vec3_texture(params, "color", Vec3::ONE)?),
+            ))
+        });
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&ParamMap, &LoadContext) -> Result<MatPtr, SceneError> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Arc::new(factory));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    /// the material of meshes without one, `lookdev::default_material` unless it's set
+    pub fn set_default_material(&mut self, material: MatPtr) {
+        self.default_material = Some(material);
+    }
+
+    pub fn set_mesh_material_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&str) -> Option<MatPtr> + Send + Sync + 'static,
+    {
+        self.mesh_material_hook = Some(Arc::new(hook));
+    }
+
+    pub fn default_material(&self) -> MatPtr {
+        self.default_material
+            .clone()
+            .unwrap_or_else(lookdev::default_material)
+    }
+
+    /// the material for the OBJ model `name` of a mesh without a material, from the hook if it
+    /// has one for it, or else the default
+    pub fn mesh_material(&self, name: &str) -> MatPtr {
+        self.mesh_material_hook
+            .as_ref()
+            .and_then(|hook| hook(name))
+            .unwrap_or_else(|| self.default_material())
+    }
+
+    /// construct the material described by `params`, dispatching on its `type`
+    pub fn create(&self, params: &ParamMap, ctx: &LoadContext) -> Result<MatPtr, SceneError> {
+        let kind = params.require_str("type")?;
+        let factory = self
+            .factories
+            .get(kind)
+            .ok_or_else(|| SceneError::UnknownType {
+                category: "material",
+                name: kind.to_string(),
+            })?;
+        let mut material = factory(params, ctx)?;
+        if params.contains("opacity") {
+            let opacity = ctx.f64_texture(params, "opacity", 1.0)?;
+            material = Arc::new(Cutout::new(material, opacity));
+        }
+        match params.str_or("sidedness", "shaded")? {
+            "shaded" => {}
+            "black" => material = Arc::new(Sided::new(material, Sidedness::Black)),
+            "invisible" => material = Arc::new(Sided::new(material, Sidedness::Invisible)),
+            other => {
+                return Err(SceneError::UnknownType {
+                    category: "sidedness",
+                    name: other.to_string(),
+                })
+            }
+        }
+        match params.get("ao") {
+            Some(_) => {
+                let ao = ao_settings(params.require_table("ao")?, AoSettings::default())?;
+                Ok(Arc::new(AoOverride::new(material, ao)))
+            }
+            None => Ok(material),
+        }
+    }
+}
+
+/// `{ distance = 1.0, falloff = 2.0, ground_only = true }`, missing keys are taken from `default`
+pub fn ao_settings(params: &ParamMap, default: AoSettings) -> Result<AoSettings, SceneError> {
+    Ok(AoSettings::new(
+        params.f64_or("distance", default.distance)?,
+        params.f64_or("falloff", default.falloff)?,
+        params.bool_or("ground_only", default.ground_only)?,
+    ))
+}
+
+/// State shared by the factories while a single scene file is being loaded
+pub struct LoadContext<'a> {
+    pub base_dir: &'a Path,
+    pub materials: &'a MaterialRegistry,
+    /// layout of the BVHs built over meshes
+    pub bvh: BvhLayout,
+    /// print the size of every BVH that's built
+    pub bvh_stats: bool,
+    /// the names of the objects and materials tagged so far, when the ID passes are rendered
+    pub ids: Option<RefCell<IdManifest>>,
+    /// the camera's color space, which color images are decoded into. None reads them as is
+    pub color_space: Option<ColorSpace>,
+    /// every file `resolve` was asked for
+    pub files: RefCell<Vec<PathBuf>>,
+    /// the `[materials]` table of the scene file, materials by name
+    pub library: Option<&'a ParamMap>,
+    /// the materials of the library built so far, None while one is still being built
+    pub built: RefCell<HashMap<String, Option<MatPtr>>>,
+}
+
+impl LoadContext<'_> {
+    /// paths in scene files are relative to the scene file itself
+    pub fn resolve(&self, path: &str) -> String {
+        let path = PathBuf::from(path);
+        let path = if path.is_absolute() {
+            path
+        } else {
+            self.base_dir.join(path)
+        };
+        self.files.borrow_mut().push(path.clone());
+        path.to_string_lossy().into_owned()
+    }
+
+    /// the image at `path` (relative to the scene file), shared with every other user of the
+    /// same file through the texture cache
+    pub fn image(&self, path: &str) -> Arc<ImageTexture> {
+        TextureCache::global().get(&self.resolve(path))
+    }
+
+    /// the image at `path` as colors, decoded into the working space if there is one
+    pub fn color_image(&self, path: &str) -> Arc<dyn Texture<Vec3>> {
+        decoded(self.image(path), self.color_space)
+    }
+
+    /// the ID of the object called `name`, None without ID passes
+    pub fn object_id(&self, name: &str) -> Option<u32> {
+        Some(self.ids.as_ref()?.borrow_mut().add_object(name))
+    }
+
+    pub fn material_id(&self, name: &str) -> Option<u32> {
+        Some(self.ids.as_ref()?.borrow_mut().add_material(name))
+    }
+
+    pub fn material(&self, params: &ParamMap) -> Result<MatPtr, SceneError> {
+        self.materials.create(params, self)
+    }
+
+    /// the material at `key` of `params`, either a table or the name of one in the library
+    pub fn material_at(&self, params: &ParamMap, key: &str) -> Result<MatPtr, SceneError> {
+        match params.get(key) {
+            Some(ParamValue::String(name)) => self.named_material(name),
+            _ => self.material(params.require_table(key)?),
+        }
+    }
+
+    /// the library material called `name`, built the first time it's asked for and shared by
+    /// everything that uses it after that
+    pub fn named_material(&self, name: &str) -> Result<MatPtr, SceneError> {
+        let path = format!("materials.{name}");
+        match self.built.borrow().get(name) {
+            Some(Some(material)) => return Ok(material.clone()),
+            Some(None) => return Err(SceneError::Parse(format!("{path} refers to itself"))),
+            None => {}
+        }
+        let params = match self.library.and_then(|library| library.get(name)) {
+            Some(ParamValue::Table(params)) => params,
+            Some(other) => {
+                return Err(SceneError::InvalidParam {
+                    name: path,
+                    expected: "table",
+                    found: other.type_name(),
+                })
+            }
+            None => return Err(SceneError::MissingParam(path)),
+        };
+        self.built.borrow_mut().insert(name.to_string(), None);
+        let material = self.material(params)?;
+        self.built
+            .borrow_mut()
+            .insert(name.to_string(), Some(material.clone()));
+        Ok(material)
+    }
+
+    /// the name the ID passes know the library material `name` by, its own `name` if it has one
+    pub fn library_name<'b>(&'b self, name: &'b str) -> &'b str {
+        self.library
+            .and_then(|library| library.get(name))
+            .and_then(ParamValue::as_table)
+            .and_then(|params| params.get("name"))
+            .and_then(ParamValue::as_str)
+            .unwrap_or(name)
+    }
+
+    /// a color given either as `[r, g, b]` or as a texture table, e.g.
+    /// `{ type = "image", file = "..." }` or `{ type = "checker", scale = 0.3, even = [...], odd = [...] }`.
+    /// the node types (mix, multiply, ramp, noise, uv_warp) take other textures as parameters.
+    /// `{ type = "vertex_color", fallback = [...] }` is the colors baked into a mesh
+    pub fn vec3_texture(
+        &self,
+        params: &ParamMap,
+        name: &str,
+        default: Vec3,
+    ) -> Result<Arc<dyn Texture<Vec3>>, SceneError> {
+        match params.get(name) {
+            None => Ok(Arc::new(SolidTexture::new(default))),
+            Some(ParamValue::Table(table)) => match table.require_str("type")? {
+                "image" => Ok(self.color_image(table.require_str("file")?)),
+                "checker" => Ok(Arc::new(CheckerTexture::new(
+                    table.f64_or("scale", 1.0)?,
+                    self.vec3_texture(table, "even", Vec3::ZERO)?,
+                    self.vec3_texture(table, "odd", Vec3::ONE)?,
+                ))),
+                "mix" => Ok(Arc::new(MixTexture::new(
+                    self.vec3_texture(table, "a", Vec3::ZERO)?,
+                    self.vec3_texture(table, "b", Vec3::ONE)?,
+                    self.f64_texture(table, "factor", 0.5)?,
+                ))),
+                "multiply" => Ok(Arc::new(MultiplyTexture::new(
+                    self.vec3_texture(table, "a", Vec3::ONE)?,
+                    self.vec3_texture(table, "b", Vec3::ONE)?,
+                ))),
+                "ramp" => Ok(Arc::new(RampTexture::new(
+                    self.f64_texture(table, "input", 0.0)?,
+                    ramp_stops(table, "colors", "list of [r, g, b]", ParamValue::as_vec3)?,
+                ))),
+                "noise" => Ok(Arc::new(noise_texture(table)?)),
+                "uv_warp" => Ok(Arc::new(
+                    self.uv_warp(table, self.vec3_texture(table, "texture", Vec3::ONE)?)?,
+                )),
+                "vertex_color" => Ok(Arc::new(VertexColorTexture::new(
+                    self.vec3_texture(table, "fallback", Vec3::splat(0.5))?,
+                    self.color_space,
+                ))),
+                other => Err(SceneError::UnknownType {
+                    category: "texture",
+                    name: other.to_string(),
+                }),
+            },
+            Some(_) => Ok(Arc::new(SolidTexture::new(params.require_vec3(name)?))),
+        }
+    }
+
+    pub fn f64_texture(
+        &self,
+        params: &ParamMap,
+        name: &str,
+        default: Float,
+    ) -> Result<Arc<dyn Texture<Float>>, SceneError> {
+        match params.get(name) {
+            Some(ParamValue::Table(table)) => match table.require_str("type")? {
+                "image" => Ok(Arc::new(LuminanceTexture::new(
+                    self.image(table.require_str("file")?),
+                ))),
+                "checker" => Ok(Arc::new(CheckerTexture::new(
+                    table.f64_or("scale", 1.0)?,
+                    self.f64_texture(table, "even", 0.0)?,
+                    self.f64_texture(table, "odd", 1.0)?,
+                ))),
+                "mix" => Ok(Arc::new(MixTexture::new(
+                    self.f64_texture(table, "a", 0.0)?,
+                    self.f64_texture(table, "b", 1.0)?,
+                    self.f64_texture(table, "factor", 0.5)?,
+                ))),
+                "multiply" => Ok(Arc::new(MultiplyTexture::new(
+                    self.f64_texture(table, "a", 1.0)?,
+                    self.f64_texture(table, "b", 1.0)?,
+                ))),
+                "ramp" => Ok(Arc::new(RampTexture::new(
+                    self.f64_texture(table, "input", 0.0)?,
+                    ramp_stops(table, "values", "list of numbers", ParamValue::as_f64)?,
+                ))),
+                "noise" => Ok(Arc::new(noise_texture(table)?)),
+                "uv_warp" => Ok(Arc::new(
+                    self.uv_warp(table, self.f64_texture(table, "texture", 1.0)?)?,
+                )),
+                other => Err(SceneError::UnknownType {
+                    category: "texture",
+                    name: other.to_string(),
+                }),
+            },
+            _ => Ok(Arc::new(SolidTexture::new(params.f64_or(name, default)?))),
+        }
+    }
+
+    /// `texture` with its coordinates tiled by `scale`, shifted by `offset` and pushed around by
+    /// an optional `warp` texture times `strength`
+    fn uv_warp<T>(
+        &self,
+        table: &ParamMap,
+        texture: Arc<dyn Texture<T>>,
+    ) -> Result<UvWarpTexture<T>, SceneError> {
+        let mut node = UvWarpTexture::new(
+            texture,
+            table.vec2_or("scale", Vec2::ONE)?,
+            table.vec2_or("offset", Vec2::ZERO)?,
+        );
+        if table.contains("warp") {
+            node = node.with_warp(
+                self.f64_texture(table, "warp", 0.5)?,
+                table.f64_or("strength", 0.1)?,
+            );
+        }
+        Ok(node)
+    }
+}
+
+/// `{ type = "noise", scale = 4, octaves = 4, seed = 0, space = "point" }`, where space is
+/// "point" or "uv"
+fn noise_texture(table: &ParamMap) -> Result<NoiseTexture, SceneError> {
+    let uv = match table.str_or("space", "point")? {
+        "point" => false,
+        "uv" => true,
+        other => {
+            return Err(SceneError::UnknownType {
+                category: "noise space",
+                name: other.to_string(),
+            })
+        }
+    };
+    Ok(NoiseTexture::new(
+        table.f64_or("scale", 1.0)?,
+        table.f64_or("octaves", 4.0)? as usize,
+        table.f64_or("seed", 0.0)? as u64,
+    )
+    .with_uv(uv))
+}
+
+/// the stops of a ramp, from a `positions` list and a list of the same length under `key`
+fn ramp_stops<T>(
+    table: &ParamMap,
+    key: &str,
+    expected: &'static str,
+    parse: fn(&ParamValue) -> Option<T>,
+) -> Result<Vec<(Float, T)>, SceneError> {
+    let positions = table.require_list("positions")?;
+    let values = table.require_list(key)?;
+    if positions.is_empty() || positions.len() != values.len() {
+        return Err(SceneError::InvalidParam {
+            name: key.to_string(),
+            expected: "non-empty list as long as `positions`",
+            found: "list",
+        });
+    }
+    positions
+        .iter()
+        .zip(values)
+        .map(|(position, value)| {
+            let invalid = |name: &str, expected, found: &ParamValue| SceneError::InvalidParam {
+                name: name.to_string(),
+                expected,
+                found: found.type_name(),
+            };
+            let position = position
+                .as_f64()
+                .ok_or_else(|| invalid("positions", "list of numbers", position))?;
+            let value = parse(value).ok_or_else(|| invalid(key, expected, value))?;
+            Ok((position, value))
+        })
+        .collect()
+}
@@ -0,0 +1,42 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Polls files for changes by their modification times, to render a scene again while it's
+/// being edited. see `Scene::files` for what a scene depends on
+#[derive(Debug, Clone, Default)]
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new(files: impl IntoIterator<Item = PathBuf>) -> FileWatcher {
+        let mut watcher = FileWatcher::default();
+        for file in files {
+            if watcher.files.iter().all(|(path, _)| *path != file) {
+                let modified = modified(&file);
+                watcher.files.push((file, modified));
+            }
+        }
+        watcher
+    }
+
+    /// the files that changed since `new` or the last call, deleted and recreated ones too
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, last) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last {
+                *last = modified;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
@@ -0,0 +1,191 @@
+//! Depth maps rendered with parallel rays from a light's point of view, so scenes can be baked
+//! into shadow maps and heightmaps for other engines.
+
+use std::{fs, io, path::Path};
+
+use image::{ImageBuffer, ImageError, Luma, Rgb, Rgb32FImage};
+use rayon::prelude::*;
+
+use crate::{
+    hittable::{Hittable, OrientedBox, World, AABB},
+    interval::Interval,
+    ray::{Ray, RayType},
+    vec3::{Float, Vec3},
+};
+
+/// An orthographic view along `direction`: a `width` by `height` rectangle centered on `origin`
+/// that rays leave in parallel, reaching `depth` past it
+#[derive(Debug, Clone, Copy)]
+pub struct LightView {
+    /// center of the near plane
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// along the rows of the image
+    pub right: Vec3,
+    /// up the columns of the image
+    pub up: Vec3,
+    pub width: Float,
+    pub height: Float,
+    pub depth: Float,
+}
+
+impl LightView {
+    /// looking along `direction` at everything in `bbox`. the image is upright with +y up, or
+    /// looking straight up or down, with -z up so it reads like a map with x to the right
+    pub fn fit(bbox: &AABB, direction: Vec3) -> LightView {
+        let direction = direction.normalize();
+        let up = if direction.y.abs() > 0.999 {
+            -Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let right = direction.cross(up).normalize();
+        let up = right.cross(direction);
+
+        // half the size of the box along each axis of the view
+        let center = bbox.centroid();
+        let half = OrientedBox::from(*bbox)
+            .corners()
+            .iter()
+            .map(|&corner| {
+                let d = corner - center;
+                Vec3::new(d.dot(right), d.dot(up), d.dot(direction)).abs()
+            })
+            .fold(Vec3::ZERO, Vec3::max);
+        LightView {
+            origin: center - direction * half.z,
+            direction,
+            right,
+            up,
+            width: 2.0 * half.x,
+            height: 2.0 * half.y,
+            depth: 2.0 * half.z,
+        }
+    }
+
+    /// looking from the center of light `i` towards the center of the objects. when the light is
+    /// among the objects, like a ceiling light, the view starts at the light
+    pub fn from_light(world: &World, i: usize) -> Option<LightView> {
+        if i >= world.lights.len() {
+            return None;
+        }
+        let light = world.lights.get(i).bounding_box().centroid();
+        let bbox = world.objects.bounding_box();
+        let direction = (bbox.centroid() - light).try_normalize()?;
+        let mut view = LightView::fit(&bbox, direction);
+        let skip = (light - view.origin).dot(direction).clamp(0.0, view.depth);
+        view.origin += direction * skip;
+        view.depth -= skip;
+        Some(view)
+    }
+
+    /// ray through the point `(s, t)` of the near plane, both from 0 to 1 with t going down
+    pub fn ray(&self, s: Float, t: Float) -> Ray {
+        let origin =
+            self.origin + (s - 0.5) * self.width * self.right + (0.5 - t) * self.height * self.up;
+        Ray::new(origin, self.direction, 0.0).with_type(RayType::Shadow)
+    }
+}
+
+/// Distance from the near plane of a `LightView` to the first object, per pixel, and infinite
+/// where the rays hit nothing. lights don't cast shadows so they aren't in it
+pub struct DepthMap {
+    pub width: usize,
+    pub height: usize,
+    pub depth: Vec<Float>,
+    pub view: LightView,
+}
+
+impl DepthMap {
+    /// one ray through the center of each pixel, with `size` pixels along the longer side
+    pub fn render(world: &World, view: &LightView, size: usize) -> DepthMap {
+        let aspect = view.width / view.height.max(1e-8);
+        let (width, height) = if aspect >= 1.0 {
+            (size, ((size as Float / aspect).round() as usize).max(1))
+        } else {
+            (((size as Float * aspect).round() as usize).max(1), size)
+        };
+        let depth = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let s = ((i % width) as Float + 0.5) / width as Float;
+                let t = ((i / width) as Float + 0.5) / height as Float;
+                world
+                    .intersect_objects(&view.ray(s, t), Interval::new(0.0, Float::INFINITY))
+                    .map_or(Float::INFINITY, |hit| hit.dist)
+            })
+            .collect();
+        DepthMap {
+            width,
+            height,
+            depth,
+            view: *view,
+        }
+    }
+
+    /// depths from 0 at the near plane to 1 at the far side of the scene, where misses are too.
+    /// with `heightmap` it's flipped, so surfaces closer to the light are brighter
+    pub fn normalized(&self, heightmap: bool) -> Vec<Float> {
+        let far = self.view.depth.max(1e-8);
+        self.depth
+            .iter()
+            .map(|&d| {
+                let d = (d / far).clamp(0.0, 1.0);
+                if heightmap {
+                    1.0 - d
+                } else {
+                    d
+                }
+            })
+            .collect()
+    }
+
+    /// `normalized` as a 16 bit greyscale image
+    pub fn to_image(&self, heightmap: bool) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        let values = self.normalized(heightmap);
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let v = values[y as usize * self.width + x as usize];
+            Luma([(v * 65535.0).round() as u16])
+        })
+    }
+
+    /// distances in scene units, with misses at the far side of the scene
+    // already f32 with the `f32` feature
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_float_image(&self) -> Rgb32FImage {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let d = self.depth[y as usize * self.width + x as usize].min(self.view.depth);
+            Rgb([d as f32; 3])
+        })
+    }
+
+    /// `.exr` files get the distances, anything else the 16 bit `to_image`
+    pub fn save(&self, path: &str, heightmap: bool) -> Result<(), ImageError> {
+        let is_exr = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+        if is_exr {
+            self.to_float_image().save(path)
+        } else {
+            self.to_image(heightmap).save(path)
+        }
+    }
+
+    /// the view as a small toml file, enough to rebuild the light's projection in an engine
+    pub fn save_view(&self, path: &str) -> io::Result<()> {
+        let vector = |v: Vec3| toml::Value::from(v.to_array().to_vec());
+        let mut table = toml::Table::new();
+        table.insert("origin".into(), vector(self.view.origin));
+        table.insert("direction".into(), vector(self.view.direction));
+        table.insert("right".into(), vector(self.view.right));
+        table.insert("up".into(), vector(self.view.up));
+        table.insert("width".into(), self.view.width.into());
+        table.insert("height".into(), self.view.height.into());
+        table.insert("depth".into(), self.view.depth.into());
+        table.insert(
+            "resolution".into(),
+            toml::Value::from(vec![self.width as i64, self.height as i64]),
+        );
+        fs::write(path, table.to_string())
+    }
+}
@@ -0,0 +1,253 @@
+//! Daylight from a place and a time: where the sun is, the light it sends through the air and
+//! the sky around it, for outdoor and architectural scenes that should look like a real day.
+
+use crate::{
+    color::ColorSpace,
+    environment::EnvironmentType,
+    hittable::{DirectionalLight, World},
+    vec3::{consts::PI, Float, Vec3},
+};
+
+/// The daylight sky of Preetham, Shirley and Smits ("A practical analytic model for daylight"):
+/// the luminance and color of the clear sky in every direction for a sun direction and a
+/// turbidity, the haze in the air. it's a closed form fit like the Hosek-Wilkie sky, which is
+/// more accurate near the horizon but needs large tables of coefficients. the radiance is in
+/// thousands of cd/m² times `intensity`, with Rec.709 primaries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sky {
+    /// normalized, towards the sun, with y up
+    sun: Vec3,
+    /// the Perez coefficients of the luminance and the x and y chromaticities
+    perez: [[Float; 5]; 3],
+    /// the luminance and chromaticities at the zenith over the Perez function there
+    zenith: Vec3,
+    intensity: Float,
+    color_space: Option<ColorSpace>,
+}
+
+impl Sky {
+    /// the sky with the sun towards `sun` and a `turbidity` from 2 (very clear) to 10 (hazy).
+    /// below the horizon the sky is that of a sun just above it, fading out by the time the
+    /// sun is 6 degrees under it
+    pub fn new(sun: Vec3, turbidity: Float, intensity: Float) -> Sky {
+        let sun = sun.normalize();
+        let t = turbidity.clamp(1.7, 10.0);
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // the fits break down with the sun at or under the horizon
+        let theta_s = sun.y.clamp(-1.0, 1.0).acos().min(0.499 * PI);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let cubic = |c: [Float; 4]| ((c[0] * theta_s + c[1]) * theta_s + c[2]) * theta_s + c[3];
+        let x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+            + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+            + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+            + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+            + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+        let zenith = Vec3::new(luminance.max(0.0), x, y);
+        let zenith = zenith / Vec3::from(perez.map(|c| perez_function(c, 0.0, theta_s)));
+
+        // twilight, from the sun on the horizon to 6 degrees under it
+        let elevation = sun.y.clamp(-1.0, 1.0).asin();
+        let fade = (1.0 + elevation / (6.0 as Float).to_radians()).clamp(0.0, 1.0);
+        Sky {
+            sun,
+            perez,
+            zenith,
+            intensity: intensity * fade,
+            color_space: None,
+        }
+    }
+
+    /// the radiance in the working space of `color_space` instead of with Rec.709 primaries
+    pub fn with_color_space(self, color_space: Option<ColorSpace>) -> Sky {
+        Sky {
+            color_space,
+            ..self
+        }
+    }
+
+    /// the radiance arriving from `direction`. the ground below the horizon gets the color
+    /// of the sky just above it
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        let theta = direction.y.clamp(0.0, 1.0).acos().min(0.499 * PI);
+        let gamma = direction.dot(self.sun).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] =
+            [0, 1, 2].map(|i| self.zenith[i] * perez_function(self.perez[i], theta, gamma));
+        let color = xyy_to_rec709(x, y, luminance.max(0.0)).max(Vec3::ZERO) * self.intensity;
+        match self.color_space {
+            Some(color_space) => color_space.from_rec709(color),
+            None => color,
+        }
+    }
+}
+
+/// the Perez distribution of the sky's brightness, `theta` from the zenith and `gamma` from
+/// the sun
+fn perez_function([a, b, c, d, e]: [Float; 5], theta: Float, gamma: Float) -> Float {
+    (1.0 + a * (b / theta.cos()).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// linear Rec.709 from chromaticities and a luminance
+fn xyy_to_rec709(x: Float, y: Float, luminance: Float) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::ZERO;
+    }
+    let xyz = Vec3::new(x / y, 1.0, (1.0 - x - y) / y) * luminance;
+    Vec3::new(
+        Vec3::new(3.240_454, -1.537_139, -0.498_531).dot(xyz),
+        Vec3::new(-0.969_266, 1.876_011, 0.041_556).dot(xyz),
+        Vec3::new(0.055_643, -0.204_026, 1.057_225).dot(xyz),
+    )
+}
+
+/// A sun and a sky that go together, from where on earth the scene is and when. the sun is a
+/// `DirectionalLight` in the color the air leaves it, and the sky the matching `Sky` as the
+/// environment. north is towards -z and east towards +x by default, with y up.
+///
+/// Both are in thousands of lux and cd/m² times `intensity`, which by default puts a white
+/// surface in the noon sun at about 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunSky {
+    /// degrees, north positive
+    pub latitude: Float,
+    /// degrees, east positive
+    pub longitude: Float,
+    /// year, month (1 to 12) and day of the month
+    pub date: (i32, u32, u32),
+    /// the local clock time in hours, 14.5 is half past two
+    pub time: Float,
+    /// hours the local clock is ahead of UTC, the longitude's time zone without daylight saving
+    /// by default
+    pub utc_offset: Float,
+    /// haze from 2 (very clear) to 10, see `Sky::new`
+    pub turbidity: Float,
+    pub intensity: Float,
+    /// the direction of north in the scene, along the ground
+    pub north: Vec3,
+    pub color_space: Option<ColorSpace>,
+}
+
+impl SunSky {
+    pub fn new(latitude: Float, longitude: Float, date: (i32, u32, u32), time: Float) -> SunSky {
+        SunSky {
+            latitude,
+            longitude,
+            date,
+            time,
+            utc_offset: (longitude / 15.0).round(),
+            turbidity: 3.0,
+            intensity: 0.03,
+            north: Vec3::NEG_Z,
+            color_space: None,
+        }
+    }
+
+    /// normalized, towards the sun. under the ground at night
+    #[allow(clippy::unnecessary_cast)]
+    pub fn sun_direction(&self) -> Vec3 {
+        // the NOAA approximation of the sun's declination and the equation of time
+        let (year, month, day) = self.date;
+        let day_of_year = day_of_year(year, month, day) as Float;
+        let g =
+            2.0 * PI / 365.0 * (day_of_year - 1.0 + (self.time - self.utc_offset - 12.0) / 24.0);
+        let equation_of_time = 229.18
+            * (0.000075 + 0.001868 * g.cos()
+                - 0.032077 * g.sin()
+                - 0.014615 * (2.0 * g).cos()
+                - 0.040849 * (2.0 * g).sin());
+        let declination = 0.006918 - 0.399912 * g.cos() + 0.070257 * g.sin()
+            - 0.006758 * (2.0 * g).cos()
+            + 0.000907 * (2.0 * g).sin()
+            - 0.002697 * (3.0 * g).cos()
+            + 0.00148 * (3.0 * g).sin();
+        // minutes of true solar time, and the hour angle from noon
+        let solar_time =
+            self.time * 60.0 + equation_of_time + 4.0 * self.longitude - 60.0 * self.utc_offset;
+        let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+        let latitude = self.latitude.to_radians();
+
+        let east = -declination.cos() * hour_angle.sin();
+        let north = latitude.cos() * declination.sin()
+            - latitude.sin() * declination.cos() * hour_angle.cos();
+        let up = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+        let north_dir = self
+            .north
+            .with_y(0.0)
+            .try_normalize()
+            .unwrap_or(Vec3::NEG_Z);
+        let east_dir = north_dir.cross(Vec3::Y);
+        (east * east_dir + north * north_dir + up * Vec3::Y).normalize()
+    }
+
+    /// the sun, with the color and strength the air leaves it. none at night
+    pub fn sun(&self) -> Option<DirectionalLight> {
+        let direction = self.sun_direction();
+        if direction.y <= 0.0 {
+            return None;
+        }
+        // Rayleigh and aerosol (Angstrom) optical depth at the wavelengths of red, green and
+        // blue in micrometers, over the air mass of Kasten and Young
+        let zenith = direction.y.clamp(0.0, 1.0).acos().to_degrees();
+        let air_mass = 1.0 / (direction.y + 0.50572 * (96.07995 - zenith).powf(-1.6364));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = Vec3::new(0.68, 0.55, 0.44).map(|wavelength| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosol = beta * wavelength.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        });
+        // the solar constant in thousands of lux
+        let irradiance = 128.0 * self.intensity * transmittance;
+        let irradiance = match self.color_space {
+            Some(color_space) => color_space.from_rec709(irradiance),
+            None => irradiance,
+        };
+        Some(DirectionalLight::new(direction, irradiance))
+    }
+
+    /// the sky for the same sun
+    pub fn sky(&self) -> Sky {
+        Sky::new(self.sun_direction(), self.turbidity, self.intensity)
+            .with_color_space(self.color_space)
+    }
+
+    /// set the environment of `world` to the sky and add the sun
+    pub fn add_to(&self, world: &mut World) {
+        world.environment = EnvironmentType::Sky(self.sky());
+        if let Some(sun) = self.sun() {
+            world.add_delta_light(sun);
+        }
+    }
+}
+
+fn day_of_year(year: i32, month: u32, day: u32) -> u32 {
+    const DAYS_BEFORE: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let month = month.clamp(1, 12);
+    DAYS_BEFORE[month as usize - 1] + day + u32::from(leap && month > 2)
+}
@@ -1,11 +1,43 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
-use image::{ImageBuffer, ImageReader, Pixel, Rgb};
+use image::{imageops, ImageBuffer, Pixel, Rgb};
 
-use crate::vec3::Vec3;
+use crate::{
+    color::ColorSpace,
+    error::{open_image, Error},
+    hittable::{HitInfo, TextureValue},
+    vec3::{Float, Vec3, VectorExt},
+};
 
 pub trait Texture<T: Clone + Send + Sync>: Send + Sync {
-    fn value(&self, u: f64, v: f64, point: &Vec3) -> T;
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> T;
+
+    /// value averaged over the area a pixel covers, textures with detail finer than a pixel
+    /// override this to avoid aliasing
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, _footprint: &Footprint) -> T {
+        self.value(u, v, point)
+    }
+
+    /// value at a hit, filtered over the pixel's footprint when there is one. textures that
+    /// read more of the hit than its texture coordinates, like vertex colors, override this
+    fn value_at_hit(&self, hit: &HitInfo) -> T {
+        match &hit.footprint {
+            Some(footprint) => self.value_filtered(hit.u, hit.v, &hit.point, footprint),
+            None => self.value(hit.u, hit.v, &hit.point),
+        }
+    }
+}
+
+/// How far the hit point and its texture coordinates move from one pixel to the next,
+/// see `HitInfo::compute_differential`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Footprint {
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: Float,
+    pub dudy: Float,
+    pub dvdx: Float,
+    pub dvdy: Float,
 }
 
 pub struct SolidTexture<T> {
@@ -19,19 +51,116 @@ impl<T> SolidTexture<T> {
 }
 
 impl<T: Clone + Send + Sync> Texture<T> for SolidTexture<T> {
-    fn value(&self, _u: f64, _v: f64, _point: &Vec3) -> T {
+    fn value(&self, _u: Float, _v: Float, _point: &Vec3) -> T {
         self.value.clone()
     }
 }
 
+/// The brightness of a color texture, e.g. an image used as a roughness or height map
+pub struct LuminanceTexture {
+    texture: Arc<dyn Texture<Vec3>>,
+}
+
+impl LuminanceTexture {
+    pub fn new(texture: Arc<dyn Texture<Vec3>>) -> Self {
+        LuminanceTexture { texture }
+    }
+}
+
+impl Texture<Float> for LuminanceTexture {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> Float {
+        self.texture.value(u, v, point).luminance()
+    }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> Float {
+        self.texture
+            .value_filtered(u, v, point, footprint)
+            .luminance()
+    }
+}
+
+/// A color texture whose values are sRGB encoded, as 8 bit images are, decoded into the
+/// working space of `color_space`. data like normal or roughness maps stays as it is
+pub struct DecodedTexture {
+    texture: Arc<dyn Texture<Vec3>>,
+    color_space: ColorSpace,
+}
+
+impl DecodedTexture {
+    pub fn new(texture: Arc<dyn Texture<Vec3>>, color_space: ColorSpace) -> Self {
+        DecodedTexture {
+            texture,
+            color_space,
+        }
+    }
+}
+
+/// `texture`, decoded from sRGB into the working space of `color_space` if there is one
+pub fn decoded(
+    texture: Arc<dyn Texture<Vec3>>,
+    color_space: Option<ColorSpace>,
+) -> Arc<dyn Texture<Vec3>> {
+    match color_space {
+        Some(color_space) => Arc::new(DecodedTexture::new(texture, color_space)),
+        None => texture,
+    }
+}
+
+impl Texture<Vec3> for DecodedTexture {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> Vec3 {
+        let encoded = self.texture.value(u, v, point);
+        self.color_space.decode_texel(encoded)
+    }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> Vec3 {
+        let encoded = self.texture.value_filtered(u, v, point, footprint);
+        self.color_space.decode_texel(encoded)
+    }
+}
+
+/// The colors baked into the vertices of the mesh that was hit, and `fallback` on everything
+/// without them. they're sRGB encoded like 8 bit textures, and decoded the same way with a
+/// `color_space`
+pub struct VertexColorTexture {
+    fallback: Arc<dyn Texture<Vec3>>,
+    color_space: Option<ColorSpace>,
+}
+
+impl VertexColorTexture {
+    pub fn new(fallback: Arc<dyn Texture<Vec3>>, color_space: Option<ColorSpace>) -> Self {
+        VertexColorTexture {
+            fallback,
+            color_space,
+        }
+    }
+}
+
+impl Texture<Vec3> for VertexColorTexture {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> Vec3 {
+        self.fallback.value(u, v, point)
+    }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> Vec3 {
+        self.fallback.value_filtered(u, v, point, footprint)
+    }
+
+    fn value_at_hit(&self, hit: &HitInfo) -> Vec3 {
+        match (hit.vertex_color, self.color_space) {
+            (Some(color), Some(color_space)) => color_space.decode_texel(color),
+            (Some(color), None) => color,
+            (None, _) => self.fallback.value_at_hit(hit),
+        }
+    }
+}
+
 pub struct CheckerTexture<T> {
-    inv_scale: f64,
+    inv_scale: Float,
     tex1: Arc<dyn Texture<T>>,
     tex2: Arc<dyn Texture<T>>,
 }
 
 impl<T> CheckerTexture<T> {
-    pub fn new(scale: f64, tex1: Arc<dyn Texture<T>>, tex2: Arc<dyn Texture<T>>) -> Self {
+    pub fn new(scale: Float, tex1: Arc<dyn Texture<T>>, tex2: Arc<dyn Texture<T>>) -> Self {
         CheckerTexture {
             inv_scale: scale.recip(),
             tex1,
@@ -40,8 +169,8 @@ impl<T> CheckerTexture<T> {
     }
 }
 
-impl<T: Clone + Send + Sync> Texture<T> for CheckerTexture<T> {
-    fn value(&self, u: f64, v: f64, point: &Vec3) -> T {
+impl<T: TextureValue> Texture<T> for CheckerTexture<T> {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> T {
         let x = (point.x * self.inv_scale).floor() as i32;
         let y = (point.y * self.inv_scale).floor() as i32;
         let z = (point.z * self.inv_scale).floor() as i32;
@@ -51,42 +180,164 @@ impl<T: Clone + Send + Sync> Texture<T> for CheckerTexture<T> {
             self.tex2.value(u, v, point)
         }
     }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> T {
+        // box filter the checker over the footprint, the wave is separable along the axes
+        let width = footprint.dpdx.abs().max(footprint.dpdy.abs()) * self.inv_scale;
+        let p = *point * self.inv_scale;
+        let parity: Float = (0..3)
+            .map(|i| square_wave_average(p[i], width[i]))
+            .product();
+        let t = 0.5 * (1.0 + parity);
+        if t >= 1.0 {
+            return self.tex1.value_filtered(u, v, point, footprint);
+        } else if t <= 0.0 {
+            return self.tex2.value_filtered(u, v, point, footprint);
+        }
+        let a = self.tex1.value_filtered(u, v, point, footprint).to_vec3();
+        let b = self.tex2.value_filtered(u, v, point, footprint).to_vec3();
+        T::from_vec3(a * t + b * (1.0 - t))
+    }
+}
+
+/// average over [t - w, t + w] of the wave that is 1 where floor(t) is even and -1 where it's odd
+fn square_wave_average(t: Float, w: Float) -> Float {
+    // the wave's integral from 0 is a triangle wave
+    let integral = |t: Float| 1.0 - (t.rem_euclid(2.0) - 1.0).abs();
+    if w < 1e-8 {
+        if t.floor().rem_euclid(2.0) == 0.0 {
+            1.0
+        } else {
+            -1.0
+        }
+    } else {
+        (integral(t + w) - integral(t - w)) / (2.0 * w)
+    }
 }
 
+type RgbImage = ImageBuffer<Rgb<u8>, Vec<u8>>;
+
 #[derive(Debug)]
 pub struct ImageTexture {
-    pub img: ImageBuffer<Rgb<u8>, Vec<u8>>,
+    pub img: RgbImage,
+    /// the image halved over and over down to a single pixel, built on the first filtered lookup
+    mipmaps: OnceLock<Vec<RgbImage>>,
 }
 
 impl ImageTexture {
+    /// the image at `filename`, or the missing texture if it can't be loaded
     pub fn new(filename: &str) -> ImageTexture {
-        let img = ImageReader::open(filename)
-            .unwrap()
-            .decode()
-            .unwrap()
-            .to_rgb8();
-        ImageTexture { img }
+        Self::load(filename).unwrap_or_else(|err| {
+            log::error!("{err}, using the missing texture instead");
+            Self::missing()
+        })
+    }
+
+    pub fn load(filename: &str) -> Result<ImageTexture, Error> {
+        Ok(Self::from_image(open_image(filename)?.to_rgb8()))
+    }
+
+    /// magenta and black squares, hard to miss in a render
+    pub fn missing() -> ImageTexture {
+        let img = ImageBuffer::from_fn(8, 8, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgb([255, 0, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        Self::from_image(img)
+    }
+
+    pub fn from_image(img: RgbImage) -> ImageTexture {
+        ImageTexture {
+            img,
+            mipmaps: OnceLock::new(),
+        }
+    }
+
+    /// bytes taken by the pixels, and by the mipmaps once they're built
+    pub fn memory_usage(&self) -> usize {
+        let levels = self.mipmaps.get().map_or(&[][..], |levels| &levels[..]);
+        std::iter::once(&self.img)
+            .chain(levels)
+            .map(|level| level.as_raw().len())
+            .sum()
+    }
+
+    fn mipmaps(&self) -> &[RgbImage] {
+        self.mipmaps.get_or_init(|| {
+            let mut levels = vec![];
+            let (mut width, mut height) = self.img.dimensions();
+            while width > 1 || height > 1 {
+                width = (width / 2).max(1);
+                height = (height / 2).max(1);
+                let prev = levels.last().unwrap_or(&self.img);
+                levels.push(imageops::resize(
+                    prev,
+                    width,
+                    height,
+                    imageops::FilterType::Triangle,
+                ));
+            }
+            levels
+        })
+    }
+
+    /// the texel at (`u`, `v`). coordinates outside of 0 to 1 repeat the image, so a quad or a
+    /// box with its texture coordinates scaled up tiles it
+    fn texel(img: &RgbImage, u: Float, v: Float) -> Vec3 {
+        let wrap = |x: Float| match x {
+            x if (0.0..=1.0).contains(&x) => x,
+            x => x.rem_euclid(1.0),
+        };
+        let u = wrap(u);
+        let v = 1.0 - wrap(v);
+
+        let i = ((u * img.width() as Float) as u32).min(img.width() - 1);
+        let j = ((v * img.height() as Float) as u32).min(img.height() - 1);
+        let pixel = img.get_pixel(i, j);
+        let color_scale = 1.0 / 255.0;
+
+        Vec3::new(
+            color_scale * pixel.channels()[0] as Float,
+            color_scale * pixel.channels()[1] as Float,
+            color_scale * pixel.channels()[2] as Float,
+        )
     }
 }
 
 impl Texture<Vec3> for ImageTexture {
-    fn value(&self, u: f64, v: f64, _point: &Vec3) -> Vec3 {
+    fn value(&self, u: Float, v: Float, _point: &Vec3) -> Vec3 {
         if self.img.height() == 0 {
             return Vec3::new(0.0, 1.0, 1.0);
         }
+        Self::texel(&self.img, u, v)
+    }
 
-        let u = u.clamp(0.0, 1.0);
-        let v = 1.0 - v.clamp(0.0, 1.0);
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> Vec3 {
+        if self.img.height() == 0 {
+            return self.value(u, v, point);
+        }
 
-        let i = (u * self.img.width() as f64) as u32;
-        let j = (v * self.img.height() as f64) as u32;
-        let pixel = self.img.get_pixel(i, j);
-        let color_scale = 1.0 / 255.0;
+        // pick the two levels whose texels are about as large as the footprint and blend them
+        let du = footprint.dudx.abs().max(footprint.dudy.abs()) * self.img.width() as Float;
+        let dv = footprint.dvdx.abs().max(footprint.dvdy.abs()) * self.img.height() as Float;
+        let width = du.max(dv);
+        if !width.is_finite() || width <= 1.0 {
+            return Self::texel(&self.img, u, v);
+        }
 
-        Vec3::new(
-            color_scale * pixel.channels()[0] as f64,
-            color_scale * pixel.channels()[1] as f64,
-            color_scale * pixel.channels()[2] as f64,
-        )
+        let mipmaps = self.mipmaps();
+        let level = width.log2().min(mipmaps.len() as Float);
+        let lower = level.floor() as usize;
+        let t = level - lower as Float;
+        let level_image = |i: usize| if i == 0 { &self.img } else { &mipmaps[i - 1] };
+        let a = Self::texel(level_image(lower), u, v);
+        if t == 0.0 || lower >= mipmaps.len() {
+            return a;
+        }
+        let b = Self::texel(level_image(lower + 1), u, v);
+        a * (1.0 - t) + b * t
     }
 }
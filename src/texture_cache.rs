@@ -0,0 +1,117 @@
+//! Images shared between everything that uses them. Scene files and OBJ materials often point
+//! several materials at the same file, and every `ImageTexture::new` would decode it into a
+//! buffer of its own. The cache loads each file the first time it's asked for and hands out
+//! the same `Arc` after that.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::texture::ImageTexture;
+
+#[derive(Debug, Default)]
+pub struct TextureCache {
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    textures: HashMap<PathBuf, Arc<ImageTexture>>,
+    requests: usize,
+    /// the files that failed to load and are the missing texture instead
+    missing: Vec<PathBuf>,
+}
+
+/// What the cache holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheReport {
+    /// distinct images loaded
+    pub textures: usize,
+    /// times an image was asked for, shared ones count every time
+    pub requests: usize,
+    /// pixels of all the images and their mipmaps
+    pub bytes: usize,
+}
+
+impl TextureCache {
+    pub fn new() -> TextureCache {
+        Self::default()
+    }
+
+    /// the cache the scene loader uses
+    pub fn global() -> &'static TextureCache {
+        static GLOBAL: OnceLock<TextureCache> = OnceLock::new();
+        GLOBAL.get_or_init(TextureCache::new)
+    }
+
+    /// the image at `path`, loaded now if nobody asked for it before. files that fail to load
+    /// are cached as the missing texture, so the error is only logged once
+    pub fn get(&self, path: &str) -> Arc<ImageTexture> {
+        // the same file can be reached through different relative paths
+        let key = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let mut entries = self.entries.lock().unwrap();
+        entries.requests += 1;
+        if let Some(texture) = entries.textures.get(&key) {
+            return texture.clone();
+        }
+        let texture = Arc::new(ImageTexture::load(path).unwrap_or_else(|err| {
+            log::error!("{err}, using the missing texture instead");
+            entries.missing.push(PathBuf::from(path));
+            ImageTexture::missing()
+        }));
+        entries.textures.insert(key, texture.clone());
+        texture
+    }
+
+    /// the image at `path` if it's been loaded, without loading it or counting the request
+    pub fn peek(&self, path: &str) -> Option<Arc<ImageTexture>> {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        self.entries.lock().unwrap().textures.get(&key).cloned()
+    }
+
+    /// the files asked for that couldn't be loaded
+    pub fn missing(&self) -> Vec<PathBuf> {
+        self.entries.lock().unwrap().missing.clone()
+    }
+
+    pub fn report(&self) -> CacheReport {
+        let entries = self.entries.lock().unwrap();
+        CacheReport {
+            textures: entries.textures.len(),
+            requests: entries.requests,
+            bytes: entries
+                .textures
+                .values()
+                .map(|texture| texture.memory_usage())
+                .sum(),
+        }
+    }
+
+    /// forget the image at `path`, so it's loaded again the next time it's asked for
+    pub fn forget(&self, path: &str) {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let mut entries = self.entries.lock().unwrap();
+        entries.textures.remove(&key);
+        entries.missing.retain(|missing| missing != Path::new(path));
+    }
+
+    /// forget every image, the ones still in use stay alive until their last user drops them
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+}
+
+impl fmt::Display for CacheReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} textures for {} uses, {:.1} MB",
+            self.textures,
+            self.requests,
+            self.bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
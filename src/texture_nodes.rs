@@ -0,0 +1,291 @@
+//! Procedural texture nodes that combine other textures, so patterns can be built up in the
+//! scene file instead of baked into images. Every node is a `Texture` itself and its inputs are
+//! `Arc<dyn Texture>`s, so they nest into graphs like
+//! `mix(image, multiply(color, ramp(noise)), noise)`. mix and multiply pass the whole hit on
+//! to their inputs, so they can tint vertex colors too.
+
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{
+    hittable::{HitInfo, TextureValue},
+    texture::{Footprint, Texture},
+    vec3::{Float, Vec2, Vec3},
+};
+
+/// `a` where `factor` is 0, `b` where it's 1, and a blend in between
+pub struct MixTexture<T> {
+    a: Arc<dyn Texture<T>>,
+    b: Arc<dyn Texture<T>>,
+    factor: Arc<dyn Texture<Float>>,
+}
+
+impl<T> MixTexture<T> {
+    pub fn new(
+        a: Arc<dyn Texture<T>>,
+        b: Arc<dyn Texture<T>>,
+        factor: Arc<dyn Texture<Float>>,
+    ) -> Self {
+        MixTexture { a, b, factor }
+    }
+}
+
+impl<T: TextureValue> Texture<T> for MixTexture<T> {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> T {
+        let t = self.factor.value(u, v, point).clamp(0.0, 1.0);
+        let a = self.a.value(u, v, point).to_vec3();
+        let b = self.b.value(u, v, point).to_vec3();
+        T::from_vec3(a.lerp(b, t))
+    }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> T {
+        let t = self
+            .factor
+            .value_filtered(u, v, point, footprint)
+            .clamp(0.0, 1.0);
+        let a = self.a.value_filtered(u, v, point, footprint).to_vec3();
+        let b = self.b.value_filtered(u, v, point, footprint).to_vec3();
+        T::from_vec3(a.lerp(b, t))
+    }
+
+    fn value_at_hit(&self, hit: &HitInfo) -> T {
+        let t = self.factor.value_at_hit(hit).clamp(0.0, 1.0);
+        let a = self.a.value_at_hit(hit).to_vec3();
+        let b = self.b.value_at_hit(hit).to_vec3();
+        T::from_vec3(a.lerp(b, t))
+    }
+}
+
+/// `a` times `b`, channel by channel
+pub struct MultiplyTexture<T> {
+    a: Arc<dyn Texture<T>>,
+    b: Arc<dyn Texture<T>>,
+}
+
+impl<T> MultiplyTexture<T> {
+    pub fn new(a: Arc<dyn Texture<T>>, b: Arc<dyn Texture<T>>) -> Self {
+        MultiplyTexture { a, b }
+    }
+}
+
+impl<T: TextureValue> Texture<T> for MultiplyTexture<T> {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> T {
+        let a = self.a.value(u, v, point).to_vec3();
+        T::from_vec3(a * self.b.value(u, v, point).to_vec3())
+    }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> T {
+        let a = self.a.value_filtered(u, v, point, footprint).to_vec3();
+        T::from_vec3(a * self.b.value_filtered(u, v, point, footprint).to_vec3())
+    }
+
+    fn value_at_hit(&self, hit: &HitInfo) -> T {
+        let a = self.a.value_at_hit(hit).to_vec3();
+        T::from_vec3(a * self.b.value_at_hit(hit).to_vec3())
+    }
+}
+
+/// Maps a greyscale `input` to colors (or numbers) interpolated between `stops`, like a color
+/// ramp node. inputs before the first stop or after the last one get the end colors
+pub struct RampTexture<T> {
+    input: Arc<dyn Texture<Float>>,
+    /// (position, value), sorted by position
+    stops: Vec<(Float, T)>,
+}
+
+impl<T: TextureValue> RampTexture<T> {
+    /// `stops` can't be empty
+    pub fn new(input: Arc<dyn Texture<Float>>, mut stops: Vec<(Float, T)>) -> Self {
+        assert!(!stops.is_empty(), "a ramp needs at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        RampTexture { input, stops }
+    }
+
+    fn lookup(&self, x: Float) -> T {
+        let i = self.stops.partition_point(|&(position, _)| position <= x);
+        if i == 0 {
+            return self.stops[0].1;
+        }
+        if i == self.stops.len() {
+            return self.stops[i - 1].1;
+        }
+        let (x0, a) = self.stops[i - 1];
+        let (x1, b) = self.stops[i];
+        let t = (x - x0) / (x1 - x0);
+        T::from_vec3(a.to_vec3().lerp(b.to_vec3(), t))
+    }
+}
+
+impl<T: TextureValue> Texture<T> for RampTexture<T> {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> T {
+        self.lookup(self.input.value(u, v, point))
+    }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> T {
+        self.lookup(self.input.value_filtered(u, v, point, footprint))
+    }
+}
+
+/// Fractal Perlin noise from 0 to 1, in 3d over the hit point or in 2d over the texture
+/// coordinates. as a color it's grey
+pub struct NoiseTexture {
+    /// features per unit
+    pub scale: Float,
+    /// layers of detail, each one twice as fine and half as strong as the one before
+    pub octaves: usize,
+    /// follow the texture coordinates instead of the hit point
+    pub uv: bool,
+    /// a shuffle of 0..256, repeated so lookups don't have to wrap
+    permutation: Vec<u8>,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: Float, octaves: usize, seed: u64) -> NoiseTexture {
+        let mut permutation: Vec<u8> = (0..=255).collect();
+        permutation.shuffle(&mut StdRng::seed_from_u64(seed));
+        permutation.extend_from_within(..);
+        NoiseTexture {
+            scale,
+            octaves: octaves.max(1),
+            uv: false,
+            permutation,
+        }
+    }
+
+    pub fn with_uv(mut self, uv: bool) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    fn hash(&self, x: usize, y: usize, z: usize) -> u8 {
+        let p = &self.permutation;
+        p[p[p[x & 255] as usize + (y & 255)] as usize + (z & 255)]
+    }
+
+    /// one octave of gradient noise, roughly from -1 to 1
+    fn perlin(&self, p: Vec3) -> Float {
+        let cell = p.floor();
+        let f = p - cell;
+        let (x, y, z) = (
+            cell.x as i64 as usize,
+            cell.y as i64 as usize,
+            cell.z as i64 as usize,
+        );
+        // the 12 edge directions of a cube, picked by hash
+        let gradient = |h: u8, d: Vec3| -> Float {
+            let (a, b) = match h % 12 {
+                0..=3 => (d.x, d.y),
+                4..=7 => (d.x, d.z),
+                _ => (d.y, d.z),
+            };
+            let a = if h & 1 == 0 { a } else { -a };
+            let b = if h & 2 == 0 { b } else { -b };
+            a + b
+        };
+        let corner = |i: usize, j: usize, k: usize| {
+            let d = f - Vec3::new(i as Float, j as Float, k as Float);
+            gradient(
+                self.hash(x.wrapping_add(i), y.wrapping_add(j), z.wrapping_add(k)),
+                d,
+            )
+        };
+        // quintic fade so the noise is smooth across cells
+        let fade = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+        let lerp = |a: Float, b: Float, t: Float| a + (b - a) * t;
+        let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fade.x);
+        let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fade.x);
+        let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fade.x);
+        let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fade.x);
+        lerp(lerp(x00, x10, fade.y), lerp(x01, x11, fade.y), fade.z)
+    }
+
+    fn fractal(&self, p: Vec3) -> Float {
+        let (mut sum, mut amplitude, mut total, mut p) = (0.0, 1.0, 0.0, p);
+        for _ in 0..self.octaves {
+            sum += amplitude * self.perlin(p);
+            total += amplitude;
+            amplitude *= 0.5;
+            p *= 2.0;
+        }
+        (0.5 + 0.5 * sum / total).clamp(0.0, 1.0)
+    }
+}
+
+impl Texture<Float> for NoiseTexture {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> Float {
+        let p = if self.uv {
+            Vec3::new(u, v, 0.0)
+        } else {
+            *point
+        };
+        self.fractal(p * self.scale)
+    }
+}
+
+impl Texture<Vec3> for NoiseTexture {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> Vec3 {
+        Vec3::splat(Texture::<Float>::value(self, u, v, point))
+    }
+}
+
+/// Looks `texture` up at moved texture coordinates: tiled by `scale`, shifted by `offset` and
+/// pushed around by `warp` (centered on 0.5) times `strength`. only textures that follow the
+/// texture coordinates change, 3d ones like the checker follow the hit point
+pub struct UvWarpTexture<T> {
+    texture: Arc<dyn Texture<T>>,
+    pub scale: Vec2,
+    pub offset: Vec2,
+    warp: Option<Arc<dyn Texture<Float>>>,
+    pub strength: Float,
+}
+
+impl<T> UvWarpTexture<T> {
+    pub fn new(texture: Arc<dyn Texture<T>>, scale: Vec2, offset: Vec2) -> Self {
+        UvWarpTexture {
+            texture,
+            scale,
+            offset,
+            warp: None,
+            strength: 0.0,
+        }
+    }
+
+    pub fn with_warp(mut self, warp: Arc<dyn Texture<Float>>, strength: Float) -> Self {
+        self.warp = Some(warp);
+        self.strength = strength;
+        self
+    }
+
+    fn warped(&self, u: Float, v: Float, point: &Vec3) -> (Float, Float) {
+        let mut uv = Vec2::new(u, v) * self.scale + self.offset;
+        if let Some(warp) = &self.warp {
+            // the second axis reads the warp somewhere else, so u and v don't move together
+            let du = warp.value(u, v, point) - 0.5;
+            let dv = warp.value(u + 0.5, v + 0.5, &(*point + Vec3::splat(17.3))) - 0.5;
+            uv += Vec2::new(du, dv) * self.strength;
+        }
+        // wrap into [0, 1) so images tile instead of clamping at the edge
+        (uv.x.rem_euclid(1.0), uv.y.rem_euclid(1.0))
+    }
+}
+
+impl<T: TextureValue> Texture<T> for UvWarpTexture<T> {
+    fn value(&self, u: Float, v: Float, point: &Vec3) -> T {
+        let (u, v) = self.warped(u, v, point);
+        self.texture.value(u, v, point)
+    }
+
+    fn value_filtered(&self, u: Float, v: Float, point: &Vec3, footprint: &Footprint) -> T {
+        let (u, v) = self.warped(u, v, point);
+        // tiling shrinks the texture, so a pixel covers more of it
+        let footprint = Footprint {
+            dudx: footprint.dudx * self.scale.x,
+            dudy: footprint.dudy * self.scale.x,
+            dvdx: footprint.dvdx * self.scale.y,
+            dvdy: footprint.dvdy * self.scale.y,
+            ..*footprint
+        };
+        self.texture.value_filtered(u, v, point, &footprint)
+    }
+}
@@ -1,12 +1,33 @@
 use rand::Rng;
 
-pub type Vec3 = glam::DVec3;
-pub type Vec2 = glam::DVec2;
-pub type Quat = glam::DQuat;
-pub type Mat4 = glam::DMat4;
+use crate::sampler::rng;
 
-pub fn random_vector_range(min: f64, max: f64) -> Vec3 {
-    let mut rng = rand::thread_rng();
+// the scalar everything is computed in. f64 by default, the `f32` feature trades precision for
+// twice the SIMD lanes and half the memory; keep f64 for validation renders
+#[cfg(not(feature = "f32"))]
+mod precision {
+    pub type Float = f64;
+    pub type Vec3 = glam::DVec3;
+    pub type Vec2 = glam::DVec2;
+    pub type Quat = glam::DQuat;
+    pub type Mat4 = glam::DMat4;
+    pub use std::f64::consts;
+}
+
+#[cfg(feature = "f32")]
+mod precision {
+    pub type Float = f32;
+    pub type Vec3 = glam::Vec3;
+    pub type Vec2 = glam::Vec2;
+    pub type Quat = glam::Quat;
+    pub type Mat4 = glam::Mat4;
+    pub use std::f32::consts;
+}
+
+pub use self::precision::*;
+
+pub fn random_vector_range(min: Float, max: Float) -> Vec3 {
+    let mut rng = rng();
     Vec3::new(
         rng.gen_range(min..max),
         rng.gen_range(min..max),
@@ -15,7 +36,7 @@ pub fn random_vector_range(min: f64, max: f64) -> Vec3 {
 }
 
 pub fn random_vector() -> Vec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     Vec3::new(rng.gen(), rng.gen(), rng.gen())
 }
 
@@ -29,16 +50,16 @@ pub fn get_rotation_to_z(input: Vec3) -> Quat {
 }
 
 pub fn step(edge: Vec3, x: Vec3) -> Vec3 {
-    let f = |e: f64, v: f64| if v >= e { 1.0 } else { 0.0 };
+    let f = |e: Float, v: Float| if v >= e { 1.0 } else { 0.0 };
     Vec3::new(f(edge.x, x.x), f(edge.y, x.y), f(edge.z, x.z))
 }
 
 pub trait VectorExt {
-    fn luminance(&self) -> f64;
+    fn luminance(&self) -> Float;
 }
 
 impl VectorExt for Vec3 {
-    fn luminance(&self) -> f64 {
+    fn luminance(&self) -> Float {
         0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
     }
 }
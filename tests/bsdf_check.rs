@@ -0,0 +1,42 @@
+//! The white furnace and chi-squared tests of `bsdf-check` on every built-in BSDF, with fewer
+//! samples and a fixed seed. the thorough run is slow without optimizations, so it only runs
+//! with `cargo test -r -- --ignored`. a quick one with a tenth of the samples on a few of them,
+//! one of each kind of lobe, runs always.
+
+use path_tracer::{
+    bsdf::testing::{builtin_bsdfs, check_bsdf},
+    sampler::seed_thread,
+};
+
+const SAMPLES: usize = 50_000;
+const QUICK_SAMPLES: usize = 5_000;
+const QUICK_BSDFS: [&str; 4] = [
+    "diffuse",
+    "glass roughness 0.3",
+    "cloth roughness 0.5",
+    "principled dielectric",
+];
+
+fn failures(samples: usize, names: &[&str]) -> Vec<String> {
+    seed_thread(Some(1));
+    builtin_bsdfs()
+        .into_iter()
+        .filter(|(name, ..)| names.is_empty() || names.contains(&name.as_str()))
+        .map(|(name, material, transmissive)| check_bsdf(&name, &material, transmissive, samples))
+        .filter(|report| !report.passed())
+        .map(|report| report.to_string())
+        .collect()
+}
+
+#[test]
+#[ignore = "slow in debug builds"]
+fn builtin_bsdfs_pass() {
+    let failed = failures(SAMPLES, &[]);
+    assert!(failed.is_empty(), "\n{}", failed.concat());
+}
+
+#[test]
+fn builtin_bsdfs_pass_quickly() {
+    let failed = failures(QUICK_SAMPLES, &QUICK_BSDFS);
+    assert!(failed.is_empty(), "\n{}", failed.concat());
+}
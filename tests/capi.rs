@@ -0,0 +1,129 @@
+//! The C API called the way a C program would, including one built against an older header
+//! with smaller structs. run with `cargo test --features capi --test capi`
+
+use std::{mem, ptr};
+
+use path_tracer::capi::*;
+
+fn diffuse() -> PtMaterial {
+    PtMaterial {
+        size: mem::size_of::<PtMaterial>(),
+        kind: PT_DIFFUSE,
+        color: [0.5; 3],
+        ..Default::default()
+    }
+}
+
+fn camera() -> PtCamera {
+    PtCamera {
+        size: mem::size_of::<PtCamera>(),
+        width: 4,
+        height: 2,
+        samples_per_pixel: 1,
+        max_depth: 4,
+        look_from: [0.0, 0.0, 3.0],
+        look_at: [0.0; 3],
+        vup: [0.0, 1.0, 0.0],
+        vfov: 40.0,
+        seed: 1,
+    }
+}
+
+#[test]
+fn renders_a_sphere() {
+    unsafe {
+        let world = pt_world_new();
+        assert_eq!(pt_set_background(world, 1.0, 1.0, 1.0), PT_OK);
+        let center = [0.0; 3];
+        assert_eq!(
+            pt_add_sphere(world, center.as_ptr(), 1.0, &diffuse()),
+            PT_OK
+        );
+        let buffer = pt_render(world, &camera());
+        assert!(!buffer.is_null());
+        let pixels = std::slice::from_raw_parts(buffer, 4 * 2 * 4);
+        assert!(pixels.iter().all(|c| c.is_finite()));
+        pt_buffer_free(buffer, 4, 2);
+        pt_world_free(world);
+    }
+}
+
+#[test]
+fn turns_away_structs_without_their_size() {
+    unsafe {
+        let world = pt_world_new();
+        let center = [0.0; 3];
+        let material = PtMaterial {
+            size: 0,
+            ..diffuse()
+        };
+        assert_eq!(
+            pt_add_sphere(world, center.as_ptr(), 1.0, &material),
+            PT_INVALID_ARGUMENT
+        );
+        let camera = PtCamera {
+            size: mem::size_of::<PtCamera>() - 8,
+            ..camera()
+        };
+        assert!(pt_render(world, &camera).is_null());
+        assert!(pt_render(world, ptr::null()).is_null());
+        pt_world_free(world);
+    }
+}
+
+/// the structs of the first header, as a program built against it passes them
+#[repr(C)]
+struct MaterialV1 {
+    size: usize,
+    kind: i32,
+    color: [f64; 3],
+    roughness: f64,
+    ior: f64,
+}
+
+#[repr(C)]
+struct CameraV1 {
+    size: usize,
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    vup: [f64; 3],
+    vfov: f64,
+    seed: u64,
+}
+
+#[test]
+fn takes_the_structs_of_the_first_header() {
+    let material = MaterialV1 {
+        size: mem::size_of::<MaterialV1>(),
+        kind: PT_DIFFUSE,
+        color: [0.5; 3],
+        roughness: 0.0,
+        ior: 0.0,
+    };
+    let camera = CameraV1 {
+        size: mem::size_of::<CameraV1>(),
+        width: 4,
+        height: 2,
+        samples_per_pixel: 1,
+        max_depth: 4,
+        look_from: [0.0, 0.0, 3.0],
+        look_at: [0.0; 3],
+        vup: [0.0, 1.0, 0.0],
+        vfov: 40.0,
+        seed: 1,
+    };
+    unsafe {
+        let world = pt_world_new();
+        let center = [0.0; 3];
+        let material = &material as *const MaterialV1 as *const PtMaterial;
+        assert_eq!(pt_add_sphere(world, center.as_ptr(), 1.0, material), PT_OK);
+        let buffer = pt_render(world, &camera as *const CameraV1 as *const PtCamera);
+        assert!(!buffer.is_null());
+        pt_buffer_free(buffer, 4, 2);
+        pt_world_free(world);
+    }
+}
@@ -0,0 +1,98 @@
+//! Rendering over TCP, see `render_distributed` and `serve`
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    thread,
+};
+
+use path_tracer::{
+    distributed::{render_distributed, serve, RenderJob},
+    Float, MaterialRegistry,
+};
+
+const SPHERE: &str = r#"
+[camera]
+vfov = 40
+
+[[objects]]
+type = "sphere"
+center = [0, 0, -3]
+radius = 1
+material = { type = "diffuse", color = [0.5, 0.5, 0.5] }
+"#;
+
+fn job(image_width: usize, aspect_ratio: Float, samples_per_pixel: usize) -> RenderJob {
+    RenderJob {
+        scene: SPHERE.to_string(),
+        base_dir: PathBuf::from("."),
+        overrides: Vec::new(),
+        image_width,
+        aspect_ratio,
+        samples_per_pixel,
+        seed: Some(1),
+    }
+}
+
+#[test]
+fn jobs_with_broken_sizes_are_refused() {
+    assert!(job(16, 1.0, 1).check().is_ok());
+    assert!(job(16, Float::NAN, 1).check().is_err());
+    assert!(job(16, 0.0, 1).check().is_err());
+    assert!(job(16, -1.0, 1).check().is_err());
+    assert!(job(16, 1e-9, 1).check().is_err());
+    assert!(job(0, 1.0, 1).check().is_err());
+    assert!(job(1 << 40, 1.0, 1).check().is_err());
+    assert!(job(16, 1.0, 0).check().is_err());
+    assert!(job(16, 1.0, usize::MAX).check().is_err());
+
+    // refused before connecting to anyone
+    let err = render_distributed(&job(16, Float::INFINITY, 1), &["127.0.0.1:1".into()], 4)
+        .expect_err("the aspect ratio is infinite");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn workers_answering_with_a_huge_image_are_dropped() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let worker = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"PTD1").unwrap();
+        stream.write_all(&[1]).unwrap();
+        stream.write_all(&(1u64 << 40).to_le_bytes()).unwrap();
+        stream.write_all(&(1u64 << 40).to_le_bytes()).unwrap();
+        stream.write_all(&[0]).unwrap();
+        // until the coordinator hangs up
+        let _ = stream.read_to_end(&mut Vec::new());
+    });
+    let err = render_distributed(&job(16, 1.0, 1), &[addr], 4).expect_err("no worker is left");
+    assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    worker.join().unwrap();
+}
+
+#[test]
+fn bands_from_a_worker_make_up_the_image() {
+    // a free port for the worker
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let workers = [addr.clone()];
+    thread::spawn(move || serve(addr, &MaterialRegistry::with_builtins()));
+
+    // the worker may not be listening yet
+    let film = (0..100)
+        .find_map(|_| {
+            let film = render_distributed(&job(8, 2.0, 1), &workers, 3).ok();
+            if film.is_none() {
+                thread::sleep(std::time::Duration::from_millis(50));
+            }
+            film
+        })
+        .expect("the worker renders the job");
+    assert_eq!((film.width(), film.height()), (8, 4));
+    assert!(film.samples.pixels().all(|count| count.0[0] == 1));
+}
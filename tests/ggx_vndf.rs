@@ -0,0 +1,125 @@
+use path_tracer::{
+    bsdf::sampling::ggx,
+    vec3::{
+        consts::{FRAC_PI_2, PI},
+        Float, Vec3,
+    },
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const THETA_BINS: usize = 32;
+const PHI_BINS: usize = 16;
+
+fn direction(theta: Float, phi: Float) -> Vec3 {
+    Vec3::new(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        theta.cos(),
+    )
+}
+
+/// D_visible integrated over each (theta, phi) bin of the hemisphere
+fn expected_histogram(v: Vec3, roughness: Float) -> Vec<Float> {
+    const STEPS: usize = 12;
+    let d_theta = FRAC_PI_2 / (THETA_BINS * STEPS) as Float;
+    let d_phi = 2.0 * PI / (PHI_BINS * STEPS) as Float;
+    let mut bins = vec![0.0; THETA_BINS * PHI_BINS];
+    for i in 0..THETA_BINS * STEPS {
+        let theta = (i as Float + 0.5) * d_theta;
+        for j in 0..PHI_BINS * STEPS {
+            let phi = (j as Float + 0.5) * d_phi;
+            let h = direction(theta, phi);
+            let density = ggx::D_visible(v, h, roughness) * theta.sin() * d_theta * d_phi;
+            bins[(i / STEPS) * PHI_BINS + j / STEPS] += density;
+        }
+    }
+    bins
+}
+
+fn sampled_histogram(v: Vec3, roughness: Float, samples: usize, seed: u64) -> Vec<Float> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut bins = vec![0.0; THETA_BINS * PHI_BINS];
+    for _ in 0..samples {
+        let h = ggx::sample_vndf(v, roughness, rng.gen(), rng.gen());
+        assert!(h.is_normalized(), "{h} is not normalized");
+        let theta = h.z.clamp(-1.0, 1.0).acos();
+        let phi = h.y.atan2(h.x).rem_euclid(2.0 * PI);
+        let i = ((theta / FRAC_PI_2 * THETA_BINS as Float) as usize).min(THETA_BINS - 1);
+        let j = ((phi / (2.0 * PI) * PHI_BINS as Float) as usize).min(PHI_BINS - 1);
+        bins[i * PHI_BINS + j] += 1.0;
+    }
+    bins
+}
+
+/// Pearson's chi-squared statistic over the bins expecting at least 5 samples, with the rest
+/// pooled into one, and the degrees of freedom
+fn chi_squared(observed: &[Float], expected: &[Float], samples: usize) -> (Float, usize) {
+    let mut statistic = 0.0;
+    let mut dof = 0usize;
+    let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+    for (&o, &e) in observed.iter().zip(expected) {
+        let e = e * samples as Float;
+        if e < 5.0 {
+            pooled_observed += o;
+            pooled_expected += e;
+        } else {
+            statistic += (o - e) * (o - e) / e;
+            dof += 1;
+        }
+    }
+    if pooled_expected >= 5.0 {
+        statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        dof += 1;
+    }
+    (statistic, dof.saturating_sub(1))
+}
+
+fn views() -> Vec<Vec3> {
+    [0.0, 0.4, 0.8, 1.2, 1.5]
+        .iter()
+        .map(|&theta| direction(theta, 0.7))
+        .collect()
+}
+
+#[test]
+fn visible_normals_are_normalized() {
+    for roughness in [0.2, 0.5, 0.9] {
+        for v in views() {
+            let total: Float = expected_histogram(v, roughness).iter().sum();
+            assert!(
+                (total - 1.0).abs() < 0.01,
+                "D_visible integrates to {total} for roughness {roughness}, view {v}"
+            );
+        }
+    }
+}
+
+#[test]
+fn samples_follow_visible_normals() {
+    const SAMPLES: usize = 200_000;
+    for (k, roughness) in [0.2, 0.5, 0.9].into_iter().enumerate() {
+        for (l, v) in views().into_iter().enumerate() {
+            let expected = expected_histogram(v, roughness);
+            let observed = sampled_histogram(v, roughness, SAMPLES, (k * 16 + l) as u64);
+            let (statistic, dof) = chi_squared(&observed, &expected, SAMPLES);
+            // about 5 standard deviations above the mean of the distribution
+            let limit = dof as Float + 5.0 * (2.0 * dof as Float).sqrt();
+            assert!(
+                statistic < limit,
+                "chi-squared {statistic:.1} over {dof} degrees of freedom (limit {limit:.1}) \
+                 for roughness {roughness}, view {v}"
+            );
+        }
+    }
+}
+
+#[test]
+fn views_from_below_give_upper_normals() {
+    for v in views() {
+        let below = Vec3::new(v.x, v.y, -v.z);
+        for _ in 0..1000 {
+            let h = ggx::sample_microfacet_normal(below, 0.5);
+            assert!(h.z >= 0.0, "{h} is below the surface");
+        }
+    }
+}
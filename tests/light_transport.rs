@@ -0,0 +1,213 @@
+//! Scenes with a known answer, rendered and checked against it within the noise of the render
+//! (`FilmStats::agrees_with`), so a bias in the integrator or the light sampling shows up as a
+//! failure instead of a slightly wrong image.
+
+use std::sync::Arc;
+
+use path_tracer::{
+    bsdf::{diffuse::DiffuseBRDF, glass::GlassBSDF},
+    camera::{Camera, FilmBuffer, Region},
+    environment::EnvironmentType,
+    hittable::{LightLink, Linked, PointLight, Quad, Sphere, Tagged, TriangleMesh, World},
+    material::DiffuseLight,
+    vec3::{consts::PI, Float, Vec3},
+};
+
+const SIZE: usize = 16;
+
+/// a narrow view from `look_from` at `look_at`, so every pixel sees about the same point
+fn render(
+    world: &mut World,
+    look_from: Vec3,
+    look_at: Vec3,
+    vfov: Float,
+    spp: usize,
+) -> FilmBuffer {
+    world.build_bvh();
+    let mut camera = Camera::new();
+    camera.aspect_ratio = 1.0;
+    camera.image_width = SIZE;
+    camera.samples_per_pixel = spp;
+    camera.integrator.max_depth = 64;
+    camera.vfov = vfov;
+    camera.look_from = look_from;
+    camera.look_at = look_at;
+    camera.vup = if (look_from - look_at).cross(Vec3::Y).length() > 0.0 {
+        Vec3::Y
+    } else {
+        Vec3::Z
+    };
+    camera.focal_length = 1.0;
+    camera.seed = Some(7);
+    camera.init();
+    camera.render_to_buffer(world)
+}
+
+fn assert_radiance(film: &FilmBuffer, expected: Vec3, slack: Float) {
+    let stats = film.stats(&Region::full(film.width(), film.height()));
+    assert!(
+        stats.agrees_with(expected, 4.0, slack),
+        "expected {expected}, rendered {} ± {}",
+        stats.mean,
+        stats.std_error
+    );
+}
+
+// a convex object never sees itself, so under a uniform sky of radiance 1 a lambertian one
+// reflects its albedo after exactly one bounce
+#[test]
+fn diffuse_sphere_under_uniform_sky() {
+    let mut world = World::new();
+    world.environment = EnvironmentType::Color(Vec3::ONE);
+    let albedo = Vec3::new(0.25, 0.5, 0.75);
+    world.add_object(Sphere::new_still(
+        1.0,
+        Vec3::ZERO,
+        Arc::new(DiffuseBRDF::from_rgb(albedo)),
+    ));
+    let film = render(&mut world, Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, 10.0, 16);
+    assert_radiance(&film, albedo, 1e-3);
+}
+
+// clear glass neither absorbs nor emits, so under a uniform sky it disappears. the little light
+// lost is from paths cut off by the depth limit
+#[test]
+fn glass_sphere_under_uniform_sky() {
+    let mut world = World::new();
+    world.environment = EnvironmentType::Color(Vec3::ONE);
+    world.add_object(Sphere::new_still(
+        1.0,
+        Vec3::ZERO,
+        Arc::new(GlassBSDF::basic(1.5)),
+    ));
+    let film = render(&mut world, Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, 10.0, 64);
+    assert_radiance(&film, Vec3::ONE, 0.01);
+}
+
+// right under a point light of intensity I at height h, a lambertian plane gets an irradiance
+// of I / h^2 and reflects albedo / pi of it. seen from the light, a pixel off to the side by an
+// angle t gets cos^3 t of that
+#[test]
+fn point_light_over_plane() {
+    let (albedo, intensity, height) = (0.5, 10.0, 2.0);
+    let mut world = World::new();
+    world.environment = EnvironmentType::Color(Vec3::ZERO);
+    world.add_object(Quad::new(
+        Vec3::new(-1000.0, 0.0, -1000.0),
+        Vec3::new(0.0, 0.0, 2000.0),
+        Vec3::new(2000.0, 0.0, 0.0),
+        Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(albedo))),
+    ));
+    let light = Vec3::new(0.0, height, 0.0);
+    world.add_delta_light(PointLight::new(light, Vec3::splat(4.0 * PI * intensity)));
+    let vfov: Float = 2.0;
+    let film = render(&mut world, light, Vec3::ZERO, vfov, 4);
+
+    let half = (vfov.to_radians() / 2.0).tan();
+    let mut expected = 0.0;
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let offset = |i: usize| ((i as Float + 0.5) / SIZE as Float * 2.0 - 1.0) * half;
+            let cos = 1.0 / (1.0 + offset(x).powi(2) + offset(y).powi(2)).sqrt();
+            expected += albedo / PI * intensity / (height * height) * cos.powi(3);
+        }
+    }
+    expected /= (SIZE * SIZE) as Float;
+    assert_radiance(&film, Vec3::splat(expected), 1e-3 * expected);
+}
+
+// a sphere of radiance Le and radius r at distance d straight above a lambertian plane lights
+// it with an irradiance of pi Le (r / d)^2, so the plane reflects albedo Le (r / d)^2
+#[test]
+fn sphere_light_over_plane() {
+    let (albedo, emission, radius, height) = (0.5, 4.0, 0.5, 3.0);
+    let mut world = World::new();
+    world.environment = EnvironmentType::Color(Vec3::ZERO);
+    world.add_object(Quad::new(
+        Vec3::new(-1000.0, 0.0, -1000.0),
+        Vec3::new(0.0, 0.0, 2000.0),
+        Vec3::new(2000.0, 0.0, 0.0),
+        Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(albedo))),
+    ));
+    world.add_light(Sphere::new_still(
+        radius,
+        Vec3::new(0.0, height, 0.0),
+        Arc::new(DiffuseLight::from_rgb(Vec3::splat(emission))),
+    ));
+    let film = render(&mut world, Vec3::new(0.0, 1.0, 4.0), Vec3::ZERO, 1.0, 64);
+    let expected = albedo * emission * (radius / height) * (radius / height);
+    assert_radiance(&film, Vec3::splat(expected), 2e-3 * expected);
+}
+
+// a square light of radiance Le lights the point under its center with pi Le F, where F is four
+// times the form factor of a rectangle with a corner over the point, so a lambertian plane
+// reflects albedo Le F there. the square is a fan of triangles far from the same size, so a
+// triangle picked with other odds than its pdf says would show
+#[test]
+fn mesh_light_over_plane() {
+    let (albedo, emission, half, height): (Float, Float, Float, Float) = (0.5, 4.0, 1.0, 2.0);
+    let mut world = World::new();
+    world.environment = EnvironmentType::Color(Vec3::ZERO);
+    world.add_object(Quad::new(
+        Vec3::new(-1000.0, 0.0, -1000.0),
+        Vec3::new(0.0, 0.0, 2000.0),
+        Vec3::new(2000.0, 0.0, 0.0),
+        Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(albedo))),
+    ));
+    let vertices = [
+        Vec3::new(-half, height, -half),
+        Vec3::new(half, height, -half),
+        Vec3::new(half, height, half),
+        Vec3::new(-half, height, half),
+        Vec3::new(0.8 * half, height, -0.8 * half),
+    ];
+    // around a point near one corner, wound to face down
+    let indices = [4, 0, 1, 4, 1, 2, 4, 2, 3, 4, 3, 0];
+    world.add_light(TriangleMesh::from_buffers(
+        &vertices,
+        &[],
+        &[],
+        &indices,
+        Arc::new(DiffuseLight::from_rgb(Vec3::splat(emission))),
+    ));
+    let film = render(&mut world, Vec3::new(0.0, 0.5, 0.0), Vec3::ZERO, 1.0, 64);
+
+    let a = half / height;
+    let s = 1.0 / (1.0 + a * a).sqrt();
+    let form_factor = 4.0 / (2.0 * PI) * 2.0 * a * s * (a * s).atan();
+    let expected = albedo * emission * form_factor;
+    assert_radiance(&film, Vec3::splat(expected), 2e-3 * expected);
+}
+
+// the sphere of `sphere_light_over_plane` with a bigger one next to it that's linked away from
+// the plane lights it as much as alone. a link that left out only the shadow rays or only the
+// bounces that run into the light would leave part of the second one's light
+#[test]
+fn light_linked_away_from_plane() {
+    let (albedo, emission, radius, height) = (0.5, 4.0, 0.5, 3.0);
+    let mut world = World::new();
+    world.environment = EnvironmentType::Color(Vec3::ZERO);
+    let plane = Quad::new(
+        Vec3::new(-1000.0, 0.0, -1000.0),
+        Vec3::new(0.0, 0.0, 2000.0),
+        Vec3::new(2000.0, 0.0, 0.0),
+        Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(albedo))),
+    );
+    world.add_object(Tagged::new(Arc::new(plane)).with_object_id(1));
+    let light = |x: Float, radius: Float| {
+        Arc::new(Sphere::new_still(
+            radius,
+            Vec3::new(x, height, 0.0),
+            Arc::new(DiffuseLight::from_rgb(Vec3::splat(emission))),
+        ))
+    };
+    world.add_shared_light(light(0.0, radius));
+    let link = LightLink {
+        include: None,
+        exclude: vec![1],
+    };
+    world.add_light(Linked::new(light(3.0, 1.5), link));
+    let film = render(&mut world, Vec3::new(0.0, 1.0, 4.0), Vec3::ZERO, 1.0, 64);
+    let expected = albedo * emission * (radius / height) * (radius / height);
+    assert_radiance(&film, Vec3::splat(expected), 2e-3 * expected);
+}
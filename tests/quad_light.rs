@@ -0,0 +1,38 @@
+//! Whether `Quad::light_pdf` finds points on the light at any scale of the scene
+
+use std::sync::Arc;
+
+use path_tracer::{
+    hittable::{Hittable, Quad},
+    material::DiffuseLight,
+    vec3::{Float, Vec3},
+};
+
+fn light(corner: Vec3, size: Float) -> Quad {
+    Quad::new(
+        corner,
+        Vec3::X * size,
+        Vec3::Z * size,
+        Arc::new(DiffuseLight::from_rgb(Vec3::ONE)),
+    )
+}
+
+#[test]
+fn sampled_points_far_from_the_origin_are_on_the_light() {
+    let quad = light(Vec3::new(1e7, 1e7, 1e7), 1e3);
+    let origin = Vec3::new(1e7, 1e7 - 1e3, 1e7);
+    for _ in 0..100 {
+        let sample = quad.sample_light(origin, 0.0).unwrap();
+        assert!(quad.light_pdf(origin, sample.point, 0.0) > 0.0);
+    }
+}
+
+#[test]
+fn points_off_a_tiny_light_are_not_on_it() {
+    let quad = light(Vec3::ZERO, 1e-4);
+    let origin = Vec3::new(5e-5, -1e-4, 5e-5);
+    let on = Vec3::new(5e-5, 0.0, 5e-5);
+    assert!(quad.light_pdf(origin, on, 0.0) > 0.0);
+    let below = Vec3::new(5e-5, -1e-5, 5e-5);
+    assert_eq!(quad.light_pdf(origin, below, 0.0), 0.0);
+}
@@ -0,0 +1,124 @@
+//! Golden image tests: small scenes in `tests/golden` rendered at a fixed seed and compared
+//! against the images stored next to them. run with `cargo test --features regression`, in
+//! release (`-r`) for speed.
+//!
+//! a change to the sampling gives the same scene other noise, so the images aren't compared
+//! pixel by pixel but by the average color of blocks of pixels, which the noise mostly cancels
+//! out of while a biased or broken integrator still moves it. a render that fails is saved to
+//! `target/regression/<name>.png` to look at. `UPDATE_GOLDEN=1` saves the renders as the new
+//! golden images instead, after a change that's meant to change them.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use image::RgbImage;
+use path_tracer::{image_metrics, load_scene, vec3::Float};
+
+/// pixels along each side of the blocks that are averaged
+const BLOCK: u32 = 8;
+
+// the tolerances are about twice the error between renders of the same scene at different
+// seeds, a light 10% darker goes over them
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// the linear average of each block of the image, a color per block
+fn block_means(image: &RgbImage) -> Vec<[Float; 3]> {
+    let (blocks_x, blocks_y) = (
+        image.width().div_ceil(BLOCK),
+        image.height().div_ceil(BLOCK),
+    );
+    let mut sums = vec![([0.0; 3], 0); (blocks_x * blocks_y) as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let (sum, count) = &mut sums[((y / BLOCK) * blocks_x + x / BLOCK) as usize];
+        for (total, value) in sum.iter_mut().zip(pixel.0) {
+            // undo the gamma the images are saved with
+            *total += (value as Float / 255.0).powi(2);
+        }
+        *count += 1;
+    }
+    sums.into_iter()
+        .map(|(sum, count)| sum.map(|total| total / count as Float))
+        .collect()
+}
+
+/// the root mean squared difference between the block averages, relative to the average
+/// brightness of the golden image
+fn block_error(golden: &RgbImage, render: &RgbImage) -> Float {
+    let (golden, render) = (block_means(golden), block_means(render));
+    let mut squared = 0.0;
+    let mut brightness = 0.0;
+    for (g, r) in golden.iter().zip(&render) {
+        for c in 0..3 {
+            squared += (g[c] - r[c]).powi(2);
+            brightness += g[c];
+        }
+    }
+    let n = (golden.len() * 3) as Float;
+    (squared / n).sqrt() / (brightness / n).max(1e-3)
+}
+
+/// render `tests/golden/<scene>.toml` and check it against `tests/golden/<golden>.png`
+fn check(scene: &str, golden: &str, tolerance: Float) {
+    let dir = golden_dir();
+    let path = dir.join(format!("{scene}.toml"));
+    let mut loaded = load_scene(&path.to_string_lossy()).expect("the scene loads");
+    loaded.camera.init();
+    let render = loaded.camera.render_to_buffer(&loaded.world).to_rgb8();
+
+    let golden_path = dir.join(format!("{golden}.png"));
+    // scenes checked against another scene's image leave it to that scene
+    if env::var_os("UPDATE_GOLDEN").is_some() && scene == golden {
+        render.save(&golden_path).expect("the golden image saves");
+        return;
+    }
+    let golden_image = image::open(&golden_path)
+        .unwrap_or_else(|err| panic!("can't open {}: {err}", golden_path.display()))
+        .to_rgb8();
+    assert_eq!(
+        golden_image.dimensions(),
+        render.dimensions(),
+        "{scene} changed size"
+    );
+
+    let error = block_error(&golden_image, &render);
+    if error > tolerance {
+        let out_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/regression");
+        let _ = std::fs::create_dir_all(&out_dir);
+        let _ = render.save(out_dir.join(format!("{scene}.png")));
+        let metrics = image_metrics::compare(&golden_image, &render).unwrap();
+        panic!(
+            "{scene} differs from {golden}.png by {error:.4} (tolerance {tolerance}), {metrics}. \
+             the render is in target/regression"
+        );
+    }
+}
+
+#[test]
+fn cornell() {
+    check("cornell", "cornell", 0.07);
+}
+
+#[test]
+fn wavefront_matches_path() {
+    check("cornell_wavefront", "cornell", 0.07);
+}
+
+#[test]
+fn materials() {
+    check("materials", "materials", 0.015);
+}
+
+#[test]
+fn point_light() {
+    check("point_light", "point_light", 0.015);
+}
+
+#[test]
+fn ambient_occlusion() {
+    check("ao", "ao", 0.015);
+}
@@ -0,0 +1,31 @@
+//! Numbers of a scene file replaced from the command line, see `parse_scene_with_overrides`
+
+use std::path::Path;
+
+use path_tracer::scene::{parse_scene_with_overrides, MaterialRegistry, SceneError};
+
+const SPHERE: &str = r#"
+[[objects]]
+type = "sphere"
+center = [0, 0, -3]
+radius = 1
+material = { type = "diffuse", color = [0.5, 0.5, 0.5] }
+"#;
+
+#[test]
+fn camera_settings_override_a_scene_without_a_camera_table() {
+    let materials = MaterialRegistry::with_builtins();
+    let overrides = [("camera.max_depth", 3.0), ("camera.samples_per_pixel", 7.0)];
+    let scene = parse_scene_with_overrides(SPHERE, Path::new("."), &materials, &overrides)
+        .expect("the camera table is made for the overrides");
+    assert_eq!(scene.camera.integrator.settings(), "max depth 3");
+    assert_eq!(scene.camera.samples_per_pixel, 7);
+}
+
+#[test]
+fn overrides_of_missing_objects_are_errors() {
+    let materials = MaterialRegistry::with_builtins();
+    let overrides = [("objects.3.radius", 2.0)];
+    let result = parse_scene_with_overrides(SPHERE, Path::new("."), &materials, &overrides);
+    assert!(matches!(result, Err(SceneError::MissingParam(path)) if path == "objects.3.radius"));
+}
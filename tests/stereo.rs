@@ -0,0 +1,110 @@
+//! The two views of a stereo camera: points of the image map to an eye's view and back, and
+//! a point an eye's ray hits projects back onto the pixel the ray went through, for toed-in
+//! perspective eyes and for omni-directional stereo panoramas.
+
+use std::sync::Arc;
+
+use path_tracer::{
+    bsdf::diffuse::DiffuseBRDF,
+    camera::{Camera, CameraSample, Eye, Projection, Stereo, StereoLayout},
+    hittable::{Sphere, World},
+    interval::Interval,
+    vec3::{Float, Vec2, Vec3},
+};
+
+const LAYOUTS: [StereoLayout; 2] = [StereoLayout::SideBySide, StereoLayout::TopBottom];
+
+fn stereo(layout: StereoLayout, convergence: Option<Float>) -> Stereo {
+    Stereo {
+        // wide apart, so mixing up the eyes moves points by many pixels
+        interpupillary_distance: 0.5,
+        convergence,
+        layout,
+    }
+}
+
+#[test]
+fn image_points_go_to_an_eye_and_back() {
+    let (width, height) = (64.0, 48.0);
+    for layout in LAYOUTS {
+        let stereo = stereo(layout, None);
+        let (eye_width, eye_height) = stereo.eye_size(width, height);
+        for y in 0..48 {
+            for x in 0..64 {
+                let p = Vec2::new(x as Float + 0.25, y as Float + 0.75);
+                let (eye, q) = stereo.eye_at(p, width, height);
+                assert!(
+                    (0.0..eye_width).contains(&q.x) && (0.0..eye_height).contains(&q.y),
+                    "{layout:?}: {p} is at {q}, outside of the {eye:?} eye's view"
+                );
+                let right_half = match layout {
+                    StereoLayout::SideBySide => p.x >= eye_width,
+                    StereoLayout::TopBottom => p.y >= eye_height,
+                };
+                assert_eq!(eye == Eye::Right, right_half, "{layout:?}: {p}");
+                let back = stereo.to_image(eye, q, width, height);
+                assert!(
+                    back.distance(p) < 1e-9,
+                    "{layout:?}: {p} came back as {back}"
+                );
+            }
+        }
+    }
+}
+
+/// every pixel's ray through the lens center hits the inside of a sphere around the camera,
+/// and the hit has to project back onto the pixel for the eye that sees it
+fn assert_hits_project_back(projection: Projection, stereo: Stereo) {
+    let mut camera = Camera::new();
+    camera.image_width = 64;
+    camera.aspect_ratio = match stereo.layout {
+        StereoLayout::SideBySide => 2.0,
+        StereoLayout::TopBottom => 0.5,
+    };
+    camera.projection = projection;
+    camera.look_from = Vec3::new(0.3, 0.2, 0.1);
+    camera.look_at = Vec3::new(1.0, 0.5, -2.0);
+    camera.vup = Vec3::Y;
+    camera.vfov = 60.0;
+    camera.focal_length = 1.0;
+    camera.stereo = Some(stereo);
+    camera.init();
+
+    let mut world = World::new();
+    let material = Arc::new(DiffuseBRDF::from_rgb(Vec3::splat(0.5)));
+    world.add_object(Sphere::new_still(5.0, Vec3::ZERO, material));
+    world.build_bvh();
+
+    let (width, height) = (64.0, camera.image_height() as Float);
+    for r in 0..camera.image_height() {
+        for c in 0..64 {
+            let pixel = Vec2::new(c as Float + 0.5, r as Float + 0.5);
+            let (eye, _) = stereo.eye_at(pixel, width, height);
+            let ray = camera.generate_ray(r, c, &CameraSample::center()).unwrap();
+            let hit = world
+                .intersect_objects(&ray, Interval::FORWARD)
+                .expect("the sphere is all around the camera");
+            let projected = camera.project_from(Some(eye), hit.point).unwrap();
+            assert!(
+                projected.distance(pixel) < 1e-6,
+                "{projection:?} {stereo:?}: pixel {pixel} projects back to {projected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn perspective_hits_project_back() {
+    for layout in LAYOUTS {
+        for convergence in [None, Some(2.0)] {
+            assert_hits_project_back(Projection::Perspective, stereo(layout, convergence));
+        }
+    }
+}
+
+#[test]
+fn omni_directional_hits_project_back() {
+    for layout in LAYOUTS {
+        assert_hits_project_back(Projection::Equirectangular, stereo(layout, None));
+    }
+}